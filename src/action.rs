@@ -0,0 +1,966 @@
+//! src/action.rs
+
+use crate::{
+    app::{Mode, StatusMode},
+    config::KeyBindings,
+    event::AppEvent,
+};
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers, MouseEvent};
+
+/// Everything the app can be asked to do. Key presses are translated into
+/// actions with [`KeyBindings::action`], background tasks report back via
+/// `Action::Event`, and `App::dispatch` carries them out. Tests drive the
+/// app headlessly by dispatching actions.
+#[derive(Debug)]
+pub enum Action {
+    Quit,
+    ShowHelp,
+    SwitchMode(Mode),
+    Undo,
+    OpenWorktrees,
+    OpenRepoSwitch,
+    /// List the commands of the current view and the scripts' actions.
+    OpenPalette,
+    /// Re-read the repository and fetch the upstream in the background.
+    Refresh,
+    /// Capture the mouse, or leave it to the terminal's text selection.
+    ToggleMouse,
+    /// Commit and push the work tree's changes on their own, or pause that.
+    ToggleSync,
+    /// `Q`: start recording the keys typed into a macro, or stop.
+    RecordMacro,
+    /// `@@`: type the recorded keys again.
+    ReplayMacro,
+    // --- Navigation ---
+    SelectNext,
+    SelectPrev,
+    /// `gg`: the first item.
+    SelectFirst,
+    SelectLast,
+    /// Half a page down (`Ctrl+d`).
+    PageDown,
+    PageUp,
+    NextHunk,
+    PrevHunk,
+    /// Show more of a diff that was cut at the line limit.
+    LoadMore,
+    PanelLeft,
+    PanelRight,
+    /// Enter: open the selected item.
+    Confirm,
+    /// Esc outside popups: clear the filter or search.
+    Cancel,
+    Search,
+    SearchNext,
+    SearchPrev,
+    /// Copy what is selected to the clipboard: a path, hunk or commit hash.
+    Copy,
+    /// Copy the full message of the selected commit.
+    CopyMessage,
+    // --- Status View ---
+    StageItem,
+    UnstageItem,
+    /// Mark or unmark the selected file; staging, unstaging and discarding
+    /// then apply to the marked files.
+    MarkItem,
+    /// Throw away the unstaged changes of the selected or marked files,
+    /// after a confirmation.
+    Discard,
+    StageAll,
+    UnstageAll,
+    IgnoreItem,
+    ToggleDirGrouping,
+    /// Pick single lines of the selected hunk, then start a range of
+    /// them (`V`).
+    SelectLines,
+    Commit,
+    Push,
+    ForcePush,
+    /// Choose the remotes and the refspec to push, e.g. to push to a
+    /// mirror as well.
+    PushTo,
+    Fetch,
+    Pull,
+    /// Review the commits a push and a pull would move.
+    Divergence,
+    /// Rename the current branch and set or clear its upstream; on a
+    /// detached HEAD, create a branch there or return to one.
+    EditBranch,
+    /// Stash the local changes; the flags add the untracked files or keep
+    /// the staged changes in the index.
+    StashPush(git2::StashFlags),
+    TakeOurs,
+    TakeTheirs,
+    OpenEditor,
+    ExternalTool,
+    /// Write the selected file, hunk or the staged changes to a patch file.
+    ExportPatch,
+    /// Apply a patch file to the work tree.
+    ApplyPatch,
+    // --- Log View ---
+    /// Mark the selected commit as one side of a comparison.
+    MarkCommit,
+    /// Diff the marked commit against the selected one, or the selected
+    /// commit against the work tree.
+    Compare,
+    /// Check out a branch or tag at the selected commit, or the commit
+    /// itself.
+    LogCheckout,
+    /// Mark commits good or bad to find the first bad one.
+    Bisect,
+    // --- Stash View ---
+    StashApply,
+    StashPop,
+    StashDrop,
+    // --- Links View ---
+    LinkCreate,
+    LinkRemove,
+    LinkAdopt,
+    /// Offer the links of GNU Stow packages or a chezmoi source directory.
+    LinkImport,
+    /// Create every link of the manifest, backing up the files in the way.
+    LinkApply,
+    ProfileSwitch,
+    // --- Status and Tree Views ---
+    FileLog,
+    // --- Tree View ---
+    TreeBlame,
+    TreeDiff,
+    // --- Reflog View ---
+    ReflogCheckout,
+    ReflogReset,
+    // --- Ignore View ---
+    IgnoreCheck,
+    IgnoreAdd,
+    IgnoreEdit,
+    IgnoreRemove,
+    /// Look for secret-looking files and offer ignore templates for them.
+    IgnoreTemplates,
+    // --- Web View ---
+    ForgeOpenRepo,
+    ForgeOpenCommit,
+    // --- Doctor View ---
+    DoctorRecheck,
+    // --- Git Config View ---
+    ConfigEdit,
+    ConfigAdd,
+    ConfigUnset,
+    /// Switch between the local and the global config.
+    ConfigScope,
+    /// A key for the open popup or text input, which read keys directly.
+    Key(KeyEvent),
+    Mouse(MouseEvent),
+    /// Sent periodically by the input loop; expires notifications.
+    Tick,
+    /// Progress or the result of a background task.
+    Event(AppEvent),
+}
+
+impl From<AppEvent> for Action {
+    fn from(event: AppEvent) -> Self {
+        Action::Event(event)
+    }
+}
+
+impl Action {
+    /// A copy of the action if a count prefix repeats it, as `5j` moves
+    /// five items down.
+    pub fn repeatable(&self) -> Option<Action> {
+        Some(match self {
+            Action::SelectNext => Action::SelectNext,
+            Action::SelectPrev => Action::SelectPrev,
+            Action::PageDown => Action::PageDown,
+            Action::PageUp => Action::PageUp,
+            Action::NextHunk => Action::NextHunk,
+            Action::PrevHunk => Action::PrevHunk,
+            Action::SearchNext => Action::SearchNext,
+            Action::SearchPrev => Action::SearchPrev,
+            _ => return None,
+        })
+    }
+
+    /// Whether the action changes the repository, its work tree or the
+    /// linked files. Read-only mode refuses these before they reach a view.
+    pub fn is_mutating(&self) -> bool {
+        matches!(
+            self,
+            Action::Undo
+                | Action::StageItem
+                | Action::UnstageItem
+                | Action::Discard
+                | Action::StageAll
+                | Action::UnstageAll
+                | Action::IgnoreItem
+                | Action::Commit
+                | Action::Push
+                | Action::ForcePush
+                | Action::PushTo
+                | Action::Pull
+                | Action::EditBranch
+                | Action::StashPush(_)
+                | Action::TakeOurs
+                | Action::TakeTheirs
+                | Action::OpenEditor
+                | Action::ApplyPatch
+                | Action::LogCheckout
+                | Action::Bisect
+                | Action::StashApply
+                | Action::StashPop
+                | Action::StashDrop
+                | Action::LinkCreate
+                | Action::LinkRemove
+                | Action::LinkAdopt
+                | Action::LinkImport
+                | Action::LinkApply
+                | Action::ProfileSwitch
+                | Action::ReflogCheckout
+                | Action::ReflogReset
+                | Action::IgnoreAdd
+                | Action::IgnoreEdit
+                | Action::IgnoreRemove
+                | Action::IgnoreTemplates
+                | Action::ConfigEdit
+                | Action::ConfigAdd
+                | Action::ConfigUnset
+        )
+    }
+
+    /// What the action does in `mode`, as shown in the help popup.
+    pub fn description(&self, mode: Mode) -> &'static str {
+        match (self, mode) {
+            (Action::Quit, _) => "quit (leaves hunk / conflict view first)",
+            (Action::ShowHelp, _) => "show this help",
+            (Action::SwitchMode(target), _) => match target {
+                Mode::Status(_) => "Status View",
+                Mode::Log => "Log View",
+                Mode::Stash => "Stash View",
+                Mode::Links => "Links View",
+                Mode::Tree => "Tree View",
+                Mode::Reflog => "Reflog View",
+                Mode::Forge => "Web View (GitHub/GitLab)",
+                Mode::Ignore => "Gitignore View",
+                Mode::Messages => "Messages View (past notifications)",
+                Mode::Debug => "Debug View (recent log lines)",
+                Mode::Stats => "Stats View (recomputes the statistics)",
+                Mode::Doctor => "Doctor View (checks the setup)",
+                Mode::GitConfig => "Git Config View (local and global settings)",
+                Mode::Custom(_) => "a registered panel",
+            },
+            (Action::Undo, _) => "undo last stage / unstage / commit",
+            (Action::OpenWorktrees, _) => "worktrees",
+            (Action::OpenRepoSwitch, _) => "switch to a recent repository",
+            (Action::OpenPalette, _) => "command palette (also the actions of init.lua)",
+            (Action::Refresh, _) => "refresh and fetch in the background",
+            (Action::RecordMacro, _) => "start / stop recording a macro",
+            (Action::ReplayMacro, _) => "replay the recorded macro",
+            (Action::ToggleMouse, _) => "mouse: TUI clicks / terminal text selection",
+            (Action::ToggleSync, _) => "sync mode: commit and push changes on their own / pause",
+            (Action::SelectNext | Action::NextHunk, Mode::Status(StatusMode::HunkSelection)) => "next hunk",
+            (Action::SelectPrev | Action::PrevHunk, Mode::Status(StatusMode::HunkSelection)) => "previous hunk",
+            (Action::SelectNext, Mode::Status(StatusMode::LineSelection)) => "next line",
+            (Action::SelectPrev, Mode::Status(StatusMode::LineSelection)) => "previous line",
+            (Action::SelectNext, Mode::Status(StatusMode::ConflictResolution)) => "scroll down",
+            (Action::SelectPrev, Mode::Status(StatusMode::ConflictResolution)) => "scroll up",
+            (Action::SelectNext, Mode::Status(_)) => "next file / scroll diff down",
+            (Action::SelectPrev, Mode::Status(_)) => "previous file / scroll diff up",
+            (Action::SelectNext, _) => "next item",
+            (Action::SelectPrev, _) => "previous item",
+            (Action::SelectFirst, _) => "first item / top of the diff",
+            (Action::SelectLast, _) => "last item / end of the diff",
+            (Action::PageDown, _) => "half a page down",
+            (Action::PageUp, _) => "half a page up",
+            (Action::NextHunk, _) => "jump to next hunk in the diff",
+            (Action::PrevHunk, _) => "jump to previous hunk in the diff",
+            (Action::LoadMore, _) => "show more lines of a long diff",
+            (Action::PanelLeft, Mode::Tree) => "collapse directory / go to parent",
+            (Action::PanelRight, Mode::Tree) => "expand directory / focus file",
+            (Action::PanelLeft, _) => "focus the left panel",
+            (Action::PanelRight, _) => "focus the right panel",
+            (Action::Confirm, Mode::Tree) => "expand / collapse directory",
+            (Action::Confirm, Mode::Forge) => "open pull request in browser",
+            (Action::Confirm, Mode::Log) => "show commit details",
+            (Action::Confirm, Mode::Doctor) => "fix the problem, or copy the command that does",
+            (Action::Confirm, _) => "enter hunk selection / conflict view, collapse directory",
+            (Action::Cancel, Mode::Status(StatusMode::LineSelection)) => "back to the hunks",
+            (Action::Cancel, Mode::Log) => "close comparison / clear search / full history",
+            (Action::Cancel, _) => "clear marks / filter",
+            (Action::Search, Mode::Log) => "search log",
+            (Action::Search, _) => "filter files",
+            (Action::SearchNext, _) => "next match",
+            (Action::SearchPrev, _) => "previous match",
+            (Action::Copy, Mode::Log) => "copy the commit hash",
+            (Action::Copy, Mode::Tree) => "copy the file path",
+            (Action::Copy, Mode::Status(StatusMode::HunkSelection)) => "copy the hunk",
+            (Action::Copy, _) => "copy the file path / the selected hunk",
+            (Action::CopyMessage, _) => "copy the full commit message",
+            (Action::StageItem, Mode::Status(StatusMode::HunkSelection)) => "stage / unstage the hunk",
+            (Action::StageItem, Mode::Status(StatusMode::LineSelection)) => "stage / unstage the picked lines",
+            (Action::StageItem, Mode::Status(StatusMode::ConflictResolution)) => "mark resolved as it is in the work tree",
+            (Action::StageItem, _) => "stage item / toggle hunk",
+            (Action::UnstageItem, _) => "unstage item",
+            (Action::MarkItem, _) => "mark file for a batch stage / unstage / discard",
+            (Action::Discard, _) => "discard unstaged changes",
+            (Action::StageAll, _) => "stage all",
+            (Action::UnstageAll, _) => "unstage all",
+            (Action::IgnoreItem, _) => "add untracked file to .gitignore",
+            (Action::ToggleDirGrouping, _) => "group files by directory",
+            (Action::SelectLines, Mode::Status(StatusMode::HunkSelection)) => "pick lines of the hunk",
+            (Action::SelectLines, Mode::Status(StatusMode::LineSelection)) => "start or drop a range",
+            (Action::SelectLines, _) => "pick lines of the hunk / start or drop a range",
+            (Action::Commit, _) => "commit",
+            (Action::Push, _) => "push to upstream",
+            (Action::ForcePush, _) => "force push with lease",
+            (Action::PushTo, _) => "push to chosen remotes",
+            (Action::Fetch, _) => "fetch from upstream",
+            (Action::Pull, _) => "pull (fetch + merge)",
+            (Action::Divergence, _) => "commits a push / pull would move",
+            (Action::EditBranch, _) => "rename the branch / set its upstream / leave a detached HEAD",
+            (Action::StashPush(flags), _) if flags.contains(git2::StashFlags::INCLUDE_UNTRACKED) => {
+                "stash changes and untracked files"
+            }
+            (Action::StashPush(flags), _) if flags.contains(git2::StashFlags::KEEP_INDEX) => {
+                "stash changes, keeping the staged ones"
+            }
+            (Action::StashPush(_), _) => "stash changes",
+            (Action::TakeOurs, _) => "take ours (conflict view)",
+            (Action::TakeTheirs, _) => "take theirs (conflict view)",
+            (Action::OpenEditor, _) => "edit conflicted file",
+            (Action::ExternalTool, _) => "open file in the diff tool / conflict in the merge tool",
+            (Action::ExportPatch, Mode::Status(StatusMode::HunkSelection)) => "export the hunk as a patch",
+            (Action::ExportPatch, _) => "export file / hunk / staged changes as a patch",
+            (Action::ApplyPatch, _) => "apply a patch file",
+            (Action::MarkCommit, _) => "mark commit to compare",
+            (Action::Compare, _) => "diff marked commit with selected, or selected with work tree",
+            (Action::LogCheckout, _) => "check out a branch or tag at the commit",
+            (Action::Bisect, _) => "bisect: mark the commit good or bad, find the first bad one",
+            (Action::StashApply, _) => "apply stash",
+            (Action::StashPop, _) => "pop stash",
+            (Action::StashDrop, _) => "drop stash",
+            (Action::LinkCreate, _) => "create link",
+            (Action::LinkRemove, _) => "remove link",
+            (Action::LinkAdopt, _) => "adopt target into the repository",
+            (Action::LinkImport, _) => "import the links of GNU Stow packages or chezmoi",
+            (Action::LinkApply, _) => "apply every link, backing up files in the way",
+            (Action::ProfileSwitch, _) => "switch machine profile",
+            (Action::TreeBlame, _) => "blame file",
+            (Action::FileLog, _) => "history of file",
+            (Action::TreeDiff, _) => "diff of file",
+            (Action::ReflogCheckout, _) => "check out entry",
+            (Action::ReflogReset, _) => "reset --hard to entry",
+            (Action::IgnoreCheck, _) => "check whether a path is ignored",
+            (Action::IgnoreAdd, _) => "add pattern",
+            (Action::IgnoreEdit, _) => "edit pattern",
+            (Action::IgnoreRemove, _) => "remove pattern",
+            (Action::IgnoreTemplates, _) => "find secret-looking files and add ignore templates",
+            (Action::ForgeOpenRepo, _) => "open repository in browser",
+            (Action::ForgeOpenCommit, _) => "open commit in browser",
+            (Action::DoctorRecheck, _) => "run the checks again",
+            (Action::ConfigEdit, _) => "edit value, or flip a true / false one",
+            (Action::ConfigAdd, _) => "set another entry",
+            (Action::ConfigUnset, _) => "unset entry",
+            (Action::ConfigScope, _) => "switch between the local and the global config",
+            (Action::Key(_) | Action::Mouse(_) | Action::Tick | Action::Event(_), _) => "",
+        }
+    }
+}
+
+/// A group of lines in the help popup.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HelpSection {
+    pub title: &'static str,
+    /// Keys, joined with `/` when several do the same, what they do, and
+    /// whether that changes the repository (see [`Action::is_mutating`]).
+    pub entries: Vec<(String, &'static str, bool)>,
+}
+
+/// The modes in the order of the tabs, with their help section titles. The
+/// status view's hunk, line and conflict modes get sections of their own.
+const HELP_MODES: [(Mode, &str); 16] = [
+    (Mode::Status(StatusMode::FileSelection), "Status View"),
+    (Mode::Status(StatusMode::HunkSelection), "Hunk Selection"),
+    (Mode::Status(StatusMode::LineSelection), "Line Selection"),
+    (Mode::Status(StatusMode::ConflictResolution), "Conflict View"),
+    (Mode::Log, "Log View"),
+    (Mode::Stash, "Stash View"),
+    (Mode::Links, "Links View"),
+    (Mode::Tree, "Tree View"),
+    (Mode::Reflog, "Reflog View"),
+    (Mode::Forge, "Web View"),
+    (Mode::Ignore, "Gitignore View"),
+    (Mode::Messages, "Messages View"),
+    (Mode::Debug, "Debug View"),
+    (Mode::Stats, "Stats View"),
+    (Mode::Doctor, "Doctor View"),
+    (Mode::GitConfig, "Git Config View"),
+];
+
+impl KeyBindings {
+    /// Translates a key pressed in `mode` while no popup or text input is
+    /// open. Views reuse keys (`c` commits in the status view and creates a
+    /// link in the links view), so only the keymap of `mode` and the global
+    /// one are considered, in that order. `None` when the key does nothing
+    /// there.
+    pub fn action(&self, key: KeyEvent, mode: Mode) -> Option<Action> {
+        let find = |bindings: Vec<(KeyEvent, Action)>| {
+            bindings
+                .into_iter()
+                .find(|(binding, _)| *binding == key)
+                .map(|(_, action)| action)
+        };
+        if let Some(action) = find(self.mode_bindings(mode)) {
+            return Some(action);
+        }
+        if key == self.global.status_mode && !matches!(mode, Mode::Status(_)) {
+            return Some(Action::SwitchMode(Mode::Status(StatusMode::FileSelection)));
+        }
+        find(self.global_bindings().into())
+    }
+
+    /// The single keys that work in `mode`, for the command palette: the
+    /// view's own first, then the global ones they do not hide.
+    pub fn commands(&self, mode: Mode) -> Vec<(KeyEvent, Action)> {
+        let mut commands = self.mode_bindings(mode);
+        for (key, action) in self.global_bindings() {
+            if !commands.iter().any(|(bound, _)| *bound == key) {
+                commands.push((key, action));
+            }
+        }
+        commands
+    }
+
+    /// The bindings of several keys pressed one after another that work in
+    /// `mode`. Once the first key of one is pressed, `App::action_for_key`
+    /// waits for the rest.
+    pub fn sequences(&self, mode: Mode) -> Vec<(Vec<KeyEvent>, Action)> {
+        let mut sequences = self.global_sequences();
+        sequences.extend(self.mode_sequences(mode));
+        sequences
+    }
+
+    /// The help popup's content, generated from the bindings so that
+    /// remapped keys show up correctly. Global keys come first, then the
+    /// keys of `mode`, the keys popups read directly and, with
+    /// `all_modes`, the other views.
+    pub fn help(&self, mode: Mode, all_modes: bool) -> Vec<HelpSection> {
+        let status_mode = Action::SwitchMode(Mode::Status(StatusMode::FileSelection));
+        let global = std::iter::once((self.global.status_mode, status_mode))
+            .chain(self.global_bindings())
+            .map(|(key, action)| (vec![key], action.description(mode), action.is_mutating()))
+            .chain(sequence_help(self.global_sequences(), mode));
+        let mut sections = vec![help_section("Global", global)];
+        sections[0]
+            .entries
+            .push(("1-9".to_string(), "count for the next move, e.g. 5j", false));
+        let current = HELP_MODES
+            .iter()
+            .position(|(help_mode, _)| *help_mode == mode)
+            .unwrap_or(0);
+        let (mode, title) = HELP_MODES[current];
+        sections.push(help_section(title, self.mode_help(mode)));
+        let popup = &self.popup;
+        sections.push(help_section(
+            "Popups",
+            [
+                (popup.confirm, "confirm / submit", false),
+                (popup.close_popup, "close popup / cancel a running push, fetch, pull or clone", false),
+                (popup.confirm_yes, "answer a confirmation", false),
+                (popup.confirm_no, "answer a confirmation", false),
+                (popup.retry, "retry a failed push, fetch, pull or clone (error popup)", false),
+                (popup.submit_commit, "commit (commit popup)", true),
+                (popup.toggle_signing, "toggle commit signing (commit popup)", false),
+                (popup.toggle_conventional, "conventional-commit helper (commit popup)", false),
+                (popup.toggle_no_verify, "skip the commit hooks (commit popup)", false),
+                (popup.next_field, "next field (conventional-commit helper, branch popup, push to, clone)", false),
+                (popup.worktree_add, "add worktree (worktree switcher)", true),
+                (popup.worktree_remove, "remove worktree (worktree switcher)", true),
+                (popup.repo_clone, "clone a repository (recent repositories)", false),
+                (popup.bisect_good, "mark good (bisect)", true),
+                (popup.bisect_bad, "mark bad (bisect)", true),
+                (popup.bisect_skip, "skip a commit that cannot be tested (bisect)", true),
+                (popup.bisect_stop, "end the bisect and return to where it started (bisect)", true),
+                (popup.toggle_entry, "choose a template or remote (ignore templates, push to)", false),
+            ]
+            .map(|(key, description, mutating)| (vec![key], description, mutating)),
+        ));
+        for (i, (mode, title)) in HELP_MODES.into_iter().enumerate() {
+            if all_modes && i != current {
+                sections.push(help_section(title, self.mode_help(mode)));
+            }
+        }
+        sections
+    }
+
+    fn mode_help(&self, mode: Mode) -> Vec<(Vec<KeyEvent>, &'static str, bool)> {
+        let bindings = match mode {
+            Mode::Status(sub_mode @ (StatusMode::HunkSelection | StatusMode::LineSelection | StatusMode::ConflictResolution)) => {
+                self.hunk_help_bindings(sub_mode)
+            }
+            _ => self.mode_bindings(mode),
+        };
+        bindings
+            .into_iter()
+            .map(|(key, action)| (vec![key], action.description(mode), action.is_mutating()))
+            .chain(sequence_help(self.mode_sequences(mode), mode))
+            .collect()
+    }
+
+    /// The hunk keymap's keys that do something in `sub_mode`. They are all
+    /// bound in every hunk mode, but are only listed where they apply; the
+    /// file list's keys that still work there are left to its own section.
+    fn hunk_help_bindings(&self, sub_mode: StatusMode) -> Vec<(KeyEvent, Action)> {
+        let applies = |action: &Action| match sub_mode {
+            StatusMode::LineSelection => matches!(
+                action,
+                Action::SelectNext | Action::SelectPrev | Action::StageItem | Action::SelectLines | Action::Cancel
+            ),
+            StatusMode::ConflictResolution => matches!(
+                action,
+                Action::SelectNext
+                    | Action::SelectPrev
+                    | Action::StageItem
+                    | Action::TakeOurs
+                    | Action::TakeTheirs
+                    | Action::OpenEditor
+                    | Action::ExternalTool
+            ),
+            _ => matches!(
+                action,
+                Action::SelectNext
+                    | Action::SelectPrev
+                    | Action::NextHunk
+                    | Action::PrevHunk
+                    | Action::StageItem
+                    | Action::SelectLines
+                    | Action::ExportPatch
+                    | Action::Copy
+            ),
+        };
+        self.hunk_bindings()
+            .into_iter()
+            .filter(|(_, action)| applies(action))
+            .collect()
+    }
+
+    /// Keys that work in every view whose keymap does not use them. The
+    /// status view's key is left out: it only applies outside the status
+    /// view.
+    fn global_bindings(&self) -> [(KeyEvent, Action); 25] {
+        let global = &self.global;
+        [
+            (global.quit, Action::Quit),
+            (global.show_help, Action::ShowHelp),
+            (global.log_mode, Action::SwitchMode(Mode::Log)),
+            (global.stash_mode, Action::SwitchMode(Mode::Stash)),
+            (global.links_mode, Action::SwitchMode(Mode::Links)),
+            (global.tree_mode, Action::SwitchMode(Mode::Tree)),
+            (global.reflog_mode, Action::SwitchMode(Mode::Reflog)),
+            (global.forge_mode, Action::SwitchMode(Mode::Forge)),
+            (global.ignore_mode, Action::SwitchMode(Mode::Ignore)),
+            (global.messages_mode, Action::SwitchMode(Mode::Messages)),
+            (global.debug_mode, Action::SwitchMode(Mode::Debug)),
+            (global.stats_mode, Action::SwitchMode(Mode::Stats)),
+            (global.doctor_mode, Action::SwitchMode(Mode::Doctor)),
+            (global.git_config_mode, Action::SwitchMode(Mode::GitConfig)),
+            (global.undo, Action::Undo),
+            (global.worktree_switch, Action::OpenWorktrees),
+            (global.repo_switch, Action::OpenRepoSwitch),
+            (global.command_palette, Action::OpenPalette),
+            (global.refresh, Action::Refresh),
+            (global.toggle_mouse, Action::ToggleMouse),
+            (global.toggle_sync, Action::ToggleSync),
+            (global.record_macro, Action::RecordMacro),
+            (global.select_last, Action::SelectLast),
+            (global.page_down, Action::PageDown),
+            (global.page_up, Action::PageUp),
+        ]
+    }
+
+    fn global_sequences(&self) -> Vec<(Vec<KeyEvent>, Action)> {
+        let global = &self.global;
+        vec![
+            (vec![global.select_first, global.select_first], Action::SelectFirst),
+            (vec![global.play_macro, global.play_macro], Action::ReplayMacro),
+        ]
+    }
+
+    /// The leader followed by a mnemonic, e.g. `\\ g p` to push, for the
+    /// git operations of the file list.
+    fn mode_sequences(&self, mode: Mode) -> Vec<(Vec<KeyEvent>, Action)> {
+        let leader = |keys: &str| -> Vec<KeyEvent> {
+            std::iter::once(self.global.leader)
+                .chain(keys.chars().map(|c| KeyEvent::new(KeyCode::Char(c), KeyModifiers::NONE)))
+                .collect()
+        };
+        match mode {
+            Mode::Status(StatusMode::FileSelection) => vec![
+                (leader("gc"), Action::Commit),
+                (leader("gp"), Action::Push),
+                (leader("gf"), Action::Fetch),
+                (leader("gl"), Action::Pull),
+                (leader("gs"), Action::StashPush(git2::StashFlags::DEFAULT)),
+            ],
+            _ => Vec::new(),
+        }
+    }
+
+    fn mode_bindings(&self, mode: Mode) -> Vec<(KeyEvent, Action)> {
+        match mode {
+            Mode::Status(StatusMode::FileSelection) => self.status_bindings(),
+            // The file list's keys keep working while hunks or lines are
+            // selected, below the hunk keymap.
+            Mode::Status(_) => {
+                let mut bindings = self.hunk_bindings();
+                bindings.extend(self.status_bindings());
+                bindings
+            }
+            Mode::Log => {
+                let log = &self.log;
+                vec![
+                    (log.select_next, Action::SelectNext),
+                    (log.select_prev, Action::SelectPrev),
+                    (log.search, Action::Search),
+                    (log.search_next, Action::SearchNext),
+                    (log.search_prev, Action::SearchPrev),
+                    (log.confirm, Action::Confirm),
+                    (log.cancel, Action::Cancel),
+                    (log.mark_commit, Action::MarkCommit),
+                    (log.compare, Action::Compare),
+                    (log.log_checkout, Action::LogCheckout),
+                    (log.bisect, Action::Bisect),
+                    (log.copy, Action::Copy),
+                    (log.copy_message, Action::CopyMessage),
+                    (log.panel_left, Action::PanelLeft),
+                    (log.panel_right, Action::PanelRight),
+                    (log.next_hunk, Action::NextHunk),
+                    (log.prev_hunk, Action::PrevHunk),
+                    (log.load_more, Action::LoadMore),
+                ]
+            }
+            Mode::Stash => {
+                let stash = &self.stash;
+                vec![
+                    (stash.select_next, Action::SelectNext),
+                    (stash.select_prev, Action::SelectPrev),
+                    (stash.stash_apply, Action::StashApply),
+                    (stash.stash_pop, Action::StashPop),
+                    (stash.stash_drop, Action::StashDrop),
+                ]
+            }
+            Mode::Links => {
+                let links = &self.links;
+                vec![
+                    (links.select_next, Action::SelectNext),
+                    (links.select_prev, Action::SelectPrev),
+                    (links.profile_switch, Action::ProfileSwitch),
+                    (links.link_create, Action::LinkCreate),
+                    (links.link_remove, Action::LinkRemove),
+                    (links.link_adopt, Action::LinkAdopt),
+                    (links.link_import, Action::LinkImport),
+                    (links.link_apply, Action::LinkApply),
+                ]
+            }
+            Mode::Tree => {
+                let tree = &self.tree;
+                vec![
+                    (tree.select_next, Action::SelectNext),
+                    (tree.select_prev, Action::SelectPrev),
+                    (tree.confirm, Action::Confirm),
+                    (tree.expand, Action::Confirm),
+                    (tree.panel_left, Action::PanelLeft),
+                    (tree.panel_right, Action::PanelRight),
+                    (tree.tree_blame, Action::TreeBlame),
+                    (tree.file_log, Action::FileLog),
+                    (tree.tree_diff, Action::TreeDiff),
+                    (tree.copy, Action::Copy),
+                ]
+            }
+            Mode::Reflog => {
+                let reflog = &self.reflog;
+                vec![
+                    (reflog.select_next, Action::SelectNext),
+                    (reflog.select_prev, Action::SelectPrev),
+                    (reflog.reflog_checkout, Action::ReflogCheckout),
+                    (reflog.reflog_reset, Action::ReflogReset),
+                ]
+            }
+            Mode::Ignore => {
+                let ignore = &self.ignore;
+                vec![
+                    (ignore.select_next, Action::SelectNext),
+                    (ignore.select_prev, Action::SelectPrev),
+                    (ignore.ignore_check, Action::IgnoreCheck),
+                    (ignore.ignore_add, Action::IgnoreAdd),
+                    (ignore.ignore_edit, Action::IgnoreEdit),
+                    (ignore.ignore_remove, Action::IgnoreRemove),
+                    (ignore.ignore_templates, Action::IgnoreTemplates),
+                ]
+            }
+            Mode::Stats | Mode::Custom(_) => Vec::new(),
+            Mode::Doctor => {
+                let doctor = &self.doctor;
+                vec![
+                    (doctor.select_next, Action::SelectNext),
+                    (doctor.select_prev, Action::SelectPrev),
+                    (doctor.confirm, Action::Confirm),
+                    (doctor.doctor_recheck, Action::DoctorRecheck),
+                ]
+            }
+            Mode::GitConfig => {
+                let gitconfig = &self.gitconfig;
+                vec![
+                    (gitconfig.select_next, Action::SelectNext),
+                    (gitconfig.select_prev, Action::SelectPrev),
+                    (gitconfig.config_edit, Action::ConfigEdit),
+                    (gitconfig.config_add, Action::ConfigAdd),
+                    (gitconfig.config_unset, Action::ConfigUnset),
+                    (gitconfig.config_scope, Action::ConfigScope),
+                ]
+            }
+            Mode::Messages | Mode::Debug => vec![
+                (self.messages.select_next, Action::SelectNext),
+                (self.messages.select_prev, Action::SelectPrev),
+            ],
+            Mode::Forge => {
+                let forge = &self.forge;
+                vec![
+                    (forge.select_next, Action::SelectNext),
+                    (forge.select_prev, Action::SelectPrev),
+                    (forge.confirm, Action::Confirm),
+                    (forge.forge_open_repo, Action::ForgeOpenRepo),
+                    (forge.forge_open_commit, Action::ForgeOpenCommit),
+                ]
+            }
+        }
+    }
+
+    fn status_bindings(&self) -> Vec<(KeyEvent, Action)> {
+        let status = &self.status;
+        vec![
+            (status.panel_left, Action::PanelLeft),
+            (status.panel_right, Action::PanelRight),
+            (status.select_next, Action::SelectNext),
+            (status.select_prev, Action::SelectPrev),
+            (status.next_hunk, Action::NextHunk),
+            (status.prev_hunk, Action::PrevHunk),
+            (status.load_more, Action::LoadMore),
+            (status.stage_item, Action::StageItem),
+            (status.unstage_item, Action::UnstageItem),
+            (status.mark_item, Action::MarkItem),
+            (status.discard, Action::Discard),
+            (status.stage_all, Action::StageAll),
+            (status.unstage_all, Action::UnstageAll),
+            (status.ignore_item, Action::IgnoreItem),
+            (status.file_log, Action::FileLog),
+            (status.group_by_dir, Action::ToggleDirGrouping),
+            (status.search, Action::Search),
+            (status.cancel, Action::Cancel),
+            (status.confirm, Action::Confirm),
+            (status.log_mode, Action::SwitchMode(Mode::Log)),
+            (status.commit, Action::Commit),
+            (status.push, Action::Push),
+            (status.force_push, Action::ForcePush),
+            (status.push_to, Action::PushTo),
+            (status.fetch, Action::Fetch),
+            (status.pull, Action::Pull),
+            (status.divergence, Action::Divergence),
+            (status.edit_branch, Action::EditBranch),
+            (status.stash_push, Action::StashPush(git2::StashFlags::DEFAULT)),
+            (status.stash_untracked, Action::StashPush(git2::StashFlags::INCLUDE_UNTRACKED)),
+            (status.stash_keep_index, Action::StashPush(git2::StashFlags::KEEP_INDEX)),
+            (status.external_tool, Action::ExternalTool),
+            (status.copy, Action::Copy),
+            (status.export_patch, Action::ExportPatch),
+            (status.apply_patch, Action::ApplyPatch),
+        ]
+    }
+
+    fn hunk_bindings(&self) -> Vec<(KeyEvent, Action)> {
+        let hunk = &self.hunk;
+        vec![
+            (hunk.panel_left, Action::PanelLeft),
+            (hunk.panel_right, Action::PanelRight),
+            (hunk.select_next, Action::SelectNext),
+            (hunk.select_prev, Action::SelectPrev),
+            (hunk.next_hunk, Action::NextHunk),
+            (hunk.prev_hunk, Action::PrevHunk),
+            (hunk.stage_item, Action::StageItem),
+            (hunk.select_lines, Action::SelectLines),
+            (hunk.cancel, Action::Cancel),
+            (hunk.export_patch, Action::ExportPatch),
+            (hunk.copy, Action::Copy),
+            (hunk.take_ours, Action::TakeOurs),
+            (hunk.take_theirs, Action::TakeTheirs),
+            (hunk.open_editor, Action::OpenEditor),
+            (hunk.external_tool, Action::ExternalTool),
+        ]
+    }
+}
+
+/// Describes multi-key bindings like single keys for the help.
+fn sequence_help(sequences: Vec<(Vec<KeyEvent>, Action)>, mode: Mode) -> Vec<(Vec<KeyEvent>, &'static str, bool)> {
+    sequences
+        .into_iter()
+        .map(|(keys, action)| (keys, action.description(mode), action.is_mutating()))
+        .collect()
+}
+
+/// Builds a help section, merging keys that do the same thing.
+fn help_section(
+    title: &'static str,
+    bindings: impl IntoIterator<Item = (Vec<KeyEvent>, &'static str, bool)>,
+) -> HelpSection {
+    let mut entries: Vec<(String, &'static str, bool)> = Vec::new();
+    for (keys, description, mutating) in bindings {
+        let label = sequence_label(&keys);
+        match entries.iter_mut().find(|(_, existing, _)| *existing == description) {
+            Some((keys, _, _)) => {
+                keys.push('/');
+                keys.push_str(&label);
+            }
+            None => entries.push((label, description, mutating)),
+        }
+    }
+    HelpSection { title, entries }
+}
+
+/// How keys pressed one after another are written, e.g. `g g`.
+pub fn sequence_label(keys: &[KeyEvent]) -> String {
+    keys.iter().map(|&key| key_label(key)).collect::<Vec<_>>().join(" ")
+}
+
+/// How a key is written in the help, e.g. `ctrl+r`, `Shift+G` or `space`.
+pub fn key_label(key: KeyEvent) -> String {
+    let name = match key.code {
+        KeyCode::Char(' ') => "space".to_string(),
+        KeyCode::Char(c) if key.modifiers.contains(KeyModifiers::SHIFT) => {
+            return format!("Shift+{}", c.to_ascii_uppercase());
+        }
+        KeyCode::Char(c) => c.to_string(),
+        KeyCode::Enter => "enter".to_string(),
+        KeyCode::Esc => "esc".to_string(),
+        KeyCode::Tab => "tab".to_string(),
+        KeyCode::Backspace => "backspace".to_string(),
+        KeyCode::Up => "↑".to_string(),
+        KeyCode::Down => "↓".to_string(),
+        KeyCode::Left => "←".to_string(),
+        KeyCode::Right => "→".to_string(),
+        KeyCode::F(n) => format!("F{}", n),
+        code => format!("{:?}", code).to_lowercase(),
+    };
+    if key.modifiers.contains(KeyModifiers::CONTROL) {
+        format!("ctrl+{}", name)
+    } else if key.modifiers.contains(KeyModifiers::ALT) {
+        format!("alt+{}", name)
+    } else {
+        name
+    }
+}
+
+/// Reads a key as written in the config, the way `key_label` writes it:
+/// `c`, `C` or `Shift+c`, `ctrl+r`, `alt+x`, `space`, `enter`, `↑` or `up`,
+/// `F5`.
+pub fn parse_key(text: &str) -> Result<KeyEvent, String> {
+    let mut modifiers = KeyModifiers::NONE;
+    let mut name = text;
+    while let Some((modifier, rest)) = name.split_once('+').filter(|(_, rest)| !rest.is_empty()) {
+        modifiers |= match modifier.to_lowercase().as_str() {
+            "ctrl" => KeyModifiers::CONTROL,
+            "alt" => KeyModifiers::ALT,
+            "shift" => KeyModifiers::SHIFT,
+            _ => return Err(format!("unknown modifier '{}' in '{}'", modifier, text)),
+        };
+        name = rest;
+    }
+    let code = match name.to_lowercase().as_str() {
+        "space" => KeyCode::Char(' '),
+        "enter" => KeyCode::Enter,
+        "esc" => KeyCode::Esc,
+        "tab" => KeyCode::Tab,
+        "backtab" => KeyCode::BackTab,
+        "backspace" => KeyCode::Backspace,
+        "delete" => KeyCode::Delete,
+        "insert" => KeyCode::Insert,
+        "home" => KeyCode::Home,
+        "end" => KeyCode::End,
+        "pageup" => KeyCode::PageUp,
+        "pagedown" => KeyCode::PageDown,
+        "up" | "↑" => KeyCode::Up,
+        "down" | "↓" => KeyCode::Down,
+        "left" | "←" => KeyCode::Left,
+        "right" | "→" => KeyCode::Right,
+        lower => {
+            let mut chars = name.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) if modifiers.contains(KeyModifiers::SHIFT) => KeyCode::Char(c.to_ascii_uppercase()),
+                (Some(c), None) => {
+                    if c.is_ascii_uppercase() {
+                        modifiers |= KeyModifiers::SHIFT;
+                    }
+                    KeyCode::Char(c)
+                }
+                _ => match lower.strip_prefix('f').and_then(|n| n.parse().ok()) {
+                    Some(n @ 1..=12) => KeyCode::F(n),
+                    _ => return Err(format!("unknown key '{}'", text)),
+                },
+            }
+        }
+    };
+    Ok(KeyEvent::new(code, modifiers))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{GlobalKeys, StatusKeys};
+
+    #[test]
+    fn help_shows_remapped_keys_and_merges_shared_actions() {
+        let keys = KeyBindings {
+            global: GlobalKeys {
+                leader: KeyEvent::new(KeyCode::Char(','), KeyModifiers::NONE),
+                ..GlobalKeys::default()
+            },
+            status: StatusKeys {
+                fetch: KeyEvent::new(KeyCode::Char('F'), KeyModifiers::SHIFT),
+                force_push: KeyEvent::new(KeyCode::Char('x'), KeyModifiers::CONTROL),
+                ..StatusKeys::default()
+            },
+            ..KeyBindings::default()
+        };
+        let help = keys.help(Mode::Tree, true);
+        assert_eq!(help[0].title, "Global");
+        assert_eq!(help[1].title, "Tree View");
+        assert!(help[1].entries.contains(&("enter/space".to_string(), "expand / collapse directory", false)));
+
+        let status = help.iter().find(|section| section.title == "Status View").unwrap();
+        assert!(status.entries.contains(&("Shift+F/, g f".to_string(), "fetch from upstream", false)));
+        assert!(status.entries.contains(&("ctrl+x".to_string(), "force push with lease", true)));
+    }
+
+    #[test]
+    fn help_lists_the_keys_of_the_current_status_mode() {
+        let keys = KeyBindings::default();
+        let titles = |mode, all_modes| -> Vec<&str> {
+            keys.help(mode, all_modes).iter().map(|section| section.title).collect()
+        };
+        assert_eq!(titles(Mode::Status(StatusMode::HunkSelection), false), ["Global", "Hunk Selection", "Popups"]);
+        assert_eq!(titles(Mode::Log, true).len(), 2 + HELP_MODES.len());
+
+        let files = &keys.help(Mode::Status(StatusMode::FileSelection), false)[1];
+        assert!(!files.entries.iter().any(|(_, description, _)| description.contains("take ours")));
+        let hunks = &keys.help(Mode::Status(StatusMode::HunkSelection), false)[1];
+        assert!(hunks.entries.contains(&("j/}".to_string(), "next hunk", false)), "{:?}", hunks.entries);
+    }
+
+    #[test]
+    fn keys_are_read_as_the_help_writes_them() {
+        for text in ["c", "Shift+C", "ctrl+r", "alt+x", "space", "enter", "esc", "↑", "pageup", "F12", "\\"] {
+            assert_eq!(key_label(parse_key(text).unwrap()), text);
+        }
+        assert_eq!(parse_key("C"), parse_key("shift+c"));
+        assert_eq!(parse_key("Ctrl+Tab").unwrap(), KeyEvent::new(KeyCode::Tab, KeyModifiers::CONTROL));
+        assert_eq!(parse_key("+"), Ok(KeyEvent::new(KeyCode::Char('+'), KeyModifiers::NONE)));
+        assert_eq!(parse_key("F13"), Err("unknown key 'F13'".to_string()));
+        assert_eq!(parse_key("meta+x"), Err("unknown modifier 'meta' in 'meta+x'".to_string()));
+    }
+}
@@ -1,20 +1,101 @@
 //! src/app.rs
 
+mod forge;
+mod macros;
+mod reflog;
+mod sync;
+mod worktrees;
+
+pub use self::forge::{ForgeState, ForgeView};
+pub use self::macros::MacroState;
+pub use self::reflog::ReflogState;
+pub use self::sync::SyncMode;
+pub use self::worktrees::WorktreeState;
+
 use crate::{
-    config::KeyBindings,
+    action::{key_label, parse_key, sequence_label, Action},
+    config::{Config, EncryptionConfig, KeyBindings, DEFAULT_SUBJECT_LIMIT},
+    doctor::{self, Check, Fix},
+    dotfiles::{self, ignore_templates::{self, TemplateMatch}, apply::{self, ApplyOutcome, ApplyResult}, import::{self, Import}, secrets, LinkEntry, LinkState, Profile, MANIFEST_FILE},
     error::{AppError, AppResult},
-    event::{AppEvent, EventHandler},
-    git::{CommitInfo, GitRepo, Hunk, StatusItem},
+    event::{AppEvent, CredentialRequest, EventPrompter},
+    git::{
+        bisect::{Bisect, BisectStep, Verdict},
+        credentials::NoPrompt,
+        gitconfig::{self, ConfigEntry, ConfigScope, ValueKind},
+        graph::render_graph,
+        ignore::{self, IgnoreFile, IgnoreRule},
+        worker::{self, ChurnKey, FileDiff, GitRequest, GitWorker, LinksSnapshot, RepoSnapshot},
+        lint::{self, lint_message},
+        subject::suggest_subject,
+        tools::tool_command,
+        diff::hunk_text,
+        clone_dir_name, qualify_push_refspec, BlameLine, CloneOptions, BranchStatus, Churn, CommitDetail, CommitInfo, HistoryStats, CommitRef, Divergence, ConflictSide, DiffFile, ConflictSides, GitRepo, DIFF_SIZE_LIMIT, Hunk, MergeOutcome, PushMode, StashInfo,
+        ReflogEntry, RepoLocation, StatusItem, UndoAction, UntrackedFiles, WorkTreeSummary,
+    },
+    scripting::{ScriptEvent, ScriptRequest, Scripts, SCRIPT_FILE},
+    session::{self, RecentRepo, RecentRepos, Session},
+    task::{self, CancelToken, Task, TaskId, TaskKind},
+    notification::{Notifications, Severity},
+    panel::{Panel, Panels},
+    prompt::PromptState,
+    ui::widgets::{FileTree, TextArea, TreeEntry},
 };
-use crossterm::event::{KeyCode, KeyEvent, MouseEvent, MouseEventKind};
-use log::{debug, error, info};
+use chrono::Local;
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers, MouseButton, MouseEvent, MouseEventKind};
+use git2::StashFlags;
+use log::{debug, error, info, warn};
 use ratatui::{layout::Rect, widgets::ListState, widgets::TableState};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    ops::RangeInclusive,
+    path::{Path, PathBuf},
+    time::{Duration, Instant, SystemTime},
+};
 use tokio::sync::mpsc;
+use unicode_width::UnicodeWidthStr;
+
+/// Number of commits loaded into the log view at a time.
+const LOG_PAGE_SIZE: usize = 200;
+/// How close to the last loaded commit the selection gets before the next
+/// page is fetched.
+const LOG_PREFETCH_MARGIN: usize = 20;
+/// Longest gap between the two clicks of a double click.
+const DOUBLE_CLICK_TIME: Duration = Duration::from_millis(400);
+/// Largest count prefix; `99999j` should not freeze the UI.
+const MAX_COUNT: usize = 999;
+/// How long a multi-key binding waits for its next key, like vim's
+/// `timeoutlen`.
+const SEQUENCE_TIMEOUT: Duration = Duration::from_secs(1);
+/// How often the screen is drawn while nothing happens, to keep times
+/// such as "fetched 2 min ago" current.
+const IDLE_REDRAW_INTERVAL: Duration = Duration::from_secs(1);
+/// How many actions can be undone in a row.
+const UNDO_LIMIT: usize = 50;
+/// Diff lines shown at first, and added by each "load more".
+const DIFF_LINE_LIMIT: usize = 1000;
+
+/// Identifies a cached diff. Staging changes the index, so the index's
+/// modification time is part of the key.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct DiffKey {
+    path: String,
+    staged: bool,
+    index_mtime: Option<SystemTime>,
+}
 
 #[derive(Debug, Clone)]
 pub enum StatusItemType {
     Header(String),
     Item(StatusItem),
+    /// A directory node when the file list is grouped by directory. `path`
+    /// ends with `/`; `count` is the number of files below it.
+    Dir {
+        path: String,
+        count: usize,
+        collapsed: bool,
+    },
 }
 
 #[derive(Debug, PartialEq, Eq)]
@@ -23,23 +104,603 @@ pub enum AppReturn {
     Exit,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum StatusMode {
     FileSelection,
     HunkSelection,
+    /// Picking single lines of the selected hunk, see `App::line_selection`.
+    LineSelection,
+    /// Choosing between the versions of a conflicted file.
+    ConflictResolution,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum Mode {
     Status(StatusMode),
     Log,
+    Stash,
+    Links,
+    Tree,
+    Reflog,
+    Forge,
+    Ignore,
+    /// The history of notifications.
+    Messages,
+    /// The newest lines of the log.
+    Debug,
+    /// Commits per month, the most changed files and the authors.
+    Stats,
+    /// Checks of the repository, remote, identity, hooks and links.
+    Doctor,
+    /// The entries of the repository's and the global git config.
+    GitConfig,
+    /// A panel added with `App::register_panel`, numbered in the order they
+    /// were added.
+    Custom(usize),
+}
+
+/// A push, fetch, pull or clone, kept to offer a retry when it fails.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NetworkOp {
+    /// The push mode and whether to set the upstream.
+    Push(PushMode, bool),
+    /// Push `refspec` to each of `remotes`.
+    PushTo { remotes: Vec<String>, refspec: String },
+    Fetch,
+    Pull,
+    /// Clone `App::clone_url` into `App::clone_path`.
+    Clone,
+}
+
+/// An operation that destroys something and is only carried out once the
+/// user answered `Popup::Confirm` with yes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConfirmAction {
+    StashPop(usize),
+    StashDrop(usize),
+    ForcePush,
+    /// Hard reset to this commit of the reflog. The commit rather than the
+    /// entry's position is kept, since a refresh may shift the entries.
+    ReflogReset(git2::Oid),
+    /// Adopt the link with this target.
+    Adopt(PathBuf),
+    /// Remove the worktree of this name.
+    RemoveWorktree(String),
+    /// Discard the unstaged changes of these paths.
+    Discard(Vec<String>),
+}
+
+impl ConfirmAction {
+    /// The popup the confirmation was opened from, shown again once it is
+    /// answered.
+    fn origin(&self) -> Option<Popup> {
+        match self {
+            ConfirmAction::RemoveWorktree(_) => Some(Popup::Worktrees),
+            _ => None,
+        }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Popup {
     Help,
     Commit,
-    Pushing(String),
+    /// Shows the progress of `App::task`; Esc cancels it.
+    Task,
+    /// Shows an error that did not end the app; `retry`, if any, can be
+    /// started again from here.
+    Error {
+        title: String,
+        message: String,
+        retry: Option<NetworkOp>,
+    },
+    /// Asks whether to carry out `on_confirm`; `y` or Enter does, `n` or
+    /// Esc cancels.
+    Confirm {
+        title: String,
+        message: String,
+        on_confirm: ConfirmAction,
+    },
+    /// Push a branch without upstream; holds the branch name.
+    SetUpstreamConfirm(String),
+    /// Chooses the remotes in `App::push_targets` and the refspec to push
+    /// to them.
+    PushTargets,
+    ProfileSwitch,
+    Worktrees,
+    /// Asks for the path of a new worktree.
+    WorktreeAdd,
+    /// Lists the recently opened repositories.
+    RepoSwitch,
+    /// Lists `App::palette` to run one of its entries.
+    Palette,
+    /// Asks for the URL of a repository to clone.
+    CloneUrl,
+    /// Asks where to clone `App::clone_url` to.
+    ClonePath,
+    /// Asks for a path to check against the ignore rules.
+    IgnoreCheckPath,
+    /// Asks for a new ignore pattern, or for the replacement of the rule at
+    /// this position of `App::ignore_rules`.
+    IgnorePattern(Option<usize>),
+    CredentialPrompt { message: String, secret: bool },
+    /// Shows `App::commit_detail`.
+    CommitDetail,
+    /// The work tree has changes that the operation could overwrite; asks
+    /// whether to stash them first.
+    AutoStashConfirm(RiskyOperation),
+    /// Asks whether to re-apply `App::auto_stash` now that the operation it
+    /// was made for has ended.
+    AutoStashRestore,
+    /// Lists what `App::log_checkout` can check out.
+    LogCheckout,
+    /// Marks commits of `App::bisect`, and shows the first bad one once it
+    /// is found.
+    Bisect,
+    /// Shows `App::divergence`.
+    Divergence,
+    /// Shows the output of `App::hook_run`.
+    Hooks,
+    /// Asks where to write `App::patch_export`.
+    PatchExport,
+    /// Asks for a patch file to apply.
+    PatchApply,
+    /// Renames the current branch and edits its upstream, as held in
+    /// `App::branch_edit`.
+    BranchEdit,
+    /// Offers to keep a detached HEAD on a new branch or to return to one,
+    /// as held in `App::detached_head`.
+    DetachedHead,
+    /// Asks for the name of a branch to create at the detached HEAD.
+    BranchCreate,
+    /// Offers the ignore templates in `App::ignore_templates`.
+    IgnoreTemplates,
+    /// Offers the links found by `App::link_import`.
+    LinkImport,
+    /// Asks for the passphrase or identity that decrypts the secret of the
+    /// link at this position of `App::links`, which is deployed once given.
+    SecretPrompt(usize),
+    /// Shows what applying the links did, as held in `App::apply_results`.
+    ApplyResults,
+    /// Asks for the name of a git config entry to set.
+    ConfigName,
+    /// Asks for the value of this git config entry in
+    /// `App::git_config_scope`; an empty value unsets it.
+    ConfigValue(String),
+}
+
+/// An entry of `Popup::Palette`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PaletteEntry {
+    /// An action of the user's scripts.
+    Script(String),
+    /// A key of the current view and what it does.
+    Key(KeyEvent, &'static str),
+    /// A panel added with `App::register_panel`, which has no key, and its
+    /// title.
+    Panel(Mode, String),
+}
+
+/// What can be exported as a patch from the status view.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PatchScope {
+    File(StatusItem),
+    /// The hunk at this position of the file's diff.
+    Hunk(StatusItem, usize),
+    /// Everything staged.
+    Staged,
+}
+
+impl PatchScope {
+    pub fn label(&self) -> String {
+        match self {
+            PatchScope::File(item) => format!("the changes to {}", item.path),
+            PatchScope::Hunk(item, _) => format!("the selected hunk of {}", item.path),
+            PatchScope::Staged => "all staged changes".to_string(),
+        }
+    }
+
+    /// The file name suggested for the patch, e.g. `~/bashrc.patch`.
+    fn file_name(&self) -> String {
+        let name = match self {
+            PatchScope::File(item) | PatchScope::Hunk(item, _) => Path::new(&item.path)
+                .file_name()
+                .map(|name| name.to_string_lossy().trim_start_matches('.').to_string())
+                .unwrap_or_default(),
+            PatchScope::Staged => "staged".to_string(),
+        };
+        format!("~/{}.patch", name)
+    }
+}
+
+/// The choices of `Popup::PatchExport`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PatchExport {
+    pub scopes: Vec<PatchScope>,
+    pub selected: usize,
+}
+
+impl PatchExport {
+    pub fn scope(&self) -> &PatchScope {
+        &self.scopes[self.selected]
+    }
+}
+
+/// The part of the branch popup that receives typed keys.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BranchField {
+    Name,
+    Remote,
+    Merge,
+}
+
+/// The field of `Popup::ClonePath` that receives typed keys.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CloneField {
+    Path,
+    /// How many commits to fetch; all of them when empty.
+    Depth,
+    /// The only branch to fetch; all of them when empty.
+    Branch,
+}
+
+/// The fields of `Popup::BranchEdit`.
+#[derive(Debug, Clone)]
+pub struct BranchEdit {
+    /// The branch as it is named now.
+    pub branch: String,
+    pub name: PromptState,
+    /// The upstream's remote; left empty, the branch has no upstream.
+    pub remote: PromptState,
+    /// The upstream branch on `remote`, without `refs/heads/`.
+    pub merge: PromptState,
+    pub focus: BranchField,
+}
+
+impl BranchEdit {
+    pub fn field(&self, field: BranchField) -> &PromptState {
+        match field {
+            BranchField::Name => &self.name,
+            BranchField::Remote => &self.remote,
+            BranchField::Merge => &self.merge,
+        }
+    }
+
+    fn field_mut(&mut self, field: BranchField) -> &mut PromptState {
+        match field {
+            BranchField::Name => &mut self.name,
+            BranchField::Remote => &mut self.remote,
+            BranchField::Merge => &mut self.merge,
+        }
+    }
+}
+
+/// Refuses names git does not allow for a branch, such as `a..b` or one
+/// with a space.
+fn validate_branch_name(name: &str) -> Result<(), String> {
+    match git2::Branch::name_is_valid(name) {
+        Ok(true) => Ok(()),
+        _ => Err(format!("'{}' is not a valid branch name", name)),
+    }
+}
+
+fn validate_clone_depth(depth: &str) -> Result<(), String> {
+    match depth.parse::<u32>() {
+        Ok(depth) if depth > 0 => Ok(()),
+        _ => Err(format!("'{}' is not a number of commits", depth)),
+    }
+}
+
+fn validate_push_refspec(refspec: &str) -> Result<(), String> {
+    qualify_push_refspec(refspec).map(|_| ())
+}
+
+/// The commit hooks running before a commit, and what they printed.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HookRun {
+    pub output: Vec<String>,
+    /// Set when a hook failed, which aborts the commit.
+    pub error: Option<String>,
+}
+
+/// An operation that rewrites the work tree and so may clobber local
+/// changes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RiskyOperation {
+    Pull,
+    CheckoutBranch(String),
+    /// A detached checkout.
+    CheckoutCommit(git2::Oid),
+    /// Switch to the profile at this position of `App::profiles`, which
+    /// checks out its branch.
+    SwitchProfile(usize),
+}
+
+impl RiskyOperation {
+    pub fn name(&self) -> String {
+        match self {
+            RiskyOperation::Pull => "pull".to_string(),
+            RiskyOperation::CheckoutBranch(branch) => format!("checkout of {}", branch),
+            RiskyOperation::CheckoutCommit(oid) => format!("checkout of {:.7}", oid.to_string()),
+            RiskyOperation::SwitchProfile(_) => "profile switch".to_string(),
+        }
+    }
+}
+
+/// Local changes stashed before a risky operation, to be re-applied once it
+/// ended.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AutoStash {
+    /// The stash commit; its `stash@{n}` position shifts with newer stashes.
+    pub oid: git2::Oid,
+    pub message: String,
+    /// `RiskyOperation::name` of the operation.
+    pub operation: String,
+}
+
+/// An external diff or merge tool for the main loop to run with the TUI
+/// suspended.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ToolRequest {
+    /// Program and arguments.
+    pub command: Vec<String>,
+    /// Holds the copies made for the tool; removed once it exits.
+    pub temp_dir: PathBuf,
+}
+
+/// Text for the main loop to put on the clipboard.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClipboardRequest {
+    pub text: String,
+    /// What the text is, for the toast, e.g. "commit hash 1a2b3c4".
+    pub what: String,
+}
+
+/// The lines picked in `StatusMode::LineSelection`, as indices into the
+/// lines of the selected hunk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LineRange {
+    /// Where `V` started a range; without it only the cursor line is picked.
+    pub anchor: Option<usize>,
+    pub cursor: usize,
+}
+
+impl LineRange {
+    pub fn lines(self) -> RangeInclusive<usize> {
+        let anchor = self.anchor.unwrap_or(self.cursor);
+        anchor.min(self.cursor)..=anchor.max(self.cursor)
+    }
+}
+
+/// Where the last frame drew the app's panels, so that mouse events hit
+/// what is actually on screen.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LayoutRects {
+    /// Between the tabs and the status bar.
+    pub main: Rect,
+    /// The file list of the status view or the tree of the tree view; empty
+    /// in other views.
+    pub files: Rect,
+    /// The diff of the status view or the preview of the tree view.
+    pub diff: Rect,
+    /// The rows of the log table, below its header.
+    pub log_rows: Rect,
+    /// The header cells of the sortable log columns, in `LogColumn::ALL`
+    /// order.
+    pub log_headers: [Rect; 3],
+}
+
+/// A column the log table can be sorted by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogColumn {
+    Hash,
+    Author,
+    Date,
+}
+
+impl LogColumn {
+    pub const ALL: [LogColumn; 3] = [LogColumn::Hash, LogColumn::Author, LogColumn::Date];
+}
+
+/// A diff between two commits, or a commit and the work tree, shown in
+/// place of the log table.
+#[derive(Debug, Clone)]
+pub struct Compare {
+    /// Short names of both sides, e.g. a short hash or "work tree".
+    pub from: String,
+    pub to: String,
+    pub files: Vec<DiffFile>,
+    pub list_state: ListState,
+}
+
+impl Compare {
+    pub fn selected_file(&self) -> Option<&DiffFile> {
+        self.files.get(self.list_state.selected()?)
+    }
+}
+
+/// The branches and tags at a log commit, offered for checkout. The entry
+/// after the last of `refs` checks out the commit itself.
+#[derive(Debug, Clone)]
+pub struct LogCheckout {
+    pub oid: git2::Oid,
+    pub refs: Vec<CommitRef>,
+    pub list_state: ListState,
+}
+
+/// A bisection started from the log view.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BisectState {
+    pub marks: Bisect,
+    pub step: BisectStep,
+    /// Checks out where HEAD was before the bisection, once it ends.
+    pub origin: RiskyOperation,
+}
+
+/// How far `Popup::Help` is scrolled and whether it lists the keys of every
+/// view or only those of the current one.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct HelpState {
+    /// Clamped to the end of the help when it is drawn.
+    pub scroll: u16,
+    pub all_modes: bool,
+}
+
+/// The templates offered by `Popup::IgnoreTemplates`, each with whether it
+/// is chosen.
+#[derive(Debug, Clone)]
+pub struct TemplatePicker {
+    pub matches: Vec<TemplateMatch>,
+    pub chosen: Vec<bool>,
+    pub list_state: ListState,
+}
+
+/// The links offered by `Popup::LinkImport`, each with whether it is
+/// chosen.
+#[derive(Debug, Clone)]
+pub struct ImportPicker {
+    pub import: Import,
+    pub chosen: Vec<bool>,
+    pub list_state: ListState,
+}
+
+/// The remotes offered by `Popup::PushTargets`, each with whether it is
+/// chosen.
+#[derive(Debug, Clone)]
+pub struct PushTargets {
+    pub remotes: Vec<String>,
+    /// The URL of each remote, to tell mirrors apart.
+    pub urls: Vec<String>,
+    pub chosen: Vec<bool>,
+    pub list_state: ListState,
+    /// Whether keys go to `App::push_refspec` instead of the list.
+    pub editing_refspec: bool,
+}
+
+/// The choices of `Popup::DetachedHead`: a new branch at HEAD, then the
+/// local branches to return to.
+#[derive(Debug, Clone)]
+pub struct DetachedHead {
+    pub oid: git2::Oid,
+    pub branches: Vec<String>,
+    /// The commits of HEAD that no branch contains.
+    pub commits_on_no_branch: usize,
+    pub list_state: ListState,
+}
+
+/// The commits only on HEAD and only on its upstream, each list with a
+/// selection, and the diff of the selected commit.
+#[derive(Debug, Clone)]
+pub struct DivergenceView {
+    pub divergence: Divergence,
+    /// Whether the upstream's commits have the focus rather than the
+    /// local ones.
+    pub upstream_focused: bool,
+    pub ahead_state: ListState,
+    pub behind_state: ListState,
+    /// The changes of the selected commit.
+    pub diff: Vec<DiffFile>,
+    pub diff_scroll: u16,
+}
+
+impl DivergenceView {
+    pub fn selected_commit(&self) -> Option<&CommitInfo> {
+        match self.upstream_focused {
+            false => self.divergence.ahead.get(self.ahead_state.selected()?),
+            true => self.divergence.behind.get(self.behind_state.selected()?),
+        }
+    }
+}
+
+/// Sorting of the log table other than history order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LogSort {
+    pub column: LogColumn,
+    pub descending: bool,
+}
+
+/// Whether a path is ignored, and which rule decides it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IgnoreCheck {
+    pub path: String,
+    pub ignored: bool,
+    /// `None` when no listed rule matches, e.g. because the pattern is in
+    /// an untracked `.gitignore` below the root.
+    pub rule: Option<IgnoreRule>,
+}
+
+/// A credential request waiting for the user, plus the popup it replaced.
+#[derive(Debug)]
+pub struct PendingCredential {
+    pub request: CredentialRequest,
+    pub previous_popup: Option<Popup>,
+}
+
+/// An active search in the log view.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogSearch {
+    pub query: String,
+    /// Positions of the matching commits in the full history, ascending.
+    pub matches: Vec<usize>,
+}
+
+/// Commit types offered by the conventional-commit helper.
+pub const COMMIT_TYPES: &[&str] = &[
+    "feat", "fix", "docs", "style", "refactor", "perf", "test", "build", "ci", "chore", "revert",
+];
+
+/// The part of the commit popup that receives typed keys.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CommitField {
+    Type,
+    Scope,
+    Message,
+}
+
+/// The conventional-commit helper of the commit popup.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConventionalCommit {
+    pub enabled: bool,
+    /// Index into `COMMIT_TYPES`.
+    pub type_index: usize,
+    pub scope: String,
+    pub focus: CommitField,
+}
+
+impl ConventionalCommit {
+    /// The `type(scope): ` prefix put in front of the subject.
+    pub fn prefix(&self) -> String {
+        let kind = COMMIT_TYPES[self.type_index];
+        if self.scope.is_empty() {
+            format!("{}: ", kind)
+        } else {
+            format!("{}({}): ", kind, self.scope)
+        }
+    }
+}
+
+/// An action that can be undone, with the description shown when it is.
+#[derive(Debug, Clone)]
+struct UndoEntry {
+    description: String,
+    action: UndoAction,
+}
+
+/// What the doctor view shows while the checks run in the background.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DoctorView {
+    Loading,
+    Loaded(Vec<Check>),
+}
+
+/// What the stats view shows while the history is read in the background.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StatsView {
+    Loading,
+    Loaded(HistoryStats),
+    Failed(String),
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -50,465 +711,7485 @@ pub enum ActivePanel {
 
 pub struct App {
     pub repo: GitRepo,
+    pub config: Config,
     pub keys: KeyBindings,
     pub mode: Mode,
     pub popup: Option<Popup>,
     pub status_display_list: Vec<StatusItemType>,
     pub status_list_state: ListState,
+    /// Branch and upstream details for the status bar.
+    pub branch_status: BranchStatus,
+    /// Every changed file, before `status_filter` is applied.
+    status_items: Vec<StatusItem>,
+    /// The totals of the staged and unstaged changes, shown above the file
+    /// list.
+    pub work_tree_summary: WorkTreeSummary,
+    /// Fuzzy filter for the file list; empty shows every file.
+    pub status_filter: String,
+    /// Whether the file list is grouped into directory nodes.
+    pub group_by_dir: bool,
+    /// Directories whose files are hidden while grouping.
+    collapsed_dirs: HashSet<String>,
+    /// The untracked directories listed file by file although
+    /// `collapse_untracked_dirs` is set.
+    expanded_untracked_dirs: Vec<String>,
+    /// The files marked for a batch stage or unstage, by path and whether
+    /// they are staged.
+    marked_items: HashSet<(String, bool)>,
     pub log_entries: Vec<CommitInfo>,
     pub log_table_state: TableState,
-    pub commit_msg: String,
-    pub cursor_pos: usize,
+    /// Whether `log_entries` already holds the whole history.
+    log_exhausted: bool,
+    /// One row of the commit graph per entry in `log_entries`.
+    pub log_graph: Vec<String>,
+    /// The width of the widest row of `log_graph`.
+    pub log_graph_width: usize,
+    pub log_search: Option<LogSearch>,
+    /// Limits the log view to the commits that changed this path.
+    pub log_path: Option<String>,
+    /// How the log table is sorted; `None` is history order.
+    pub log_sort: Option<LogSort>,
+    /// The position in `log_entries` of each row of the log table while it
+    /// is sorted or narrowed to the matches of `log_search`; `None` shows
+    /// every commit in history order.
+    log_order: Option<Vec<usize>>,
+    /// The commit marked with `m` as the first side of a comparison.
+    pub log_mark: Option<git2::Oid>,
+    /// The comparison shown in the log view, if any.
+    pub compare: Option<Compare>,
+    pub log_checkout: Option<LogCheckout>,
+    pub bisect: Option<BisectState>,
+    pub divergence: Option<DivergenceView>,
+    /// The commit shown by `Popup::CommitDetail`.
+    pub commit_detail: Option<CommitDetail>,
+    /// The query typed after `/`.
+    pub search_input: PromptState,
+    /// Whether `search_input` is open; keys then go to it.
+    pub searching: bool,
+    pub stash_entries: Vec<StashInfo>,
+    pub stash_table_state: TableState,
+    pub reflog: ReflogState,
+    /// Every ignore pattern that applies to the work tree, lowest precedence
+    /// first.
+    pub ignore_rules: Vec<IgnoreRule>,
+    pub ignore_table_state: TableState,
+    /// The path last checked in the ignore view.
+    pub ignore_check: Option<IgnoreCheck>,
+    pub ignore_input: PromptState,
+    pub forge: ForgeState,
+    pub stats_view: StatsView,
+    pub doctor_view: DoctorView,
+    pub doctor_table_state: TableState,
+    pub git_config: Vec<ConfigEntry>,
+    pub git_config_error: Option<String>,
+    pub git_config_table_state: TableState,
+    /// The config file the git config view edits.
+    pub git_config_scope: ConfigScope,
+    pub config_name: PromptState,
+    pub config_value: PromptState,
+    pub links: Vec<LinkEntry>,
+    pub links_error: Option<String>,
+    pub links_table_state: TableState,
+    /// The machine profiles defined in the link manifest.
+    pub profiles: Vec<Profile>,
+    pub active_profile: Option<Profile>,
+    pub profile_list_state: ListState,
+    pub file_tree: FileTree,
+    /// Blame of the file previewed in the tree view, when toggled on.
+    pub tree_blame: Option<(String, Vec<BlameLine>)>,
+    /// The path and text of the file previewed in the tree view, read once
+    /// per selected file and refresh rather than on every frame.
+    tree_preview: Option<(String, String)>,
+    pub commit_editor: TextArea,
+    /// Whether the commit being written will be signed.
+    pub sign_commit: bool,
+    /// Commit without running the hooks, like `git commit --no-verify`.
+    pub no_verify: bool,
+    pub hook_run: Option<HookRun>,
+    pub conventional: ConventionalCommit,
+    /// Problems found in the commit message, shown under the editor.
+    pub commit_warnings: Vec<String>,
+    /// Set when a commit was attempted despite warnings, so that the next
+    /// attempt goes through.
+    commit_warnings_confirmed: bool,
+    #[cfg(feature = "spellcheck")]
+    dictionary: Option<lint::Dictionary>,
     exiting: bool,
     app_event_sender: mpsc::UnboundedSender<AppEvent>,
+    /// Reads status, log and diffs off the UI thread.
+    git: GitWorker,
+    /// Generation of the last refresh sent to `git`, and of the last one
+    /// applied. They differ while a refresh is loading.
+    refresh_requested: u64,
+    refresh_loaded: u64,
+    /// Counts the ticks while the status loads, to turn the spinner.
+    pub spinner_frame: usize,
+    diff_requested: u64,
+    diff_loaded: u64,
+    /// Generations of the log searches sent to the git worker and answered.
+    log_search_requested: u64,
+    log_search_loaded: u64,
+    /// Whether the answer of the log search selects its first match.
+    log_search_jump: bool,
+    /// Generations of the requests for the whole history and of the last
+    /// one answered.
+    whole_log_requested: u64,
+    whole_log_loaded: u64,
+    /// Whether the oldest commit is selected once the whole history loaded.
+    log_select_last: bool,
+    /// The file whose diff was requested last.
+    diff_item: Option<StatusItem>,
+    /// Diffs loaded since the last refresh, so that moving through the file
+    /// list does not ask the worker for the same diff again.
+    diff_cache: HashMap<DiffKey, FileDiff>,
+    /// The churn of each status item by path and stagedness, with what it
+    /// was computed from. `None` while the worker is counting.
+    churn: HashMap<(String, bool), (ChurnKey, Option<Churn>)>,
+    /// The diff shown next to the file list; rendering reads only this.
+    pub diff: Option<FileDiff>,
     pub current_hunks: Vec<Hunk>,
     pub hunk_list_state: ListState,
+    pub line_selection: Option<LineRange>,
     pub active_panel: ActivePanel,
+    pub diff_scroll: u16,
+    /// Lines of the diff shown before the rest is cut off.
+    pub diff_line_limit: usize,
+    pub pending_credential: Option<PendingCredential>,
+    /// The conflicted file shown in `StatusMode::ConflictResolution`.
+    pub conflict: Option<ConflictSides>,
+    /// A file the main loop should open in `$EDITOR`.
+    editor_request: Option<PathBuf>,
+    /// An external tool the main loop should run.
+    tool_request: Option<ToolRequest>,
+    /// Text the main loop should copy to the clipboard.
+    clipboard_request: Option<ClipboardRequest>,
+    pub patch_export: Option<PatchExport>,
+    pub branch_edit: Option<BranchEdit>,
+    pub detached_head: Option<DetachedHead>,
+    pub ignore_templates: Option<TemplatePicker>,
+    pub link_import: Option<ImportPicker>,
+    pub apply_results: Vec<ApplyResult>,
+    pub secret_input: String,
+    /// The passphrase or age identity file entered this session, kept so
+    /// that the next secret does not ask again.
+    secret: Option<String>,
+    pub push_targets: Option<PushTargets>,
+    pub push_refspec: PromptState,
+    pub help: HelpState,
+    /// The name typed into `Popup::BranchCreate`.
+    pub branch_input: PromptState,
+    /// The file typed into `Popup::PatchExport` or `Popup::PatchApply`.
+    pub patch_input: PromptState,
+    /// Set when `repo` was replaced, so that the caller can watch the new one.
+    repo_switched: bool,
+    pub worktrees: WorktreeState,
+    pub repo_list_state: ListState,
+    pub palette: Vec<PaletteEntry>,
+    pub palette_state: ListState,
+    /// The handlers and actions of `init.lua`.
+    scripts: Scripts,
+    /// The views that draw themselves, with the modes that show them.
+    pub panels: Panels,
+    pub clone_url: PromptState,
+    pub clone_path: PromptState,
+    pub clone_depth: PromptState,
+    pub clone_branch: PromptState,
+    pub clone_field: CloneField,
+    /// The push, fetch, pull or clone running in the background.
+    pub task: Option<Task>,
+    /// The network operation started last, retried from `Popup::Error`.
+    network_op: Option<NetworkOp>,
+    /// Changes stashed before the running risky operation.
+    pub auto_stash: Option<AutoStash>,
+    next_task_id: TaskId,
+    /// When the last quiet background fetch was due.
+    last_auto_fetch: Instant,
+    /// Whether the screen is out of date. Ticks where nothing changed leave
+    /// it clean, so an idle app does not draw.
+    needs_redraw: bool,
+    last_drawn: Instant,
+    auto_fetching: bool,
+    /// Whether the last quiet fetch failed, so that a failure that keeps
+    /// recurring is reported once.
+    auto_fetch_failed: bool,
+    pub sync: SyncMode,
+    /// Most recent last.
+    undo_stack: Vec<UndoEntry>,
+    pub notifications: Notifications,
+    pub messages_list_state: ListState,
+    /// How many lines the debug view is scrolled up from the newest one,
+    /// which it follows at 0.
+    pub debug_scroll: u16,
+    /// Whether the TUI should capture the mouse; the main loop applies it.
+    pub mouse_capture: bool,
+    /// A count typed before a navigation key, e.g. the 5 of `5j`.
+    pending_count: Option<usize>,
+    /// The keys typed so far of a multi-key binding, e.g. the `g` of `gg`,
+    /// and when the last of them was pressed.
+    pending_keys: Vec<KeyEvent>,
+    pending_since: Instant,
+    macros: MacroState,
+    /// How often the next navigation action runs, from its count.
+    repeat: usize,
+    /// Where the last frame drew each panel; set while rendering.
+    pub layout: LayoutRects,
+    /// When and on which row the mouse was last clicked, to detect double
+    /// clicks.
+    last_click: Option<(Instant, usize)>,
+    pub credential_input: String,
+    /// The saved session, whose selections are applied once the first
+    /// refresh has loaded the lists they refer to.
+    pending_session: Option<Session>,
+    /// The repositories opened last, offered by the repository switcher.
+    pub recent: RecentRepos,
 }
 
 impl App {
-    pub fn new(repo: GitRepo, event_handler: &EventHandler) -> Self {
+    /// Background tasks report back through `app_event_sender`, usually
+    /// `EventHandler::get_app_event_sender`. Fails only when the git worker
+    /// cannot be started; a repository that cannot be read yet, e.g. with a
+    /// broken index, opens with the error shown in a popup.
+    pub fn new(
+        repo: GitRepo,
+        config: Config,
+        app_event_sender: mpsc::UnboundedSender<AppEvent>,
+    ) -> AppResult<Self> {
+        let git = GitWorker::spawn(repo.location(), app_event_sender.clone())?;
+        let mouse_capture = !config.disable_mouse;
+        let sync = config.sync && !config.read_only;
+        let diff_line_limit = config.diff_line_limit.unwrap_or(DIFF_LINE_LIMIT);
         let mut app = Self {
             repo,
-            keys: KeyBindings::default(),
+            keys: config.keys.clone(),
+            config,
             mode: Mode::Status(StatusMode::FileSelection),
             popup: None,
             status_display_list: Vec::new(),
             status_list_state: ListState::default(),
+            branch_status: BranchStatus::default(),
+            status_items: Vec::new(),
+            work_tree_summary: WorkTreeSummary::default(),
+            status_filter: String::new(),
+            group_by_dir: false,
+            collapsed_dirs: HashSet::new(),
+            expanded_untracked_dirs: Vec::new(),
+            marked_items: HashSet::new(),
             log_entries: Vec::new(),
             log_table_state: TableState::default(),
-            commit_msg: String::new(),
-            cursor_pos: 0,
+            log_exhausted: false,
+            log_graph: Vec::new(),
+            log_graph_width: 0,
+            log_search: None,
+            log_path: None,
+            log_sort: None,
+            log_order: None,
+            log_mark: None,
+            compare: None,
+            log_checkout: None,
+            bisect: None,
+            divergence: None,
+            commit_detail: None,
+            search_input: PromptState::new(),
+            searching: false,
+            stash_entries: Vec::new(),
+            stash_table_state: TableState::default(),
+            reflog: ReflogState::default(),
+            ignore_rules: Vec::new(),
+            ignore_table_state: TableState::default(),
+            ignore_check: None,
+            ignore_input: PromptState::new(),
+            forge: ForgeState::default(),
+            stats_view: StatsView::Loading,
+            doctor_view: DoctorView::Loading,
+            doctor_table_state: TableState::default(),
+            git_config: Vec::new(),
+            git_config_error: None,
+            git_config_table_state: TableState::default(),
+            git_config_scope: ConfigScope::Global,
+            config_name: PromptState::with_validator(gitconfig::validate_name),
+            config_value: PromptState::new(),
+            links: Vec::new(),
+            links_error: None,
+            links_table_state: TableState::default(),
+            profiles: Vec::new(),
+            active_profile: None,
+            profile_list_state: ListState::default(),
+            file_tree: FileTree::default(),
+            tree_blame: None,
+            tree_preview: None,
+            commit_editor: TextArea::new(),
+            sign_commit: false,
+            no_verify: false,
+            hook_run: None,
+            conventional: ConventionalCommit {
+                enabled: false,
+                type_index: 0,
+                scope: String::new(),
+                focus: CommitField::Message,
+            },
+            commit_warnings: Vec::new(),
+            commit_warnings_confirmed: false,
+            #[cfg(feature = "spellcheck")]
+            dictionary: None,
             exiting: false,
-            app_event_sender: event_handler.get_app_event_sender(),
+            app_event_sender,
+            git,
+            refresh_requested: 0,
+            refresh_loaded: 0,
+            spinner_frame: 0,
+            diff_requested: 0,
+            diff_loaded: 0,
+            log_search_requested: 0,
+            log_search_loaded: 0,
+            log_search_jump: false,
+            whole_log_requested: 0,
+            whole_log_loaded: 0,
+            log_select_last: false,
+            diff_item: None,
+            diff_cache: HashMap::new(),
+            churn: HashMap::new(),
+            diff: None,
             current_hunks: Vec::new(),
             hunk_list_state: ListState::default(),
+            line_selection: None,
             active_panel: ActivePanel::Files,
+            diff_scroll: 0,
+            diff_line_limit,
+            pending_credential: None,
+            conflict: None,
+            editor_request: None,
+            tool_request: None,
+            clipboard_request: None,
+            patch_export: None,
+            branch_edit: None,
+            detached_head: None,
+            ignore_templates: None,
+            link_import: None,
+            apply_results: Vec::new(),
+            secret_input: String::new(),
+            secret: None,
+            push_targets: None,
+            push_refspec: PromptState::with_validator(validate_push_refspec),
+            help: HelpState::default(),
+            branch_input: PromptState::with_validator(validate_branch_name),
+            patch_input: PromptState::new(),
+            repo_switched: false,
+            worktrees: WorktreeState::default(),
+            repo_list_state: ListState::default(),
+            palette: Vec::new(),
+            palette_state: ListState::default(),
+            scripts: Scripts::default(),
+            panels: Panels::default(),
+            clone_url: PromptState::new(),
+            clone_path: PromptState::new(),
+            clone_depth: PromptState::with_validator(validate_clone_depth),
+            clone_branch: PromptState::with_validator(validate_branch_name),
+            clone_field: CloneField::Path,
+            task: None,
+            network_op: None,
+            auto_stash: None,
+            next_task_id: 0,
+            last_auto_fetch: Instant::now(),
+            needs_redraw: true,
+            last_drawn: Instant::now(),
+            auto_fetching: false,
+            auto_fetch_failed: false,
+            sync: SyncMode::new(sync),
+            undo_stack: Vec::new(),
+            notifications: Notifications::default(),
+            messages_list_state: ListState::default(),
+            debug_scroll: 0,
+            mouse_capture,
+            pending_count: None,
+            pending_keys: Vec::new(),
+            pending_since: Instant::now(),
+            macros: MacroState::default(),
+            repeat: 1,
+            layout: LayoutRects::default(),
+            last_click: None,
+            credential_input: String::new(),
+            pending_session: None,
+            recent: RecentRepos::default(),
         };
-        app.refresh().unwrap();
-        app
-    }
-
-    pub fn is_exiting(&self) -> bool {
-        self.exiting
+        app.restore_session();
+        app.load_recent();
+        app.load_scripts();
+        if let Err(e) = app.refresh() {
+            app.show_error("Refresh failed", &e, None);
+        }
+        app.remember_repo();
+        Ok(app)
     }
 
-    pub fn refresh(&mut self) -> AppResult<()> {
-        info!("Refreshing app state...");
-        let raw_status_items = self.repo.get_status()?;
-        self.log_entries = self.repo.get_log()?;
-        self.status_display_list.clear();
-        let (staged, unstaged): (Vec<_>, Vec<_>) =
-            raw_status_items.into_iter().partition(|i| i.is_staged);
-
-        if !staged.is_empty() {
-            self.status_display_list
-                .push(StatusItemType::Header("Staged changes:".to_string()));
-            self.status_display_list
-                .extend(staged.into_iter().map(StatusItemType::Item));
-        }
-        if !unstaged.is_empty() {
-            self.status_display_list
-                .push(StatusItemType::Header("Unstaged changes:".to_string()));
-            self.status_display_list
-                .extend(unstaged.into_iter().map(StatusItemType::Item));
+    /// Runs `init.lua` next to the config file, when there is one. A script
+    /// that fails is reported and leaves no handlers behind.
+    fn load_scripts(&mut self) {
+        let Some(path) = self.config.path.as_ref().map(|path| path.with_file_name(SCRIPT_FILE)) else {
+            return;
+        };
+        match Scripts::load(&path, &self.repo.location()) {
+            Ok(scripts) => self.scripts = scripts,
+            Err(e) => {
+                warn!("Could not load {}: {}", path.display(), e);
+                self.notify(Severity::Error, e.to_string());
+            }
         }
+    }
 
-        info!(
-            "Refresh complete. Display list has {} items.",
-            self.status_display_list.len()
-        );
+    /// Calls the scripts' handlers of `event` and carries out their requests.
+    fn emit_script_event(&mut self, event: ScriptEvent, argument: Option<&str>) -> AppResult<()> {
+        let result = self.scripts.emit(event, argument);
+        self.apply_script_requests(result)
+    }
 
-        if self.status_display_list.is_empty() {
-            self.status_list_state.select(None);
-        } else {
-            if let Some(selected) = self.status_list_state.selected() {
-                if selected >= self.status_display_list.len() {
-                    self.status_list_state
-                        .select(Some(self.status_display_list.len() - 1));
-                }
-            } else {
-                self.status_list_state.select(Some(0));
+    /// Carries out what a script asked for, or reports why it failed.
+    fn apply_script_requests(&mut self, result: AppResult<Vec<ScriptRequest>>) -> AppResult<()> {
+        let requests = match result {
+            Ok(requests) => requests,
+            Err(e) => {
+                warn!("{}", e);
+                self.notify(Severity::Error, e.to_string());
+                return Ok(());
+            }
+        };
+        let mut refresh = false;
+        for request in requests {
+            match request {
+                ScriptRequest::Notify(message) => self.notify(Severity::Info, message),
+                ScriptRequest::Refresh => refresh = true,
             }
-            self.skip_headers_forward();
         }
-
-        if self.log_entries.is_empty() {
-            self.log_table_state.select(None);
-        } else if self.log_table_state.selected().is_none() {
-            self.log_table_state.select(Some(0));
+        if refresh {
+            self.refresh()?;
         }
         Ok(())
     }
 
-    pub fn handle_key_event(&mut self, key: KeyEvent) -> AppResult<AppReturn> {
-        debug!("Received key event: {:?}", key.code);
-        if self.popup.is_some() {
-            let popup = self.popup.clone().unwrap();
-            return self.handle_popup_keys(key, popup);
-        }
-        if key == self.keys.quit {
-            if let Mode::Status(StatusMode::HunkSelection) = self.mode {
-                info!("Quitting HunkSelection mode, returning to FileSelection");
-                self.mode = Mode::Status(StatusMode::FileSelection);
-                self.current_hunks.clear();
-                self.hunk_list_state.select(None);
-                return Ok(AppReturn::Continue);
-            }
-            self.exiting = true;
-            return Ok(AppReturn::Exit);
-        }
-        if key == self.keys.show_help {
-            self.popup = Some(Popup::Help);
-            return Ok(AppReturn::Continue);
+    /// Lists the scripts' actions and the keys of the current view, which
+    /// `Popup::Palette` runs.
+    fn open_palette(&mut self) {
+        let mut entries: Vec<PaletteEntry> = self.scripts.actions().into_iter().map(PaletteEntry::Script).collect();
+        for (key, action) in self.keys.commands(self.mode) {
+            let description = action.description(self.mode);
+            // Keys that do the same, like `enter` and `space` in the tree,
+            // are listed once.
+            let listed = entries
+                .iter()
+                .any(|entry| matches!(entry, PaletteEntry::Key(_, listed) if *listed == description));
+            if !listed && !matches!(action, Action::OpenPalette) {
+                entries.push(PaletteEntry::Key(key, description));
+            }
         }
-        match self.mode {
-            Mode::Status(sub_mode) => self.handle_status_keys(key, sub_mode)?,
-            Mode::Log => self.handle_log_keys(key)?,
+        for (mode, title) in self.panels.custom() {
+            entries.push(PaletteEntry::Panel(mode, title.to_string()));
         }
-        Ok(AppReturn::Continue)
+        self.palette = entries;
+        self.palette_state.select(Some(0));
+        self.popup = Some(Popup::Palette);
     }
 
-    pub fn handle_mouse_event(&mut self, event: MouseEvent) -> AppResult<()> {
-        debug!("Received mouse event: {:?}", event);
-        if let Mode::Status(_) = self.mode {
-            let terminal_width = 200;
-            let files_panel_width = (terminal_width as f32 * 0.4) as u16;
-
-            let files_panel_rect = Rect::new(0, 1, files_panel_width, 999);
-            let diff_panel_rect = Rect::new(files_panel_width, 1, terminal_width - files_panel_width, 999);
-
-            match event.kind {
-                MouseEventKind::ScrollUp => {
-                    if self.active_panel == ActivePanel::Files {
-                        self.select_previous_status_item();
-                    }
-                }
-                MouseEventKind::ScrollDown => {
-                    if self.active_panel == ActivePanel::Files {
-                        self.select_next_status_item();
-                    }
-                }
-                MouseEventKind::Down(_) => {
-                    if is_inside(event.column, event.row, files_panel_rect) {
-                        self.active_panel = ActivePanel::Files;
-                        let index = (event.row.saturating_sub(1)) as usize;
-                        if index < self.status_display_list.len() {
-                            self.status_list_state.select(Some(index));
-                            self.skip_headers_forward();
-                        }
-                    } else if is_inside(event.column, event.row, diff_panel_rect) {
-                        self.active_panel = ActivePanel::Diff;
-                    }
-                }
-                _ => {}
-            }
-        }
-        Ok(())
+    /// Adds a panel with a tab after the built-in ones, opened from the
+    /// command palette. Returns the mode that shows it.
+    pub fn register_panel(&mut self, panel: Box<dyn Panel>) -> Mode {
+        let mode = self.panels.register(panel);
+        info!("Registered the panel {}", self.panels.title(mode).unwrap_or_default());
+        mode
     }
 
-    pub fn handle_app_event(&mut self, event: AppEvent) -> AppResult<()> {
-        match event {
-            AppEvent::PushFinished(result) => {
-                let msg = match result {
-                    Ok(_) => {
-                        info!("Async push operation completed successfully.");
-                        "Push successful!".to_string()
-                    }
-                    Err(e) => {
-                        error!("Async push operation failed: {}", e);
-                        format!("Push failed: {}", e)
-                    }
-                };
-                self.popup = Some(Popup::Pushing(msg));
-            }
-        }
-        Ok(())
+    /// Calls `f` with the panel shown in `mode`, which is taken out of
+    /// `panels` meanwhile so that it can change the app. `None` when no
+    /// panel shows `mode`.
+    pub fn with_panel<T>(&mut self, mode: Mode, f: impl FnOnce(&mut dyn Panel, &mut App) -> T) -> Option<T> {
+        let (index, mut panel) = self.panels.take(mode)?;
+        let result = f(panel.as_mut(), self);
+        self.panels.put_back(index, mode, panel);
+        Some(result)
     }
 
-    fn handle_popup_keys(&mut self, key: KeyEvent, popup: Popup) -> AppResult<AppReturn> {
-        match popup {
-            Popup::Commit => {
-                if key == self.keys.close_popup {
-                    self.popup = None;
-                } else if key == self.keys.confirm {
-                    self.submit_commit()?;
-                } else {
-                    self.handle_commit_input(key);
-                }
-            }
-            _ => {
-                if key == self.keys.close_popup || key == self.keys.confirm {
-                    self.popup = None;
-                    if let Popup::Pushing(_) = popup {
-                        self.refresh()?;
-                    }
-                }
-            }
-        }
-        Ok(AppReturn::Continue)
+    /// Whether something changed since the last frame, so that the main
+    /// loop draws one.
+    pub fn needs_redraw(&self) -> bool {
+        self.needs_redraw
     }
 
-    fn handle_status_keys(&mut self, key: KeyEvent, sub_mode: StatusMode) -> AppResult<()> {
-        if key == self.keys.panel_left {
-            self.active_panel = ActivePanel::Files;
-            return Ok(());
-        }
-        if key == self.keys.panel_right {
-            self.active_panel = ActivePanel::Diff;
-            return Ok(());
-        }
-
-        match sub_mode {
-            StatusMode::FileSelection => {
-                match self.active_panel {
-                    ActivePanel::Files => {
-                        if key == self.keys.select_next {
-                            self.select_next_status_item();
-                        } else if key == self.keys.select_prev {
-                            self.select_previous_status_item();
-                        } else if key == self.keys.stage_item {
-                            self.stage_selected()?;
-                        } else if key == self.keys.unstage_item {
-                            self.unstage_selected()?;
-                        } else if key == self.keys.confirm {
-                            if let Some(item) = self.get_selected_status_item() {
-                                self.current_hunks = self.repo.get_diff_hunks(&item)?;
-                                if !self.current_hunks.is_empty() {
-                                    info!("Entering HunkSelection mode for file: {}", item.path);
-                                    self.mode = Mode::Status(StatusMode::HunkSelection);
-                                    self.hunk_list_state.select(Some(0));
-                                } else {
-                                    info!("No hunks to select for file: {}", item.path);
-                                }
-                            }
-                        }
-                    }
-                    ActivePanel::Diff => {}
-                }
+    /// Asks for a frame, e.g. after the terminal was resized or an external
+    /// program had it.
+    pub fn request_redraw(&mut self) {
+        self.needs_redraw = true;
+    }
 
-                if key == self.keys.log_mode {
-                    self.mode = Mode::Log;
-                } else if key == self.keys.commit {
-                    self.popup = Some(Popup::Commit);
-                } else if key == self.keys.push {
-                    self.push_to_remote();
-                }
-            }
-            StatusMode::HunkSelection => {
-                if key == self.keys.select_next {
-                    self.select_next_hunk();
-                } else if key == self.keys.select_prev {
-                    self.select_previous_hunk();
-                } else if key == self.keys.stage_item {
-                    self.stage_selected_hunk()?;
-                }
-            }
-        }
-        Ok(())
+    /// Notes that a frame was drawn.
+    pub fn mark_drawn(&mut self) {
+        self.needs_redraw = false;
+        self.last_drawn = Instant::now();
     }
 
-    fn handle_log_keys(&mut self, key: KeyEvent) -> AppResult<()> {
-        if let Mode::Status(_) = self.mode {
-            self.mode = Mode::Status(StatusMode::FileSelection);
-        } else if key == self.keys.select_next {
-            self.select_next_log_item();
-        } else if key == self.keys.select_prev {
-            self.select_previous_log_item();
+    pub fn is_exiting(&self) -> bool {
+        self.exiting
+    }
+
+    /// Whether the git worker is still loading a refresh or diff.
+    pub fn is_loading(&self) -> bool {
+        self.refresh_loaded != self.refresh_requested || self.diff_loaded != self.diff_requested
+    }
+
+    /// Whether the git worker is still reading the status, branch and log.
+    /// The previous ones stay on screen until the new ones replace them.
+    pub fn is_refreshing(&self) -> bool {
+        self.refresh_loaded != self.refresh_requested
+    }
+
+    /// Whether the git worker is still loading the whole history.
+    pub fn is_loading_whole_log(&self) -> bool {
+        self.whole_log_loaded != self.whole_log_requested
+    }
+
+    /// Whether the git worker is still searching the log.
+    pub fn is_searching_log(&self) -> bool {
+        self.log_search_loaded != self.log_search_requested
+    }
+
+    /// Whether the git worker is still counting the churn of status items.
+    /// Nothing waits for it, so it does not count as loading.
+    pub fn is_counting_churn(&self) -> bool {
+        self.churn.values().any(|(_, churn)| churn.is_none())
+    }
+
+    /// Returns the file the user asked to open in an external editor, if any.
+    pub fn take_editor_request(&mut self) -> Option<PathBuf> {
+        self.editor_request.take()
+    }
+
+    /// Returns the external tool the user asked to run, if any.
+    pub fn take_tool_request(&mut self) -> Option<ToolRequest> {
+        self.tool_request.take()
+    }
+
+    /// Cleans up after the tool of `request` exited with `result`, and picks
+    /// up what it changed in the work tree.
+    pub fn finish_tool(&mut self, request: ToolRequest, result: AppResult<()>) -> AppResult<()> {
+        if let Err(e) = result {
+            error!("External tool failed: {}", e);
+            self.notify(Severity::Error, e.to_string());
         }
-        Ok(())
+        if let Err(e) = std::fs::remove_dir_all(&request.temp_dir) {
+            warn!("Could not remove {}: {}", request.temp_dir.display(), e);
+        }
+        if let Some(conflict) = &self.conflict {
+            // The merge tool may have rewritten the work tree file.
+            self.conflict = Some(self.repo.conflict_sides(&conflict.path)?);
+        }
+        self.refresh()
+    }
+
+    /// Returns the text the user asked to copy, if any.
+    pub fn take_clipboard_request(&mut self) -> Option<ClipboardRequest> {
+        self.clipboard_request.take()
     }
 
-    fn handle_commit_input(&mut self, key: KeyEvent) {
-        match key.code {
-            KeyCode::Char(c) => {
-                self.commit_msg.insert(self.cursor_pos, c);
-                self.cursor_pos += 1;
+    /// Confirms that the text of `request` was copied, or why it was not.
+    pub fn finish_copy(&mut self, request: ClipboardRequest, result: AppResult<()>) {
+        match result {
+            Ok(()) => self.notify(Severity::Success, format!("Copied {}", request.what)),
+            Err(e) => {
+                error!("Copying {} failed: {}", request.what, e);
+                self.notify(Severity::Error, format!("Could not copy {}: {}", request.what, e));
             }
-            KeyCode::Backspace => {
-                if self.cursor_pos > 0 {
-                    self.cursor_pos -= 1;
-                    self.commit_msg.remove(self.cursor_pos);
-                }
+        }
+    }
+
+    fn copy(&mut self, text: String, what: String) {
+        info!("Copying {} to the clipboard", what);
+        self.clipboard_request = Some(ClipboardRequest { text, what });
+    }
+
+    /// Returns whether the repository was switched since the last call.
+    pub fn take_repo_switch(&mut self) -> bool {
+        std::mem::take(&mut self.repo_switched)
+    }
+
+    /// Replaces the open repository, e.g. with another worktree, and resets
+    /// the state that belonged to the previous one.
+    pub fn switch_repo(&mut self, repo: GitRepo) -> AppResult<()> {
+        info!("Switching to repository at {}", repo.path().display());
+        self.save_session();
+        self.git = GitWorker::spawn(repo.location(), self.app_event_sender.clone())?;
+        self.repo = repo;
+        self.repo_switched = true;
+        self.mode = Mode::Status(StatusMode::FileSelection);
+        self.active_panel = ActivePanel::Files;
+        self.status_filter.clear();
+        self.log_entries.clear();
+        self.log_search = None;
+        self.log_path = None;
+        self.log_sort = None;
+        self.log_order = None;
+        self.log_mark = None;
+        self.compare = None;
+        self.log_checkout = None;
+        self.divergence = None;
+        self.auto_stash = None;
+        self.current_hunks.clear();
+        self.hunk_list_state.select(None);
+        self.line_selection = None;
+        self.conflict = None;
+        self.diff = None;
+        self.diff_item = None;
+        self.diff_cache.clear();
+        self.churn.clear();
+        self.marked_items.clear();
+        self.reset_diff_view();
+        self.forge = ForgeState::default();
+        self.stats_view = StatsView::Loading;
+        self.doctor_view = DoctorView::Loading;
+        // Undo entries refer to objects and refs of the previous repository.
+        self.undo_stack.clear();
+        self.macros.recording = None;
+        // The sync commit still running belongs to the previous repository.
+        self.sync.forget_commit();
+        self.scripts.set_repo(&self.repo.location());
+        self.remember_repo();
+        self.restore_session();
+        self.refresh()
+    }
+
+    /// Saves the mode, selections and collapsed directories, for the next
+    /// launch on this repository to restore.
+    pub fn save_session(&self) {
+        // Read-only mode leaves the git directory alone as well.
+        if self.config.read_only {
+            return;
+        }
+        let mode = match self.mode {
+            // Hunks and conflicts are not saved, only the file.
+            Mode::Status(_) => Mode::Status(StatusMode::FileSelection),
+            mode => mode,
+        };
+        let session = Session {
+            // Registered panels are not there yet when the session is
+            // restored.
+            mode: Some(mode).filter(|mode| !matches!(mode, Mode::Custom(_))),
+            selected_path: self.get_selected_status_item().map(|item| item.path),
+            log_selected: self.selected_log_entry().map(|commit| commit.oid.to_string()),
+            log_offset: self.log_table_state.offset(),
+            group_by_dir: self.group_by_dir,
+            collapsed_dirs: self.collapsed_dirs.iter().cloned().collect(),
+            macro_keys: self.macros.keys.iter().map(|&key| key_label(key)).collect(),
+        };
+        if let Err(e) = session.save(&self.repo.location().git_dir) {
+            warn!("Could not save the session: {}", e);
+        }
+    }
+
+    /// Loads the saved session of the open repository. The mode and the
+    /// grouping apply right away, the selections with the next refresh.
+    fn restore_session(&mut self) {
+        let session = match Session::load(&self.repo.location().git_dir) {
+            Ok(session) => session,
+            Err(e) => {
+                warn!("Could not restore the session: {}", e);
+                return;
             }
-            KeyCode::Left => {
-                if self.cursor_pos > 0 {
-                    self.cursor_pos -= 1;
-                }
+        };
+        if let Some(mode) = session.mode {
+            self.mode = mode;
+            self.with_panel(mode, |panel, app| panel.open(app));
+        }
+        self.group_by_dir = session.group_by_dir;
+        self.collapsed_dirs = session.collapsed_dirs.iter().cloned().collect();
+        self.macros.keys = match session.macro_keys.iter().map(|key| parse_key(key)).collect() {
+            Ok(keys) => keys,
+            Err(e) => {
+                warn!("Dropping the saved macro: {}", e);
+                Vec::new()
             }
-            KeyCode::Right => {
-                if self.cursor_pos < self.commit_msg.len() {
-                    self.cursor_pos += 1;
-                }
+        };
+        self.pending_session = Some(session);
+    }
+
+    /// Where the recent repositories are kept. Like the scripts, they are
+    /// only kept when the app runs on a config file.
+    fn recent_path(&self) -> Option<PathBuf> {
+        self.config.path.as_ref()?;
+        session::default_recent_path()
+    }
+
+    /// Reads the recent repositories, or takes the `[[recent]]` entries of
+    /// an older config while none were saved yet.
+    fn load_recent(&mut self) {
+        let Some(path) = self.recent_path() else {
+            return;
+        };
+        self.recent = match RecentRepos::load(&path) {
+            Ok(recent) if !recent.recent.is_empty() => recent,
+            Ok(_) => RecentRepos {
+                recent: self.config.recent.clone(),
+            },
+            Err(e) => {
+                warn!("Could not read the recent repositories: {}", e);
+                RecentRepos::default()
             }
-            _ => {}
-        }
+        };
     }
 
-    fn stage_selected(&mut self) -> AppResult<()> {
-        if let Some(item) = self.get_selected_status_item() {
-            if !item.is_staged {
-                info!("Staging item: {}", item.path);
-                self.repo.stage_item(&item)?;
-                self.refresh()?;
+    /// Puts the open repository at the top of the recent repositories.
+    fn remember_repo(&mut self) {
+        let location = self.repo.location();
+        let repo = RecentRepo {
+            git_dir: location.git_dir,
+            work_tree: location.work_tree,
+        };
+        if !self.recent.remember(repo) {
+            return;
+        }
+        if let Some(path) = self.recent_path() {
+            if let Err(e) = self.recent.save(&path) {
+                warn!("Could not save the recent repositories: {}", e);
             }
         }
-        Ok(())
     }
 
-    fn stage_selected_hunk(&mut self) -> AppResult<()> {
-        if let Some(hunk_index) = self.hunk_list_state.selected() {
-            if let Some(item) = self.get_selected_status_item() {
-                info!("Staging hunk #{} for file '{}'", hunk_index, item.path);
-                self.repo.stage_hunk(&item, hunk_index)?;
-                self.mode = Mode::Status(StatusMode::FileSelection);
-                self.current_hunks.clear();
-                self.hunk_list_state.select(None);
-                self.refresh()?;
+    /// Reads the config file again after it changed on disk and applies it.
+    /// `read_only` is only ever turned on, since it may have come from
+    /// `--read-only`, and the log settings and the tick interval take a
+    /// restart. A file that does
+    /// not parse leaves the current config in place.
+    fn reload_config(&mut self) -> AppResult<()> {
+        let Some(path) = self.config.path.clone() else {
+            return Ok(());
+        };
+        let mut config = match Config::load(Some(&path)) {
+            Ok(config) => config,
+            Err(e) => {
+                warn!("Could not reload the config: {}", e);
+                self.notify(Severity::Error, format!("Config not reloaded: {}", e));
+                return Ok(());
+            }
+        };
+        config.read_only |= self.config.read_only;
+        if config == self.config {
+            return Ok(());
+        }
+        info!("Reloaded the config from {}", path.display());
+        let old = std::mem::replace(&mut self.config, config);
+        if old.disable_mouse != self.config.disable_mouse {
+            self.mouse_capture = !self.config.disable_mouse;
+        }
+        if old.sync != self.config.sync {
+            self.sync.enabled = self.config.sync && !self.config.read_only;
+            self.sync.changed = self.sync.enabled.then(Instant::now);
+        }
+        if old.diff_line_limit != self.config.diff_line_limit {
+            self.diff_line_limit = self.default_diff_line_limit();
+        }
+        if old.keys != self.config.keys {
+            self.keys = self.config.keys.clone();
+            self.pending_keys.clear();
+        }
+        #[cfg(feature = "spellcheck")]
+        if old.dictionary != self.config.dictionary {
+            self.dictionary = None;
+        }
+        if old.forge != self.config.forge {
+            self.forge.project = None;
+            if self.mode == Mode::Forge {
+                self.load_forge();
             }
         }
+        self.notify(Severity::Success, "Config reloaded".to_string());
+        if old.hide_untracked != self.config.hide_untracked
+            || old.collapse_untracked_dirs != self.config.collapse_untracked_dirs
+            || old.diff_size_limit != self.config.diff_size_limit {
+            self.refresh()?;
+        }
         Ok(())
     }
 
-    fn unstage_selected(&mut self) -> AppResult<()> {
-        if let Some(item) = self.get_selected_status_item() {
-            if item.is_staged {
-                info!("Unstaging file: {}", item.path);
-                self.repo.unstage_file(&item.path)?;
-                self.refresh()?;
-            }
-        }
+    /// Reloads the app state. Status, branch, log, stashes, reflog, links
+    /// and the file tree are read by the git worker and applied when
+    /// `AppEvent::RefreshLoaded` arrives; only the ignore rules, a few small
+    /// files, are reread right away.
+    pub fn refresh(&mut self) -> AppResult<()> {
+        info!("Refreshing app state...");
+        // Work tree changes do not touch the index, so every cached diff may
+        // be outdated now.
+        self.diff_cache.clear();
+        self.refresh_requested += 1;
+        self.git.send(GitRequest::Refresh {
+            generation: self.refresh_requested,
+            untracked: self.config.untracked_files(&self.expanded_untracked_dirs),
+            // Keep at least as many commits as were already loaded so that a
+            // refresh does not cut the log view short.
+            log_count: self.log_entries.len().max(LOG_PAGE_SIZE),
+            log_path: self.log_path.clone(),
+        });
+        // A failed link operation reports itself once this refresh is sent.
+        self.links_error = None;
+        self.refresh_ignore_rules();
         Ok(())
     }
 
-    fn submit_commit(&mut self) -> AppResult<()> {
-        if !self.commit_msg.is_empty() {
-            info!("Attempting to commit with message: '{}'", self.commit_msg);
-            self.repo.commit(&self.commit_msg)?;
-            info!("Commit successful.");
-            self.commit_msg.clear();
-            self.cursor_pos = 0;
-            self.popup = None;
-            self.refresh()?;
+    /// Takes the stashes and the reflog of a snapshot, keeping their
+    /// selections in range.
+    fn apply_stashes_and_reflog(&mut self, stashes: Vec<StashInfo>, reflog: Vec<ReflogEntry>) {
+        self.stash_entries = stashes;
+        self.reflog.set_entries(reflog);
+
+        if self.stash_entries.is_empty() {
+            self.stash_table_state.select(None);
+        } else {
+            let selected = self
+                .stash_table_state
+                .selected()
+                .unwrap_or(0)
+                .min(self.stash_entries.len() - 1);
+            self.stash_table_state.select(Some(selected));
         }
-        Ok(())
     }
 
-    fn push_to_remote(&mut self) {
-        info!("Spawning background task for git push.");
-        self.popup = Some(Popup::Pushing("Pushing...".to_string()));
-        let repo_path = self.repo.path().to_path_buf();
-        let sender = self.app_event_sender.clone();
-        tokio::spawn(async move {
-            let push_result = async {
-                let repo = git2::Repository::open(repo_path)?;
-                let mut remote = repo.find_remote("origin")?;
-                let mut callbacks = git2::RemoteCallbacks::new();
-                callbacks.credentials(|_url, username, _| {
-                    git2::Cred::ssh_key_from_agent(username.unwrap_or("git"))
+    /// Counts the staged, unstaged and untracked files, ignoring the filter.
+    /// A partially staged file counts as both staged and unstaged.
+    pub fn status_counts(&self) -> (usize, usize, usize) {
+        self.status_items.iter().fold((0, 0, 0), |(staged, unstaged, untracked), item| {
+            if item.is_staged {
+                (staged + 1, unstaged, untracked)
+            } else if item.status.is_wt_new() {
+                (staged, unstaged, untracked + 1)
+            } else {
+                (staged, unstaged + 1, untracked)
+            }
+        })
+    }
+
+    /// Rebuilds `status_display_list` from `status_items`, keeping only the
+    /// paths that match `status_filter`.
+    fn rebuild_status_list(&mut self) {
+        self.status_display_list.clear();
+        let items = &self.status_items;
+        self.marked_items
+            .retain(|(path, staged)| items.iter().any(|item| item.path == *path && item.is_staged == *staged));
+        let (staged, unstaged): (Vec<_>, Vec<_>) = self
+            .status_items
+            .iter()
+            .filter(|item| fuzzy_match(&self.status_filter, &item.path))
+            .cloned()
+            .partition(|i| i.is_staged);
+        let (conflicted, unstaged): (Vec<_>, Vec<_>) =
+            unstaged.into_iter().partition(|i| i.status.is_conflicted());
+        let (untracked, unstaged): (Vec<_>, Vec<_>) =
+            unstaged.into_iter().partition(|i| i.status.is_wt_new());
+
+        self.push_status_section("Conflicts:", conflicted);
+        self.push_status_section("Staged changes:", staged);
+        self.push_status_section("Unstaged changes:", unstaged);
+        self.push_status_section("Untracked files:", untracked);
+    }
+
+    /// Adds a section of the file list. While grouping by directory, files
+    /// in the top level come first, then one node per directory followed by
+    /// its files unless the directory is collapsed.
+    fn push_status_section(&mut self, header: &str, items: Vec<StatusItem>) {
+        if items.is_empty() {
+            return;
+        }
+        self.status_display_list
+            .push(StatusItemType::Header(header.to_string()));
+        if !self.group_by_dir {
+            self.status_display_list
+                .extend(items.into_iter().map(StatusItemType::Item));
+            return;
+        }
+        let mut dirs: BTreeMap<&str, Vec<StatusItem>> = BTreeMap::new();
+        for item in &items {
+            dirs.entry(parent_dir(&item.path)).or_default().push(item.clone());
+        }
+        for (dir, items) in dirs {
+            if !dir.is_empty() {
+                let collapsed = self.collapsed_dirs.contains(dir);
+                self.status_display_list.push(StatusItemType::Dir {
+                    path: dir.to_string(),
+                    count: items.len(),
+                    collapsed,
                 });
-                let mut push_options = git2::PushOptions::new();
-                push_options.remote_callbacks(callbacks);
-                let head = repo.head()?;
-                let head_name = head.shorthand().unwrap_or("main");
-                let refspec = format!("refs/heads/{}:refs/heads/{}", head_name, head_name);
-                remote
-                    .push(&[refspec], Some(&mut push_options))
-                    .map_err(|e| AppError::PushFailed(e.to_string()))
-            }
-            .await;
-            let _ = sender.send(AppEvent::PushFinished(push_result));
-        });
+                if collapsed {
+                    continue;
+                }
+            }
+            self.status_display_list
+                .extend(items.into_iter().map(StatusItemType::Item));
+        }
     }
 
-    fn select_next_status_item(&mut self) {
-        if self.status_display_list.is_empty() { return; }
-        let selected = self.status_list_state.selected().unwrap_or(0);
-        let new_selected = if selected >= self.status_display_list.len() - 1 { 0 } else { selected + 1 };
-        self.status_list_state.select(Some(new_selected));
-        self.skip_headers_forward();
+    /// Turns directory grouping on or off, keeping the selected file selected.
+    fn toggle_dir_grouping(&mut self) {
+        self.group_by_dir = !self.group_by_dir;
+        info!("Grouping files by directory: {}", self.group_by_dir);
+        self.apply_status_filter(self.status_filter.clone());
     }
 
-    fn select_previous_status_item(&mut self) {
-        if self.status_display_list.is_empty() { return; }
-        let selected = self.status_list_state.selected().unwrap_or(0);
-        let new_selected = if selected == 0 { self.status_display_list.len() - 1 } else { selected - 1 };
-        self.status_list_state.select(Some(new_selected));
-        self.skip_headers_backward();
+    /// Lists the files of the collapsed untracked directory under the
+    /// selection from the next refresh on.
+    fn expand_untracked_dir(&mut self) -> AppResult<()> {
+        let Some(item) = self.get_selected_status_item() else {
+            return Ok(());
+        };
+        info!("Listing the files of the untracked directory {}", item.path);
+        self.expanded_untracked_dirs.push(item.path);
+        self.refresh()
     }
 
-    fn skip_headers_forward(&mut self) {
-        if let Some(selected) = self.status_list_state.selected() {
-            if let Some(item_type) = self.status_display_list.get(selected) {
-                if matches!(item_type, StatusItemType::Header(_)) {
-                    if selected >= self.status_display_list.len() - 1 {
-                        self.status_list_state.select(Some(0));
-                        self.skip_headers_forward();
-                    } else {
-                        self.select_next_status_item();
-                    }
+    /// Collapses or expands the directory node under the selection.
+    fn toggle_selected_dir(&mut self) {
+        let Some(StatusItemType::Dir { path, .. }) = self
+            .status_list_state
+            .selected()
+            .and_then(|i| self.status_display_list.get(i))
+        else {
+            return;
+        };
+        let path = path.clone();
+        if !self.collapsed_dirs.remove(&path) {
+            self.collapsed_dirs.insert(path);
+        }
+        // The node stays where it is, since only the files after it change.
+        self.rebuild_status_list();
+        self.clamp_status_selection();
+    }
+
+    fn clamp_status_selection(&mut self) {
+        if self.status_display_list.is_empty() {
+            self.status_list_state.select(None);
+        } else {
+            if let Some(selected) = self.status_list_state.selected() {
+                if selected >= self.status_display_list.len() {
+                    self.status_list_state
+                        .select(Some(self.status_display_list.len() - 1));
                 }
+            } else {
+                self.status_list_state.select(Some(0));
             }
+            self.skip_headers_forward();
         }
     }
 
-    fn skip_headers_backward(&mut self) {
-        if let Some(selected) = self.status_list_state.selected() {
-            if let Some(item_type) = self.status_display_list.get(selected) {
-                if matches!(item_type, StatusItemType::Header(_)) {
-                    if selected == 0 {
-                        self.status_list_state
-                            .select(Some(self.status_display_list.len() - 1));
-                        self.skip_headers_backward();
-                    } else {
-                        self.select_previous_status_item();
+    /// Narrows the file list to `filter`, keeping the selected file selected
+    /// when it still matches.
+    fn apply_status_filter(&mut self, filter: String) {
+        let previous = self.get_selected_status_item();
+        self.status_filter = filter;
+        self.rebuild_status_list();
+        let index = previous.and_then(|previous| {
+            self.status_display_list.iter().position(|item_type| {
+                matches!(item_type, StatusItemType::Item(item)
+                    if item.path == previous.path && item.is_staged == previous.is_staged)
+            })
+        });
+        self.status_list_state.select(index);
+        self.clamp_status_selection();
+    }
+
+    /// Translates a key press into an action. Popups and text inputs get the
+    /// key itself. Digits build up a count for the next navigation key, as
+    /// in vim's `5j`, and the keys of a multi-key binding such as `gg` wait
+    /// for the rest of it, so those keys only update the pending state.
+    /// While a macro is recorded the keys are added to it.
+    pub fn action_for_key(&mut self, key: KeyEvent) -> Option<Action> {
+        // Pending keys and counts show in the footer even without an action.
+        self.needs_redraw = true;
+        self.macros.record(key);
+        let action = self.translate_key(key);
+        // The keys of `Q` and `@@` are not part of the macro.
+        let own_keys = match action {
+            Some(Action::RecordMacro) => 1,
+            Some(Action::ReplayMacro) => 2,
+            _ => 0,
+        };
+        self.macros.unrecord(own_keys);
+        action
+    }
+
+    fn translate_key(&mut self, key: KeyEvent) -> Option<Action> {
+        debug!("Received key event: {:?}", key.code);
+        if self.popup.is_some() || self.searching {
+            return Some(Action::Key(key));
+        }
+        let action = match self.follow_sequence(key) {
+            Some(action) => action,
+            None if !self.pending_keys.is_empty() => return None,
+            None => {
+                if let (KeyCode::Char(c @ '0'..='9'), KeyModifiers::NONE) = (key.code, key.modifiers) {
+                    let digit = c as usize - '0' as usize;
+                    if digit > 0 || self.pending_count.is_some() {
+                        let count = self.pending_count.unwrap_or(0) * 10 + digit;
+                        self.pending_count = Some(count.min(MAX_COUNT));
+                        return None;
+                    }
+                }
+                match self.keys.action(key, self.mode) {
+                    Some(action) => action,
+                    // Panels may use keys no keymap binds.
+                    None if self.panels.contains(self.mode) => Action::Key(key),
+                    None => {
+                        self.pending_count = None;
+                        return None;
                     }
                 }
             }
+        };
+        let count = self.pending_count.take();
+        if action.repeatable().is_some() {
+            self.repeat = count.unwrap_or(1);
         }
+        Some(action)
     }
 
-    pub fn get_selected_status_item(&self) -> Option<StatusItem> {
-        self.status_list_state
-            .selected()
-            .and_then(|i| self.status_display_list.get(i))
-            .and_then(|item_type| match item_type {
-                StatusItemType::Item(item) => Some(item.clone()),
-                StatusItemType::Header(_) => None,
-            })
+    /// Adds `key` to the keys typed so far of a multi-key binding, returning
+    /// the action once the binding is complete. A key that does not continue
+    /// the binding drops it and is looked at on its own, so it may start
+    /// another one; `pending_keys` stays empty when it does not.
+    fn follow_sequence(&mut self, key: KeyEvent) -> Option<Action> {
+        if self.pending_since.elapsed() >= SEQUENCE_TIMEOUT {
+            self.pending_keys.clear();
+        }
+        let mut sequences = self.keys.sequences(self.mode);
+        let mut pressed = std::mem::take(&mut self.pending_keys);
+        loop {
+            pressed.push(key);
+            if sequences.iter().any(|(keys, _)| keys.len() > pressed.len() && keys.starts_with(&pressed)) {
+                self.pending_keys = pressed;
+                self.pending_since = Instant::now();
+                return None;
+            }
+            if let Some(index) = sequences.iter().position(|(keys, _)| *keys == pressed) {
+                return Some(sequences.swap_remove(index).1);
+            }
+            if pressed.len() == 1 {
+                return None;
+            }
+            pressed.clear();
+        }
     }
 
-    fn select_next_hunk(&mut self) {
-        if self.current_hunks.is_empty() { return; }
-        let i = self.hunk_list_state.selected().map_or(0, |i| (i + 1) % self.current_hunks.len());
-        self.hunk_list_state.select(Some(i));
-        debug!("Selected hunk index: {}", i);
+    /// The count and the keys of a multi-key binding typed so far, for the
+    /// status bar; `None` when nothing is pending.
+    pub fn pending_keys_label(&self) -> Option<String> {
+        let keys = sequence_label(&self.pending_keys);
+        match (self.pending_count, keys.is_empty()) {
+            (None, true) => None,
+            (None, false) => Some(keys),
+            (Some(count), _) => Some(format!("{}{}", count, keys)),
+        }
     }
 
-    fn select_previous_hunk(&mut self) {
-        if self.current_hunks.is_empty() { return; }
-        let i = self.hunk_list_state.selected().map_or(0, |i| {
-            if i == 0 { self.current_hunks.len() - 1 } else { i - 1 }
-        });
-        self.hunk_list_state.select(Some(i));
-        debug!("Selected hunk index: {}", i);
+    /// Carries out `action`. This is the only way the main loop changes the
+    /// app's state.
+    pub fn dispatch(&mut self, action: Action) -> AppResult<AppReturn> {
+        // Ticks decide for themselves whether they changed anything.
+        if !matches!(action, Action::Tick) {
+            self.needs_redraw = true;
+        }
+        if action.is_mutating() && self.config.read_only {
+            let description = action.description(self.mode);
+            warn!("Ignoring \"{}\" in read-only mode", description);
+            self.notify(Severity::Warning, format!("Disabled in read-only mode: {}", description));
+            self.repeat = 1;
+            return Ok(AppReturn::Continue);
+        }
+        let result = self.handle_action(action);
+        let result = self.recover("Error", result).map(|returned| returned.unwrap_or(AppReturn::Continue));
+        self.sync_diff();
+        result
     }
 
-    fn select_next_log_item(&mut self) {
-        if self.log_entries.is_empty() { return; }
-        let i = self.log_table_state.selected().map_or(0, |i| (i + 1) % self.log_entries.len());
-        self.log_table_state.select(Some(i));
+    /// Shows a recoverable error in `Popup::Error` instead of passing it on,
+    /// so that a failed git operation does not end the app. `None` then
+    /// stands for the result that was lost.
+    pub fn recover<T>(&mut self, title: &str, result: AppResult<T>) -> AppResult<Option<T>> {
+        match result {
+            Ok(value) => Ok(Some(value)),
+            Err(e) if e.is_recoverable() => {
+                self.show_error(title, &e, None);
+                Ok(None)
+            }
+            Err(e) => Err(e),
+        }
     }
 
-    fn select_previous_log_item(&mut self) {
-        if self.log_entries.is_empty() { return; }
-        let i = self.log_table_state.selected().map_or(0, |i| {
-            if i == 0 { self.log_entries.len() - 1 } else { i - 1 }
+    /// Logs `error`, records it in the messages and opens `Popup::Error`,
+    /// which replaces any other popup.
+    fn show_error(&mut self, title: &str, error: &AppError, retry: Option<NetworkOp>) {
+        error!("{}: {}", title, error);
+        self.notify(Severity::Error, format!("{}: {}", title, error));
+        self.popup = Some(Popup::Error {
+            title: title.to_string(),
+            message: error.to_string(),
+            retry,
         });
-        self.log_table_state.select(Some(i));
     }
-}
 
-fn is_inside(cx: u16, cy: u16, rect: Rect) -> bool {
-    cx >= rect.x && cx < rect.x + rect.width && cy >= rect.y && cy < rect.y + rect.height
+    /// Starts a failed network operation again.
+    fn retry(&mut self, op: NetworkOp) -> AppResult<()> {
+        info!("Retrying {:?}", op);
+        match op {
+            NetworkOp::Push(mode, set_upstream) => self.push_to_remote(mode, set_upstream),
+            NetworkOp::PushTo { remotes, refspec } => self.push_to_remotes(remotes, refspec),
+            NetworkOp::Fetch => self.fetch_from_remote(),
+            NetworkOp::Pull => self.start_risky_operation(RiskyOperation::Pull)?,
+            NetworkOp::Clone => self.start_clone(),
+        }
+        Ok(())
+    }
+
+    fn handle_action(&mut self, action: Action) -> AppResult<AppReturn> {
+        let times = std::mem::replace(&mut self.repeat, 1);
+        for _ in 1..times {
+            if let Some(again) = action.repeatable() {
+                self.handle_action(again)?;
+            }
+        }
+        match action {
+            Action::Key(key) => {
+                if let Some(popup) = self.popup.clone() {
+                    return self.handle_popup_keys(key, popup);
+                }
+                if self.searching {
+                    self.handle_search_input(key)?;
+                } else if let Some(result) = self.with_panel(self.mode, |panel, app| panel.handle_key(app, action)) {
+                    result?;
+                }
+            }
+            Action::Mouse(event) => self.handle_mouse_event(event)?,
+            Action::Event(event) => self.handle_app_event(event)?,
+            Action::Tick => {
+                let expired = self.notifications.expire(Instant::now());
+                if self.is_refreshing() {
+                    self.spinner_frame = self.spinner_frame.wrapping_add(1);
+                }
+                // The debug view shows log lines as they are written.
+                if expired
+                    || self.is_refreshing()
+                    || self.mode == Mode::Debug
+                    || self.last_drawn.elapsed() >= IDLE_REDRAW_INTERVAL
+                {
+                    self.needs_redraw = true;
+                }
+                self.auto_fetch_if_due();
+                self.sync_if_due()?;
+                if self.pending_since.elapsed() >= SEQUENCE_TIMEOUT && !self.pending_keys.is_empty() {
+                    self.needs_redraw = true;
+                    self.pending_keys.clear();
+                }
+            }
+            Action::RecordMacro => self.toggle_macro_recording(),
+            Action::ReplayMacro => return self.replay_macro(),
+            Action::Refresh => {
+                self.refresh()?;
+                self.fetch_quietly();
+            }
+            Action::ToggleMouse => {
+                self.mouse_capture = !self.mouse_capture;
+                let message = if self.mouse_capture {
+                    "Mouse captured: click and scroll in the TUI"
+                } else {
+                    "Mouse released: select and copy text with the terminal"
+                };
+                self.notify(Severity::Info, message.to_string());
+            }
+            Action::ToggleSync => self.toggle_sync(),
+            Action::Quit => {
+                if let Mode::Status(StatusMode::LineSelection) = self.mode {
+                    self.leave_line_selection();
+                    return Ok(AppReturn::Continue);
+                }
+                if let Mode::Status(StatusMode::HunkSelection) = self.mode {
+                    info!("Quitting HunkSelection mode, returning to FileSelection");
+                    self.mode = Mode::Status(StatusMode::FileSelection);
+                    self.current_hunks.clear();
+                    self.hunk_list_state.select(None);
+                    return Ok(AppReturn::Continue);
+                }
+                if let Mode::Status(StatusMode::ConflictResolution) = self.mode {
+                    self.leave_conflict_view();
+                    return Ok(AppReturn::Continue);
+                }
+                self.exiting = true;
+                return Ok(AppReturn::Exit);
+            }
+            Action::ShowHelp => {
+                self.help = HelpState::default();
+                self.popup = Some(Popup::Help);
+            }
+            Action::SwitchMode(mode) => {
+                self.mode = mode;
+                self.with_panel(mode, |panel, app| panel.open(app));
+                if mode == Mode::Messages {
+                    let newest = (self.notifications.history_len() > 0).then_some(0);
+                    self.messages_list_state.select(newest);
+                }
+                if mode == Mode::Debug {
+                    self.debug_scroll = 0;
+                }
+            }
+            Action::Undo => self.undo()?,
+            Action::SelectFirst | Action::SelectLast | Action::PageDown | Action::PageUp => {
+                self.jump(action)?
+            }
+            Action::OpenWorktrees => self.open_worktrees()?,
+            Action::OpenPalette => self.open_palette(),
+            Action::OpenRepoSwitch => {
+                // The open repository is first; preselect the one before it.
+                let selected = if self.recent.recent.len() > 1 { 1 } else { 0 };
+                self.repo_list_state.select(Some(selected));
+                self.popup = Some(Popup::RepoSwitch);
+            }
+            action => match self.mode {
+                Mode::Status(sub_mode) => self.handle_status_action(action, sub_mode)?,
+                Mode::Log => self.handle_log_action(action)?,
+                Mode::Stash => self.handle_stash_action(action)?,
+                Mode::Tree => self.handle_tree_action(action)?,
+                Mode::Reflog => self.handle_reflog_action(action)?,
+                Mode::Ignore => self.handle_ignore_action(action)?,
+                Mode::Messages => self.handle_messages_action(action),
+                Mode::Debug => match action {
+                    Action::SelectNext => self.debug_scroll = self.debug_scroll.saturating_sub(1),
+                    Action::SelectPrev => self.debug_scroll = self.debug_scroll.saturating_add(1),
+                    _ => {}
+                },
+                mode => {
+                    if let Some(result) = self.with_panel(mode, |panel, app| panel.handle_key(app, action)) {
+                        result?;
+                    }
+                }
+            },
+        }
+        Ok(AppReturn::Continue)
+    }
+
+    fn handle_mouse_event(&mut self, event: MouseEvent) -> AppResult<()> {
+        debug!("Received mouse event: {:?}", event);
+        // Popups are drawn over the panels and take only keys.
+        if self.popup.is_some() {
+            return Ok(());
+        }
+        let (column, row) = (event.column, event.row);
+        let panel = if is_inside(column, row, self.layout.files) {
+            Some(ActivePanel::Files)
+        } else if is_inside(column, row, self.layout.diff) {
+            Some(ActivePanel::Diff)
+        } else {
+            None
+        };
+        match event.kind {
+            MouseEventKind::ScrollUp | MouseEventKind::ScrollDown => {
+                let down = event.kind == MouseEventKind::ScrollDown;
+                self.scroll_panel(panel, down, is_inside(column, row, self.layout.main))?;
+            }
+            MouseEventKind::Down(MouseButton::Left) if self.mode == Mode::Log && self.compare.is_none() => {
+                self.click_log(column, row)?;
+            }
+            MouseEventKind::Down(MouseButton::Left) => {
+                let Some(panel) = panel else {
+                    return Ok(());
+                };
+                self.active_panel = panel;
+                if panel == ActivePanel::Files {
+                    self.click_file_row(row)?;
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Scrolls the panel under the mouse, whether or not it is focused.
+    /// Views without panels move their selection when the wheel turns over
+    /// the main area.
+    fn scroll_panel(&mut self, panel: Option<ActivePanel>, down: bool, in_main: bool) -> AppResult<()> {
+        match (self.mode, panel) {
+            (Mode::Status(StatusMode::FileSelection), Some(ActivePanel::Files)) => {
+                if down { self.select_next_status_item() } else { self.select_previous_status_item() }
+            }
+            (Mode::Status(StatusMode::LineSelection), Some(ActivePanel::Diff)) => self.move_line_cursor(down),
+            (Mode::Status(StatusMode::HunkSelection), Some(ActivePanel::Diff)) => {
+                if down { self.select_next_hunk() } else { self.select_previous_hunk() }
+            }
+            (Mode::Tree, Some(ActivePanel::Files)) => {
+                if down { self.file_tree.select_next() } else { self.file_tree.select_previous() }
+            }
+            (Mode::Log, Some(ActivePanel::Files)) => {
+                self.handle_compare_action(if down { Action::SelectNext } else { Action::SelectPrev })?;
+            }
+            (Mode::Status(_) | Mode::Tree | Mode::Log, Some(ActivePanel::Diff)) => {
+                if down { self.scroll_diff_down() } else { self.scroll_diff_up() }
+            }
+            (Mode::Status(_) | Mode::Tree, _) => {}
+            _ if in_main => {
+                self.handle_action(if down { Action::SelectNext } else { Action::SelectPrev })?;
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Selects the row clicked at `row` in the file list or file tree. A
+    /// second click on the same row stages or unstages the file, collapses
+    /// or expands a directory, or opens a tree entry.
+    fn click_file_row(&mut self, row: u16) -> AppResult<()> {
+        match self.mode {
+            Mode::Status(StatusMode::FileSelection) => {
+                let offset = self.status_list_state.offset();
+                let Some(index) = list_index_at(self.layout.files, row, offset)
+                    .filter(|&i| i < self.status_display_list.len())
+                else {
+                    return Ok(());
+                };
+                if let Some(StatusItemType::Header(_)) = self.status_display_list.get(index) {
+                    return Ok(());
+                }
+                let repeated = is_double_click(self.last_click, index);
+                self.last_click = Some((Instant::now(), index));
+                self.status_list_state.select(Some(index));
+                self.reset_diff_view();
+                if !repeated {
+                    return Ok(());
+                }
+                self.last_click = None;
+                if self.selected_status_is_dir() {
+                    self.toggle_selected_dir();
+                } else if self.get_selected_status_item().is_some_and(|item| item.is_staged) {
+                    self.unstage_selected()?;
+                } else {
+                    self.stage_selected()?;
+                }
+            }
+            Mode::Tree => {
+                let offset = self.file_tree.state.offset();
+                let Some(index) = list_index_at(self.layout.files, row, offset)
+                    .filter(|&i| i < self.file_tree.rows().len())
+                else {
+                    return Ok(());
+                };
+                let repeated = is_double_click(self.last_click, index);
+                self.last_click = Some((Instant::now(), index));
+                self.file_tree.state.select(Some(index));
+                if repeated {
+                    self.last_click = None;
+                    self.handle_action(Action::Confirm)?;
+                }
+            }
+            Mode::Log => {
+                let files = self.layout.files;
+                if let Some(compare) = &mut self.compare {
+                    let offset = compare.list_state.offset();
+                    if let Some(index) = list_index_at(files, row, offset).filter(|&i| i < compare.files.len()) {
+                        compare.list_state.select(Some(index));
+                        self.reset_diff_view();
+                    }
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Sorts the log by the clicked column header, or selects the clicked
+    /// commit; a second click on the same commit shows its details.
+    fn click_log(&mut self, column: u16, row: u16) -> AppResult<()> {
+        let header = self.layout.log_headers.iter().position(|&rect| is_inside(column, row, rect));
+        if let Some(i) = header {
+            return self.toggle_log_sort(LogColumn::ALL[i]);
+        }
+        let rows = self.layout.log_rows;
+        if !is_inside(column, row, rows) {
+            return Ok(());
+        }
+        let index = (row - rows.y) as usize + self.log_table_state.offset();
+        if index >= self.log_row_count() {
+            return Ok(());
+        }
+        let repeated = is_double_click(self.last_click, index);
+        self.last_click = Some((Instant::now(), index));
+        self.log_table_state.select(Some(index));
+        if repeated {
+            self.last_click = None;
+            self.open_commit_detail()?;
+        }
+        Ok(())
+    }
+
+    fn handle_app_event(&mut self, event: AppEvent) -> AppResult<()> {
+        match event {
+            AppEvent::PushFinished(result) => {
+                let cancelled = self.finish_task();
+                match result {
+                    Err(_) if cancelled => self.notify(Severity::Warning, "Push cancelled.".to_string()),
+                    Ok(_) => {
+                        info!("Async push operation completed successfully.");
+                        self.notify(Severity::Success, "Push successful!".to_string());
+                        self.emit_script_event(ScriptEvent::PostPush, None)?;
+                    }
+                    Err(e) => {
+                        let retry = self.network_op.take();
+                        self.show_error("Push failed", &e, retry);
+                    }
+                }
+                self.refresh()?;
+            }
+            AppEvent::PushToFinished(results) => {
+                let cancelled = self.finish_task();
+                let op = self.network_op.take();
+                let failed: Vec<String> = results
+                    .iter()
+                    .filter(|(_, result)| result.is_err())
+                    .map(|(remote, _)| remote.clone())
+                    .collect();
+                if failed.is_empty() {
+                    let remotes: Vec<&str> = results.iter().map(|(remote, _)| remote.as_str()).collect();
+                    info!("Pushed to {}.", remotes.join(", "));
+                    self.notify(Severity::Success, format!("Pushed to {}", remotes.join(", ")));
+                    self.emit_script_event(ScriptEvent::PostPush, Some(&remotes.join(" ")))?;
+                } else if cancelled {
+                    self.notify(Severity::Warning, "Push cancelled.".to_string());
+                } else {
+                    let message = results
+                        .iter()
+                        .map(|(remote, result)| match result {
+                            Ok(()) => format!("✓ {}", remote),
+                            Err(e) => format!("✗ {}: {}", remote, e),
+                        })
+                        .collect::<Vec<_>>()
+                        .join("\n");
+                    error!("Push failed: {}", message);
+                    let summary = format!("Push failed on {} of {} remotes", failed.len(), results.len());
+                    self.notify(Severity::Error, summary);
+                    let retry = match op {
+                        Some(NetworkOp::PushTo { refspec, .. }) => Some(NetworkOp::PushTo { remotes: failed, refspec }),
+                        _ => None,
+                    };
+                    self.popup = Some(Popup::Error {
+                        title: "Push failed".to_string(),
+                        message,
+                        retry,
+                    });
+                }
+                self.refresh()?;
+            }
+            AppEvent::HookOutput(line) => {
+                if let Some(run) = &mut self.hook_run {
+                    run.output.push(line);
+                }
+            }
+            AppEvent::HooksFinished(result) => match result {
+                Ok(message) => {
+                    info!("Commit hooks passed.");
+                    self.hook_run = None;
+                    self.create_commit(&message)?;
+                }
+                Err(e) => {
+                    error!("Commit hooks failed: {}", e);
+                    self.notify(Severity::Error, format!("Commit aborted: {}", e));
+                    if let Some(run) = &mut self.hook_run {
+                        run.error = Some(e.to_string());
+                    }
+                }
+            },
+            AppEvent::SyncCommitted(generation, result) => self.finish_sync_commit(generation, result)?,
+            AppEvent::SyncPushFinished(result) => self.finish_sync_push(result)?,
+            AppEvent::AutoFetchFinished(result) => {
+                self.auto_fetching = false;
+                match result {
+                    Ok(()) => {
+                        debug!("Background fetch completed.");
+                        self.auto_fetch_failed = false;
+                        self.refresh()?;
+                    }
+                    Err(e) => {
+                        warn!("Background fetch failed: {}", e);
+                        if !self.auto_fetch_failed {
+                            self.notify(Severity::Warning, format!("Background fetch failed: {}", e));
+                        }
+                        self.auto_fetch_failed = true;
+                    }
+                }
+            }
+            AppEvent::FetchFinished(result) => {
+                let cancelled = self.finish_task();
+                match result {
+                    Err(_) if cancelled => self.notify(Severity::Warning, "Fetch cancelled.".to_string()),
+                    Ok(_) => {
+                        info!("Async fetch operation completed successfully.");
+                        self.notify(Severity::Success, "Fetch successful!".to_string());
+                        self.emit_script_event(ScriptEvent::PostFetch, None)?;
+                    }
+                    Err(e) => {
+                        let retry = self.network_op.take();
+                        self.show_error("Fetch failed", &e, retry);
+                    }
+                }
+                self.refresh()?;
+            }
+            AppEvent::PullFinished(result) => {
+                let cancelled = self.finish_task();
+                let (severity, msg) = match result {
+                    Err(_) if cancelled => (Severity::Warning, "Pull cancelled.".to_string()),
+                    Ok(outcome) => {
+                        info!("Async pull operation completed: {:?}", outcome);
+                        match outcome {
+                            MergeOutcome::UpToDate => (Severity::Info, "Already up to date.".to_string()),
+                            MergeOutcome::FastForward => {
+                                (Severity::Success, "Pull successful (fast-forward).".to_string())
+                            }
+                            MergeOutcome::Merged => {
+                                (Severity::Success, "Pull successful (merge commit created).".to_string())
+                            }
+                            MergeOutcome::Conflicts => (
+                                Severity::Warning,
+                                "Pull stopped: merge conflicts need to be resolved.".to_string(),
+                            ),
+                        }
+                    }
+                    Err(e) => {
+                        let retry = self.network_op.take();
+                        self.show_error("Pull failed", &e, retry);
+                        // The auto-stash is offered back once the error is
+                        // closed without a retry.
+                        return self.refresh();
+                    }
+                };
+                let pulled = severity == Severity::Success;
+                self.notify(severity, msg);
+                if pulled {
+                    self.emit_script_event(ScriptEvent::PostPull, None)?;
+                }
+                self.refresh()?;
+                self.offer_auto_stash();
+            }
+            AppEvent::FsChanged(paths) => {
+                // The git directory may well be ignored, but its index, HEAD
+                // and refs change with every commit, checkout and fetch.
+                let git_dir = self.repo.location().git_dir;
+                if paths
+                    .iter()
+                    .all(|path| !path.starts_with(&git_dir) && self.repo.is_path_ignored(path))
+                {
+                    return Ok(());
+                }
+                debug!("Detected {} changed path(s), refreshing", paths.len());
+                // Sync mode commits the work tree, not what git itself writes.
+                if self.sync.enabled && paths.iter().any(|path| !path.starts_with(&git_dir)) {
+                    self.sync.changed = Some(Instant::now());
+                }
+                self.refresh()?;
+            }
+            AppEvent::ConfigChanged => self.reload_config()?,
+            AppEvent::RefreshLoaded(generation, result) => {
+                // A newer refresh is on its way; this one is already outdated.
+                if generation != self.refresh_requested {
+                    return Ok(());
+                }
+                self.refresh_loaded = generation;
+                match result {
+                    Ok(snapshot) => self.apply_snapshot(*snapshot)?,
+                    Err(e) => {
+                        error!("Refreshing failed: {}", e);
+                        self.notify(Severity::Error, format!("Refresh failed: {}", e));
+                    }
+                }
+            }
+            AppEvent::WholeLogLoaded(generation, result) => {
+                if generation != self.whole_log_requested {
+                    return Ok(());
+                }
+                self.whole_log_loaded = generation;
+                let select_last = std::mem::take(&mut self.log_select_last);
+                match result {
+                    // A file's history, shown meanwhile, is complete anyway.
+                    Ok(entries) if self.log_path.is_none() => self.show_whole_log(entries, select_last)?,
+                    Ok(_) => {}
+                    Err(e) => {
+                        error!("Loading the history failed: {}", e);
+                        self.notify(Severity::Error, format!("Loading the history failed: {}", e));
+                    }
+                }
+            }
+            AppEvent::LogSearched(generation, result) => {
+                if generation != self.log_search_requested {
+                    return Ok(());
+                }
+                self.log_search_loaded = generation;
+                let jump = std::mem::take(&mut self.log_search_jump);
+                match result {
+                    Ok(matches) => self.show_log_matches(matches, jump)?,
+                    Err(e) => {
+                        error!("Searching the log failed: {}", e);
+                        self.notify(Severity::Error, format!("Searching the log failed: {}", e));
+                    }
+                }
+            }
+            AppEvent::DiffLoaded(generation, result) => {
+                if generation != self.diff_requested {
+                    return Ok(());
+                }
+                self.diff_loaded = generation;
+                let diff = match result {
+                    Ok(diff) => diff,
+                    Err(e) => {
+                        error!("Loading the diff failed: {}", e);
+                        let Some(item) = self.diff_item.clone() else {
+                            return Ok(());
+                        };
+                        FileDiff {
+                            item,
+                            hunks: Vec::new(),
+                            binary: None,
+                            text: format!("Error loading diff: {}", e),
+                        }
+                    }
+                };
+                if let Mode::Status(StatusMode::HunkSelection | StatusMode::LineSelection) = self.mode {
+                    self.show_hunks(diff.hunks.clone());
+                }
+                self.diff_cache.insert(self.diff_key(&diff.item), diff.clone());
+                self.diff = Some(diff);
+            }
+            AppEvent::ChurnLoaded(churns) => {
+                for (key, churn) in churns {
+                    // Answers for files that changed again meanwhile are dropped.
+                    if let Some((known, slot)) = self.churn.get_mut(&(key.path.clone(), key.staged)) {
+                        if *known == key {
+                            *slot = Some(churn);
+                        }
+                    }
+                }
+            }
+            AppEvent::CredentialsNeeded(request) => {
+                info!("Prompting for credentials: {}", request.prompt.message);
+                let previous_popup = self.popup.take();
+                self.popup = Some(Popup::CredentialPrompt {
+                    message: request.prompt.message.clone(),
+                    secret: request.prompt.secret,
+                });
+                self.credential_input.clear();
+                self.pending_credential = Some(PendingCredential {
+                    request,
+                    previous_popup,
+                });
+            }
+            AppEvent::ForgeLoaded(result) => self.forge.finish_loading(result),
+            AppEvent::DoctorChecked(checks) => {
+                let selected = self.doctor_table_state.selected().unwrap_or(0).min(checks.len().saturating_sub(1));
+                self.doctor_table_state.select((!checks.is_empty()).then_some(selected));
+                self.doctor_view = DoctorView::Loaded(checks);
+            }
+            AppEvent::StatsLoaded(result) => {
+                self.stats_view = match result {
+                    Ok(stats) => StatsView::Loaded(stats),
+                    Err(e) => {
+                        error!("Computing the history statistics failed: {}", e);
+                        StatsView::Failed(e.to_string())
+                    }
+                };
+            }
+            AppEvent::TaskProgress { id, pct, msg } => {
+                let running = self.task.as_mut().filter(|task| task.id == id);
+                // Keep showing "Cancelling..." until the task gives up.
+                if let Some(task) = running.filter(|task| !task.cancel.is_cancelled()) {
+                    task.pct = pct;
+                    task.msg = msg;
+                }
+            }
+            AppEvent::CloneFinished(result) => {
+                let cancelled = self.finish_task();
+                match result.and_then(|location| GitRepo::open(&location)) {
+                    Err(_) if cancelled => {
+                        info!("Clone cancelled");
+                        self.notify(Severity::Warning, "Clone cancelled.".to_string());
+                    }
+                    Ok(repo) => {
+                        info!("Clone finished: {}", repo.path().display());
+                        self.notify(Severity::Success, format!("Cloned into {}", repo.path().display()));
+                        self.switch_repo(repo)?;
+                    }
+                    Err(e) => {
+                        let retry = self.network_op.take();
+                        self.show_error("Clone failed", &e, retry);
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn handle_popup_keys(&mut self, key: KeyEvent, popup: Popup) -> AppResult<AppReturn> {
+        match popup {
+            Popup::Commit => {
+                if key == self.keys.popup.close_popup {
+                    self.popup = None;
+                } else if key == self.keys.popup.submit_commit {
+                    self.submit_commit()?;
+                } else if key == self.keys.popup.toggle_signing {
+                    self.sign_commit = !self.sign_commit;
+                } else if key == self.keys.popup.toggle_no_verify {
+                    self.no_verify = !self.no_verify;
+                } else if key == self.keys.popup.toggle_conventional {
+                    self.conventional.enabled = !self.conventional.enabled;
+                    self.conventional.focus = if self.conventional.enabled {
+                        CommitField::Type
+                    } else {
+                        CommitField::Message
+                    };
+                    self.update_subject_guide();
+                    self.update_commit_warnings();
+                } else if key == self.keys.popup.next_field && self.conventional.enabled {
+                    self.conventional.focus = match self.conventional.focus {
+                        CommitField::Type => CommitField::Scope,
+                        CommitField::Scope => CommitField::Message,
+                        CommitField::Message => CommitField::Type,
+                    };
+                } else {
+                    self.handle_commit_field_input(key);
+                }
+            }
+            Popup::SecretPrompt(index) => {
+                if key == self.keys.popup.confirm {
+                    self.answer_secret_prompt(index)?;
+                } else if key == self.keys.popup.close_popup {
+                    self.secret_input.clear();
+                    self.popup = None;
+                } else {
+                    match key.code {
+                        KeyCode::Char(c) => self.secret_input.push(c),
+                        KeyCode::Backspace => {
+                            self.secret_input.pop();
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            Popup::CredentialPrompt { .. } => {
+                if key == self.keys.popup.confirm {
+                    let answer = std::mem::take(&mut self.credential_input);
+                    self.answer_credential_prompt(Some(answer));
+                } else if key == self.keys.popup.close_popup {
+                    self.credential_input.clear();
+                    self.answer_credential_prompt(None);
+                } else {
+                    match key.code {
+                        KeyCode::Char(c) => self.credential_input.push(c),
+                        KeyCode::Backspace => {
+                            self.credential_input.pop();
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            Popup::Error { retry, .. } => {
+                if let Some(op) = retry.clone().filter(|_| key == self.keys.popup.retry) {
+                    self.popup = None;
+                    self.retry(op)?;
+                } else if key == self.keys.popup.close_popup || key == self.keys.popup.confirm {
+                    self.popup = None;
+                    if retry == Some(NetworkOp::Pull) {
+                        self.offer_auto_stash();
+                    }
+                }
+            }
+            Popup::Confirm { on_confirm, .. } => {
+                if key == self.keys.popup.confirm || key == self.keys.popup.confirm_yes {
+                    self.popup = on_confirm.origin();
+                    self.run_confirmed(on_confirm)?;
+                } else if key == self.keys.popup.close_popup || key == self.keys.popup.confirm_no {
+                    self.popup = on_confirm.origin();
+                }
+            }
+            Popup::SetUpstreamConfirm(_) => {
+                if key == self.keys.popup.confirm || key == self.keys.popup.confirm_yes {
+                    self.push_to_remote(PushMode::Normal, true);
+                } else if key == self.keys.popup.confirm_no {
+                    self.push_to_remote(PushMode::Normal, false);
+                } else if key == self.keys.popup.close_popup {
+                    self.popup = None;
+                }
+            }
+            Popup::AutoStashConfirm(operation) => {
+                if key == self.keys.popup.confirm || key == self.keys.popup.confirm_yes {
+                    self.popup = None;
+                    self.auto_stash_and_run(operation)?;
+                } else if key == self.keys.popup.close_popup || key == self.keys.popup.confirm_no {
+                    self.popup = None;
+                }
+            }
+            Popup::Divergence => self.handle_divergence_keys(key)?,
+            Popup::Help => {
+                let page = self.half_page() as u16;
+                if key == self.keys.popup.close_popup || key == self.keys.popup.confirm || key == self.keys.global.show_help {
+                    self.popup = None;
+                } else if key == self.keys.popup.select_next {
+                    self.help.scroll = self.help.scroll.saturating_add(1);
+                } else if key == self.keys.popup.select_prev {
+                    self.help.scroll = self.help.scroll.saturating_sub(1);
+                } else if key == self.keys.popup.page_down {
+                    self.help.scroll = self.help.scroll.saturating_add(page);
+                } else if key == self.keys.popup.page_up {
+                    self.help.scroll = self.help.scroll.saturating_sub(page);
+                } else if key == self.keys.popup.select_last {
+                    self.help.scroll = u16::MAX;
+                } else if key == self.keys.popup.next_field {
+                    self.help = HelpState {
+                        scroll: 0,
+                        all_modes: !self.help.all_modes,
+                    };
+                }
+            }
+            Popup::Hooks => {
+                // The hooks cannot be interrupted; once they failed, Esc
+                // goes back to the message.
+                let failed = self.hook_run.as_ref().is_some_and(|run| run.error.is_some());
+                if failed && key == self.keys.popup.close_popup {
+                    self.hook_run = None;
+                    self.popup = Some(Popup::Commit);
+                }
+            }
+            Popup::LogCheckout => {
+                let Some(checkout) = self.log_checkout.as_mut() else {
+                    self.popup = None;
+                    return Ok(AppReturn::Continue);
+                };
+                if key == self.keys.popup.select_next || key == self.keys.popup.select_prev {
+                    let last = checkout.refs.len();
+                    let i = match checkout.list_state.selected() {
+                        Some(i) if key == self.keys.popup.select_next => if i >= last { 0 } else { i + 1 },
+                        Some(i) => if i == 0 { last } else { i - 1 },
+                        None => 0,
+                    };
+                    checkout.list_state.select(Some(i));
+                } else if key == self.keys.popup.confirm {
+                    self.popup = None;
+                    let checkout = self.log_checkout.take();
+                    if let Some(checkout) = checkout {
+                        let selected = checkout.list_state.selected().unwrap_or(0);
+                        let operation = match checkout.refs.get(selected) {
+                            Some(CommitRef::Branch(branch)) => RiskyOperation::CheckoutBranch(branch.clone()),
+                            Some(CommitRef::Tag(_)) | None => RiskyOperation::CheckoutCommit(checkout.oid),
+                        };
+                        self.start_risky_operation(operation)?;
+                    }
+                } else if key == self.keys.popup.close_popup {
+                    self.popup = None;
+                    self.log_checkout = None;
+                }
+            }
+            Popup::Bisect => {
+                let verdict = [
+                    (self.keys.popup.bisect_good, Verdict::Good),
+                    (self.keys.popup.bisect_bad, Verdict::Bad),
+                    (self.keys.popup.bisect_skip, Verdict::Skip),
+                ]
+                .into_iter()
+                .find_map(|(bound, verdict)| (key == bound).then_some(verdict));
+                let found = self
+                    .bisect
+                    .as_ref()
+                    .is_some_and(|bisect| matches!(bisect.step, BisectStep::Found(_) | BisectStep::Undecided(_)));
+                if key == self.keys.popup.confirm || key == self.keys.popup.close_popup {
+                    self.popup = None;
+                    if found {
+                        self.bisect = None;
+                    }
+                } else if found {
+                    // The result stays until the popup is closed.
+                } else if let Some(verdict) = verdict {
+                    self.mark_bisect(verdict)?;
+                } else if key == self.keys.popup.bisect_stop {
+                    self.popup = None;
+                    self.stop_bisect()?;
+                }
+            }
+            Popup::AutoStashRestore => {
+                if key == self.keys.popup.confirm || key == self.keys.popup.confirm_yes {
+                    self.popup = None;
+                    self.restore_auto_stash()?;
+                } else if key == self.keys.popup.close_popup || key == self.keys.popup.confirm_no {
+                    self.popup = None;
+                    self.keep_auto_stash()?;
+                }
+            }
+            Popup::ProfileSwitch => {
+                if key == self.keys.popup.select_next || key == self.keys.popup.select_prev {
+                    let last = self.profiles.len().saturating_sub(1);
+                    let i = match self.profile_list_state.selected() {
+                        Some(i) if key == self.keys.popup.select_next => if i >= last { 0 } else { i + 1 },
+                        Some(i) => if i == 0 { last } else { i - 1 },
+                        None => 0,
+                    };
+                    self.profile_list_state.select(Some(i));
+                } else if key == self.keys.popup.confirm {
+                    self.popup = None;
+                    if let Some(index) = self.profile_list_state.selected() {
+                        let current = self.repo.current_branch().ok().flatten();
+                        let checks_out = self
+                            .profiles
+                            .get(index)
+                            .and_then(|profile| profile.branch.as_ref())
+                            .is_some_and(|branch| current.as_ref() != Some(branch));
+                        if checks_out {
+                            self.start_risky_operation(RiskyOperation::SwitchProfile(index))?;
+                        } else {
+                            self.switch_profile(index)?;
+                        }
+                    }
+                } else if key == self.keys.popup.close_popup {
+                    self.popup = None;
+                }
+            }
+            Popup::Worktrees => self.handle_worktree_keys(key)?,
+            Popup::RepoSwitch => {
+                if key == self.keys.popup.select_next || key == self.keys.popup.select_prev {
+                    let last = self.recent.recent.len().saturating_sub(1);
+                    let i = match self.repo_list_state.selected() {
+                        Some(i) if key == self.keys.popup.select_next => if i >= last { 0 } else { i + 1 },
+                        Some(i) => if i == 0 { last } else { i - 1 },
+                        None => 0,
+                    };
+                    self.repo_list_state.select(Some(i));
+                } else if key == self.keys.popup.confirm {
+                    self.popup = None;
+                    let selected = self.repo_list_state.selected().and_then(|i| self.recent.recent.get(i));
+                    let current = self.repo.location().git_dir;
+                    if let Some(recent) = selected.filter(|r| r.git_dir != current).cloned() {
+                        let location = RepoLocation {
+                            git_dir: recent.git_dir,
+                            work_tree: recent.work_tree,
+                        };
+                        match GitRepo::open(&location) {
+                            Ok(repo) => self.switch_repo(repo)?,
+                            Err(e) => self.notify(Severity::Error, format!(
+                                "Could not open {}: {}",
+                                location.git_dir.display(),
+                                e
+                            )),
+                        }
+                    }
+                } else if key == self.keys.popup.repo_clone {
+                    self.clone_url.clear();
+                    self.popup = Some(Popup::CloneUrl);
+                } else if key == self.keys.popup.close_popup {
+                    self.popup = None;
+                }
+            }
+            Popup::Palette => {
+                if key == self.keys.popup.select_next || key == self.keys.popup.select_prev {
+                    let last = self.palette.len().saturating_sub(1);
+                    let i = match self.palette_state.selected() {
+                        Some(i) if key == self.keys.popup.select_next => if i >= last { 0 } else { i + 1 },
+                        Some(i) => if i == 0 { last } else { i - 1 },
+                        None => 0,
+                    };
+                    self.palette_state.select(Some(i));
+                } else if key == self.keys.popup.confirm {
+                    self.popup = None;
+                    match self.palette_state.selected().and_then(|i| self.palette.get(i)).cloned() {
+                        Some(PaletteEntry::Script(name)) => {
+                            info!("Running script action {}", name);
+                            let result = self.scripts.run_action(&name);
+                            self.apply_script_requests(result)?;
+                        }
+                        Some(PaletteEntry::Key(key, _)) => {
+                            if let Some(action) = self.keys.action(key, self.mode) {
+                                return self.dispatch(action);
+                            }
+                        }
+                        Some(PaletteEntry::Panel(mode, _)) => return self.dispatch(Action::SwitchMode(mode)),
+                        None => {}
+                    }
+                } else if key == self.keys.popup.close_popup {
+                    self.popup = None;
+                }
+            }
+            Popup::IgnoreCheckPath => {
+                if key == self.keys.popup.confirm {
+                    let path = self.ignore_input.submit().unwrap_or_default();
+                    self.popup = None;
+                    if !path.trim().is_empty() {
+                        self.check_ignored(path.trim());
+                    }
+                } else if key == self.keys.popup.close_popup {
+                    self.ignore_input.clear();
+                    self.popup = None;
+                } else {
+                    self.ignore_input.handle_key(key);
+                }
+            }
+            Popup::PushTargets => {
+                let Some(targets) = self.push_targets.as_mut() else {
+                    self.popup = None;
+                    return Ok(AppReturn::Continue);
+                };
+                let last = targets.remotes.len().saturating_sub(1);
+                let selected = targets.list_state.selected().unwrap_or(0);
+                if key == self.keys.popup.confirm {
+                    self.push_to_targets();
+                } else if key == self.keys.popup.close_popup {
+                    self.popup = None;
+                    self.push_targets = None;
+                } else if key == self.keys.popup.next_field {
+                    targets.editing_refspec = !targets.editing_refspec;
+                } else if targets.editing_refspec {
+                    self.push_refspec.handle_key(key);
+                } else if key == self.keys.popup.select_next {
+                    targets.list_state.select(Some(if selected >= last { 0 } else { selected + 1 }));
+                } else if key == self.keys.popup.select_prev {
+                    targets.list_state.select(Some(if selected == 0 { last } else { selected - 1 }));
+                } else if key == self.keys.popup.toggle_entry {
+                    if let Some(chosen) = targets.chosen.get_mut(selected) {
+                        *chosen = !*chosen;
+                    }
+                }
+            }
+            Popup::IgnoreTemplates => {
+                let Some(picker) = self.ignore_templates.as_mut() else {
+                    self.popup = None;
+                    return Ok(AppReturn::Continue);
+                };
+                let last = picker.matches.len().saturating_sub(1);
+                let selected = picker.list_state.selected().unwrap_or(0);
+                if key == self.keys.popup.select_next {
+                    picker.list_state.select(Some(if selected >= last { 0 } else { selected + 1 }));
+                } else if key == self.keys.popup.select_prev {
+                    picker.list_state.select(Some(if selected == 0 { last } else { selected - 1 }));
+                } else if key == self.keys.popup.toggle_entry {
+                    if let Some(chosen) = picker.chosen.get_mut(selected) {
+                        *chosen = !*chosen;
+                    }
+                } else if key == self.keys.popup.confirm {
+                    self.popup = None;
+                    self.add_ignore_templates()?;
+                } else if key == self.keys.popup.close_popup {
+                    self.popup = None;
+                    self.ignore_templates = None;
+                }
+            }
+            Popup::LinkImport => {
+                let Some(picker) = self.link_import.as_mut() else {
+                    self.popup = None;
+                    return Ok(AppReturn::Continue);
+                };
+                let last = picker.import.links.len().saturating_sub(1);
+                let selected = picker.list_state.selected().unwrap_or(0);
+                if key == self.keys.popup.select_next {
+                    picker.list_state.select(Some(if selected >= last { 0 } else { selected + 1 }));
+                } else if key == self.keys.popup.select_prev {
+                    picker.list_state.select(Some(if selected == 0 { last } else { selected - 1 }));
+                } else if key == self.keys.popup.toggle_entry {
+                    if let Some(chosen) = picker.chosen.get_mut(selected) {
+                        *chosen = !*chosen;
+                    }
+                } else if key == self.keys.popup.confirm {
+                    self.popup = None;
+                    self.import_links()?;
+                } else if key == self.keys.popup.close_popup {
+                    self.popup = None;
+                    self.link_import = None;
+                }
+            }
+            Popup::ApplyResults => {
+                if key == self.keys.popup.confirm || key == self.keys.popup.close_popup {
+                    self.popup = None;
+                    self.apply_results.clear();
+                }
+            }
+            Popup::ConfigName => {
+                if key == self.keys.popup.confirm {
+                    // An invalid name stays to be corrected.
+                    if let Ok(name) = self.config_name.submit() {
+                        self.popup = None;
+                        if !name.trim().is_empty() {
+                            self.open_config_value(name.trim());
+                        }
+                    }
+                } else if key == self.keys.popup.close_popup {
+                    self.config_name.clear();
+                    self.popup = None;
+                } else {
+                    self.config_name.handle_key(key);
+                }
+            }
+            Popup::ConfigValue(name) => {
+                let kind = gitconfig::kind_of(&name);
+                if key == self.keys.popup.confirm {
+                    let value = self.config_value.text().trim().to_string();
+                    if value.is_empty() {
+                        self.popup = None;
+                        self.write_git_config(&name, None);
+                    } else if let Ok(value) = gitconfig::normalize(kind, &value) {
+                        self.popup = None;
+                        self.config_value.clear();
+                        self.write_git_config(&name, Some(&value));
+                    }
+                } else if key == self.keys.popup.close_popup {
+                    self.config_value.clear();
+                    self.popup = None;
+                } else if let (ValueKind::Choice(choices), true) = (kind, key == self.keys.popup.next_field) {
+                    let typed = self.config_value.text().trim();
+                    let next = choices
+                        .iter()
+                        .position(|choice| choice.eq_ignore_ascii_case(typed))
+                        .map_or(0, |i| (i + 1) % choices.len());
+                    self.config_value.set(choices[next]);
+                } else {
+                    self.config_value.handle_key(key);
+                }
+            }
+            Popup::IgnorePattern(index) => {
+                if key == self.keys.popup.confirm {
+                    let pattern = self.ignore_input.submit().unwrap_or_default();
+                    self.popup = None;
+                    if !pattern.trim().is_empty() {
+                        self.save_ignore_pattern(index, pattern.trim())?;
+                    }
+                } else if key == self.keys.popup.close_popup {
+                    self.ignore_input.clear();
+                    self.popup = None;
+                } else {
+                    self.ignore_input.handle_key(key);
+                }
+            }
+            Popup::CloneUrl => {
+                if key == self.keys.popup.confirm {
+                    let url = self.clone_url.text().trim();
+                    if !url.is_empty() {
+                        self.clone_path.set(format!("~/{}", clone_dir_name(url)));
+                        self.clone_field = CloneField::Path;
+                        self.popup = Some(Popup::ClonePath);
+                    }
+                } else if key == self.keys.popup.close_popup {
+                    self.popup = Some(Popup::RepoSwitch);
+                } else {
+                    self.clone_url.handle_key(key);
+                }
+            }
+            Popup::ClonePath => {
+                if key == self.keys.popup.confirm {
+                    let valid = self.clone_depth.error().is_none() && self.clone_branch.error().is_none();
+                    if valid && !self.clone_path.text().trim().is_empty() {
+                        self.start_clone();
+                    }
+                } else if key == self.keys.popup.close_popup {
+                    self.popup = Some(Popup::CloneUrl);
+                } else if key == self.keys.popup.next_field {
+                    self.clone_field = match self.clone_field {
+                        CloneField::Path => CloneField::Depth,
+                        CloneField::Depth => CloneField::Branch,
+                        CloneField::Branch => CloneField::Path,
+                    };
+                } else {
+                    self.clone_input(self.clone_field).handle_key(key);
+                }
+            }
+            // The popup stays until the task reports that it stopped.
+            Popup::Task => {
+                if key == self.keys.popup.close_popup {
+                    match &mut self.task {
+                        Some(task) => {
+                            info!("Cancelling the {} task", task.kind.name().to_lowercase());
+                            task.cancel.cancel();
+                            task.msg = "Cancelling...".to_string();
+                        }
+                        None => self.popup = None,
+                    }
+                }
+            }
+            Popup::PatchExport => {
+                if key == self.keys.popup.confirm {
+                    if !self.patch_input.text().trim().is_empty() {
+                        self.export_patch()?;
+                    }
+                } else if key == self.keys.popup.close_popup {
+                    self.popup = None;
+                    self.patch_export = None;
+                } else if key == self.keys.popup.next_field {
+                    if let Some(export) = &mut self.patch_export {
+                        // Keep a name the user typed; follow the scope otherwise.
+                        let suggested = self.patch_input.text() == export.scope().file_name();
+                        export.selected = (export.selected + 1) % export.scopes.len();
+                        if suggested {
+                            self.patch_input.set(export.scope().file_name());
+                        }
+                    }
+                } else {
+                    self.patch_input.handle_key(key);
+                }
+            }
+            Popup::BranchEdit => {
+                if key == self.keys.popup.confirm {
+                    self.apply_branch_edit()?;
+                } else if key == self.keys.popup.close_popup {
+                    self.popup = None;
+                    self.branch_edit = None;
+                } else if let Some(edit) = &mut self.branch_edit {
+                    if key == self.keys.popup.next_field {
+                        edit.focus = match edit.focus {
+                            BranchField::Name => BranchField::Remote,
+                            BranchField::Remote => BranchField::Merge,
+                            BranchField::Merge => BranchField::Name,
+                        };
+                    } else {
+                        edit.field_mut(edit.focus).handle_key(key);
+                    }
+                }
+            }
+            Popup::DetachedHead => {
+                let Some(detached) = self.detached_head.as_mut() else {
+                    self.popup = None;
+                    return Ok(AppReturn::Continue);
+                };
+                if key == self.keys.popup.select_next || key == self.keys.popup.select_prev {
+                    let last = detached.branches.len();
+                    let i = match detached.list_state.selected() {
+                        Some(i) if key == self.keys.popup.select_next => if i >= last { 0 } else { i + 1 },
+                        Some(i) => if i == 0 { last } else { i - 1 },
+                        None => 0,
+                    };
+                    detached.list_state.select(Some(i));
+                } else if key == self.keys.popup.confirm {
+                    let selected = detached.list_state.selected().unwrap_or(0);
+                    match selected.checked_sub(1).and_then(|i| detached.branches.get(i)).cloned() {
+                        Some(branch) => {
+                            self.popup = None;
+                            self.detached_head = None;
+                            self.start_risky_operation(RiskyOperation::CheckoutBranch(branch))?;
+                        }
+                        None => {
+                            self.branch_input.clear();
+                            self.popup = Some(Popup::BranchCreate);
+                        }
+                    }
+                } else if key == self.keys.popup.close_popup {
+                    self.popup = None;
+                    self.detached_head = None;
+                }
+            }
+            Popup::BranchCreate => {
+                if key == self.keys.popup.confirm {
+                    let name = self.branch_input.text().trim().to_string();
+                    match self.branch_input.error() {
+                        Some(error) => self.notify(Severity::Warning, error),
+                        None if !name.is_empty() => self.create_branch_at_head(&name)?,
+                        None => {}
+                    }
+                } else if key == self.keys.popup.close_popup {
+                    self.popup = Some(Popup::DetachedHead);
+                } else {
+                    self.branch_input.handle_key(key);
+                }
+            }
+            Popup::PatchApply => {
+                if key == self.keys.popup.confirm {
+                    if !self.patch_input.text().trim().is_empty() {
+                        self.popup = None;
+                        self.apply_patch()?;
+                    }
+                } else if key == self.keys.popup.close_popup {
+                    self.popup = None;
+                } else {
+                    self.patch_input.handle_key(key);
+                }
+            }
+            Popup::WorktreeAdd => self.handle_worktree_add_keys(key)?,
+            _ => {
+                if key == self.keys.popup.close_popup || key == self.keys.popup.confirm {
+                    self.popup = None;
+                }
+            }
+        }
+        Ok(AppReturn::Continue)
+    }
+
+    /// Asks `message` in a popup titled `title`, carrying out `on_confirm`
+    /// once the answer is yes.
+    fn confirm(&mut self, title: &str, message: String, on_confirm: ConfirmAction) {
+        self.popup = Some(Popup::Confirm {
+            title: title.to_string(),
+            message,
+            on_confirm,
+        });
+    }
+
+    fn run_confirmed(&mut self, action: ConfirmAction) -> AppResult<()> {
+        match action {
+            ConfirmAction::StashPop(index) => {
+                info!("Popping stash@{{{}}}", index);
+                self.repo.stash_pop(index)?;
+                self.refresh()?;
+            }
+            ConfirmAction::StashDrop(index) => {
+                info!("Dropping stash@{{{}}}", index);
+                self.repo.stash_drop(index)?;
+                self.refresh()?;
+            }
+            ConfirmAction::ForcePush => {
+                let set_upstream = !self.repo.has_upstream()?;
+                self.push_to_remote(PushMode::ForceWithLease, set_upstream);
+            }
+            ConfirmAction::ReflogReset(oid) => {
+                info!("Resetting to {}", oid);
+                self.repo.reset_hard(oid)?;
+                self.refresh()?;
+            }
+            ConfirmAction::Adopt(target) => {
+                if let Some(entry) = self.links.iter().find(|entry| entry.target == target).cloned() {
+                    info!("Adopting {} into {}", entry.target.display(), entry.spec.source);
+                    self.run_link_operation(entry.adopt())?;
+                }
+            }
+            ConfirmAction::RemoveWorktree(name) => self.remove_worktree(name)?,
+            ConfirmAction::Discard(paths) => self.discard(&paths)?,
+        }
+        Ok(())
+    }
+
+    /// Sends the user's answer (or `None` to cancel) back to the waiting
+    /// background task and restores the popup the prompt replaced.
+    fn answer_credential_prompt(&mut self, answer: Option<String>) {
+        if let Some(pending) = self.pending_credential.take() {
+            if answer.is_none() {
+                info!("Credential prompt cancelled");
+            }
+            let _ = pending.request.reply.send(answer);
+            self.popup = pending.previous_popup;
+        } else {
+            self.popup = None;
+        }
+    }
+
+    fn handle_status_action(&mut self, action: Action, sub_mode: StatusMode) -> AppResult<()> {
+        match action {
+            Action::PanelLeft => {
+                self.active_panel = ActivePanel::Files;
+                return Ok(());
+            }
+            Action::PanelRight => {
+                self.active_panel = ActivePanel::Diff;
+                return Ok(());
+            }
+            _ => {}
+        }
+
+        match (sub_mode, self.active_panel, action) {
+            (StatusMode::FileSelection, _, Action::SwitchMode(mode)) => self.mode = mode,
+            (StatusMode::FileSelection, _, Action::Commit) => {
+                if !self.blocked_by_read_only("commit") {
+                    self.open_commit_popup();
+                }
+            }
+            (StatusMode::FileSelection, _, Action::Push) => self.request_push()?,
+            (StatusMode::FileSelection, _, Action::PushTo) => self.open_push_targets()?,
+            (StatusMode::FileSelection, _, Action::ForcePush) => {
+                if !self.blocked_by_read_only("force push") {
+                    self.confirm(
+                        "Force Push",
+                        "Force-push, overwriting the remote branch? This is refused if the remote changed since your last fetch.".to_string(),
+                        ConfirmAction::ForcePush,
+                    );
+                }
+            }
+            (StatusMode::FileSelection, _, Action::Fetch) => self.fetch_from_remote(),
+            (StatusMode::FileSelection, _, Action::Pull) => self.start_risky_operation(RiskyOperation::Pull)?,
+            (StatusMode::FileSelection, _, Action::Divergence) => self.open_divergence()?,
+            (StatusMode::FileSelection, _, Action::EditBranch) => self.open_branch_edit()?,
+            (StatusMode::FileSelection, _, Action::StashPush(flags)) => self.stash_changes(flags)?,
+            (StatusMode::FileSelection, _, Action::Copy) => {
+                if let Some(item) = self.get_selected_status_item() {
+                    self.copy(item.path.clone(), format!("path {}", item.path));
+                }
+            }
+            (StatusMode::FileSelection, _, Action::LoadMore) => self.load_more_diff(),
+            (StatusMode::FileSelection, _, Action::ExportPatch) => self.open_patch_export(None),
+            (StatusMode::FileSelection, _, Action::ApplyPatch) => {
+                if !self.blocked_by_read_only("apply patch") {
+                    self.patch_input.clear();
+                    self.popup = Some(Popup::PatchApply);
+                }
+            }
+            (StatusMode::FileSelection, _, Action::ExternalTool) => {
+                match self.get_selected_status_item() {
+                    Some(item) if item.status.is_conflicted() => self.request_merge_tool(&item.path)?,
+                    Some(item) => self.request_diff_tool(&item)?,
+                    None => {}
+                }
+            }
+            (StatusMode::FileSelection, ActivePanel::Files, action) => match action {
+                Action::SelectNext => self.select_next_status_item(),
+                Action::SelectPrev => self.select_previous_status_item(),
+                Action::StageItem if !self.marked_items.is_empty() => self.stage_marked(true)?,
+                Action::UnstageItem if !self.marked_items.is_empty() => self.stage_marked(false)?,
+                Action::StageItem => self.stage_selected()?,
+                Action::UnstageItem => self.unstage_selected()?,
+                Action::MarkItem => self.toggle_mark(),
+                Action::Discard => self.confirm_discard(),
+                Action::StageAll => self.stage_all()?,
+                Action::UnstageAll => self.unstage_all()?,
+                Action::IgnoreItem => self.ignore_selected()?,
+                Action::FileLog => {
+                    if let Some(item) = self.get_selected_status_item() {
+                        self.show_file_log(item.path)?;
+                    }
+                }
+                Action::ToggleDirGrouping => self.toggle_dir_grouping(),
+                Action::Confirm if self.selected_status_is_dir() => self.toggle_selected_dir(),
+                Action::Confirm if self.get_selected_status_item().is_some_and(|item| item.is_untracked_dir()) => {
+                    self.expand_untracked_dir()?
+                }
+                Action::Search => {
+                    self.search_input.set(self.status_filter.clone());
+                    self.searching = true;
+                }
+                Action::Cancel if !self.marked_items.is_empty() => self.marked_items.clear(),
+                Action::Cancel if !self.status_filter.is_empty() => {
+                    self.apply_status_filter(String::new());
+                }
+                Action::Confirm => {
+                    if let Some(item) = self.get_selected_status_item().filter(|i| i.status.is_conflicted()) {
+                        info!("Entering ConflictResolution mode for file: {}", item.path);
+                        self.conflict = Some(self.repo.conflict_sides(&item.path)?);
+                        self.reset_diff_view();
+                        self.mode = Mode::Status(StatusMode::ConflictResolution);
+                    } else if let Some(item) = self.get_selected_status_item() {
+                        info!("Entering HunkSelection mode for file: {}", item.path);
+                        self.mode = Mode::Status(StatusMode::HunkSelection);
+                        self.current_hunks.clear();
+                        self.hunk_list_state.select(None);
+                        // Otherwise the hunks are shown once the diff loads.
+                        if let Some(hunks) = self.loaded_hunks(&item) {
+                            self.show_hunks(hunks);
+                        }
+                    }
+                }
+                _ => {}
+            },
+            (StatusMode::FileSelection, ActivePanel::Diff, action) => match action {
+                Action::SelectNext => self.scroll_diff_down(),
+                Action::SelectPrev => self.scroll_diff_up(),
+                Action::NextHunk => self.jump_to_hunk(true)?,
+                Action::PrevHunk => self.jump_to_hunk(false)?,
+                _ => {}
+            },
+            (StatusMode::HunkSelection, _, action) => match action {
+                Action::SelectNext | Action::NextHunk => self.select_next_hunk(),
+                Action::SelectPrev | Action::PrevHunk => self.select_previous_hunk(),
+                Action::StageItem => self.stage_selected_hunk()?,
+                Action::SelectLines => self.enter_line_selection(),
+                Action::ExportPatch => self.open_patch_export(self.hunk_list_state.selected()),
+                Action::Copy => {
+                    if let Some(hunk) = self.hunk_list_state.selected().and_then(|i| self.current_hunks.get(i)) {
+                        self.copy(hunk_text(hunk), "the hunk".to_string());
+                    }
+                }
+                _ => {}
+            },
+            (StatusMode::LineSelection, _, action) => match action {
+                Action::SelectNext => self.move_line_cursor(true),
+                Action::SelectPrev => self.move_line_cursor(false),
+                Action::StageItem => self.stage_selected_lines()?,
+                Action::SelectLines => {
+                    if let Some(range) = &mut self.line_selection {
+                        range.anchor = match range.anchor {
+                            Some(_) => None,
+                            None => Some(range.cursor),
+                        };
+                    }
+                }
+                Action::Cancel => self.leave_line_selection(),
+                _ => {}
+            },
+            (StatusMode::ConflictResolution, _, action) => match action {
+                Action::SelectNext => self.scroll_diff_down(),
+                Action::SelectPrev => self.scroll_diff_up(),
+                Action::TakeOurs => self.resolve_conflict(Some(ConflictSide::Ours))?,
+                Action::TakeTheirs => self.resolve_conflict(Some(ConflictSide::Theirs))?,
+                Action::StageItem => self.resolve_conflict(None)?,
+                Action::OpenEditor => {
+                    if let Some(conflict) = &self.conflict {
+                        self.editor_request = Some(self.repo.path().join(&conflict.path));
+                    }
+                }
+                Action::ExternalTool => {
+                    if let Some(path) = self.conflict.as_ref().map(|c| c.path.clone()) {
+                        self.request_merge_tool(&path)?;
+                    }
+                }
+                _ => {}
+            },
+        }
+        Ok(())
+    }
+
+    /// Resolves the open conflict with `side`, or with the work tree version
+    /// as it is (e.g. after editing it) when `side` is `None`.
+    fn resolve_conflict(&mut self, side: Option<ConflictSide>) -> AppResult<()> {
+        if self.blocked_by_read_only("resolve conflict") {
+            return Ok(());
+        }
+        let Some(conflict) = &self.conflict else {
+            return Ok(());
+        };
+        info!("Resolving conflict in {} with {:?}", conflict.path, side);
+        match side {
+            Some(side) => self.repo.resolve_conflict(&conflict.path, side)?,
+            None => self.repo.stage_path(Path::new(&conflict.path))?,
+        }
+        self.leave_conflict_view();
+        self.refresh()
+    }
+
+    /// Offers the selected file, its hunk `hunk` when given, and the staged
+    /// changes for export, the narrowest first.
+    fn open_patch_export(&mut self, hunk: Option<usize>) {
+        let mut scopes = Vec::new();
+        if let Some(item) = self.get_selected_status_item().filter(|item| !item.status.is_conflicted()) {
+            if let Some(hunk) = hunk {
+                scopes.push(PatchScope::Hunk(item.clone(), hunk));
+            }
+            scopes.push(PatchScope::File(item));
+        }
+        if self.status_items.iter().any(|item| item.is_staged) {
+            scopes.push(PatchScope::Staged);
+        }
+        if scopes.is_empty() {
+            self.notify(Severity::Info, "Nothing to export".to_string());
+            return;
+        }
+        let export = PatchExport { scopes, selected: 0 };
+        self.patch_input.set(export.scope().file_name());
+        self.patch_export = Some(export);
+        self.popup = Some(Popup::PatchExport);
+    }
+
+    /// Writes the chosen scope of `Popup::PatchExport` to the typed file.
+    /// An existing file is not overwritten; the popup stays open to pick
+    /// another name.
+    fn export_patch(&mut self) -> AppResult<()> {
+        let Some(export) = &self.patch_export else {
+            return Ok(());
+        };
+        let file = self.repo.path().join(dotfiles::expand_home(self.patch_input.text().trim()));
+        if file.exists() {
+            self.notify(Severity::Warning, format!("{} already exists", file.display()));
+            return Ok(());
+        }
+        let patch = match export.scope() {
+            PatchScope::File(item) => self.repo.item_patch(item, None)?,
+            PatchScope::Hunk(item, hunk) => self.repo.item_patch(item, Some(*hunk))?,
+            PatchScope::Staged => self.repo.staged_patch()?,
+        };
+        let label = export.scope().label();
+        self.patch_input.remember();
+        self.popup = None;
+        self.patch_export = None;
+        if patch.is_empty() {
+            self.notify(Severity::Info, format!("No changes in {}", label));
+            return Ok(());
+        }
+        info!("Exporting {} to {}", label, file.display());
+        match std::fs::write(&file, patch) {
+            Ok(()) => self.notify(Severity::Success, format!("Wrote {} to {}", label, file.display())),
+            Err(e) => {
+                error!("Writing {} failed: {}", file.display(), e);
+                self.notify(Severity::Error, format!("Could not write {}: {}", file.display(), e));
+            }
+        }
+        Ok(())
+    }
+
+    /// Applies the patch file typed into `Popup::PatchApply` to the work
+    /// tree, leaving the result to review and stage.
+    fn apply_patch(&mut self) -> AppResult<()> {
+        self.patch_input.remember();
+        let file = self.repo.path().join(dotfiles::expand_home(self.patch_input.text().trim()));
+        info!("Applying patch {}", file.display());
+        match self.repo.apply_patch_file(&file) {
+            Ok(()) => self.notify(Severity::Success, format!("Applied {}", file.display())),
+            Err(e) => {
+                error!("Applying {} failed: {}", file.display(), e);
+                self.notify(Severity::Error, format!("Could not apply {}: {}", file.display(), e));
+            }
+        }
+        self.refresh()
+    }
+
+    /// Asks the main loop to compare the versions of `item` in the
+    /// configured diff tool.
+    fn request_diff_tool(&mut self, item: &StatusItem) -> AppResult<()> {
+        let Some(tool) = self.configured_tool("diff") else {
+            return Ok(());
+        };
+        let temp_dir = self.repo.tool_dir()?;
+        let files = self.repo.diff_tool_files(item, &temp_dir)?;
+        info!("Opening {} in diff tool {}", item.path, tool);
+        self.tool_request = Some(ToolRequest { command: tool_command(&tool, &files), temp_dir });
+        Ok(())
+    }
+
+    /// Asks the main loop to resolve the conflict in `path` with the
+    /// configured merge tool.
+    fn request_merge_tool(&mut self, path: &str) -> AppResult<()> {
+        if self.blocked_by_read_only("merge tool") {
+            return Ok(());
+        }
+        let Some(tool) = self.configured_tool("merge") else {
+            return Ok(());
+        };
+        let temp_dir = self.repo.tool_dir()?;
+        let files = self.repo.merge_tool_files(path, &temp_dir)?;
+        info!("Opening {} in merge tool {}", path, tool);
+        self.tool_request = Some(ToolRequest { command: tool_command(&tool, &files), temp_dir });
+        Ok(())
+    }
+
+    /// The `tool` of the `[diff]` or `[merge]` config section; tells the
+    /// user how to set one when it is missing.
+    fn configured_tool(&mut self, section: &str) -> Option<String> {
+        let config = if section == "merge" { &self.config.merge } else { &self.config.diff };
+        let tool = config.as_ref().map(|config| config.tool.clone());
+        if tool.is_none() {
+            self.notify(
+                Severity::Warning,
+                format!("No {} tool configured; set {}.tool in the config", section, section),
+            );
+        }
+        tool
+    }
+
+    fn leave_conflict_view(&mut self) {
+        info!("Quitting ConflictResolution mode, returning to FileSelection");
+        self.mode = Mode::Status(StatusMode::FileSelection);
+        self.conflict = None;
+        self.reset_diff_view();
+    }
+
+    fn handle_log_action(&mut self, action: Action) -> AppResult<()> {
+        if self.compare.is_some() {
+            return self.handle_compare_action(action);
+        }
+        match action {
+            Action::SelectNext => self.select_next_log_item()?,
+            Action::SelectPrev => self.select_previous_log_item(),
+            Action::Search => {
+                self.search_input.clear();
+                self.searching = true;
+            }
+            Action::SearchNext => self.jump_to_log_match(true)?,
+            Action::SearchPrev => self.jump_to_log_match(false)?,
+            Action::Confirm => self.open_commit_detail()?,
+            Action::Cancel if self.log_search.is_some() => self.clear_log_search()?,
+            Action::Cancel if self.log_path.is_some() => self.show_full_log()?,
+            Action::MarkCommit => self.mark_selected_commit(),
+            Action::Compare => self.compare_selected_commit()?,
+            Action::LogCheckout => self.open_log_checkout()?,
+            Action::Bisect if !self.blocked_by_read_only("bisect") => self.popup = Some(Popup::Bisect),
+            Action::Copy => {
+                if let Some(oid) = self.selected_log_entry().map(|entry| entry.oid) {
+                    let short = oid.to_string()[..7].to_string();
+                    self.copy(oid.to_string(), format!("commit hash {}", short));
+                }
+            }
+            Action::CopyMessage => {
+                if let Some(oid) = self.selected_log_entry().map(|entry| entry.oid) {
+                    let detail = self.repo.get_commit_detail(oid)?;
+                    let short = oid.to_string()[..7].to_string();
+                    self.copy(detail.message, format!("the message of {}", short));
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn handle_search_input(&mut self, key: KeyEvent) -> AppResult<()> {
+        if key == self.keys.popup.close_popup {
+            self.searching = false;
+            self.search_input.clear();
+            if let Mode::Status(_) = self.mode {
+                self.apply_status_filter(String::new());
+            }
+        } else if key == self.keys.popup.confirm {
+            self.searching = false;
+            let query = self.search_input.submit().unwrap_or_default();
+            if self.mode == Mode::Log {
+                self.search_log(query)?;
+            }
+        } else if self.search_input.handle_key(key) {
+            // The file list narrows live as the filter is typed.
+            if let Mode::Status(_) = self.mode {
+                let filter = self.search_input.text().to_string();
+                self.apply_status_filter(filter);
+            }
+        }
+        Ok(())
+    }
+
+    fn search_log(&mut self, query: String) -> AppResult<()> {
+        if query.is_empty() {
+            return self.clear_log_search();
+        }
+        info!("Searching the log for '{}'", query);
+        self.log_search = Some(LogSearch {
+            query,
+            matches: Vec::new(),
+        });
+        self.request_log_search(true)
+    }
+
+    /// Searches the log for the current query again: the history of a
+    /// single file in memory, the full history in the git worker. With
+    /// `jump`, the first match from the selected commit on is selected once
+    /// the matches are known.
+    fn request_log_search(&mut self, jump: bool) -> AppResult<()> {
+        let Some(search) = &self.log_search else {
+            return Ok(());
+        };
+        if self.log_path.is_some() {
+            let matches = self.file_log_matches(&search.query);
+            return self.show_log_matches(matches, jump);
+        }
+        self.log_search_requested += 1;
+        self.log_search_jump |= jump;
+        self.git.send(GitRequest::SearchLog {
+            generation: self.log_search_requested,
+            query: search.query.clone(),
+        });
+        Ok(())
+    }
+
+    /// Shows every commit again.
+    fn clear_log_search(&mut self) -> AppResult<()> {
+        self.log_search = None;
+        self.order_log()
+    }
+
+    /// Narrows the log to the matches of the current search, selecting the
+    /// first one from the selected commit on with `jump`. Matches past the
+    /// loaded pages have the rest of the history loaded.
+    fn show_log_matches(&mut self, matches: Vec<usize>, jump: bool) -> AppResult<()> {
+        let selected = self.selected_log_entry().map(|commit| commit.oid);
+        let Some(search) = &mut self.log_search else {
+            return Ok(());
+        };
+        let unloaded = matches.last().is_some_and(|&last| last >= self.log_entries.len());
+        search.matches = matches;
+        if unloaded && !self.log_exhausted {
+            self.load_whole_log(false);
+        }
+        self.order_log()?;
+        if !jump {
+            return Ok(());
+        }
+        // The first match at or below the commit that was selected.
+        let below = selected
+            .and_then(|oid| self.log_entries.iter().position(|commit| commit.oid == oid))
+            .and_then(|position| {
+                let search = self.log_search.as_ref()?;
+                search.matches.iter().find(|&&index| index >= position).copied()
+            });
+        match below.and_then(|position| self.log_row(position)) {
+            Some(row) => self.log_table_state.select(Some(row)),
+            None => self.log_table_state.select((self.log_row_count() > 0).then_some(0)),
+        }
+        Ok(())
+    }
+
+    /// Marks the selected commit for a comparison, or unmarks it.
+    fn mark_selected_commit(&mut self) {
+        let Some(oid) = self.selected_log_entry().map(|commit| commit.oid) else {
+            return;
+        };
+        self.log_mark = if self.log_mark == Some(oid) { None } else { Some(oid) };
+    }
+
+    /// Lists the branches and tags at the selected commit for checkout, the
+    /// first branch preselected.
+    fn open_log_checkout(&mut self) -> AppResult<()> {
+        if self.blocked_by_read_only("checkout") {
+            return Ok(());
+        }
+        let Some(oid) = self.selected_log_entry().map(|entry| entry.oid) else {
+            return Ok(());
+        };
+        let refs = self.repo.refs_at(oid)?;
+        self.log_checkout = Some(LogCheckout {
+            oid,
+            refs,
+            list_state: ListState::default().with_selected(Some(0)),
+        });
+        self.popup = Some(Popup::LogCheckout);
+        Ok(())
+    }
+
+    /// The commit the bisect popup marks: the one under test, or else the
+    /// selected commit of the log.
+    pub fn bisect_target(&self) -> Option<git2::Oid> {
+        match self.bisect.as_ref().map(|bisect| &bisect.step) {
+            Some(BisectStep::Test { commit, .. }) => Some(*commit),
+            _ => self.selected_log_entry().map(|entry| entry.oid),
+        }
+    }
+
+    /// Marks `bisect_target` and checks out the next commit to test, or goes
+    /// back to where the bisection started once the first bad commit is
+    /// known. The first mark starts the bisection, which needs a clean work
+    /// tree to check commits out.
+    fn mark_bisect(&mut self, verdict: Verdict) -> AppResult<()> {
+        let Some(oid) = self.bisect_target() else {
+            return Ok(());
+        };
+        let previous = self.bisect.clone();
+        let mut bisect = match self.bisect.take() {
+            Some(bisect) => bisect,
+            None => {
+                if self.repo.has_local_changes()? {
+                    self.notify(Severity::Warning, "Commit or stash the local changes before bisecting.".to_string());
+                    return Ok(());
+                }
+                let origin = match (&self.branch_status.branch, self.branch_status.detached) {
+                    (Some(branch), false) => RiskyOperation::CheckoutBranch(branch.clone()),
+                    _ => RiskyOperation::CheckoutCommit(self.repo.head_commit_id()?),
+                };
+                BisectState {
+                    marks: Bisect::default(),
+                    step: BisectStep::Incomplete,
+                    origin,
+                }
+            }
+        };
+        let mut marks = bisect.marks.clone();
+        marks.mark(oid, verdict);
+        info!("Bisect: {} is {}", oid, verdict.name());
+        let step = match self.repo.bisect_step(&marks) {
+            Ok(step) => step,
+            Err(e) => {
+                self.notify(Severity::Error, e.to_string());
+                self.bisect = Some(bisect);
+                return Ok(());
+            }
+        };
+        bisect.marks = marks;
+        bisect.step = step.clone();
+        let origin = bisect.origin.clone();
+        self.bisect = Some(bisect);
+        match step {
+            BisectStep::Incomplete => {
+                self.popup = None;
+                let missing = if verdict == Verdict::Good { "bad" } else { "good" };
+                self.notify(
+                    Severity::Info,
+                    format!("Marked {:.7} {}. Now select a {} commit and mark it.", oid.to_string(), verdict.name(), missing),
+                );
+            }
+            BisectStep::Test { commit, candidates, steps } => {
+                self.popup = None;
+                match self.repo.checkout_detached(commit) {
+                    Ok(()) => self.notify(
+                        Severity::Info,
+                        format!(
+                            "Testing {:.7}: {} commits left, about {} more tests. Mark it with '{}'.",
+                            commit.to_string(),
+                            candidates,
+                            steps,
+                            key_label(self.keys.log.bisect)
+                        ),
+                    ),
+                    Err(e) => {
+                        // The commit tested before is still checked out, so
+                        // the mark is dropped and that commit stays under test.
+                        error!("Checking out {} to bisect failed: {}", commit, e);
+                        self.bisect = previous;
+                        self.notify(
+                            Severity::Error,
+                            format!("Checkout failed, {:.7} is not marked: {}", oid.to_string(), e),
+                        );
+                    }
+                }
+                self.refresh()?;
+            }
+            BisectStep::Found(_) | BisectStep::Undecided(_) => {
+                self.run_risky_operation(origin)?;
+                if let BisectStep::Found(first_bad) = step {
+                    info!("Bisect: the first bad commit is {}", first_bad);
+                    if let Some(position) = self.log_entries.iter().position(|entry| entry.oid == first_bad) {
+                        self.select_log_position(position)?;
+                    }
+                }
+                self.popup = Some(Popup::Bisect);
+            }
+        }
+        Ok(())
+    }
+
+    /// Ends the bisection, returning to where it started when a commit was
+    /// checked out for it.
+    fn stop_bisect(&mut self) -> AppResult<()> {
+        let Some(bisect) = self.bisect.take() else {
+            return Ok(());
+        };
+        info!("Bisect ended");
+        if matches!(bisect.step, BisectStep::Test { .. }) {
+            self.run_risky_operation(bisect.origin)?;
+        }
+        Ok(())
+    }
+
+    /// Diffs the marked commit against the selected one, or the selected
+    /// commit against the work tree when nothing else is marked.
+    fn compare_selected_commit(&mut self) -> AppResult<()> {
+        let Some(selected) = self.selected_log_entry().map(|commit| commit.oid) else {
+            return Ok(());
+        };
+        let (from, to) = match self.log_mark {
+            Some(mark) if mark != selected => (mark, Some(selected)),
+            _ => (selected, None),
+        };
+        info!("Comparing {} with {:?}", from, to);
+        let to_rev = to.map(|oid| oid.to_string());
+        let files = self.repo.diff_refs(&from.to_string(), to_rev.as_deref())?;
+        let short = |oid: git2::Oid| oid.to_string().chars().take(7).collect::<String>();
+        let mut list_state = ListState::default();
+        list_state.select((!files.is_empty()).then_some(0));
+        self.compare = Some(Compare {
+            from: short(from),
+            to: to.map_or_else(|| "work tree".to_string(), short),
+            files,
+            list_state,
+        });
+        self.active_panel = ActivePanel::Files;
+        self.reset_diff_view();
+        Ok(())
+    }
+
+    /// Moves through the files and the diff of the open comparison, with the
+    /// keys of the status view.
+    fn handle_compare_action(&mut self, action: Action) -> AppResult<()> {
+        let Some(compare) = &mut self.compare else {
+            return Ok(());
+        };
+        match (self.active_panel, &action) {
+            (_, Action::Cancel) => {
+                self.compare = None;
+                self.active_panel = ActivePanel::Files;
+                self.reset_diff_view();
+            }
+            (_, Action::PanelLeft) => self.active_panel = ActivePanel::Files,
+            (_, Action::PanelRight) => self.active_panel = ActivePanel::Diff,
+            (_, Action::LoadMore) => self.load_more_diff(),
+            (_, Action::NextHunk | Action::PrevHunk) => {
+                let forward = matches!(action, Action::NextHunk);
+                let hunks = compare.selected_file().map_or(&[][..], |file| &file.hunks);
+                if let Some(row) = next_hunk_header(hunks, self.diff_scroll, forward) {
+                    self.diff_scroll = row;
+                }
+            }
+            (ActivePanel::Files, Action::SelectNext | Action::SelectPrev) => {
+                let len = compare.files.len();
+                if len == 0 {
+                    return Ok(());
+                }
+                let i = match (compare.list_state.selected(), &action) {
+                    (Some(i), Action::SelectNext) => (i + 1) % len,
+                    (Some(0), _) => len - 1,
+                    (Some(i), _) => i - 1,
+                    (None, _) => 0,
+                };
+                compare.list_state.select(Some(i));
+                self.reset_diff_view();
+            }
+            (ActivePanel::Diff, Action::SelectNext) => self.scroll_diff_down(),
+            (ActivePanel::Diff, Action::SelectPrev) => self.scroll_diff_up(),
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Positions in `log_entries` of the commits matching `query` in the
+    /// history of a single file, which is always loaded completely.
+    fn file_log_matches(&self, query: &str) -> Vec<usize> {
+        let query = query.to_lowercase();
+        self
+            .log_entries
+            .iter()
+            .enumerate()
+            .filter(|(_, commit)| {
+                commit.message.to_lowercase().contains(&query) || commit.author.to_lowercase().contains(&query)
+            })
+            .map(|(position, _)| position)
+            .collect()
+    }
+
+    /// Switches to the log view, limited to the commits that changed `path`.
+    fn show_file_log(&mut self, path: String) -> AppResult<()> {
+        info!("Showing the history of {}", path);
+        self.log_entries = self.repo.get_file_log(&path)?;
+        self.log_path = Some(path);
+        self.log_exhausted = true;
+        self.reset_log_view()
+    }
+
+    /// Goes back from the history of a file to the full history.
+    fn show_full_log(&mut self) -> AppResult<()> {
+        info!("Showing the full history");
+        self.log_path = None;
+        self.log_entries.clear();
+        self.log_exhausted = false;
+        self.load_more_log()?;
+        self.reset_log_view()
+    }
+
+    /// Draws the commit graph of `log_entries` again. Its width is kept so
+    /// that drawing a frame does not go through every row.
+    fn update_log_graph(&mut self) {
+        self.log_graph = render_graph(&self.log_entries);
+        self.log_graph_width = self.log_graph.iter().map(|row| row.width()).max().unwrap_or(0);
+    }
+
+    /// Selects the newest commit of freshly loaded `log_entries`.
+    fn reset_log_view(&mut self) -> AppResult<()> {
+        self.update_log_graph();
+        self.log_search = None;
+        self.log_table_state = TableState::default();
+        self.order_log()?;
+        let first = (!self.log_entries.is_empty()).then_some(0);
+        self.log_table_state.select(first);
+        self.mode = Mode::Log;
+        Ok(())
+    }
+
+    /// Selects the next (or previous) search match, wrapping around.
+    fn jump_to_log_match(&mut self, forward: bool) -> AppResult<()> {
+        let Some(search) = &self.log_search else {
+            return Ok(());
+        };
+        let selected = self.log_table_state.selected();
+        let rows = self.log_rows_of(&search.matches);
+        let target = if forward {
+            rows.iter()
+                .find(|&&row| selected.is_none_or(|s| row > s))
+                .or(rows.first())
+        } else {
+            rows.iter()
+                .rev()
+                .find(|&&row| selected.is_none_or(|s| row < s))
+                .or(rows.last())
+        };
+        let Some(position) = target.and_then(|&row| self.log_entry_index(row)) else {
+            info!("No commits match '{}'", search.query);
+            return Ok(());
+        };
+        self.select_log_position(position)
+    }
+
+    /// Selects the commit at `position` in the history, loading more pages
+    /// of the log until it is available.
+    fn select_log_position(&mut self, position: usize) -> AppResult<()> {
+        while self.log_entries.len() <= position && !self.log_exhausted {
+            self.load_more_log()?;
+        }
+        if let Some(row) = self.log_row(position) {
+            self.log_table_state.select(Some(row));
+        }
+        Ok(())
+    }
+
+    /// How many rows the log table has: the loaded commits, or those the
+    /// search left.
+    pub fn log_row_count(&self) -> usize {
+        self.log_order.as_ref().map_or(self.log_entries.len(), Vec::len)
+    }
+
+    /// The position in `log_entries` of row `row` of the log table.
+    pub fn log_entry_index(&self, row: usize) -> Option<usize> {
+        match &self.log_order {
+            Some(order) => order.get(row).copied(),
+            None => (row < self.log_entries.len()).then_some(row),
+        }
+    }
+
+    /// The row of the log table that shows `log_entries[position]`; `None`
+    /// when the search filtered it out.
+    fn log_row(&self, position: usize) -> Option<usize> {
+        match &self.log_order {
+            Some(order) => order.iter().position(|&index| index == position),
+            None => (position < self.log_entries.len()).then_some(position),
+        }
+    }
+
+    /// The table rows of the history positions `positions` that are shown,
+    /// ascending.
+    fn log_rows_of(&self, positions: &[usize]) -> Vec<usize> {
+        let mut rows: Vec<usize> = positions.iter().filter_map(|&position| self.log_row(position)).collect();
+        rows.sort_unstable();
+        rows
+    }
+
+    pub fn selected_log_entry(&self) -> Option<&CommitInfo> {
+        let row = self.log_table_state.selected()?;
+        self.log_entries.get(self.log_entry_index(row)?)
+    }
+
+    /// Sorts the log by `column`: ascending on the first click of its
+    /// header, descending on the second and back in history order on the
+    /// third.
+    fn toggle_log_sort(&mut self, column: LogColumn) -> AppResult<()> {
+        self.log_sort = match self.log_sort {
+            Some(sort) if sort.column == column && !sort.descending => Some(LogSort { column, descending: true }),
+            Some(sort) if sort.column == column => None,
+            _ => Some(LogSort { column, descending: false }),
+        };
+        info!("Sorting the log by {:?}", self.log_sort);
+        self.order_log()
+    }
+
+    /// Has the git worker load the rest of the history, which jumping to
+    /// the oldest commit and sorting need, selecting the oldest commit once
+    /// it is there with `select_last`.
+    fn load_whole_log(&mut self, select_last: bool) {
+        self.log_select_last |= select_last;
+        if self.is_loading_whole_log() {
+            return;
+        }
+        info!("Loading the rest of the history");
+        self.whole_log_requested += 1;
+        self.git.send(GitRequest::WholeLog {
+            generation: self.whole_log_requested,
+        });
+    }
+
+    /// Takes the whole history, keeping the selected commit selected, or
+    /// selecting the oldest one with `select_last`.
+    fn show_whole_log(&mut self, entries: Vec<CommitInfo>, select_last: bool) -> AppResult<()> {
+        let selected = self.selected_log_entry().map(|commit| commit.oid);
+        self.log_entries = entries;
+        self.log_exhausted = true;
+        self.update_log_graph();
+        if let Some(position) = selected.and_then(|oid| self.log_entries.iter().position(|c| c.oid == oid)) {
+            self.log_table_state.select(Some(position));
+        }
+        self.order_log()?;
+        if select_last && self.log_row_count() > 0 {
+            self.log_table_state.select(Some(self.log_row_count() - 1));
+        }
+        self.request_log_search(false)
+    }
+
+    /// Rebuilds `log_order` for `log_sort` and the matches of `log_search`,
+    /// keeping the selected commit selected while it is shown, or else
+    /// selecting the first row. Sorting needs every commit: until the git
+    /// worker loaded the rest of the history, what is loaded is sorted.
+    /// While a search runs, every commit is shown, as its matches are not
+    /// known yet.
+    fn order_log(&mut self) -> AppResult<()> {
+        let selected = self.selected_log_entry().map(|commit| commit.oid);
+        let filter = self.log_search.as_ref().filter(|_| !self.is_searching_log());
+        let mut order: Vec<usize> = match filter {
+            Some(search) => search.matches.iter().copied().filter(|&index| index < self.log_entries.len()).collect(),
+            None => (0..self.log_entries.len()).collect(),
+        };
+        let filtered = filter.is_some();
+        if let Some(sort) = self.log_sort {
+            if !self.log_exhausted {
+                self.load_whole_log(false);
+            }
+            let entries = &self.log_entries;
+            // A stable sort, so that ties stay in history order.
+            order.sort_by(|&a, &b| {
+                let (a, b) = (&entries[a], &entries[b]);
+                let ordering = match sort.column {
+                    LogColumn::Hash => a.oid.cmp(&b.oid),
+                    LogColumn::Author => a.author.to_lowercase().cmp(&b.author.to_lowercase()),
+                    LogColumn::Date => a.timestamp.cmp(&b.timestamp),
+                };
+                if sort.descending { ordering.reverse() } else { ordering }
+            });
+        }
+        self.log_order = (filtered || self.log_sort.is_some()).then_some(order);
+        let row = selected
+            .and_then(|oid| self.log_entries.iter().position(|c| c.oid == oid))
+            .and_then(|position| self.log_row(position));
+        let first = (self.log_row_count() > 0).then_some(0);
+        self.log_table_state.select(row.or(first));
+        Ok(())
+    }
+
+    fn open_commit_detail(&mut self) -> AppResult<()> {
+        let Some(oid) = self.selected_log_entry().map(|commit| commit.oid) else {
+            return Ok(());
+        };
+        self.commit_detail = Some(self.repo.get_commit_detail(oid)?);
+        self.popup = Some(Popup::CommitDetail);
+        Ok(())
+    }
+
+    fn handle_stash_action(&mut self, action: Action) -> AppResult<()> {
+        match action {
+            Action::SelectNext => self.select_next_stash(),
+            Action::SelectPrev => self.select_previous_stash(),
+            Action::StashApply | Action::StashPop | Action::StashDrop => {
+                if self.blocked_by_read_only("stash operation") {
+                    return Ok(());
+                }
+                let Some(index) = self.selected_stash_index() else {
+                    return Ok(());
+                };
+                match action {
+                    Action::StashApply => {
+                        info!("Applying stash@{{{}}}", index);
+                        self.repo.stash_apply(index)?;
+                        self.refresh()?;
+                    }
+                    Action::StashPop => {
+                        self.confirm("Confirm", format!("Pop stash@{{{}}}?", index), ConfirmAction::StashPop(index))
+                    }
+                    _ => self.confirm("Confirm", format!("Drop stash@{{{}}}?", index), ConfirmAction::StashDrop(index)),
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn handle_messages_action(&mut self, action: Action) {
+        let len = self.notifications.history_len();
+        if len == 0 {
+            return;
+        }
+        let selected = self.messages_list_state.selected();
+        let i = match action {
+            Action::SelectNext => selected.map_or(0, |i| (i + 1) % len),
+            Action::SelectPrev => selected.map_or(0, |i| if i == 0 { len - 1 } else { i - 1 }),
+            _ => return,
+        };
+        self.messages_list_state.select(Some(i));
+    }
+
+    fn handle_ignore_action(&mut self, action: Action) -> AppResult<()> {
+        match action {
+            Action::SelectNext => self.select_next_ignore_rule(),
+            Action::SelectPrev => self.select_previous_ignore_rule(),
+            Action::IgnoreCheck => {
+                let path = self.ignore_check.as_ref().map(|check| check.path.clone()).unwrap_or_default();
+                self.ignore_input.set(path);
+                self.popup = Some(Popup::IgnoreCheckPath);
+            }
+            Action::IgnoreAdd => {
+                if self.blocked_by_read_only("adding an ignore pattern") {
+                    return Ok(());
+                }
+                self.ignore_input.clear();
+                self.popup = Some(Popup::IgnorePattern(None));
+            }
+            Action::IgnoreEdit => {
+                if self.blocked_by_read_only("editing an ignore pattern") {
+                    return Ok(());
+                }
+                let Some(index) = self.ignore_table_state.selected() else {
+                    return Ok(());
+                };
+                if let Some(rule) = self.ignore_rules.get(index) {
+                    self.ignore_input.set(rule.pattern.clone());
+                    self.popup = Some(Popup::IgnorePattern(Some(index)));
+                }
+            }
+            Action::IgnoreTemplates => self.open_ignore_templates(),
+            Action::IgnoreRemove => {
+                if self.blocked_by_read_only("removing an ignore pattern") {
+                    return Ok(());
+                }
+                let Some(rule) = self
+                    .ignore_table_state
+                    .selected()
+                    .and_then(|index| self.ignore_rules.get(index))
+                    .cloned()
+                else {
+                    return Ok(());
+                };
+                info!("Removing '{}' from {}", rule.pattern, rule.file.label);
+                let result = ignore::replace_line(&rule.file.path, rule.line, None);
+                self.ignore_file_changed(&rule.file, result)?;
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Reloads the ignore rules and checks the last checked path again.
+    fn refresh_ignore_rules(&mut self) {
+        self.ignore_rules = self.repo.ignore_rules().unwrap_or_else(|e| {
+            error!("Failed to read the ignore files: {}", e);
+            Vec::new()
+        });
+        if self.ignore_rules.is_empty() {
+            self.ignore_table_state.select(None);
+        } else {
+            let last = self.ignore_rules.len() - 1;
+            let selected = self.ignore_table_state.selected().map_or(0, |i| i.min(last));
+            self.ignore_table_state.select(Some(selected));
+        }
+        if let Some(check) = self.ignore_check.take() {
+            self.check_ignored(&check.path);
+        }
+    }
+
+    /// Checks whether `path` (relative to the work tree) is ignored and
+    /// selects the rule that decides it. libgit2 has the final say; the
+    /// rule is worked out separately, since libgit2 does not report it.
+    fn check_ignored(&mut self, path: &str) {
+        let is_dir = path.ends_with('/') || self.repo.path().join(path).is_dir();
+        let ignored = self.repo.is_path_ignored(Path::new(path));
+        let rule = ignore::deciding_rule(&self.ignore_rules, path, is_dir).cloned();
+        if let Some(index) = rule
+            .as_ref()
+            .and_then(|rule| self.ignore_rules.iter().position(|r| r == rule))
+        {
+            self.ignore_table_state.select(Some(index));
+        }
+        debug!("{} ignored: {} ({:?})", path, ignored, rule);
+        self.ignore_check = Some(IgnoreCheck {
+            path: path.to_string(),
+            ignored,
+            rule,
+        });
+    }
+
+    /// Adds `pattern` to the root `.gitignore`, or replaces the rule at
+    /// `index` with it.
+    fn save_ignore_pattern(&mut self, index: Option<usize>, pattern: &str) -> AppResult<()> {
+        match index.and_then(|index| self.ignore_rules.get(index)).cloned() {
+            Some(rule) => {
+                info!("Changing '{}' in {} to '{}'", rule.pattern, rule.file.label, pattern);
+                let result = ignore::replace_line(&rule.file.path, rule.line, Some(pattern));
+                self.ignore_file_changed(&rule.file, result)
+            }
+            None => {
+                info!("Adding '{}' to .gitignore", pattern);
+                let result = self.repo.add_ignore_pattern(pattern);
+                self.ignore_file_changed(&self.root_gitignore(), result)
+            }
+        }
+    }
+
+    fn root_gitignore(&self) -> IgnoreFile {
+        IgnoreFile {
+            path: self.repo.path().join(".gitignore"),
+            label: ".gitignore".to_string(),
+            base: String::new(),
+            in_work_tree: true,
+        }
+    }
+
+    /// Scans the work tree for secret-looking files that nothing ignores
+    /// yet and offers the templates that would, all of them chosen.
+    fn open_ignore_templates(&mut self) {
+        let matches = ignore_templates::detect(self.repo.path(), |path| self.repo.is_path_ignored(Path::new(path)));
+        if matches.is_empty() {
+            self.notify(Severity::Info, "No secret-looking files that are not ignored yet".to_string());
+            return;
+        }
+        self.ignore_templates = Some(TemplatePicker {
+            chosen: vec![true; matches.len()],
+            matches,
+            list_state: ListState::default().with_selected(Some(0)),
+        });
+        self.popup = Some(Popup::IgnoreTemplates);
+    }
+
+    /// Appends the patterns of the chosen templates that the root
+    /// `.gitignore` lacks, each template under a comment with its name.
+    fn add_ignore_templates(&mut self) -> AppResult<()> {
+        let Some(picker) = self.ignore_templates.take() else {
+            return Ok(());
+        };
+        let file = self.root_gitignore();
+        let existing = std::fs::read_to_string(&file.path).unwrap_or_default();
+        let mut added = 0;
+        let mut result = Ok(());
+        let chosen = picker.matches.iter().zip(&picker.chosen).filter(|(_, &chosen)| chosen);
+        for (found, _) in chosen {
+            let patterns = found.template.missing_patterns(&existing);
+            if patterns.is_empty() {
+                continue;
+            }
+            info!("Adding the {} template to .gitignore", found.template.name);
+            result = std::iter::once(format!("# {}", found.template.name))
+                .chain(patterns.iter().map(|pattern| pattern.to_string()))
+                .try_for_each(|line| self.repo.add_ignore_pattern(&line));
+            if result.is_err() {
+                break;
+            }
+            added += patterns.len();
+        }
+        if result.is_ok() {
+            let message = match added {
+                0 => "The chosen patterns are already in .gitignore".to_string(),
+                1 => "Added 1 pattern to .gitignore".to_string(),
+                added => format!("Added {} patterns to .gitignore", added),
+            };
+            self.notify(Severity::Success, message);
+        }
+        self.ignore_file_changed(&file, result)
+    }
+
+    /// Reports a failed edit of an ignore file, or stages the file when
+    /// `stage_gitignore` is set, and refreshes so the status shows what is
+    /// ignored now.
+    fn ignore_file_changed(&mut self, file: &IgnoreFile, result: AppResult<()>) -> AppResult<()> {
+        if let Err(e) = result {
+            error!("Editing {} failed: {}", file.label, e);
+            self.notify(Severity::Error, format!("Could not edit {}: {}", file.label, e));
+            return Ok(());
+        }
+        if self.config.stage_gitignore && file.in_work_tree {
+            self.repo.stage_path(Path::new(&file.label))?;
+        }
+        self.refresh()
+    }
+
+    /// Reads the whole history for the stats view in the background; on a
+    /// large repository that takes a while.
+    pub(crate) fn load_stats(&mut self) {
+        info!("Computing history statistics");
+        self.stats_view = StatsView::Loading;
+        let location = self.repo.location();
+        let sender = self.app_event_sender.clone();
+        tokio::task::spawn_blocking(move || {
+            let stats = GitRepo::open(&location).and_then(|repo| repo.history_stats());
+            let _ = sender.send(AppEvent::StatsLoaded(stats));
+        });
+    }
+
+    /// Runs the checks of the doctor view in the background; reaching the
+    /// remote may take a while.
+    pub(crate) fn load_doctor(&mut self) {
+        info!("Running the doctor's checks");
+        self.doctor_view = DoctorView::Loading;
+        let location = self.repo.location();
+        let encryption = self.config.encryption.clone();
+        let sender = self.app_event_sender.clone();
+        tokio::task::spawn_blocking(move || {
+            let checks = doctor::examine(&location, encryption.as_ref());
+            let _ = sender.send(AppEvent::DoctorChecked(checks));
+        });
+    }
+
+    pub(crate) fn handle_doctor_action(&mut self, action: Action) -> AppResult<()> {
+        let checks = match &self.doctor_view {
+            DoctorView::Loaded(checks) => checks.as_slice(),
+            DoctorView::Loading => &[],
+        };
+        match action {
+            Action::SelectNext | Action::SelectPrev if !checks.is_empty() => {
+                let last = checks.len() - 1;
+                let i = match self.doctor_table_state.selected() {
+                    Some(i) if matches!(action, Action::SelectNext) => if i >= last { 0 } else { i + 1 },
+                    Some(i) => if i == 0 { last } else { i - 1 },
+                    None => 0,
+                };
+                self.doctor_table_state.select(Some(i));
+            }
+            Action::DoctorRecheck => self.load_doctor(),
+            Action::Confirm => {
+                let fix = self
+                    .doctor_table_state
+                    .selected()
+                    .and_then(|i| checks.get(i))
+                    .and_then(|check| check.fix.clone());
+                if let Some(fix) = fix {
+                    self.run_fix(fix)?;
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Carries out a fix of the doctor view and checks again, except for
+    /// commands, which are copied for the user to run, and files and the
+    /// git config, which are checked again once edited and `DoctorRecheck`
+    /// is pressed.
+    fn run_fix(&mut self, fix: Fix) -> AppResult<()> {
+        let modifies = matches!(fix, Fix::SetUpstream(_) | Fix::MakeExecutable(_) | Fix::ApplyLinks);
+        if modifies && self.blocked_by_read_only("fix") {
+            return Ok(());
+        }
+        info!("Doctor: {}", fix.description());
+        match fix {
+            Fix::SetUpstream(remote) => {
+                self.repo.set_upstream(&remote)?;
+                self.notify(Severity::Success, format!("Tracking the branch of the same name on {}", remote));
+                self.refresh()?;
+            }
+            Fix::EditFile(path) => {
+                self.editor_request = Some(path);
+                return Ok(());
+            }
+            Fix::EditGitConfig => {
+                self.dispatch(Action::SwitchMode(Mode::GitConfig))?;
+                return Ok(());
+            }
+            Fix::MakeExecutable(hooks) => {
+                #[cfg(unix)]
+                for hook in &hooks {
+                    use std::os::unix::fs::PermissionsExt;
+                    let mut permissions = std::fs::metadata(hook)?.permissions();
+                    permissions.set_mode(permissions.mode() | 0o111);
+                    std::fs::set_permissions(hook, permissions)?;
+                }
+                self.notify(Severity::Success, format!("Made {} hooks executable", hooks.len()));
+            }
+            Fix::ApplyLinks => self.apply_links()?,
+            Fix::Run(command) => {
+                self.copy(command, "the command".to_string());
+                return Ok(());
+            }
+        }
+        self.load_doctor();
+        Ok(())
+    }
+
+    /// Reads the git config entries again, keeping the selection.
+    pub(crate) fn load_git_config(&mut self) {
+        match self.repo.config_entries() {
+            Ok(entries) => {
+                self.git_config = entries;
+                self.git_config_error = None;
+            }
+            Err(e) => {
+                error!("Failed to read the git config: {}", e);
+                self.git_config.clear();
+                self.git_config_error = Some(e.to_string());
+            }
+        }
+        let last = self.git_config.len().saturating_sub(1);
+        let selected = self.git_config_table_state.selected().unwrap_or(0).min(last);
+        self.git_config_table_state.select((!self.git_config.is_empty()).then_some(selected));
+    }
+
+    pub(crate) fn handle_git_config_action(&mut self, action: Action) -> AppResult<()> {
+        let selected = self
+            .git_config_table_state
+            .selected()
+            .and_then(|i| self.git_config.get(i))
+            .cloned();
+        match action {
+            Action::SelectNext | Action::SelectPrev if !self.git_config.is_empty() => {
+                let last = self.git_config.len() - 1;
+                let i = match self.git_config_table_state.selected() {
+                    Some(i) if matches!(action, Action::SelectNext) => if i >= last { 0 } else { i + 1 },
+                    Some(i) => if i == 0 { last } else { i - 1 },
+                    None => 0,
+                };
+                self.git_config_table_state.select(Some(i));
+            }
+            Action::ConfigScope => self.git_config_scope = self.git_config_scope.other(),
+            Action::ConfigAdd => {
+                if self.blocked_by_read_only("editing the git config") {
+                    return Ok(());
+                }
+                self.config_name.clear();
+                self.popup = Some(Popup::ConfigName);
+            }
+            Action::ConfigEdit => {
+                if self.blocked_by_read_only("editing the git config") {
+                    return Ok(());
+                }
+                let Some(entry) = selected else {
+                    return Ok(());
+                };
+                if entry.kind == ValueKind::Bool && !entry.multivalued {
+                    let value = entry.value(self.git_config_scope).or(entry.local.as_deref()).or(entry.global.as_deref());
+                    let flipped = match value.map(|value| gitconfig::normalize(ValueKind::Bool, value)) {
+                        Some(Ok(value)) if value == "true" => "false",
+                        _ => "true",
+                    };
+                    self.write_git_config(&entry.name, Some(flipped));
+                } else {
+                    self.open_config_value(&entry.name);
+                }
+            }
+            Action::ConfigUnset => {
+                if self.blocked_by_read_only("editing the git config") {
+                    return Ok(());
+                }
+                if let Some(entry) = selected {
+                    self.write_git_config(&entry.name, None);
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Asks for the value of `name` in the selected scope, starting from the
+    /// one it has. Entries set more than once are left to `git config`.
+    fn open_config_value(&mut self, name: &str) {
+        let entry = self.git_config.iter().find(|entry| entry.name.eq_ignore_ascii_case(name));
+        if entry.is_some_and(|entry| entry.multivalued) {
+            self.notify(
+                Severity::Warning,
+                format!("{} is set more than once; edit it with `git config --edit`", name),
+            );
+            return;
+        }
+        let value = entry.and_then(|entry| entry.value(self.git_config_scope)).unwrap_or_default();
+        self.config_value.set(value);
+        self.popup = Some(Popup::ConfigValue(name.to_string()));
+    }
+
+    /// Sets `name` to `value` in the config file of `git_config_scope`, or
+    /// unsets it for `None`, and selects it.
+    fn write_git_config(&mut self, name: &str, value: Option<&str>) {
+        let scope = self.git_config_scope;
+        let is_set = self
+            .git_config
+            .iter()
+            .find(|entry| entry.name.eq_ignore_ascii_case(name))
+            .is_some_and(|entry| entry.value(scope).is_some());
+        let result = match value {
+            Some(value) => self.repo.set_config(scope, name, value),
+            None if is_set => self.repo.unset_config(scope, name),
+            None => {
+                self.notify(Severity::Info, format!("{} is not set in the {} config", name, scope.name()));
+                return;
+            }
+        };
+        match (result, value) {
+            (Ok(()), Some(value)) => {
+                info!("Set {} to '{}' in the {} config", name, value, scope.name());
+                self.notify(Severity::Success, format!("{} = {} ({})", name, value, scope.name()));
+            }
+            (Ok(()), None) => {
+                info!("Unset {} in the {} config", name, scope.name());
+                self.notify(Severity::Success, format!("Unset {} ({})", name, scope.name()));
+            }
+            (Err(e), _) => {
+                error!("Writing {} to the {} config failed: {}", name, scope.name(), e);
+                self.notify(Severity::Error, format!("Could not write the {} config: {}", scope.name(), e));
+            }
+        }
+        self.load_git_config();
+        if let Some(i) = self.git_config.iter().position(|entry| entry.name.eq_ignore_ascii_case(name)) {
+            self.git_config_table_state.select(Some(i));
+        }
+    }
+
+    pub(crate) fn handle_links_action(&mut self, action: Action) -> AppResult<()> {
+        match action {
+            Action::SelectNext => self.select_next_link(),
+            Action::SelectPrev => self.select_previous_link(),
+            Action::ProfileSwitch if !self.blocked_by_read_only("link operation") => {
+                self.open_profile_switch();
+            }
+            Action::LinkImport if !self.blocked_by_read_only("link import") => self.open_link_import(),
+            Action::LinkApply if !self.blocked_by_read_only("link operation") => self.apply_links()?,
+            Action::LinkCreate | Action::LinkRemove | Action::LinkAdopt => {
+                if self.blocked_by_read_only("link operation") {
+                    return Ok(());
+                }
+                let Some(index) = self.links_table_state.selected() else {
+                    return Ok(());
+                };
+                let Some(entry) = self.links.get(index).cloned() else {
+                    return Ok(());
+                };
+                match action {
+                    Action::LinkCreate => {
+                        info!("Linking {} -> {}", entry.target.display(), entry.source.display());
+                        match self.create_link(&entry) {
+                            Err(AppError::SecretNeeded) => self.open_secret_prompt(index),
+                            result => self.run_link_operation(result)?,
+                        }
+                    }
+                    Action::LinkRemove => {
+                        info!("Removing link {}", entry.target.display());
+                        self.run_link_operation(entry.remove())?;
+                    }
+                    _ if entry.state == LinkState::Decrypted => self.confirm(
+                        "Confirm",
+                        "Copy the decrypted file back into the repository? It is encrypted again when staged.".to_string(),
+                        ConfirmAction::Adopt(entry.target.clone()),
+                    ),
+                    _ => self.confirm(
+                        "Confirm",
+                        "Move the existing file into the repository (replacing the repository copy) and link it?".to_string(),
+                        ConfirmAction::Adopt(entry.target.clone()),
+                    ),
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Looks for GNU Stow packages or a chezmoi source directory and offers
+    /// the links they imply that the manifest lacks, all of them chosen.
+    fn open_link_import(&mut self) {
+        let mut import = match import::detect(self.repo.path()) {
+            Ok(Some(import)) => import,
+            Ok(None) => {
+                self.notify(Severity::Info, "No GNU Stow packages or chezmoi source directory found".to_string());
+                return;
+            }
+            Err(e) => {
+                error!("Looking for links to import failed: {}", e);
+                self.notify(Severity::Error, format!("Cannot import links: {}", e));
+                return;
+            }
+        };
+        for (path, reason) in &import.skipped {
+            info!("Not importing {}: {}", path, reason);
+        }
+        import.links.retain(|link| !self.links.iter().any(|entry| entry.spec.target == link.target));
+        if import.links.is_empty() {
+            self.notify(Severity::Info, format!("{} already has the {} links", MANIFEST_FILE, import.source.name()));
+            return;
+        }
+        self.link_import = Some(ImportPicker {
+            chosen: vec![true; import.links.len()],
+            import,
+            list_state: ListState::default().with_selected(Some(0)),
+        });
+        self.popup = Some(Popup::LinkImport);
+    }
+
+    /// Adds the chosen links of `Popup::LinkImport` to the manifest.
+    fn import_links(&mut self) -> AppResult<()> {
+        let Some(picker) = self.link_import.take() else {
+            return Ok(());
+        };
+        let links: Vec<_> = picker
+            .import
+            .links
+            .into_iter()
+            .zip(picker.chosen)
+            .filter_map(|(link, chosen)| chosen.then_some(link))
+            .collect();
+        if links.is_empty() {
+            return Ok(());
+        }
+        info!("Importing {} {} links into {}", links.len(), picker.import.source.name(), MANIFEST_FILE);
+        let result = dotfiles::Manifest::add_links(self.repo.path(), &links);
+        if result.is_ok() {
+            self.notify(Severity::Success, format!("Added {} links to {}", links.len(), MANIFEST_FILE));
+        }
+        self.run_link_operation(result)
+    }
+
+    /// Brings every link of the manifest in line with the repository, see
+    /// `apply::apply`, and lists the result of each.
+    fn apply_links(&mut self) -> AppResult<()> {
+        let backup_dir = apply::backup_dir(self.config.backup_dir.as_deref());
+        let results = apply::apply(&self.links, &backup_dir, |entry| match &self.config.encryption {
+            Some(config) => secrets::decrypt(config, &entry.source, self.secret.as_deref()),
+            None => Err(AppError::Secret("no [encryption] settings".to_string())),
+        });
+        let failed = results
+            .iter()
+            .filter(|result| matches!(result.outcome, ApplyOutcome::Failed(_)))
+            .count();
+        info!("Applied {} links, {} failed", results.len(), failed);
+        if results.iter().any(|result| matches!(result.outcome, ApplyOutcome::BackedUp(_))) {
+            info!("Backed up the files in the way to {}", backup_dir.display());
+        }
+        self.apply_results = results;
+        self.popup = Some(Popup::ApplyResults);
+        self.run_link_operation(Ok(()))
+    }
+
+    /// Creates the link of `entry`, or for a secret writes its decrypted
+    /// copy to the target.
+    fn create_link(&self, entry: &LinkEntry) -> AppResult<()> {
+        let Some(config) = self.config.encryption.as_ref().filter(|_| entry.encrypted) else {
+            return entry.create();
+        };
+        let plaintext = secrets::decrypt(config, &entry.source, self.secret.as_deref())?;
+        entry.deploy(&plaintext)
+    }
+
+    fn open_secret_prompt(&mut self, index: usize) {
+        self.secret_input.clear();
+        self.popup = Some(Popup::SecretPrompt(index));
+    }
+
+    /// Deploys the secret of the link at `index` with what the user entered
+    /// in `Popup::SecretPrompt`, which is forgotten again if it does not
+    /// work.
+    fn answer_secret_prompt(&mut self, index: usize) -> AppResult<()> {
+        self.popup = None;
+        self.secret = Some(std::mem::take(&mut self.secret_input));
+        let Some(entry) = self.links.get(index).cloned() else {
+            return Ok(());
+        };
+        let result = self.create_link(&entry);
+        if result.is_err() {
+            self.secret = None;
+        }
+        self.run_link_operation(result)
+    }
+
+    /// Whether `path` is one of the secrets `[encryption]` keeps encrypted.
+    pub fn is_secret(&self, path: &str) -> bool {
+        self.config
+            .encryption
+            .as_ref()
+            .is_some_and(|config| secrets::is_secret(config, path))
+    }
+
+    /// Encrypts the secrets among `paths` (files or untracked directories)
+    /// in place, so that only their ciphertext is staged. Files that are
+    /// encrypted already are left alone.
+    fn encrypt_secrets(&self, paths: &[String]) -> AppResult<()> {
+        match &self.config.encryption {
+            Some(config) => encrypt_work_tree_secrets(config, self.repo.path(), paths),
+            None => Ok(()),
+        }
+    }
+
+    fn open_profile_switch(&mut self) {
+        if self.profiles.is_empty() {
+            self.notify(Severity::Warning, format!("No [[profile]] entries in {}", MANIFEST_FILE));
+            return;
+        }
+        let active = self.active_profile.as_ref().map(|p| p.name.as_str());
+        let selected = self.profiles.iter().position(|p| Some(p.name.as_str()) == active);
+        self.profile_list_state.select(Some(selected.unwrap_or(0)));
+        self.popup = Some(Popup::ProfileSwitch);
+    }
+
+    /// Makes the profile at `index` the active one: checks out its branch and
+    /// links its files, replacing the links of the previous profile.
+    fn switch_profile(&mut self, index: usize) -> AppResult<()> {
+        let Some(profile) = self.profiles.get(index).cloned() else {
+            return Ok(());
+        };
+        info!("Switching to profile {}", profile.name);
+        if let Some(branch) = &profile.branch {
+            if self.repo.current_branch().ok().flatten().as_ref() != Some(branch) {
+                if let Err(e) = self.repo.checkout_branch(branch) {
+                    error!("Checking out {} failed: {}", branch, e);
+                    self.links_error = Some(format!("Could not check out {}: {}", branch, e));
+                    return Ok(());
+                }
+            }
+        }
+        self.repo.set_selected_profile(&profile.name)?;
+        self.refresh()?;
+        // The links of the new profile are needed right away.
+        self.refresh_links();
+
+        let mut updated = 0;
+        let mut failure = None;
+        for entry in &self.links {
+            if !matches!(entry.state, LinkState::Missing | LinkState::Stale) {
+                continue;
+            }
+            match self.create_link(entry) {
+                Ok(()) => updated += 1,
+                Err(e) => {
+                    error!("Linking {} failed: {}", entry.target.display(), e);
+                    failure = Some(e.to_string());
+                }
+            }
+        }
+        self.refresh()?;
+        self.links_error = failure;
+        self.notify(Severity::Success, format!(
+            "Switched to profile '{}', updated {} link(s)",
+            profile.name, updated
+        ));
+        Ok(())
+    }
+
+    /// Returns `true`, logging the skipped `action`, when the app runs read-only.
+    fn blocked_by_read_only(&self, action: &str) -> bool {
+        if self.config.read_only {
+            warn!("Ignoring {} in read-only mode", action);
+        }
+        self.config.read_only
+    }
+
+    fn stage_selected(&mut self) -> AppResult<()> {
+        if self.blocked_by_read_only("stage") {
+            return Ok(());
+        }
+        if let Some(item) = self.get_selected_status_item() {
+            if !item.is_staged {
+                info!("Staging item: {}", item.path);
+                self.encrypt_secrets(std::slice::from_ref(&item.path))?;
+                self.record_path_change(std::slice::from_ref(&item.path), format!("stage {}", item.path), |repo| {
+                    repo.stage_item(&item)
+                })?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Stages the selected hunk of an unstaged file, or unstages it when the
+    /// file is staged. Stays in hunk mode while the file still has hunks left.
+    fn stage_selected_hunk(&mut self) -> AppResult<()> {
+        if self.blocked_by_read_only("hunk staging") || self.blocked_as_secret() {
+            return Ok(());
+        }
+        // Hunks are addressed by index, which is only safe once the hunks
+        // shown match the file again.
+        if self.is_loading() {
+            debug!("Ignoring hunk staging while the diff is loading");
+            return Ok(());
+        }
+        if let Some(hunk_index) = self.hunk_list_state.selected() {
+            if let Some(item) = self.get_selected_status_item() {
+                let paths = [item.path.clone()];
+                if item.is_staged {
+                    info!("Unstaging hunk #{} for file '{}'", hunk_index, item.path);
+                    self.record_path_change(&paths, format!("unstage hunk in {}", item.path), |repo| {
+                        repo.unstage_hunk(&item, hunk_index)
+                    })?;
+                } else {
+                    info!("Staging hunk #{} for file '{}'", hunk_index, item.path);
+                    self.record_path_change(&paths, format!("stage hunk in {}", item.path), |repo| {
+                        repo.stage_hunk(&item, hunk_index)
+                    })?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Shows `hunks` in hunk mode, falling back to file selection once
+    /// nothing is left to pick from.
+    fn show_hunks(&mut self, hunks: Vec<Hunk>) {
+        self.current_hunks = hunks;
+        if self.current_hunks.is_empty() {
+            info!("No hunks left to select");
+            self.mode = Mode::Status(StatusMode::FileSelection);
+            self.hunk_list_state.select(None);
+            self.line_selection = None;
+        } else {
+            let selected = self
+                .hunk_list_state
+                .selected()
+                .unwrap_or(0)
+                .min(self.current_hunks.len() - 1);
+            self.hunk_list_state.select(Some(selected));
+            // The hunk may have shrunk under the line cursor.
+            let last = self.current_hunks[selected].lines.len().saturating_sub(1);
+            if let Some(range) = &mut self.line_selection {
+                range.anchor = range.anchor.map(|anchor| anchor.min(last));
+                range.cursor = range.cursor.min(last);
+            }
+        }
+    }
+
+    /// Starts picking lines of the selected hunk, on its first changed line.
+    fn enter_line_selection(&mut self) {
+        let Some(hunk) = self.hunk_list_state.selected().and_then(|i| self.current_hunks.get(i)) else {
+            return;
+        };
+        let first = hunk
+            .lines
+            .iter()
+            .position(|line| matches!(line.origin, '+' | '-'))
+            .unwrap_or(0);
+        info!("Entering LineSelection mode");
+        self.line_selection = Some(LineRange { anchor: None, cursor: first });
+        self.mode = Mode::Status(StatusMode::LineSelection);
+    }
+
+    fn leave_line_selection(&mut self) {
+        info!("Quitting LineSelection mode, returning to HunkSelection");
+        self.line_selection = None;
+        self.mode = Mode::Status(StatusMode::HunkSelection);
+    }
+
+    /// Moves the line cursor, which extends or shrinks a started range. It
+    /// stops at the ends of the hunk.
+    fn move_line_cursor(&mut self, down: bool) {
+        let len = self
+            .hunk_list_state
+            .selected()
+            .and_then(|i| self.current_hunks.get(i))
+            .map_or(0, |hunk| hunk.lines.len());
+        if let Some(range) = &mut self.line_selection {
+            range.cursor = match down {
+                true => (range.cursor + 1).min(len.saturating_sub(1)),
+                false => range.cursor.saturating_sub(1),
+            };
+        }
+    }
+
+    /// Stages (or, for a staged file, unstages) the selected lines of the
+    /// hunk, then goes back to picking hunks.
+    fn stage_selected_lines(&mut self) -> AppResult<()> {
+        if self.blocked_by_read_only("line staging") || self.blocked_as_secret() {
+            return Ok(());
+        }
+        if self.is_loading() {
+            debug!("Ignoring line staging while the diff is loading");
+            return Ok(());
+        }
+        let hunk = self.hunk_list_state.selected().and_then(|i| self.current_hunks.get(i)).cloned();
+        let (Some(range), Some(hunk), Some(item)) = (self.line_selection, hunk, self.get_selected_status_item()) else {
+            return Ok(());
+        };
+        let lines = range.lines();
+        let changed = hunk
+            .lines
+            .iter()
+            .enumerate()
+            .any(|(i, line)| lines.contains(&i) && matches!(line.origin, '+' | '-'));
+        if !changed {
+            self.notify(Severity::Info, "Select added or removed lines to stage".to_string());
+            return Ok(());
+        }
+        let verb = if item.is_staged { "unstage" } else { "stage" };
+        info!("Trying to {} lines {:?} of a hunk in '{}'", verb, lines, item.path);
+        self.record_path_change(std::slice::from_ref(&item.path), format!("{} lines in {}", verb, item.path), |repo| {
+            repo.stage_lines(&item, &hunk, lines)
+        })?;
+        self.leave_line_selection();
+        Ok(())
+    }
+
+    /// The hunks of `item`, if its diff is loaded and up to date.
+    fn loaded_hunks(&self, item: &StatusItem) -> Option<Vec<Hunk>> {
+        if self.diff_loaded != self.diff_requested {
+            return None;
+        }
+        self.diff
+            .as_ref()
+            .filter(|diff| diff.item == *item)
+            .map(|diff| diff.hunks.clone())
+    }
+
+    /// Shows the diff of the selected file, asking the git worker for it
+    /// unless it is cached.
+    fn request_diff(&mut self) {
+        self.diff_item = self.get_selected_status_item();
+        self.diff_requested += 1;
+        let cached = self
+            .diff_item
+            .as_ref()
+            .and_then(|item| self.diff_cache.get(&self.diff_key(item)).cloned());
+        match (&self.diff_item, cached) {
+            (Some(_), Some(diff)) => {
+                self.diff = Some(diff);
+                self.diff_loaded = self.diff_requested;
+            }
+            (Some(item), None) => self.git.send(GitRequest::Diff {
+                generation: self.diff_requested,
+                item: item.clone(),
+                size_limit: self.config.diff_size_limit.unwrap_or(DIFF_SIZE_LIMIT),
+            }),
+            (None, _) => {
+                self.diff = None;
+                self.diff_loaded = self.diff_requested;
+            }
+        }
+    }
+
+    /// Asks the worker for the churn of the status items that have none
+    /// or an outdated one, and forgets the files that are gone.
+    fn request_churn(&mut self) {
+        let index_mtime = self.repo.index_mtime();
+        let mut churn = HashMap::with_capacity(self.status_items.len());
+        let mut missing = Vec::new();
+        for item in &self.status_items {
+            let file_mtime = match item.is_staged {
+                true => None,
+                false => std::fs::metadata(self.repo.path().join(&item.path))
+                    .and_then(|metadata| metadata.modified())
+                    .ok(),
+            };
+            let key = ChurnKey {
+                path: item.path.clone(),
+                staged: item.is_staged,
+                index_mtime,
+                file_mtime,
+            };
+            let id = (item.path.clone(), item.is_staged);
+            match self.churn.remove(&id) {
+                Some(known) if known.0 == key => {
+                    churn.insert(id, known);
+                }
+                _ => {
+                    missing.push((key.clone(), item.clone()));
+                    churn.insert(id, (key, None));
+                }
+            }
+        }
+        self.churn = churn;
+        if !missing.is_empty() {
+            self.git.send(GitRequest::Churn {
+                items: missing,
+                size_limit: self.config.diff_size_limit.unwrap_or(DIFF_SIZE_LIMIT),
+            });
+        }
+    }
+
+    /// The churn of `item`, once the worker counted it.
+    pub fn churn_of(&self, item: &StatusItem) -> Option<&Churn> {
+        let (_, churn) = self.churn.get(&(item.path.clone(), item.is_staged))?;
+        churn.as_ref()
+    }
+
+    fn diff_key(&self, item: &StatusItem) -> DiffKey {
+        DiffKey {
+            path: item.path.clone(),
+            staged: item.is_staged,
+            index_mtime: self.repo.index_mtime(),
+        }
+    }
+
+    /// Requests the diff again when another file was selected.
+    fn sync_diff(&mut self) {
+        if self.get_selected_status_item() != self.diff_item {
+            self.request_diff();
+        }
+    }
+
+    /// Applies a refresh loaded by the git worker. In hunk mode the file
+    /// being edited stays selected, since staging a hunk moves it around.
+    fn apply_snapshot(&mut self, snapshot: RepoSnapshot) -> AppResult<()> {
+        let hunk_item = self.hunk_mode_item();
+        self.status_items = snapshot.status_items;
+        self.work_tree_summary = snapshot.summary;
+        // A bisection detaches HEAD on purpose.
+        if snapshot.branch_status.detached && !self.branch_status.detached && self.bisect.is_none() {
+            let commit = snapshot.branch_status.branch.as_deref().unwrap_or_default();
+            warn!("HEAD is detached at {}", commit);
+            self.notify(
+                Severity::Warning,
+                format!(
+                    "HEAD is detached at {}: new commits belong to no branch. Press {} to create a branch here or return to one.",
+                    commit,
+                    key_label(self.keys.status.edit_branch)
+                ),
+            );
+        }
+        self.branch_status = snapshot.branch_status;
+        self.request_churn();
+        self.apply_stashes_and_reflog(snapshot.stashes, snapshot.reflog);
+        self.take_links(snapshot.links);
+        self.refresh_tree(snapshot.tracked_files);
+        // The log view may have switched between a file's history and the
+        // full one since; its own log is loaded already then.
+        if snapshot.log_path == self.log_path {
+            self.log_entries = snapshot.log_entries;
+            self.log_exhausted = snapshot.log_exhausted;
+        }
+        self.update_log_graph();
+        self.order_log()?;
+        // New commits shift every position, so the matches are looked up
+        // again; the worker answers from its cache while HEAD stays put.
+        self.request_log_search(false)?;
+        self.rebuild_status_list();
+
+        info!(
+            "Refresh complete. Display list has {} items.",
+            self.status_display_list.len()
+        );
+
+        self.reselect_hunk_item(hunk_item);
+        if let Some(session) = self.pending_session.take() {
+            self.apply_session_selections(&session);
+        }
+        self.clamp_status_selection();
+
+        match self.log_row_count().checked_sub(1) {
+            None => self.log_table_state.select(None),
+            Some(last) => {
+                let selected = self.log_table_state.selected().map_or(0, |i| i.min(last));
+                self.log_table_state.select(Some(selected));
+            }
+        }
+
+        // The selected file may have changed even if it is still selected.
+        self.request_diff();
+        Ok(())
+    }
+
+    /// The file whose hunks or lines are shown.
+    fn hunk_mode_item(&self) -> Option<StatusItem> {
+        match self.mode {
+            Mode::Status(StatusMode::HunkSelection | StatusMode::LineSelection) => self.get_selected_status_item(),
+            _ => None,
+        }
+    }
+
+    /// Selects the file whose hunks were shown again after the file list
+    /// was rebuilt, or leaves hunk mode when it has no changes left there.
+    fn reselect_hunk_item(&mut self, hunk_item: Option<StatusItem>) {
+        let Some(item) = hunk_item else {
+            return;
+        };
+        let position = self.status_display_list.iter().position(|entry| {
+            matches!(entry, StatusItemType::Item(i) if i.path == item.path && i.is_staged == item.is_staged)
+        });
+        match position {
+            Some(index) => self.status_list_state.select(Some(index)),
+            None => self.show_hunks(Vec::new()),
+        }
+    }
+
+    fn apply_session_selections(&mut self, session: &Session) {
+        if let Some(path) = &session.selected_path {
+            let index = self.status_display_list.iter().position(
+                |item_type| matches!(item_type, StatusItemType::Item(item) if &item.path == path),
+            );
+            if index.is_some() {
+                self.status_list_state.select(index);
+            }
+        }
+        if let Some(id) = &session.log_selected {
+            let position = self.log_entries.iter().position(|commit| &commit.oid.to_string() == id);
+            if let Some(row) = position.and_then(|position| self.log_row(position)) {
+                self.log_table_state.select(Some(row));
+                // Keeps the selection where it was on screen.
+                *self.log_table_state.offset_mut() = session.log_offset.min(row);
+            }
+        }
+    }
+
+    fn unstage_selected(&mut self) -> AppResult<()> {
+        if self.blocked_by_read_only("unstage") {
+            return Ok(());
+        }
+        if let Some(item) = self.get_selected_status_item() {
+            if item.is_staged {
+                info!("Unstaging file: {}", item.path);
+                self.record_path_change(std::slice::from_ref(&item.path), format!("unstage {}", item.path), |repo| {
+                    repo.unstage_file(&item.path)
+                })?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Marks the selected file for a batch operation, or unmarks it, and
+    /// moves on to the next file.
+    fn toggle_mark(&mut self) {
+        let Some(item) = self.get_selected_status_item() else {
+            return;
+        };
+        let key = (item.path, item.is_staged);
+        if !self.marked_items.remove(&key) {
+            self.marked_items.insert(key);
+        }
+        self.select_next_status_item();
+    }
+
+    /// Whether `item` is marked for a batch operation.
+    pub fn is_marked(&self, item: &StatusItem) -> bool {
+        self.marked_items.contains(&(item.path.clone(), item.is_staged))
+    }
+
+    pub fn marked_count(&self) -> usize {
+        self.marked_items.len()
+    }
+
+    /// Stages the marked unstaged files, or unstages the marked staged ones,
+    /// as one undoable change with a single refresh. The marks are cleared.
+    fn stage_marked(&mut self, stage: bool) -> AppResult<()> {
+        let verb = if stage { "stage" } else { "unstage" };
+        if self.blocked_by_read_only(verb) {
+            return Ok(());
+        }
+        let items: Vec<StatusItem> = self
+            .status_items
+            .iter()
+            .filter(|item| item.is_staged != stage && self.is_marked(item) && !item.status.is_conflicted())
+            .cloned()
+            .collect();
+        self.marked_items.clear();
+        if items.is_empty() {
+            self.notify(Severity::Info, format!("None of the marked files can be {}d", verb));
+            return Ok(());
+        }
+        info!("Trying to {} {} marked file(s)", verb, items.len());
+        let description = match items.len() {
+            1 => format!("{} {}", verb, items[0].path),
+            count => format!("{} {} files", verb, count),
+        };
+        let paths: Vec<String> = items.iter().map(|item| item.path.clone()).collect();
+        if stage {
+            self.encrypt_secrets(&paths)?;
+        }
+        self.record_path_change(&paths, description, |repo| {
+            items.iter().try_for_each(|item| match stage {
+                true => repo.stage_item(item),
+                false => repo.unstage_file(&item.path),
+            })
+        })
+    }
+
+    /// Asks before throwing away the unstaged changes of the marked files,
+    /// or of the selected one when none are marked.
+    fn confirm_discard(&mut self) {
+        if self.blocked_by_read_only("discard") {
+            return;
+        }
+        let items: Vec<StatusItem> = match self.marked_items.is_empty() {
+            true => self.get_selected_status_item().into_iter().collect(),
+            false => self.status_items.iter().filter(|item| self.is_marked(item)).cloned().collect(),
+        };
+        let paths: Vec<String> = items
+            .into_iter()
+            .filter(|item| !item.is_staged && !item.status.is_conflicted())
+            .map(|item| item.path)
+            .collect();
+        let files = match paths.as_slice() {
+            [] => {
+                self.notify(Severity::Info, "Only unstaged changes can be discarded".to_string());
+                return;
+            }
+            [path] => path.clone(),
+            paths => format!("{} files", paths.len()),
+        };
+        self.confirm(
+            "Discard Changes",
+            format!("Discard the unstaged changes to {}? Untracked files are deleted. This cannot be undone.", files),
+            ConfirmAction::Discard(paths),
+        );
+    }
+
+    /// Discards the unstaged changes of `paths`, as far as they still have
+    /// any, with a single refresh. The marks are cleared.
+    fn discard(&mut self, paths: &[String]) -> AppResult<()> {
+        let items: Vec<StatusItem> = self
+            .status_items
+            .iter()
+            .filter(|item| !item.is_staged && !item.status.is_conflicted() && paths.contains(&item.path))
+            .cloned()
+            .collect();
+        self.marked_items.clear();
+        info!("Discarding the changes to {} file(s)", items.len());
+        for item in &items {
+            self.repo.discard_changes(item)?;
+        }
+        self.refresh()
+    }
+
+    fn stage_all(&mut self) -> AppResult<()> {
+        if self.blocked_by_read_only("stage all") {
+            return Ok(());
+        }
+        info!("Staging all changes");
+        let untracked = self.config.untracked_files(&self.expanded_untracked_dirs);
+        self.encrypt_unstaged_secrets(Some(&untracked))?;
+        self.record_index_change("stage all".to_string(), |repo| repo.stage_all(Some(&untracked)))?;
+        self.refresh()
+    }
+
+    /// Encrypts the secrets `GitRepo::stage_all` would stage with
+    /// `untracked`. The work tree is read again rather than taken from the
+    /// status list, which may be stale or show untracked directories as
+    /// one entry.
+    fn encrypt_unstaged_secrets(&self, untracked: Option<&UntrackedFiles>) -> AppResult<()> {
+        if self.config.encryption.is_none() {
+            return Ok(());
+        }
+        self.encrypt_secrets(&self.repo.unstaged_paths(untracked)?)
+    }
+
+    /// Secrets are encrypted as a whole, so their hunks and lines cannot be
+    /// staged one by one.
+    fn blocked_as_secret(&mut self) -> bool {
+        let Some(item) = self.get_selected_status_item().filter(|item| !item.is_staged) else {
+            return false;
+        };
+        if !self.is_secret(&item.path) {
+            return false;
+        }
+        self.notify(Severity::Info, format!("{} is a secret; stage it whole to encrypt it", item.path));
+        true
+    }
+
+    fn unstage_all(&mut self) -> AppResult<()> {
+        if self.blocked_by_read_only("unstage all") {
+            return Ok(());
+        }
+        info!("Unstaging all changes");
+        self.record_index_change("unstage all".to_string(), GitRepo::unstage_all)?;
+        self.refresh()
+    }
+
+    /// Adds the selected untracked file to `.gitignore`, staging the ignore
+    /// file as well when `stage_gitignore` is set.
+    fn ignore_selected(&mut self) -> AppResult<()> {
+        if self.blocked_by_read_only("ignore") {
+            return Ok(());
+        }
+        let Some(item) = self.get_selected_status_item() else {
+            return Ok(());
+        };
+        if item.is_staged || !item.status.is_wt_new() {
+            info!("Only untracked files can be ignored: {}", item.path);
+            return Ok(());
+        }
+        info!("Adding {} to .gitignore", item.path);
+        self.repo.ignore_path(&item.path)?;
+        if self.config.stage_gitignore {
+            self.repo.stage_path(Path::new(".gitignore"))?;
+        }
+        self.refresh()
+    }
+
+    fn open_commit_popup(&mut self) {
+        self.sign_commit = self.repo.signing_enabled();
+        self.no_verify = false;
+        if self.commit_editor.is_empty() {
+            self.conventional.enabled = self.config.conventional_commits;
+            if let Some(template) = self.repo.commit_template() {
+                self.commit_editor.set_text(&template);
+            } else if self.config.prefill_subject {
+                self.prefill_subject();
+            }
+            self.conventional.focus = if self.conventional.enabled {
+                CommitField::Type
+            } else {
+                CommitField::Message
+            };
+        }
+        self.update_subject_guide();
+        #[cfg(feature = "spellcheck")]
+        if self.dictionary.is_none() {
+            let path = self
+                .config
+                .dictionary
+                .clone()
+                .unwrap_or_else(|| PathBuf::from(lint::DEFAULT_DICTIONARY));
+            match lint::Dictionary::load(&path) {
+                Ok(dictionary) => self.dictionary = Some(dictionary),
+                Err(e) => warn!("Spell check disabled, cannot read {}: {}", path.display(), e),
+            }
+        }
+        self.update_commit_warnings();
+        self.popup = Some(Popup::Commit);
+    }
+
+    /// Puts a subject guessed from the staged paths into the empty commit
+    /// popup. With the conventional-commit helper the topics become the
+    /// scope.
+    fn prefill_subject(&mut self) {
+        let staged: Vec<StatusItem> = self.status_items.iter().filter(|item| item.is_staged).cloned().collect();
+        let Some(subject) = suggest_subject(&staged) else {
+            return;
+        };
+        if self.conventional.enabled {
+            self.conventional.scope = subject.scope.replace(", ", ",");
+            self.commit_editor.set_text(&subject.summary);
+        } else {
+            self.commit_editor.set_text(&subject.to_string());
+        }
+    }
+
+    /// Re-lints the commit message. Called after every edit, which also
+    /// withdraws a confirmation to commit despite warnings.
+    fn update_commit_warnings(&mut self) {
+        let prefix = if self.conventional.enabled {
+            self.conventional.prefix().chars().count()
+        } else {
+            0
+        };
+        let text = self.commit_editor.text();
+        let body_width = self.config.body_width.unwrap_or(lint::BODY_WIDTH);
+        self.commit_warnings = lint_message(&text, prefix, self.subject_limit(), body_width);
+        #[cfg(feature = "spellcheck")]
+        if let Some(dictionary) = &self.dictionary {
+            let prose: Vec<&str> = text.lines().filter(|line| !line.starts_with('#')).collect();
+            let unknown = dictionary.misspelled(&prose.join("\n"));
+            if !unknown.is_empty() {
+                self.commit_warnings
+                    .push(format!("Unknown words: {}", unknown.join(", ")));
+            }
+        }
+        self.commit_warnings_confirmed = false;
+    }
+
+    /// The longest subject, prefix included, before the commit popup warns.
+    pub fn subject_limit(&self) -> usize {
+        self.config.subject_limit.unwrap_or(DEFAULT_SUBJECT_LIMIT)
+    }
+
+    /// Moves the editor's subject guide so that it accounts for the prefix
+    /// that will be added to the subject.
+    fn update_subject_guide(&mut self) {
+        let prefix = if self.conventional.enabled {
+            self.conventional.prefix().chars().count()
+        } else {
+            0
+        };
+        self.commit_editor
+            .set_subject_guide(self.subject_limit().saturating_sub(prefix));
+    }
+
+    fn handle_commit_field_input(&mut self, key: KeyEvent) {
+        match self.conventional.focus {
+            CommitField::Message => {
+                let before = self.commit_editor.text();
+                self.commit_editor.input(key);
+                if self.commit_editor.text() != before {
+                    self.update_commit_warnings();
+                }
+            }
+            CommitField::Type => {
+                let count = COMMIT_TYPES.len();
+                let index = &mut self.conventional.type_index;
+                match key.code {
+                    KeyCode::Right | KeyCode::Down | KeyCode::Char('j') => *index = (*index + 1) % count,
+                    KeyCode::Left | KeyCode::Up | KeyCode::Char('k') => *index = (*index + count - 1) % count,
+                    _ => {}
+                }
+                self.update_subject_guide();
+                self.update_commit_warnings();
+            }
+            CommitField::Scope => {
+                match key.code {
+                    KeyCode::Char(c) if !c.is_whitespace() && !"():".contains(c) => {
+                        self.conventional.scope.push(c)
+                    }
+                    KeyCode::Backspace => {
+                        self.conventional.scope.pop();
+                    }
+                    _ => {}
+                }
+                self.update_subject_guide();
+                self.update_commit_warnings();
+            }
+        }
+    }
+
+    fn submit_commit(&mut self) -> AppResult<()> {
+        if !self.commit_editor.is_empty() {
+            // Strip comments, trailing whitespace and surplus blank lines like
+            // `git commit` does.
+            let mut message = git2::message_prettify(self.commit_editor.text(), Some(b'#'))?;
+            if message.trim().is_empty() {
+                self.notify(Severity::Warning, "Aborting commit due to empty commit message".to_string());
+                return Ok(());
+            }
+            if !self.commit_warnings.is_empty() && !self.commit_warnings_confirmed {
+                self.commit_warnings_confirmed = true;
+                self.notify(Severity::Warning, format!(
+                    "The message has {} warning(s); press Ctrl+S again to commit anyway",
+                    self.commit_warnings.len()
+                ));
+                return Ok(());
+            }
+            if self.conventional.enabled {
+                message.insert_str(0, &self.conventional.prefix());
+            }
+            if !self.no_verify && !self.repo.commit_hooks().is_empty() {
+                self.run_commit_hooks(message);
+                return Ok(());
+            }
+            self.create_commit(&message)?;
+        }
+        Ok(())
+    }
+
+    /// Runs the commit hooks in the background, showing their output; the
+    /// commit is created once they passed.
+    fn run_commit_hooks(&mut self, message: String) {
+        info!("Running the commit hooks.");
+        self.hook_run = Some(HookRun::default());
+        self.popup = Some(Popup::Hooks);
+        let location = self.repo.location();
+        let sender = self.app_event_sender.clone();
+        tokio::task::spawn_blocking(move || {
+            let result = GitRepo::open(&location).and_then(|repo| {
+                repo.run_commit_hooks(&message, |line| {
+                    let _ = sender.send(AppEvent::HookOutput(line));
+                })
+            });
+            let _ = sender.send(AppEvent::HooksFinished(result));
+        });
+    }
+
+    fn create_commit(&mut self, message: &str) -> AppResult<()> {
+        info!("Attempting to commit with message: '{}'", message.trim_end());
+        // The first commit of a repository has no parent to go back to.
+        let parent = match self.repo.head_commit_id() {
+            Ok(parent) => Some(parent),
+            Err(AppError::NoCommits) => None,
+            Err(e) => return Err(e),
+        };
+        let commit = self.repo.commit(message, self.sign_commit)?;
+        info!("Commit successful.");
+        let subject = message.lines().next().unwrap_or_default();
+        self.push_undo(format!("commit \"{}\"", subject), UndoAction::Commit { commit, parent });
+        self.commit_editor.clear();
+        self.conventional.scope.clear();
+        self.popup = None;
+        self.emit_script_event(ScriptEvent::PostCommit, Some(&commit.to_string()))?;
+        self.refresh()
+    }
+
+    /// Applies `change` to the index and records the previous index on the
+    /// undo stack, unless the change left the index as it was.
+    fn record_index_change<F>(&mut self, description: String, change: F) -> AppResult<()>
+    where
+        F: FnOnce(&GitRepo) -> AppResult<()>,
+    {
+        let before = self.repo.snapshot_index();
+        change(&self.repo)?;
+        match before {
+            Ok(before) if self.repo.snapshot_index().ok() != Some(before) => {
+                self.push_undo(description, before);
+            }
+            Ok(_) => {}
+            Err(e) => warn!("Cannot offer undo for {}: {}", description, e),
+        }
+        Ok(())
+    }
+
+    /// Makes an undoable change to the index like `record_index_change`
+    /// and then rereads the status of `paths` only, which it changed,
+    /// instead of the whole work tree and the log. Refreshes fully when a
+    /// refresh is under way, since it was read before the change, and for
+    /// untracked directories and conflicts, which change more than a file.
+    fn record_path_change<F>(&mut self, paths: &[String], description: String, change: F) -> AppResult<()>
+    where
+        F: FnOnce(&GitRepo) -> AppResult<()>,
+    {
+        let untracked = self.config.untracked_files(&self.expanded_untracked_dirs);
+        let conflicted = self
+            .status_items
+            .iter()
+            .any(|item| item.status.is_conflicted() && paths.contains(&item.path));
+        if self.is_refreshing() || conflicted || paths.iter().any(|path| path.ends_with('/')) {
+            self.record_index_change(description, change)?;
+            return self.refresh();
+        }
+        let before = self.repo.work_tree_summary_for_paths(paths, &untracked)?;
+        self.record_index_change(description, change)?;
+        let items = self.repo.get_status_for_paths(paths, &untracked)?;
+        // An unstaged new file may belong in a collapsed directory.
+        if matches!(untracked, UntrackedFiles::Collapsed(_)) && items.iter().any(|item| item.status.is_wt_new()) {
+            return self.refresh();
+        }
+        let after = self.repo.work_tree_summary_for_paths(paths, &untracked)?;
+        debug!("Updating the status of {} path(s)", paths.len());
+        self.work_tree_summary.replace(before, after);
+        self.diff_cache.clear();
+        let hunk_item = self.hunk_mode_item();
+        self.status_items.retain(|item| !paths.contains(&item.path));
+        self.status_items.extend(items);
+        // Stable, so a partially staged file stays unstaged first.
+        self.status_items.sort_by(|a, b| a.path.cmp(&b.path));
+        self.request_churn();
+        self.rebuild_status_list();
+        self.reselect_hunk_item(hunk_item);
+        self.clamp_status_selection();
+        self.request_diff();
+        Ok(())
+    }
+
+    fn push_undo(&mut self, description: String, action: UndoAction) {
+        if self.undo_stack.len() == UNDO_LIMIT {
+            self.undo_stack.remove(0);
+        }
+        self.undo_stack.push(UndoEntry { description, action });
+    }
+
+    /// Reverts the most recent stage, unstage or commit.
+    fn undo(&mut self) -> AppResult<()> {
+        if self.blocked_by_read_only("undo") {
+            return Ok(());
+        }
+        let Some(entry) = self.undo_stack.pop() else {
+            self.notify(Severity::Info, "Nothing to undo".to_string());
+            return Ok(());
+        };
+        info!("Undoing {}", entry.description);
+        match self.repo.undo(&entry.action) {
+            Ok(()) => self.notify(Severity::Success, format!("Undid {}", entry.description)),
+            Err(e) => {
+                error!("Undoing {} failed: {}", entry.description, e);
+                self.notify(Severity::Error, format!("Could not undo {}: {}", entry.description, e));
+            }
+        }
+        self.refresh()
+    }
+
+    /// Shows `message` as a toast and keeps it in the messages view.
+    fn notify(&mut self, severity: Severity, message: String) {
+        self.notifications.push(severity, message);
+    }
+
+    fn stash_changes(&mut self, flags: StashFlags) -> AppResult<()> {
+        if self.blocked_by_read_only("stash") {
+            return Ok(());
+        }
+        if self.status_display_list.is_empty() {
+            info!("Nothing to stash.");
+            return Ok(());
+        }
+        if flags.contains(StashFlags::INCLUDE_UNTRACKED) {
+            let untracked = self.config.untracked_files(&self.expanded_untracked_dirs);
+            if !self.repo.shows_untracked(&untracked)? {
+                self.notify(
+                    Severity::Warning,
+                    "Untracked files are hidden in this repository, so they are not stashed.".to_string(),
+                );
+                return Ok(());
+            }
+        }
+        info!("Stashing local changes ({:?}).", flags);
+        self.repo.stash_save(None, flags)?;
+        self.refresh()
+    }
+
+    /// Lists `tracked_files`, those of HEAD, plus the new files from the
+    /// work tree.
+    fn refresh_tree(&mut self, tracked_files: Vec<String>) {
+        let mut entries: Vec<TreeEntry> = tracked_files
+            .into_iter()
+            .map(|path| TreeEntry { path, tracked: true })
+            .collect();
+        for item in &self.status_items {
+            if (item.status.is_wt_new() || item.status.is_index_new())
+                && !item.is_untracked_dir()
+                && !entries.iter().any(|entry| entry.path == item.path)
+            {
+                entries.push(TreeEntry {
+                    path: item.path.clone(),
+                    tracked: false,
+                });
+            }
+        }
+        self.file_tree.set_entries(entries);
+        // The previewed file may have changed on disk.
+        self.tree_preview = None;
+    }
+
+    /// The text of `path` for the preview of the tree view.
+    pub fn tree_preview(&mut self, path: &str) -> &str {
+        if self.tree_preview.as_ref().is_none_or(|(previewed, _)| previewed != path) {
+            let text = match std::fs::read(self.repo.path().join(path)) {
+                Ok(bytes) if bytes.contains(&0) => "(binary file)".to_string(),
+                Ok(bytes) => String::from_utf8_lossy(&bytes).into_owned(),
+                Err(e) => format!("Cannot read file: {}", e),
+            };
+            self.tree_preview = Some((path.to_string(), text));
+        }
+        self.tree_preview.as_ref().map_or("", |(_, text)| text)
+    }
+
+    fn handle_tree_action(&mut self, action: Action) -> AppResult<()> {
+        if self.active_panel == ActivePanel::Diff {
+            match action {
+                Action::SelectNext => self.scroll_diff_down(),
+                Action::SelectPrev => self.scroll_diff_up(),
+                Action::PanelLeft => self.active_panel = ActivePanel::Files,
+                _ => {}
+            }
+        } else {
+            match action {
+                Action::SelectNext => {
+                    self.file_tree.select_next();
+                    self.reset_diff_view();
+                }
+                Action::SelectPrev => {
+                    self.file_tree.select_previous();
+                    self.reset_diff_view();
+                }
+                Action::Confirm => self.file_tree.toggle(),
+                Action::PanelLeft => self.file_tree.collapse(),
+                Action::PanelRight => match self.file_tree.selected() {
+                    Some(row) if row.is_dir && !row.expanded => self.file_tree.toggle(),
+                    Some(row) if !row.is_dir => self.active_panel = ActivePanel::Diff,
+                    _ => {}
+                },
+                _ => {}
+            }
+        }
+
+        let Some(row) = self.file_tree.selected().filter(|row| !row.is_dir).cloned() else {
+            return Ok(());
+        };
+        match action {
+            Action::TreeBlame => {
+                if self.tree_blame.as_ref().is_some_and(|(path, _)| *path == row.path) {
+                    self.tree_blame = None;
+                } else if row.tracked {
+                    self.tree_blame = Some((row.path.clone(), self.repo.blame(&row.path)?));
+                    self.reset_diff_view();
+                }
+            }
+            Action::FileLog => self.show_file_log(row.path)?,
+            Action::TreeDiff => self.show_status_for(&row.path),
+            Action::Copy => self.copy(row.path.clone(), format!("path {}", row.path)),
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Switches to the status view with the changes of `path` selected.
+    fn show_status_for(&mut self, path: &str) {
+        if !self.status_items.iter().any(|item| item.path == path) {
+            info!("{} has no changes", path);
+            return;
+        }
+        self.apply_status_filter(String::new());
+        let index = self.status_display_list.iter().position(
+            |item_type| matches!(item_type, StatusItemType::Item(item) if item.path == path),
+        );
+        self.status_list_state.select(index);
+        self.mode = Mode::Status(StatusMode::FileSelection);
+        self.active_panel = ActivePanel::Files;
+        self.reset_diff_view();
+    }
+
+    /// Re-reads the link manifest and the state of every link target right
+    /// away, for actions that go on with the links.
+    fn refresh_links(&mut self) {
+        let links = worker::read_links(&self.repo);
+        self.take_links(links);
+    }
+
+    /// Takes the links read by `worker::read_links`. A manifest that cannot
+    /// be read is reported in the Links view.
+    fn take_links(&mut self, links: Result<LinksSnapshot, String>) {
+        match links {
+            Ok(snapshot) => {
+                self.active_profile = snapshot.active_profile;
+                self.links = snapshot.links;
+                secrets::mark_secrets(self.config.encryption.as_ref(), &mut self.links);
+                self.profiles = snapshot.profiles;
+            }
+            Err(e) => {
+                error!("Failed to load link manifest: {}", e);
+                self.links.clear();
+                self.profiles.clear();
+                self.active_profile = None;
+                self.links_error = Some(e);
+            }
+        }
+        if self.links.is_empty() {
+            self.links_table_state.select(None);
+        } else {
+            let selected = self
+                .links_table_state
+                .selected()
+                .unwrap_or(0)
+                .min(self.links.len() - 1);
+            self.links_table_state.select(Some(selected));
+        }
+    }
+
+    /// Link failures are reported inside the Links view instead of aborting.
+    fn run_link_operation(&mut self, result: AppResult<()>) -> AppResult<()> {
+        self.refresh()?;
+        if let Err(e) = result {
+            error!("Link operation failed: {}", e);
+            self.links_error = Some(e.to_string());
+        }
+        Ok(())
+    }
+
+    /// Pushes right away when the branch tracks an upstream, otherwise asks
+    /// whether to set one up.
+    fn request_push(&mut self) -> AppResult<()> {
+        if self.blocked_by_read_only("push") {
+            return Ok(());
+        }
+        if self.repo.has_upstream()? {
+            self.push_to_remote(PushMode::Normal, false);
+        } else if let Some(branch) = self.repo.current_branch()? {
+            self.popup = Some(Popup::SetUpstreamConfirm(branch));
+        } else {
+            self.notify(Severity::Error, "Push failed: HEAD is detached".to_string());
+        }
+        Ok(())
+    }
+
+    /// Pushes in the background; with `set_upstream`, the pushed branch on
+    /// `origin` becomes the upstream once the push succeeds.
+    fn push_to_remote(&mut self, mode: PushMode, set_upstream: bool) {
+        if self.blocked_by_read_only("push") {
+            return;
+        }
+        info!("Spawning background task for git push ({:?}).", mode);
+        self.network_op = Some(NetworkOp::Push(mode, set_upstream));
+        let (id, cancel) = self.start_task(TaskKind::Push);
+        let location = self.repo.location();
+        let sender = self.app_event_sender.clone();
+        tokio::task::spawn_blocking(move || {
+            let mut prompter = EventPrompter::new(sender.clone());
+            let progress = sender.clone();
+            let push_result = GitRepo::open(&location).and_then(|repo| {
+                repo.push(&mut prompter, mode, &cancel, |current, total, bytes| {
+                    let (pct, msg) = task::push_progress(current, total, bytes);
+                    let _ = progress.send(AppEvent::TaskProgress { id, pct, msg });
+                })?;
+                if set_upstream {
+                    repo.set_upstream("origin")?;
+                }
+                Ok(())
+            });
+            let _ = sender.send(AppEvent::PushFinished(push_result));
+        });
+    }
+
+    /// Offers the remotes to push the current branch to, with the ones
+    /// pushed to last time chosen.
+    fn open_push_targets(&mut self) -> AppResult<()> {
+        if self.blocked_by_read_only("push") {
+            return Ok(());
+        }
+        let Some(branch) = self.repo.current_branch()? else {
+            self.notify(Severity::Error, "Push failed: HEAD is detached".to_string());
+            return Ok(());
+        };
+        let remotes = self.repo.remote_names()?;
+        if remotes.is_empty() {
+            self.notify(Severity::Error, "There is no remote to push to".to_string());
+            return Ok(());
+        }
+        let last = self.repo.push_remotes()?;
+        self.push_targets = Some(PushTargets {
+            urls: remotes.iter().map(|remote| self.repo.remote_url(remote).unwrap_or_default()).collect(),
+            chosen: remotes.iter().map(|remote| last.contains(remote)).collect(),
+            remotes,
+            list_state: ListState::default().with_selected(Some(0)),
+            editing_refspec: false,
+        });
+        self.push_refspec.set(branch);
+        self.popup = Some(Popup::PushTargets);
+        Ok(())
+    }
+
+    /// Pushes the refspec of `Popup::PushTargets` to the chosen remotes,
+    /// which are remembered for the next time.
+    fn push_to_targets(&mut self) {
+        let Some(targets) = &self.push_targets else {
+            return;
+        };
+        let remotes: Vec<String> = targets
+            .remotes
+            .iter()
+            .zip(&targets.chosen)
+            .filter(|(_, &chosen)| chosen)
+            .map(|(remote, _)| remote.clone())
+            .collect();
+        if remotes.is_empty() {
+            self.notify(Severity::Warning, "Choose a remote to push to".to_string());
+            return;
+        }
+        let refspec = match qualify_push_refspec(self.push_refspec.text()) {
+            Ok(refspec) => refspec,
+            Err(e) => {
+                self.notify(Severity::Warning, format!("Cannot push: {}", e));
+                return;
+            }
+        };
+        if let Err(e) = self.repo.set_push_remotes(&remotes) {
+            warn!("Cannot remember the remotes to push to: {}", e);
+        }
+        self.push_refspec.remember();
+        self.push_targets = None;
+        self.popup = None;
+        self.push_to_remotes(remotes, refspec);
+    }
+
+    /// Pushes `refspec` to each of `remotes` in the background, one after
+    /// the other.
+    fn push_to_remotes(&mut self, remotes: Vec<String>, refspec: String) {
+        if self.blocked_by_read_only("push") {
+            return;
+        }
+        info!("Spawning background task to push {} to {}.", refspec, remotes.join(", "));
+        self.network_op = Some(NetworkOp::PushTo {
+            remotes: remotes.clone(),
+            refspec: refspec.clone(),
+        });
+        let (id, cancel) = self.start_task(TaskKind::Push);
+        let location = self.repo.location();
+        let sender = self.app_event_sender.clone();
+        tokio::task::spawn_blocking(move || {
+            let mut prompter = EventPrompter::new(sender.clone());
+            let results = match GitRepo::open(&location) {
+                Ok(repo) => repo.push_to_remotes(&mut prompter, &remotes, &refspec, &cancel, |remote, current, total, bytes| {
+                    let (pct, msg) = task::push_progress(current, total, bytes);
+                    let msg = format!("{}: {}", remote, msg);
+                    let _ = sender.send(AppEvent::TaskProgress { id, pct, msg });
+                }),
+                Err(e) => remotes
+                    .into_iter()
+                    .map(|remote| (remote, Err(AppError::PushFailed(e.to_string()))))
+                    .collect(),
+            };
+            let _ = sender.send(AppEvent::PushToFinished(results));
+        });
+    }
+
+    pub fn clone_input(&mut self, field: CloneField) -> &mut PromptState {
+        match field {
+            CloneField::Path => &mut self.clone_path,
+            CloneField::Depth => &mut self.clone_depth,
+            CloneField::Branch => &mut self.clone_branch,
+        }
+    }
+
+    /// Clones `clone_url` into `clone_path` in the background. Relative paths
+    /// start at the current work tree. With a depth, only that many commits
+    /// are fetched, and with a branch only that branch.
+    fn start_clone(&mut self) {
+        self.network_op = Some(NetworkOp::Clone);
+        self.clone_url.remember();
+        self.clone_path.remember();
+        self.clone_depth.remember();
+        self.clone_branch.remember();
+        let url = self.clone_url.text().trim().to_string();
+        let path = dotfiles::expand_home(self.clone_path.text().trim());
+        let branch = Some(self.clone_branch.text().trim().to_string()).filter(|branch| !branch.is_empty());
+        let options = CloneOptions {
+            depth: self.clone_depth.text().trim().parse().ok(),
+            single_branch: branch.is_some(),
+            branch,
+        };
+        info!("Spawning background task for git clone of {} into {} ({:?}).", url, path.display(), options);
+        let (id, cancel) = self.start_task(TaskKind::Clone);
+        let sender = self.app_event_sender.clone();
+        tokio::task::spawn_blocking(move || {
+            let mut prompter = EventPrompter::new(sender.clone());
+            let clone_result = GitRepo::clone_from_url(&url, &path, &options, &mut prompter, &cancel, |stats| {
+                let (pct, msg) = task::transfer_progress(&stats);
+                let _ = sender.send(AppEvent::TaskProgress { id, pct, msg });
+            })
+            .map(|repo| repo.location());
+            let _ = sender.send(AppEvent::CloneFinished(clone_result));
+        });
+    }
+
+    fn auto_fetch_if_due(&mut self) {
+        let Some(interval) = self.config.auto_fetch_interval else {
+            return;
+        };
+        if self.last_auto_fetch.elapsed() >= Duration::from_secs(interval) {
+            self.fetch_quietly();
+        }
+    }
+
+    /// Fetches the upstream without the progress popup and without asking
+    /// for credentials, so that it can run unattended. Skipped while
+    /// another fetch or task is running.
+    fn fetch_quietly(&mut self) {
+        self.last_auto_fetch = Instant::now();
+        if self.auto_fetching || self.task.is_some() || !self.repo.has_upstream().unwrap_or(false) {
+            return;
+        }
+        debug!("Fetching the upstream in the background.");
+        self.auto_fetching = true;
+        let location = self.repo.location();
+        let sender = self.app_event_sender.clone();
+        tokio::task::spawn_blocking(move || {
+            let result = GitRepo::open(&location)
+                .and_then(|repo| repo.fetch(&mut NoPrompt, &CancelToken::new(), |_| {}));
+            let _ = sender.send(AppEvent::AutoFetchFinished(result));
+        });
+    }
+
+    fn fetch_from_remote(&mut self) {
+        info!("Spawning background task for git fetch.");
+        self.network_op = Some(NetworkOp::Fetch);
+        let (id, cancel) = self.start_task(TaskKind::Fetch);
+        let location = self.repo.location();
+        let sender = self.app_event_sender.clone();
+        tokio::task::spawn_blocking(move || {
+            let mut prompter = EventPrompter::new(sender.clone());
+            let fetch_result = GitRepo::open(&location).and_then(|repo| {
+                repo.fetch(&mut prompter, &cancel, |stats| {
+                    let (pct, msg) = task::transfer_progress(&stats);
+                    let _ = sender.send(AppEvent::TaskProgress { id, pct, msg });
+                })
+            });
+            let _ = sender.send(AppEvent::FetchFinished(fetch_result));
+        });
+    }
+
+    /// Opens the popup that renames the current branch and sets or clears
+    /// its upstream.
+    fn open_branch_edit(&mut self) -> AppResult<()> {
+        let Some(branch) = self.repo.current_branch()? else {
+            return self.open_detached_head();
+        };
+        let (remote, merge) = self.repo.branch_upstream(&branch)?.unwrap_or_default();
+        let field = |text: String| {
+            let mut prompt = PromptState::new();
+            prompt.set(text);
+            prompt
+        };
+        let mut name = PromptState::with_validator(validate_branch_name);
+        name.set(branch.clone());
+        self.branch_edit = Some(BranchEdit {
+            name,
+            branch,
+            remote: field(remote),
+            merge: field(merge),
+            focus: BranchField::Name,
+        });
+        self.popup = Some(Popup::BranchEdit);
+        Ok(())
+    }
+
+    /// Offers to create a branch at a detached HEAD, so that the commits
+    /// made there are not lost, or to return to a branch, the one HEAD was
+    /// on before preselected after the new branch.
+    fn open_detached_head(&mut self) -> AppResult<()> {
+        let Ok(oid) = self.repo.head_commit_id() else {
+            return Ok(());
+        };
+        let branches = self.repo.local_branches()?;
+        self.detached_head = Some(DetachedHead {
+            oid,
+            commits_on_no_branch: self.repo.commits_on_no_branch()?,
+            list_state: ListState::default().with_selected(Some(0)),
+            branches,
+        });
+        self.popup = Some(Popup::DetachedHead);
+        Ok(())
+    }
+
+    /// Creates `name` at the detached HEAD and checks it out. On errors, such
+    /// as an existing branch of that name, the popup stays open.
+    fn create_branch_at_head(&mut self, name: &str) -> AppResult<()> {
+        info!("Creating branch {} at the detached HEAD", name);
+        match self.repo.create_branch_at_head(name) {
+            Ok(()) => {
+                self.notify(Severity::Success, format!("Created and checked out {}", name));
+                self.popup = None;
+                self.detached_head = None;
+                self.branch_input.remember();
+                self.branch_input.clear();
+            }
+            Err(e) => {
+                error!("Creating branch {} failed: {}", name, e);
+                self.notify(Severity::Error, format!("Could not create {}: {}", name, e));
+            }
+        }
+        self.refresh()
+    }
+
+    /// Writes `Popup::BranchEdit`: renames the branch if its name changed,
+    /// then sets or clears the upstream. On errors the popup stays open to
+    /// correct the fields.
+    fn apply_branch_edit(&mut self) -> AppResult<()> {
+        let Some(edit) = self.branch_edit.clone() else {
+            return Ok(());
+        };
+        let name = edit.name.text().trim();
+        if name.is_empty() {
+            self.notify(Severity::Warning, "The branch needs a name".to_string());
+            return Ok(());
+        }
+        if let Some(error) = edit.name.error() {
+            self.notify(Severity::Warning, error);
+            return Ok(());
+        }
+        let remote = edit.remote.text().trim();
+        // Like `git push -u`, the upstream defaults to the same name.
+        let merge = match edit.merge.text().trim() {
+            "" => name,
+            merge => merge,
+        };
+        let upstream = (!remote.is_empty()).then_some((remote, merge));
+        let result = if name == edit.branch {
+            Ok(())
+        } else {
+            info!("Renaming branch {} to {}", edit.branch, name);
+            self.repo.rename_branch(&edit.branch, name)
+        };
+        let result = result.and_then(|()| self.repo.set_branch_upstream(name, upstream));
+        if let Err(e) = result {
+            error!("Editing branch {} failed: {}", edit.branch, e);
+            self.notify(Severity::Error, format!("Could not edit {}: {}", edit.branch, e));
+            // A rename that went through is kept; later retries start from it.
+            if let Some(current) = self.repo.current_branch()? {
+                if let Some(edit) = &mut self.branch_edit {
+                    edit.branch = current;
+                }
+            }
+            return self.refresh();
+        }
+        let message = match upstream {
+            Some((remote, merge)) => format!("{} tracks {}/{}", name, remote, merge),
+            None => format!("{} has no upstream", name),
+        };
+        self.notify(Severity::Success, message);
+        self.popup = None;
+        self.branch_edit = None;
+        self.refresh()
+    }
+
+    /// Shows the commits only on HEAD and only on its upstream, as of the
+    /// last fetch.
+    fn open_divergence(&mut self) -> AppResult<()> {
+        let Some(divergence) = self.repo.divergence()? else {
+            self.notify(Severity::Info, "There is no fetched upstream branch to compare with.".to_string());
+            return Ok(());
+        };
+        let first = |commits: &[CommitInfo]| ListState::default().with_selected((!commits.is_empty()).then_some(0));
+        let mut view = DivergenceView {
+            upstream_focused: divergence.ahead.is_empty() && !divergence.behind.is_empty(),
+            ahead_state: first(&divergence.ahead),
+            behind_state: first(&divergence.behind),
+            divergence,
+            diff: Vec::new(),
+            diff_scroll: 0,
+        };
+        self.load_divergence_diff(&mut view);
+        self.divergence = Some(view);
+        self.popup = Some(Popup::Divergence);
+        Ok(())
+    }
+
+    fn load_divergence_diff(&mut self, view: &mut DivergenceView) {
+        view.diff_scroll = 0;
+        self.diff_line_limit = self.default_diff_line_limit();
+        view.diff = match view.selected_commit().map(|commit| self.repo.commit_diff(commit.oid)) {
+            Some(Ok(files)) => files,
+            Some(Err(e)) => {
+                error!("Loading the commit diff failed: {}", e);
+                self.notify(Severity::Error, format!("Could not load the diff: {}", e));
+                Vec::new()
+            }
+            None => Vec::new(),
+        };
+    }
+
+    /// `j`/`k` move through the focused list, `h`/`l` or Tab switch lists
+    /// and `Ctrl+d`/`Ctrl+u` scroll the diff.
+    fn handle_divergence_keys(&mut self, key: KeyEvent) -> AppResult<()> {
+        let Some(mut view) = self.divergence.take() else {
+            self.popup = None;
+            return Ok(());
+        };
+        let page = self.half_page() as u16;
+        if key == self.keys.popup.close_popup {
+            self.popup = None;
+            return Ok(());
+        } else if key == self.keys.popup.select_next || key == self.keys.popup.select_prev {
+            let (state, len) = match view.upstream_focused {
+                false => (&mut view.ahead_state, view.divergence.ahead.len()),
+                true => (&mut view.behind_state, view.divergence.behind.len()),
+            };
+            if let Some(i) = state.selected() {
+                let i = match key == self.keys.popup.select_next {
+                    true => (i + 1).min(len.saturating_sub(1)),
+                    false => i.saturating_sub(1),
+                };
+                state.select(Some(i));
+                self.load_divergence_diff(&mut view);
+            }
+        } else if key == self.keys.popup.panel_left || key == self.keys.popup.panel_right || key == self.keys.popup.next_field {
+            view.upstream_focused = !view.upstream_focused;
+            self.load_divergence_diff(&mut view);
+        } else if key == self.keys.popup.page_down {
+            view.diff_scroll = view.diff_scroll.saturating_add(page);
+        } else if key == self.keys.popup.page_up {
+            view.diff_scroll = view.diff_scroll.saturating_sub(page);
+        } else if key == self.keys.popup.load_more {
+            self.load_more_diff();
+        }
+        self.divergence = Some(view);
+        Ok(())
+    }
+
+    /// Runs `operation`, first offering to stash the local changes it could
+    /// overwrite.
+    fn start_risky_operation(&mut self, operation: RiskyOperation) -> AppResult<()> {
+        if self.blocked_by_read_only(&operation.name()) {
+            return Ok(());
+        }
+        if self.repo.has_local_changes()? {
+            self.popup = Some(Popup::AutoStashConfirm(operation));
+            return Ok(());
+        }
+        self.run_risky_operation(operation)
+    }
+
+    /// Stashes the local changes under a generated message and runs
+    /// `operation`. The operation is not started when stashing fails.
+    fn auto_stash_and_run(&mut self, operation: RiskyOperation) -> AppResult<()> {
+        let name = operation.name();
+        let message = format!(
+            "dotatui auto-stash before {} ({})",
+            name,
+            Local::now().format("%Y-%m-%d %H:%M")
+        );
+        match self.repo.stash_save(Some(&message), StashFlags::DEFAULT) {
+            Ok(oid) => {
+                info!("Stashed local changes as {} before the {}", oid, name);
+                self.notify(Severity::Info, format!("Stashed local changes before the {}.", name));
+                self.auto_stash = Some(AutoStash { oid, message, operation: name });
+            }
+            Err(e) => {
+                error!("Auto-stash before the {} failed: {}", name, e);
+                self.notify(
+                    Severity::Error,
+                    format!("Could not stash local changes, the {} was not started: {}", name, e),
+                );
+                return self.refresh();
+            }
+        }
+        self.run_risky_operation(operation)
+    }
+
+    /// Runs `operation`. A pull ends in the background, the others right
+    /// away; either way the auto-stash is offered back once it ended, also
+    /// when it failed.
+    fn run_risky_operation(&mut self, operation: RiskyOperation) -> AppResult<()> {
+        match operation {
+            RiskyOperation::Pull => self.pull_from_remote(),
+            RiskyOperation::CheckoutBranch(branch) => {
+                info!("Checking out {}", branch);
+                match self.repo.checkout_branch(&branch) {
+                    Ok(()) => self.notify(Severity::Success, format!("Switched to branch '{}'.", branch)),
+                    Err(e) => {
+                        error!("Checking out {} failed: {}", branch, e);
+                        self.notify(Severity::Error, format!("Checkout failed: {}", e));
+                    }
+                }
+                self.refresh()?;
+                self.offer_auto_stash();
+            }
+            RiskyOperation::CheckoutCommit(oid) => {
+                match self.repo.checkout_detached(oid) {
+                    Ok(()) => self.notify(
+                        Severity::Warning,
+                        format!(
+                            "HEAD is now detached at {:.7}. Create a branch to keep commits made here.",
+                            oid.to_string()
+                        ),
+                    ),
+                    Err(e) => {
+                        error!("Checking out {} failed: {}", oid, e);
+                        self.notify(Severity::Error, format!("Checkout failed: {}", e));
+                    }
+                }
+                self.refresh()?;
+                self.offer_auto_stash();
+            }
+            RiskyOperation::SwitchProfile(index) => {
+                self.switch_profile(index)?;
+                self.offer_auto_stash();
+            }
+        }
+        Ok(())
+    }
+
+    fn offer_auto_stash(&mut self) {
+        if self.auto_stash.is_some() {
+            self.popup = Some(Popup::AutoStashRestore);
+        }
+    }
+
+    /// Pops the auto-stash. When that fails, e.g. on conflicts, the stash is
+    /// kept and the user is told where to find it.
+    fn restore_auto_stash(&mut self) -> AppResult<()> {
+        let Some(stash) = self.auto_stash.take() else {
+            return Ok(());
+        };
+        let Some(index) = self.repo.stash_index(stash.oid)? else {
+            self.notify(Severity::Warning, format!("The stash '{}' no longer exists.", stash.message));
+            return Ok(());
+        };
+        match self.repo.stash_pop(index) {
+            Ok(()) => {
+                info!("Re-applied the auto-stash {}", stash.oid);
+                self.notify(Severity::Success, "Re-applied the stashed changes.".to_string());
+            }
+            Err(e) => {
+                error!("Re-applying the auto-stash {} failed: {}", stash.oid, e);
+                self.notify(
+                    Severity::Error,
+                    format!(
+                        "Could not re-apply stash@{{{}}}: {}. It is kept; apply it from the stash view.",
+                        index, e
+                    ),
+                );
+            }
+        }
+        self.refresh()
+    }
+
+    fn keep_auto_stash(&mut self) -> AppResult<()> {
+        let Some(stash) = self.auto_stash.take() else {
+            return Ok(());
+        };
+        if let Some(index) = self.repo.stash_index(stash.oid)? {
+            self.notify(
+                Severity::Info,
+                format!("Your changes are kept in stash@{{{}}}: {}", index, stash.message),
+            );
+        }
+        self.refresh()
+    }
+
+    fn pull_from_remote(&mut self) {
+        if self.blocked_by_read_only("pull") {
+            return;
+        }
+        info!("Spawning background task for git pull.");
+        self.network_op = Some(NetworkOp::Pull);
+        let (id, cancel) = self.start_task(TaskKind::Pull);
+        let location = self.repo.location();
+        let sender = self.app_event_sender.clone();
+        tokio::task::spawn_blocking(move || {
+            let mut prompter = EventPrompter::new(sender.clone());
+            let pull_result = GitRepo::open(&location).and_then(|repo| {
+                repo.fetch(&mut prompter, &cancel, |stats| {
+                    let (pct, msg) = task::transfer_progress(&stats);
+                    let _ = sender.send(AppEvent::TaskProgress { id, pct, msg });
+                })?;
+                repo.merge_upstream()
+            });
+            let _ = sender.send(AppEvent::PullFinished(pull_result));
+        });
+    }
+
+    /// Registers a new background task and shows its progress popup.
+    /// Returns what the task needs to report progress and notice
+    /// cancellation.
+    fn start_task(&mut self, kind: TaskKind) -> (TaskId, CancelToken) {
+        self.next_task_id += 1;
+        let task = Task::new(self.next_task_id, kind);
+        let handle = (task.id, task.cancel.clone());
+        self.task = Some(task);
+        self.popup = Some(Popup::Task);
+        handle
+    }
+
+    /// Forgets the finished background task and closes its progress popup;
+    /// returns whether the user cancelled it.
+    fn finish_task(&mut self) -> bool {
+        if let Some(Popup::Task) = self.popup {
+            self.popup = None;
+        }
+        self.task
+            .take()
+            .is_some_and(|task| task.cancel.is_cancelled())
+    }
+
+    /// Half of the main view's height, the distance `Ctrl+d` and `Ctrl+u`
+    /// move.
+    fn half_page(&self) -> usize {
+        (self.layout.main.height.saturating_sub(2) / 2).max(1) as usize
+    }
+
+    /// Scrolls a diff panel to its top or end, or by half a page.
+    fn jump_diff(&mut self, action: &Action, page: usize) {
+        self.diff_scroll = match action {
+            Action::SelectFirst => 0,
+            Action::SelectLast => u16::MAX,
+            Action::PageDown => self.diff_scroll.saturating_add(page as u16),
+            _ => self.diff_scroll.saturating_sub(page as u16),
+        };
+    }
+
+    /// Carries out `gg`, `G`, `Ctrl+d` and `Ctrl+u` in the current view:
+    /// they move the selection of its list, or scroll the diff when the
+    /// diff panel is focused.
+    fn jump(&mut self, action: Action) -> AppResult<()> {
+        let page = self.half_page();
+        let target = |selected: Option<usize>, len: usize| jump_target(&action, selected, len, page);
+        let backward = matches!(action, Action::SelectLast | Action::PageUp);
+        match self.mode {
+            Mode::Status(StatusMode::FileSelection) | Mode::Tree if self.active_panel == ActivePanel::Diff => {
+                self.jump_diff(&action, page);
+            }
+            Mode::Log if self.compare.is_some() && self.active_panel == ActivePanel::Diff => {
+                self.jump_diff(&action, page);
+            }
+            Mode::Log if self.compare.is_some() => {
+                if let Some(compare) = &mut self.compare {
+                    let selected = target(compare.list_state.selected(), compare.files.len());
+                    compare.list_state.select(selected);
+                }
+                self.reset_diff_view();
+            }
+            Mode::Status(StatusMode::FileSelection) => {
+                let selected = target(self.status_list_state.selected(), self.status_display_list.len());
+                self.status_list_state.select(selected);
+                self.reset_diff_view();
+                if backward && selected > Some(0) {
+                    self.skip_headers_backward();
+                } else {
+                    self.skip_headers_forward();
+                }
+            }
+            Mode::Status(StatusMode::HunkSelection) => {
+                let selected = target(self.hunk_list_state.selected(), self.current_hunks.len());
+                self.hunk_list_state.select(selected);
+            }
+            Mode::Status(StatusMode::LineSelection) => {
+                let len = self
+                    .hunk_list_state
+                    .selected()
+                    .and_then(|i| self.current_hunks.get(i))
+                    .map_or(0, |hunk| hunk.lines.len());
+                if let Some(range) = &mut self.line_selection {
+                    range.cursor = target(Some(range.cursor), len).unwrap_or(0);
+                }
+            }
+            Mode::Status(StatusMode::ConflictResolution) => {}
+            Mode::Log => {
+                // The last loaded commit for now; the oldest one once the
+                // git worker loaded the rest.
+                let select_last = matches!(action, Action::SelectLast) && !self.log_exhausted;
+                if select_last {
+                    self.load_whole_log(true);
+                }
+                let selected = target(self.log_table_state.selected(), self.log_row_count());
+                if !select_last && selected.is_some_and(|i| i + LOG_PREFETCH_MARGIN >= self.log_row_count()) {
+                    self.load_more_log()?;
+                }
+                self.log_table_state.select(selected);
+            }
+            Mode::Stash => {
+                let selected = target(self.stash_table_state.selected(), self.stash_entries.len());
+                self.stash_table_state.select(selected);
+            }
+            Mode::Links => {
+                let selected = target(self.links_table_state.selected(), self.links.len());
+                self.links_table_state.select(selected);
+            }
+            Mode::Tree => {
+                let selected = target(self.file_tree.state.selected(), self.file_tree.rows().len());
+                self.file_tree.state.select(selected);
+            }
+            Mode::Reflog => {
+                let selected = target(self.reflog.table_state.selected(), self.reflog.entries.len());
+                self.reflog.table_state.select(selected);
+            }
+            Mode::Ignore => {
+                let selected = target(self.ignore_table_state.selected(), self.ignore_rules.len());
+                self.ignore_table_state.select(selected);
+            }
+            // Scrolled from the bottom, so the directions are reversed.
+            Mode::Debug => {
+                self.debug_scroll = match action {
+                    Action::SelectFirst => u16::MAX,
+                    Action::SelectLast => 0,
+                    Action::PageDown => self.debug_scroll.saturating_sub(page as u16),
+                    _ => self.debug_scroll.saturating_add(page as u16),
+                };
+            }
+            Mode::Messages => {
+                let selected = target(self.messages_list_state.selected(), self.notifications.history_len());
+                self.messages_list_state.select(selected);
+            }
+            Mode::Forge => {
+                let len = match &self.forge.view {
+                    ForgeView::Loaded(status) => status.pull_requests.len(),
+                    _ => 0,
+                };
+                let selected = target(self.forge.table_state.selected(), len);
+                self.forge.table_state.select(selected);
+            }
+            Mode::Stats => {}
+            Mode::Doctor => {
+                let len = match &self.doctor_view {
+                    DoctorView::Loaded(checks) => checks.len(),
+                    DoctorView::Loading => 0,
+                };
+                let selected = target(self.doctor_table_state.selected(), len);
+                self.doctor_table_state.select(selected);
+            }
+            Mode::GitConfig => {
+                let selected = target(self.git_config_table_state.selected(), self.git_config.len());
+                self.git_config_table_state.select(selected);
+            }
+            Mode::Custom(_) => {
+                if let Some(result) = self.with_panel(self.mode, |panel, app| panel.handle_key(app, action)) {
+                    result?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn select_next_status_item(&mut self) {
+        if self.status_display_list.is_empty() { return; }
+        let selected = self.status_list_state.selected().unwrap_or(0);
+        let new_selected = if selected >= self.status_display_list.len() - 1 { 0 } else { selected + 1 };
+        self.status_list_state.select(Some(new_selected));
+        self.reset_diff_view();
+        self.skip_headers_forward();
+    }
+
+    fn select_previous_status_item(&mut self) {
+        if self.status_display_list.is_empty() { return; }
+        let selected = self.status_list_state.selected().unwrap_or(0);
+        let new_selected = if selected == 0 { self.status_display_list.len() - 1 } else { selected - 1 };
+        self.status_list_state.select(Some(new_selected));
+        self.reset_diff_view();
+        self.skip_headers_backward();
+    }
+
+    /// Scrolls the diff back to the top and cuts it at the configured line
+    /// limit again, for when another diff is shown.
+    fn reset_diff_view(&mut self) {
+        self.diff_scroll = 0;
+        self.diff_line_limit = self.default_diff_line_limit();
+    }
+
+    fn default_diff_line_limit(&self) -> usize {
+        self.config.diff_line_limit.unwrap_or(DIFF_LINE_LIMIT)
+    }
+
+    /// Shows another batch of lines of a diff that was cut off.
+    fn load_more_diff(&mut self) {
+        self.diff_line_limit = self.diff_line_limit.saturating_add(self.default_diff_line_limit());
+    }
+
+    /// Scrolls the diff panel down one line. The upper bound is clamped while
+    /// rendering, where the length of the diff is known.
+    fn scroll_diff_down(&mut self) {
+        self.diff_scroll = self.diff_scroll.saturating_add(1);
+    }
+
+    fn scroll_diff_up(&mut self) {
+        self.diff_scroll = self.diff_scroll.saturating_sub(1);
+    }
+
+    /// Scrolls the diff panel so that the next (or previous) hunk header is
+    /// at the top.
+    fn jump_to_hunk(&mut self, forward: bool) -> AppResult<()> {
+        let Some(hunks) = self
+            .get_selected_status_item()
+            .and_then(|item| self.loaded_hunks(&item))
+        else {
+            return Ok(());
+        };
+        if let Some(row) = next_hunk_header(&hunks, self.diff_scroll, forward) {
+            self.diff_scroll = row;
+        }
+        Ok(())
+    }
+
+    fn skip_headers_forward(&mut self) {
+        if let Some(selected) = self.status_list_state.selected() {
+            if let Some(item_type) = self.status_display_list.get(selected) {
+                if matches!(item_type, StatusItemType::Header(_)) {
+                    if selected >= self.status_display_list.len() - 1 {
+                        self.status_list_state.select(Some(0));
+                        self.skip_headers_forward();
+                    } else {
+                        self.select_next_status_item();
+                    }
+                }
+            }
+        }
+    }
+
+    fn skip_headers_backward(&mut self) {
+        if let Some(selected) = self.status_list_state.selected() {
+            if let Some(item_type) = self.status_display_list.get(selected) {
+                if matches!(item_type, StatusItemType::Header(_)) {
+                    if selected == 0 {
+                        self.status_list_state
+                            .select(Some(self.status_display_list.len() - 1));
+                        self.skip_headers_backward();
+                    } else {
+                        self.select_previous_status_item();
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn get_selected_status_item(&self) -> Option<StatusItem> {
+        self.status_list_state
+            .selected()
+            .and_then(|i| self.status_display_list.get(i))
+            .and_then(|item_type| match item_type {
+                StatusItemType::Item(item) => Some(item.clone()),
+                StatusItemType::Header(_) | StatusItemType::Dir { .. } => None,
+            })
+    }
+
+    fn selected_status_is_dir(&self) -> bool {
+        self.status_list_state
+            .selected()
+            .and_then(|i| self.status_display_list.get(i))
+            .is_some_and(|item_type| matches!(item_type, StatusItemType::Dir { .. }))
+    }
+
+    fn select_next_hunk(&mut self) {
+        if self.current_hunks.is_empty() { return; }
+        let i = self.hunk_list_state.selected().map_or(0, |i| (i + 1) % self.current_hunks.len());
+        self.hunk_list_state.select(Some(i));
+        debug!("Selected hunk index: {}", i);
+    }
+
+    fn select_previous_hunk(&mut self) {
+        if self.current_hunks.is_empty() { return; }
+        let i = self.hunk_list_state.selected().map_or(0, |i| {
+            if i == 0 { self.current_hunks.len() - 1 } else { i - 1 }
+        });
+        self.hunk_list_state.select(Some(i));
+        debug!("Selected hunk index: {}", i);
+    }
+
+    fn selected_stash_index(&self) -> Option<usize> {
+        self.stash_table_state
+            .selected()
+            .and_then(|i| self.stash_entries.get(i))
+            .map(|stash| stash.index)
+    }
+
+    fn select_next_stash(&mut self) {
+        if self.stash_entries.is_empty() { return; }
+        let i = self.stash_table_state.selected().map_or(0, |i| (i + 1) % self.stash_entries.len());
+        self.stash_table_state.select(Some(i));
+    }
+
+    fn select_previous_stash(&mut self) {
+        if self.stash_entries.is_empty() { return; }
+        let i = self.stash_table_state.selected().map_or(0, |i| {
+            if i == 0 { self.stash_entries.len() - 1 } else { i - 1 }
+        });
+        self.stash_table_state.select(Some(i));
+    }
+
+    fn select_next_ignore_rule(&mut self) {
+        if self.ignore_rules.is_empty() { return; }
+        let i = self.ignore_table_state.selected().map_or(0, |i| (i + 1) % self.ignore_rules.len());
+        self.ignore_table_state.select(Some(i));
+    }
+
+    fn select_previous_ignore_rule(&mut self) {
+        if self.ignore_rules.is_empty() { return; }
+        let i = self.ignore_table_state.selected().map_or(0, |i| {
+            if i == 0 { self.ignore_rules.len() - 1 } else { i - 1 }
+        });
+        self.ignore_table_state.select(Some(i));
+    }
+
+    fn select_next_link(&mut self) {
+        if self.links.is_empty() { return; }
+        let i = self.links_table_state.selected().map_or(0, |i| (i + 1) % self.links.len());
+        self.links_table_state.select(Some(i));
+    }
+
+    fn select_previous_link(&mut self) {
+        if self.links.is_empty() { return; }
+        let i = self.links_table_state.selected().map_or(0, |i| {
+            if i == 0 { self.links.len() - 1 } else { i - 1 }
+        });
+        self.links_table_state.select(Some(i));
+    }
+
+    /// Appends the next page of history to `log_entries`.
+    fn load_more_log(&mut self) -> AppResult<()> {
+        if self.log_exhausted {
+            return Ok(());
+        }
+        debug!("Loading commits after the first {}", self.log_entries.len());
+        let page = self.repo.get_log(self.log_entries.len(), LOG_PAGE_SIZE)?;
+        self.log_exhausted = page.len() < LOG_PAGE_SIZE;
+        self.log_entries.extend(page);
+        self.update_log_graph();
+        Ok(())
+    }
+
+    fn select_next_log_item(&mut self) -> AppResult<()> {
+        if self.log_row_count() == 0 { return Ok(()); }
+        let selected = self.log_table_state.selected().unwrap_or(0);
+        if selected + LOG_PREFETCH_MARGIN >= self.log_row_count() {
+            self.load_more_log()?;
+        }
+        let i = self.log_table_state.selected().map_or(0, |i| (i + 1) % self.log_row_count());
+        self.log_table_state.select(Some(i));
+        Ok(())
+    }
+
+    fn select_previous_log_item(&mut self) {
+        if self.log_row_count() == 0 { return; }
+        let i = self.log_table_state.selected().map_or(0, |i| {
+            if i == 0 { self.log_row_count() - 1 } else { i - 1 }
+        });
+        self.log_table_state.select(Some(i));
+    }
+}
+
+/// Where `action` (a jump such as `G`) lands in a list of `len` items when
+/// `selected` is selected. Unlike `j` and `k`, jumps stop at the ends
+/// instead of wrapping around.
+fn jump_target(action: &Action, selected: Option<usize>, len: usize, page: usize) -> Option<usize> {
+    let last = len.checked_sub(1)?;
+    let selected = selected.unwrap_or(0);
+    Some(match action {
+        Action::SelectFirst => 0,
+        Action::SelectLast => last,
+        Action::PageDown => (selected + page).min(last),
+        Action::PageUp => selected.saturating_sub(page),
+        _ => selected.min(last),
+    })
+}
+
+/// The directory part of `path` including the trailing `/`, or `""` for
+/// files in the top level.
+/// Adds the files below `dir`, a directory of the work tree given with a
+/// trailing slash as git reports untracked ones, to `files`.
+/// `App::encrypt_secrets` for the work tree at `root`, which background
+/// tasks call without the app.
+fn encrypt_work_tree_secrets(config: &EncryptionConfig, root: &Path, paths: &[String]) -> AppResult<()> {
+    let mut files = Vec::new();
+    for path in paths {
+        if path.ends_with('/') {
+            collect_files(root, path, &mut files)?;
+        } else {
+            files.push(path.clone());
+        }
+    }
+    for path in files.iter().filter(|path| secrets::is_secret(config, path)) {
+        let full = root.join(path);
+        let Ok(contents) = std::fs::read(&full) else {
+            // Deleted; there is nothing to encrypt.
+            continue;
+        };
+        if secrets::is_encrypted(&contents) {
+            continue;
+        }
+        info!("Encrypting {} before staging it", path);
+        let ciphertext = secrets::encrypt(config, &contents)?;
+        std::fs::write(&full, ciphertext)?;
+    }
+    Ok(())
+}
+
+fn collect_files(root: &std::path::Path, dir: &str, files: &mut Vec<String>) -> std::io::Result<()> {
+    for entry in std::fs::read_dir(root.join(dir))? {
+        let entry = entry?;
+        let path = format!("{}{}", dir, entry.file_name().to_string_lossy());
+        if entry.file_type()?.is_dir() {
+            collect_files(root, &format!("{}/", path), files)?;
+        } else {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+fn parent_dir(path: &str) -> &str {
+    // A collapsed untracked directory belongs to the one it is in.
+    let path = path.strip_suffix('/').unwrap_or(path);
+    path.rfind('/').map_or("", |i| &path[..=i])
+}
+
+/// Whether every character of `query` appears in `text` in the same order,
+/// ignoring case.
+fn fuzzy_match(query: &str, text: &str) -> bool {
+    let mut text = text.chars().flat_map(char::to_lowercase);
+    query
+        .chars()
+        .flat_map(char::to_lowercase)
+        .all(|q| text.any(|t| t == q))
+}
+
+fn is_inside(cx: u16, cy: u16, rect: Rect) -> bool {
+    cx >= rect.x && cx < rect.x + rect.width && cy >= rect.y && cy < rect.y + rect.height
+}
+
+/// The row of the first hunk header after (or the last one before) row
+/// `scroll` of a diff. Diffs are drawn as each hunk's header followed by its
+/// lines, so the header rows follow from the hunk sizes.
+fn next_hunk_header(hunks: &[Hunk], scroll: u16, forward: bool) -> Option<u16> {
+    let headers: Vec<u16> = hunks
+        .iter()
+        .scan(0u16, |row, hunk| {
+            let header = *row;
+            *row = row.saturating_add(1 + hunk.lines.len() as u16);
+            Some(header)
+        })
+        .collect();
+    if forward {
+        headers.into_iter().find(|&row| row > scroll)
+    } else {
+        headers.into_iter().rev().find(|&row| row < scroll)
+    }
+}
+
+/// Whether a click on item `index` follows `last_click` on the same item
+/// quickly enough to count as a double click.
+fn is_double_click(last_click: Option<(Instant, usize)>, index: usize) -> bool {
+    last_click.is_some_and(|(time, last)| last == index && time.elapsed() < DOUBLE_CLICK_TIME)
+}
+
+/// The item at screen row `row` of a bordered list drawn in `rect` and
+/// scrolled by `offset`; `None` on the borders.
+fn list_index_at(rect: Rect, row: u16, offset: usize) -> Option<usize> {
+    if row <= rect.y || row + 1 >= rect.y + rect.height {
+        return None;
+    }
+    Some((row - rect.y - 1) as usize + offset)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{EncryptionConfig, ToolConfig};
+    use crate::testing::{ctrl, key, RepoFixture, TestApp, FIXTURE_BRANCH};
+
+    fn staged_paths(app: &TestApp) -> Vec<String> {
+        let mut paths: Vec<String> = app
+            .app
+            .status_items
+            .iter()
+            .filter(|item| item.is_staged)
+            .map(|item| item.path.clone())
+            .collect();
+        paths.sort();
+        paths
+    }
+
+    fn mouse(kind: MouseEventKind, column: u16, row: u16) -> Action {
+        Action::Mouse(MouseEvent {
+            kind,
+            column,
+            row,
+            modifiers: KeyModifiers::NONE,
+        })
+    }
+
+    #[test]
+    fn mouse_events_hit_the_panels_drawn_on_screen() {
+        let fixture = RepoFixture::new()
+            .committed("a.txt", "one\n")
+            .unstaged("a.txt", "two\n")
+            .unstaged("b.txt", "new\n");
+        let mut app = TestApp::with_size(fixture, 60, 20);
+        app.render();
+        let files = app.app.layout.files;
+        let diff = app.app.layout.diff;
+        // Row 0 is the border, row 1 the "Unstaged changes:" header.
+        let row_of_a = files.y + 2;
+
+        app.dispatch(mouse(MouseEventKind::Down(MouseButton::Left), diff.x + 2, diff.y + 2));
+        assert_eq!(app.app.active_panel, ActivePanel::Diff);
+        app.dispatch(mouse(MouseEventKind::Down(MouseButton::Left), files.x + 2, row_of_a));
+        assert_eq!(app.app.active_panel, ActivePanel::Files);
+        assert_eq!(staged_paths(&app), Vec::<String>::new());
+        app.dispatch(mouse(MouseEventKind::Down(MouseButton::Left), files.x + 2, row_of_a));
+        assert_eq!(staged_paths(&app), ["a.txt"]);
+
+        // The wheel scrolls the diff under the mouse, not the focused list.
+        app.dispatch(mouse(MouseEventKind::ScrollDown, diff.x + 2, diff.y + 2));
+        assert_eq!(app.app.diff_scroll, 1);
+        assert_eq!(app.app.active_panel, ActivePanel::Files);
+    }
+
+    #[test]
+    fn log_clicks_sort_columns_and_open_commit_details() {
+        let fixture = RepoFixture::new()
+            .committed("a", "1\n")
+            .committed("b", "2\n")
+            .committed("c", "3\n");
+        let mut app = TestApp::with_size(fixture, 100, 20);
+        app.dispatch(Action::SwitchMode(Mode::Log));
+        app.render();
+        let selected = |app: &TestApp| app.app.selected_log_entry().map(|commit| commit.message.clone());
+        let rows = app.app.layout.log_rows;
+        let [hash_header, ..] = app.app.layout.log_headers;
+
+        app.dispatch(mouse(MouseEventKind::Down(MouseButton::Left), rows.x + 20, rows.y + 1));
+        assert_eq!(selected(&app).as_deref(), Some("Add b"));
+
+        app.dispatch(mouse(MouseEventKind::Down(MouseButton::Left), hash_header.x, hash_header.y));
+        let mut by_hash = app.app.log_entries.clone();
+        by_hash.sort_by_key(|commit| commit.oid);
+        let shown: Vec<_> = (0..3).map(|row| app.app.log_entries[app.app.log_entry_index(row).unwrap()].oid).collect();
+        assert_eq!(shown, by_hash.iter().map(|commit| commit.oid).collect::<Vec<_>>());
+        // The selected commit stays selected in its new row.
+        assert_eq!(selected(&app).as_deref(), Some("Add b"));
+
+        app.render();
+        let row = app.app.log_table_state.selected().unwrap() as u16;
+        app.dispatch(mouse(MouseEventKind::Down(MouseButton::Left), rows.x + 20, rows.y + row));
+        app.dispatch(mouse(MouseEventKind::Down(MouseButton::Left), rows.x + 20, rows.y + row));
+        assert_eq!(app.app.popup, Some(Popup::CommitDetail));
+        let detail = app.app.commit_detail.as_ref().unwrap();
+        assert_eq!(detail.message, "Add b");
+        assert_eq!(detail.files, [('A', "b".to_string())]);
+    }
+
+    #[test]
+    fn comparing_commits_shows_the_files_changed_between_them() {
+        let fixture = RepoFixture::new()
+            .committed("a", "one\n")
+            .committed("a", "two\n")
+            .committed("b", "new\n")
+            .unstaged("a", "three\n");
+        let mut app = TestApp::with_size(fixture, 120, 40);
+        app.dispatch(Action::SwitchMode(Mode::Log));
+        let files = |app: &TestApp| -> Vec<(char, String)> {
+            let compare = app.app.compare.as_ref().expect("comparison open");
+            compare.files.iter().map(|file| (file.status, file.path.clone())).collect()
+        };
+
+        // Mark the oldest commit and compare it with the newest.
+        app.press(key(KeyCode::Char('G')));
+        app.press(key(KeyCode::Char('m')));
+        app.type_text("ggd");
+        assert_eq!(files(&app), [('M', "a".to_string()), ('A', "b".to_string())]);
+        let hunk = &app.app.compare.as_ref().unwrap().files[0].hunks[0];
+        let changed: Vec<_> = hunk.lines.iter().map(|line| (line.origin, line.content.as_str())).collect();
+        assert_eq!(changed, [('-', "one\n"), ('+', "two\n")]);
+        app.press(key(KeyCode::Char('j')));
+        assert_eq!(app.app.compare.as_ref().unwrap().selected_file().unwrap().path, "b");
+        app.press(key(KeyCode::Esc));
+        assert!(app.app.compare.is_none());
+
+        // The marked commit itself is compared with the work tree.
+        app.type_text("Gd");
+        assert_eq!(files(&app), [('M', "a".to_string()), ('A', "b".to_string())]);
+        let hunk = &app.app.compare.as_ref().unwrap().files[0].hunks[0];
+        assert_eq!(hunk.lines.last().map(|line| line.content.as_str()), Some("three\n"));
+    }
+
+    #[test]
+    fn checkouts_stash_local_changes_and_offer_them_back() {
+        let fixture = RepoFixture::new()
+            .committed("rc", "1\n")
+            .committed("other", "x\n")
+            .unstaged("rc", "local\n");
+        let mut app = TestApp::new(fixture);
+        let rc = app.fixture().path().join("rc");
+        // Untracked files are neither a reason to stash nor stashed.
+        let notes = app.fixture().path().join("notes");
+        std::fs::write(&notes, "keep\n").unwrap();
+
+        // HEAD@{1} is the first commit, before "other" existed.
+        app.press(KeyEvent::new(KeyCode::Char('R'), KeyModifiers::SHIFT));
+        app.type_text("jc");
+        assert!(matches!(
+            app.app.popup,
+            Some(Popup::AutoStashConfirm(RiskyOperation::CheckoutCommit(_)))
+        ));
+        app.type_text("y");
+        assert_eq!(app.app.popup, Some(Popup::AutoStashRestore));
+        assert_eq!(std::fs::read_to_string(&rc).unwrap(), "1\n");
+        assert!(!app.fixture().path().join("other").exists());
+        assert_eq!(std::fs::read_to_string(&notes).unwrap(), "keep\n");
+
+        app.type_text("y");
+        assert_eq!(app.app.popup, None);
+        assert_eq!(std::fs::read_to_string(&rc).unwrap(), "local\n");
+        assert!(app.app.repo.get_stashes().unwrap().is_empty());
+        assert_eq!(std::fs::read_to_string(&notes).unwrap(), "keep\n");
+    }
+
+    #[test]
+    fn the_log_is_searched_in_the_background_and_again_after_new_commits() {
+        let fixture = RepoFixture::new()
+            .committed(".zshrc", "1\n")
+            .committed(".vimrc", "1\n")
+            .committed(".zshrc", "2\n");
+        let mut app = TestApp::with_size(fixture, 120, 24);
+        app.dispatch(Action::SwitchMode(Mode::Log));
+        app.type_text("/zsh");
+        app.press(key(KeyCode::Enter));
+        assert_eq!(app.app.log_search.as_ref().unwrap().matches, [0, 2]);
+        assert!(app.render().contains("2 commits matching 'zsh'"));
+        assert_eq!(app.app.log_row_count(), 2);
+        app.type_text("n");
+        assert_eq!(app.app.log_table_state.selected(), Some(1));
+        assert_eq!(app.app.log_entry_index(1), Some(2));
+
+        std::fs::write(app.fixture().path().join(".zshenv"), "1\n").unwrap();
+        app.app.repo.stage_all(Some(&UntrackedFiles::All)).unwrap();
+        app.app.repo.commit("Add .zshenv", false).unwrap();
+        app.app.refresh().unwrap();
+        app.dispatch(Action::Tick);
+        assert_eq!(app.app.log_search.as_ref().unwrap().matches, [0, 1, 3]);
+        assert_eq!(app.app.log_row_count(), 3);
+    }
+
+    #[test]
+    fn a_search_without_matches_leaves_no_commit_selected() {
+        let fixture = RepoFixture::new().committed(".zshrc", "1\n").committed(".vimrc", "1\n");
+        let mut app = TestApp::with_size(fixture, 120, 24);
+        app.dispatch(Action::SwitchMode(Mode::Log));
+        app.type_text("/tmux");
+        app.press(key(KeyCode::Enter));
+        app.dispatch(Action::Tick);
+        assert_eq!(app.app.log_row_count(), 0);
+        assert_eq!(app.app.log_table_state.selected(), None);
+        assert!(app.app.selected_log_entry().is_none());
+        app.type_text("jkG");
+        app.press(key(KeyCode::Enter));
+        app.render();
+        assert!(app.app.selected_log_entry().is_none());
+
+        app.press(key(KeyCode::Esc));
+        assert_eq!(app.app.log_row_count(), 2);
+        assert_eq!(app.app.selected_log_entry().map(|commit| commit.message.as_str()), Some("Add .vimrc"));
+    }
+
+    #[test]
+    fn jumping_to_the_oldest_commit_loads_the_history_in_the_background() {
+        let mut fixture = RepoFixture::new();
+        for i in 0..LOG_PAGE_SIZE + 5 {
+            fixture = fixture.committed("zshrc", &format!("{}\n", i));
+        }
+        let mut app = TestApp::new(fixture);
+        app.dispatch(Action::SwitchMode(Mode::Log));
+        assert_eq!(app.app.log_entries.len(), LOG_PAGE_SIZE);
+
+        app.app.dispatch(Action::SelectLast).unwrap();
+        assert_eq!(app.app.log_table_state.selected(), Some(LOG_PAGE_SIZE - 1));
+        assert!(app.app.is_loading_whole_log());
+        assert!(app.render().contains("loading the rest of the history"));
+        while app.app.is_loading_whole_log() {
+            std::thread::sleep(Duration::from_millis(10));
+            app.process_events();
+        }
+        assert_eq!(app.app.log_entries.len(), LOG_PAGE_SIZE + 5);
+        assert_eq!(app.app.log_table_state.selected(), Some(LOG_PAGE_SIZE + 4));
+        assert!(app.app.selected_log_entry().unwrap().parents.is_empty());
+    }
+
+    #[test]
+    fn the_tree_preview_is_read_once_per_file_and_refresh() {
+        let fixture = RepoFixture::new().committed("zshrc", "export A=1\n");
+        let mut app = TestApp::with_size(fixture, 100, 24);
+        app.dispatch(Action::SwitchMode(Mode::Tree));
+        app.app.file_tree.state.select(Some(0));
+        assert!(app.render().contains("export A=1"));
+
+        std::fs::write(app.fixture().path().join("zshrc"), "export A=2\n").unwrap();
+        assert!(app.render().contains("export A=1"));
+        app.app.refresh().unwrap();
+        app.dispatch(Action::Tick);
+        assert!(app.render().contains("export A=2"));
+    }
+
+    #[test]
+    fn log_checks_out_the_branches_at_a_commit() {
+        let fixture = RepoFixture::new().committed("a", "1\n");
+        {
+            let repo = git2::Repository::open(fixture.path()).unwrap();
+            let first = repo.head().unwrap().peel_to_commit().unwrap();
+            repo.branch("old", &first, false).unwrap();
+            repo.tag_lightweight("v1", first.as_object(), false).unwrap();
+        }
+        let fixture = fixture.committed("b", "2\n");
+        let mut app = TestApp::new(fixture);
+        app.dispatch(Action::SwitchMode(Mode::Log));
+
+        app.type_text("jo");
+        assert_eq!(app.app.popup, Some(Popup::LogCheckout));
+        let checkout = app.app.log_checkout.as_ref().unwrap();
+        assert_eq!(checkout.refs, [CommitRef::Branch("old".into()), CommitRef::Tag("v1".into())]);
+        app.press(key(KeyCode::Enter));
+        assert_eq!(app.app.repo.current_branch().unwrap().as_deref(), Some("old"));
+        assert!(!app.fixture().path().join("b").exists());
+
+        // The entry after the refs detaches HEAD at the commit itself.
+        app.type_text("ojj");
+        app.press(key(KeyCode::Enter));
+        assert_eq!(app.app.repo.current_branch().unwrap(), None);
+    }
+
+    #[test]
+    fn external_tools_get_copies_of_the_versions_they_compare() {
+        let fixture = RepoFixture::new()
+            .committed("rc.sh", "base\n")
+            .committed("env", "1\n")
+            .conflicted("rc.sh", "ours\n", "theirs\n")
+            .unstaged("env", "2\n");
+        let mut app = TestApp::new(fixture);
+        let shift_e = KeyEvent::new(KeyCode::Char('E'), KeyModifiers::SHIFT);
+        let read = |path: &String| std::fs::read_to_string(path).unwrap();
+
+        app.press(shift_e);
+        assert_eq!(app.app.take_tool_request(), None);
+        assert_eq!(app.app.notifications.history().next().unwrap().severity, Severity::Warning);
+
+        app.app.config.merge = Some(ToolConfig { tool: "merge $BASE $MERGED".to_string() });
+        app.press(shift_e);
+        let request = app.app.take_tool_request().unwrap();
+        let [program, base, merged] = &request.command[..] else {
+            panic!("{:?}", request.command);
+        };
+        assert_eq!(program, "merge");
+        assert!(base.ends_with("rc_BASE.sh"));
+        assert_eq!(read(base), "base\n");
+        assert_eq!(Path::new(merged), app.fixture().path().join("rc.sh"));
+
+        app.app.config.diff = Some(ToolConfig { tool: "nvim -d".to_string() });
+        app.type_text("j");
+        app.press(shift_e);
+        let request = app.app.take_tool_request().unwrap();
+        assert_eq!(read(&request.command[2]), "1\n");
+        assert_eq!(read(&request.command[3]), "2\n");
+        let temp_dir = request.temp_dir.clone();
+        app.app.finish_tool(request, Ok(())).unwrap();
+        assert!(!temp_dir.exists());
+    }
+
+    #[test]
+    fn y_copies_the_selection_and_confirms_it() {
+        let fixture = RepoFixture::new()
+            .committed("rc", "a\n")
+            .unstaged("rc", "b\n");
+        let mut app = TestApp::new(fixture);
+        let copied = |app: &mut TestApp| {
+            let request = app.app.take_clipboard_request().unwrap();
+            let text = request.text.clone();
+            app.app.finish_copy(request, Ok(()));
+            text
+        };
+
+        app.press(key(KeyCode::Char('y')));
+        assert_eq!(copied(&mut app), "rc");
+        assert_eq!(app.app.notifications.history().next().unwrap().message, "Copied path rc");
+
+        app.press(key(KeyCode::Enter));
+        app.press(key(KeyCode::Char('y')));
+        assert_eq!(copied(&mut app), "@@ -1 +1 @@\n-a\n+b\n");
+
+        app.press(key(KeyCode::Char('q')));
+        app.dispatch(Action::SwitchMode(Mode::Log));
+        app.press(key(KeyCode::Char('y')));
+        let head = app.app.repo.head_commit_id().unwrap();
+        assert_eq!(copied(&mut app), head.to_string());
+        app.press(KeyEvent::new(KeyCode::Char('Y'), KeyModifiers::SHIFT));
+        assert_eq!(copied(&mut app), "Add rc");
+    }
+
+    #[test]
+    fn patches_are_exported_and_applied_back() {
+        let fixture = RepoFixture::new()
+            .committed("rc", "a\n")
+            .unstaged("rc", "b\n")
+            .staged("env", "x\n");
+        let mut app = TestApp::new(fixture);
+        let dir = tempfile::TempDir::new().unwrap();
+        let patch_file = dir.path().join("rc.patch");
+        let rc = app.fixture().path().join("rc");
+
+        // "env" is staged, so the unstaged "rc" comes second.
+        app.type_text("jx");
+        assert_eq!(app.app.popup, Some(Popup::PatchExport));
+        assert_eq!(app.app.patch_input.text(), "~/rc.patch");
+        app.press(key(KeyCode::Tab));
+        assert_eq!(app.app.patch_export.as_ref().unwrap().scope(), &PatchScope::Staged);
+        assert_eq!(app.app.patch_input.text(), "~/staged.patch");
+        app.press(key(KeyCode::Tab));
+        app.app.patch_input.set(patch_file.display().to_string());
+        app.press(key(KeyCode::Enter));
+        assert_eq!(app.app.popup, None);
+        let patch = std::fs::read_to_string(&patch_file).unwrap();
+        assert!(patch.starts_with("diff --git a/rc b/rc\n"), "{}", patch);
+        assert!(patch.ends_with("-a\n+b\n"), "{}", patch);
+
+        std::fs::write(&rc, "a\n").unwrap();
+        app.press(KeyEvent::new(KeyCode::Char('X'), KeyModifiers::SHIFT));
+        app.type_text(&patch_file.display().to_string());
+        app.press(key(KeyCode::Enter));
+        assert_eq!(std::fs::read_to_string(&rc).unwrap(), "b\n");
+    }
+
+    #[test]
+    fn ctrl_o_leaves_the_mouse_to_the_terminal() {
+        let mut app = TestApp::new(RepoFixture::new().committed("rc", "1\n"));
+        assert!(app.app.mouse_capture);
+        app.press(ctrl('o'));
+        assert!(!app.app.mouse_capture);
+        assert!(app.render().contains("mouse: select text"));
+        app.press(ctrl('o'));
+        assert!(app.app.mouse_capture);
+    }
+
+    #[test]
+    fn ticks_fetch_the_upstream_once_the_interval_elapsed() {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let _guard = runtime.enter();
+        let upstream = RepoFixture::new().committed("a", "1\n");
+        let local = RepoFixture::new().tracking(&upstream);
+        let _upstream = upstream.committed("b", "2\n");
+        let mut app = TestApp::new(local);
+        assert_eq!(app.app.branch_status.ahead_behind, Some((0, 0)));
+
+        app.app.config.auto_fetch_interval = Some(3600);
+        app.dispatch(Action::Tick);
+        assert!(!app.app.auto_fetching);
+
+        app.app.config.auto_fetch_interval = Some(0);
+        app.dispatch(Action::Tick);
+        while app.app.auto_fetching {
+            std::thread::sleep(Duration::from_millis(10));
+            app.process_events();
+        }
+        assert_eq!(app.app.branch_status.ahead_behind, Some((0, 1)));
+    }
+
+    #[test]
+    fn single_lines_of_a_hunk_can_be_staged() {
+        let fixture = RepoFixture::new()
+            .committed("rc", "a\nb\nc\n")
+            .unstaged("rc", "a\nB\nc\nd\n");
+        let mut app = TestApp::new(fixture);
+        let index_contents = |app: &TestApp| {
+            let repo = git2::Repository::open(app.fixture().path()).unwrap();
+            let entry = repo.index().unwrap().get_path(Path::new("rc"), 0).unwrap();
+            let contents = String::from_utf8(repo.find_blob(entry.id).unwrap().content().to_vec());
+            contents.unwrap()
+        };
+
+        // The hunk is " a", "-b", "+B", " c", "+d"; V starts on "-b".
+        app.press(key(KeyCode::Enter));
+        app.press(key(KeyCode::Char('V')));
+        assert_eq!(app.app.mode, Mode::Status(StatusMode::LineSelection));
+        app.type_text("jjj ");
+        assert_eq!(index_contents(&app), "a\nb\nc\nd\n");
+        assert_eq!(app.app.mode, Mode::Status(StatusMode::HunkSelection));
+
+        // A range: both lines of the replacement.
+        app.type_text("VVj ");
+        assert_eq!(index_contents(&app), "a\nB\nc\nd\n");
+    }
+
+    #[test]
+    fn counts_and_jumps_move_through_long_lists() {
+        let mut fixture = RepoFixture::new().committed("README", "dotfiles\n");
+        for i in 0..20 {
+            fixture = fixture.unstaged(&format!("f{:02}", i), "x\n");
+        }
+        let mut app = TestApp::new(fixture);
+        app.render();
+        let selected = |app: &TestApp| app.app.get_selected_status_item().map(|item| item.path);
+
+        app.type_text("3j");
+        assert_eq!(selected(&app).as_deref(), Some("f03"));
+        app.press(key(KeyCode::Char('G')));
+        assert_eq!(selected(&app).as_deref(), Some("f19"));
+        // Half of the 22 rows of the view, minus the borders.
+        app.press(ctrl('u'));
+        assert_eq!(selected(&app).as_deref(), Some("f09"));
+        app.type_text("gg");
+        assert_eq!(selected(&app).as_deref(), Some("f00"));
+        app.press(ctrl('d'));
+        assert_eq!(selected(&app).as_deref(), Some("f10"));
+    }
+
+    #[test]
+    fn space_stages_the_selected_file_and_ctrl_z_undoes_it() {
+        let fixture = RepoFixture::new()
+            .committed("a.txt", "one\n")
+            .unstaged("a.txt", "two\n");
+        let mut app = TestApp::new(fixture);
+        app.press(key(KeyCode::Char(' ')));
+        assert_eq!(staged_paths(&app), ["a.txt"]);
+        app.press(ctrl('z'));
+        assert!(staged_paths(&app).is_empty());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn commit_hooks_run_before_committing_unless_skipped() {
+        use std::os::unix::fs::PermissionsExt;
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let _guard = runtime.enter();
+        let fixture = RepoFixture::new()
+            .committed("a.txt", "one\n")
+            .staged("b.txt", "two\n");
+        let hooks = fixture.path().join(".git/hooks");
+        let write_hook = |name: &str, script: &str| {
+            std::fs::create_dir_all(&hooks).unwrap();
+            std::fs::write(hooks.join(name), script).unwrap();
+            std::fs::set_permissions(hooks.join(name), std::fs::Permissions::from_mode(0o755)).unwrap();
+        };
+        write_hook("pre-commit", "#!/bin/sh\necho linting\n");
+        write_hook("commit-msg", "#!/bin/sh\necho 'Checked-by: hook' >> \"$1\"\n");
+        let mut app = TestApp::new(fixture);
+        let wait_for_hooks = |app: &mut TestApp| {
+            while app.app.hook_run.as_ref().is_some_and(|run| run.error.is_none()) {
+                std::thread::sleep(Duration::from_millis(10));
+                app.process_events();
+            }
+        };
+        let head_message = |app: &TestApp| {
+            let repo = git2::Repository::open(app.fixture().path()).unwrap();
+            let message = repo.head().unwrap().peel_to_commit().unwrap().message().unwrap().to_string();
+            message
+        };
+
+        app.press(key(KeyCode::Char('c')));
+        app.type_text("Add b.txt");
+        app.press(ctrl('s'));
+        assert_eq!(app.app.popup, Some(Popup::Hooks));
+        wait_for_hooks(&mut app);
+        assert_eq!(head_message(&app), "Add b.txt\nChecked-by: hook\n");
+
+        // A failing hook aborts the commit and keeps the message.
+        write_hook("pre-commit", "#!/bin/sh\necho 'trailing whitespace' >&2\nexit 1\n");
+        std::fs::write(app.fixture().path().join("c.txt"), "three\n").unwrap();
+        app.app.repo.stage_path(Path::new("c.txt")).unwrap();
+        app.press(key(KeyCode::Char('c')));
+        app.type_text("Add c.txt");
+        app.press(ctrl('s'));
+        wait_for_hooks(&mut app);
+        let run = app.app.hook_run.clone().unwrap();
+        assert!(run.output.contains(&"trailing whitespace".to_string()), "{:?}", run.output);
+        assert!(head_message(&app).starts_with("Add b.txt"));
+
+        app.press(key(KeyCode::Esc));
+        assert_eq!(app.app.popup, Some(Popup::Commit));
+        app.press(ctrl('n'));
+        app.press(ctrl('s'));
+        assert_eq!(head_message(&app), "Add c.txt\n");
+    }
+
+    #[test]
+    fn committing_from_the_popup_shows_up_in_the_log() {
+        let fixture = RepoFixture::new()
+            .committed("a.txt", "one\n")
+            .staged("b.txt", "two\n");
+        let mut app = TestApp::new(fixture);
+        app.press(key(KeyCode::Char('c')));
+        assert_eq!(app.app.popup, Some(Popup::Commit));
+        app.type_text("Add b.txt for real");
+        app.press(ctrl('s'));
+        assert_eq!(app.app.popup, None);
+        assert!(staged_paths(&app).is_empty());
+
+        app.dispatch(Action::SwitchMode(Mode::Log));
+        assert!(app.render().contains("Add b.txt for real"));
+    }
+
+    #[test]
+    fn taking_ours_resolves_a_conflict() {
+        let fixture = RepoFixture::new()
+            .committed("a.txt", "base\n")
+            .conflicted("a.txt", "ours\n", "theirs\n");
+        let mut app = TestApp::new(fixture);
+        app.press(key(KeyCode::Enter));
+        assert_eq!(app.app.mode, Mode::Status(StatusMode::ConflictResolution));
+        app.press(key(KeyCode::Char('o')));
+        assert_eq!(app.app.mode, Mode::Status(StatusMode::FileSelection));
+        // Our side is what HEAD has, so nothing is left to commit.
+        assert!(app.app.status_items.is_empty());
+        let contents = std::fs::read_to_string(app.fixture().path().join("a.txt")).unwrap();
+        assert_eq!(contents, "ours\n");
+    }
+
+    #[test]
+    fn quit_leaves_hunk_selection_before_exiting() {
+        let fixture = RepoFixture::new()
+            .committed("a.txt", "one\n")
+            .unstaged("a.txt", "two\n");
+        let mut app = TestApp::new(fixture);
+        app.press(key(KeyCode::Enter));
+        assert_eq!(app.app.mode, Mode::Status(StatusMode::HunkSelection));
+        assert_eq!(app.press(key(KeyCode::Char('q'))), AppReturn::Continue);
+        assert_eq!(app.press(key(KeyCode::Char('q'))), AppReturn::Exit);
+        assert!(app.app.is_exiting());
+    }
+
+    #[test]
+    fn staging_a_hunk_keeps_the_remaining_hunks_selectable() {
+        let base: String = (1..=20).map(|i| format!("line {}\n", i)).collect();
+        let changed = base.replace("line 1\n", "first\n").replace("line 20\n", "last\n");
+        let fixture = RepoFixture::new()
+            .committed("a.txt", &base)
+            .unstaged("a.txt", &changed);
+        let mut app = TestApp::new(fixture);
+        app.press(key(KeyCode::Enter));
+        assert_eq!(app.app.current_hunks.len(), 2);
+
+        app.press(key(KeyCode::Char(' ')));
+        assert_eq!(app.app.mode, Mode::Status(StatusMode::HunkSelection));
+        assert_eq!(staged_paths(&app), ["a.txt"]);
+        let selected = app.app.get_selected_status_item().unwrap();
+        assert!(!selected.is_staged);
+        assert_eq!(app.app.current_hunks.len(), 1);
+        let remaining = &app.app.current_hunks[0].lines;
+        assert!(remaining.iter().any(|line| line.origin == '+' && line.content == "last\n"));
+    }
+
+    #[test]
+    fn returning_to_a_file_reuses_its_loaded_diff() {
+        let fixture = RepoFixture::new()
+            .committed("a.txt", "one\n")
+            .committed("b.txt", "one\n")
+            .unstaged("a.txt", "two\n")
+            .unstaged("b.txt", "two\n");
+        let mut app = TestApp::new(fixture);
+        app.press(key(KeyCode::Char('j')));
+        assert_eq!(app.app.get_selected_status_item().unwrap().path, "b.txt");
+
+        app.app.dispatch(Action::SelectPrev).unwrap();
+        assert!(!app.app.is_loading());
+        assert_eq!(app.app.diff.as_ref().unwrap().item.path, "a.txt");
+    }
+
+    #[test]
+    fn patterns_added_in_the_ignore_view_hide_files_and_explain_why() {
+        let fixture = RepoFixture::new()
+            .committed(".gitignore", "*.log\n")
+            .unstaged("notes.txt", "todo\n");
+        let mut app = TestApp::new(fixture);
+        app.press(key(KeyCode::Char('O')));
+        assert_eq!(app.app.mode, Mode::Ignore);
+
+        app.press(key(KeyCode::Char('a')));
+        app.type_text("notes.*");
+        app.press(key(KeyCode::Enter));
+        assert!(app.app.status_items.iter().all(|item| item.path != "notes.txt"));
+
+        app.press(key(KeyCode::Char('c')));
+        app.type_text("notes.txt");
+        app.press(key(KeyCode::Enter));
+        let check = app.app.ignore_check.clone().unwrap();
+        assert!(check.ignored);
+        assert_eq!(check.rule.map(|rule| (rule.line, rule.pattern)), Some((2, "notes.*".to_string())));
+        assert!(app.render().contains("notes.txt is ignored (.gitignore:2: notes.*)"));
+    }
+
+    #[test]
+    fn the_session_is_restored_on_the_next_launch() {
+        let fixture = RepoFixture::new()
+            .committed("a", "1\n")
+            .committed("b", "2\n")
+            .committed("c", "3\n")
+            .unstaged("nvim/init.lua", "set number\n")
+            .unstaged("zsh/.zshrc", "export EDITOR=nvim\n")
+            .unstaged("zsh/.zshenv", "\n");
+        let mut app = TestApp::new(fixture);
+        app.dispatch(Action::ToggleDirGrouping);
+        let row_of = |app: &TestApp, wanted: &str| {
+            app.app.status_display_list.iter().position(|entry| match entry {
+                StatusItemType::Item(item) => item.path == wanted,
+                StatusItemType::Dir { path, .. } => path == wanted,
+                StatusItemType::Header(_) => false,
+            })
+        };
+        app.app.status_list_state.select(row_of(&app, "zsh/"));
+        app.app.toggle_selected_dir();
+        app.app.status_list_state.select(row_of(&app, "nvim/init.lua"));
+        app.dispatch(Action::SwitchMode(Mode::Log));
+        app.dispatch(Action::SelectNext);
+        let selected = app.app.selected_log_entry().unwrap().oid;
+
+        let app = app.relaunch();
+        assert_eq!(app.app.mode, Mode::Log);
+        assert_eq!(app.app.selected_log_entry().map(|commit| commit.oid), Some(selected));
+        assert!(app.app.group_by_dir);
+        assert_eq!(row_of(&app, "zsh/.zshrc"), None, "zsh/ stays collapsed");
+        assert_eq!(app.app.get_selected_status_item().unwrap().path, "nvim/init.lua");
+    }
+
+    #[test]
+    fn read_only_mode_refuses_mutating_actions() {
+        let fixture = RepoFixture::new()
+            .committed("a.txt", "one\n")
+            .unstaged("a.txt", "two\n");
+        let mut app = TestApp::new(fixture);
+        app.app.config.read_only = true;
+
+        app.press(key(KeyCode::Char(' ')));
+        assert_eq!(staged_paths(&app), Vec::<String>::new());
+        let message = &app.app.notifications.history().next().unwrap().message;
+        assert_eq!(message, "Disabled in read-only mode: stage item / toggle hunk");
+        app.dispatch(Action::Commit);
+        assert_eq!(app.app.popup, None);
+
+        // Looking around still works.
+        app.dispatch(Action::SwitchMode(Mode::Log));
+        assert_eq!(app.app.mode, Mode::Log);
+        app.dispatch(Action::ShowHelp);
+        assert!(app.render().contains("Help (?) - read-only"));
+    }
+
+    #[test]
+    fn the_branch_popup_renames_the_branch_and_edits_its_upstream() {
+        let upstream = RepoFixture::new().committed("a", "1\n");
+        let mut app = TestApp::new(RepoFixture::new().tracking(&upstream));
+        app.press(KeyEvent::new(KeyCode::Char('B'), KeyModifiers::SHIFT));
+        let edit = app.app.branch_edit.clone().unwrap();
+        assert_eq!((edit.name.text(), edit.remote.text(), edit.merge.text()), ("main", "origin", "main"));
+
+        for _ in 0..4 {
+            app.press(key(KeyCode::Backspace));
+        }
+        app.type_text("trunk");
+        app.press(key(KeyCode::Enter));
+        assert_eq!(app.app.popup, None);
+        let repo = app.fixture().open();
+        assert_eq!(repo.current_branch().unwrap().as_deref(), Some("trunk"));
+        assert_eq!(repo.branch_upstream("trunk").unwrap(), Some(("origin".to_string(), "main".to_string())));
+
+        // Clearing the remote removes the upstream; an unknown one is refused.
+        app.press(KeyEvent::new(KeyCode::Char('B'), KeyModifiers::SHIFT));
+        app.press(key(KeyCode::Tab));
+        app.type_text("x");
+        app.press(key(KeyCode::Enter));
+        assert_eq!(app.app.popup, Some(Popup::BranchEdit));
+        for _ in 0..7 {
+            app.press(key(KeyCode::Backspace));
+        }
+        app.press(key(KeyCode::Enter));
+        assert_eq!(app.app.popup, None);
+        assert_eq!(app.fixture().open().branch_upstream("trunk").unwrap(), None);
+    }
+
+    #[test]
+    fn a_detached_head_is_flagged_and_can_be_kept_on_a_new_branch() {
+        let fixture = RepoFixture::new().committed("a", "1\n").committed("b", "2\n");
+        {
+            let repo = git2::Repository::open(fixture.path()).unwrap();
+            let head = repo.head().unwrap().peel_to_commit().unwrap();
+            repo.set_head_detached(head.parent_id(0).unwrap()).unwrap();
+            repo.checkout_head(Some(git2::build::CheckoutBuilder::new().force())).unwrap();
+        }
+        let fixture = fixture.committed("c", "3\n");
+        let mut app = TestApp::new(fixture);
+        assert!(app.app.branch_status.detached);
+        let warning = app.app.notifications.history().next().unwrap();
+        assert_eq!(warning.severity, Severity::Warning);
+        assert!(warning.message.starts_with("HEAD is detached at "), "{}", warning.message);
+        assert!(app.render().contains(" DETACHED HEAD at "));
+
+        app.press(KeyEvent::new(KeyCode::Char('B'), KeyModifiers::SHIFT));
+        let detached = app.app.detached_head.clone().unwrap();
+        assert_eq!(detached.branches, ["main"]);
+        assert_eq!(detached.commits_on_no_branch, 1);
+
+        app.press(key(KeyCode::Enter));
+        assert_eq!(app.app.popup, Some(Popup::BranchCreate));
+        app.type_text("rescued");
+        app.press(key(KeyCode::Enter));
+        assert_eq!(app.app.popup, None);
+        assert_eq!(app.fixture().open().current_branch().unwrap().as_deref(), Some("rescued"));
+        assert!(!app.app.branch_status.detached);
+    }
+
+    #[test]
+    fn ignore_templates_are_offered_for_secret_looking_files() {
+        let fixture = RepoFixture::new()
+            .committed(".gitignore", ".env\n")
+            .unstaged(".ssh/id_rsa", "key\n")
+            .unstaged(".env.local", "TOKEN=1\n")
+            .unstaged(".bash_history", "ls\n");
+        let mut app = TestApp::new(fixture);
+        app.dispatch(Action::SwitchMode(Mode::Ignore));
+        app.press(key(KeyCode::Char('d')));
+        let picker = app.app.ignore_templates.clone().unwrap();
+        let names: Vec<_> = picker.matches.iter().map(|found| found.template.name).collect();
+        assert_eq!(names, ["SSH private keys", "Shell and tool history", "Environment files"]);
+
+        // Leave the history out.
+        app.press(key(KeyCode::Char('j')));
+        app.press(key(KeyCode::Char(' ')));
+        app.press(key(KeyCode::Enter));
+        let gitignore = std::fs::read_to_string(app.fixture().path().join(".gitignore")).unwrap();
+        assert_eq!(
+            gitignore,
+            ".env\n# SSH private keys\nid_rsa\nid_dsa\nid_ecdsa\nid_ed25519\n*.pem\n# Environment files\n.env.*\n!.env.example\n"
+        );
+        assert_eq!(app.app.notifications.history().next().unwrap().message, "Added 7 patterns to .gitignore");
+    }
+
+    #[test]
+    fn marked_files_are_staged_together_and_undone_at_once() {
+        let fixture = RepoFixture::new()
+            .committed("a", "1\n")
+            .committed("b", "1\n")
+            .committed("c", "1\n")
+            .unstaged("a", "2\n")
+            .unstaged("b", "2\n")
+            .unstaged("c", "2\n");
+        let mut app = TestApp::new(fixture);
+        let staged = |app: &TestApp| {
+            let mut paths: Vec<_> =
+                app.app.status_items.iter().filter(|item| item.is_staged).map(|item| item.path.clone()).collect();
+            paths.sort();
+            paths
+        };
+        app.press(key(KeyCode::Char('m')));
+        app.press(key(KeyCode::Char('j')));
+        app.press(key(KeyCode::Char('m')));
+        assert_eq!(app.app.marked_count(), 2);
+        assert!(app.render().contains("Files (2 marked, Esc to clear)"));
+
+        app.press(key(KeyCode::Char(' ')));
+        assert_eq!(staged(&app), ["a", "c"]);
+        assert_eq!(app.app.marked_count(), 0);
+
+        app.press(ctrl('z'));
+        assert!(staged(&app).is_empty());
+    }
+
+    #[test]
+    fn marked_files_are_discarded_after_a_confirmation() {
+        let fixture = RepoFixture::new()
+            .committed("a", "1\n")
+            .committed("b", "1\n")
+            .unstaged("a", "2\n")
+            .unstaged("b", "2\n")
+            .unstaged("new", "x\n");
+        let root = fixture.path().to_path_buf();
+        let mut app = TestApp::new(fixture);
+        app.press(key(KeyCode::Char('m')));
+        app.press(key(KeyCode::Char('j')));
+        app.press(key(KeyCode::Char('m')));
+        app.press(key(KeyCode::Char('d')));
+        assert!(matches!(
+            &app.app.popup,
+            Some(Popup::Confirm { on_confirm: ConfirmAction::Discard(paths), .. }) if paths == &["a", "new"]
+        ));
+        app.press(key(KeyCode::Char('n')));
+        assert_eq!(std::fs::read_to_string(root.join("a")).unwrap(), "2\n");
+
+        app.press(key(KeyCode::Char('d')));
+        app.press(key(KeyCode::Char('y')));
+        assert_eq!(std::fs::read_to_string(root.join("a")).unwrap(), "1\n");
+        assert!(!root.join("new").exists());
+        assert_eq!(std::fs::read_to_string(root.join("b")).unwrap(), "2\n");
+        let paths: Vec<_> = app.app.status_items.iter().map(|item| item.path.as_str()).collect();
+        assert_eq!(paths, ["b"]);
+        assert_eq!(app.app.marked_count(), 0);
+    }
+
+    #[test]
+    fn the_help_popup_scrolls_and_widens_to_every_view() {
+        let mut app = TestApp::with_size(RepoFixture::new().committed("a.txt", "one\n"), 80, 20);
+        app.press(key(KeyCode::Char('?')));
+        let screen = app.render();
+        assert!(screen.contains(" Help (?) - Status View "), "{}", screen);
+        assert!(screen.contains("tab: all views"));
+
+        app.press(key(KeyCode::Char('G')));
+        app.render();
+        let end = app.app.help.scroll;
+        assert!(end < u16::MAX, "the end is where the last line shows");
+        assert!(end > 0);
+        app.press(key(KeyCode::Char('k')));
+        assert_eq!(app.app.help.scroll, end - 1);
+
+        app.press(key(KeyCode::Tab));
+        assert_eq!(app.app.help, HelpState { scroll: 0, all_modes: true });
+        app.press(key(KeyCode::Char('G')));
+        app.render();
+        assert!(app.app.help.scroll > end, "every view makes the help longer");
+        app.press(key(KeyCode::Char('?')));
+        assert_eq!(app.app.popup, None);
+    }
+
+    #[test]
+    fn destructive_actions_wait_for_a_yes() {
+        let fixture = RepoFixture::new().committed("a", "1\n").unstaged("a", "2\n");
+        let mut app = TestApp::new(fixture);
+        app.dispatch(Action::StashPush(StashFlags::DEFAULT));
+        app.dispatch(Action::SwitchMode(Mode::Stash));
+
+        app.dispatch(Action::StashDrop);
+        assert!(matches!(
+            app.app.popup,
+            Some(Popup::Confirm { on_confirm: ConfirmAction::StashDrop(0), .. })
+        ));
+        assert!(app.render().contains("Drop stash@{0}? (y/n)"));
+        app.press(key(KeyCode::Char('n')));
+        assert_eq!(app.app.popup, None);
+        assert_eq!(app.app.repo.get_stashes().unwrap().len(), 1);
+
+        app.dispatch(Action::StashDrop);
+        app.press(key(KeyCode::Char('y')));
+        assert_eq!(app.app.popup, None);
+        assert!(app.app.repo.get_stashes().unwrap().is_empty());
+    }
+
+    #[test]
+    fn invalid_branch_names_are_flagged_before_renaming() {
+        let mut app = TestApp::new(RepoFixture::new().committed("a", "1\n"));
+        app.press(KeyEvent::new(KeyCode::Char('B'), KeyModifiers::SHIFT));
+        app.type_text("..x");
+        assert!(app.render().contains("'main..x' is not a valid branch name"));
+        app.press(key(KeyCode::Enter));
+        assert_eq!(app.app.popup, Some(Popup::BranchEdit));
+        assert_eq!(app.app.repo.current_branch().unwrap().as_deref(), Some("main"));
+    }
+
+    #[test]
+    fn errors_open_a_popup_instead_of_ending_the_app() {
+        let mut app = TestApp::new(RepoFixture::new().committed("a", "1\n"));
+        app.app.confirm("Confirm", "Drop stash@{3}?".to_string(), ConfirmAction::StashDrop(3));
+        assert_eq!(app.press(key(KeyCode::Char('y'))), AppReturn::Continue);
+        assert!(matches!(&app.app.popup, Some(Popup::Error { retry: None, .. })));
+        app.press(key(KeyCode::Esc));
+        assert_eq!(app.app.popup, None);
+
+        app.app.network_op = Some(NetworkOp::Fetch);
+        let failed = AppError::FetchFailed("connection timed out".to_string());
+        app.dispatch(Action::Event(AppEvent::FetchFinished(Err(failed))));
+        assert_eq!(
+            app.app.popup,
+            Some(Popup::Error {
+                title: "Fetch failed".to_string(),
+                message: "Fetch failed: connection timed out".to_string(),
+                retry: Some(NetworkOp::Fetch),
+            })
+        );
+        let screen = app.render();
+        assert!(screen.contains("r: retry, Enter/Esc: close"), "{}", screen);
+    }
+
+    #[test]
+    fn a_repository_without_commits_opens_and_takes_its_first_commit() {
+        let fixture = RepoFixture::new();
+        std::fs::write(fixture.path().join(".bashrc"), "alias ll='ls -l'\n").unwrap();
+        let mut app = TestApp::new(fixture);
+        let screen = app.render();
+        assert!(screen.contains("main (no commits yet)"), "{}", screen);
+        assert!(screen.contains("+alias ll='ls -l'"), "{}", screen);
+
+        app.dispatch(Action::SwitchMode(Mode::Log));
+        assert!(app.render().contains("No commits yet."));
+        assert_eq!(
+            app.app.repo.create_branch_at_head("topic").unwrap_err().to_string(),
+            "The branch has no commits yet"
+        );
+
+        app.dispatch(Action::SwitchMode(Mode::Status(StatusMode::FileSelection)));
+        app.press(key(KeyCode::Char(' ')));
+        app.press(key(KeyCode::Char('c')));
+        app.type_text("Add bashrc");
+        app.press(ctrl('s'));
+        app.dispatch(Action::SwitchMode(Mode::Log));
+        let screen = app.render();
+        assert!(screen.contains("Add bashrc") && !screen.contains("no commits yet"), "{}", screen);
+    }
+
+    #[test]
+    fn pushes_can_go_to_chosen_remotes_and_refspecs() {
+        let upstream = RepoFixture::new().committed("a", "1\n");
+        let fixture = RepoFixture::new().tracking(&upstream);
+        git2::Repository::open(fixture.path()).unwrap().remote("backup", "/srv/git/dotfiles.git").unwrap();
+        let mut app = TestApp::new(fixture);
+        app.press(ctrl('p'));
+        let screen = app.render();
+        assert!(screen.contains("[ ] backup  /srv/git/dotfiles.git") && screen.contains("[x] origin"), "{}", screen);
+        assert!(screen.contains("Refspec: main"), "{}", screen);
+
+        app.press(key(KeyCode::Char(' ')));
+        app.press(key(KeyCode::Tab));
+        app.type_text(":a..b");
+        let screen = app.render();
+        assert!(screen.contains("[x] backup") && screen.contains("'refs/heads/a..b' is not a valid ref name"), "{}", screen);
+        app.press(key(KeyCode::Enter));
+        assert_eq!(app.app.popup, Some(Popup::PushTargets));
+
+        app.press(key(KeyCode::Esc));
+        assert_eq!(app.app.popup, None);
+        assert_eq!(app.app.repo.push_remotes().unwrap(), ["origin"]);
+    }
+
+    #[test]
+    fn clones_take_a_depth_and_a_branch() {
+        let mut app = TestApp::new(RepoFixture::new().committed("a", "1\n"));
+        app.press(ctrl('r'));
+        app.press(key(KeyCode::Char('c')));
+        app.type_text("https://example.com/me/dotfiles.git");
+        app.press(key(KeyCode::Enter));
+        assert_eq!(app.app.popup, Some(Popup::ClonePath));
+        app.press(key(KeyCode::Tab));
+        app.type_text("0");
+        app.press(key(KeyCode::Tab));
+        app.type_text("laptop");
+        let screen = app.render();
+        assert!(screen.contains("Directory: ~/dotfiles") && screen.contains("Branch:    laptop"), "{}", screen);
+        assert!(screen.contains("'0' is not a number of commits"), "{}", screen);
+        app.press(key(KeyCode::Enter));
+        assert_eq!(app.app.popup, Some(Popup::ClonePath));
+        assert!(app.app.task.is_none());
+        assert_eq!(app.app.clone_input(CloneField::Depth).text(), "0");
+    }
+
+    #[test]
+    fn the_config_is_reloaded_when_its_file_changes() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("config.toml");
+        std::fs::write(&path, "read_only = true\n").unwrap();
+        let mut app = TestApp::new(RepoFixture::new().committed("a", "1\n").unstaged("new", "x\n"));
+        app.app.config = Config::load(Some(&path)).unwrap();
+        let reloaded = |app: &TestApp| app.app.notifications.history().filter(|n| n.message == "Config reloaded").count();
+        assert!(app.app.status_items.iter().any(|item| item.path == "new"));
+
+        std::fs::write(&path, "hide_untracked = true\ndisable_mouse = true\n").unwrap();
+        app.dispatch(Action::from(AppEvent::ConfigChanged));
+        assert_eq!(reloaded(&app), 1);
+        assert!(app.app.config.read_only && !app.app.mouse_capture);
+        assert!(app.app.status_items.iter().all(|item| item.path != "new"));
+
+        std::fs::write(&path, "hide_untracked = maybe\n").unwrap();
+        app.dispatch(Action::from(AppEvent::ConfigChanged));
+        let error = app.app.notifications.history().next().unwrap();
+        assert_eq!(error.severity, Severity::Error);
+        assert!(error.message.starts_with("Config not reloaded: "), "{}", error.message);
+        assert!(app.app.config.hide_untracked);
+
+        std::fs::write(&path, "hide_untracked = true\ndisable_mouse = true\n").unwrap();
+        app.dispatch(Action::from(AppEvent::ConfigChanged));
+        assert_eq!(reloaded(&app), 1);
+
+        std::fs::write(&path, "hide_untracked = true\ndisable_mouse = true\n[theme]\npreset = \"light\"\n").unwrap();
+        app.dispatch(Action::from(AppEvent::ConfigChanged));
+        assert_eq!(reloaded(&app), 2);
+        assert_eq!(app.app.config.theme, crate::config::Theme::preset("light").unwrap());
+    }
+
+    #[test]
+    fn multi_key_bindings_wait_for_their_next_key() {
+        let fixture = RepoFixture::new().committed("a", "1\n").staged("b", "2\n").unstaged("a", "3\n");
+        let mut app = TestApp::new(fixture);
+        app.type_text("\\g");
+        assert_eq!(app.app.pending_keys_label().as_deref(), Some("\\ g"));
+        assert!(app.render().contains("\\ g …"));
+        app.type_text("c");
+        assert_eq!(app.app.popup, Some(Popup::Commit));
+        app.press(key(KeyCode::Esc));
+
+        // A key that does not continue the binding is taken on its own.
+        app.type_text("gg");
+        let first = app.app.status_list_state.selected();
+        app.type_text("\\j");
+        assert_eq!(app.app.pending_keys_label(), None);
+        assert_ne!(app.app.status_list_state.selected(), first);
+        app.type_text("3g");
+        assert_eq!(app.app.pending_keys_label().as_deref(), Some("3g"));
+        app.type_text("g");
+        assert_eq!(app.app.status_list_state.selected(), first);
+
+        app.type_text("\\");
+        app.app.pending_since -= SEQUENCE_TIMEOUT;
+        app.dispatch(Action::Tick);
+        assert_eq!(app.app.pending_keys_label(), None);
+        app.type_text("g");
+        assert_eq!(app.app.pending_keys_label().as_deref(), Some("g"));
+    }
+
+    #[test]
+    fn each_view_reads_keys_from_its_own_keymap() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("config.toml");
+        std::fs::write(&path, "").unwrap();
+        let mut app = TestApp::new(RepoFixture::new().committed("a", "1\n").unstaged("a", "2\n"));
+        app.app.config = Config::load(Some(&path)).unwrap();
+        app.type_text("l");
+        assert_eq!(app.app.mode, Mode::Status(StatusMode::FileSelection));
+        app.press(ctrl('l'));
+        assert_eq!(app.app.mode, Mode::Log);
+        app.type_text("tl");
+        assert_eq!(app.app.mode, Mode::Log);
+
+        std::fs::write(&path, "[keys.status]\ncommit = \"C\"\n").unwrap();
+        app.dispatch(Action::from(AppEvent::ConfigChanged));
+        app.type_text("sc");
+        assert_eq!(app.app.popup, None);
+        app.type_text("C");
+        assert_eq!(app.app.popup, Some(Popup::Commit));
+    }
+
+    #[test]
+    fn the_palette_runs_the_keys_of_the_current_view() {
+        let mut app = TestApp::new(RepoFixture::new().committed("a", "1\n").unstaged("a", "2\n"));
+        app.type_text(":");
+        assert_eq!(app.app.popup, Some(Popup::Palette));
+        let stage = app
+            .app
+            .palette
+            .iter()
+            .position(|entry| matches!(entry, PaletteEntry::Key(_, description) if description.starts_with("stage item")))
+            .unwrap();
+        assert!(!app.app.palette.iter().any(|entry| matches!(entry, PaletteEntry::Key(_, "command palette (also the actions of init.lua)"))));
+        assert!(app.render().contains(">> h         focus the left panel"));
+        app.app.palette_state.select(Some(stage));
+        app.press(key(KeyCode::Enter));
+        assert_eq!(app.app.popup, None);
+        assert_eq!(staged_paths(&app), ["a"]);
+    }
+
+    /// Counts the keys pressed while it is shown.
+    struct CounterPanel(Vec<String>);
+
+    impl Panel for CounterPanel {
+        fn title(&self) -> &str {
+            "Counter"
+        }
+
+        fn render(&mut self, frame: &mut ratatui::Frame, _app: &mut App, area: Rect) {
+            let text = format!("pressed {}", self.0.join(" "));
+            frame.render_widget(ratatui::widgets::Paragraph::new(text), area);
+        }
+
+        fn handle_key(&mut self, _app: &mut App, action: Action) -> AppResult<()> {
+            if let Action::Key(key) = action {
+                self.0.push(key_label(key));
+            }
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn registered_panels_get_a_tab_and_the_unbound_keys() {
+        let mut app = TestApp::with_size(RepoFixture::new().committed("a", "1\n"), 160, 24);
+        let mode = app.app.register_panel(Box::new(CounterPanel(Vec::new())));
+        assert_eq!(mode, Mode::Custom(0));
+        assert!(app.render().lines().next().unwrap().ends_with("Git [C]onfig │ Counter"));
+
+        app.type_text(":");
+        let entry = app.app.palette.iter().position(|entry| *entry == PaletteEntry::Panel(mode, "Counter".to_string()));
+        app.app.palette_state.select(entry);
+        app.press(key(KeyCode::Enter));
+        assert_eq!(app.app.mode, mode);
+        // `x` and `z` are bound in other views only; `?` is global.
+        app.type_text("xz?");
+        app.press(key(KeyCode::Esc));
+        assert!(app.render().contains("pressed x z"));
+
+        app.type_text("i");
+        assert_eq!(app.app.mode, Mode::Links);
+        let app = app.relaunch();
+        assert_eq!(app.app.mode, Mode::Links);
+    }
+
+    #[test]
+    fn the_files_title_spins_while_the_status_loads() {
+        let mut app = TestApp::new(RepoFixture::new().committed("a", "1\n").unstaged("a", "2\n"));
+        app.app.refresh().unwrap();
+        assert!(app.app.is_refreshing());
+        // The status read before stays until the worker answers.
+        assert!(app.render().contains("⠋ Files ('h' to focus)"));
+        assert!(!app.app.status_items.is_empty());
+        app.app.dispatch(Action::Tick).unwrap();
+        assert!(app.render().contains("⠙ Files"));
+
+        app.dispatch(Action::Tick);
+        assert!(!app.app.is_refreshing());
+        assert!(app.render().contains("┌Files ('h' to focus)"));
+    }
+
+    #[test]
+    fn collapsed_untracked_directories_stage_as_one_and_expand_on_enter() {
+        let fixture = RepoFixture::new()
+            .committed("a", "1\n")
+            .unstaged("more/z", "z\n")
+            .unstaged("new/x", "x\n")
+            .unstaged("new/sub/y", "y\n");
+        let mut app = TestApp::new(fixture);
+        app.app.config.collapse_untracked_dirs = true;
+        app.app.refresh().unwrap();
+        app.dispatch(Action::Tick);
+        let paths: Vec<&str> = app.app.status_items.iter().map(|item| item.path.as_str()).collect();
+        assert_eq!(paths, ["more/", "new/"]);
+        assert!(app.render().contains("An untracked directory. Enter lists its files"));
+
+        app.type_text(" ");
+        assert_eq!(staged_paths(&app), ["more/z"]);
+        app.type_text("G");
+        app.press(key(KeyCode::Enter));
+        assert_eq!(app.app.mode, Mode::Status(StatusMode::FileSelection));
+        let mut untracked: Vec<&str> = app
+            .app
+            .status_items
+            .iter()
+            .filter(|item| !item.is_staged)
+            .map(|item| item.path.as_str())
+            .collect();
+        untracked.sort();
+        assert_eq!(untracked, ["new/sub/y", "new/x"]);
+    }
+
+    #[test]
+    fn changes_in_the_git_directory_refresh_even_when_it_is_ignored() {
+        let fixture = RepoFixture::new().committed(".gitignore", "*.log\n.git\n");
+        let mut app = TestApp::new(fixture);
+        let root = app.fixture().path().to_path_buf();
+        let requested = app.app.refresh_requested;
+        app.dispatch(AppEvent::FsChanged(vec![root.join("build.log")]).into());
+        assert_eq!(app.app.refresh_requested, requested);
+
+        let git_dir = app.app.repo.location().git_dir;
+        std::fs::write(git_dir.join("HEAD"), "ref: refs/heads/other\n").unwrap();
+        assert!(app.app.repo.is_path_ignored(&git_dir.join("HEAD")));
+        app.dispatch(AppEvent::FsChanged(vec![git_dir.join("HEAD")]).into());
+        assert_eq!(app.app.refresh_requested, requested + 1);
+    }
+
+    #[test]
+    fn staging_a_file_rereads_only_its_status() {
+        let lines: String = (1..=20).map(|i| format!("{}\n", i)).collect();
+        let fixture = RepoFixture::new()
+            .committed("a", &lines)
+            .committed("b", "1\n")
+            .unstaged("a", &lines.replace("2\n", "two\n").replace("19\n", "nineteen\n"))
+            .unstaged("b", "2\n")
+            .unstaged("c", "new\n");
+        let mut app = TestApp::new(fixture);
+        let requested = app.app.refresh_requested;
+        // The first of the two hunks of `a`, then the untracked file.
+        app.press(key(KeyCode::Enter));
+        app.type_text(" ");
+        assert_eq!(app.app.mode, Mode::Status(StatusMode::HunkSelection));
+        app.type_text("qG ");
+        assert_eq!(staged_paths(&app), ["a", "c"]);
+        assert_eq!(app.app.refresh_requested, requested);
+        let incremental = (
+            app.app.status_items.clone(),
+            app.app.work_tree_summary,
+            app.app.status_list_state.selected(),
+            app.app.mode,
+        );
+
+        app.app.refresh().unwrap();
+        app.dispatch(Action::Tick);
+        let full = (
+            app.app.status_items.clone(),
+            app.app.work_tree_summary,
+            app.app.status_list_state.selected(),
+            app.app.mode,
+        );
+        assert_eq!(incremental, full);
+    }
+
+    #[test]
+    fn idle_ticks_leave_the_screen_alone() {
+        let mut app = TestApp::new(RepoFixture::new().committed("a", "1\n").unstaged("a", "2\n"));
+        app.render();
+        app.app.mark_drawn();
+        app.dispatch(Action::Tick);
+        assert!(!app.app.needs_redraw());
+
+        app.type_text("j");
+        assert!(app.app.needs_redraw());
+        app.app.mark_drawn();
+        app.app.refresh().unwrap();
+        app.app.dispatch(Action::Tick).unwrap();
+        assert!(app.app.needs_redraw());
+    }
+
+    #[test]
+    fn the_commit_subject_can_be_guessed_from_the_staged_paths() {
+        let fixture = RepoFixture::new()
+            .committed(".zshrc", "1\n")
+            .staged(".zshrc", "2\n")
+            .staged(".config/nvim/init.lua", "set number\n")
+            .unstaged(".tmux.conf", "new\n");
+        let mut app = TestApp::new(fixture);
+        app.app.config.prefill_subject = true;
+        app.press(key(KeyCode::Char('c')));
+        assert_eq!(app.app.commit_editor.text(), "nvim, zsh: update configs");
+        app.press(ctrl('s'));
+        let repo = git2::Repository::open(app.fixture().path()).unwrap();
+        let head = repo.head().unwrap().peel_to_commit().unwrap();
+        assert_eq!(head.message(), Some("nvim, zsh: update configs\n"));
+    }
+
+    #[test]
+    fn stow_packages_are_imported_into_the_manifest() {
+        let fixture = RepoFixture::new()
+            .committed("zsh/.zshrc", "1\n")
+            .committed("nvim/.config/nvim/init.lua", "1\n")
+            .committed(MANIFEST_FILE, "[[link]]\nsource = \"zsh/.zshrc\"\ntarget = \"~/.zshrc\"\n");
+        let mut app = TestApp::new(fixture);
+        app.dispatch(Action::SwitchMode(Mode::Links));
+        app.press(key(KeyCode::Char('I')));
+        assert_eq!(app.app.popup, Some(Popup::LinkImport));
+        let picker = app.app.link_import.clone().unwrap();
+        let targets: Vec<_> = picker.import.links.iter().map(|link| link.target.as_str()).collect();
+        assert_eq!(targets, ["~/.config/nvim"]);
+
+        app.press(key(KeyCode::Enter));
+        let manifest = std::fs::read_to_string(app.fixture().path().join(MANIFEST_FILE)).unwrap();
+        assert!(manifest.ends_with("[[link]]\nsource = \"nvim/.config/nvim\"\ntarget = \"~/.config/nvim\"\n"), "{}", manifest);
+        assert_eq!(app.app.links.len(), 2);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn secrets_are_encrypted_when_staged_and_decrypted_when_linked() {
+        use std::os::unix::fs::PermissionsExt;
+        let home = tempfile::TempDir::new().unwrap();
+        // Stands in for age: encrypting prepends the armor header, and
+        // `--decrypt --identity <file> <secret>` takes it off again.
+        let program = home.path().join("fake-age");
+        std::fs::write(
+            &program,
+            "#!/bin/sh\nif [ \"$1\" = --decrypt ]; then tail -n +2 \"$4\"; else echo '-----BEGIN AGE ENCRYPTED FILE-----'; cat; fi\n",
+        )
+        .unwrap();
+        std::fs::set_permissions(&program, std::fs::Permissions::from_mode(0o755)).unwrap();
+        let target = home.path().join("token");
+        let fixture = RepoFixture::new()
+            .committed(
+                MANIFEST_FILE,
+                &format!("[[link]]\nsource = \"secrets/token\"\ntarget = \"{}\"\n", target.display()),
+            )
+            .unstaged("secrets/token", "hunter2\n");
+        let mut app = TestApp::new(fixture);
+        app.app.config.encryption = Some(EncryptionConfig {
+            patterns: vec!["secrets/**".to_string(), "*.key".to_string()],
+            program: Some(program.display().to_string()),
+            recipients: vec!["age1test".to_string()],
+            ..EncryptionConfig::default()
+        });
+        // Written after the status was read, so only the work tree knows it.
+        std::fs::write(app.fixture().path().join("aws.key"), "AKIA\n").unwrap();
+        app.dispatch(Action::StageAll);
+        let stored = std::fs::read_to_string(app.fixture().path().join("secrets/token")).unwrap();
+        assert_eq!(stored, "-----BEGIN AGE ENCRYPTED FILE-----\nhunter2\n");
+        let stored = std::fs::read_to_string(app.fixture().path().join("aws.key")).unwrap();
+        assert_eq!(stored, "-----BEGIN AGE ENCRYPTED FILE-----\nAKIA\n");
+
+        app.dispatch(Action::SwitchMode(Mode::Links));
+        assert!(app.app.links[0].encrypted);
+        app.press(key(KeyCode::Char('c')));
+        assert_eq!(app.app.popup, Some(Popup::SecretPrompt(0)));
+        app.type_text("~/.config/age/key.txt");
+        app.press(key(KeyCode::Enter));
+        assert_eq!(std::fs::read_to_string(&target).unwrap(), "hunter2\n");
+        assert_eq!(app.app.links[0].state, LinkState::Decrypted);
+    }
+
+    #[test]
+    fn applying_the_links_backs_up_files_in_the_way_and_lists_the_results() {
+        let home = tempfile::TempDir::new().unwrap();
+        let backups = tempfile::TempDir::new().unwrap();
+        std::fs::write(home.path().join(".vimrc"), "old\n").unwrap();
+        let manifest = format!(
+            "[[link]]\nsource = \"zshrc\"\ntarget = \"{}\"\n\n[[link]]\nsource = \"vimrc\"\ntarget = \"{}\"\n",
+            home.path().join(".zshrc").display(),
+            home.path().join(".vimrc").display()
+        );
+        let fixture = RepoFixture::new()
+            .committed("zshrc", "1\n")
+            .committed("vimrc", "1\n")
+            .committed(MANIFEST_FILE, &manifest);
+        let mut app = TestApp::new(fixture);
+        app.app.config.backup_dir = Some(backups.path().to_path_buf());
+        app.dispatch(Action::SwitchMode(Mode::Links));
+        app.press(key(KeyCode::Char('A')));
+        assert_eq!(app.app.popup, Some(Popup::ApplyResults));
+        let labels: Vec<_> = app.app.apply_results.iter().map(|result| result.outcome.label()).collect();
+        assert_eq!(labels, ["linked", "backed up"]);
+        assert!(app.app.links.iter().all(|entry| entry.state == LinkState::Linked));
+        let ApplyOutcome::BackedUp(backup) = &app.app.apply_results[1].outcome else {
+            unreachable!();
+        };
+        assert!(backup.starts_with(backups.path()));
+        assert_eq!(std::fs::read_to_string(backup).unwrap(), "old\n");
+        assert!(app.render().contains("backed up"));
+
+        app.press(key(KeyCode::Enter));
+        assert_eq!(app.app.popup, None);
+    }
+
+    fn wait_for_doctor(app: &mut TestApp) -> Vec<Check> {
+        loop {
+            app.process_events();
+            if let DoctorView::Loaded(checks) = &app.app.doctor_view {
+                return checks.clone();
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn the_doctor_finds_problems_and_fixes_them() {
+        use std::os::unix::fs::PermissionsExt;
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let _guard = runtime.enter();
+        let fixture = RepoFixture::new().committed("a", "1\n");
+        let hook = fixture.path().join(".git/hooks/pre-commit");
+        std::fs::create_dir_all(hook.parent().unwrap()).unwrap();
+        std::fs::write(&hook, "#!/bin/sh\n").unwrap();
+        std::fs::set_permissions(&hook, std::fs::Permissions::from_mode(0o644)).unwrap();
+        let mut app = TestApp::new(fixture);
+        app.press(key(KeyCode::Char('D')));
+        assert_eq!(app.app.mode, Mode::Doctor);
+        let checks = wait_for_doctor(&mut app);
+        let names: Vec<_> = checks.iter().map(|check| check.name).collect();
+        assert_eq!(names, ["repository", "remote", "identity", "hooks", "links"]);
+        assert!(app.render().contains("pre-commit not executable"));
+
+        app.press(key(KeyCode::Char('j')));
+        app.press(key(KeyCode::Enter));
+        let copied = app.app.take_clipboard_request().unwrap();
+        assert_eq!(copied.text, "git remote add origin <url>");
+
+        app.app.doctor_table_state.select(Some(3));
+        app.press(key(KeyCode::Enter));
+        assert_ne!(std::fs::metadata(&hook).unwrap().permissions().mode() & 0o111, 0);
+        let checks = wait_for_doctor(&mut app);
+        assert_eq!(checks[3].health, doctor::Health::Pass);
+    }
+
+    #[test]
+    fn the_git_config_view_checks_values_and_writes_the_chosen_file() {
+        let fixture = RepoFixture::new().committed("a", "1\n");
+        let path = fixture.path().to_path_buf();
+        let local = |name: &str| {
+            let config = git2::Repository::open(&path).unwrap().config().unwrap();
+            config.open_level(git2::ConfigLevel::Local).unwrap().get_string(name).ok()
+        };
+        let mut app = TestApp::with_size(fixture, 160, 40);
+        app.dispatch(Action::SwitchMode(Mode::GitConfig));
+        app.press(key(KeyCode::Tab));
+        assert_eq!(app.app.git_config_scope, ConfigScope::Local);
+        assert!(app.render().contains("test@example.com"));
+
+        let rebase = app.app.git_config.iter().position(|entry| entry.name == "pull.rebase").unwrap();
+        app.app.git_config_table_state.select(Some(rebase));
+        app.press(key(KeyCode::Enter));
+        app.type_text("always");
+        assert!(app.render().contains("'always' is not one of"));
+        app.press(key(KeyCode::Enter));
+        assert_eq!(app.app.popup, Some(Popup::ConfigValue("pull.rebase".to_string())));
+        app.press(ctrl('u'));
+        app.press(key(KeyCode::Tab));
+        app.press(key(KeyCode::Tab));
+        app.press(key(KeyCode::Enter));
+        assert_eq!(local("pull.rebase").as_deref(), Some("true"));
+
+        let prune = app.app.git_config.iter().position(|entry| entry.name == "fetch.prune").unwrap();
+        app.app.git_config_table_state.select(Some(prune));
+        app.press(key(KeyCode::Enter));
+        assert_eq!(local("fetch.prune").as_deref(), Some("true"));
+        app.press(key(KeyCode::Char('x')));
+        assert_eq!(local("fetch.prune"), None);
+
+        app.press(key(KeyCode::Char('a')));
+        app.type_text("st");
+        app.press(key(KeyCode::Enter));
+        assert_eq!(app.app.popup, Some(Popup::ConfigName));
+        app.press(ctrl('u'));
+        app.type_text("alias.st");
+        app.press(key(KeyCode::Enter));
+        app.type_text("status -sb");
+        app.press(key(KeyCode::Enter));
+        assert_eq!(local("alias.st").as_deref(), Some("status -sb"));
+        let selected = app.app.git_config_table_state.selected().unwrap();
+        assert_eq!(app.app.git_config[selected].name, "alias.st");
+    }
+
+    #[test]
+    fn bisecting_from_the_log_finds_the_first_bad_commit_and_returns_to_the_branch() {
+        let mut fixture = RepoFixture::new();
+        for i in 0..6 {
+            fixture = fixture.committed("zshrc", &format!("{}\n", i));
+        }
+        let path = fixture.path().to_path_buf();
+        let mut app = TestApp::new(fixture);
+        app.dispatch(Action::SwitchMode(Mode::Log));
+        app.press(key(KeyCode::Char('b')));
+        app.press(key(KeyCode::Char('b')));
+        assert_eq!(app.app.popup, None);
+        app.press(key(KeyCode::Char('G')));
+        app.press(key(KeyCode::Char('b')));
+        app.press(key(KeyCode::Char('g')));
+        let mut tests = 0;
+        while let Some(BisectStep::Test { .. }) = app.app.bisect.as_ref().map(|bisect| &bisect.step) {
+            tests += 1;
+            let broken = std::fs::read_to_string(path.join("zshrc")).unwrap().trim().parse::<u32>().unwrap() >= 3;
+            app.press(key(KeyCode::Char('b')));
+            app.press(key(KeyCode::Char(if broken { 'b' } else { 'g' })));
+        }
+        assert!(tests <= 3);
+        let Some(BisectStep::Found(first_bad)) = app.app.bisect.as_ref().map(|bisect| bisect.step.clone()) else {
+            panic!("{:?}", app.app.bisect);
+        };
+        let repo = git2::Repository::open(&path).unwrap();
+        let tree = repo.find_commit(first_bad).unwrap().tree().unwrap();
+        let blob = tree.get_name("zshrc").unwrap().to_object(&repo).unwrap().peel_to_blob().unwrap();
+        assert_eq!(blob.content(), b"3\n");
+        assert!(app.render().contains("The first bad commit is"));
+        assert_eq!(app.app.branch_status.branch.as_deref(), Some(FIXTURE_BRANCH));
+        assert!(!app.app.branch_status.detached);
+        assert_eq!(std::fs::read_to_string(path.join("zshrc")).unwrap(), "5\n");
+
+        app.press(key(KeyCode::Enter));
+        assert_eq!(app.app.popup, None);
+        assert_eq!(app.app.bisect, None);
+    }
+
+    #[test]
+    fn a_failed_bisect_checkout_keeps_the_previous_step() {
+        let mut fixture = RepoFixture::new();
+        for i in 0..4 {
+            fixture = fixture.committed("zshrc", &format!("{}\n", i));
+        }
+        let path = fixture.path().to_path_buf();
+        let mut app = TestApp::new(fixture);
+        app.dispatch(Action::SwitchMode(Mode::Log));
+        app.type_text("bb");
+        let before = app.app.bisect.clone();
+        assert_eq!(before.as_ref().map(|bisect| &bisect.step), Some(&BisectStep::Incomplete));
+
+        // A local change to the file stops the safe checkout.
+        std::fs::write(path.join("zshrc"), "local\n").unwrap();
+        app.type_text("Gbg");
+        assert_eq!(app.app.bisect, before);
+        assert!(!app.app.branch_status.detached);
+        assert!(app.app.notifications.history().next().unwrap().message.starts_with("Checkout failed"));
+
+        std::fs::write(path.join("zshrc"), "3\n").unwrap();
+        app.type_text("bg");
+        assert!(matches!(app.app.bisect.as_ref().map(|bisect| &bisect.step), Some(BisectStep::Test { .. })));
+        assert!(app.app.branch_status.detached);
+    }
+
+    #[test]
+    fn long_subjects_are_warned_about_with_the_conventional_prefix_counted() {
+        let fixture = RepoFixture::new().committed("a", "1\n").staged("a", "2\n");
+        let mut app = TestApp::new(fixture);
+        app.app.config.conventional_commits = true;
+        app.app.config.subject_limit = Some(20);
+        app.press(key(KeyCode::Char('c')));
+        app.press(key(KeyCode::Tab));
+        app.press(key(KeyCode::Tab));
+        app.type_text("update the zshrc");
+        assert_eq!(app.app.commit_warnings, ["Subject is 22 characters long (limit 20)"]);
+        app.press(ctrl('s'));
+        assert_eq!(app.app.popup, Some(Popup::Commit));
+        app.press(ctrl('s'));
+        assert_eq!(app.app.popup, None);
+        let repo = git2::Repository::open(app.fixture().path()).unwrap();
+        let head = repo.head().unwrap().peel_to_commit().unwrap();
+        assert_eq!(head.message(), Some("feat: update the zshrc\n"));
+    }
+
+    #[test]
+    fn stashing_takes_the_untracked_files_only_when_asked_and_listed() {
+        let fixture = RepoFixture::new().committed("a", "1\n").unstaged("a", "2\n").unstaged("new", "1\n");
+        let mut app = TestApp::new(fixture);
+        app.dispatch(Action::StashPush(StashFlags::DEFAULT));
+        assert!(app.fixture().path().join("new").exists());
+        app.app.repo.stash_pop(0).unwrap();
+
+        app.press(key(KeyCode::Char('Z')));
+        assert!(!app.fixture().path().join("new").exists());
+        assert_eq!(std::fs::read_to_string(app.fixture().path().join("a")).unwrap(), "1\n");
+        assert_eq!(app.app.repo.get_stashes().unwrap().len(), 1);
+        app.app.repo.stash_pop(0).unwrap();
+        assert!(app.fixture().path().join("new").exists());
+
+        let repo = git2::Repository::open(app.fixture().path()).unwrap();
+        repo.config().unwrap().set_str("status.showUntrackedFiles", "no").unwrap();
+        app.app.refresh().unwrap();
+        app.dispatch(Action::StashPush(StashFlags::INCLUDE_UNTRACKED));
+        assert!(app.fixture().path().join("new").exists());
+        assert!(app.app.repo.get_stashes().unwrap().is_empty());
+    }
 }
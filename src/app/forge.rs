@@ -0,0 +1,137 @@
+//! src/app/forge.rs
+
+use super::App;
+use crate::{
+    action::Action,
+    error::AppResult,
+    event::AppEvent,
+    forge::{self, Forge, ForgeStatus},
+    notification::Severity,
+};
+use log::{error, info};
+use ratatui::widgets::TableState;
+
+/// What the forge view shows.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ForgeView {
+    /// The integration cannot be used here; holds the reason.
+    Unavailable(String),
+    Loading,
+    Loaded(ForgeStatus),
+    Failed(String),
+}
+
+/// The forge view: the hosted project and the status loaded for HEAD.
+#[derive(Debug)]
+pub struct ForgeState {
+    /// The hosted project of `origin`, once the forge view has been opened.
+    pub project: Option<Forge>,
+    pub view: ForgeView,
+    pub table_state: TableState,
+}
+
+impl Default for ForgeState {
+    fn default() -> Self {
+        Self {
+            project: None,
+            view: ForgeView::Loading,
+            table_state: TableState::default(),
+        }
+    }
+}
+
+impl ForgeState {
+    /// Takes the status `App::load_forge` loaded in the background.
+    pub fn finish_loading(&mut self, result: AppResult<ForgeStatus>) {
+        self.view = match result {
+            Ok(status) => {
+                let selected = if status.pull_requests.is_empty() { None } else { Some(0) };
+                self.table_state.select(selected);
+                ForgeView::Loaded(status)
+            }
+            Err(e) => {
+                error!("Loading forge status failed: {}", e);
+                ForgeView::Failed(e.to_string())
+            }
+        };
+    }
+}
+
+impl App {
+    /// Looks up the forge behind `origin` and loads its status for HEAD in
+    /// the background.
+    pub(crate) fn load_forge(&mut self) {
+        let Some(config) = &self.config.forge else {
+            self.forge.view = ForgeView::Unavailable(
+                "Add a [forge] section to config.toml to enable this view.".to_string(),
+            );
+            return;
+        };
+        let Some(url) = self.repo.remote_url("origin") else {
+            self.forge.view = ForgeView::Unavailable("There is no 'origin' remote.".to_string());
+            return;
+        };
+        let Some(forge) = Forge::from_remote_url(&url, config.token.clone()) else {
+            self.forge.view =
+                ForgeView::Unavailable(format!("{} is not a GitHub or GitLab remote.", url));
+            return;
+        };
+        self.forge.project = Some(forge.clone());
+        let (Ok(Some(branch)), Ok(head)) = (self.repo.current_branch(), self.repo.head_commit_id())
+        else {
+            self.forge.view = ForgeView::Unavailable("HEAD is not on a branch.".to_string());
+            return;
+        };
+        info!("Loading {} status for {}", forge.name(), branch);
+        self.forge.view = ForgeView::Loading;
+        let sender = self.app_event_sender.clone();
+        tokio::task::spawn_blocking(move || {
+            let status = forge.status(&branch, &head.to_string());
+            let _ = sender.send(AppEvent::ForgeLoaded(status));
+        });
+    }
+
+    pub(crate) fn handle_forge_action(&mut self, action: Action) {
+        let pull_requests = match &self.forge.view {
+            ForgeView::Loaded(status) => status.pull_requests.as_slice(),
+            _ => &[],
+        };
+        let url = match action {
+            Action::SelectNext | Action::SelectPrev => {
+                if !pull_requests.is_empty() {
+                    let last = pull_requests.len() - 1;
+                    let i = match self.forge.table_state.selected() {
+                        Some(i) if matches!(action, Action::SelectNext) => if i >= last { 0 } else { i + 1 },
+                        Some(i) => if i == 0 { last } else { i - 1 },
+                        None => 0,
+                    };
+                    self.forge.table_state.select(Some(i));
+                }
+                None
+            }
+            Action::Confirm => self
+                .forge
+                .table_state
+                .selected()
+                .and_then(|i| pull_requests.get(i))
+                .map(|pr| pr.url.clone()),
+            Action::ForgeOpenRepo => self.forge.project.as_ref().map(Forge::repo_url),
+            Action::ForgeOpenCommit => {
+                let head = self.repo.head_commit_id().ok();
+                self.forge
+                    .project
+                    .as_ref()
+                    .zip(head)
+                    .map(|(forge, head)| forge.commit_url(&head.to_string()))
+            }
+            _ => None,
+        };
+        if let Some(url) = url {
+            info!("Opening {} in the browser", url);
+            if let Err(e) = forge::open_in_browser(&url) {
+                error!("{}", e);
+                self.notify(Severity::Error, e.to_string());
+            }
+        }
+    }
+}
@@ -0,0 +1,122 @@
+//! src/app/macros.rs
+
+use super::{App, AppReturn, Popup};
+use crate::{action::key_label, error::AppResult, notification::Severity};
+use crossterm::event::KeyEvent;
+use log::{info, warn};
+
+/// The keys recorded with `Q` and typed again with `@@`.
+#[derive(Debug, Default)]
+pub struct MacroState {
+    /// The keys typed since `Q` started recording a macro; `None` when not
+    /// recording.
+    pub recording: Option<Vec<KeyEvent>>,
+    /// The last recorded macro, which `@@` types again. Saved with the
+    /// session.
+    pub keys: Vec<KeyEvent>,
+}
+
+impl MacroState {
+    /// Adds `key` to the macro being recorded, if any.
+    pub fn record(&mut self, key: KeyEvent) {
+        if let Some(keys) = &mut self.recording {
+            keys.push(key);
+        }
+    }
+
+    /// Takes the last `count` keys back out of the macro being recorded.
+    pub fn unrecord(&mut self, count: usize) {
+        if let Some(keys) = &mut self.recording {
+            keys.truncate(keys.len().saturating_sub(count));
+        }
+    }
+}
+
+impl App {
+    /// Whether `Q` is recording a macro, for the status bar.
+    pub fn is_recording_macro(&self) -> bool {
+        self.macros.recording.is_some()
+    }
+
+    pub(super) fn toggle_macro_recording(&mut self) {
+        let record = key_label(self.keys.global.record_macro);
+        match self.macros.recording.take() {
+            None => {
+                self.macros.recording = Some(Vec::new());
+                self.notify(Severity::Info, format!("Recording a macro; {} stops", record));
+            }
+            Some(keys) if keys.is_empty() => {
+                self.notify(Severity::Warning, "Nothing recorded; the previous macro is kept".to_string());
+            }
+            Some(keys) => {
+                info!("Recorded a macro of {} key(s)", keys.len());
+                let message = match keys.len() {
+                    1 => format!("Recorded a macro of {}", key_label(keys[0])),
+                    count => format!("Recorded a macro of {} keys", count),
+                };
+                self.notify(Severity::Success, message);
+                self.macros.keys = keys;
+            }
+        }
+    }
+
+    /// Types the keys of the last macro again, stopping early when one of
+    /// them ends in an error.
+    pub(super) fn replay_macro(&mut self) -> AppResult<AppReturn> {
+        if self.macros.keys.is_empty() {
+            let record = key_label(self.keys.global.record_macro);
+            self.notify(Severity::Warning, format!("No macro recorded yet; {} records one", record));
+            return Ok(AppReturn::Continue);
+        }
+        self.pending_keys.clear();
+        for key in self.macros.keys.clone() {
+            if let Some(action) = self.action_for_key(key) {
+                if self.dispatch(action)? == AppReturn::Exit {
+                    return Ok(AppReturn::Exit);
+                }
+            }
+            if matches!(self.popup, Some(Popup::Error { .. })) {
+                warn!("Macro stopped at an error");
+                break;
+            }
+        }
+        Ok(AppReturn::Continue)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{key, RepoFixture, TestApp};
+    use crossterm::event::KeyCode;
+
+    fn staged_paths(app: &App) -> Vec<&str> {
+        app.status_items.iter().filter(|item| item.is_staged).map(|item| item.path.as_str()).collect()
+    }
+
+    #[test]
+    fn macros_replay_the_recorded_keys_and_are_saved() {
+        let fixture = RepoFixture::new()
+            .committed("a", "1\n")
+            .unstaged("a", "2\n")
+            .unstaged("b", "3\n")
+            .unstaged("c", "4\n");
+        let mut app = TestApp::new(fixture);
+        app.type_text("@@");
+        assert!(app.app.notifications.history().next().unwrap().message.starts_with("No macro recorded yet"));
+
+        app.type_text("Q");
+        assert!(app.app.is_recording_macro());
+        assert!(app.render().contains("recording macro"));
+        app.press(key(KeyCode::Char(' ')));
+        app.type_text("Q");
+        assert_eq!(app.app.macros.keys, [key(KeyCode::Char(' '))]);
+        assert_eq!(staged_paths(&app.app), ["a"]);
+
+        app.type_text("G@@");
+        assert_eq!(staged_paths(&app.app), ["a", "c"]);
+
+        let app = app.relaunch();
+        assert_eq!(app.app.macros.keys, [key(KeyCode::Char(' '))]);
+    }
+}
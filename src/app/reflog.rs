@@ -0,0 +1,107 @@
+//! src/app/reflog.rs
+
+use super::{App, ConfirmAction, RiskyOperation};
+use crate::{action::Action, error::AppResult, git::ReflogEntry};
+use log::info;
+use ratatui::widgets::TableState;
+
+/// The entries of the reflog view.
+#[derive(Debug, Default)]
+pub struct ReflogState {
+    pub entries: Vec<ReflogEntry>,
+    pub table_state: TableState,
+}
+
+impl ReflogState {
+    /// Takes the entries of a refresh, keeping the selection in range.
+    pub fn set_entries(&mut self, entries: Vec<ReflogEntry>) {
+        self.entries = entries;
+        if self.entries.is_empty() {
+            self.table_state.select(None);
+        } else {
+            let last = self.entries.len() - 1;
+            let selected = self.table_state.selected().map_or(0, |i| i.min(last));
+            self.table_state.select(Some(selected));
+        }
+    }
+
+    fn select_next(&mut self) {
+        if self.entries.is_empty() { return; }
+        let i = self.table_state.selected().map_or(0, |i| (i + 1) % self.entries.len());
+        self.table_state.select(Some(i));
+    }
+
+    fn select_previous(&mut self) {
+        if self.entries.is_empty() { return; }
+        let i = self.table_state.selected().map_or(0, |i| {
+            if i == 0 { self.entries.len() - 1 } else { i - 1 }
+        });
+        self.table_state.select(Some(i));
+    }
+}
+
+impl App {
+    pub(super) fn handle_reflog_action(&mut self, action: Action) -> AppResult<()> {
+        match action {
+            Action::SelectNext => self.reflog.select_next(),
+            Action::SelectPrev => self.reflog.select_previous(),
+            Action::ReflogCheckout | Action::ReflogReset => {
+                if self.blocked_by_read_only("reflog operation") {
+                    return Ok(());
+                }
+                let Some(index) = self.reflog.table_state.selected() else {
+                    return Ok(());
+                };
+                let Some(entry) = self.reflog.entries.get(index).cloned() else {
+                    return Ok(());
+                };
+                if let Action::ReflogCheckout = action {
+                    info!("Checking out HEAD@{{{}}} ({})", entry.index, entry.new_oid);
+                    self.start_risky_operation(RiskyOperation::CheckoutCommit(entry.new_oid))?;
+                } else {
+                    self.confirm(
+                        "Confirm Reset",
+                        format!(
+                            "Reset to HEAD@{{{}}} ({:.7})? Uncommitted changes will be lost.",
+                            entry.index,
+                            entry.new_oid.to_string()
+                        ),
+                        ConfirmAction::ReflogReset(entry.new_oid),
+                    );
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{app::{Mode, Popup}, action::Action, testing::{key, RepoFixture, TestApp}};
+    use crossterm::event::KeyCode;
+
+    #[test]
+    fn a_confirmed_reflog_reset_goes_to_the_commit_it_named() {
+        let fixture = RepoFixture::new().committed("a", "1\n").committed("a", "2\n").committed("a", "3\n");
+        let mut app = TestApp::new(fixture);
+        app.dispatch(Action::SwitchMode(Mode::Reflog));
+        app.press(key(KeyCode::Char('j')));
+        let target = app.app.reflog.entries[1].new_oid;
+        app.press(key(KeyCode::Char('x')));
+        assert!(matches!(app.app.popup, Some(Popup::Confirm { .. })));
+
+        // A commit made meanwhile shifts the reflog by one entry.
+        let repo = git2::Repository::open(app.fixture().path()).unwrap();
+        let head = repo.head().unwrap().peel_to_commit().unwrap();
+        let signature = repo.signature().unwrap();
+        repo.commit(Some("HEAD"), &signature, &signature, "elsewhere", &head.tree().unwrap(), &[&head])
+            .unwrap();
+        app.dispatch(Action::Refresh);
+        assert_ne!(app.app.reflog.entries[1].new_oid, target);
+
+        app.press(key(KeyCode::Char('y')));
+        assert_eq!(repo.head().unwrap().target(), Some(target));
+    }
+}
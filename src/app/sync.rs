@@ -0,0 +1,265 @@
+//! src/app/sync.rs
+
+use super::{encrypt_work_tree_secrets, App};
+use crate::{
+    error::AppResult,
+    event::AppEvent,
+    git::{credentials::NoPrompt, GitRepo, PushMode},
+    notification::Severity,
+    scripting::ScriptEvent,
+    task::CancelToken,
+};
+use chrono::{DateTime, Local};
+use log::{debug, info, warn};
+use std::time::{Duration, Instant};
+
+/// Seconds the work tree has to stay quiet before sync mode commits, when
+/// `sync_delay` is not configured.
+const DEFAULT_SYNC_DELAY: u64 = 5;
+
+/// Sync mode, which commits and pushes the changes to the work tree on its
+/// own while the app is open.
+#[derive(Debug, Default)]
+pub struct SyncMode {
+    /// Set by the `sync` option or `ToggleSync`; turning it off pauses it.
+    pub enabled: bool,
+    /// When the work tree last changed, if it changed since the last sync.
+    pub changed: Option<Instant>,
+    pub pushing: bool,
+    /// A sync commit made while the last one was being pushed.
+    push_again: bool,
+    /// Why the last sync failed, until one succeeds.
+    pub error: Option<String>,
+    pub last_synced: Option<DateTime<Local>>,
+    /// The generation of the last sync commit started in the background,
+    /// and of the last one answered.
+    commit_requested: u64,
+    commit_loaded: u64,
+}
+
+impl SyncMode {
+    pub fn new(enabled: bool) -> Self {
+        Self {
+            enabled,
+            // Changes made while the app was closed are synced too.
+            changed: enabled.then(Instant::now),
+            ..Self::default()
+        }
+    }
+
+    /// Whether a sync commit is running in the background.
+    pub fn is_committing(&self) -> bool {
+        self.commit_requested != self.commit_loaded
+    }
+
+    /// Drops the answer of the sync commit running in the background, e.g.
+    /// one of the repository before a switch.
+    pub fn forget_commit(&mut self) {
+        self.commit_loaded = self.commit_requested;
+    }
+}
+
+impl App {
+    /// Turns sync mode on, or pauses it.
+    pub(super) fn toggle_sync(&mut self) {
+        if self.sync.enabled {
+            self.sync.enabled = false;
+            self.sync.changed = None;
+            self.notify(Severity::Info, "Sync paused".to_string());
+            return;
+        }
+        if self.blocked_by_read_only("sync") {
+            return;
+        }
+        self.sync.enabled = true;
+        self.sync.changed = Some(Instant::now());
+        let delay = self.config.sync_delay.unwrap_or(DEFAULT_SYNC_DELAY);
+        self.notify(
+            Severity::Info,
+            format!("Sync on: changes are committed and pushed after {}s without changes", delay),
+        );
+    }
+
+    /// Commits the changes of the tracked files once the work tree has been
+    /// quiet for `sync_delay` seconds, and pushes the commit in the
+    /// background. Untracked files are only committed with `sync_untracked`
+    /// set. The commit hooks run before the commit like for any other. Waits
+    /// while a popup is open, so that e.g. a commit being written is not
+    /// overtaken.
+    pub(super) fn sync_if_due(&mut self) -> AppResult<()> {
+        let Some(changed) = self.sync.changed else {
+            return Ok(());
+        };
+        let delay = Duration::from_secs(self.config.sync_delay.unwrap_or(DEFAULT_SYNC_DELAY));
+        if !self.sync.enabled || changed.elapsed() < delay || self.popup.is_some() || self.sync.is_committing() {
+            return Ok(());
+        }
+        self.sync.changed = None;
+        let message = format!("sync: {}", Local::now().format("%Y-%m-%d %H:%M:%S"));
+        let untracked = self
+            .config
+            .sync_untracked
+            .then(|| self.config.untracked_files(&self.expanded_untracked_dirs));
+        let sign = self.repo.signing_enabled();
+        let encryption = self.config.encryption.clone();
+        self.sync.commit_requested += 1;
+        let generation = self.sync.commit_requested;
+        debug!("Committing sync #{} in the background.", generation);
+        let location = self.repo.location();
+        let sender = self.app_event_sender.clone();
+        // Hooks and encryption may take a while, so they stay off the UI
+        // thread along with the commit.
+        tokio::task::spawn_blocking(move || {
+            let result = GitRepo::open(&location).and_then(|repo| {
+                if let Some(encryption) = &encryption {
+                    encrypt_work_tree_secrets(encryption, repo.path(), &repo.unstaged_paths(untracked.as_ref())?)?;
+                }
+                repo.commit_all(&message, sign, untracked.as_ref(), |line| debug!("Sync hook: {}", line))
+            });
+            let _ = sender.send(AppEvent::SyncCommitted(generation, result));
+        });
+        Ok(())
+    }
+
+    /// Takes the answer to the sync commit `sync_if_due` started, pushing
+    /// the commit when there was anything to commit.
+    pub(super) fn finish_sync_commit(&mut self, generation: u64, committed: AppResult<Option<git2::Oid>>) -> AppResult<()> {
+        // An older sync, or one of the repository before `switch_repo`.
+        if generation != self.sync.commit_requested || !self.sync.is_committing() {
+            return Ok(());
+        }
+        self.sync.commit_loaded = generation;
+        match committed {
+            Ok(None) => return Ok(()),
+            Ok(Some(commit)) => {
+                info!("Synced the work tree as {}", commit);
+                self.sync.error = None;
+                self.sync.last_synced = Some(Local::now());
+                self.emit_script_event(ScriptEvent::PostCommit, Some(&commit.to_string()))?;
+                self.push_synced();
+            }
+            Err(e) => {
+                warn!("Sync failed: {}", e);
+                if self.sync.error.is_none() {
+                    self.notify(Severity::Warning, format!("Sync failed: {}", e));
+                }
+                self.sync.error = Some(e.to_string());
+            }
+        }
+        self.refresh()
+    }
+
+    /// Pushes a sync commit quietly like `fetch_quietly`. Without an
+    /// upstream the commits stay local.
+    fn push_synced(&mut self) {
+        if !self.repo.has_upstream().unwrap_or(false) {
+            return;
+        }
+        if self.sync.pushing {
+            self.sync.push_again = true;
+            return;
+        }
+        debug!("Pushing the sync commit in the background.");
+        self.sync.pushing = true;
+        let location = self.repo.location();
+        let sender = self.app_event_sender.clone();
+        tokio::task::spawn_blocking(move || {
+            let result = GitRepo::open(&location)
+                .and_then(|repo| repo.push(&mut NoPrompt, PushMode::Normal, &CancelToken::new(), |_, _, _| {}));
+            let _ = sender.send(AppEvent::SyncPushFinished(result));
+        });
+    }
+
+    /// Takes the answer to the push `push_synced` started, pushing again
+    /// when a sync commit was made meanwhile.
+    pub(super) fn finish_sync_push(&mut self, result: AppResult<()>) -> AppResult<()> {
+        self.sync.pushing = false;
+        match result {
+            Ok(()) => {
+                debug!("Pushed the sync commit.");
+                if std::mem::take(&mut self.sync.push_again) {
+                    self.push_synced();
+                }
+                self.refresh()?;
+            }
+            Err(e) => {
+                warn!("Pushing the sync commit failed: {}", e);
+                if self.sync.error.is_none() {
+                    self.notify(Severity::Warning, format!("Sync push failed: {}", e));
+                }
+                self.sync.error = Some(e.to_string());
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{action::Action, testing::{ctrl, RepoFixture, TestApp, FIXTURE_BRANCH}};
+
+    #[test]
+    fn sync_mode_commits_and_pushes_quiet_changes_until_paused() {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let _guard = runtime.enter();
+        let upstream = RepoFixture::new().committed(".zshrc", "1\n");
+        let local = RepoFixture::new().tracking(&upstream);
+        // Only bare repositories take pushes.
+        let bare_dir = tempfile::TempDir::new().unwrap();
+        let bare = git2::build::RepoBuilder::new()
+            .bare(true)
+            .clone(upstream.path().to_str().unwrap(), bare_dir.path())
+            .unwrap();
+        git2::Repository::open(local.path())
+            .unwrap()
+            .remote_set_url("origin", bare_dir.path().to_str().unwrap())
+            .unwrap();
+        let mut app = TestApp::new(local);
+        app.press(ctrl('y'));
+        assert!(app.app.sync.enabled);
+        std::fs::write(app.fixture().path().join(".zshrc"), "2\n").unwrap();
+        std::fs::write(app.fixture().path().join(".vimrc"), "set number\n").unwrap();
+        app.app.sync.changed = Some(Instant::now());
+        app.dispatch(Action::Tick);
+        let screen = app.render();
+        assert!(screen.contains("sync pending"), "{}", screen);
+
+        app.app.config.sync_delay = Some(0);
+        app.app.dispatch(Action::Tick).unwrap();
+        // The commit runs in the background; the next tick waits for it.
+        assert!(app.app.sync.is_committing());
+        app.dispatch(Action::Tick);
+        while app.app.sync.pushing {
+            std::thread::sleep(Duration::from_millis(10));
+            app.process_events();
+        }
+        assert_eq!(app.app.sync.error, None);
+        let local = git2::Repository::open(app.fixture().path()).unwrap();
+        let head = local.head().unwrap().peel_to_commit().unwrap();
+        assert!(head.message().unwrap().starts_with("sync: "), "{:?}", head.message());
+        // The untracked file is left alone.
+        assert!(head.tree().unwrap().get_name(".vimrc").is_none());
+        let paths: Vec<&str> = app.app.status_items.iter().map(|item| item.path.as_str()).collect();
+        assert_eq!(paths, vec![".vimrc"]);
+        let pushed = bare.find_reference(&format!("refs/heads/{}", FIXTURE_BRANCH)).unwrap().target();
+        assert_eq!(pushed, Some(head.id()));
+
+        app.app.config.sync_untracked = true;
+        app.app.sync.changed = Some(Instant::now());
+        app.dispatch(Action::Tick);
+        while app.app.sync.pushing {
+            std::thread::sleep(Duration::from_millis(10));
+            app.process_events();
+        }
+        let head = local.head().unwrap().peel_to_commit().unwrap();
+        assert!(head.tree().unwrap().get_name(".vimrc").is_some());
+        assert!(app.app.status_items.is_empty());
+
+        app.press(ctrl('y'));
+        std::fs::write(app.fixture().path().join(".zshrc"), "3\n").unwrap();
+        app.dispatch(Action::Tick);
+        assert_eq!(local.head().unwrap().target(), Some(head.id()));
+        assert!(!app.render().contains("| sync"));
+    }
+}
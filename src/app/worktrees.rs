@@ -0,0 +1,139 @@
+//! src/app/worktrees.rs
+
+use super::{App, ConfirmAction, Popup};
+use crate::{
+    error::AppResult,
+    git::{GitRepo, WorktreeInfo},
+    notification::Severity,
+    prompt::PromptState,
+};
+use crossterm::event::KeyEvent;
+use log::{error, info};
+use ratatui::widgets::ListState;
+use std::path::Path;
+
+/// The worktree switcher and the path typed for a new worktree.
+#[derive(Debug, Default)]
+pub struct WorktreeState {
+    pub list: Vec<WorktreeInfo>,
+    pub list_state: ListState,
+    pub input: PromptState,
+}
+
+impl App {
+    pub(super) fn open_worktrees(&mut self) -> AppResult<()> {
+        self.reload_worktrees()?;
+        let current = self.worktrees.list.iter().position(|w| w.is_current);
+        self.worktrees.list_state.select(current.or(Some(0)));
+        self.popup = Some(Popup::Worktrees);
+        Ok(())
+    }
+
+    fn reload_worktrees(&mut self) -> AppResult<()> {
+        self.worktrees.list = self.repo.worktrees()?;
+        let last = self.worktrees.list.len().saturating_sub(1);
+        let selected = self.worktrees.list_state.selected().map(|i| i.min(last));
+        self.worktrees.list_state.select(selected);
+        Ok(())
+    }
+
+    pub(super) fn handle_worktree_keys(&mut self, key: KeyEvent) -> AppResult<()> {
+        if key == self.keys.popup.select_next || key == self.keys.popup.select_prev {
+            let last = self.worktrees.list.len().saturating_sub(1);
+            let i = match self.worktrees.list_state.selected() {
+                Some(i) if key == self.keys.popup.select_next => if i >= last { 0 } else { i + 1 },
+                Some(i) => if i == 0 { last } else { i - 1 },
+                None => 0,
+            };
+            self.worktrees.list_state.select(Some(i));
+        } else if key == self.keys.popup.confirm {
+            let Some(worktree) = self.worktrees.list_state.selected().and_then(|i| self.worktrees.list.get(i)) else {
+                return Ok(());
+            };
+            if worktree.is_current {
+                self.popup = None;
+            } else if !worktree.valid {
+                self.notify(Severity::Warning, format!("{} is missing", worktree.path.display()));
+            } else {
+                let location = worktree.location.clone();
+                self.popup = None;
+                match GitRepo::open(&location) {
+                    Ok(repo) => self.switch_repo(repo)?,
+                    Err(e) => self.notify(Severity::Error, format!("Could not open worktree: {}", e)),
+                }
+            }
+        } else if key == self.keys.popup.worktree_add {
+            if !self.blocked_by_read_only("add worktree") {
+                self.worktrees.input.clear();
+                self.popup = Some(Popup::WorktreeAdd);
+            }
+        } else if key == self.keys.popup.worktree_remove {
+            if let Some(index) = self.worktrees.list_state.selected() {
+                match self.worktrees.list.get(index).cloned() {
+                    Some(worktree) if worktree.name.is_none() => {
+                        self.notify(Severity::Warning, "The main work tree cannot be removed".to_string())
+                    }
+                    Some(worktree) if !self.blocked_by_read_only("remove worktree") => {
+                        let message = format!(
+                            "Remove the worktree at {} and delete the directory?",
+                            worktree.path.display()
+                        );
+                        let name = worktree.name.unwrap_or_default();
+                        self.confirm("Remove Worktree", message, ConfirmAction::RemoveWorktree(name))
+                    }
+                    _ => {}
+                }
+            }
+        } else if key == self.keys.popup.close_popup {
+            self.popup = None;
+        }
+        Ok(())
+    }
+
+    /// Adds the worktree typed into `Popup::WorktreeAdd`, going back to the
+    /// list either way.
+    pub(super) fn handle_worktree_add_keys(&mut self, key: KeyEvent) -> AppResult<()> {
+        if key == self.keys.popup.confirm {
+            let path = self.worktrees.input.submit().unwrap_or_default();
+            self.popup = Some(Popup::Worktrees);
+            if !path.trim().is_empty() {
+                self.add_worktree(Path::new(path.trim()))?;
+            }
+        } else if key == self.keys.popup.close_popup {
+            self.worktrees.input.clear();
+            self.popup = Some(Popup::Worktrees);
+        } else {
+            self.worktrees.input.handle_key(key);
+        }
+        Ok(())
+    }
+
+    fn add_worktree(&mut self, path: &Path) -> AppResult<()> {
+        info!("Adding worktree at {}", path.display());
+        match self.repo.add_worktree(path) {
+            Ok(()) => self.notify(Severity::Success, format!("Added worktree {}", path.display())),
+            Err(e) => {
+                error!("Adding worktree {} failed: {}", path.display(), e);
+                self.notify(Severity::Error, format!("Could not add worktree: {}", e));
+            }
+        }
+        self.reload_worktrees()?;
+        // Select the new worktree so that Enter switches to it.
+        if let Some(index) = self.worktrees.list.iter().rposition(|w| w.name.is_some()) {
+            self.worktrees.list_state.select(Some(index));
+        }
+        self.refresh()
+    }
+
+    pub(super) fn remove_worktree(&mut self, name: String) -> AppResult<()> {
+        info!("Removing worktree {}", name);
+        match self.repo.remove_worktree(&name) {
+            Ok(()) => self.notify(Severity::Success, format!("Removed worktree {}", name)),
+            Err(e) => {
+                error!("Removing worktree {} failed: {}", name, e);
+                self.notify(Severity::Error, format!("Could not remove worktree: {}", e));
+            }
+        }
+        self.reload_worktrees()
+    }
+}
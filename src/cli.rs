@@ -0,0 +1,238 @@
+//! src/cli.rs
+
+use crate::{
+    config::Config,
+    dotfiles::{
+        apply::{self, ApplyOutcome},
+        expand_home, secrets, Manifest,
+    },
+    error::{AppError, AppResult},
+    git::{credentials::NoPrompt, GitRepo, PushMode},
+    logging::default_log_path,
+    task::CancelToken,
+    ui::status_to_prefix_and_color,
+};
+use clap::{Parser, Subcommand};
+use log::LevelFilter;
+use std::{env, path::PathBuf};
+
+/// A nimble Git TUI for managing dotfiles.
+#[derive(Debug, Parser)]
+#[command(name = "dotatui", version, about)]
+pub struct Cli {
+    /// Directory inside the repository to open [default: current directory]
+    pub path: Option<PathBuf>,
+
+    /// Path to the git directory, e.g. a bare dotfiles repo [env: GIT_DIR]
+    #[arg(long, global = true, value_name = "DIR")]
+    pub git_dir: Option<PathBuf>,
+
+    /// Path to the work tree of the repository [env: GIT_WORK_TREE]
+    #[arg(long, global = true, value_name = "DIR")]
+    pub work_tree: Option<PathBuf>,
+
+    /// Config file to use instead of the default location
+    #[arg(long, global = true, value_name = "FILE")]
+    pub config: Option<PathBuf>,
+
+    /// Disable every action that modifies the repository
+    #[arg(long, global = true)]
+    pub read_only: bool,
+
+    /// Log verbosity: off, error, warn, info, debug or trace
+    #[arg(long, global = true, value_name = "LEVEL")]
+    pub log_level: Option<LevelFilter>,
+
+    /// Log file to write instead of the default in the cache directory
+    #[arg(long, global = true, value_name = "FILE")]
+    pub log_file: Option<PathBuf>,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+/// Subcommands that print their result to stdout instead of starting the TUI.
+#[derive(Debug, Clone, PartialEq, Eq, Subcommand)]
+pub enum Command {
+    /// Show staged and unstaged changes
+    Status,
+    /// Show the commit history
+    Log {
+        /// Limit the number of commits shown
+        #[arg(short = 'n', long, value_name = "N")]
+        max_count: Option<usize>,
+    },
+    /// Clone a repository and open it
+    Clone {
+        /// URL of the repository to clone
+        url: String,
+        /// Directory to clone into [default: the repository name]
+        path: Option<PathBuf>,
+        /// Fetch only the newest N commits of each branch
+        #[arg(long, value_name = "N", value_parser = clap::value_parser!(u32).range(1..))]
+        depth: Option<u32>,
+        /// Check out BRANCH instead of the remote's default branch
+        #[arg(short = 'b', long, value_name = "BRANCH")]
+        branch: Option<String>,
+        /// Fetch only one branch: --branch, or the default branch
+        #[arg(long)]
+        single_branch: bool,
+    },
+    /// Push the current branch to its upstream (or origin)
+    Push {
+        /// Overwrite the remote branch if it has not changed since the last fetch
+        #[arg(long)]
+        force_with_lease: bool,
+        /// Make the pushed branch the upstream of the current branch
+        #[arg(short = 'u', long)]
+        set_upstream: bool,
+    },
+    /// Create every link of dotatui.toml, backing up the files in the way
+    Apply,
+}
+
+impl Cli {
+    /// Opens the repository selected by `--git-dir`/`--work-tree` (or their
+    /// environment variables), discovering it from `path` otherwise.
+    pub fn open_repo(&self) -> AppResult<GitRepo> {
+        let git_dir = self
+            .git_dir
+            .clone()
+            .or_else(|| env::var_os("GIT_DIR").map(PathBuf::from));
+        let work_tree = self
+            .work_tree
+            .clone()
+            .or_else(|| env::var_os("GIT_WORK_TREE").map(PathBuf::from));
+        match git_dir {
+            Some(git_dir) => GitRepo::open_with_work_tree(&git_dir, work_tree.as_deref()),
+            None => match &self.path {
+                Some(path) => GitRepo::new(path),
+                None => GitRepo::new(env::current_dir()?),
+            },
+        }
+    }
+
+    /// The effective log level: the flag wins over the config file, and
+    /// debug logging is the default.
+    pub fn log_level(&self, config: &Config) -> AppResult<LevelFilter> {
+        if let Some(level) = self.log_level {
+            return Ok(level);
+        }
+        match &config.log_level {
+            Some(level) => level
+                .parse()
+                .map_err(|_| AppError::Config(format!("unknown log level '{}'", level))),
+            None => Ok(LevelFilter::Debug),
+        }
+    }
+
+    /// `--log-file`, else `log_file` from the config, else the default in
+    /// the cache directory.
+    pub fn log_file(&self, config: &Config) -> PathBuf {
+        self.log_file
+            .clone()
+            .or_else(|| config.log_file.as_deref().map(|path| expand_home(&path.to_string_lossy())))
+            .unwrap_or_else(default_log_path)
+    }
+}
+
+/// Runs a non-interactive subcommand, printing its result to stdout.
+/// `clone` is refused: it makes its own repository, so it runs before one
+/// is opened (see `setup::clone_repo`).
+pub fn run_command(command: &Command, repo: &GitRepo, config: &Config) -> AppResult<()> {
+    match command {
+        Command::Status => print_status(repo, config),
+        Command::Log { max_count } => print_log(repo, *max_count),
+        Command::Clone { .. } => Err(AppError::CloneFailed(
+            "clone creates a repository and cannot run in an open one".to_string(),
+        )),
+        Command::Push {
+            force_with_lease,
+            set_upstream,
+        } => {
+            if config.read_only {
+                return Err(AppError::ReadOnly);
+            }
+            let mode = if *force_with_lease {
+                PushMode::ForceWithLease
+            } else {
+                PushMode::Normal
+            };
+            repo.push(&mut NoPrompt, mode, &CancelToken::new(), |_, _, _| {})?;
+            if *set_upstream {
+                repo.set_upstream("origin")?;
+            }
+            println!("Push successful!");
+            Ok(())
+        }
+        Command::Apply => {
+            if config.read_only {
+                return Err(AppError::ReadOnly);
+            }
+            apply_links(repo, config)
+        }
+    }
+}
+
+/// Applies the links of the active profile and prints a line per link.
+/// Secrets need `identity` or a gpg agent, as nothing is asked for.
+fn apply_links(repo: &GitRepo, config: &Config) -> AppResult<()> {
+    let manifest = Manifest::load(repo.path())?;
+    let selected = repo.selected_profile();
+    let mut entries = manifest.resolve(repo.path(), manifest.active_profile(selected.as_deref()));
+    secrets::mark_secrets(config.encryption.as_ref(), &mut entries);
+    let backup_dir = apply::backup_dir(config.backup_dir.as_deref());
+    let results = apply::apply(&entries, &backup_dir, |entry| match &config.encryption {
+        Some(encryption) => secrets::decrypt(encryption, &entry.source, None),
+        None => Err(AppError::Secret("no [encryption] settings".to_string())),
+    });
+    for result in &results {
+        println!(
+            "{:<10} {} {}",
+            result.outcome.label(),
+            result.target.display(),
+            result.outcome.detail()
+        );
+    }
+    let failed = results
+        .iter()
+        .filter(|result| matches!(result.outcome, ApplyOutcome::Failed(_)))
+        .count();
+    if failed > 0 {
+        return Err(AppError::Link(format!("{} of {} links failed", failed, results.len())));
+    }
+    Ok(())
+}
+
+fn print_status(repo: &GitRepo, config: &Config) -> AppResult<()> {
+    let (staged, unstaged): (Vec<_>, Vec<_>) = repo
+        .get_status(&config.untracked_files(&[]))?
+        .into_iter()
+        .partition(|i| i.is_staged);
+    if staged.is_empty() && unstaged.is_empty() {
+        println!("Nothing to commit, working tree clean");
+        return Ok(());
+    }
+    for (header, items) in [("Staged changes:", staged), ("Unstaged changes:", unstaged)] {
+        if items.is_empty() {
+            continue;
+        }
+        println!("{}", header);
+        for item in items {
+            let (prefix, _) = status_to_prefix_and_color(item.status);
+            println!("  {}{}", prefix, item.path);
+        }
+    }
+    Ok(())
+}
+
+fn print_log(repo: &GitRepo, max_count: Option<usize>) -> AppResult<()> {
+    let commits = repo.get_log(0, max_count.unwrap_or(usize::MAX))?;
+    for commit in &commits {
+        println!(
+            "{} {} {:<15} {}",
+            commit.id, commit.time, commit.author, commit.message
+        );
+    }
+    Ok(())
+}
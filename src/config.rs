@@ -1,44 +1,767 @@
 //! src/config.rs
 
+use crate::action::{key_label, parse_key};
+use crate::error::{AppError, AppResult};
+use crate::git::UntrackedFiles;
+use crate::session::RecentRepo;
 use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use ratatui::style::Color;
+use serde::Deserialize;
+use std::{
+    collections::BTreeMap,
+    env, fs,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
 
-/// Represents the keybindings for the application.
-pub struct KeyBindings {
-    pub quit: KeyEvent,
-    pub show_help: KeyEvent,
-    pub status_mode: KeyEvent,
-    pub log_mode: KeyEvent,
-    pub select_next: KeyEvent,
-    pub select_prev: KeyEvent,
-    pub stage_item: KeyEvent,
-    pub unstage_item: KeyEvent,
-    pub commit: KeyEvent,
-    pub push: KeyEvent,
-    pub confirm: KeyEvent,
-    pub close_popup: KeyEvent,
-    // --- New V2 Keybindings ---
-    pub panel_right: KeyEvent,
-    pub panel_left: KeyEvent,
-}
-
-impl Default for KeyBindings {
+/// Longest commit subject, used when `subject_limit` is not configured.
+/// Longer subjects are cut off by most tools that show them.
+pub const DEFAULT_SUBJECT_LIMIT: usize = 72;
+
+/// User settings read from `config.toml`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Disables every action that modifies the repository.
+    pub read_only: bool,
+    /// Log verbosity (`off`, `error`, `warn`, `info`, `debug`, `trace`).
+    pub log_level: Option<String>,
+    /// Where the log is written (default
+    /// `$XDG_CACHE_HOME/dotatui/dotatui.log`).
+    pub log_file: Option<PathBuf>,
+    /// Leaves the mouse to the terminal, so that its text selection works,
+    /// instead of clicking and scrolling in the TUI.
+    pub disable_mouse: bool,
+    /// Leaves untracked files out of the status view.
+    pub hide_untracked: bool,
+    /// Lists an untracked directory as one entry, like `git status`, instead
+    /// of every file in it. Enter on the entry lists its files.
+    pub collapse_untracked_dirs: bool,
+    /// Where applying the links moves the files in their way, in a
+    /// directory per run (default `$XDG_STATE_HOME/dotatui/backups`).
+    pub backup_dir: Option<PathBuf>,
+    /// Stages `.gitignore` after a file is added to it from the status view.
+    pub stage_gitignore: bool,
+    /// Milliseconds between the ticks that turn spinners and expire toasts
+    /// (default 100). The screen is only drawn when something changed.
+    /// Takes a restart.
+    pub tick_interval_ms: Option<u64>,
+    /// Fetches the upstream in the background every this many seconds, so
+    /// that the ahead/behind counts stay current. Off when unset.
+    pub auto_fetch_interval: Option<u64>,
+    /// Starts in sync mode: changes to the work tree are committed with a
+    /// timestamped message and pushed in the background. `ToggleSync`
+    /// pauses it.
+    pub sync: bool,
+    /// Seconds the work tree has to stay unchanged before sync mode commits
+    /// (default 5).
+    pub sync_delay: Option<u64>,
+    /// Lets sync mode commit untracked files as well, as far as the status
+    /// view lists them. Off by default: in a `$HOME` work tree these are
+    /// every other file of the home directory, `~/.ssh` included.
+    pub sync_untracked: bool,
+    /// Opens the commit popup with the conventional-commit helper turned on.
+    pub conventional_commits: bool,
+    /// Opens the commit popup with a subject guessed from the staged paths,
+    /// e.g. `nvim, zsh: update configs`, unless `commit.template` is set.
+    pub prefill_subject: bool,
+    /// Subjects longer than this, a conventional-commit prefix included,
+    /// are warned about before committing (default 72).
+    pub subject_limit: Option<usize>,
+    /// Width past which commit body lines are flagged (default 72).
+    pub body_width: Option<usize>,
+    /// Diff lines shown before the rest waits for "load more" (default
+    /// 1000).
+    pub diff_line_limit: Option<usize>,
+    /// Files larger than this many bytes are summarised instead of diffed
+    /// in the status view (default 10 MiB).
+    pub diff_size_limit: Option<u64>,
+    /// Word list for spell-checking commit messages, one word per line
+    /// (default `/usr/share/dict/words`). Only used when built with the
+    /// `spellcheck` feature.
+    pub dictionary: Option<PathBuf>,
+    /// The external tool changed files are compared in, e.g. `nvim -d`.
+    pub diff: Option<ToolConfig>,
+    /// The external tool conflicts are resolved in, e.g. `meld`.
+    pub merge: Option<ToolConfig>,
+    /// Enables the GitHub/GitLab view when present.
+    pub forge: Option<ForgeConfig>,
+    /// Keeps the matching files of the repository encrypted when present.
+    pub encryption: Option<EncryptionConfig>,
+    /// Keys moved from their defaults, per keymap.
+    pub keys: KeyBindings,
+    /// The colours of the interface.
+    pub theme: Theme,
+    /// `[[recent]]` entries older versions kept here. They are read until
+    /// the app saved the recent repositories to their own state file.
+    pub recent: Vec<RecentRepo>,
+    /// The file the config was loaded from, and where it is saved.
+    #[serde(skip)]
+    pub path: Option<PathBuf>,
+}
+
+/// The `[forge]` section of the config.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ForgeConfig {
+    /// API token; without it only public repositories can be queried, and
+    /// with a low rate limit.
+    pub token: Option<String>,
+}
+
+/// The `[encryption]` section of the config: which files of the repository
+/// hold secrets, and who can read them.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EncryptionConfig {
+    /// Gitignore-style patterns of the secret files, e.g. `secrets/**`.
+    pub patterns: Vec<String>,
+    pub tool: Cipher,
+    /// The program to run, when it is not on the `PATH` as `age` or `gpg`.
+    pub program: Option<String>,
+    /// Whom the files are encrypted to: age recipients (`age1…`) or gpg
+    /// key ids.
+    pub recipients: Vec<String>,
+    /// The age identity file that decrypts them. Asked for when unset; gpg
+    /// asks its agent and, failing that, for the passphrase.
+    pub identity: Option<PathBuf>,
+}
+
+/// The program secrets are encrypted with.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Cipher {
+    #[default]
+    Age,
+    Gpg,
+}
+
+/// The `[diff]` and `[merge]` sections of the config.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ToolConfig {
+    /// The command, which may refer to the files as `$LOCAL`, `$REMOTE`,
+    /// `$BASE` and `$MERGED`; see [`crate::git::tools::tool_command`].
+    pub tool: String,
+}
+
+/// The `[theme]` section of the config: a built-in `preset` (`dark`, the
+/// default, or `light`) and the colours moved from it, as in
+/// `accent = "magenta"` or `selection = "#3a3a3a"`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "BTreeMap<String, String>")]
+pub struct Theme {
+    /// Focused borders, hunk headers and other highlights.
+    pub accent: Color,
+    /// Background of the selected row.
+    pub selection: Color,
+    /// Added lines and counts.
+    pub added: Color,
+    /// Removed lines and counts.
+    pub removed: Color,
+}
+
+impl Theme {
+    /// The built-in themes, by the name `preset` takes.
+    pub const PRESETS: [&'static str; 2] = ["dark", "light"];
+
+    const DARK: Self = Self {
+        accent: Color::Cyan,
+        selection: Color::DarkGray,
+        added: Color::Green,
+        removed: Color::Red,
+    };
+
+    const LIGHT: Self = Self {
+        accent: Color::Blue,
+        selection: Color::Gray,
+        added: Color::Green,
+        removed: Color::Red,
+    };
+
+    /// The built-in theme called `name`.
+    pub fn preset(name: &str) -> Option<Self> {
+        match name {
+            "dark" => Some(Self::DARK),
+            "light" => Some(Self::LIGHT),
+            _ => None,
+        }
+    }
+}
+
+impl Default for Theme {
     fn default() -> Self {
-        Self {
-            quit: KeyEvent::new(KeyCode::Char('q'), KeyModifiers::NONE),
-            show_help: KeyEvent::new(KeyCode::Char('?'), KeyModifiers::NONE),
-            status_mode: KeyEvent::new(KeyCode::Char('s'), KeyModifiers::NONE),
-            log_mode: KeyEvent::new(KeyCode::Char('l'), KeyModifiers::NONE),
-            select_next: KeyEvent::new(KeyCode::Char('j'), KeyModifiers::NONE),
-            select_prev: KeyEvent::new(KeyCode::Char('k'), KeyModifiers::NONE),
-            stage_item: KeyEvent::new(KeyCode::Char(' '), KeyModifiers::NONE),
-            unstage_item: KeyEvent::new(KeyCode::Char('u'), KeyModifiers::NONE),
-            commit: KeyEvent::new(KeyCode::Char('c'), KeyModifiers::NONE),
-            push: KeyEvent::new(KeyCode::Char('p'), KeyModifiers::SHIFT), // Shift + P
-            confirm: KeyEvent::new(KeyCode::Enter, KeyModifiers::NONE),
-            close_popup: KeyEvent::new(KeyCode::Esc, KeyModifiers::NONE),
-            // --- New V2 Keybindings ---
-            panel_right: KeyEvent::new(KeyCode::Char('l'), KeyModifiers::NONE),
-            panel_left: KeyEvent::new(KeyCode::Char('h'), KeyModifiers::NONE),
+        Self::DARK
+    }
+}
+
+impl TryFrom<BTreeMap<String, String>> for Theme {
+    type Error = String;
+
+    /// Starts from the `preset` and moves the colours the table names,
+    /// listing every mistake at once.
+    fn try_from(mut table: BTreeMap<String, String>) -> Result<Self, Self::Error> {
+        let mut theme = match table.remove("preset") {
+            Some(name) => Self::preset(&name).ok_or_else(|| {
+                format!("[theme] preset '{}' is not one of {}", name, Self::PRESETS.join(", "))
+            })?,
+            None => Self::default(),
+        };
+        let mut errors = Vec::new();
+        for (name, text) in &table {
+            let color = match name.as_str() {
+                "accent" => &mut theme.accent,
+                "selection" => &mut theme.selection,
+                "added" => &mut theme.added,
+                "removed" => &mut theme.removed,
+                _ => {
+                    errors.push(format!("[theme] has no colour '{}'", name));
+                    continue;
+                }
+            };
+            match Color::from_str(text) {
+                Ok(parsed) => *color = parsed,
+                Err(_) => errors.push(format!("[theme] {}: '{}' is not a colour", name, text)),
+            }
+        }
+        match errors.is_empty() {
+            true => Ok(theme),
+            false => Err(format!("invalid theme:\n{}", errors.join("\n"))),
+        }
+    }
+}
+
+impl Config {
+    /// How the status lists untracked files; `expanded` are the collapsed
+    /// directories whose files were asked for.
+    pub fn untracked_files<'a>(&self, expanded: impl IntoIterator<Item = &'a String>) -> UntrackedFiles {
+        if self.hide_untracked {
+            UntrackedFiles::Hidden
+        } else if self.collapse_untracked_dirs {
+            UntrackedFiles::Collapsed(expanded.into_iter().cloned().collect())
+        } else {
+            UntrackedFiles::All
+        }
+    }
+
+    /// Loads the config from `path`, or from the default location when `None`.
+    /// A missing file at the default location yields the default config.
+    pub fn load(path: Option<&Path>) -> AppResult<Self> {
+        let path = match path {
+            Some(path) => path.to_path_buf(),
+            None => match default_config_path() {
+                Some(path) if path.exists() => path,
+                path => return Ok(Self { path, ..Self::default() }),
+            },
+        };
+        let contents = fs::read_to_string(&path)?;
+        let mut config: Self = toml::from_str(&contents)
+            .map_err(|e| AppError::Config(format!("{}: {}", path.display(), e)))?;
+        config.path = Some(path);
+        Ok(config)
+    }
+}
+
+/// `$XDG_CONFIG_HOME/dotatui/config.toml`, falling back to `~/.config` (or
+/// `%APPDATA%` on Windows).
+pub fn default_config_path() -> Option<PathBuf> {
+    env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("APPDATA").map(PathBuf::from))
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+        .map(|dir| dir.join("dotatui").join("config.toml"))
+}
+
+/// Declares a keymap: a struct of keys with their defaults. The field
+/// names are the action names of its `[keys.<keymap>]` table.
+macro_rules! keymap {
+    ($(#[$doc:meta])* $name:ident { $($(#[$field_doc:meta])* $field:ident: $default:expr,)* }) => {
+        $(#[$doc])*
+        #[derive(Debug, Clone, PartialEq, Eq)]
+        pub struct $name {
+            $($(#[$field_doc])* pub $field: KeyEvent,)*
+        }
+
+        impl Default for $name {
+            fn default() -> Self {
+                Self {
+                    $($field: $default,)*
+                }
+            }
+        }
+
+        impl Keymap for $name {
+            fn keys(&self) -> Vec<(&'static str, KeyEvent)> {
+                vec![$((stringify!($field), self.$field),)*]
+            }
+
+            fn key_mut(&mut self, name: &str) -> Option<&mut KeyEvent> {
+                match name {
+                    $(stringify!($field) => Some(&mut self.$field),)*
+                    _ => None,
+                }
+            }
+        }
+    };
+}
+
+/// The keys of one `[keys.<keymap>]` table, by their names there.
+pub trait Keymap {
+    fn keys(&self) -> Vec<(&'static str, KeyEvent)>;
+    fn key_mut(&mut self, name: &str) -> Option<&mut KeyEvent>;
+}
+
+fn key(c: char) -> KeyEvent {
+    match c.is_ascii_uppercase() {
+        true => KeyEvent::new(KeyCode::Char(c), KeyModifiers::SHIFT),
+        false => KeyEvent::new(KeyCode::Char(c), KeyModifiers::NONE),
+    }
+}
+
+fn ctrl(c: char) -> KeyEvent {
+    KeyEvent::new(KeyCode::Char(c), KeyModifiers::CONTROL)
+}
+
+fn code(code: KeyCode) -> KeyEvent {
+    KeyEvent::new(code, KeyModifiers::NONE)
+}
+
+keymap!(
+    /// Keys that work in every view, unless the view's keymap uses them.
+    GlobalKeys {
+        quit: key('q'),
+        show_help: key('?'),
+        /// Only outside the status view.
+        status_mode: key('s'),
+        log_mode: key('l'),
+        stash_mode: key('t'),
+        links_mode: key('i'),
+        tree_mode: key('r'),
+        reflog_mode: key('R'),
+        forge_mode: key('w'),
+        ignore_mode: key('O'),
+        messages_mode: key('M'),
+        debug_mode: code(KeyCode::F(12)),
+        stats_mode: key('S'),
+        doctor_mode: key('D'),
+        /// Only outside the status view, where it commits.
+        git_config_mode: key('C'),
+        worktree_switch: key('W'),
+        repo_switch: ctrl('r'),
+        command_palette: key(':'),
+        undo: ctrl('z'),
+        refresh: code(KeyCode::F(5)),
+        toggle_mouse: ctrl('o'),
+        /// Turns sync mode on, or pauses it.
+        toggle_sync: ctrl('y'),
+        /// Pressed twice (`gg`).
+        select_first: key('g'),
+        select_last: key('G'),
+        page_down: ctrl('d'),
+        page_up: ctrl('u'),
+        /// Starts the multi-key bindings listed by `KeyBindings::sequences`,
+        /// like vim's `<Leader>`.
+        leader: key('\\'),
+        /// Starts recording the keys typed into a macro, or stops.
+        record_macro: key('Q'),
+        /// Pressed twice (`@@`) it replays the macro.
+        play_macro: key('@'),
+    }
+);
+
+keymap!(
+    /// The file list of the status view.
+    StatusKeys {
+        select_next: key('j'),
+        select_prev: key('k'),
+        /// `l` focuses the diff here, so the log has a key of its own.
+        log_mode: ctrl('l'),
+        panel_left: key('h'),
+        panel_right: key('l'),
+        next_hunk: key('}'),
+        prev_hunk: key('{'),
+        load_more: key('+'),
+        stage_item: key(' '),
+        unstage_item: key('u'),
+        mark_item: key('m'),
+        /// Discards the unstaged changes of the selected or marked files.
+        discard: key('d'),
+        stage_all: key('a'),
+        unstage_all: key('A'),
+        ignore_item: key('I'),
+        /// The history of the selected file.
+        file_log: key('L'),
+        group_by_dir: key('v'),
+        search: key('/'),
+        cancel: code(KeyCode::Esc),
+        confirm: code(KeyCode::Enter),
+        commit: key('c'),
+        push: key('P'),
+        force_push: key('F'),
+        /// Pushes to remotes chosen in a popup, with an editable refspec.
+        push_to: ctrl('p'),
+        fetch: key('f'),
+        pull: key('p'),
+        divergence: key('U'),
+        edit_branch: key('B'),
+        stash_push: key('z'),
+        /// Stashes the untracked files as well.
+        stash_untracked: key('Z'),
+        /// Stashes the changes but leaves the staged ones in the index too.
+        stash_keep_index: ctrl('k'),
+        /// Opens the selected file in the diff tool.
+        external_tool: key('E'),
+        copy: key('y'),
+        export_patch: key('x'),
+        apply_patch: key('X'),
+    }
+);
+
+keymap!(
+    /// The hunk, line and conflict modes of the status view.
+    HunkKeys {
+        select_next: key('j'),
+        select_prev: key('k'),
+        panel_left: key('h'),
+        panel_right: key('l'),
+        next_hunk: key('}'),
+        prev_hunk: key('{'),
+        stage_item: key(' '),
+        /// Switches from hunk to line selection.
+        select_lines: key('V'),
+        cancel: code(KeyCode::Esc),
+        export_patch: key('x'),
+        copy: key('y'),
+        take_ours: key('o'),
+        take_theirs: key('T'),
+        open_editor: key('e'),
+        /// Opens the conflict in the merge tool.
+        external_tool: key('E'),
+    }
+);
+
+keymap!(
+    LogKeys {
+        select_next: key('j'),
+        select_prev: key('k'),
+        search: key('/'),
+        search_next: key('n'),
+        search_prev: key('N'),
+        confirm: code(KeyCode::Enter),
+        cancel: code(KeyCode::Esc),
+        mark_commit: key('m'),
+        compare: key('d'),
+        log_checkout: key('o'),
+        /// Opens the bisect popup, which marks the selected commit, or the
+        /// one under test, good or bad.
+        bisect: key('b'),
+        copy: key('y'),
+        copy_message: key('Y'),
+        panel_left: key('h'),
+        panel_right: key('l'),
+        next_hunk: key('}'),
+        prev_hunk: key('{'),
+        load_more: key('+'),
+    }
+);
+
+keymap!(
+    StashKeys {
+        select_next: key('j'),
+        select_prev: key('k'),
+        stash_apply: key(' '),
+        stash_pop: key('p'),
+        stash_drop: key('d'),
+    }
+);
+
+keymap!(
+    LinksKeys {
+        select_next: key('j'),
+        select_prev: key('k'),
+        profile_switch: key('p'),
+        link_create: key('c'),
+        link_remove: key('x'),
+        link_adopt: key('a'),
+        link_import: key('I'),
+        link_apply: key('A'),
+    }
+);
+
+keymap!(
+    TreeKeys {
+        select_next: key('j'),
+        select_prev: key('k'),
+        confirm: code(KeyCode::Enter),
+        /// Expands or collapses a directory, like `confirm`.
+        expand: key(' '),
+        panel_left: key('h'),
+        panel_right: key('l'),
+        tree_blame: key('b'),
+        file_log: key('L'),
+        tree_diff: key('D'),
+        copy: key('y'),
+    }
+);
+
+keymap!(
+    ReflogKeys {
+        select_next: key('j'),
+        select_prev: key('k'),
+        reflog_checkout: key('c'),
+        reflog_reset: key('x'),
+    }
+);
+
+keymap!(
+    IgnoreKeys {
+        select_next: key('j'),
+        select_prev: key('k'),
+        ignore_check: key('c'),
+        ignore_add: key('a'),
+        ignore_edit: key('e'),
+        ignore_remove: key('x'),
+        ignore_templates: key('d'),
+    }
+);
+
+keymap!(
+    ForgeKeys {
+        select_next: key('j'),
+        select_prev: key('k'),
+        confirm: code(KeyCode::Enter),
+        forge_open_repo: key('b'),
+        forge_open_commit: key('c'),
+    }
+);
+
+keymap!(
+    DoctorKeys {
+        select_next: key('j'),
+        select_prev: key('k'),
+        /// Runs or copies the fix of the selected check.
+        confirm: code(KeyCode::Enter),
+        doctor_recheck: key('c'),
+    }
+);
+
+keymap!(
+    GitConfigKeys {
+        select_next: key('j'),
+        select_prev: key('k'),
+        /// Edits the value of the selected entry, or flips a true/false one.
+        config_edit: code(KeyCode::Enter),
+        config_add: key('a'),
+        config_unset: key('x'),
+        /// Switches between the local and the global config file.
+        config_scope: code(KeyCode::Tab),
+    }
+);
+
+keymap!(
+    /// The messages and debug views.
+    MessagesKeys {
+        select_next: key('j'),
+        select_prev: key('k'),
+    }
+);
+
+keymap!(
+    /// Popups and text inputs, which read these keys directly; each popup
+    /// uses only some of them.
+    PopupKeys {
+        confirm: code(KeyCode::Enter),
+        close_popup: code(KeyCode::Esc),
+        confirm_yes: key('y'),
+        confirm_no: key('n'),
+        retry: key('r'),
+        select_next: key('j'),
+        select_prev: key('k'),
+        select_last: key('G'),
+        page_down: ctrl('d'),
+        page_up: ctrl('u'),
+        panel_left: key('h'),
+        panel_right: key('l'),
+        load_more: key('+'),
+        next_field: code(KeyCode::Tab),
+        toggle_entry: key(' '),
+        submit_commit: ctrl('s'),
+        toggle_signing: ctrl('g'),
+        toggle_conventional: ctrl('t'),
+        toggle_no_verify: ctrl('n'),
+        worktree_add: key('a'),
+        worktree_remove: key('x'),
+        repo_clone: key('c'),
+        bisect_good: key('g'),
+        bisect_bad: key('b'),
+        bisect_skip: key('s'),
+        bisect_stop: key('e'),
+    }
+);
+
+/// The keybindings of the application: one keymap per group of views, and
+/// one for the popups. A key in a view's keymap hides the same key in the
+/// global one, e.g. `l` focuses the diff in the status view and opens the
+/// log elsewhere. The `[keys.<keymap>]` tables of the config move keys, as
+/// in `commit = "C"`; keys that clash fail the config.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "BTreeMap<String, BTreeMap<String, String>>")]
+pub struct KeyBindings {
+    pub global: GlobalKeys,
+    pub status: StatusKeys,
+    pub hunk: HunkKeys,
+    pub log: LogKeys,
+    pub stash: StashKeys,
+    pub links: LinksKeys,
+    pub tree: TreeKeys,
+    pub reflog: ReflogKeys,
+    pub ignore: IgnoreKeys,
+    pub forge: ForgeKeys,
+    pub doctor: DoctorKeys,
+    pub gitconfig: GitConfigKeys,
+    pub messages: MessagesKeys,
+    pub popup: PopupKeys,
+}
+
+impl KeyBindings {
+    fn keymaps(&self) -> [(&'static str, &dyn Keymap); 14] {
+        [
+            ("global", &self.global),
+            ("status", &self.status),
+            ("hunk", &self.hunk),
+            ("log", &self.log),
+            ("stash", &self.stash),
+            ("links", &self.links),
+            ("tree", &self.tree),
+            ("reflog", &self.reflog),
+            ("ignore", &self.ignore),
+            ("forge", &self.forge),
+            ("doctor", &self.doctor),
+            ("gitconfig", &self.gitconfig),
+            ("messages", &self.messages),
+            ("popup", &self.popup),
+        ]
+    }
+
+    fn keymap_mut(&mut self, name: &str) -> Option<&mut dyn Keymap> {
+        Some(match name {
+            "global" => &mut self.global,
+            "status" => &mut self.status,
+            "hunk" => &mut self.hunk,
+            "log" => &mut self.log,
+            "stash" => &mut self.stash,
+            "links" => &mut self.links,
+            "tree" => &mut self.tree,
+            "reflog" => &mut self.reflog,
+            "ignore" => &mut self.ignore,
+            "forge" => &mut self.forge,
+            "doctor" => &mut self.doctor,
+            "gitconfig" => &mut self.gitconfig,
+            "messages" => &mut self.messages,
+            "popup" => &mut self.popup,
+            _ => return None,
+        })
+    }
+
+    /// Keys that cannot work as bound: a key used twice in one keymap, and
+    /// a view's key that starts a multi-key binding (`gg`, `@@` or the
+    /// leader), which would wait for the next key instead.
+    pub fn clashes(&self) -> Vec<String> {
+        let starts = [
+            ("select_first", self.global.select_first),
+            ("leader", self.global.leader),
+            ("play_macro", self.global.play_macro),
+        ];
+        let mut clashes = Vec::new();
+        for (keymap, keys) in self.keymaps() {
+            let keys = keys.keys();
+            for (i, (name, key)) in keys.iter().enumerate() {
+                if let Some((other, _)) = keys[..i].iter().find(|(_, other)| other == key) {
+                    clashes.push(format!("[keys.{}] {} and {} are both '{}'", keymap, other, name, key_label(*key)));
+                }
+                if keymap == "popup" {
+                    continue;
+                }
+                for (start, start_key) in starts {
+                    if *key == start_key && !(keymap == "global" && *name == start) {
+                        clashes.push(format!(
+                            "[keys.{}] {} is '{}', which starts the [keys.global] {} bindings",
+                            keymap,
+                            name,
+                            key_label(*key),
+                            start
+                        ));
+                    }
+                }
+            }
+        }
+        clashes
+    }
+}
+
+impl TryFrom<BTreeMap<String, BTreeMap<String, String>>> for KeyBindings {
+    type Error = String;
+
+    /// Applies the `[keys]` tables to the default keys, listing every
+    /// mistake and clash at once.
+    fn try_from(tables: BTreeMap<String, BTreeMap<String, String>>) -> Result<Self, Self::Error> {
+        let mut bindings = Self::default();
+        let mut errors = Vec::new();
+        for (keymap, table) in &tables {
+            let Some(keys) = bindings.keymap_mut(keymap) else {
+                errors.push(format!("there is no keymap [keys.{}]", keymap));
+                continue;
+            };
+            for (name, text) in table {
+                match (keys.key_mut(name), parse_key(text)) {
+                    (None, _) => errors.push(format!("[keys.{}] has no action '{}'", keymap, name)),
+                    (Some(_), Err(e)) => errors.push(format!("[keys.{}] {}: {}", keymap, name, e)),
+                    (Some(key), Ok(parsed)) => *key = parsed,
+                }
+            }
+        }
+        errors.extend(bindings.clashes());
+        match errors.is_empty() {
+            true => Ok(bindings),
+            false => Err(format!("invalid key bindings:\n{}", errors.join("\n"))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keymaps_move_keys_and_report_every_clash() {
+        let config: Config = toml::from_str("[keys.status]\ncommit = \"C\"\npush_to = \"ctrl+p\"\n").unwrap();
+        assert_eq!(config.keys.status.commit, key('C'));
+        assert_eq!(config.keys.log, LogKeys::default());
+
+        let contents = "[keys.global]\nleader = \",\"\n[keys.hunk]\ncopy = \"T\"\n[keys.log]\nrevert = \"v\"\ncopy = \",\"\n[keys.views]\n";
+        let error = toml::from_str::<Config>(contents).unwrap_err().to_string();
+        for report in [
+            "there is no keymap [keys.views]",
+            "[keys.log] has no action 'revert'",
+            "[keys.hunk] copy and take_theirs are both 'Shift+T'",
+            "[keys.log] copy is ',', which starts the [keys.global] leader bindings",
+        ] {
+            assert!(error.contains(report), "{}", error);
+        }
+    }
+
+    #[test]
+    fn themes_start_from_a_preset_and_report_every_bad_colour() {
+        let config: Config = toml::from_str("[theme]\npreset = \"light\"\naccent = \"#5f87af\"\n").unwrap();
+        assert_eq!(config.theme.accent, Color::Rgb(0x5f, 0x87, 0xaf));
+        assert_eq!(config.theme.selection, Color::Gray);
+        assert_eq!(Config::default().theme.accent, Color::Cyan);
+
+        let error = toml::from_str::<Config>("[theme]\naccent = \"teal-ish\"\nborder = \"red\"\n").unwrap_err().to_string();
+        for report in ["[theme] accent: 'teal-ish' is not a colour", "[theme] has no colour 'border'"] {
+            assert!(error.contains(report), "{}", error);
         }
+        let error = toml::from_str::<Config>("[theme]\npreset = \"solarized\"\n").unwrap_err().to_string();
+        assert!(error.contains("preset 'solarized' is not one of dark, light"), "{}", error);
     }
 }
@@ -0,0 +1,312 @@
+//! src/doctor.rs
+
+use crate::{
+    config::EncryptionConfig,
+    dotfiles::{secrets, LinkState, Manifest, MANIFEST_FILE},
+    git::{BranchStatus, GitRepo, RepoLocation},
+};
+use std::{
+    collections::HashSet,
+    env, fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+/// How a check went.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Health {
+    Pass,
+    /// Works, but something the user probably wants is missing.
+    Warn,
+    /// Something that should work does not.
+    Fail,
+}
+
+/// What the doctor view can do about a problem.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Fix {
+    /// Make this remote's branch of the same name the upstream.
+    SetUpstream(String),
+    EditFile(PathBuf),
+    /// Open the git config view, e.g. to set `user.name`.
+    EditGitConfig,
+    /// Set the executable bits of these hooks.
+    MakeExecutable(Vec<PathBuf>),
+    /// Apply the links of the manifest, backing up the files in the way.
+    ApplyLinks,
+    /// A command for the user to run in a shell. It is copied, not run.
+    Run(String),
+}
+
+impl Fix {
+    pub fn description(&self) -> String {
+        match self {
+            Fix::SetUpstream(remote) => format!("track the branch of the same name on {}", remote),
+            Fix::EditFile(path) => format!("edit {}", path.display()),
+            Fix::EditGitConfig => "set them in the git config view".to_string(),
+            Fix::MakeExecutable(_) => "make them executable".to_string(),
+            Fix::ApplyLinks => "apply the links, backing up files in the way".to_string(),
+            Fix::Run(command) => format!("run `{}`", command),
+        }
+    }
+}
+
+/// The result of one check of the doctor view.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Check {
+    pub name: &'static str,
+    pub health: Health,
+    pub message: String,
+    pub fix: Option<Fix>,
+}
+
+impl Check {
+    fn new(name: &'static str, health: Health, message: impl Into<String>, fix: Option<Fix>) -> Self {
+        Self {
+            name,
+            health,
+            message: message.into(),
+            fix,
+        }
+    }
+}
+
+/// Checks what a dotfiles repository needs to be committed to, pushed and
+/// deployed: the repository, its remote and upstream, the ssh-agent, the
+/// commit identity, the hooks and the link manifest. Connects to the
+/// remote, so it belongs on a background thread.
+pub fn examine(location: &RepoLocation, encryption: Option<&EncryptionConfig>) -> Vec<Check> {
+    let repo = match GitRepo::open(location) {
+        Ok(repo) => repo,
+        Err(e) => return vec![Check::new("repository", Health::Fail, e.to_string(), None)],
+    };
+    let status = repo.branch_status();
+    let mut checks = vec![Check::new("repository", Health::Pass, describe_head(&status), None)];
+    checks.extend(remote_checks(&repo, &status));
+    checks.push(identity_check(&repo));
+    checks.push(hooks_check(&repo));
+    checks.push(links_check(&repo, encryption));
+    checks
+}
+
+fn describe_head(status: &BranchStatus) -> String {
+    match (&status.branch, status.unborn) {
+        (Some(branch), true) => format!("on {}, no commits yet", branch),
+        (Some(branch), false) if status.detached => format!("HEAD detached at {}", branch),
+        (Some(branch), false) => format!("on {}", branch),
+        (None, _) => "HEAD detached".to_string(),
+    }
+}
+
+/// The remote, whether it answers, the upstream and the ssh-agent when the
+/// remote is reached over SSH.
+fn remote_checks(repo: &GitRepo, status: &BranchStatus) -> Vec<Check> {
+    let remotes = repo.remote_names().unwrap_or_default();
+    let upstream_remote = status
+        .upstream
+        .as_deref()
+        .and_then(|upstream| upstream.split_once('/'))
+        .map(|(remote, _)| remote.to_string());
+    let remote = upstream_remote
+        .filter(|remote| remotes.contains(remote))
+        .or_else(|| remotes.iter().find(|remote| *remote == "origin").cloned())
+        .or_else(|| remotes.first().cloned());
+    let Some(remote) = remote else {
+        return vec![Check::new(
+            "remote",
+            Health::Warn,
+            "no remote; the dotfiles are not backed up anywhere",
+            Some(Fix::Run("git remote add origin <url>".to_string())),
+        )];
+    };
+    let url = repo.remote_url(&remote).unwrap_or_default();
+    let mut checks = vec![Check::new("remote", Health::Pass, format!("{} is {}", remote, url), None)];
+    checks.push(match repo.check_remote(&remote) {
+        Ok(()) => Check::new("reachable", Health::Pass, format!("{} answers", remote), None),
+        Err(e) => Check::new(
+            "reachable",
+            Health::Fail,
+            e.to_string(),
+            Some(Fix::Run(format!("git ls-remote {}", remote))),
+        ),
+    });
+    checks.push(match status {
+        BranchStatus { detached: true, .. } => Check::new("upstream", Health::Warn, "HEAD is detached", None),
+        BranchStatus {
+            branch: Some(branch),
+            upstream: Some(upstream),
+            ..
+        } => Check::new("upstream", Health::Pass, format!("{} tracks {}", branch, upstream), None),
+        BranchStatus { branch: Some(branch), .. } => Check::new(
+            "upstream",
+            Health::Warn,
+            format!("{} has no upstream to pull from and count commits against", branch),
+            Some(Fix::SetUpstream(remote.clone())),
+        ),
+        _ => Check::new("upstream", Health::Warn, "no branch checked out", None),
+    });
+    checks.push(if is_ssh_url(&url) {
+        ssh_agent_check()
+    } else {
+        Check::new("ssh-agent", Health::Pass, format!("not needed, {} is not reached over SSH", remote), None)
+    });
+    checks
+}
+
+/// `ssh://host/path` or scp-like `user@host:path`.
+fn is_ssh_url(url: &str) -> bool {
+    url.starts_with("ssh://") || (!url.contains("://") && url.split_once(':').is_some_and(|(host, _)| host.contains('@')))
+}
+
+fn ssh_agent_check() -> Check {
+    if env::var_os("SSH_AUTH_SOCK").is_none() {
+        return Check::new(
+            "ssh-agent",
+            Health::Warn,
+            "no ssh-agent is running; keys with a passphrase are asked for on every push",
+            Some(Fix::Run("eval \"$(ssh-agent)\" && ssh-add".to_string())),
+        );
+    }
+    // `ssh-add -l` exits with 1 when the agent has no keys and with 2 when
+    // it cannot reach the agent.
+    match Command::new("ssh-add").arg("-l").output() {
+        Ok(output) if output.status.success() => {
+            let keys = String::from_utf8_lossy(&output.stdout).lines().count();
+            Check::new("ssh-agent", Health::Pass, format!("{} keys loaded", keys), None)
+        }
+        Ok(output) if output.status.code() == Some(1) => Check::new(
+            "ssh-agent",
+            Health::Warn,
+            "the ssh-agent has no keys",
+            Some(Fix::Run("ssh-add".to_string())),
+        ),
+        Ok(_) => Check::new("ssh-agent", Health::Warn, "SSH_AUTH_SOCK is set, but the agent does not answer", None),
+        Err(e) => Check::new("ssh-agent", Health::Warn, format!("cannot run ssh-add: {}", e), None),
+    }
+}
+
+fn identity_check(repo: &GitRepo) -> Check {
+    match repo.user_identity() {
+        (Some(name), Some(email)) => Check::new("identity", Health::Pass, format!("{} <{}>", name, email), None),
+        (name, email) => {
+            let missing: Vec<&str> = [(name.is_none(), "user.name"), (email.is_none(), "user.email")]
+                .into_iter()
+                .filter_map(|(missing, key)| missing.then_some(key))
+                .collect();
+            Check::new(
+                "identity",
+                Health::Fail,
+                format!("{} not set; commits cannot be made", missing.join(" and ")),
+                Some(Fix::EditGitConfig),
+            )
+        }
+    }
+}
+
+/// Hooks git would skip because they are not executable. Samples are left
+/// out.
+fn hooks_check(repo: &GitRepo) -> Check {
+    let hooks = repo
+        .hooks_dir()
+        .ok()
+        .and_then(|dir| fs::read_dir(dir).ok())
+        .map(|entries| {
+            let mut hooks: Vec<PathBuf> = entries
+                .flatten()
+                .map(|entry| entry.path())
+                .filter(|path| path.is_file() && path.extension().is_none_or(|ext| ext != "sample"))
+                .collect();
+            hooks.sort();
+            hooks
+        })
+        .unwrap_or_default();
+    let skipped: Vec<PathBuf> = hooks.iter().filter(|hook| !is_executable(hook)).cloned().collect();
+    if skipped.is_empty() {
+        let message = match hooks.len() {
+            0 => "no hooks".to_string(),
+            n => format!("{} executable", n),
+        };
+        return Check::new("hooks", Health::Pass, message, None);
+    }
+    let names: Vec<String> = skipped
+        .iter()
+        .map(|hook| hook.file_name().unwrap_or_default().to_string_lossy().into_owned())
+        .collect();
+    Check::new(
+        "hooks",
+        Health::Warn,
+        format!("{} not executable, so git skips them", names.join(", ")),
+        Some(Fix::MakeExecutable(skipped)),
+    )
+}
+
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    fs::metadata(path).is_ok_and(|metadata| metadata.permissions().mode() & 0o111 != 0)
+}
+
+#[cfg(not(unix))]
+fn is_executable(_path: &Path) -> bool {
+    true
+}
+
+/// Whether the manifest parses, names sources that exist and distinct
+/// targets, and is applied.
+fn links_check(repo: &GitRepo, encryption: Option<&EncryptionConfig>) -> Check {
+    let edit = Some(Fix::EditFile(repo.path().join(MANIFEST_FILE)));
+    let manifest = match Manifest::load(repo.path()) {
+        Ok(manifest) => manifest,
+        Err(e) => return Check::new("links", Health::Fail, e.to_string(), edit),
+    };
+    if manifest.links.is_empty() {
+        return Check::new("links", Health::Pass, format!("no links in {}", MANIFEST_FILE), None);
+    }
+    let selected = repo.selected_profile();
+    let mut entries = manifest.resolve(repo.path(), manifest.active_profile(selected.as_deref()));
+    secrets::mark_secrets(encryption, &mut entries);
+
+    let mut targets = HashSet::new();
+    if let Some(twice) = entries.iter().find(|entry| !targets.insert(&entry.target)) {
+        let message = format!("{} is the target of two links", twice.spec.target);
+        return Check::new("links", Health::Fail, message, edit);
+    }
+    let count = |states: &[LinkState]| entries.iter().filter(|entry| states.contains(&entry.state)).count();
+    let missing_sources = count(&[LinkState::SourceMissing]);
+    if missing_sources > 0 {
+        let message = format!("{} sources missing from the repository", missing_sources);
+        return Check::new("links", Health::Fail, message, edit);
+    }
+    let unapplied = count(&[LinkState::Missing, LinkState::Stale]);
+    let conflicts = count(&[LinkState::Conflict]);
+    if unapplied + conflicts > 0 {
+        let message = format!("{} links not created, {} targets taken by other files", unapplied, conflicts);
+        return Check::new("links", Health::Warn, message, Some(Fix::ApplyLinks));
+    }
+    Check::new("links", Health::Pass, format!("{} links in place", entries.len()), None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::RepoFixture;
+
+    #[test]
+    fn a_repository_without_remote_is_warned_about_and_broken_manifests_fail() {
+        let fixture = RepoFixture::new()
+            .committed(MANIFEST_FILE, "[[link]]\nsource = \"gone\"\ntarget = \"~/.gone\"\n");
+        let checks = examine(&fixture.open().location(), None);
+        let health = |name: &str| checks.iter().find(|check| check.name == name).map(|check| check.health);
+        assert_eq!(health("repository"), Some(Health::Pass));
+        assert_eq!(health("remote"), Some(Health::Warn));
+        assert_eq!(health("reachable"), None);
+        assert_eq!(health("identity"), Some(Health::Pass));
+        assert_eq!(health("hooks"), Some(Health::Pass));
+        assert_eq!(health("links"), Some(Health::Fail));
+
+        assert!(is_ssh_url("git@github.com:me/dotfiles.git"));
+        assert!(is_ssh_url("ssh://git@example.com/dotfiles"));
+        assert!(!is_ssh_url("https://github.com/me/dotfiles.git"));
+        assert!(!is_ssh_url("/srv/git/dotfiles.git"));
+    }
+}
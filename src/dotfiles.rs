@@ -0,0 +1,378 @@
+//! src/dotfiles.rs
+
+pub mod apply;
+pub mod ignore_templates;
+pub mod import;
+pub mod secrets;
+
+use crate::error::{AppError, AppResult};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::OnceLock;
+use toml_edit::{value, ArrayOfTables, DocumentMut, Item, Table};
+
+/// Name of the manifest file, looked up at the root of the repository.
+pub const MANIFEST_FILE: &str = "dotatui.toml";
+
+/// The link manifest, mapping files in the repository to targets in `$HOME`.
+///
+/// ```toml
+/// [[link]]
+/// source = "zsh/.zshrc"
+/// target = "~/.zshrc"
+///
+/// [[profile]]
+/// name = "work"
+/// hosts = ["work-laptop"]
+/// branch = "work"
+/// overlay = "hosts/work"
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Manifest {
+    #[serde(default, rename = "link")]
+    pub links: Vec<LinkSpec>,
+    #[serde(default, rename = "profile")]
+    pub profiles: Vec<Profile>,
+}
+
+/// A machine profile: the hosts it applies to, the branch they check out and
+/// a directory whose files replace the link sources on those hosts.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Profile {
+    pub name: String,
+    #[serde(default)]
+    pub hosts: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub branch: Option<String>,
+    /// Directory relative to the repository root. A link whose source also
+    /// exists below it (e.g. `hosts/work/zsh/.zshrc` for `zsh/.zshrc`)
+    /// points at that file instead.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub overlay: Option<String>,
+}
+
+/// A single `source -> target` mapping from the manifest.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LinkSpec {
+    /// Path relative to the repository root.
+    pub source: String,
+    /// Destination path; a leading `~` expands to the home directory.
+    pub target: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LinkState {
+    /// The target is a symlink pointing at the repository file.
+    Linked,
+    /// Nothing exists at the target yet.
+    Missing,
+    /// The target links to another file of the repository, e.g. the source
+    /// of a different profile. Creating the link replaces it.
+    Stale,
+    /// Something else occupies the target path.
+    Conflict,
+    /// The repository file the link should point to does not exist.
+    SourceMissing,
+    /// The target is a decrypted copy of the encrypted repository file.
+    Decrypted,
+}
+
+/// A manifest entry resolved against the filesystem.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LinkEntry {
+    pub spec: LinkSpec,
+    pub source: PathBuf,
+    /// Whether `source` comes from the active profile's overlay.
+    pub overlaid: bool,
+    pub target: PathBuf,
+    pub state: LinkState,
+    /// Whether the source is an encrypted secret, deployed as a decrypted
+    /// copy instead of a symlink.
+    pub encrypted: bool,
+}
+
+impl Manifest {
+    /// Reads the manifest from `repo_root`, returning an empty manifest when
+    /// the file does not exist.
+    pub fn load(repo_root: &Path) -> AppResult<Self> {
+        let path = repo_root.join(MANIFEST_FILE);
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let contents = fs::read_to_string(&path)?;
+        toml::from_str(&contents).map_err(|e| AppError::Manifest(e.to_string()))
+    }
+
+    /// Appends `links` to the manifest in `repo_root` as `[[link]]` tables,
+    /// keeping the rest of the file as it is, and creates the file if it
+    /// does not exist.
+    pub fn add_links(repo_root: &Path, links: &[LinkSpec]) -> AppResult<()> {
+        let path = repo_root.join(MANIFEST_FILE);
+        let contents = match fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(e.into()),
+        };
+        let mut document: DocumentMut = contents.parse().map_err(|e| AppError::Manifest(format!("{}", e)))?;
+        if !document.contains_key("link") {
+            document["link"] = Item::ArrayOfTables(ArrayOfTables::new());
+        }
+        let Some(tables) = document["link"].as_array_of_tables_mut() else {
+            return Err(AppError::Manifest("`link` is not an array of tables".to_string()));
+        };
+        for link in links {
+            let mut table = Table::new();
+            table["source"] = value(link.source.as_str());
+            table["target"] = value(link.target.as_str());
+            tables.push(table);
+        }
+        fs::write(path, document.to_string())?;
+        Ok(())
+    }
+
+    /// Resolves every entry of the manifest, taking sources from `profile`'s
+    /// overlay where it has them, and inspects its current state.
+    pub fn resolve(&self, repo_root: &Path, profile: Option<&Profile>) -> Vec<LinkEntry> {
+        let overlay = profile.and_then(|profile| profile.overlay.as_deref());
+        self.links
+            .iter()
+            .map(|spec| LinkEntry::resolve(spec, repo_root, overlay))
+            .collect()
+    }
+
+    /// The profile named `selected`, or else the first profile listing this
+    /// machine's hostname.
+    pub fn active_profile(&self, selected: Option<&str>) -> Option<&Profile> {
+        if let Some(name) = selected {
+            if let Some(profile) = self.profiles.iter().find(|p| p.name == name) {
+                return Some(profile);
+            }
+        }
+        let host = hostname()?;
+        self.profiles
+            .iter()
+            .find(|profile| profile.hosts.iter().any(|h| h.eq_ignore_ascii_case(host)))
+    }
+}
+
+impl LinkEntry {
+    pub fn resolve(spec: &LinkSpec, repo_root: &Path, overlay: Option<&str>) -> Self {
+        let overlay_source = overlay
+            .map(|dir| repo_root.join(dir).join(&spec.source))
+            .filter(|path| path.exists());
+        let overlaid = overlay_source.is_some();
+        let source = overlay_source.unwrap_or_else(|| repo_root.join(&spec.source));
+        let target = expand_home(&spec.target);
+        let state = link_state(&source, &target, repo_root, &spec.source);
+        Self {
+            spec: spec.clone(),
+            source,
+            overlaid,
+            target,
+            state,
+            encrypted: false,
+        }
+    }
+
+    /// Marks the source as an encrypted secret. A plain file at the target
+    /// is then its decrypted copy rather than a conflict.
+    pub fn mark_encrypted(&mut self) {
+        self.encrypted = true;
+        if self.state == LinkState::Conflict && !self.target.is_symlink() && self.target.is_file() {
+            self.state = LinkState::Decrypted;
+        }
+    }
+
+    /// Writes `plaintext`, the decrypted source, to the target, readable by
+    /// the user only. A symlink into the repository left there is replaced.
+    pub fn deploy(&self, plaintext: &[u8]) -> AppResult<()> {
+        match self.state {
+            LinkState::Missing | LinkState::Decrypted => {}
+            LinkState::Linked | LinkState::Stale => fs::remove_file(&self.target)?,
+            LinkState::Conflict => {
+                return Err(AppError::Link(format!(
+                    "{} already exists; remove it first",
+                    self.target.display()
+                )))
+            }
+            LinkState::SourceMissing => {
+                return Err(AppError::Link(format!(
+                    "{} does not exist in the repository",
+                    self.spec.source
+                )))
+            }
+        }
+        if let Some(parent) = self.target.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        std::io::Write::write_all(&mut options.open(&self.target)?, plaintext)?;
+        Ok(())
+    }
+
+    /// Creates the symlink, including any missing parent directories.
+    pub fn create(&self) -> AppResult<()> {
+        match self.state {
+            LinkState::Missing => {
+                if let Some(parent) = self.target.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+                symlink(&self.source, &self.target)?;
+                Ok(())
+            }
+            LinkState::Stale => {
+                fs::remove_file(&self.target)?;
+                symlink(&self.source, &self.target)?;
+                Ok(())
+            }
+            LinkState::Linked | LinkState::Decrypted => Ok(()),
+            LinkState::Conflict => Err(AppError::Link(format!(
+                "{} already exists; adopt or remove it first",
+                self.target.display()
+            ))),
+            LinkState::SourceMissing => Err(AppError::Link(format!(
+                "{} does not exist in the repository",
+                self.spec.source
+            ))),
+        }
+    }
+
+    /// Removes the symlink, or the decrypted copy of a secret. Only links
+    /// that point into the repository are touched, so user files are never
+    /// deleted.
+    pub fn remove(&self) -> AppResult<()> {
+        if !matches!(self.state, LinkState::Linked | LinkState::Stale | LinkState::Decrypted) {
+            return Err(AppError::Link(format!(
+                "{} is not managed by dotatui",
+                self.target.display()
+            )));
+        }
+        fs::remove_file(&self.target)?;
+        Ok(())
+    }
+
+    /// Moves the existing file at the target into the repository, replacing
+    /// the repository copy, and links it back in place. The decrypted copy
+    /// of a secret is copied back instead, in plain text until it is staged.
+    pub fn adopt(&self) -> AppResult<()> {
+        if self.state == LinkState::Decrypted {
+            fs::copy(&self.target, &self.source)?;
+            return Ok(());
+        }
+        if self.state != LinkState::Conflict {
+            return Err(AppError::Link(format!(
+                "nothing to adopt at {}",
+                self.target.display()
+            )));
+        }
+        if self.target.is_symlink() {
+            return Err(AppError::Link(format!(
+                "{} is a symlink to somewhere else",
+                self.target.display()
+            )));
+        }
+        if let Some(parent) = self.source.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        if self.source.is_dir() {
+            fs::remove_dir_all(&self.source)?;
+        } else if self.source.exists() {
+            fs::remove_file(&self.source)?;
+        }
+        move_path(&self.target, &self.source)?;
+        symlink(&self.source, &self.target)?;
+        Ok(())
+    }
+}
+
+fn link_state(source: &Path, target: &Path, repo_root: &Path, spec_source: &str) -> LinkState {
+    if let Ok(dest) = fs::read_link(target) {
+        if dest == source {
+            return LinkState::Linked;
+        }
+        // Another variant of the same file, e.g. from a different overlay.
+        if dest.starts_with(repo_root) && dest.ends_with(spec_source) {
+            return LinkState::Stale;
+        }
+        return LinkState::Conflict;
+    }
+    if !source.exists() {
+        LinkState::SourceMissing
+    } else if target.exists() {
+        LinkState::Conflict
+    } else {
+        LinkState::Missing
+    }
+}
+
+/// Expands a leading `~` to the user's home directory.
+pub fn expand_home(path: &str) -> PathBuf {
+    match (path.strip_prefix('~'), home_dir()) {
+        (Some(rest), Some(home)) => home.join(rest.trim_start_matches(['/', '\\'])),
+        _ => PathBuf::from(path),
+    }
+}
+
+/// The machine's hostname, without any domain part. It is looked up once,
+/// as the fallback runs `hostname`, and profiles are matched on every
+/// refresh.
+pub fn hostname() -> Option<&'static str> {
+    static HOSTNAME: OnceLock<Option<String>> = OnceLock::new();
+    HOSTNAME
+        .get_or_init(|| {
+            let name = match std::env::var("COMPUTERNAME") {
+                Ok(name) => name,
+                Err(_) => fs::read_to_string("/proc/sys/kernel/hostname")
+                    .or_else(|_| fs::read_to_string("/etc/hostname"))
+                    .ok()
+                    .or_else(|| {
+                        let output = Command::new("hostname").output().ok()?;
+                        Some(String::from_utf8_lossy(&output.stdout).into_owned())
+                    })?,
+            };
+            let name = name.trim().split('.').next().unwrap_or_default();
+            (!name.is_empty()).then(|| name.to_string())
+        })
+        .as_deref()
+}
+
+pub fn home_dir() -> Option<PathBuf> {
+    std::env::var_os("HOME")
+        .or_else(|| std::env::var_os("USERPROFILE"))
+        .map(PathBuf::from)
+}
+
+fn move_path(from: &Path, to: &Path) -> AppResult<()> {
+    if fs::rename(from, to).is_ok() {
+        return Ok(());
+    }
+    // `rename` fails across filesystems; fall back to copy + delete for files.
+    if from.is_dir() {
+        return Err(AppError::Link(format!(
+            "cannot move directory {} across filesystems",
+            from.display()
+        )));
+    }
+    fs::copy(from, to)?;
+    fs::remove_file(from)?;
+    Ok(())
+}
+
+#[cfg(unix)]
+fn symlink(source: &Path, target: &Path) -> std::io::Result<()> {
+    std::os::unix::fs::symlink(source, target)
+}
+
+#[cfg(windows)]
+fn symlink(source: &Path, target: &Path) -> std::io::Result<()> {
+    if source.is_dir() {
+        std::os::windows::fs::symlink_dir(source, target)
+    } else {
+        std::os::windows::fs::symlink_file(source, target)
+    }
+}
@@ -0,0 +1,191 @@
+//! src/dotfiles/apply.rs
+
+use super::{expand_home, home_dir, move_path, LinkEntry, LinkState};
+use crate::error::{AppError, AppResult};
+use chrono::Local;
+use std::{
+    env, fs,
+    path::{Component, Path, PathBuf},
+};
+
+/// What `apply` did with one link.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ApplyOutcome {
+    /// The target was right already.
+    Unchanged,
+    /// The symlink was created.
+    Linked,
+    /// A symlink to another file of the repository was replaced.
+    Relinked,
+    /// The decrypted copy of a secret was written.
+    Copied,
+    /// The file in the way was moved to this path before linking.
+    BackedUp(PathBuf),
+    /// Nothing could be done, for this reason.
+    Failed(String),
+}
+
+impl ApplyOutcome {
+    pub fn label(&self) -> &'static str {
+        match self {
+            ApplyOutcome::Unchanged => "unchanged",
+            ApplyOutcome::Linked => "linked",
+            ApplyOutcome::Relinked => "relinked",
+            ApplyOutcome::Copied => "copied",
+            ApplyOutcome::BackedUp(_) => "backed up",
+            ApplyOutcome::Failed(_) => "failed",
+        }
+    }
+
+    /// The backup path or the reason of a failure.
+    pub fn detail(&self) -> String {
+        match self {
+            ApplyOutcome::BackedUp(path) => format!("old file in {}", path.display()),
+            ApplyOutcome::Failed(reason) => reason.clone(),
+            _ => String::new(),
+        }
+    }
+}
+
+/// The result of applying one link.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ApplyResult {
+    pub target: PathBuf,
+    pub outcome: ApplyOutcome,
+}
+
+/// A directory named after the current time below `configured`, the
+/// `backup_dir` setting, or else below `$XDG_STATE_HOME/dotatui/backups`
+/// (falling back to `~/.local/state`). Every run gets one of its own,
+/// created only when something is backed up.
+pub fn backup_dir(configured: Option<&Path>) -> PathBuf {
+    let base = match configured {
+        Some(dir) => expand_home(&dir.to_string_lossy()),
+        None => env::var_os("XDG_STATE_HOME")
+            .map(PathBuf::from)
+            .or_else(|| home_dir().map(|home| home.join(".local").join("state")))
+            .unwrap_or_else(env::temp_dir)
+            .join("dotatui")
+            .join("backups"),
+    };
+    base.join(Local::now().format("%Y-%m-%d_%H-%M-%S").to_string())
+}
+
+/// Brings every target of `entries` in line with the repository: missing
+/// and stale symlinks are (re)created, secrets are written decrypted with
+/// `decrypt`, and whatever else is in the way is first moved below
+/// `backup_dir`, keeping its path relative to the home directory. One
+/// failing link does not stop the others.
+pub fn apply(
+    entries: &[LinkEntry],
+    backup_dir: &Path,
+    mut decrypt: impl FnMut(&LinkEntry) -> AppResult<Vec<u8>>,
+) -> Vec<ApplyResult> {
+    entries
+        .iter()
+        .map(|entry| ApplyResult {
+            target: entry.target.clone(),
+            outcome: apply_one(entry, backup_dir, &mut decrypt).unwrap_or_else(|e| ApplyOutcome::Failed(e.to_string())),
+        })
+        .collect()
+}
+
+fn apply_one(
+    entry: &LinkEntry,
+    backup_dir: &Path,
+    decrypt: &mut impl FnMut(&LinkEntry) -> AppResult<Vec<u8>>,
+) -> AppResult<ApplyOutcome> {
+    if entry.state == LinkState::SourceMissing {
+        return Err(AppError::Link(format!("{} does not exist in the repository", entry.spec.source)));
+    }
+    if entry.encrypted {
+        let plaintext = decrypt(entry)?;
+        return match entry.state {
+            LinkState::Decrypted if fs::read(&entry.target)? == plaintext => Ok(ApplyOutcome::Unchanged),
+            // The copy was edited since it was written, or is not one.
+            LinkState::Decrypted | LinkState::Conflict => {
+                let backup = back_up(&entry.target, backup_dir)?;
+                missing(entry).deploy(&plaintext)?;
+                Ok(ApplyOutcome::BackedUp(backup))
+            }
+            _ => {
+                entry.deploy(&plaintext)?;
+                Ok(ApplyOutcome::Copied)
+            }
+        };
+    }
+    match entry.state {
+        LinkState::Linked => Ok(ApplyOutcome::Unchanged),
+        LinkState::Missing => entry.create().map(|()| ApplyOutcome::Linked),
+        LinkState::Stale => entry.create().map(|()| ApplyOutcome::Relinked),
+        _ => {
+            let backup = back_up(&entry.target, backup_dir)?;
+            missing(entry).create()?;
+            Ok(ApplyOutcome::BackedUp(backup))
+        }
+    }
+}
+
+/// `entry` once its target has been moved out of the way.
+fn missing(entry: &LinkEntry) -> LinkEntry {
+    LinkEntry {
+        state: LinkState::Missing,
+        ..entry.clone()
+    }
+}
+
+/// Moves `target` into `backup_dir` and returns where it went.
+fn back_up(target: &Path, backup_dir: &Path) -> AppResult<PathBuf> {
+    let relative = home_dir()
+        .and_then(|home| target.strip_prefix(home).ok().map(Path::to_path_buf))
+        .unwrap_or_else(|| target.components().filter(|c| matches!(c, Component::Normal(_))).collect());
+    let backup = backup_dir.join(relative);
+    if let Some(parent) = backup.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    move_path(target, &backup)?;
+    Ok(backup)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dotfiles::LinkSpec;
+
+    fn entry(repo: &Path, source: &str, target: &Path) -> LinkEntry {
+        let spec = LinkSpec {
+            source: source.to_string(),
+            target: target.display().to_string(),
+        };
+        LinkEntry::resolve(&spec, repo, None)
+    }
+
+    #[test]
+    fn links_are_created_and_files_in_the_way_backed_up() {
+        let repo = tempfile::TempDir::new().unwrap();
+        let home = tempfile::TempDir::new().unwrap();
+        let backups = tempfile::TempDir::new().unwrap();
+        fs::write(repo.path().join("zshrc"), "new\n").unwrap();
+        fs::write(repo.path().join("vimrc"), "new\n").unwrap();
+        fs::write(home.path().join(".vimrc"), "old\n").unwrap();
+        let entries = [
+            entry(repo.path(), "zshrc", &home.path().join(".zshrc")),
+            entry(repo.path(), "vimrc", &home.path().join(".vimrc")),
+            entry(repo.path(), "gone", &home.path().join(".gone")),
+        ];
+
+        let results = apply(&entries, backups.path(), |_| unreachable!());
+        assert_eq!(results[0].outcome, ApplyOutcome::Linked);
+        let ApplyOutcome::BackedUp(backup) = &results[1].outcome else {
+            panic!("{:?}", results[1]);
+        };
+        assert!(backup.starts_with(backups.path()));
+        assert_eq!(fs::read_to_string(backup).unwrap(), "old\n");
+        assert_eq!(fs::read_to_string(home.path().join(".vimrc")).unwrap(), "new\n");
+        assert!(matches!(results[2].outcome, ApplyOutcome::Failed(_)));
+
+        let entries: Vec<_> = entries.iter().map(|e| entry(repo.path(), &e.spec.source, &e.target)).collect();
+        let results = apply(&entries[..2], backups.path(), |_| unreachable!());
+        assert!(results.iter().all(|result| result.outcome == ApplyOutcome::Unchanged));
+    }
+}
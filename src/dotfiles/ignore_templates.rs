@@ -0,0 +1,168 @@
+//! src/dotfiles/ignore_templates.rs
+
+use crate::git::ignore::{self, IgnoreFile, IgnoreRule};
+use std::{fs, path::Path};
+
+/// How many directory levels below the work tree are looked at. A dotfiles
+/// work tree is often the whole home directory, so the scan stays near the
+/// top, where shells and tools put these files.
+pub const SCAN_DEPTH: usize = 3;
+
+/// The entries looked at before the scan stops, for the same reason.
+const SCAN_LIMIT: usize = 20_000;
+
+/// Curated ignore patterns for a kind of file that should stay out of a
+/// dotfiles repository.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IgnoreTemplate {
+    pub name: &'static str,
+    pub patterns: &'static [&'static str],
+}
+
+pub const TEMPLATES: [IgnoreTemplate; 5] = [
+    IgnoreTemplate {
+        name: "SSH private keys",
+        patterns: &["id_rsa", "id_dsa", "id_ecdsa", "id_ed25519", "*.pem"],
+    },
+    IgnoreTemplate {
+        name: "Credentials",
+        patterns: &[".netrc", "_netrc", ".git-credentials", ".pgpass", ".aws/credentials"],
+    },
+    IgnoreTemplate {
+        name: "Shell and tool history",
+        patterns: &["*history", ".lesshst", ".viminfo"],
+    },
+    IgnoreTemplate {
+        name: "Environment files",
+        patterns: &[".env", ".env.*", "!.env.example"],
+    },
+    IgnoreTemplate {
+        name: "GnuPG secrets",
+        patterns: &[".gnupg/private-keys-v1.d/", ".gnupg/secring.gpg", ".gnupg/random_seed"],
+    },
+];
+
+impl IgnoreTemplate {
+    /// Whether the template ignores `path`, relative to the work tree. The
+    /// patterns are read as if they were in the root `.gitignore`, so a
+    /// later `!pattern` re-includes what an earlier one matched.
+    pub fn matches(&self, path: &str, is_dir: bool) -> bool {
+        let file = IgnoreFile {
+            path: ".gitignore".into(),
+            label: ".gitignore".to_string(),
+            base: String::new(),
+            in_work_tree: true,
+        };
+        let rules: Vec<IgnoreRule> = self
+            .patterns
+            .iter()
+            .enumerate()
+            .map(|(index, pattern)| IgnoreRule {
+                file: file.clone(),
+                line: index + 1,
+                pattern: pattern.to_string(),
+                ignore_case: false,
+            })
+            .collect();
+        ignore::deciding_rule(&rules, path, is_dir).is_some_and(|rule| !rule.is_negated())
+    }
+
+    /// The patterns `gitignore`, the contents of an ignore file, lacks.
+    pub fn missing_patterns(&self, gitignore: &str) -> Vec<&'static str> {
+        let existing: Vec<&str> = gitignore.lines().map(str::trim).collect();
+        self.patterns
+            .iter()
+            .copied()
+            .filter(|pattern| !existing.contains(pattern))
+            .collect()
+    }
+}
+
+/// A template and the files of the work tree it would ignore.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TemplateMatch {
+    pub template: IgnoreTemplate,
+    /// Relative to the work tree, with `/` as separator; directories end
+    /// in `/`.
+    pub paths: Vec<String>,
+}
+
+/// Looks for files in `work_tree` that `TEMPLATES` match and that
+/// `is_ignored` does not already cover, returning the templates that found
+/// any. `.git` and ignored directories are not entered.
+pub fn detect(work_tree: &Path, is_ignored: impl Fn(&str) -> bool) -> Vec<TemplateMatch> {
+    let mut entries = Vec::new();
+    scan(work_tree, "", 0, &is_ignored, &mut entries);
+    TEMPLATES
+        .iter()
+        .filter_map(|template| {
+            let paths: Vec<String> = entries
+                .iter()
+                .filter(|(path, is_dir)| template.matches(path, *is_dir))
+                .map(|(path, is_dir)| if *is_dir { format!("{}/", path) } else { path.clone() })
+                .collect();
+            (!paths.is_empty()).then_some(TemplateMatch {
+                template: *template,
+                paths,
+            })
+        })
+        .collect()
+}
+
+/// Collects the entries below `dir` that are not ignored, as paths
+/// relative to the work tree and whether they are directories.
+fn scan(dir: &Path, prefix: &str, depth: usize, is_ignored: &impl Fn(&str) -> bool, entries: &mut Vec<(String, bool)>) {
+    let Ok(read_dir) = fs::read_dir(dir) else {
+        return;
+    };
+    let mut children: Vec<_> = read_dir.flatten().collect();
+    children.sort_by_key(|entry| entry.file_name());
+    for entry in children {
+        if entries.len() >= SCAN_LIMIT {
+            return;
+        }
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if name == ".git" {
+            continue;
+        }
+        let path = format!("{}{}", prefix, name);
+        // Symbolic links to directories are not followed.
+        let is_dir = entry.file_type().is_ok_and(|kind| kind.is_dir());
+        if is_ignored(&path) {
+            continue;
+        }
+        entries.push((path.clone(), is_dir));
+        let matched = TEMPLATES.iter().any(|template| template.matches(&path, is_dir));
+        if is_dir && !matched && depth + 1 < SCAN_DEPTH {
+            scan(&entry.path(), &format!("{}/", path), depth + 1, is_ignored, entries);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn templates_find_secrets_that_are_not_ignored_yet() {
+        let dir = tempfile::TempDir::new().unwrap();
+        for path in [".ssh/id_ed25519", ".ssh/id_ed25519.pub", ".zsh_history", ".env", ".env.example", "a/b/c/.netrc"] {
+            let path = dir.path().join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, "").unwrap();
+        }
+        fs::create_dir_all(dir.path().join(".gnupg/private-keys-v1.d")).unwrap();
+
+        let found = detect(dir.path(), |path| path == ".zsh_history");
+        let found: Vec<_> = found.iter().map(|m| (m.template.name, m.paths.clone())).collect();
+        assert_eq!(
+            found,
+            [
+                ("SSH private keys", vec![".ssh/id_ed25519".to_string()]),
+                ("Environment files", vec![".env".to_string()]),
+                ("GnuPG secrets", vec![".gnupg/private-keys-v1.d/".to_string()]),
+            ]
+        );
+        assert_eq!(TEMPLATES[3].missing_patterns("node_modules\n.env\n"), [".env.*", "!.env.example"]);
+    }
+}
@@ -0,0 +1,265 @@
+//! src/dotfiles/import.rs
+
+use super::LinkSpec;
+use crate::error::AppResult;
+use std::{fs, io, path::Path};
+
+/// Directories under `$HOME` that many programs share, so a Stow package
+/// links what it puts into them instead of the directory itself, like
+/// Stow's tree folding does once a second package uses them.
+const SHARED_DIRS: [&str; 5] = [".config", ".local", ".local/bin", ".local/share", ".local/state"];
+
+/// Names Stow leaves out of a package by default.
+const STOW_IGNORED: [&str; 6] = [".git", ".gitignore", ".gitmodules", "README", "LICENSE", "COPYING"];
+
+/// Attribute prefixes of chezmoi source names that a symlink keeps or does
+/// not need, in the order chezmoi writes them.
+const CHEZMOI_ATTRIBUTES: [&str; 6] = ["exact_", "private_", "readonly_", "empty_", "executable_", "external_"];
+
+/// chezmoi source names that are not plain files: scripts, files that are
+/// created or modified once, removals and symlinks.
+const CHEZMOI_SPECIAL: [&str; 5] = ["run_", "create_", "modify_", "remove_", "symlink_"];
+
+/// The tool whose layout the links are imported from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImportSource {
+    Stow,
+    Chezmoi,
+}
+
+impl ImportSource {
+    pub fn name(self) -> &'static str {
+        match self {
+            ImportSource::Stow => "GNU Stow",
+            ImportSource::Chezmoi => "chezmoi",
+        }
+    }
+}
+
+/// The links a Stow or chezmoi layout implies.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Import {
+    pub source: ImportSource,
+    pub links: Vec<LinkSpec>,
+    /// Files that cannot be linked, e.g. chezmoi templates and scripts,
+    /// with the reason.
+    pub skipped: Vec<(String, &'static str)>,
+}
+
+/// Looks for a chezmoi source directory at `repo_root` (or where its
+/// `.chezmoiroot` points), and else for Stow packages: directories at the
+/// top whose contents mirror `$HOME`, i.e. that hold a dotfile. Returns
+/// `None` when neither is there.
+pub fn detect(repo_root: &Path) -> AppResult<Option<Import>> {
+    if let Some(import) = chezmoi(repo_root)? {
+        return Ok(Some(import));
+    }
+    stow(repo_root)
+}
+
+fn stow(repo_root: &Path) -> AppResult<Option<Import>> {
+    let mut links = Vec::new();
+    for package in sorted_entries(repo_root)? {
+        let path = repo_root.join(&package);
+        if package.starts_with('.') || !path.is_dir() {
+            continue;
+        }
+        let entries = sorted_entries(&path)?;
+        let is_package = entries
+            .iter()
+            .any(|name| (name.starts_with('.') || name.starts_with("dot-")) && !is_stow_ignored(name));
+        if is_package {
+            stow_links(repo_root, &package, "", &mut links)?;
+        }
+    }
+    Ok((!links.is_empty()).then_some(Import {
+        source: ImportSource::Stow,
+        links,
+        skipped: Vec::new(),
+    }))
+}
+
+/// Adds the links of `dir`, a directory of a Stow package mirroring
+/// `target` below `$HOME`.
+fn stow_links(repo_root: &Path, dir: &str, target: &str, links: &mut Vec<LinkSpec>) -> io::Result<()> {
+    for name in sorted_entries(&repo_root.join(dir))? {
+        if (target.is_empty() && is_stow_ignored(&name)) || name.ends_with('~') {
+            continue;
+        }
+        let source = format!("{}/{}", dir, name);
+        // `stow --dotfiles` names `.zshrc` `dot-zshrc`.
+        let name = match name.strip_prefix("dot-") {
+            Some(rest) => format!(".{}", rest),
+            None => name,
+        };
+        let target = if target.is_empty() { name } else { format!("{}/{}", target, name) };
+        if SHARED_DIRS.contains(&target.as_str()) && repo_root.join(&source).is_dir() {
+            stow_links(repo_root, &source, &target, links)?;
+        } else {
+            links.push(LinkSpec {
+                source,
+                target: format!("~/{}", target),
+            });
+        }
+    }
+    Ok(())
+}
+
+fn is_stow_ignored(name: &str) -> bool {
+    STOW_IGNORED
+        .iter()
+        .any(|ignored| name == *ignored || name.starts_with(&format!("{}.", ignored)))
+}
+
+fn chezmoi(repo_root: &Path) -> AppResult<Option<Import>> {
+    let source_dir = match fs::read_to_string(repo_root.join(".chezmoiroot")) {
+        Ok(root) => root.trim().trim_end_matches('/').to_string(),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(e.into()),
+    };
+    let entries = sorted_entries(&repo_root.join(&source_dir))?;
+    let is_source = !source_dir.is_empty()
+        || entries
+            .iter()
+            .any(|name| name.starts_with(".chezmoi") || name.starts_with("dot_"));
+    if !is_source {
+        return Ok(None);
+    }
+    let mut import = Import {
+        source: ImportSource::Chezmoi,
+        links: Vec::new(),
+        skipped: Vec::new(),
+    };
+    chezmoi_links(repo_root, &source_dir, "", &mut import)?;
+    Ok(Some(import))
+}
+
+/// Adds a link for every plain file below `dir` of a chezmoi source
+/// directory; its target is `target` below `$HOME`. Names starting with a
+/// dot are chezmoi's own files. `.chezmoiignore` is not read.
+fn chezmoi_links(repo_root: &Path, dir: &str, target: &str, import: &mut Import) -> io::Result<()> {
+    for name in sorted_entries(&repo_root.join(dir))? {
+        if name.starts_with('.') {
+            continue;
+        }
+        let source = if dir.is_empty() { name.clone() } else { format!("{}/{}", dir, name) };
+        if CHEZMOI_SPECIAL.iter().any(|prefix| name.starts_with(prefix)) {
+            import.skipped.push((source, "a script or special file"));
+            continue;
+        }
+        if name.starts_with("encrypted_") {
+            import.skipped.push((source, "encrypted"));
+            continue;
+        }
+        if name.ends_with(".tmpl") {
+            import.skipped.push((source, "a template"));
+            continue;
+        }
+        let target = match target {
+            "" => chezmoi_target_name(&name),
+            _ => format!("{}/{}", target, chezmoi_target_name(&name)),
+        };
+        if repo_root.join(&source).is_dir() {
+            chezmoi_links(repo_root, &source, &target, import)?;
+        } else {
+            import.links.push(LinkSpec {
+                source,
+                target: format!("~/{}", target),
+            });
+        }
+    }
+    Ok(())
+}
+
+/// The name of the target of a chezmoi source name, e.g. `.ssh` for
+/// `private_dot_ssh`.
+fn chezmoi_target_name(name: &str) -> String {
+    let mut name = name;
+    for attribute in CHEZMOI_ATTRIBUTES {
+        name = name.strip_prefix(attribute).unwrap_or(name);
+    }
+    if let Some(rest) = name.strip_prefix("literal_") {
+        return rest.strip_suffix(".literal").unwrap_or(rest).to_string();
+    }
+    let name = name.strip_suffix(".literal").unwrap_or(name);
+    match name.strip_prefix("dot_") {
+        Some(rest) => format!(".{}", rest),
+        None => name.to_string(),
+    }
+}
+
+fn sorted_entries(dir: &Path) -> io::Result<Vec<String>> {
+    let mut names = fs::read_dir(dir)?
+        .map(|entry| entry.map(|entry| entry.file_name().to_string_lossy().into_owned()))
+        .collect::<io::Result<Vec<_>>>()?;
+    names.sort();
+    Ok(names)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn layout(paths: &[&str]) -> tempfile::TempDir {
+        let dir = tempfile::TempDir::new().unwrap();
+        for path in paths {
+            let path = dir.path().join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, "").unwrap();
+        }
+        dir
+    }
+
+    fn mappings(import: &Import) -> Vec<(&str, &str)> {
+        import.links.iter().map(|link| (link.source.as_str(), link.target.as_str())).collect()
+    }
+
+    #[test]
+    fn stow_packages_link_their_top_and_into_shared_directories() {
+        let dir = layout(&[
+            "zsh/.zshrc",
+            "zsh/README.md",
+            "nvim/.config/nvim/init.lua",
+            "git/dot-gitconfig",
+            "scripts/install.sh",
+            ".git/HEAD",
+        ]);
+        let import = detect(dir.path()).unwrap().unwrap();
+        assert_eq!(import.source, ImportSource::Stow);
+        assert_eq!(
+            mappings(&import),
+            [
+                ("git/dot-gitconfig", "~/.gitconfig"),
+                ("nvim/.config/nvim", "~/.config/nvim"),
+                ("zsh/.zshrc", "~/.zshrc"),
+            ]
+        );
+        assert_eq!(detect(layout(&["src/main.rs"]).path()).unwrap(), None);
+    }
+
+    #[test]
+    fn chezmoi_names_are_translated_and_templates_skipped() {
+        let dir = layout(&[
+            ".chezmoiroot",
+            "home/dot_zshrc",
+            "home/private_dot_ssh/config",
+            "home/dot_config/git/config.tmpl",
+            "home/executable_dot_local/bin/literal_dot_keep",
+            "home/run_once_install.sh",
+            "home/.chezmoiignore",
+        ]);
+        fs::write(dir.path().join(".chezmoiroot"), "home\n").unwrap();
+        let import = detect(dir.path()).unwrap().unwrap();
+        assert_eq!(import.source, ImportSource::Chezmoi);
+        assert_eq!(
+            mappings(&import),
+            [
+                ("home/dot_zshrc", "~/.zshrc"),
+                ("home/executable_dot_local/bin/literal_dot_keep", "~/.local/bin/dot_keep"),
+                ("home/private_dot_ssh/config", "~/.ssh/config"),
+            ]
+        );
+        let skipped: Vec<&str> = import.skipped.iter().map(|(path, _)| path.as_str()).collect();
+        assert_eq!(skipped, ["home/dot_config/git/config.tmpl", "home/run_once_install.sh"]);
+    }
+}
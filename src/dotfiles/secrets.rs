@@ -0,0 +1,181 @@
+//! src/dotfiles/secrets.rs
+
+use super::{expand_home, LinkEntry};
+use crate::{
+    config::{Cipher, EncryptionConfig},
+    error::{AppError, AppResult},
+    git::ignore::{self, IgnoreFile, IgnoreRule},
+};
+use std::{
+    io::Write,
+    path::Path,
+    process::{Command, Stdio},
+    thread,
+};
+
+/// How the files `age --armor` and `gpg --armor` write begin.
+const ARMOR_HEADERS: [&str; 2] = ["-----BEGIN AGE ENCRYPTED FILE-----", "-----BEGIN PGP MESSAGE-----"];
+
+/// Whether `path`, relative to the work tree, is one of the secret files.
+/// The patterns are read like those of the root `.gitignore`.
+pub fn is_secret(config: &EncryptionConfig, path: &str) -> bool {
+    let file = IgnoreFile {
+        path: ".gitignore".into(),
+        label: "[encryption]".to_string(),
+        base: String::new(),
+        in_work_tree: true,
+    };
+    let rules: Vec<IgnoreRule> = config
+        .patterns
+        .iter()
+        .enumerate()
+        .map(|(index, pattern)| IgnoreRule {
+            file: file.clone(),
+            line: index + 1,
+            pattern: pattern.clone(),
+            ignore_case: false,
+        })
+        .collect();
+    ignore::deciding_rule(&rules, path, false).is_some_and(|rule| !rule.is_negated())
+}
+
+/// Marks the entries whose source is a secret with
+/// `LinkEntry::mark_encrypted`.
+pub fn mark_secrets(config: Option<&EncryptionConfig>, entries: &mut [LinkEntry]) {
+    let Some(config) = config else {
+        return;
+    };
+    for entry in entries.iter_mut().filter(|entry| is_secret(config, &entry.spec.source)) {
+        entry.mark_encrypted();
+    }
+}
+
+/// Whether `contents` were already encrypted, so that staging them again
+/// leaves them alone.
+pub fn is_encrypted(contents: &[u8]) -> bool {
+    let start = String::from_utf8_lossy(&contents[..contents.len().min(64)]);
+    ARMOR_HEADERS.iter().any(|header| start.trim_start().starts_with(header))
+}
+
+/// Encrypts `plaintext` to the configured recipients, ASCII-armored so
+/// that git can diff and merge the file as text.
+pub fn encrypt(config: &EncryptionConfig, plaintext: &[u8]) -> AppResult<Vec<u8>> {
+    if config.recipients.is_empty() {
+        return Err(AppError::Secret("no recipients in [encryption]".to_string()));
+    }
+    let mut command = Command::new(program(config));
+    match config.tool {
+        Cipher::Age => {
+            command.arg("--armor");
+            for recipient in &config.recipients {
+                command.args(["--recipient", recipient]);
+            }
+        }
+        Cipher::Gpg => {
+            command.args(["--batch", "--yes", "--armor", "--trust-model", "always", "--encrypt"]);
+            for recipient in &config.recipients {
+                command.args(["--recipient", recipient]);
+            }
+        }
+    }
+    run(&mut command, plaintext).map_err(|e| match e {
+        AppError::SecretNeeded => AppError::Secret("encrypting needs no passphrase".to_string()),
+        e => e,
+    })
+}
+
+/// Decrypts `file`. `secret` is what the user entered when asked: the
+/// identity file for age, the passphrase for gpg. Fails with
+/// `AppError::SecretNeeded` when one is needed and missing.
+pub fn decrypt(config: &EncryptionConfig, file: &Path, secret: Option<&str>) -> AppResult<Vec<u8>> {
+    let mut command = Command::new(program(config));
+    match config.tool {
+        Cipher::Age => {
+            let identity = match (secret, &config.identity) {
+                (Some(path), _) => expand_home(path),
+                (None, Some(path)) => expand_home(&path.to_string_lossy()),
+                (None, None) => return Err(AppError::SecretNeeded),
+            };
+            command.arg("--decrypt").arg("--identity").arg(identity).arg(file);
+            run(&mut command, &[])
+        }
+        Cipher::Gpg => {
+            // The passphrase comes from the agent or stdin, never from a
+            // pinentry drawing over the TUI.
+            command.args(["--batch", "--quiet", "--pinentry-mode", "loopback"]);
+            if secret.is_some() {
+                command.args(["--passphrase-fd", "0"]);
+            }
+            command.arg("--decrypt").arg(file);
+            run(&mut command, secret.unwrap_or_default().as_bytes())
+        }
+    }
+}
+
+fn program(config: &EncryptionConfig) -> String {
+    config.program.clone().unwrap_or_else(|| match config.tool {
+        Cipher::Age => "age".to_string(),
+        Cipher::Gpg => "gpg".to_string(),
+    })
+}
+
+/// Feeds `input` to the command and returns what it prints.
+fn run(command: &mut Command, input: &[u8]) -> AppResult<Vec<u8>> {
+    let program = command.get_program().to_string_lossy().into_owned();
+    let mut child = command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| AppError::Secret(format!("could not run {}: {}", program, e)))?;
+    // Written from another thread: a large file fills the stdout pipe
+    // before it is read whole, and the command then waits for us to read.
+    let writer = child.stdin.take().map(|mut stdin| {
+        let input = input.to_vec();
+        thread::spawn(move || stdin.write_all(&input))
+    });
+    let output = child.wait_with_output()?;
+    let written = writer.map_or(Ok(()), |writer| writer.join().unwrap_or(Ok(())));
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        if stderr.to_lowercase().contains("passphrase") {
+            return Err(AppError::SecretNeeded);
+        }
+        return Err(AppError::Secret(format!(
+            "{} failed: {}",
+            program,
+            stderr.lines().last().unwrap_or("no output")
+        )));
+    }
+    written?;
+    Ok(output.stdout)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn secrets_are_matched_like_gitignore_patterns_and_recognised_when_encrypted() {
+        let config = EncryptionConfig {
+            patterns: vec!["secrets/**".to_string(), "*.key".to_string(), "!public.key".to_string()],
+            ..EncryptionConfig::default()
+        };
+        assert!(is_secret(&config, "secrets/aws/credentials"));
+        assert!(is_secret(&config, ".ssh/deploy.key"));
+        assert!(!is_secret(&config, "public.key"));
+        assert!(!is_secret(&config, ".zshrc"));
+
+        assert!(is_encrypted(b"-----BEGIN AGE ENCRYPTED FILE-----\nYWdl\n"));
+        assert!(is_encrypted(b"\n-----BEGIN PGP MESSAGE-----\n"));
+        assert!(!is_encrypted(b"TOKEN=1\n"));
+        assert!(matches!(decrypt(&config, Path::new("secrets/a"), None), Err(AppError::SecretNeeded)));
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn files_larger_than_a_pipe_go_through_the_command_whole() {
+        let input = vec![b'x'; 1 << 20];
+        assert_eq!(run(&mut Command::new("cat"), &input).unwrap(), input);
+    }
+}
@@ -19,8 +19,85 @@ pub enum AppError {
     #[error("No git repository found at or above the current directory")]
     RepoNotFound,
 
+    #[error("Bare repository has no work tree; pass --work-tree or set core.worktree")]
+    NoWorkTree,
+
     #[error("Push failed: {0}")]
     PushFailed(String),
+
+    #[error("Fetch failed: {0}")]
+    FetchFailed(String),
+
+    #[error("Clone failed: {0}")]
+    CloneFailed(String),
+
+    #[error("Invalid config file {0}")]
+    Config(String),
+
+    #[error("Repository is opened read-only")]
+    ReadOnly,
+
+    #[error("File watcher error: {0}")]
+    Watch(String),
+
+    #[error("Invalid link manifest: {0}")]
+    Manifest(String),
+
+    #[error("Link error: {0}")]
+    Link(String),
+
+    #[error("Conflict error: {0}")]
+    Conflict(String),
+
+    #[error("Could not run editor: {0}")]
+    Editor(String),
+
+    #[error("Could not run external tool: {0}")]
+    Tool(String),
+
+    #[error("Signing failed: {0}")]
+    Signing(String),
+
+    #[error("Hook error: {0}")]
+    Hook(String),
+
+    #[error("Forge error: {0}")]
+    Forge(String),
+
+    #[error("Cannot undo: {0}")]
+    Undo(String),
+
+    #[error("Cannot bisect: {0}")]
+    Bisect(String),
+
+    #[error("Worktree error: {0}")]
+    Worktree(String),
+
+    #[error("Invalid session file {0}")]
+    Session(String),
+
+    #[error("Script error: {0}")]
+    Script(String),
+
+    #[error("Could not set up logging: {0}")]
+    Logging(String),
+
+    #[error("The branch has no commits yet")]
+    NoCommits,
+
+    #[error("Encryption error: {0}")]
+    Secret(String),
+
+    #[error("A passphrase or identity is needed to decrypt")]
+    SecretNeeded,
+}
+
+impl AppError {
+    /// Whether the app can show the error and go on. Without the event
+    /// channel it cannot receive input anymore.
+    pub fn is_recoverable(&self) -> bool {
+        !matches!(self, AppError::EventChannelClosed)
+    }
 }
 
 /// A specialized `Result` type for application functions.
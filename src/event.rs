@@ -1,13 +1,110 @@
 //! src/event.rs
 
+use crate::doctor::Check;
 use crate::error::{AppError, AppResult};
-use crossterm::event::{self, Event as CrosstermEvent, KeyEvent, MouseEvent};
+use crate::forge::ForgeStatus;
+use crate::git::{
+    credentials::{CredentialPrompt, Prompter},
+    worker::{ChurnKey, FileDiff, RepoSnapshot},
+    Churn, CommitInfo, GitRepo, HistoryStats, MergeOutcome, RepoLocation,
+};
+use crate::task::TaskId;
+use crossterm::event::{self, Event as CrosstermEvent, KeyEvent, KeyEventKind, MouseEvent};
+use git2::Oid;
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use std::path::{Path, PathBuf};
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    mpsc as std_mpsc, Arc,
+};
 use std::time::Duration;
 use tokio::sync::mpsc;
 
+/// How long the file system has to be quiet before `FsChanged` is sent.
+const FS_DEBOUNCE: Duration = Duration::from_millis(300);
+/// How often `InputEvent::Tick` comes when `tick_interval_ms` is not set.
+pub const DEFAULT_TICK_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Files inside the git directory whose changes affect what the UI shows.
+const WATCHED_GIT_FILES: &[&str] = &["index", "HEAD", "packed-refs", "MERGE_HEAD"];
+
 #[derive(Debug)]
 pub enum AppEvent {
     PushFinished(AppResult<()>),
+    /// A push to chosen remotes ended; holds how it went on each remote.
+    PushToFinished(Vec<(String, AppResult<()>)>),
+    FetchFinished(AppResult<()>),
+    /// A line printed by a commit hook.
+    HookOutput(String),
+    /// The commit hooks ended; holds the message to commit, which
+    /// `commit-msg` may have changed.
+    HooksFinished(AppResult<String>),
+    /// A fetch started by the auto-fetch timer or `Action::Refresh` ended.
+    AutoFetchFinished(AppResult<()>),
+    /// The sync-mode commit with this generation ended; holds the commit,
+    /// or `None` when there was nothing to commit.
+    SyncCommitted(u64, AppResult<Option<Oid>>),
+    /// The push of a sync-mode commit finished.
+    SyncPushFinished(AppResult<()>),
+    PullFinished(AppResult<MergeOutcome>),
+    /// Progress of the background task `id`, in percent.
+    TaskProgress { id: TaskId, pct: u16, msg: String },
+    /// A clone started from the TUI ended; holds the new repository's location.
+    CloneFinished(AppResult<RepoLocation>),
+    /// Files in the work tree or git directory changed on disk.
+    FsChanged(Vec<PathBuf>),
+    /// The config file was written, replaced or removed.
+    ConfigChanged,
+    /// A background network task is blocked until the user answers a prompt.
+    CredentialsNeeded(CredentialRequest),
+    ForgeLoaded(AppResult<ForgeStatus>),
+    /// The checks of the doctor view ran.
+    DoctorChecked(Vec<Check>),
+    /// The history statistics for the stats view were computed.
+    StatsLoaded(AppResult<HistoryStats>),
+    /// The git worker answered the refresh with this generation.
+    RefreshLoaded(u64, AppResult<Box<RepoSnapshot>>),
+    /// The git worker loaded the diff requested with this generation.
+    DiffLoaded(u64, AppResult<FileDiff>),
+    /// The git worker loaded the whole history asked for with this
+    /// generation.
+    WholeLogLoaded(u64, AppResult<Vec<CommitInfo>>),
+    /// The git worker searched the log for the search with this generation.
+    LogSearched(u64, AppResult<Vec<usize>>),
+    /// The git worker counted the changes of these status items.
+    ChurnLoaded(Vec<(ChurnKey, Churn)>),
+}
+
+/// A credential question sent to the UI, with the channel to answer it on.
+/// Replying `None` cancels the authentication.
+#[derive(Debug)]
+pub struct CredentialRequest {
+    pub prompt: CredentialPrompt,
+    pub reply: std_mpsc::Sender<Option<String>>,
+}
+
+/// Forwards credential prompts from a background task to the UI and blocks
+/// until the user answers. Only use it from blocking threads.
+pub struct EventPrompter {
+    sender: mpsc::UnboundedSender<AppEvent>,
+}
+
+impl EventPrompter {
+    pub fn new(sender: mpsc::UnboundedSender<AppEvent>) -> Self {
+        Self { sender }
+    }
+}
+
+impl Prompter for EventPrompter {
+    fn ask(&mut self, prompt: &CredentialPrompt) -> Option<String> {
+        let (reply, answer) = std_mpsc::channel();
+        let request = CredentialRequest {
+            prompt: prompt.clone(),
+            reply,
+        };
+        self.sender.send(AppEvent::CredentialsNeeded(request)).ok()?;
+        answer.recv().ok().flatten()
+    }
 }
 
 /// Terminal events (user input).
@@ -15,6 +112,8 @@ pub enum AppEvent {
 pub enum InputEvent {
     Key(KeyEvent),
     Mouse(MouseEvent), // Add Mouse variant
+    /// The terminal changed size, so the screen has to be drawn again.
+    Resize,
     Tick,
 }
 
@@ -23,30 +122,56 @@ pub struct EventHandler {
     app_rx: mpsc::UnboundedReceiver<AppEvent>,
     app_tx: mpsc::UnboundedSender<AppEvent>,
     _input_handle: tokio::task::JoinHandle<()>,
+    _watcher: Option<RecommendedWatcher>,
+    _config_watcher: Option<RecommendedWatcher>,
+    /// Set while an external program (e.g. `$EDITOR`) owns the terminal.
+    input_paused: Arc<AtomicBool>,
+}
+
+impl Default for EventHandler {
+    fn default() -> Self {
+        Self::new(DEFAULT_TICK_INTERVAL)
+    }
 }
 
 impl EventHandler {
-    pub fn new() -> Self {
+    /// Starts reading input. Without input a `Tick` comes every
+    /// `tick_interval`.
+    pub fn new(tick_interval: Duration) -> Self {
         let (input_tx, input_rx) = mpsc::unbounded_channel();
         let (app_tx, app_rx) = mpsc::unbounded_channel();
 
+        // The crossterm poll loop blocks, so it gets a dedicated blocking thread
+        // instead of starving the runtime's worker threads.
+        let input_paused = Arc::new(AtomicBool::new(false));
         let input_handle = {
-            tokio::spawn(async move {
+            let paused = input_paused.clone();
+            tokio::task::spawn_blocking(move || {
                 loop {
-                    if event::poll(Duration::from_millis(100)).unwrap_or(false) {
-                        match event::read() {
-                            Ok(CrosstermEvent::Key(key)) => {
-                                if input_tx.send(InputEvent::Key(key)).is_err() {
-                                    break;
-                                }
-                            }
+                    if paused.load(Ordering::SeqCst) {
+                        std::thread::sleep(Duration::from_millis(50));
+                        continue;
+                    }
+                    if event::poll(tick_interval).unwrap_or(false) {
+                        // Input that arrives after pausing belongs to the
+                        // external program, so it must not be read here.
+                        if paused.load(Ordering::SeqCst) {
+                            continue;
+                        }
+                        let input = match event::read() {
+                            // Windows reports releases too, which would run
+                            // every key's action twice.
+                            Ok(CrosstermEvent::Key(key)) if key.kind == KeyEventKind::Release => None,
+                            Ok(CrosstermEvent::Key(key)) => Some(InputEvent::Key(key)),
                             // Capture mouse events
-                            Ok(CrosstermEvent::Mouse(mouse)) => {
-                                if input_tx.send(InputEvent::Mouse(mouse)).is_err() {
-                                    break;
-                                }
+                            Ok(CrosstermEvent::Mouse(mouse)) => Some(InputEvent::Mouse(mouse)),
+                            Ok(CrosstermEvent::Resize(..)) => Some(InputEvent::Resize),
+                            _ => None,
+                        };
+                        if let Some(input) = input {
+                            if input_tx.send(input).is_err() {
+                                break;
                             }
-                            _ => {} // Other events like Resize are ignored for now
                         }
                     }
                     if input_tx.send(InputEvent::Tick).is_err() {
@@ -61,9 +186,107 @@ impl EventHandler {
             app_rx,
             app_tx,
             _input_handle: input_handle,
+            _watcher: None,
+            _config_watcher: None,
+            input_paused,
         }
     }
 
+    /// Starts watching the repository's work tree and git directory, sending a
+    /// debounced `AppEvent::FsChanged` when relevant files change. Paths in
+    /// `ignored` (e.g. the log file) never trigger an event.
+    pub fn watch_repo(&mut self, repo: &GitRepo, ignored: Vec<PathBuf>) -> AppResult<()> {
+        let (fs_tx, mut fs_rx) = mpsc::unbounded_channel::<PathBuf>();
+        let work_tree = repo.path();
+        let git_dir = repo.location().git_dir;
+        let watched_git_dir = git_dir.clone();
+        let mut watcher = notify::recommended_watcher(move |res: notify::Result<notify::Event>| {
+            if let Ok(event) = res {
+                // Reads (e.g. our own status scans) must not trigger a refresh.
+                if matches!(event.kind, EventKind::Access(_)) {
+                    return;
+                }
+                for path in event.paths {
+                    if !ignored.contains(&path) && is_relevant_path(&path, &git_dir) {
+                        let _ = fs_tx.send(path);
+                    }
+                }
+            }
+        })
+        .map_err(|e| AppError::Watch(e.to_string()))?;
+
+        watcher
+            .watch(work_tree, RecursiveMode::Recursive)
+            .map_err(|e| AppError::Watch(e.to_string()))?;
+        if !watched_git_dir.starts_with(work_tree) {
+            watcher
+                .watch(&watched_git_dir, RecursiveMode::Recursive)
+                .map_err(|e| AppError::Watch(e.to_string()))?;
+        }
+
+        let app_tx = self.app_tx.clone();
+        tokio::spawn(async move {
+            while let Some(first) = fs_rx.recv().await {
+                let mut paths = vec![first];
+                while let Ok(Some(path)) = tokio::time::timeout(FS_DEBOUNCE, fs_rx.recv()).await {
+                    if !paths.contains(&path) {
+                        paths.push(path);
+                    }
+                }
+                if app_tx.send(AppEvent::FsChanged(paths)).is_err() {
+                    break;
+                }
+            }
+        });
+
+        self._watcher = Some(watcher);
+        Ok(())
+    }
+
+    /// Starts watching the config file at `path`, sending a debounced
+    /// `AppEvent::ConfigChanged` when it changes. Its directory is watched
+    /// rather than the file, since editors often save by replacing the
+    /// file; when `path` is a symbolic link, e.g. into a dotfiles
+    /// repository, the directory of its target is watched as well.
+    pub fn watch_config(&mut self, path: &Path) -> AppResult<()> {
+        let mut files = vec![path.to_path_buf()];
+        if let Ok(target) = path.canonicalize() {
+            if target != path {
+                files.push(target);
+            }
+        }
+        let (fs_tx, mut fs_rx) = mpsc::unbounded_channel::<()>();
+        let watched_files = files.clone();
+        let mut watcher = notify::recommended_watcher(move |res: notify::Result<notify::Event>| {
+            if let Ok(event) = res {
+                if !matches!(event.kind, EventKind::Access(_))
+                    && event.paths.iter().any(|path| watched_files.contains(path))
+                {
+                    let _ = fs_tx.send(());
+                }
+            }
+        })
+        .map_err(|e| AppError::Watch(e.to_string()))?;
+        for dir in files.iter().filter_map(|file| file.parent()) {
+            watcher
+                .watch(dir, RecursiveMode::NonRecursive)
+                .map_err(|e| AppError::Watch(e.to_string()))?;
+        }
+
+        let app_tx = self.app_tx.clone();
+        tokio::spawn(async move {
+            while fs_rx.recv().await.is_some() {
+                while let Ok(Some(())) = tokio::time::timeout(FS_DEBOUNCE, fs_rx.recv()).await {}
+                if app_tx.send(AppEvent::ConfigChanged).is_err() {
+                    break;
+                }
+            }
+        });
+
+        self._config_watcher = Some(watcher);
+        Ok(())
+    }
+
     pub async fn next(&mut self) -> AppResult<Either<InputEvent, AppEvent>> {
         tokio::select! {
             Some(event) = self.input_rx.recv() => Ok(Either::Left(event)),
@@ -72,11 +295,35 @@ impl EventHandler {
         }
     }
 
+    /// Stops reading terminal input so that a child process can use it.
+    pub fn pause_input(&self) {
+        self.input_paused.store(true, Ordering::SeqCst);
+    }
+
+    pub fn resume_input(&self) {
+        self.input_paused.store(false, Ordering::SeqCst);
+    }
+
     pub fn get_app_event_sender(&self) -> mpsc::UnboundedSender<AppEvent> {
         self.app_tx.clone()
     }
 }
 
+/// Work tree changes are always relevant; inside the git directory only the
+/// index and refs matter, so object writes and lock files are skipped.
+fn is_relevant_path(path: &Path, git_dir: &Path) -> bool {
+    let Ok(relative) = path.strip_prefix(git_dir) else {
+        return true;
+    };
+    if relative.extension().is_some_and(|ext| ext == "lock") {
+        return false;
+    }
+    relative.starts_with("refs")
+        || relative
+            .to_str()
+            .is_some_and(|name| WATCHED_GIT_FILES.contains(&name))
+}
+
 pub enum Either<L, R> {
     Left(L),
     Right(R),
@@ -0,0 +1,431 @@
+//! src/forge.rs
+
+use crate::error::{AppError, AppResult};
+use log::warn;
+use serde::{de::DeserializeOwned, Deserialize};
+use std::{
+    io::Write,
+    process::{Command, Stdio},
+};
+
+/// Seconds before an API request is abandoned.
+const REQUEST_TIMEOUT: &str = "20";
+
+/// The hosting services the forge integration knows how to talk to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ForgeKind {
+    GitHub,
+    GitLab,
+}
+
+/// The hosted project behind a remote URL.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Forge {
+    pub kind: ForgeKind,
+    pub host: String,
+    /// `owner/repo` on GitHub, the full namespace path on GitLab.
+    pub path: String,
+    token: Option<String>,
+}
+
+/// The combined state of the CI runs for a commit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CiStatus {
+    Success,
+    Pending,
+    Failure,
+}
+
+/// An open pull request (GitHub) or merge request (GitLab).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PullRequest {
+    pub number: u64,
+    pub title: String,
+    pub author: String,
+    pub url: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ForgeStatus {
+    /// `None` when the commit has no CI runs or the forge does not know it,
+    /// e.g. because it has not been pushed yet.
+    pub ci: Option<CiStatus>,
+    pub pull_requests: Vec<PullRequest>,
+}
+
+/// A pull request as `GET /repos/:path/pulls` lists it.
+#[derive(Debug, Deserialize)]
+struct GitHubPull {
+    number: u64,
+    title: String,
+    html_url: String,
+    /// `None` for pull requests of deleted accounts.
+    user: Option<GitHubUser>,
+}
+
+#[derive(Debug, Deserialize)]
+struct GitHubUser {
+    login: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct GitHubCheckRuns {
+    check_runs: Vec<GitHubCheckRun>,
+}
+
+#[derive(Debug, Deserialize)]
+struct GitHubCheckRun {
+    status: String,
+    /// Only set once the run is completed.
+    conclusion: Option<String>,
+}
+
+/// A merge request as `GET /projects/:id/merge_requests` lists it.
+#[derive(Debug, Deserialize)]
+struct GitLabMerge {
+    iid: u64,
+    title: String,
+    web_url: String,
+    author: Option<GitLabUser>,
+}
+
+#[derive(Debug, Deserialize)]
+struct GitLabUser {
+    username: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct GitLabCommit {
+    /// `null` without a pipeline, but never missing, so that an error body
+    /// like `{"message": "404 Commit Not Found"}` does not pass for a
+    /// commit.
+    #[serde(deserialize_with = "Option::deserialize")]
+    last_pipeline: Option<GitLabPipeline>,
+}
+
+#[derive(Debug, Deserialize)]
+struct GitLabPipeline {
+    status: String,
+}
+
+impl From<GitHubPull> for PullRequest {
+    fn from(pull: GitHubPull) -> Self {
+        Self {
+            number: pull.number,
+            title: pull.title,
+            author: pull.user.map(|user| user.login).unwrap_or_default(),
+            url: pull.html_url,
+        }
+    }
+}
+
+impl From<GitLabMerge> for PullRequest {
+    fn from(merge: GitLabMerge) -> Self {
+        Self {
+            number: merge.iid,
+            title: merge.title,
+            author: merge.author.map(|user| user.username).unwrap_or_default(),
+            url: merge.web_url,
+        }
+    }
+}
+
+impl GitHubCheckRuns {
+    /// Failed as soon as one run failed, pending while any still runs.
+    fn status(&self) -> Option<CiStatus> {
+        if self.check_runs.is_empty() {
+            return None;
+        }
+        let mut status = CiStatus::Success;
+        for run in &self.check_runs {
+            match (run.status.as_str(), run.conclusion.as_deref()) {
+                ("completed", Some("success" | "neutral" | "skipped")) => {}
+                ("completed", _) => return Some(CiStatus::Failure),
+                _ => status = CiStatus::Pending,
+            }
+        }
+        Some(status)
+    }
+}
+
+impl GitLabCommit {
+    fn status(&self) -> Option<CiStatus> {
+        match self.last_pipeline.as_ref()?.status.as_str() {
+            "success" | "skipped" => Some(CiStatus::Success),
+            "failed" | "canceled" => Some(CiStatus::Failure),
+            _ => Some(CiStatus::Pending),
+        }
+    }
+}
+
+impl Forge {
+    /// Recognizes GitHub (github.com) and GitLab (any host with "gitlab" in
+    /// its name) remotes, in both URL and scp-like (`git@host:path`) form.
+    pub fn from_remote_url(url: &str, token: Option<String>) -> Option<Self> {
+        let (host, path) = split_remote_url(url)?;
+        let kind = if host == "github.com" {
+            ForgeKind::GitHub
+        } else if host.contains("gitlab") {
+            ForgeKind::GitLab
+        } else {
+            return None;
+        };
+        Some(Self {
+            kind,
+            host,
+            path,
+            token,
+        })
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self.kind {
+            ForgeKind::GitHub => "GitHub",
+            ForgeKind::GitLab => "GitLab",
+        }
+    }
+
+    pub fn repo_url(&self) -> String {
+        format!("https://{}/{}", self.host, self.path)
+    }
+
+    pub fn commit_url(&self, sha: &str) -> String {
+        match self.kind {
+            ForgeKind::GitHub => format!("{}/commit/{}", self.repo_url(), sha),
+            ForgeKind::GitLab => format!("{}/-/commit/{}", self.repo_url(), sha),
+        }
+    }
+
+    /// Fetches the CI status of `head` and the open pull requests that target
+    /// `branch`. This blocks on the network, so call it from a background task.
+    pub fn status(&self, branch: &str, head: &str) -> AppResult<ForgeStatus> {
+        let pull_requests = self.pull_requests(branch)?;
+        let ci = self.ci_status(head).unwrap_or_else(|e| {
+            warn!("No CI status for {}: {}", head, e);
+            None
+        });
+        Ok(ForgeStatus { ci, pull_requests })
+    }
+
+    fn api_url(&self) -> String {
+        match self.kind {
+            ForgeKind::GitHub => format!("https://api.github.com/repos/{}", self.path),
+            ForgeKind::GitLab => format!(
+                "https://{}/api/v4/projects/{}",
+                self.host,
+                url_encode(&self.path)
+            ),
+        }
+    }
+
+    fn pull_requests(&self, branch: &str) -> AppResult<Vec<PullRequest>> {
+        let branch = url_encode(branch);
+        Ok(match self.kind {
+            ForgeKind::GitHub => self
+                .get::<Vec<GitHubPull>>(&format!("{}/pulls?state=open&base={}", self.api_url(), branch))?
+                .into_iter()
+                .map(PullRequest::from)
+                .collect(),
+            ForgeKind::GitLab => self
+                .get::<Vec<GitLabMerge>>(&format!(
+                    "{}/merge_requests?state=opened&target_branch={}",
+                    self.api_url(),
+                    branch
+                ))?
+                .into_iter()
+                .map(PullRequest::from)
+                .collect(),
+        })
+    }
+
+    fn ci_status(&self, sha: &str) -> AppResult<Option<CiStatus>> {
+        Ok(match self.kind {
+            ForgeKind::GitHub => self
+                .get::<GitHubCheckRuns>(&format!("{}/commits/{}/check-runs", self.api_url(), sha))?
+                .status(),
+            ForgeKind::GitLab => self
+                .get::<GitLabCommit>(&format!("{}/repository/commits/{}", self.api_url(), sha))?
+                .status(),
+        })
+    }
+
+    /// Requests `url` with `curl`. Headers are passed on stdin so that the
+    /// token does not show up in the process list.
+    fn get<T: DeserializeOwned>(&self, url: &str) -> AppResult<T> {
+        let mut headers = String::from("User-Agent: dotatui\n");
+        if self.kind == ForgeKind::GitHub {
+            headers.push_str("Accept: application/vnd.github+json\n");
+        }
+        if let Some(token) = &self.token {
+            match self.kind {
+                ForgeKind::GitHub => headers.push_str(&format!("Authorization: Bearer {}\n", token)),
+                ForgeKind::GitLab => headers.push_str(&format!("PRIVATE-TOKEN: {}\n", token)),
+            }
+        }
+
+        let mut child = Command::new("curl")
+            .args(["--silent", "--show-error", "--fail", "--location"])
+            .args(["--max-time", REQUEST_TIMEOUT, "--header", "@-", url])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| AppError::Forge(format!("could not run curl: {}", e)))?;
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin.write_all(headers.as_bytes())?;
+        }
+        let output = child.wait_with_output()?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(AppError::Forge(stderr.trim().to_string()));
+        }
+        parse_response(&self.host, &output.stdout)
+    }
+}
+
+/// Reads the JSON body `host` answered with.
+fn parse_response<T: DeserializeOwned>(host: &str, body: &[u8]) -> AppResult<T> {
+    serde_json::from_slice(body).map_err(|e| AppError::Forge(format!("unexpected response from {}: {}", host, e)))
+}
+
+/// Opens `url` with the desktop's default browser.
+pub fn open_in_browser(url: &str) -> AppResult<()> {
+    let mut command = if cfg!(target_os = "macos") {
+        Command::new("open")
+    } else if cfg!(windows) {
+        let mut command = Command::new("cmd");
+        command.args(["/C", "start", ""]);
+        command
+    } else {
+        Command::new("xdg-open")
+    };
+    let status = command
+        .arg(url)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .map_err(|e| AppError::Forge(format!("could not open a browser: {}", e)))?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(AppError::Forge(format!("could not open {}", url)))
+    }
+}
+
+/// Splits a remote URL into host and project path, dropping the user,
+/// port and `.git` suffix.
+fn split_remote_url(url: &str) -> Option<(String, String)> {
+    let url = url.trim().trim_end_matches('/');
+    let url = url.strip_suffix(".git").unwrap_or(url);
+    let (host, path) = match url.split_once("://") {
+        Some((_, rest)) => {
+            let (authority, path) = rest.split_once('/')?;
+            let host = authority.rsplit('@').next()?;
+            (host.split(':').next()?, path)
+        }
+        // scp-like syntax: [user@]host:path
+        None => {
+            let (authority, path) = url.split_once(':')?;
+            (authority.rsplit('@').next()?, path)
+        }
+    };
+    let path = path.trim_start_matches('/');
+    if host.is_empty() || !path.contains('/') {
+        return None;
+    }
+    Some((host.to_lowercase(), path.to_string()))
+}
+
+/// Percent-encodes everything except unreserved URL characters.
+fn url_encode(text: &str) -> String {
+    text.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn remote_urls_split_into_host_and_project_path() {
+        let split = |url: &str| split_remote_url(url).map(|(host, path)| format!("{} {}", host, path));
+        for (url, expected) in [
+            ("git@github.com:owner/dotfiles.git", "github.com owner/dotfiles"),
+            ("ssh://git@GitHub.com:22/owner/dotfiles", "github.com owner/dotfiles"),
+            ("https://user@github.com/owner/dotfiles.git/", "github.com owner/dotfiles"),
+            ("https://gitlab.example.org/group/sub/dotfiles.git", "gitlab.example.org group/sub/dotfiles"),
+            ("git@gitlab.com:group/sub/dotfiles", "gitlab.com group/sub/dotfiles"),
+        ] {
+            assert_eq!(split(url).as_deref(), Some(expected), "{}", url);
+        }
+        assert_eq!(split("/home/me/dotfiles.git"), None);
+        assert_eq!(split("https://github.com/dotfiles"), None);
+
+        let forge = Forge::from_remote_url("git@gitlab.com:group/sub/dotfiles.git", None).unwrap();
+        assert_eq!(forge.kind, ForgeKind::GitLab);
+        assert_eq!(forge.api_url(), "https://gitlab.com/api/v4/projects/group%2Fsub%2Fdotfiles");
+        assert_eq!(Forge::from_remote_url("git@example.com:owner/dotfiles", None), None);
+    }
+
+    #[test]
+    fn url_encode_keeps_only_unreserved_characters() {
+        assert_eq!(url_encode("feature/été~1.x_y-z"), "feature%2F%C3%A9t%C3%A9~1.x_y-z");
+        assert_eq!(url_encode("a b&c"), "a%20b%26c");
+    }
+
+    #[test]
+    fn github_payloads_parse_into_pull_requests_and_ci_status() {
+        let body = br#"[
+            {"number": 7, "title": "Add zsh", "html_url": "https://github.com/o/r/pull/7", "user": {"login": "ana", "id": 1}, "draft": false},
+            {"number": 8, "title": "Ghost", "html_url": "https://github.com/o/r/pull/8", "user": null}
+        ]"#;
+        let pulls: Vec<GitHubPull> = parse_response("github.com", body).unwrap();
+        let pulls: Vec<PullRequest> = pulls.into_iter().map(PullRequest::from).collect();
+        assert_eq!(pulls[0].number, 7);
+        assert_eq!(pulls[0].author, "ana");
+        assert_eq!(pulls[0].url, "https://github.com/o/r/pull/7");
+        assert_eq!(pulls[1].author, "");
+
+        let runs = |body: &[u8]| parse_response::<GitHubCheckRuns>("github.com", body).unwrap().status();
+        assert_eq!(runs(br#"{"total_count": 0, "check_runs": []}"#), None);
+        assert_eq!(
+            runs(br#"{"check_runs": [{"status": "completed", "conclusion": "success"}, {"status": "in_progress", "conclusion": null}]}"#),
+            Some(CiStatus::Pending)
+        );
+        assert_eq!(
+            runs(br#"{"check_runs": [{"status": "in_progress"}, {"status": "completed", "conclusion": "failure"}]}"#),
+            Some(CiStatus::Failure)
+        );
+
+        let error = parse_response::<Vec<GitHubPull>>(
+            "github.com",
+            br#"{"message": "Not Found", "documentation_url": "https://docs.github.com/rest"}"#,
+        )
+        .unwrap_err();
+        assert!(error.to_string().contains("unexpected response from github.com"), "{}", error);
+    }
+
+    #[test]
+    fn gitlab_payloads_parse_into_merge_requests_and_pipeline_status() {
+        let body = br#"[{"iid": 3, "id": 991, "title": "Fix vim", "web_url": "https://gitlab.com/g/r/-/merge_requests/3", "author": {"username": "bo"}}]"#;
+        let merges: Vec<GitLabMerge> = parse_response("gitlab.com", body).unwrap();
+        let merge = PullRequest::from(merges.into_iter().next().unwrap());
+        assert_eq!((merge.number, merge.author.as_str()), (3, "bo"));
+
+        let pipeline = |body: &[u8]| parse_response::<GitLabCommit>("gitlab.com", body).unwrap().status();
+        assert_eq!(pipeline(br#"{"id": "abc", "last_pipeline": null}"#), None);
+        assert_eq!(pipeline(br#"{"last_pipeline": {"id": 5, "status": "success"}}"#), Some(CiStatus::Success));
+        assert_eq!(pipeline(br#"{"last_pipeline": {"status": "canceled"}}"#), Some(CiStatus::Failure));
+        assert_eq!(pipeline(br#"{"last_pipeline": {"status": "running"}}"#), Some(CiStatus::Pending));
+
+        let error = parse_response::<GitLabCommit>("gitlab.com", br#"{"message": "404 Commit Not Found"}"#).unwrap_err();
+        assert!(error.to_string().contains("unexpected response from gitlab.com"), "{}", error);
+    }
+}
@@ -1,13 +1,39 @@
 //! src/git.rs
 
+pub mod credentials;
+pub mod bisect;
+pub mod diff;
+pub mod gitconfig;
+pub mod graph;
+pub mod hooks;
+pub mod ignore;
+pub mod lint;
+pub mod signing;
+pub mod subject;
+pub mod tools;
+pub mod worker;
+
+use self::bisect::{Bisect, BisectStep};
+use self::credentials::{remote_callbacks, NoPrompt, Prompter};
+use self::gitconfig::{ConfigEntry, ConfigScope};
+use self::ignore::{IgnoreFile, IgnoreRule};
+use self::tools::{tool_file_name, ToolFiles};
+use crate::task::CancelToken;
 use crate::error::{AppError, AppResult};
-use chrono::{DateTime, Local};
-// Added std::io::Write and removed unused Delta
+use chrono::{DateTime, Datelike, Local};
 use git2::{
-    ApplyLocation, ApplyOptions, Commit, Diff, DiffOptions, Patch, Repository, Status, StatusOptions,
+    build::CheckoutBuilder, ApplyLocation, ApplyOptions, Commit, Diff, DiffOptions,
+    FetchOptions, IndexAddOption, IndexConflict, Patch, PushOptions, Repository,
+    RepositoryState, ResetType, StashFlags, Status, StatusOptions,
+};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs::{self, OpenOptions},
+    io::{ErrorKind, Write},
+    ops::RangeInclusive,
+    path::{Path, PathBuf},
+    time::SystemTime,
 };
-use std::io::Write;
-use std::path::{Path, PathBuf};
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Hunk {
@@ -26,6 +52,15 @@ pub struct Line {
 pub struct GitRepo {
     repo: Repository,
     path: PathBuf,
+    location: RepoLocation,
+}
+
+/// Where a repository lives on disk. Unlike `Repository` this is `Send`, so it
+/// is what background tasks use to open their own handle to the same repo.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RepoLocation {
+    pub git_dir: PathBuf,
+    pub work_tree: Option<PathBuf>,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -35,19 +70,274 @@ pub struct StatusItem {
     pub is_staged: bool,
 }
 
+impl StatusItem {
+    /// Whether the item is an untracked directory listed as one entry; its
+    /// path ends with `/`.
+    pub fn is_untracked_dir(&self) -> bool {
+        self.path.ends_with('/')
+    }
+}
+
+/// How `GitRepo::get_status` lists untracked files.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UntrackedFiles {
+    /// Left out.
+    Hidden,
+    /// Every file, however deep in untracked directories.
+    All,
+    /// An untracked directory as one entry, like `git status` shows it,
+    /// which saves reading a large one. The directories listed here are
+    /// listed file by file instead.
+    Collapsed(Vec<String>),
+}
+
+/// A line of `GitRepo::blame` output.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlameLine {
+    pub id: String,
+    pub author: String,
+    pub content: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StashInfo {
+    pub index: usize,
+    pub message: String,
+    pub time: String,
+}
+
+/// An entry of HEAD's reflog: where HEAD moved from and to, and why.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReflogEntry {
+    pub index: usize,
+    pub old_oid: git2::Oid,
+    pub new_oid: git2::Oid,
+    pub message: String,
+    pub time: String,
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct CommitInfo {
+    pub oid: git2::Oid,
+    pub parents: Vec<git2::Oid>,
     pub id: String,
     pub message: String,
     pub author: String,
     pub time: String,
+    /// Commit time in seconds since the epoch, for sorting.
+    pub timestamp: i64,
+    /// Whether the commit carries a GPG or SSH signature. The signature is
+    /// not verified.
+    pub signed: bool,
+    /// Whether the history of a shallow clone is cut off below this commit,
+    /// so that it shows no parents although it has some.
+    pub shallow: bool,
+}
+
+/// One changed file of a diff between two revisions.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiffFile {
+    pub path: String,
+    /// A status letter such as `M` or `A`.
+    pub status: char,
+    /// Set instead of hunks when git does not diff the file line by line.
+    pub binary: Option<BinaryChange>,
+    pub hunks: Vec<Hunk>,
+}
+
+/// Files larger than this, in bytes, are not diffed line by line in the
+/// status view unless the config says otherwise.
+pub const DIFF_SIZE_LIMIT: u64 = 10 * 1024 * 1024;
+
+/// The reference whose reflog lists the stashes.
+const STASH_REF: &str = "refs/stash";
+
+/// A change shown as a summary rather than as hunks: a binary file, or a
+/// file over the diff size limit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BinaryChange {
+    pub old_size: u64,
+    pub new_size: u64,
+    pub too_large: bool,
+}
+
+impl BinaryChange {
+    /// What the diff panels show, e.g. `(binary file, 512 bytes changed)`,
+    /// where the bytes are how much the size of the file changed.
+    pub fn summary(&self) -> String {
+        let kind = if self.too_large { "file too large to diff" } else { "binary file" };
+        format!("({}, {} bytes changed)", kind, self.old_size.abs_diff(self.new_size))
+    }
+}
+
+/// How much a status item changes its file, shown next to it in the file
+/// list.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Churn {
+    pub additions: usize,
+    pub deletions: usize,
+    /// The new size minus the old one, in bytes.
+    pub size_delta: i64,
+}
+
+/// The totals of a diff, like the last line of `git diff --stat`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DiffSummary {
+    pub files: usize,
+    pub insertions: usize,
+    pub deletions: usize,
+}
+
+impl DiffSummary {
+    fn of(diff: &Diff) -> AppResult<Self> {
+        let stats = diff.stats()?;
+        Ok(Self {
+            files: stats.files_changed(),
+            insertions: stats.insertions(),
+            deletions: stats.deletions(),
+        })
+    }
+}
+
+/// The totals shown above the file list of the status view.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct WorkTreeSummary {
+    pub staged: DiffSummary,
+    pub unstaged: DiffSummary,
+}
+
+impl WorkTreeSummary {
+    /// Swaps the part `old` of the totals for `new`, e.g. the totals of a
+    /// few paths before and after they were staged.
+    pub fn replace(&mut self, old: WorkTreeSummary, new: WorkTreeSummary) {
+        for (total, old, new) in [
+            (&mut self.staged, old.staged, new.staged),
+            (&mut self.unstaged, old.unstaged, new.unstaged),
+        ] {
+            total.files = total.files.saturating_sub(old.files) + new.files;
+            total.insertions = total.insertions.saturating_sub(old.insertions) + new.insertions;
+            total.deletions = total.deletions.saturating_sub(old.deletions) + new.deletions;
+        }
+    }
+}
+
+/// Everything the commit detail popup shows about one commit.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CommitDetail {
+    /// The full hash.
+    pub id: String,
+    /// Name and email.
+    pub author: String,
+    pub time: String,
+    /// The whole message, not just the summary.
+    pub message: String,
+    /// The changed paths, each with a status letter such as `M` or `A`.
+    pub files: Vec<(char, String)>,
+    pub insertions: usize,
+    pub deletions: usize,
 }
 
 impl GitRepo {
+    /// Discovers the repository containing `path`. A bare repository is only
+    /// usable when it has `core.worktree` configured.
     pub fn new<P: AsRef<Path>>(path: P) -> AppResult<Self> {
         let repo = Repository::discover(path.as_ref()).map_err(|_| AppError::RepoNotFound)?;
-        let path = repo.path().parent().unwrap().to_path_buf();
-        Ok(Self { repo, path })
+        Self::from_repository(repo, None)
+    }
+
+    /// Opens the repository at `git_dir`, using `work_tree` as its working
+    /// directory. This is how a bare dotfiles repo (e.g. `~/.dotfiles` with
+    /// `$HOME` as the work tree) is opened.
+    pub fn open_with_work_tree(git_dir: &Path, work_tree: Option<&Path>) -> AppResult<Self> {
+        let repo = Repository::open(git_dir).map_err(|_| AppError::RepoNotFound)?;
+        Self::from_repository(repo, work_tree)
+    }
+
+    /// Creates an empty repository with its work tree at `path`.
+    pub fn init(path: &Path) -> AppResult<Self> {
+        let repo = Repository::init(path)?;
+        Self::from_repository(repo, None)
+    }
+
+    /// Clones `url` into `path` with `options`, reporting transfer
+    /// statistics through `on_progress` as objects arrive. Cancelling
+    /// `cancel` aborts the transfer.
+    pub fn clone_from_url<F>(
+        url: &str,
+        path: &Path,
+        options: &CloneOptions,
+        prompter: &mut dyn Prompter,
+        cancel: &CancelToken,
+        mut on_progress: F,
+    ) -> AppResult<Self>
+    where
+        F: FnMut(TransferStats),
+    {
+        let branch = match (&options.branch, options.single_branch) {
+            (Some(branch), _) => Some(branch.clone()),
+            (None, true) => Some(remote_default_branch(url, prompter)?),
+            (None, false) => None,
+        };
+        let mut callbacks = remote_callbacks(git2::Config::open_default()?, prompter);
+        let mut last_received = usize::MAX;
+        callbacks.transfer_progress(|progress| {
+            if progress.received_objects() != last_received {
+                last_received = progress.received_objects();
+                on_progress(TransferStats::from_progress(&progress));
+            }
+            !cancel.is_cancelled()
+        });
+        let mut fetch_options = FetchOptions::new();
+        fetch_options.remote_callbacks(callbacks);
+        if let Some(depth) = options.depth {
+            fetch_options.depth(i32::try_from(depth).unwrap_or(i32::MAX));
+        }
+        let mut builder = git2::build::RepoBuilder::new();
+        if let Some(branch) = branch {
+            builder.branch(&branch);
+            if options.single_branch {
+                // Fetching only this branch, now and in later fetches.
+                builder.remote_create(move |repo, name, url| {
+                    let refspec = format!("+refs/heads/{0}:refs/remotes/{1}/{0}", branch, name);
+                    repo.remote_with_fetch(name, url, &refspec)
+                });
+            }
+        }
+        let repo = builder
+            .fetch_options(fetch_options)
+            .clone(url, path)
+            .map_err(|e| AppError::CloneFailed(e.message().to_string()))?;
+        Self::from_repository(repo, None)
+    }
+
+    /// Re-opens a repository from a location obtained via [`GitRepo::location`].
+    pub fn open(location: &RepoLocation) -> AppResult<Self> {
+        Self::open_with_work_tree(&location.git_dir, location.work_tree.as_deref())
+    }
+
+    fn from_repository(repo: Repository, work_tree: Option<&Path>) -> AppResult<Self> {
+        let work_tree = match work_tree {
+            Some(work_tree) => Some(work_tree.to_path_buf()),
+            None if repo.is_bare() => configured_work_tree(&repo)?,
+            None => None,
+        };
+        if let Some(work_tree) = &work_tree {
+            repo.set_workdir(work_tree, false)?;
+        }
+        let path: PathBuf = repo
+            .workdir()
+            .ok_or(AppError::NoWorkTree)?
+            .components()
+            .collect();
+        let location = RepoLocation {
+            git_dir: repo.path().to_path_buf(),
+            work_tree,
+        };
+        Ok(Self { repo, path, location })
+    }
+
+    pub fn location(&self) -> RepoLocation {
+        self.location.clone()
     }
 
     pub fn path(&self) -> &Path {
@@ -58,14 +348,122 @@ impl GitRepo {
         self.path.to_str().unwrap_or("Invalid UTF-8 Path")
     }
 
-    pub fn get_status(&self) -> AppResult<Vec<StatusItem>> {
+    /// When the index file was last written; `None` before the first `git add`.
+    pub fn index_mtime(&self) -> Option<SystemTime> {
+        fs::metadata(self.repo.path().join("index"))
+            .and_then(|metadata| metadata.modified())
+            .ok()
+    }
+
+    /// The files whose patterns decide what is ignored, lowest precedence
+    /// first: `core.excludesFile`, `info/exclude`, the root `.gitignore` and
+    /// the tracked `.gitignore` files below it, shallowest first. Untracked
+    /// `.gitignore` files in subdirectories are not looked for, since that
+    /// would mean walking all of `$HOME` in a bare dotfiles setup.
+    pub fn ignore_files(&self) -> AppResult<Vec<IgnoreFile>> {
+        let mut files = Vec::new();
+        let global = self
+            .repo
+            .config()?
+            .get_path("core.excludesFile")
+            .ok()
+            .or_else(default_excludes_file);
+        if let Some(path) = global {
+            files.push(IgnoreFile {
+                label: path.display().to_string(),
+                path,
+                base: String::new(),
+                in_work_tree: false,
+            });
+        }
+        files.push(IgnoreFile {
+            path: self.repo.path().join("info").join("exclude"),
+            label: "info/exclude".to_string(),
+            base: String::new(),
+            in_work_tree: false,
+        });
+        let mut nested: Vec<String> = self
+            .repo
+            .index()?
+            .iter()
+            .filter_map(|entry| String::from_utf8(entry.path).ok())
+            .filter(|path| path.ends_with("/.gitignore"))
+            .collect();
+        nested.sort_by_key(|path| path.matches('/').count());
+        for label in std::iter::once(".gitignore".to_string()).chain(nested) {
+            files.push(IgnoreFile {
+                path: self.path.join(&label),
+                base: label.trim_end_matches(".gitignore").to_string(),
+                label,
+                in_work_tree: true,
+            });
+        }
+        Ok(files)
+    }
+
+    pub fn ignore_rules(&self) -> AppResult<Vec<IgnoreRule>> {
+        let ignore_case = self.repo.config()?.get_bool("core.ignorecase").unwrap_or(false);
+        ignore::read_rules(&self.ignore_files()?, ignore_case)
+    }
+
+    /// Whether `path` (absolute or relative to the work tree) is excluded by
+    /// the repository's ignore rules.
+    pub fn is_path_ignored(&self, path: &Path) -> bool {
+        let relative = path.strip_prefix(&self.path).unwrap_or(path);
+        self.repo.is_path_ignored(relative).unwrap_or(false)
+    }
+
+    /// Lists staged and unstaged changes. Untracked files are listed as
+    /// `untracked` says unless the repository disables them.
+    pub fn get_status(&self, untracked: &UntrackedFiles) -> AppResult<Vec<StatusItem>> {
+        let show_untracked = self.shows_untracked(untracked)?;
+        let recurse = show_untracked && matches!(untracked, UntrackedFiles::All);
+        let mut opts = StatusOptions::new();
+        opts.include_untracked(show_untracked)
+            .recurse_untracked_dirs(recurse);
+        let mut items = self.status_items(&mut opts)?;
+        if let (true, UntrackedFiles::Collapsed(expanded)) = (show_untracked, untracked) {
+            for dir in expanded {
+                let Some(index) = items.iter().position(|item| item.path == *dir && item.is_untracked_dir()) else {
+                    continue;
+                };
+                items.remove(index);
+                let mut opts = StatusOptions::new();
+                opts.include_untracked(true).recurse_untracked_dirs(true).pathspec(dir);
+                items.extend(self.status_items(&mut opts)?.into_iter().filter(|item| item.status.is_wt_new()));
+            }
+        }
+        Ok(items)
+    }
+
+    /// Lists the changes of `paths` only, as `get_status` lists them, e.g.
+    /// after staging a file, without reading the rest of the work tree.
+    pub fn get_status_for_paths(&self, paths: &[String], untracked: &UntrackedFiles) -> AppResult<Vec<StatusItem>> {
         let mut opts = StatusOptions::new();
-        opts.include_untracked(true).recurse_untracked_dirs(true);
-        let statuses = self.repo.statuses(Some(&mut opts))?;
+        opts.include_untracked(self.shows_untracked(untracked)?)
+            .disable_pathspec_match(true);
+        for path in paths {
+            opts.pathspec(path);
+        }
+        self.status_items(&mut opts)
+    }
+
+    fn status_items(&self, opts: &mut StatusOptions) -> AppResult<Vec<StatusItem>> {
+        let statuses = self.repo.statuses(Some(opts))?;
         let mut items = Vec::new();
         for entry in statuses.iter() {
             if let Some(path) = entry.path() {
                 let status = entry.status();
+                // A conflicted path is listed once, in its own section, until
+                // it is resolved by staging it.
+                if status.is_conflicted() {
+                    items.push(StatusItem {
+                        path: path.to_string(),
+                        status,
+                        is_staged: false,
+                    });
+                    continue;
+                }
                 if status.is_wt_new()
                     || status.is_wt_modified()
                     || status.is_wt_deleted()
@@ -95,16 +493,67 @@ impl GitRepo {
         Ok(items)
     }
 
-    fn get_diff_for_item<'a>(&'a self, item: &StatusItem) -> AppResult<Diff<'a>> {
+    /// Whether untracked files are listed: when `untracked` does not hide
+    /// them and the repository does not disable them.
+    pub fn shows_untracked(&self, untracked: &UntrackedFiles) -> AppResult<bool> {
+        // Bare dotfiles setups usually set `status.showUntrackedFiles=no`, since
+        // otherwise every file in `$HOME` would show up as untracked.
+        Ok(*untracked != UntrackedFiles::Hidden
+            && self
+                .repo
+                .config()?
+                .get_string("status.showUntrackedFiles")
+                .map(|value| value != "no")
+                .unwrap_or(true))
+    }
+
+    /// The totals of the staged and of the unstaged changes, like
+    /// `git diff --cached --shortstat` and `git diff --shortstat`. The
+    /// unstaged ones count untracked files when `get_status` lists them; a
+    /// collapsed untracked directory counts as one file without lines.
+    pub fn work_tree_summary(&self, untracked: &UntrackedFiles) -> AppResult<WorkTreeSummary> {
+        self.work_tree_summary_for_paths(&[], untracked)
+    }
+
+    /// The part of `work_tree_summary` that `paths` make up; all of it when
+    /// there are none.
+    pub fn work_tree_summary_for_paths(&self, paths: &[String], untracked: &UntrackedFiles) -> AppResult<WorkTreeSummary> {
+        let tree = self.head_tree()?;
         let mut opts = DiffOptions::new();
+        opts.disable_pathspec_match(true);
+        for path in paths {
+            opts.pathspec(path);
+        }
+        let staged = self.repo.diff_tree_to_index(tree.as_ref(), None, Some(&mut opts))?;
+        let show_untracked = self.shows_untracked(untracked)?;
+        opts.include_untracked(show_untracked)
+            .recurse_untracked_dirs(show_untracked && matches!(untracked, UntrackedFiles::All))
+            .show_untracked_content(show_untracked);
+        let unstaged = self.repo.diff_index_to_workdir(None, Some(&mut opts))?;
+        Ok(WorkTreeSummary {
+            staged: DiffSummary::of(&staged)?,
+            unstaged: DiffSummary::of(&unstaged)?,
+        })
+    }
+
+    fn get_diff_for_item<'a>(&'a self, item: &StatusItem) -> AppResult<Diff<'a>> {
+        self.diff_item_with(item, &mut DiffOptions::new())
+    }
+
+    /// The diff of `item` with `opts`, to which its path is added.
+    fn diff_item_with<'a>(&'a self, item: &StatusItem, opts: &mut DiffOptions) -> AppResult<Diff<'a>> {
         opts.pathspec(&item.path);
         let diff = if item.is_staged {
-            let head_commit = self.find_last_commit()?;
-            let tree = head_commit.tree()?;
+            let tree = self.head_tree()?;
             self.repo
-                .diff_tree_to_index(Some(&tree), None, Some(&mut opts))?
+                .diff_tree_to_index(tree.as_ref(), None, Some(opts))?
+        } else if item.status.is_wt_new() {
+            // Untracked files only show up in the diff when asked for, which
+            // in a repository without commits is every file.
+            opts.include_untracked(true).show_untracked_content(true);
+            self.repo.diff_index_to_workdir(None, Some(opts))?
         } else {
-            self.repo.diff_index_to_workdir(None, Some(&mut opts))?
+            self.repo.diff_index_to_workdir(None, Some(opts))?
         };
         Ok(diff)
     }
@@ -126,75 +575,155 @@ impl GitRepo {
         Ok(diff_text)
     }
 
-    pub fn get_diff_hunks(&self, item: &StatusItem) -> AppResult<Vec<Hunk>> {
-        let diff = self.get_diff_for_item(item)?;
-        if let Some(patch) = Patch::from_diff(&diff, 0)? {
-            let mut hunks = Vec::with_capacity(patch.num_hunks());
-            for i in 0..patch.num_hunks() {
-                let (hunk_header, num_lines) = patch.hunk(i)?;
-                let mut lines = Vec::with_capacity(num_lines);
-                for j in 0..num_lines {
-                    let line = patch.line_in_hunk(i, j)?;
-                    lines.push(Line {
-                        origin: line.origin(),
-                        content: String::from_utf8_lossy(line.content()).to_string(),
-                        old_lineno: line.old_lineno(),
-                        new_lineno: line.new_lineno(),
-                    });
-                }
-                hunks.push(Hunk {
-                    header: String::from_utf8_lossy(hunk_header.header()).to_string(),
-                    lines,
-                });
-            }
-            Ok(hunks)
-        } else {
-            Ok(Vec::new())
+    /// The hunks of `item`, or the summary of a binary change. Files larger
+    /// than `size_limit` bytes count as binary, so that diffing them does
+    /// not hold up the worker.
+    pub fn get_diff_hunks(&self, item: &StatusItem, size_limit: u64) -> AppResult<(Vec<Hunk>, Option<BinaryChange>)> {
+        let mut opts = DiffOptions::new();
+        opts.max_size(i64::try_from(size_limit).unwrap_or(i64::MAX));
+        let diff = self.diff_item_with(item, &mut opts)?;
+        match Patch::from_diff(&diff, 0)? {
+            Some(patch) => match binary_change(&patch, size_limit) {
+                Some(binary) => Ok((Vec::new(), Some(binary))),
+                None => Ok((patch_hunks(&patch)?, None)),
+            },
+            None => Ok((Vec::new(), None)),
         }
     }
 
-    pub fn stage_hunk(&self, item: &StatusItem, hunk_index: usize) -> AppResult<()> {
-        let diff = self.get_diff_for_item(item)?;
-        let patch = Patch::from_diff(&diff, 0)?
-            .ok_or_else(|| AppError::Git(git2::Error::from_str("Could not create patch from diff")))?;
+    /// The lines `item` adds and deletes and how much it changes the file's
+    /// size. Binary files and files larger than `size_limit` bytes only get
+    /// the size change.
+    pub fn churn(&self, item: &StatusItem, size_limit: u64) -> AppResult<Churn> {
+        if item.is_untracked_dir() {
+            return Ok(Churn::default());
+        }
+        let mut opts = DiffOptions::new();
+        opts.max_size(i64::try_from(size_limit).unwrap_or(i64::MAX));
+        if item.status.is_wt_new() {
+            opts.include_untracked(true)
+                .recurse_untracked_dirs(true)
+                .show_untracked_content(true);
+        }
+        let diff = self.diff_item_with(item, &mut opts)?;
+        let Some(patch) = Patch::from_diff(&diff, 0)? else {
+            return Ok(Churn::default());
+        };
+        let delta = patch.delta();
+        let size_delta = delta.new_file().size() as i64 - delta.old_file().size() as i64;
+        let (_, additions, deletions) = match delta.flags().is_binary() {
+            true => (0, 0, 0),
+            false => patch.line_stats()?,
+        };
+        Ok(Churn {
+            additions,
+            deletions,
+            size_delta,
+        })
+    }
+
+    /// The changes of `item` as `git diff` prints them, or only those of its
+    /// hunk `hunk`. Untracked files are included with all their lines.
+    pub fn item_patch(&self, item: &StatusItem, hunk: Option<usize>) -> AppResult<String> {
+        let diff = if item.status.is_wt_new() {
+            let mut opts = DiffOptions::new();
+            opts.pathspec(&item.path)
+                .include_untracked(true)
+                .recurse_untracked_dirs(true)
+                .show_untracked_content(true);
+            self.repo.diff_index_to_workdir(None, Some(&mut opts))?
+        } else {
+            self.get_diff_for_item(item)?
+        };
+        patch_text(&diff, hunk)
+    }
 
-        let mut patch_buffer = Vec::new();
+    /// Everything staged, as `git diff --cached` prints it.
+    pub fn staged_patch(&self) -> AppResult<String> {
+        let tree = self.head_tree()?;
+        let diff = self.repo.diff_tree_to_index(tree.as_ref(), None, None)?;
+        patch_text(&diff, None)
+    }
 
-        // Correctly get delta without `if let`
-        let delta = patch.delta();
-        let old_file = delta.old_file();
-        let new_file = delta.new_file();
-
-        // Use writeln! with std::io::Write in scope
-        writeln!(
-            &mut patch_buffer,
-            "--- a/{}",
-            old_file.path().unwrap().display()
-        )?;
-        writeln!(
-            &mut patch_buffer,
-            "+++ b/{}",
-            new_file.path().unwrap().display()
-        )?;
+    /// Applies the patch in `file` to the work tree like `git apply`. When
+    /// any part of it does not apply, nothing is changed.
+    pub fn apply_patch_file(&self, file: &Path) -> AppResult<()> {
+        let diff = Diff::from_buffer(&fs::read(file)?)?;
+        self.repo.apply(&diff, ApplyLocation::WorkDir, None)?;
+        Ok(())
+    }
+
+    /// Diffs revision `from` against revision `to`, or against the work tree
+    /// when `to` is `None`. Revisions are anything `git rev-parse` accepts,
+    /// e.g. a hash, a branch or `HEAD~2`. Untracked files are left out.
+    pub fn diff_refs(&self, from: &str, to: Option<&str>) -> AppResult<Vec<DiffFile>> {
+        let from_tree = self.repo.revparse_single(from)?.peel_to_tree()?;
+        let mut diff = match to {
+            Some(to) => {
+                let to_tree = self.repo.revparse_single(to)?.peel_to_tree()?;
+                self.repo.diff_tree_to_tree(Some(&from_tree), Some(&to_tree), None)?
+            }
+            None => self.repo.diff_tree_to_workdir_with_index(Some(&from_tree), None)?,
+        };
+        diff_files(&mut diff)
+    }
 
-        let (hunk_header, num_lines) = patch.hunk(hunk_index)?;
-        patch_buffer.extend_from_slice(hunk_header.header());
+    /// The changes commit `oid` made to its first parent; a root commit
+    /// adds all of its files.
+    pub fn commit_diff(&self, oid: git2::Oid) -> AppResult<Vec<DiffFile>> {
+        let commit = self.repo.find_commit(oid)?;
+        let parent_tree = match commit.parents().next() {
+            Some(parent) => Some(parent.tree()?),
+            None => None,
+        };
+        let mut diff = self
+            .repo
+            .diff_tree_to_tree(parent_tree.as_ref(), Some(&commit.tree()?), None)?;
+        diff_files(&mut diff)
+    }
 
-        for i in 0..num_lines {
-            let line = patch.line_in_hunk(hunk_index, i)?;
-            // Correctly cast char to u8
-            patch_buffer.push(line.origin() as u8);
-            patch_buffer.extend_from_slice(line.content());
-        }
-        
-        // Add a newline at the end of the patch for robustness
-        writeln!(&mut patch_buffer)?;
+    /// Stages the changed lines `lines` (indices into `hunk.lines`) of a hunk
+    /// of `item`'s diff, or unstages them when `item` is staged, leaving the
+    /// other lines of the hunk as they are.
+    pub fn stage_lines(&self, item: &StatusItem, hunk: &Hunk, lines: RangeInclusive<usize>) -> AppResult<()> {
+        let patch = diff::partial_patch(&item.path, hunk, lines, item.is_staged)
+            .ok_or_else(|| git2::Error::from_str(&format!("Unreadable hunk header '{}'", hunk.header.trim_end())))?;
+        let diff = Diff::from_buffer(patch.as_bytes())?;
+        self.repo.apply(&diff, ApplyLocation::Index, None)?;
+        Ok(())
+    }
 
-        let new_diff = Diff::from_buffer(&patch_buffer)?;
+    /// Stages a single hunk of an unstaged item by applying only that hunk
+    /// of the index-to-workdir diff to the index.
+    pub fn stage_hunk(&self, item: &StatusItem, hunk_index: usize) -> AppResult<()> {
+        let diff = self.get_diff_for_item(item)?;
+        self.apply_hunk_to_index(&diff, hunk_index)
+    }
 
-        self.repo
-            .apply(&new_diff, ApplyLocation::Index, Some(&mut ApplyOptions::new()))?;
+    /// Unstages a single hunk of a staged item. The HEAD-to-index diff is
+    /// generated in reverse so that applying the selected hunk to the index
+    /// restores the HEAD version of those lines.
+    pub fn unstage_hunk(&self, item: &StatusItem, hunk_index: usize) -> AppResult<()> {
+        let tree = self.head_tree()?;
+        let mut opts = DiffOptions::new();
+        opts.pathspec(&item.path).reverse(true);
+        let diff = self
+            .repo
+            .diff_tree_to_index(tree.as_ref(), None, Some(&mut opts))?;
+        self.apply_hunk_to_index(&diff, hunk_index)
+    }
 
+    /// Applies the partial patch made of hunk `hunk_index` of `diff` to the index,
+    /// skipping every other hunk.
+    fn apply_hunk_to_index(&self, diff: &Diff<'_>, hunk_index: usize) -> AppResult<()> {
+        let mut current = 0;
+        let mut opts = ApplyOptions::new();
+        opts.hunk_callback(|_| {
+            let keep = current == hunk_index;
+            current += 1;
+            keep
+        });
+        self.repo.apply(diff, ApplyLocation::Index, Some(&mut opts))?;
         Ok(())
     }
 
@@ -203,6 +732,8 @@ impl GitRepo {
         let path = Path::new(&item.path);
         if item.status.is_wt_deleted() {
             index.remove_path(path)?;
+        } else if item.is_untracked_dir() {
+            index.add_all([&item.path], IndexAddOption::DEFAULT, None)?;
         } else {
             index.add_path(path)?;
         }
@@ -210,54 +741,2221 @@ impl GitRepo {
         Ok(())
     }
 
+    /// Stages `path`, relative to the work tree.
+    pub fn stage_path(&self, path: &Path) -> AppResult<()> {
+        let mut index = self.repo.index()?;
+        index.add_path(path)?;
+        index.write()?;
+        Ok(())
+    }
+
+    /// Appends `path` to the `.gitignore` at the root of the work tree. The
+    /// pattern is anchored and escaped so that only this exact path matches.
+    pub fn ignore_path(&self, path: &str) -> AppResult<()> {
+        let mut pattern = String::from("/");
+        for c in path.chars() {
+            if matches!(c, '\\' | '*' | '?' | '[') {
+                pattern.push('\\');
+            }
+            pattern.push(c);
+        }
+        self.add_ignore_pattern(&pattern)
+    }
+
+    /// Appends `pattern` as is to the `.gitignore` at the root of the work
+    /// tree.
+    pub fn add_ignore_pattern(&self, pattern: &str) -> AppResult<()> {
+        let gitignore = self.path.join(".gitignore");
+        let existing = match fs::read_to_string(&gitignore) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(e.into()),
+        };
+        let mut file = OpenOptions::new().create(true).append(true).open(&gitignore)?;
+        if !existing.is_empty() && !existing.ends_with('\n') {
+            writeln!(file)?;
+        }
+        writeln!(file, "{}", pattern)?;
+        Ok(())
+    }
+
+    /// Stages every change of the tracked files, deletions included, and
+    /// the untracked files too when `untracked` shows them (see
+    /// `shows_untracked`). Ignored files are left alone.
+    pub fn stage_all(&self, untracked: Option<&UntrackedFiles>) -> AppResult<()> {
+        let mut index = self.repo.index()?;
+        // In a `$HOME` work tree every file of the home directory is
+        // untracked, so they are only added when they are listed.
+        if let Some(untracked) = untracked {
+            if self.shows_untracked(untracked)? {
+                index.add_all(["*"], IndexAddOption::DEFAULT, None)?;
+            }
+        }
+        index.update_all(["*"], None)?;
+        index.write()?;
+        Ok(())
+    }
+
+    /// The files `stage_all` would stage with `untracked`, read afresh and
+    /// with untracked directories walked file by file, so that the secrets
+    /// among them can be encrypted first.
+    pub fn unstaged_paths(&self, untracked: Option<&UntrackedFiles>) -> AppResult<Vec<String>> {
+        let include_untracked = match untracked {
+            Some(untracked) => self.shows_untracked(untracked)?,
+            None => false,
+        };
+        let mut opts = StatusOptions::new();
+        opts.include_untracked(include_untracked).recurse_untracked_dirs(true);
+        Ok(self
+            .status_items(&mut opts)?
+            .into_iter()
+            .filter(|item| !item.is_staged)
+            .map(|item| item.path)
+            .collect())
+    }
+
+    /// Stages the changes of the tracked files, and the untracked files too
+    /// when `untracked` lists them (see `shows_untracked`), runs the commit
+    /// hooks and commits, returning `None` when nothing differed from HEAD.
+    /// Refuses while a merge, rebase or the like is in progress or a file
+    /// is conflicted, since finishing those needs the user.
+    pub fn commit_all<F>(
+        &self,
+        message: &str,
+        sign: bool,
+        untracked: Option<&UntrackedFiles>,
+        on_output: F,
+    ) -> AppResult<Option<git2::Oid>>
+    where
+        F: FnMut(String),
+    {
+        if self.repo.state() != RepositoryState::Clean {
+            return Err(AppError::Conflict(format!("{:?} in progress", self.repo.state())));
+        }
+        if self.repo.index()?.has_conflicts() {
+            return Err(AppError::Conflict("there are conflicted files".to_string()));
+        }
+        self.stage_all(untracked)?;
+        let staged = Status::INDEX_NEW
+            | Status::INDEX_MODIFIED
+            | Status::INDEX_DELETED
+            | Status::INDEX_RENAMED
+            | Status::INDEX_TYPECHANGE;
+        let mut opts = StatusOptions::new();
+        opts.include_untracked(false);
+        let changed = self.repo.statuses(Some(&mut opts))?.iter().any(|entry| entry.status().intersects(staged));
+        if !changed {
+            return Ok(None);
+        }
+        let message = self.run_commit_hooks(message, on_output)?;
+        self.commit(&message, sign).map(Some)
+    }
+
+    /// Resets the whole index back to HEAD, keeping the work tree untouched.
+    pub fn unstage_all(&self) -> AppResult<()> {
+        let head = self.head_commit()?.map(|commit| commit.into_object());
+        self.repo.reset_default(head.as_ref(), ["*"])?;
+        Ok(())
+    }
+
     pub fn unstage_file(&self, path: &str) -> AppResult<()> {
-        let head = self.repo.head()?.peel(git2::ObjectType::Commit)?;
+        let head = self.head_commit()?.map(|commit| commit.into_object());
         let path_obj = Some(Path::new(path));
-        self.repo.reset_default(Some(&head), path_obj)?;
+        self.repo.reset_default(head.as_ref(), path_obj)?;
+        Ok(())
+    }
+
+    /// Throws away the unstaged changes of `item`: a tracked file gets its
+    /// staged contents back, with the line endings a checkout would give
+    /// it, and an untracked file or directory is deleted.
+    pub fn discard_changes(&self, item: &StatusItem) -> AppResult<()> {
+        let full_path = self.path.join(&item.path);
+        if item.status.is_wt_new() {
+            match item.is_untracked_dir() {
+                true => fs::remove_dir_all(&full_path)?,
+                false => fs::remove_file(&full_path)?,
+            }
+            return Ok(());
+        }
+        let entry = self
+            .repo
+            .index()?
+            .get_path(Path::new(&item.path), 0)
+            .ok_or_else(|| git2::Error::from_str(&format!("{} is not in the index", item.path)))?;
+        if let Some(parent) = full_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&full_path, self.checkout_contents(entry.id, &item.path)?)?;
         Ok(())
     }
 
-    pub fn commit(&self, message: &str) -> AppResult<()> {
+    /// Whether `commit.gpgsign` asks for commits to be signed.
+    pub fn signing_enabled(&self) -> bool {
+        self.repo
+            .config()
+            .and_then(|config| config.get_bool("commit.gpgsign"))
+            .unwrap_or(false)
+    }
+
+    /// Reads the file named by `commit.template`, if one is configured.
+    pub fn commit_template(&self) -> Option<String> {
+        let path = self.repo.config().ok()?.get_path("commit.template").ok()?;
+        let path = if path.is_relative() { self.path.join(path) } else { path };
+        fs::read_to_string(path).ok()
+    }
+
+    /// Creates a commit from the index and returns its id. When `sign` is
+    /// set, the commit is signed with the key configured for git.
+    pub fn commit(&self, message: &str, sign: bool) -> AppResult<git2::Oid> {
         let mut index = self.repo.index()?;
         let tree_id = index.write_tree()?;
         let tree = self.repo.find_tree(tree_id)?;
         let signature = self.repo.signature()?;
-        let parent_commit = self.find_last_commit()?;
-        self.repo.commit(
-            Some("HEAD"),
-            &signature,
-            &signature,
-            message,
-            &tree,
-            &[&parent_commit],
-        )?;
+        // The first commit of a repository has no parent.
+        let parent_commit = self.head_commit()?;
+        // Concluding a conflicted merge: the merged heads become extra parents.
+        let merge_heads = if self.repo.state() == RepositoryState::Merge {
+            fs::read_to_string(self.repo.path().join("MERGE_HEAD"))?
+        } else {
+            String::new()
+        };
+        let merge_commits = merge_heads
+            .lines()
+            .map(|line| self.repo.find_commit(git2::Oid::from_str(line.trim())?))
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut parents: Vec<&Commit<'_>> = parent_commit.iter().collect();
+        parents.extend(merge_commits.iter());
+        let oid = if sign {
+            let buffer =
+                self.repo
+                    .commit_create_buffer(&signature, &signature, message, &tree, &parents)?;
+            let content = buffer
+                .as_str()
+                .ok_or_else(|| AppError::Signing("commit is not valid UTF-8".to_string()))?;
+            let signer = signing::signer_from_config(&self.repo.config()?, &signature)?;
+            let oid = self.repo.commit_signed(content, &signer.sign(content)?, None)?;
+            // `commit_signed` only writes the object; move the branch by hand.
+            let subject = message.lines().next().unwrap_or_default();
+            let log_message = format!("commit: {}", subject);
+            let head = self.repo.find_reference("HEAD")?;
+            match head.resolve() {
+                Ok(mut branch) => {
+                    branch.set_target(oid, &log_message)?;
+                }
+                // On an unborn branch HEAD names a branch that does not exist yet.
+                Err(_) => {
+                    let branch = head
+                        .symbolic_target()
+                        .ok_or_else(|| git2::Error::from_str("HEAD is not a branch"))?;
+                    self.repo.reference(branch, oid, false, &log_message)?;
+                }
+            }
+            oid
+        } else {
+            self.repo.commit(
+                Some("HEAD"),
+                &signature,
+                &signature,
+                message,
+                &tree,
+                &parents,
+            )?
+        };
+        if !merge_commits.is_empty() {
+            self.repo.cleanup_state()?;
+        }
+        Ok(oid)
+    }
+
+    /// Where git looks for the repository's hooks, see `hooks::hooks_dir`.
+    pub fn hooks_dir(&self) -> AppResult<PathBuf> {
+        let config = self.repo.config()?;
+        Ok(hooks::hooks_dir(&config, &common_dir(&self.repo), &self.path))
+    }
+
+    /// The commit hooks (`pre-commit`, `commit-msg`) the repository has.
+    pub fn commit_hooks(&self) -> Vec<PathBuf> {
+        let Ok(dir) = self.hooks_dir() else {
+            return Vec::new();
+        };
+        hooks::COMMIT_HOOKS
+            .iter()
+            .filter_map(|name| hooks::find_hook(&dir, name))
+            .collect()
+    }
+
+    /// Runs the commit hooks like `git commit` does, passing what they print
+    /// to `on_output`. `commit-msg` gets the message in `COMMIT_EDITMSG` and
+    /// may rewrite it, so the message to commit is returned.
+    pub fn run_commit_hooks<F>(&self, message: &str, mut on_output: F) -> AppResult<String>
+    where
+        F: FnMut(String),
+    {
+        let env = hooks::HookEnv {
+            git_dir: self.repo.path().to_path_buf(),
+            work_tree: self.path.clone(),
+        };
+        let message_file = self.repo.path().join("COMMIT_EDITMSG");
+        let mut message = message.to_string();
+        for hook in self.commit_hooks() {
+            on_output(format!("Running {}...", hook.display()));
+            if hook.ends_with("commit-msg") {
+                fs::write(&message_file, &message)?;
+                hooks::run_hook(&hook, &[message_file.as_os_str()], &env, &mut on_output)?;
+                message = git2::message_prettify(fs::read_to_string(&message_file)?, Some(b'#'))?;
+            } else {
+                hooks::run_hook(&hook, &[], &env, &mut on_output)?;
+            }
+        }
+        Ok(message)
+    }
+
+    /// Returns the commit HEAD points to.
+    pub fn head_commit_id(&self) -> AppResult<git2::Oid> {
+        Ok(self.find_last_commit()?.id())
+    }
+
+    /// Records the current index so that a following stage or unstage can be
+    /// undone. Fails while the index has conflicts.
+    pub fn snapshot_index(&self) -> AppResult<UndoAction> {
+        Ok(UndoAction::Index(self.repo.index()?.write_tree()?))
+    }
+
+    /// Reverts a change recorded with `snapshot_index` or returned by
+    /// `commit`. A commit is undone like `git reset --soft HEAD~`, and only
+    /// while it is still the tip of the branch.
+    pub fn undo(&self, action: &UndoAction) -> AppResult<()> {
+        match *action {
+            UndoAction::Index(tree) => {
+                let mut index = self.repo.index()?;
+                index.read_tree(&self.repo.find_tree(tree)?)?;
+                index.write()?;
+            }
+            UndoAction::Commit { commit, parent } => {
+                if self.repo.head()?.target() != Some(commit) {
+                    return Err(AppError::Undo(
+                        "the commit is no longer at the tip of the branch".to_string(),
+                    ));
+                }
+                match parent {
+                    Some(parent) => {
+                        let parent = self.repo.find_object(parent, None)?;
+                        self.repo.reset(&parent, ResetType::Soft, None)?;
+                    }
+                    None => self.repo.head()?.resolve()?.delete()?,
+                }
+            }
+        }
         Ok(())
     }
 
-    fn find_last_commit(&self) -> AppResult<Commit<'_>> {
-        let obj = self.repo.head()?.resolve()?.peel(git2::ObjectType::Commit)?;
-        Ok(obj.into_commit()
-            .map_err(|_| git2::Error::from_str("Couldn't find commit"))?)
+    /// Reads the base, our and their versions of a conflicted file.
+    pub fn conflict_sides(&self, path: &str) -> AppResult<ConflictSides> {
+        let conflict = self.find_conflict(path)?;
+        let read = |entry: &Option<git2::IndexEntry>| -> AppResult<Option<String>> {
+            match entry {
+                Some(entry) => {
+                    let blob = self.repo.find_blob(entry.id)?;
+                    Ok(Some(String::from_utf8_lossy(blob.content()).into_owned()))
+                }
+                None => Ok(None),
+            }
+        };
+        Ok(ConflictSides {
+            path: path.to_string(),
+            base: read(&conflict.ancestor)?,
+            ours: read(&conflict.our)?,
+            theirs: read(&conflict.their)?,
+        })
     }
 
-    pub fn get_log(&self) -> AppResult<Vec<CommitInfo>> {
-        let mut revwalk = self.repo.revwalk()?;
-        revwalk.push_head()?;
-        revwalk.set_sorting(git2::Sort::TIME)?;
-        let mut commits = Vec::new();
-        for oid in revwalk {
-            let commit = self.repo.find_commit(oid?)?;
-            let author = commit.author();
-            let name = author.name().unwrap_or("Unknown");
-            let dt = DateTime::from_timestamp(commit.time().seconds(), 0).unwrap_or_default();
-            let local_dt: DateTime<Local> = dt.into();
-            commits.push(CommitInfo {
-                id: commit.id().to_string().chars().take(7).collect(),
-                message: commit.summary().unwrap_or("").to_string(),
-                author: name.to_string(),
-                time: local_dt.format("%Y-%m-%d %H:%M:%S").to_string(),
-            });
+    /// Resolves a conflict by writing one side's version into the work tree,
+    /// with the line endings a checkout would give it, and staging it. A side that deleted the file deletes it here too.
+    pub fn resolve_conflict(&self, path: &str, side: ConflictSide) -> AppResult<()> {
+        let conflict = self.find_conflict(path)?;
+        let entry = match side {
+            ConflictSide::Ours => conflict.our,
+            ConflictSide::Theirs => conflict.their,
+        };
+        let full_path = self.path.join(path);
+        let mut index = self.repo.index()?;
+        match entry {
+            Some(entry) => {
+                fs::write(&full_path, self.checkout_contents(entry.id, path)?)?;
+                index.add_path(Path::new(path))?;
+            }
+            None => {
+                if full_path.exists() {
+                    fs::remove_file(&full_path)?;
+                }
+                index.remove_path(Path::new(path))?;
+            }
+        }
+        index.write()?;
+        Ok(())
+    }
+
+    /// An empty directory for the copies an external diff or merge tool
+    /// compares, inside the git directory so that it never shows up as
+    /// untracked. Whatever a previous run left in it is removed.
+    pub fn tool_dir(&self) -> AppResult<PathBuf> {
+        let dir = self.repo.path().join("dotatui-tool");
+        match fs::remove_dir_all(&dir) {
+            Err(e) if e.kind() != ErrorKind::NotFound => return Err(e.into()),
+            _ => {}
+        }
+        fs::create_dir_all(&dir)?;
+        Ok(dir)
+    }
+
+    /// Writes the versions of `item` a diff tool compares into `dir`: HEAD
+    /// and the index for a staged change, the index and the work tree for an
+    /// unstaged one. The work tree file is passed as it is, so that edits
+    /// made in the tool are kept; a missing version is an empty file. The
+    /// copies get the line endings a checkout would give them, so that they
+    /// compare cleanly with the work tree file.
+    pub fn diff_tool_files(&self, item: &StatusItem, dir: &Path) -> AppResult<ToolFiles> {
+        let path = Path::new(&item.path);
+        let index_blob = || -> AppResult<Option<git2::Oid>> {
+            Ok(self.repo.index()?.get_path(path, 0).map(|entry| entry.id))
+        };
+        let write = |label: &str, blob: Option<git2::Oid>| -> AppResult<PathBuf> {
+            let file = tool_file_name(dir, &item.path, label);
+            match blob {
+                Some(id) => fs::write(&file, self.checkout_contents(id, &item.path)?)?,
+                None => fs::write(&file, [])?,
+            }
+            Ok(file)
+        };
+        let (local, remote) = if item.is_staged {
+            let head = match self.head_tree()? {
+                Some(tree) => match tree.get_path(path) {
+                    Ok(entry) => Some(entry.id()),
+                    Err(e) if e.code() == git2::ErrorCode::NotFound => None,
+                    Err(e) => return Err(e.into()),
+                },
+                None => None,
+            };
+            (write("HEAD", head)?, write("INDEX", index_blob()?)?)
+        } else {
+            let work_file = self.path.join(path);
+            let remote = if work_file.exists() { work_file } else { write("DELETED", None)? };
+            (write("INDEX", index_blob()?)?, remote)
+        };
+        Ok(ToolFiles { local, remote, base: None, merged: None })
+    }
+
+    /// Writes our, their and the base version of the conflicted `path` into
+    /// `dir` for a merge tool, which saves its result over the work tree
+    /// file. Like a checkout, the copies get the configured line endings.
+    pub fn merge_tool_files(&self, path: &str, dir: &Path) -> AppResult<ToolFiles> {
+        let conflict = self.find_conflict(path)?;
+        let write = |label: &str, entry: &Option<git2::IndexEntry>| -> AppResult<PathBuf> {
+            let file = tool_file_name(dir, path, label);
+            match entry {
+                Some(entry) => fs::write(&file, self.checkout_contents(entry.id, path)?)?,
+                None => fs::write(&file, [])?,
+            }
+            Ok(file)
+        };
+        Ok(ToolFiles {
+            local: write("LOCAL", &conflict.our)?,
+            remote: write("REMOTE", &conflict.their)?,
+            base: Some(write("BASE", &conflict.ancestor)?),
+            merged: Some(self.path.join(path)),
+        })
+    }
+
+    /// Blob `id` as checking it out to `path` would write it, converted by
+    /// the filters `.gitattributes`, `core.autocrlf` and `core.eol` ask for,
+    /// e.g. to CRLF line endings.
+    fn checkout_contents(&self, id: git2::Oid, path: &str) -> AppResult<Vec<u8>> {
+        let time = git2::IndexTime::new(0, 0);
+        let mut index = git2::Index::new()?;
+        index.add(&git2::IndexEntry {
+            ctime: time,
+            mtime: time,
+            dev: 0,
+            ino: 0,
+            mode: 0o100644,
+            uid: 0,
+            gid: 0,
+            file_size: 0,
+            id,
+            flags: 0,
+            flags_extended: 0,
+            path: path.as_bytes().to_vec(),
+        })?;
+        // libgit2 only applies the filters while checking out, so the blob is
+        // checked out on its own into a scratch directory and read back.
+        // Without `update_index(false)` the checkout would replace the
+        // repository's index with this one-entry index.
+        let dir = self.repo.path().join("dotatui-checkout");
+        let mut checkout = CheckoutBuilder::new();
+        checkout.force().update_index(false).target_dir(&dir);
+        let result = self
+            .repo
+            .checkout_index(Some(&mut index), Some(&mut checkout))
+            .map_err(AppError::from)
+            .and_then(|()| Ok(fs::read(dir.join(path))?));
+        fs::remove_dir_all(&dir)?;
+        result
+    }
+
+    fn find_conflict(&self, path: &str) -> AppResult<IndexConflict> {
+        let index = self.repo.index()?;
+        for conflict in index.conflicts()? {
+            let conflict = conflict?;
+            let conflict_path = [&conflict.ancestor, &conflict.our, &conflict.their]
+                .into_iter()
+                .flatten()
+                .next()
+                .map(|entry| String::from_utf8_lossy(&entry.path).into_owned());
+            if conflict_path.as_deref() == Some(path) {
+                return Ok(conflict);
+            }
+        }
+        Err(AppError::Conflict(format!("{} has no conflict", path)))
+    }
+
+    /// The commit HEAD points to, or `None` on an unborn branch, i.e. in a
+    /// repository without commits.
+    fn head_commit(&self) -> AppResult<Option<Commit<'_>>> {
+        match self.find_last_commit() {
+            Ok(commit) => Ok(Some(commit)),
+            Err(AppError::NoCommits) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn head_tree(&self) -> AppResult<Option<git2::Tree<'_>>> {
+        self.head_commit()?.map(|commit| commit.tree()).transpose().map_err(Into::into)
+    }
+
+    /// The commit HEAD points to; fails with `AppError::NoCommits` on an
+    /// unborn branch.
+    fn find_last_commit(&self) -> AppResult<Commit<'_>> {
+        let head = match self.repo.head() {
+            Ok(head) => head,
+            Err(e) if e.code() == git2::ErrorCode::UnbornBranch => return Err(AppError::NoCommits),
+            Err(e) => return Err(e.into()),
+        };
+        let obj = head.resolve()?.peel(git2::ObjectType::Commit)?;
+        Ok(obj.into_commit()
+            .map_err(|_| git2::Error::from_str("Couldn't find commit"))?)
+    }
+
+    /// Reads HEAD's reflog, newest entry first.
+    pub fn get_reflog(&self) -> AppResult<Vec<ReflogEntry>> {
+        let reflog = self.repo.reflog("HEAD")?;
+        Ok(reflog
+            .iter()
+            .enumerate()
+            .map(|(index, entry)| ReflogEntry {
+                index,
+                old_oid: entry.id_old(),
+                new_oid: entry.id_new(),
+                message: entry.message().unwrap_or_default().to_string(),
+                time: format_time(entry.committer().when()),
+            })
+            .collect())
+    }
+
+    /// Checks out `oid` as a detached HEAD, refusing to overwrite local changes.
+    pub fn checkout_detached(&self, oid: git2::Oid) -> AppResult<()> {
+        let target = self.repo.find_object(oid, None)?;
+        self.repo
+            .checkout_tree(&target, Some(CheckoutBuilder::new().safe()))?;
+        self.repo.set_head_detached(oid)?;
+        Ok(())
+    }
+
+    /// The local branches, starting with the one HEAD was on before it got
+    /// detached, as its reflog remembers it.
+    pub fn local_branches(&self) -> AppResult<Vec<String>> {
+        let mut branches = Vec::new();
+        for branch in self.repo.branches(Some(git2::BranchType::Local))? {
+            if let Some(name) = branch?.0.name()? {
+                branches.push(name.to_string());
+            }
+        }
+        branches.sort();
+        let reflog = self.repo.reflog("HEAD")?;
+        let previous = reflog.iter().find_map(|entry| {
+            let message = entry.message()?.strip_prefix("checkout: moving from ")?;
+            let (from, _) = message.split_once(" to ")?;
+            branches.iter().position(|branch| branch == from)
+        });
+        if let Some(previous) = previous {
+            let branch = branches.remove(previous);
+            branches.insert(0, branch);
+        }
+        Ok(branches)
+    }
+
+    /// How many commits HEAD has that no local branch contains: the ones
+    /// lost from sight when a detached HEAD moves elsewhere.
+    pub fn commits_on_no_branch(&self) -> AppResult<usize> {
+        let mut walk = self.repo.revwalk()?;
+        walk.push_head()?;
+        for branch in self.repo.branches(Some(git2::BranchType::Local))? {
+            if let Some(oid) = branch?.0.get().target() {
+                walk.hide(oid)?;
+            }
+        }
+        Ok(walk.count())
+    }
+
+    /// Creates the branch `name` at HEAD and checks it out, like
+    /// `git switch -c`. The work tree stays as it is.
+    pub fn create_branch_at_head(&self, name: &str) -> AppResult<()> {
+        let commit = self.find_last_commit()?;
+        let branch = self.repo.branch(name, &commit, false)?;
+        let refname = branch.get().name().ok_or_else(|| git2::Error::from_str("invalid branch name"))?;
+        self.repo.set_head(refname)?;
+        Ok(())
+    }
+
+    /// Points the current branch (or the detached HEAD) at `oid` and resets
+    /// the index and work tree to it, like `git reset --hard`.
+    pub fn reset_hard(&self, oid: git2::Oid) -> AppResult<()> {
+        let target = self.repo.find_object(oid, None)?;
+        self.repo.reset(&target, ResetType::Hard, None)?;
+        Ok(())
+    }
+
+    /// Stashes the local changes and returns the stash commit. With
+    /// `StashFlags::DEFAULT` only the tracked files are stashed; callers
+    /// check that untracked files are listed before passing
+    /// `INCLUDE_UNTRACKED`, since in a `$HOME` work tree they are the rest
+    /// of the home directory.
+    pub fn stash_save(&mut self, message: Option<&str>, flags: StashFlags) -> AppResult<git2::Oid> {
+        let signature = self.repo.signature()?;
+        Ok(self.repo.stash_save2(&signature, message, Some(flags))?)
+    }
+
+    /// The current position of the stash commit `oid`, which shifts as
+    /// newer stashes are pushed; `None` once it was dropped.
+    pub fn stash_index(&self, oid: git2::Oid) -> AppResult<Option<usize>> {
+        Ok(self.repo.reflog(STASH_REF)?.iter().position(|entry| entry.id_new() == oid))
+    }
+
+    /// Whether tracked files differ from HEAD, in the index or the work
+    /// tree. Untracked files are left out, since checkouts and merges do not
+    /// touch them unless they are in the way.
+    pub fn has_local_changes(&self) -> AppResult<bool> {
+        let mut options = StatusOptions::new();
+        options.include_untracked(false).include_ignored(false);
+        Ok(!self.repo.statuses(Some(&mut options))?.is_empty())
+    }
+
+    /// The stashes, newest first. `stash@{n}` is entry `n` of the reflog
+    /// of `refs/stash`, which is empty until something is stashed.
+    pub fn get_stashes(&self) -> AppResult<Vec<StashInfo>> {
+        let reflog = self.repo.reflog(STASH_REF)?;
+        let mut stashes = Vec::with_capacity(reflog.len());
+        for (index, entry) in reflog.iter().enumerate() {
+            let commit = self.repo.find_commit(entry.id_new())?;
+            stashes.push(StashInfo {
+                index,
+                message: entry.message().unwrap_or_default().to_string(),
+                time: format_commit_time(&commit),
+            });
+        }
+        Ok(stashes)
+    }
+
+    pub fn stash_apply(&mut self, index: usize) -> AppResult<()> {
+        self.repo.stash_apply(index, None)?;
+        Ok(())
+    }
+
+    pub fn stash_pop(&mut self, index: usize) -> AppResult<()> {
+        self.repo.stash_pop(index, None)?;
+        Ok(())
+    }
+
+    pub fn stash_drop(&mut self, index: usize) -> AppResult<()> {
+        self.repo.stash_drop(index)?;
+        Ok(())
+    }
+
+    /// Returns the name of the checked out branch, or `None` for a detached HEAD.
+    pub fn current_branch(&self) -> AppResult<Option<String>> {
+        let head = self.repo.head()?;
+        if head.is_branch() {
+            Ok(head.shorthand().map(str::to_string))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// The ref HEAD points to, like `refs/heads/main`, also on a branch
+    /// without commits, where `current_branch` fails.
+    fn head_branch_ref(&self) -> AppResult<String> {
+        let head = self.repo.find_reference("HEAD")?;
+        match head.symbolic_target() {
+            Some(target) => Ok(target.to_string()),
+            None => Err(git2::Error::from_str("HEAD is detached").into()),
+        }
+    }
+
+    /// Resolves the remote name and remote-tracking ref for the current branch,
+    /// falling back to `origin/<branch>` when no upstream is configured.
+    fn upstream_of_head(&self) -> AppResult<(String, String)> {
+        let local_ref = self.head_branch_ref()?;
+        let branch = local_ref.trim_start_matches("refs/heads/");
+        let remote = self
+            .repo
+            .branch_upstream_remote(&local_ref)
+            .ok()
+            .and_then(|buf| buf.as_str().map(str::to_string))
+            .unwrap_or_else(|| "origin".to_string());
+        let upstream = self
+            .repo
+            .branch_upstream_name(&local_ref)
+            .ok()
+            .and_then(|buf| buf.as_str().map(str::to_string))
+            .unwrap_or_else(|| format!("refs/remotes/{}/{}", remote, branch));
+        Ok((remote, upstream))
+    }
+
+    /// Fetches the upstream remote of the current branch, reporting transfer
+    /// statistics through `on_progress` as objects arrive. Cancelling
+    /// `cancel` aborts the transfer.
+    pub fn fetch<F>(
+        &self,
+        prompter: &mut dyn Prompter,
+        cancel: &CancelToken,
+        mut on_progress: F,
+    ) -> AppResult<()>
+    where
+        F: FnMut(TransferStats),
+    {
+        let (remote_name, _) = self.upstream_of_head()?;
+        let mut remote = self.repo.find_remote(&remote_name)?;
+        let mut callbacks = remote_callbacks(self.repo.config()?, prompter);
+        let mut last_received = usize::MAX;
+        callbacks.transfer_progress(|progress| {
+            if progress.received_objects() != last_received {
+                last_received = progress.received_objects();
+                on_progress(TransferStats::from_progress(&progress));
+            }
+            !cancel.is_cancelled()
+        });
+        let mut fetch_options = FetchOptions::new();
+        fetch_options.remote_callbacks(callbacks);
+        remote
+            .fetch(&[] as &[&str], Some(&mut fetch_options), None)
+            .map_err(|e| AppError::FetchFailed(e.to_string()))
+    }
+
+    /// Pushes the current branch to the same-named branch on `origin`.
+    /// Pushes the current branch to its upstream, or to a branch of the same
+    /// name on `origin` when it has none. With `PushMode::ForceWithLease` the
+    /// remote branch is overwritten, but only if it still points where our
+    /// remote-tracking branch says it did at the last fetch.
+    ///
+    /// Progress is reported through `on_progress` as (objects sent, total
+    /// objects, bytes sent). libgit2 can only abort a push before the upload
+    /// starts, so cancelling `cancel` after that has no effect.
+    pub fn push<F>(
+        &self,
+        prompter: &mut dyn Prompter,
+        mode: PushMode,
+        cancel: &CancelToken,
+        on_progress: F,
+    ) -> AppResult<()>
+    where
+        F: FnMut(usize, usize, usize),
+    {
+        let branch = self
+            .current_branch()?
+            .ok_or_else(|| AppError::PushFailed("HEAD is detached".to_string()))?;
+        let local_ref = format!("refs/heads/{}", branch);
+        let (remote_name, tracking_ref) = self.upstream_of_head()?;
+        let dest_ref = self
+            .repo
+            .config()?
+            .get_string(&format!("branch.{}.merge", branch))
+            .unwrap_or_else(|_| local_ref.clone());
+        let mut remote = self.repo.find_remote(&remote_name)?;
+
+        let refspec = match mode {
+            PushMode::Normal => format!("{}:{}", local_ref, dest_ref),
+            PushMode::ForceWithLease => {
+                // libgit2 has no lease support, so compare the remote branch
+                // with our remote-tracking branch right before force-pushing.
+                let expected = self.repo.refname_to_id(&tracking_ref).ok();
+                let actual = {
+                    let callbacks = remote_callbacks(self.repo.config()?, prompter);
+                    let connection = remote
+                        .connect_auth(git2::Direction::Push, Some(callbacks), None)
+                        .map_err(|e| AppError::PushFailed(e.to_string()))?;
+                    let actual = connection
+                        .list()?
+                        .iter()
+                        .find(|head| head.name() == dest_ref)
+                        .map(|head| head.oid());
+                    actual
+                };
+                if actual != expected {
+                    return Err(AppError::PushFailed(format!(
+                        "{} on {} changed since the last fetch; fetch and review it first",
+                        dest_ref, remote_name
+                    )));
+                }
+                format!("+{}:{}", local_ref, dest_ref)
+            }
+        };
+        self.upload(&mut remote, &refspec, prompter, cancel, on_progress)
+    }
+
+    /// Pushes `refspec` to each of `remotes` in turn, e.g. to mirror the
+    /// dotfiles on GitHub and on a personal server, and returns how each
+    /// push went. Progress is reported with the name of the remote; once
+    /// `cancel` is cancelled, the remaining remotes are skipped.
+    pub fn push_to_remotes<F>(
+        &self,
+        prompter: &mut dyn Prompter,
+        remotes: &[String],
+        refspec: &str,
+        cancel: &CancelToken,
+        mut on_progress: F,
+    ) -> Vec<(String, AppResult<()>)>
+    where
+        F: FnMut(&str, usize, usize, usize),
+    {
+        remotes
+            .iter()
+            .map(|name| {
+                let result = if cancel.is_cancelled() {
+                    Err(AppError::PushFailed("cancelled".to_string()))
+                } else {
+                    self.repo
+                        .find_remote(name)
+                        .map_err(AppError::from)
+                        .and_then(|mut remote| {
+                            self.upload(&mut remote, refspec, prompter, cancel, |current, total, bytes| {
+                                on_progress(name, current, total, bytes)
+                            })
+                        })
+                };
+                (name.clone(), result)
+            })
+            .collect()
+    }
+
+    /// Sends `refspec` to `remote`, failing when the remote rejects it.
+    fn upload<F>(
+        &self,
+        remote: &mut git2::Remote<'_>,
+        refspec: &str,
+        prompter: &mut dyn Prompter,
+        cancel: &CancelToken,
+        mut on_progress: F,
+    ) -> AppResult<()>
+    where
+        F: FnMut(usize, usize, usize),
+    {
+        let mut rejection = None;
+        {
+            let mut callbacks = remote_callbacks(self.repo.config()?, prompter);
+            callbacks.push_negotiation(|_| {
+                if cancel.is_cancelled() {
+                    Err(git2::Error::from_str("cancelled"))
+                } else {
+                    Ok(())
+                }
+            });
+            callbacks.push_transfer_progress(&mut on_progress);
+            callbacks.push_update_reference(|refname, status| {
+                if let Some(status) = status {
+                    rejection = Some(format!("{} was rejected: {}", refname, status));
+                }
+                Ok(())
+            });
+            let mut push_options = PushOptions::new();
+            push_options.remote_callbacks(callbacks);
+            remote
+                .push(&[refspec], Some(&mut push_options))
+                .map_err(|e| AppError::PushFailed(e.to_string()))?;
+        }
+        match rejection {
+            Some(message) => Err(AppError::PushFailed(message)),
+            None => Ok(()),
+        }
+    }
+
+    /// The machine profile picked in this clone (`dotatui.profile`).
+    pub fn selected_profile(&self) -> Option<String> {
+        self.repo.config().ok()?.get_string("dotatui.profile").ok()
+    }
+
+    pub fn set_selected_profile(&self, name: &str) -> AppResult<()> {
+        self.repo.config()?.set_str("dotatui.profile", name)?;
+        Ok(())
+    }
+
+    /// The local branches and tags that point at `oid`, branches first.
+    pub fn refs_at(&self, oid: git2::Oid) -> AppResult<Vec<CommitRef>> {
+        let mut branches = Vec::new();
+        let mut tags = Vec::new();
+        for reference in self.repo.references()? {
+            let reference = reference?;
+            if reference.peel_to_commit().map(|commit| commit.id()).ok() != Some(oid) {
+                continue;
+            }
+            let Some(name) = reference.name() else {
+                continue;
+            };
+            if let Some(branch) = name.strip_prefix("refs/heads/") {
+                branches.push(CommitRef::Branch(branch.to_string()));
+            } else if let Some(tag) = name.strip_prefix("refs/tags/") {
+                tags.push(CommitRef::Tag(tag.to_string()));
+            }
+        }
+        branches.sort();
+        tags.sort();
+        branches.extend(tags);
+        Ok(branches)
+    }
+
+    /// Checks out the local branch `name`, refusing to overwrite local changes.
+    pub fn checkout_branch(&self, name: &str) -> AppResult<()> {
+        let refname = format!("refs/heads/{}", name);
+        let target = self.repo.find_reference(&refname)?.peel(git2::ObjectType::Commit)?;
+        self.repo
+            .checkout_tree(&target, Some(CheckoutBuilder::new().safe()))?;
+        self.repo.set_head(&refname)?;
+        Ok(())
+    }
+
+    /// Lists the main work tree followed by the linked worktrees.
+    pub fn worktrees(&self) -> AppResult<Vec<WorktreeInfo>> {
+        let common_dir = common_dir(&self.repo);
+        let current = fs::canonicalize(&self.path).ok();
+        let is_current = |path: &Path| fs::canonicalize(path).ok() == current;
+        let mut worktrees = Vec::new();
+        let main = if self.repo.is_worktree() {
+            GitRepo::open_with_work_tree(&common_dir, None).ok()
+        } else {
+            None
+        };
+        let (main_path, main_branch) = match &main {
+            Some(main) => (main.path().to_path_buf(), main.current_branch().ok().flatten()),
+            None if self.repo.is_worktree() => (PathBuf::new(), None),
+            None => (self.path.clone(), self.current_branch().ok().flatten()),
+        };
+        // A bare repository without a configured work tree has no main entry.
+        if !main_path.as_os_str().is_empty() {
+            worktrees.push(WorktreeInfo {
+                name: None,
+                is_current: is_current(&main_path),
+                location: RepoLocation {
+                    git_dir: common_dir.clone(),
+                    work_tree: Some(main_path.clone()),
+                },
+                path: main_path,
+                branch: main_branch,
+                locked: false,
+                valid: true,
+            });
+        }
+
+        for name in self.repo.worktrees()?.iter().flatten() {
+            let worktree = self.repo.find_worktree(name)?;
+            let path: PathBuf = worktree.path().components().collect();
+            let valid = worktree.validate().is_ok();
+            let branch = if valid {
+                Repository::open_from_worktree(&worktree)
+                    .ok()
+                    .and_then(|repo| repo.head().ok()?.shorthand().map(str::to_string))
+            } else {
+                None
+            };
+            worktrees.push(WorktreeInfo {
+                name: Some(name.to_string()),
+                is_current: is_current(&path),
+                location: RepoLocation {
+                    git_dir: common_dir.join("worktrees").join(name),
+                    work_tree: Some(path.clone()),
+                },
+                path,
+                branch,
+                locked: matches!(worktree.is_locked(), Ok(git2::WorktreeLockStatus::Locked(_))),
+                valid,
+            });
+        }
+        Ok(worktrees)
+    }
+
+    /// Adds a linked worktree at `path`, like `git worktree add <path>`: the
+    /// worktree is named after the directory and checks out the branch of
+    /// that name, which is created from HEAD if it does not exist yet.
+    /// Relative paths start next to the work tree, since a worktree inside
+    /// it would show up as untracked files of its own checkout; such paths
+    /// are refused.
+    pub fn add_worktree(&self, path: &Path) -> AppResult<()> {
+        // `join` keeps absolute paths as they are.
+        let path = self.path.parent().unwrap_or(&self.path).join(path);
+        let name = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .ok_or_else(|| AppError::Worktree(format!("invalid path {}", path.display())))?;
+        if path.exists() {
+            return Err(AppError::Worktree(format!("{} already exists", path.display())));
+        }
+        // The directory does not exist yet, so its parent is compared.
+        let inside = path
+            .parent()
+            .and_then(|parent| fs::canonicalize(parent).ok())
+            .zip(fs::canonicalize(&self.path).ok())
+            .is_some_and(|(parent, work_tree)| parent.starts_with(work_tree));
+        if inside {
+            return Err(AppError::Worktree(format!(
+                "{} is inside the work tree {}",
+                path.display(),
+                self.path.display()
+            )));
+        }
+        let branch = self.repo.find_branch(name, git2::BranchType::Local).ok();
+        let mut opts = git2::WorktreeAddOptions::new();
+        if let Some(branch) = &branch {
+            opts.reference(Some(branch.get()));
+        }
+        self.repo.worktree(name, &path, Some(&opts))?;
+        Ok(())
+    }
+
+    /// Removes the linked worktree `name` and deletes its directory. Like
+    /// `git worktree remove`, this refuses when the worktree has changes,
+    /// untracked files included, or is locked.
+    pub fn remove_worktree(&self, name: &str) -> AppResult<()> {
+        let worktree = self.repo.find_worktree(name)?;
+        if let Ok(git2::WorktreeLockStatus::Locked(_)) = worktree.is_locked() {
+            return Err(AppError::Worktree(format!("'{}' is locked", name)));
+        }
+        if fs::canonicalize(worktree.path()).ok() == fs::canonicalize(&self.path).ok() {
+            return Err(AppError::Worktree(
+                "cannot remove the worktree that is open".to_string(),
+            ));
+        }
+        if worktree.validate().is_ok() {
+            let repo = Repository::open_from_worktree(&worktree)?;
+            let mut opts = StatusOptions::new();
+            opts.include_untracked(true).include_ignored(false);
+            if !repo.statuses(Some(&mut opts))?.is_empty() {
+                return Err(AppError::Worktree(format!(
+                    "'{}' has uncommitted changes or untracked files",
+                    name
+                )));
+            }
+        }
+        worktree.prune(Some(
+            git2::WorktreePruneOptions::new()
+                .valid(true)
+                .working_tree(true),
+        ))?;
+        Ok(())
+    }
+
+    /// The names of the configured remotes.
+    /// `user.name` and `user.email` as commits would be signed off with,
+    /// `None` where unset.
+    pub fn user_identity(&self) -> (Option<String>, Option<String>) {
+        let Ok(config) = self.repo.config() else {
+            return (None, None);
+        };
+        (config.get_string("user.name").ok(), config.get_string("user.email").ok())
+    }
+
+    /// The entries of the repository's and the global config file, for the
+    /// git config view.
+    pub fn config_entries(&self) -> AppResult<Vec<ConfigEntry>> {
+        Ok(gitconfig::entries(&self.repo.config()?))
+    }
+
+    /// What to test next in `bisect`; see [`bisect::next_step`].
+    pub fn bisect_step(&self, bisect: &Bisect) -> AppResult<BisectStep> {
+        bisect::next_step(&self.repo, bisect)
+    }
+
+    pub fn set_config(&self, scope: ConfigScope, name: &str, value: &str) -> AppResult<()> {
+        gitconfig::set(&self.repo.config()?, scope, name, value)
+    }
+
+    pub fn unset_config(&self, scope: ConfigScope, name: &str) -> AppResult<()> {
+        gitconfig::unset(&self.repo.config()?, scope, name)
+    }
+
+    /// Connects to `remote` like a fetch does, without asking for
+    /// credentials, and hangs up again.
+    pub fn check_remote(&self, remote: &str) -> AppResult<()> {
+        let mut remote = self.repo.find_remote(remote)?;
+        let mut prompter = NoPrompt;
+        let callbacks = remote_callbacks(self.repo.config()?, &mut prompter);
+        remote
+            .connect_auth(git2::Direction::Fetch, Some(callbacks), None)
+            .map_err(|e| AppError::FetchFailed(e.message().to_string()))?;
+        Ok(())
+    }
+
+    pub fn remote_names(&self) -> AppResult<Vec<String>> {
+        Ok(self.repo.remotes()?.iter().flatten().map(str::to_string).collect())
+    }
+
+    /// The remotes a push to chosen remotes starts with: the ones last
+    /// chosen (`dotatui.pushRemote`, given once per remote), or else the
+    /// upstream remote of the current branch.
+    pub fn push_remotes(&self) -> AppResult<Vec<String>> {
+        let mut remotes = Vec::new();
+        let config = self.repo.config()?;
+        if let Ok(entries) = config.multivar("dotatui.pushRemote", None) {
+            entries.for_each(|entry| remotes.extend(entry.value().map(str::to_string)))?;
+        }
+        if remotes.is_empty() {
+            remotes.push(self.upstream_of_head()?.0);
+        }
+        Ok(remotes)
+    }
+
+    pub fn set_push_remotes(&self, remotes: &[String]) -> AppResult<()> {
+        let mut config = self.repo.config()?.open_level(git2::ConfigLevel::Local)?;
+        match config.remove_multivar("dotatui.pushRemote", ".*") {
+            Err(e) if e.code() != git2::ErrorCode::NotFound => return Err(e.into()),
+            _ => {}
+        }
+        for remote in remotes {
+            config.set_multivar("dotatui.pushRemote", "^$", remote)?;
+        }
+        Ok(())
+    }
+
+    /// Returns the URL of the remote called `name`, if there is one.
+    pub fn remote_url(&self, name: &str) -> Option<String> {
+        let remote = self.repo.find_remote(name).ok()?;
+        remote.url().map(str::to_string)
+    }
+
+    /// Whether the current branch has `branch.<name>.remote` and
+    /// `branch.<name>.merge` configured.
+    pub fn has_upstream(&self) -> AppResult<bool> {
+        let Some(branch) = self.current_branch()? else {
+            return Ok(false);
+        };
+        Ok(self
+            .repo
+            .branch_upstream_name(&format!("refs/heads/{}", branch))
+            .is_ok())
+    }
+
+    /// Makes `remote`'s branch of the same name the upstream of the current
+    /// branch. Unlike `Branch::set_upstream`, this works before the
+    /// remote-tracking branch exists, i.e. before the first push.
+    pub fn set_upstream(&self, remote: &str) -> AppResult<()> {
+        let branch = self
+            .current_branch()?
+            .ok_or_else(|| git2::Error::from_str("HEAD is detached"))?;
+        self.set_branch_upstream(&branch, Some((remote, &branch)))
+    }
+
+    /// The configured upstream of the local branch `branch`: the remote and
+    /// the branch on it, or `None` when it has none.
+    pub fn branch_upstream(&self, branch: &str) -> AppResult<Option<(String, String)>> {
+        let config = self.repo.config()?;
+        let remote = config.get_string(&format!("branch.{}.remote", branch));
+        let merge = config.get_string(&format!("branch.{}.merge", branch));
+        Ok(match (remote, merge) {
+            (Ok(remote), Ok(merge)) => Some((remote, merge.trim_start_matches("refs/heads/").to_string())),
+            _ => None,
+        })
+    }
+
+    /// Makes the local branch `branch` track `upstream`, a remote and the
+    /// branch on it, by writing `branch.<name>.remote` and `.merge`. `None`
+    /// removes both, like `git branch --unset-upstream`.
+    pub fn set_branch_upstream(&self, branch: &str, upstream: Option<(&str, &str)>) -> AppResult<()> {
+        let mut config = self.repo.config()?;
+        let remote_key = format!("branch.{}.remote", branch);
+        let merge_key = format!("branch.{}.merge", branch);
+        match upstream {
+            Some((remote, merge)) => {
+                self.repo.find_remote(remote)?;
+                config.set_str(&remote_key, remote)?;
+                config.set_str(&merge_key, &format!("refs/heads/{}", merge))?;
+            }
+            None => {
+                for key in [&remote_key, &merge_key] {
+                    match config.remove(key) {
+                        Err(e) if e.code() != git2::ErrorCode::NotFound => return Err(e.into()),
+                        _ => {}
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Renames the local branch `old` to `new` like `git branch -m`: its
+    /// reflog and configuration move along, and HEAD follows when `old` is
+    /// checked out.
+    pub fn rename_branch(&self, old: &str, new: &str) -> AppResult<()> {
+        self.repo.find_branch(old, git2::BranchType::Local)?.rename(new, false)?;
+        Ok(())
+    }
+
+    /// Returns the upstream of the current branch as `remote/branch`, or
+    /// `None` when none is configured or HEAD is detached.
+    pub fn upstream_name(&self) -> AppResult<Option<String>> {
+        if !self.has_upstream()? {
+            return Ok(None);
+        }
+        let (_, upstream) = self.upstream_of_head()?;
+        Ok(Some(upstream.trim_start_matches("refs/remotes/").to_string()))
+    }
+
+    /// Counts the commits HEAD is ahead of and behind its upstream, or `None`
+    /// when there is no upstream or it has not been fetched yet.
+    pub fn ahead_behind(&self) -> AppResult<Option<(usize, usize)>> {
+        if !self.has_upstream()? {
+            return Ok(None);
+        }
+        let (_, upstream) = self.upstream_of_head()?;
+        let Ok(upstream) = self.repo.refname_to_id(&upstream) else {
+            return Ok(None);
+        };
+        let Some(head) = self.repo.head()?.target() else {
+            return Ok(None);
+        };
+        Ok(Some(self.repo.graph_ahead_behind(head, upstream)?))
+    }
+
+    /// The commits a push and a pull of the current branch would move, as of
+    /// the last fetch, or `None` when there is no fetched upstream.
+    pub fn divergence(&self) -> AppResult<Option<Divergence>> {
+        let Some(name) = self.upstream_name()? else {
+            return Ok(None);
+        };
+        let (_, upstream) = self.upstream_of_head()?;
+        let Ok(upstream) = self.repo.refname_to_id(&upstream) else {
+            return Ok(None);
+        };
+        let Some(head) = self.repo.head()?.target() else {
+            return Ok(None);
+        };
+        Ok(Some(Divergence {
+            upstream: name,
+            ahead: self.commits_between(upstream, head)?,
+            behind: self.commits_between(head, upstream)?,
+        }))
+    }
+
+    /// The commits reachable from `to` but not from `from`, newest first.
+    fn commits_between(&self, from: git2::Oid, to: git2::Oid) -> AppResult<Vec<CommitInfo>> {
+        let mut revwalk = self.repo.revwalk()?;
+        revwalk.push(to)?;
+        revwalk.hide(from)?;
+        revwalk.set_sorting(git2::Sort::TOPOLOGICAL | git2::Sort::TIME)?;
+        let mut commits = Vec::new();
+        for oid in revwalk {
+            commits.push(self.commit_info(&self.repo.find_commit(oid?)?));
         }
         Ok(commits)
     }
+
+    /// When the repository was last fetched, judging by `FETCH_HEAD`.
+    pub fn last_fetch_time(&self) -> Option<DateTime<Local>> {
+        let modified = fs::metadata(self.repo.path().join("FETCH_HEAD"))
+            .and_then(|metadata| metadata.modified())
+            .ok()?;
+        Some(modified.into())
+    }
+
+    /// Gathers what the status bar shows about HEAD and its upstream. Parts
+    /// that cannot be determined, e.g. on an unborn branch, are left empty.
+    pub fn branch_status(&self) -> BranchStatus {
+        let head = self.repo.head().ok();
+        let branch = match &head {
+            Some(head) if head.is_branch() => head.shorthand().map(str::to_string),
+            Some(head) => head
+                .target()
+                .map(|oid| format!("{:.7}", oid.to_string())),
+            // An unborn branch has no commit yet, but HEAD still names it.
+            None => self
+                .repo
+                .find_reference("HEAD")
+                .ok()
+                .and_then(|head| head.symbolic_target().map(str::to_string))
+                .map(|target| target.trim_start_matches("refs/heads/").to_string()),
+        };
+        BranchStatus {
+            branch,
+            detached: self.repo.head_detached().unwrap_or(false),
+            unborn: self.repo.head().is_err_and(|e| e.code() == git2::ErrorCode::UnbornBranch),
+            upstream: self.upstream_name().ok().flatten(),
+            ahead_behind: self.ahead_behind().ok().flatten(),
+            last_fetch: self.last_fetch_time(),
+        }
+    }
+
+    /// Merges the already fetched upstream branch into HEAD. Fast-forwards when
+    /// possible, otherwise creates a merge commit, leaving conflicts in the index.
+    pub fn merge_upstream(&self) -> AppResult<MergeOutcome> {
+        let (_, upstream_ref) = self.upstream_of_head()?;
+        let upstream = self.repo.find_reference(&upstream_ref)?;
+        let their_commit = self.repo.reference_to_annotated_commit(&upstream)?;
+        let (analysis, _) = self.repo.merge_analysis(&[&their_commit])?;
+
+        if analysis.is_up_to_date() {
+            return Ok(MergeOutcome::UpToDate);
+        }
+
+        if analysis.is_fast_forward() || analysis.is_unborn() {
+            let target = self.repo.find_object(their_commit.id(), None)?;
+            self.repo
+                .checkout_tree(&target, Some(CheckoutBuilder::new().safe()))?;
+            if analysis.is_unborn() {
+                // The branch has no ref yet to move; it starts at theirs.
+                self.repo
+                    .reference(&self.head_branch_ref()?, their_commit.id(), true, "pull: initial")?;
+            } else {
+                let mut head_ref = self.repo.head()?;
+                head_ref.set_target(their_commit.id(), "pull: fast-forward")?;
+            }
+            return Ok(MergeOutcome::FastForward);
+        }
+
+        self.repo.merge(&[&their_commit], None, None)?;
+        let mut index = self.repo.index()?;
+        if index.has_conflicts() {
+            return Ok(MergeOutcome::Conflicts);
+        }
+
+        let tree = self.repo.find_tree(index.write_tree()?)?;
+        let signature = self.repo.signature()?;
+        let ours = self.find_last_commit()?;
+        let theirs = self.repo.find_commit(their_commit.id())?;
+        let message = format!(
+            "Merge remote-tracking branch '{}'",
+            upstream_ref.trim_start_matches("refs/remotes/")
+        );
+        self.repo.commit(
+            Some("HEAD"),
+            &signature,
+            &signature,
+            &message,
+            &tree,
+            &[&ours, &theirs],
+        )?;
+        self.repo.cleanup_state()?;
+        Ok(MergeOutcome::Merged)
+    }
+
+    /// Returns up to `limit` commits reachable from HEAD, newest first,
+    /// starting after the first `skip` of them.
+    pub fn get_log(&self, skip: usize, limit: usize) -> AppResult<Vec<CommitInfo>> {
+        let mut commits = Vec::new();
+        for oid in self.history()?.skip(skip).take(limit) {
+            let commit = self.repo.find_commit(oid?)?;
+            commits.push(self.commit_info(&commit));
+        }
+        Ok(commits)
+    }
+
+    /// Lists the commits that changed `path`, newest first, like
+    /// `git log -- <path>`. A directory matches every file below it. Merges
+    /// only count when they changed `path` relative to all of their
+    /// parents, i.e. when resolving the merge touched it.
+    pub fn get_file_log(&self, path: &str) -> AppResult<Vec<CommitInfo>> {
+        let mut options = DiffOptions::new();
+        // Literal paths, so that names with glob characters match only
+        // themselves.
+        options.pathspec(path).disable_pathspec_match(true);
+        let mut commits = Vec::new();
+        for oid in self.history()? {
+            let commit = self.repo.find_commit(oid?)?;
+            let tree = commit.tree()?;
+            let changed = |parent_tree: Option<&git2::Tree<'_>>, options: &mut DiffOptions| -> AppResult<bool> {
+                let diff = self.repo.diff_tree_to_tree(parent_tree, Some(&tree), Some(options))?;
+                Ok(diff.deltas().len() > 0)
+            };
+            let touched = if commit.parent_count() == 0 {
+                changed(None, &mut options)?
+            } else {
+                let mut touched = true;
+                for parent in commit.parents() {
+                    if !changed(Some(&parent.tree()?), &mut options)? {
+                        touched = false;
+                        break;
+                    }
+                }
+                touched
+            };
+            if touched {
+                commits.push(self.commit_info(&commit));
+            }
+        }
+        Ok(commits)
+    }
+
+    fn commit_info(&self, commit: &Commit<'_>) -> CommitInfo {
+        CommitInfo {
+            oid: commit.id(),
+            parents: commit.parent_ids().collect(),
+            id: commit.id().to_string().chars().take(7).collect(),
+            message: commit.summary().unwrap_or("").to_string(),
+            author: commit.author().name().unwrap_or("Unknown").to_string(),
+            time: format_commit_time(commit),
+            timestamp: commit.time().seconds(),
+            signed: self.repo.extract_signature(&commit.id(), None).is_ok(),
+            shallow: commit.parent_count() == 0 && self.repo.is_shallow(),
+        }
+    }
+
+    /// Returns the positions, in `get_log` order, of the commits whose
+    /// message, author or changed paths contain `query`, ignoring case.
+    pub fn search_log(&self, query: &str) -> AppResult<Vec<usize>> {
+        let query = query.to_lowercase();
+        let mut matches = Vec::new();
+        for (position, oid) in self.history()?.enumerate() {
+            let commit = self.repo.find_commit(oid?)?;
+            let message = commit.message().unwrap_or("").to_lowercase();
+            let author = commit.author().name().unwrap_or("").to_lowercase();
+            if message.contains(&query)
+                || author.contains(&query)
+                || self.commit_touches_path(&commit, &query)?
+            {
+                matches.push(position);
+            }
+        }
+        Ok(matches)
+    }
+
+    /// Loads the full message and the changed files of commit `oid`.
+    pub fn get_commit_detail(&self, oid: git2::Oid) -> AppResult<CommitDetail> {
+        let commit = self.repo.find_commit(oid)?;
+        let parent_tree = match commit.parent(0) {
+            Ok(parent) => Some(parent.tree()?),
+            Err(_) => None,
+        };
+        let mut diff = self
+            .repo
+            .diff_tree_to_tree(parent_tree.as_ref(), Some(&commit.tree()?), None)?;
+        diff.find_similar(None)?;
+        let files = diff
+            .deltas()
+            .map(|delta| (delta_status_char(delta.status()), delta_path(&delta)))
+            .collect();
+        let stats = diff.stats()?;
+        let author = commit.author();
+        Ok(CommitDetail {
+            id: commit.id().to_string(),
+            author: format!(
+                "{} <{}>",
+                author.name().unwrap_or("Unknown"),
+                author.email().unwrap_or("")
+            ),
+            time: format_commit_time(&commit),
+            message: commit.message().unwrap_or("").trim_end().to_string(),
+            files,
+            insertions: stats.insertions(),
+            deletions: stats.deletions(),
+        })
+    }
+
+    fn commit_touches_path(&self, commit: &Commit<'_>, query: &str) -> AppResult<bool> {
+        let parent_tree = match commit.parent(0) {
+            Ok(parent) => Some(parent.tree()?),
+            Err(_) => None,
+        };
+        let diff = self
+            .repo
+            .diff_tree_to_tree(parent_tree.as_ref(), Some(&commit.tree()?), None)?;
+        let touches = diff.deltas().any(|delta| {
+            [delta.old_file().path(), delta.new_file().path()]
+                .into_iter()
+                .flatten()
+                .any(|path| path.to_string_lossy().to_lowercase().contains(query))
+        });
+        Ok(touches)
+    }
+
+    /// Lists every file in the HEAD tree; empty when nothing is committed yet.
+    pub fn tracked_files(&self) -> AppResult<Vec<String>> {
+        let tree = match self.repo.head() {
+            Ok(head) => head.peel_to_tree()?,
+            Err(e) if e.code() == git2::ErrorCode::UnbornBranch => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+        let mut files = Vec::new();
+        tree.walk(git2::TreeWalkMode::PreOrder, |root, entry| {
+            if entry.kind() == Some(git2::ObjectType::Blob) {
+                if let Some(name) = entry.name() {
+                    files.push(format!("{}{}", root, name));
+                }
+            }
+            git2::TreeWalkResult::Ok
+        })?;
+        Ok(files)
+    }
+
+    /// Annotates each line of the committed version of `path` with the
+    /// commit that last changed it.
+    pub fn blame(&self, path: &str) -> AppResult<Vec<BlameLine>> {
+        let blame = self.repo.blame_file(Path::new(path), None)?;
+        let blob = self
+            .find_last_commit()?
+            .tree()?
+            .get_path(Path::new(path))?
+            .to_object(&self.repo)?
+            .peel_to_blob()?;
+        let contents = String::from_utf8_lossy(blob.content());
+        let lines = contents
+            .lines()
+            .enumerate()
+            .map(|(i, line)| {
+                // Blame line numbers are 1-based.
+                let hunk = blame.get_line(i + 1);
+                let (id, author) = match hunk {
+                    Some(hunk) => (
+                        hunk.final_commit_id().to_string().chars().take(7).collect(),
+                        hunk.final_signature().name().unwrap_or("Unknown").to_string(),
+                    ),
+                    None => (String::new(), String::new()),
+                };
+                BlameLine {
+                    id,
+                    author,
+                    content: line.to_string(),
+                }
+            })
+            .collect();
+        Ok(lines)
+    }
+
+    /// Walks the history reachable from HEAD, newest commit first but never
+    /// showing a parent before its children (the commit graph relies on it).
+    /// The walk is empty in a repository without commits.
+    /// Counts commits per month, changes per file and commits per author
+    /// over the history of HEAD, and sums the added and deleted lines.
+    /// Merges count as commits but not as changes, like `git log --numstat`
+    /// shows nothing for them.
+    pub fn history_stats(&self) -> AppResult<HistoryStats> {
+        let mut stats = HistoryStats::default();
+        let mut months: BTreeMap<(i32, u32), u64> = BTreeMap::new();
+        let mut files: HashMap<String, usize> = HashMap::new();
+        let mut authors: HashMap<String, usize> = HashMap::new();
+        for oid in self.history()? {
+            let commit = self.repo.find_commit(oid?)?;
+            stats.commits += 1;
+            let time: DateTime<Local> = DateTime::from_timestamp(commit.time().seconds(), 0)
+                .unwrap_or_default()
+                .into();
+            *months.entry((time.year(), time.month())).or_default() += 1;
+            let author = commit.author().name().unwrap_or("Unknown").to_string();
+            *authors.entry(author).or_default() += 1;
+            if commit.parent_count() > 1 {
+                continue;
+            }
+            let parent_tree = commit.parents().next().map(|parent| parent.tree()).transpose()?;
+            let diff = self.repo.diff_tree_to_tree(parent_tree.as_ref(), Some(&commit.tree()?), None)?;
+            for delta in diff.deltas() {
+                if let Some(path) = delta.new_file().path().or(delta.old_file().path()) {
+                    *files.entry(path.to_string_lossy().into_owned()).or_default() += 1;
+                }
+            }
+            let diff_stats = diff.stats()?;
+            stats.additions += diff_stats.insertions();
+            stats.deletions += diff_stats.deletions();
+        }
+
+        // Every month from the first commit to the last, quiet ones included,
+        // so that the sparkline's x axis is time.
+        if let (Some(&first), Some(&last)) = (months.keys().next(), months.keys().next_back()) {
+            let mut month = first;
+            while month <= last {
+                let count = months.get(&month).copied().unwrap_or(0);
+                stats.commits_per_month.push((format!("{}-{:02}", month.0, month.1), count));
+                month = if month.1 == 12 { (month.0 + 1, 1) } else { (month.0, month.1 + 1) };
+            }
+        }
+        stats.top_files = most_first(files);
+        stats.top_files.truncate(STATS_TOP_FILES);
+        stats.authors = most_first(authors);
+        Ok(stats)
+    }
+
+    fn history(&self) -> AppResult<git2::Revwalk<'_>> {
+        let mut revwalk = self.repo.revwalk()?;
+        if !self.repo.head().is_err_and(|e| e.code() == git2::ErrorCode::UnbornBranch) {
+            revwalk.push_head()?;
+        }
+        revwalk.set_sorting(git2::Sort::TOPOLOGICAL | git2::Sort::TIME)?;
+        Ok(revwalk)
+    }
+}
+
+/// Reads `core.worktree` from a bare repository's config. Relative values are
+/// resolved against the git directory, as git does.
+fn configured_work_tree(repo: &Repository) -> AppResult<Option<PathBuf>> {
+    match repo.config()?.get_path("core.worktree") {
+        Ok(path) if path.is_relative() => Ok(Some(repo.path().join(path))),
+        Ok(path) => Ok(Some(path)),
+        Err(_) => Ok(None),
+    }
+}
+
+/// Where git looks for global ignore patterns when `core.excludesFile` is
+/// not set.
+fn default_excludes_file() -> Option<PathBuf> {
+    std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+        .map(|dir| dir.join("git").join("ignore"))
+}
+
+/// `diff` in patch format, with only the hunk at position `hunk` of each
+/// file when given.
+fn patch_text(diff: &Diff<'_>, hunk: Option<usize>) -> AppResult<String> {
+    let mut text = String::new();
+    let mut hunk_index = None;
+    diff.print(git2::DiffFormat::Patch, |_, _, line| {
+        match line.origin() {
+            // A file header resets the count.
+            'F' => hunk_index = None,
+            'H' => hunk_index = Some(hunk_index.map_or(0, |i: usize| i + 1)),
+            _ => {}
+        }
+        if hunk.is_some() && line.origin() != 'F' && hunk_index != hunk {
+            return true;
+        }
+        if let origin @ ('+' | '-' | ' ') = line.origin() {
+            text.push(origin);
+        }
+        text.push_str(&String::from_utf8_lossy(line.content()));
+        true
+    })?;
+    Ok(text)
+}
+
+/// The git directory shared by all worktrees. libgit2 0.18 does not expose
+/// it, so it is read from a linked worktree's `commondir` file.
+fn common_dir(repo: &Repository) -> PathBuf {
+    if repo.is_worktree() {
+        if let Ok(contents) = fs::read_to_string(repo.path().join("commondir")) {
+            let dir = repo.path().join(contents.trim());
+            return fs::canonicalize(&dir).unwrap_or(dir);
+        }
+    }
+    repo.path().components().collect()
+}
+
+/// The directory name `git clone` picks for `url`: its last path segment
+/// without `.git`.
+pub fn clone_dir_name(url: &str) -> String {
+    let url = url.trim().trim_end_matches('/');
+    let url = url.strip_suffix(".git").unwrap_or(url).trim_end_matches('/');
+    url.rsplit(['/', ':'])
+        .next()
+        .filter(|name| !name.is_empty())
+        .unwrap_or("dotfiles")
+        .to_string()
+}
+
+/// The files of `diff` with their hunks, detecting renames.
+fn diff_files(diff: &mut Diff<'_>) -> AppResult<Vec<DiffFile>> {
+    diff.find_similar(None)?;
+    let mut files = Vec::with_capacity(diff.deltas().len());
+    for (i, delta) in diff.deltas().enumerate() {
+        let (hunks, binary) = match Patch::from_diff(diff, i)? {
+            Some(patch) => match binary_change(&patch, u64::MAX) {
+                Some(binary) => (Vec::new(), Some(binary)),
+                None => (patch_hunks(&patch)?, None),
+            },
+            None => (Vec::new(), None),
+        };
+        files.push(DiffFile {
+            path: delta_path(&delta),
+            status: delta_status_char(delta.status()),
+            binary,
+            hunks,
+        });
+    }
+    Ok(files)
+}
+
+/// The sizes of a change git treats as binary, which includes files over
+/// `size_limit` bytes.
+fn binary_change(patch: &Patch<'_>, size_limit: u64) -> Option<BinaryChange> {
+    let delta = patch.delta();
+    if !delta.flags().is_binary() {
+        return None;
+    }
+    let (old_size, new_size) = (delta.old_file().size(), delta.new_file().size());
+    Some(BinaryChange {
+        old_size,
+        new_size,
+        too_large: old_size.max(new_size) > size_limit,
+    })
+}
+
+/// The hunks of `patch` with their lines.
+fn patch_hunks(patch: &Patch<'_>) -> AppResult<Vec<Hunk>> {
+    let mut hunks = Vec::with_capacity(patch.num_hunks());
+    for i in 0..patch.num_hunks() {
+        let (hunk_header, num_lines) = patch.hunk(i)?;
+        let mut lines = Vec::with_capacity(num_lines);
+        for j in 0..num_lines {
+            let line = patch.line_in_hunk(i, j)?;
+            lines.push(Line {
+                origin: line.origin(),
+                content: String::from_utf8_lossy(line.content()).to_string(),
+                old_lineno: line.old_lineno(),
+                new_lineno: line.new_lineno(),
+            });
+        }
+        hunks.push(Hunk {
+            header: String::from_utf8_lossy(hunk_header.header()).to_string(),
+            lines,
+        });
+    }
+    Ok(hunks)
+}
+
+/// The letter `git diff --name-status` shows for `status`.
+fn delta_status_char(status: git2::Delta) -> char {
+    match status {
+        git2::Delta::Added => 'A',
+        git2::Delta::Deleted => 'D',
+        git2::Delta::Renamed => 'R',
+        git2::Delta::Copied => 'C',
+        git2::Delta::Typechange => 'T',
+        _ => 'M',
+    }
+}
+
+/// The new path of a changed file, or the old one if it was deleted.
+fn delta_path(delta: &git2::DiffDelta<'_>) -> String {
+    delta
+        .new_file()
+        .path()
+        .or(delta.old_file().path())
+        .map(|path| path.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+fn format_commit_time(commit: &Commit<'_>) -> String {
+    format_time(commit.time())
+}
+
+fn format_time(time: git2::Time) -> String {
+    let dt = DateTime::from_timestamp(time.seconds(), 0).unwrap_or_default();
+    let local_dt: DateTime<Local> = dt.into();
+    local_dt.format("%Y-%m-%d %H:%M:%S").to_string()
+}
+
+/// How many of the most often changed files `GitRepo::history_stats` lists.
+pub const STATS_TOP_FILES: usize = 10;
+
+/// Figures about the history of HEAD, shown in the stats view.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HistoryStats {
+    pub commits: usize,
+    /// `YYYY-MM` and the number of commits in it, oldest first.
+    pub commits_per_month: Vec<(String, u64)>,
+    /// The most often changed files and the number of commits changing
+    /// them, most first.
+    pub top_files: Vec<(String, usize)>,
+    /// Every author and the number of their commits, most first.
+    pub authors: Vec<(String, usize)>,
+    pub additions: usize,
+    pub deletions: usize,
+}
+
+/// The counts sorted by count, most first, and by name among equals.
+fn most_first(counts: HashMap<String, usize>) -> Vec<(String, usize)> {
+    let mut counts: Vec<_> = counts.into_iter().collect();
+    counts.sort_by(|(a_name, a), (b_name, b)| b.cmp(a).then_with(|| a_name.cmp(b_name)));
+    counts
+}
+
+/// A work tree of the repository, as listed by `GitRepo::worktrees`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WorktreeInfo {
+    /// The name of a linked worktree; `None` for the main work tree.
+    pub name: Option<String>,
+    pub path: PathBuf,
+    /// The checked-out branch, or `None` when HEAD is detached or the
+    /// worktree is missing.
+    pub branch: Option<String>,
+    /// Whether this is the work tree the app has open.
+    pub is_current: bool,
+    pub locked: bool,
+    /// `false` when the worktree's directory has gone missing.
+    pub valid: bool,
+    /// Where to open the worktree from with `GitRepo::open`.
+    pub location: RepoLocation,
+}
+
+/// A change made through the app that `GitRepo::undo` can revert.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UndoAction {
+    /// Restores the index to this tree, undoing a stage or unstage.
+    Index(git2::Oid),
+    /// Moves the branch from `commit` back to `parent`, keeping the index.
+    /// Without a parent, i.e. for the first commit, the branch is removed
+    /// again.
+    Commit { commit: git2::Oid, parent: Option<git2::Oid> },
+}
+
+/// The commits that only HEAD or only its upstream has.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Divergence {
+    /// Short name of the upstream, e.g. `origin/main`.
+    pub upstream: String,
+    /// Only on HEAD, what a push sends; newest first.
+    pub ahead: Vec<CommitInfo>,
+    /// Only on the upstream, what a pull brings in; newest first.
+    pub behind: Vec<CommitInfo>,
+}
+
+/// A named reference to a commit.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum CommitRef {
+    Branch(String),
+    Tag(String),
+}
+
+/// The branch information shown in the status bar.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BranchStatus {
+    /// The branch name, or the abbreviated commit when HEAD is detached.
+    pub branch: Option<String>,
+    pub detached: bool,
+    /// Whether the branch has no commits yet, as in a new repository.
+    pub unborn: bool,
+    pub upstream: Option<String>,
+    pub ahead_behind: Option<(usize, usize)>,
+    pub last_fetch: Option<DateTime<Local>>,
+}
+
+/// How much of a repository `GitRepo::clone_from_url` fetches, to clone a
+/// large one quickly.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CloneOptions {
+    /// Only the newest `depth` commits of each branch, like
+    /// `git clone --depth`.
+    pub depth: Option<u32>,
+    /// The branch to check out instead of the remote's default branch.
+    pub branch: Option<String>,
+    /// Fetch only `branch`, or the default branch, like
+    /// `git clone --single-branch`.
+    pub single_branch: bool,
+}
+
+/// The branch the remote at `url` has checked out, e.g. `main`.
+fn remote_default_branch(url: &str, prompter: &mut dyn Prompter) -> AppResult<String> {
+    let mut remote = git2::Remote::create_detached(url)?;
+    let callbacks = remote_callbacks(git2::Config::open_default()?, prompter);
+    let connection = remote
+        .connect_auth(git2::Direction::Fetch, Some(callbacks), None)
+        .map_err(|e| AppError::CloneFailed(e.message().to_string()))?;
+    let head = connection.default_branch()?;
+    let head = head
+        .as_str()
+        .ok_or_else(|| AppError::CloneFailed("the default branch is not valid UTF-8".to_string()))?;
+    Ok(head.trim_start_matches("refs/heads/").to_string())
+}
+
+/// Snapshot of `git2::Progress` sent to the UI while a fetch or clone is running.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TransferStats {
+    pub received_objects: usize,
+    pub indexed_objects: usize,
+    pub total_objects: usize,
+    pub received_bytes: usize,
+}
+
+impl TransferStats {
+    /// How much of the transfer is done, from 0.0 to 1.0. Objects count
+    /// once when received and once when indexed.
+    pub fn ratio(&self) -> f64 {
+        if self.total_objects == 0 {
+            return 0.0;
+        }
+        (self.received_objects + self.indexed_objects) as f64 / (2 * self.total_objects) as f64
+    }
+
+    fn from_progress(progress: &git2::Progress<'_>) -> Self {
+        Self {
+            received_objects: progress.received_objects(),
+            indexed_objects: progress.indexed_objects(),
+            total_objects: progress.total_objects(),
+            received_bytes: progress.received_bytes(),
+        }
+    }
+}
+
+/// The three versions of a conflicted file; a side is `None` when the file
+/// does not exist there (e.g. it was deleted on one branch).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConflictSides {
+    pub path: String,
+    pub base: Option<String>,
+    pub ours: Option<String>,
+    pub theirs: Option<String>,
+}
+
+/// Which version of a conflicted file to keep.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConflictSide {
+    Ours,
+    Theirs,
+}
+
+/// Expands a push refspec typed as `src[:dst]`, e.g. `main` or
+/// `main:master`, to full ref names. Names not starting with `refs/` are
+/// branches, and a leading `+` forces the push.
+pub fn qualify_push_refspec(spec: &str) -> Result<String, String> {
+    let spec = spec.trim();
+    let (force, spec) = match spec.strip_prefix('+') {
+        Some(spec) => ("+", spec),
+        None => ("", spec),
+    };
+    let (src, dst) = spec.split_once(':').unwrap_or((spec, spec));
+    if src.is_empty() || dst.is_empty() {
+        return Err("name a branch on both sides, e.g. main or main:master".to_string());
+    }
+    let qualify = |name: &str| {
+        let name = match name.starts_with("refs/") {
+            true => name.to_string(),
+            false => format!("refs/heads/{}", name),
+        };
+        match git2::Reference::is_valid_name(&name) {
+            true => Ok(name),
+            false => Err(format!("'{}' is not a valid ref name", name)),
+        }
+    };
+    Ok(format!("{}{}:{}", force, qualify(src)?, qualify(dst)?))
+}
+
+/// How `GitRepo::push` treats a remote branch that is not an ancestor of ours.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PushMode {
+    /// Reject non-fast-forward updates.
+    #[default]
+    Normal,
+    /// Overwrite the remote branch if it has not moved since the last fetch.
+    ForceWithLease,
+}
+
+/// The result of merging the upstream branch into HEAD after a fetch.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MergeOutcome {
+    UpToDate,
+    FastForward,
+    Merged,
+    Conflicts,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{RepoFixture, FIXTURE_BRANCH};
+
+    #[test]
+    fn cancelling_a_fetch_aborts_the_transfer() {
+        let upstream = RepoFixture::new().committed("a.txt", "one\n");
+        let local = RepoFixture::new().committed("b.txt", "two\n");
+        {
+            let repo = Repository::open(local.path()).unwrap();
+            repo.remote("origin", upstream.path().to_str().unwrap()).unwrap();
+        }
+        let repo = local.open();
+
+        let cancel = CancelToken::new();
+        cancel.cancel();
+        let result = repo.fetch(&mut NoPrompt, &cancel, |_| {});
+        assert!(matches!(result, Err(AppError::FetchFailed(_))), "{:?}", result);
+
+        repo.fetch(&mut NoPrompt, &CancelToken::new(), |_| {}).unwrap();
+        let tracking = format!("refs/remotes/origin/{}", FIXTURE_BRANCH);
+        assert!(repo.repo.refname_to_id(&tracking).is_ok());
+    }
+
+    #[test]
+    fn divergence_lists_what_a_push_and_a_pull_would_move() {
+        let upstream = RepoFixture::new().committed("a", "1\n");
+        let local = RepoFixture::new().tracking(&upstream).committed("b", "2\n");
+        let _upstream = upstream.committed("c", "3\n");
+        let local = local.fetched();
+
+        let repo = local.open();
+        let divergence = repo.divergence().unwrap().unwrap();
+        let messages = |commits: &[CommitInfo]| commits.iter().map(|c| c.message.clone()).collect::<Vec<_>>();
+        assert_eq!(divergence.upstream, format!("origin/{}", FIXTURE_BRANCH));
+        assert_eq!(messages(&divergence.ahead), ["Add b"]);
+        assert_eq!(messages(&divergence.behind), ["Add c"]);
+
+        let files = repo.commit_diff(divergence.behind[0].oid).unwrap();
+        assert_eq!(files.len(), 1);
+        assert_eq!((files[0].status, files[0].path.as_str()), ('A', "c"));
+    }
+
+    #[test]
+    fn line_endings_follow_the_checkout_filters() {
+        let fixture = RepoFixture::new().committed("a.txt", "one\ntwo\n");
+        fs::write(fixture.path().join("a.txt"), "one\r\ntwo\r\n").unwrap();
+        let item = StatusItem {
+            path: "a.txt".to_string(),
+            status: Status::WT_MODIFIED,
+            is_staged: false,
+        };
+        let repo = fixture.open();
+        let (hunks, _) = repo.get_diff_hunks(&item, DIFF_SIZE_LIMIT).unwrap();
+        assert_eq!(diff::line_ending_change(&hunks), Some(("LF", "CRLF")));
+
+        repo.repo.config().unwrap().set_bool("core.autocrlf", true).unwrap();
+        let repo = fixture.open();
+        assert_eq!(repo.get_diff_hunks(&item, DIFF_SIZE_LIMIT).unwrap(), (Vec::new(), None));
+        let files = repo.diff_tool_files(&item, &repo.tool_dir().unwrap()).unwrap();
+        assert_eq!(fs::read_to_string(files.local).unwrap(), "one\r\ntwo\r\n");
+    }
+
+    #[test]
+    fn file_log_lists_only_the_commits_that_changed_the_path() {
+        let fixture = RepoFixture::new()
+            .committed(".bashrc", "one\n")
+            .committed(".config/nvim/init.lua", "one\n")
+            .committed(".bashrc", "two\n")
+            .committed(".config/nvim/lua/plugins.lua", "one\n");
+        let repo = fixture.open();
+        let messages = |path: &str| -> Vec<String> {
+            repo.get_file_log(path).unwrap().into_iter().map(|commit| commit.message).collect()
+        };
+
+        assert_eq!(messages(".bashrc"), ["Add .bashrc", "Add .bashrc"]);
+        assert_eq!(messages(".config/nvim/init.lua"), ["Add .config/nvim/init.lua"]);
+        assert_eq!(
+            messages(".config/nvim"),
+            ["Add .config/nvim/lua/plugins.lua", "Add .config/nvim/init.lua"]
+        );
+        assert!(messages(".profile").is_empty());
+    }
+
+    #[test]
+    fn history_stats_count_commits_files_and_lines() {
+        let fixture = RepoFixture::new()
+            .committed(".bashrc", "one\n")
+            .committed(".vimrc", "set number\nsyntax on\n")
+            .committed(".bashrc", "two\nthree\n");
+        let stats = fixture.open().history_stats().unwrap();
+        assert_eq!(stats.commits, 3);
+        assert_eq!(stats.commits_per_month.len(), 1);
+        assert_eq!(stats.commits_per_month[0].1, 3);
+        assert_eq!(stats.top_files, [(".bashrc".to_string(), 2), (".vimrc".to_string(), 1)]);
+        assert_eq!(stats.authors, [("Test".to_string(), 3)]);
+        assert_eq!((stats.additions, stats.deletions), (5, 1));
+    }
+
+    #[test]
+    fn churn_and_summaries_count_lines_and_size() {
+        let fixture = RepoFixture::new()
+            .committed(".zshrc", "a\nb\nc\n")
+            .committed("font.bin", "\0\0")
+            .unstaged(".zshrc", "a\nB\nc\nd\n")
+            .unstaged("font.bin", "\0\0\0\0\0")
+            .unstaged("new.txt", "one\ntwo\n")
+            .staged("added.txt", "x\n");
+        let repo = fixture.open();
+        let churn = |path: &str| {
+            let item = repo.get_status(&UntrackedFiles::All).unwrap().into_iter().find(|item| item.path == path).unwrap();
+            repo.churn(&item, DIFF_SIZE_LIMIT).unwrap()
+        };
+        let lines = |churn: Churn| (churn.additions, churn.deletions, churn.size_delta);
+        assert_eq!(lines(churn(".zshrc")), (2, 1, 2));
+        assert_eq!(lines(churn("font.bin")), (0, 0, 3));
+        assert_eq!(lines(churn("new.txt")), (2, 0, 8));
+
+        let summary = repo.work_tree_summary(&UntrackedFiles::All).unwrap();
+        let totals = |summary: DiffSummary| (summary.files, summary.insertions, summary.deletions);
+        assert_eq!(totals(summary.staged), (1, 1, 0));
+        assert_eq!(totals(summary.unstaged), (3, 4, 1));
+        assert_eq!(totals(repo.work_tree_summary(&UntrackedFiles::Hidden).unwrap().unstaged), (2, 2, 1));
+    }
+
+    #[test]
+    fn the_first_commit_has_no_parents() {
+        let fixture = RepoFixture::new().staged(".bashrc", "alias ll='ls -l'\n");
+        let repo = fixture.open();
+        assert!(repo.branch_status().unborn);
+        assert_eq!(repo.get_log(0, 10).unwrap(), []);
+        assert!(matches!(repo.head_commit_id(), Err(AppError::NoCommits)));
+
+        let oid = repo.commit("Add bashrc", false).unwrap();
+        assert_eq!(repo.head_commit_id().unwrap(), oid);
+        assert_eq!(repo.get_log(0, 10).unwrap()[0].parents, []);
+        let files = repo.commit_diff(oid).unwrap();
+        assert_eq!(files.iter().map(|file| (file.path.as_str(), file.status)).collect::<Vec<_>>(), [(".bashrc", 'A')]);
+        assert!(!repo.branch_status().unborn);
+        assert_eq!(repo.branch_status().branch.as_deref(), Some(FIXTURE_BRANCH));
+
+        repo.undo(&UndoAction::Commit { commit: oid, parent: None }).unwrap();
+        assert!(repo.branch_status().unborn);
+        let staged = repo.get_status(&UntrackedFiles::All).unwrap();
+        assert_eq!(staged.iter().map(|item| (item.path.as_str(), item.is_staged)).collect::<Vec<_>>(), [(".bashrc", true)]);
+    }
+
+    #[test]
+    fn a_branch_is_pushed_to_each_chosen_remote() {
+        let local = RepoFixture::new().committed(".bashrc", "one\n");
+        let mirrors = [tempfile::TempDir::new().unwrap(), tempfile::TempDir::new().unwrap()];
+        let git = git2::Repository::open(local.path()).unwrap();
+        for (name, mirror) in ["github", "server"].into_iter().zip(&mirrors) {
+            git2::Repository::init_bare(mirror.path()).unwrap();
+            git.remote(name, mirror.path().to_str().unwrap()).unwrap();
+        }
+        git.remote("gone", local.path().join("gone").to_str().unwrap()).unwrap();
+        let repo = local.open();
+        assert_eq!(repo.push_remotes().unwrap(), ["origin"]);
+        let remotes = ["github", "gone", "server"].map(String::from);
+        repo.set_push_remotes(&remotes).unwrap();
+        assert_eq!(repo.push_remotes().unwrap(), remotes);
+
+        let refspec = qualify_push_refspec("main:dotfiles").unwrap();
+        assert_eq!(refspec, "refs/heads/main:refs/heads/dotfiles");
+        let results = repo.push_to_remotes(&mut NoPrompt, &remotes, &refspec, &CancelToken::new(), |_, _, _, _| {});
+        let failed: Vec<_> = results.iter().filter(|(_, result)| result.is_err()).map(|(name, _)| name.as_str()).collect();
+        assert_eq!(failed, ["gone"]);
+        let head = repo.head_commit_id().unwrap();
+        for mirror in &mirrors {
+            let mirror = git2::Repository::open_bare(mirror.path()).unwrap();
+            assert_eq!(mirror.refname_to_id("refs/heads/dotfiles").unwrap(), head);
+        }
+
+        assert_eq!(qualify_push_refspec("+main:refs/heads/x").unwrap(), "+refs/heads/main:refs/heads/x");
+        assert!(qualify_push_refspec(":main").is_err());
+        assert!(qualify_push_refspec("a..b").is_err());
+    }
+
+    #[test]
+    fn shallow_single_branch_clones_end_the_log_at_the_boundary() {
+        let origin = RepoFixture::new().committed(".vimrc", "set number\n").committed(".bashrc", "one\n");
+        let git = git2::Repository::open(origin.path()).unwrap();
+        git.branch("laptop", &git.head().unwrap().peel_to_commit().unwrap(), false).unwrap();
+        let dir = tempfile::TempDir::new().unwrap();
+        let options = CloneOptions {
+            depth: Some(1),
+            branch: Some(FIXTURE_BRANCH.to_string()),
+            single_branch: true,
+        };
+        let url = format!("file://{}", origin.path().display());
+        let repo = GitRepo::clone_from_url(&url, dir.path(), &options, &mut NoPrompt, &CancelToken::new(), |_| {}).unwrap();
+        let remote = repo.repo.find_remote("origin").unwrap();
+        let fetch: Vec<_> = remote.fetch_refspecs().unwrap().iter().flatten().map(String::from).collect();
+        assert_eq!(fetch, ["+refs/heads/main:refs/remotes/origin/main"]);
+        assert!(repo.repo.find_reference("refs/remotes/origin/laptop").is_err());
+
+        // The local transport ignores the depth, so the boundary git leaves
+        // behind is written by hand.
+        let log = repo.get_log(0, 10).unwrap();
+        fs::write(repo.repo.path().join("shallow"), format!("{}\n", log[0].oid)).unwrap();
+        let repo = GitRepo::open(&repo.location()).unwrap();
+        let log = repo.get_log(0, 10).unwrap();
+        assert_eq!(log.iter().map(|c| (c.message.as_str(), c.shallow)).collect::<Vec<_>>(), [("Add .bashrc", true)]);
+        assert_eq!(log[0].parents, []);
+        assert_eq!(repo.history_stats().unwrap().commits, 1);
+        assert!(repo.commit_diff(log[0].oid).is_ok());
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn committing_everything_leaves_untracked_files_and_runs_the_hooks() {
+        use std::os::unix::fs::PermissionsExt;
+        let fixture = RepoFixture::new().committed(".zshrc", "1\n");
+        let hook = fixture.path().join(".git/hooks/commit-msg");
+        std::fs::create_dir_all(hook.parent().unwrap()).unwrap();
+        std::fs::write(&hook, "#!/bin/sh\necho checked >> \"$1\"\n").unwrap();
+        std::fs::set_permissions(&hook, std::fs::Permissions::from_mode(0o755)).unwrap();
+        std::fs::write(fixture.path().join(".zshrc"), "2\n").unwrap();
+        std::fs::create_dir(fixture.path().join(".ssh")).unwrap();
+        std::fs::write(fixture.path().join(".ssh/id_ed25519"), "secret\n").unwrap();
+        let repo = fixture.open();
+
+        let oid = repo.commit_all("sync\n", false, None, |_| {}).unwrap().unwrap();
+        let commit = repo.repo.find_commit(oid).unwrap();
+        assert_eq!(commit.message(), Some("sync\nchecked\n"));
+        assert!(commit.tree().unwrap().get_name(".ssh").is_none());
+        assert_eq!(repo.commit_all("sync", false, None, |_| {}).unwrap(), None);
+
+        repo.repo.config().unwrap().set_str("status.showUntrackedFiles", "no").unwrap();
+        assert_eq!(repo.commit_all("sync", false, Some(&UntrackedFiles::All), |_| {}).unwrap(), None);
+        repo.repo.config().unwrap().set_str("status.showUntrackedFiles", "normal").unwrap();
+        let oid = repo.commit_all("sync", false, Some(&UntrackedFiles::All), |_| {}).unwrap().unwrap();
+        assert!(repo.repo.find_commit(oid).unwrap().tree().unwrap().get_path(Path::new(".ssh/id_ed25519")).is_ok());
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn signed_commits_carry_the_signature_of_the_configured_program() {
+        use std::os::unix::fs::PermissionsExt;
+        let fixture = RepoFixture::new().committed(".zshrc", "1\n").staged(".zshrc", "2\n");
+        let signer = fixture.path().join(".git/stub-gpg");
+        std::fs::write(
+            &signer,
+            "#!/bin/sh\ncat > /dev/null\nprintf -- '-----BEGIN PGP SIGNATURE-----\\nstub\\n-----END PGP SIGNATURE-----\\n'\n",
+        )
+        .unwrap();
+        std::fs::set_permissions(&signer, std::fs::Permissions::from_mode(0o755)).unwrap();
+        let repo = fixture.open();
+        repo.repo.config().unwrap().set_str("gpg.program", signer.to_str().unwrap()).unwrap();
+
+        let oid = repo.commit("Sign it\n", true).unwrap();
+        let (signature, _) = repo.repo.extract_signature(&oid, None).unwrap();
+        assert_eq!(signature.as_str(), Some("-----BEGIN PGP SIGNATURE-----\nstub\n-----END PGP SIGNATURE-----\n"));
+        assert_eq!(repo.head_commit().unwrap().unwrap().id(), oid);
+
+        std::fs::write(
+            &signer,
+            "#!/bin/sh\ncat > /dev/null\necho '[GNUPG:] KEY_CONSIDERED 1234 2' >&2\necho '[GNUPG:] INV_SGNR 9 dev@example.com' >&2\necho 'gpg: signing failed: No secret key' >&2\nexit 2\n",
+        )
+        .unwrap();
+        let error = repo.commit("Sign it again\n", true).unwrap_err().to_string();
+        assert!(error.contains("INV_SGNR 9 dev@example.com; gpg: signing failed: No secret key"), "{}", error);
+        assert!(!error.contains("KEY_CONSIDERED"), "{}", error);
+    }
+
+    #[test]
+    fn pulling_into_a_branch_without_commits_starts_it_at_the_upstream() {
+        let upstream = RepoFixture::new().committed(".zshrc", "1\n");
+        let local = RepoFixture::new();
+        git2::Repository::open(local.path())
+            .unwrap()
+            .remote("origin", upstream.path().to_str().unwrap())
+            .unwrap();
+        let local = local.fetched();
+        let repo = local.open();
+        assert!(repo.branch_status().unborn);
+
+        assert_eq!(repo.merge_upstream().unwrap(), MergeOutcome::FastForward);
+        let theirs = git2::Repository::open(upstream.path()).unwrap().head().unwrap().target().unwrap();
+        assert_eq!(repo.head_commit_id().unwrap(), theirs);
+        assert_eq!(repo.current_branch().unwrap().as_deref(), Some(FIXTURE_BRANCH));
+        assert_eq!(std::fs::read_to_string(local.path().join(".zshrc")).unwrap(), "1\n");
+    }
+
+    #[test]
+    fn relative_worktree_paths_start_next_to_the_work_tree() {
+        let fixture = RepoFixture::new().committed("a", "1\n");
+        let repo = fixture.open();
+        let inside = repo.add_worktree(&fixture.path().join("feature"));
+        assert!(matches!(inside, Err(AppError::Worktree(_))), "{:?}", inside);
+
+        let sibling = tempfile::TempDir::new_in(fixture.path().parent().unwrap()).unwrap();
+        let relative = Path::new(sibling.path().file_name().unwrap()).join("feature");
+        repo.add_worktree(&relative).unwrap();
+        assert!(sibling.path().join("feature").join("a").exists());
+        assert!(repo.repo.find_branch("feature", git2::BranchType::Local).is_ok());
+    }
 }
@@ -0,0 +1,190 @@
+//! src/git/bisect.rs
+
+use crate::error::{AppError, AppResult};
+use git2::{Oid, Repository, Sort};
+use std::collections::HashMap;
+
+/// The marks of a bisection, kept by the app rather than in `refs/bisect`,
+/// so that `git bisect` in a shell is not disturbed.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Bisect {
+    pub good: Vec<Oid>,
+    pub bad: Option<Oid>,
+    /// Commits that could not be tested, e.g. because they do not build.
+    pub skipped: Vec<Oid>,
+}
+
+/// What a tested commit turned out to be.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verdict {
+    Good,
+    Bad,
+    Skip,
+}
+
+impl Verdict {
+    pub fn name(self) -> &'static str {
+        match self {
+            Verdict::Good => "good",
+            Verdict::Bad => "bad",
+            Verdict::Skip => "skipped",
+        }
+    }
+}
+
+impl Bisect {
+    /// Records `verdict` for `oid`. A commit marked bad replaces the bad
+    /// commit before it.
+    pub fn mark(&mut self, oid: Oid, verdict: Verdict) {
+        self.good.retain(|good| *good != oid);
+        self.skipped.retain(|skipped| *skipped != oid);
+        match verdict {
+            Verdict::Good => self.good.push(oid),
+            Verdict::Bad => self.bad = Some(oid),
+            Verdict::Skip => self.skipped.push(oid),
+        }
+    }
+
+    pub fn verdict(&self, oid: Oid) -> Option<Verdict> {
+        if self.bad == Some(oid) {
+            Some(Verdict::Bad)
+        } else if self.good.contains(&oid) {
+            Some(Verdict::Good)
+        } else if self.skipped.contains(&oid) {
+            Some(Verdict::Skip)
+        } else {
+            None
+        }
+    }
+}
+
+/// Where a bisection stands.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BisectStep {
+    /// A good and a bad commit are needed before anything can be tested.
+    Incomplete,
+    /// Test this commit next. `candidates` commits, the bad one included,
+    /// may still be the first bad one, which takes about `steps` tests.
+    Test { commit: Oid, candidates: usize, steps: u32 },
+    /// The first bad commit.
+    Found(Oid),
+    /// Only skipped commits are left to test; the first bad commit is one
+    /// of these, the bad one included.
+    Undecided(Vec<Oid>),
+}
+
+/// The next step of `bisect`: of the commits that are ancestors of the bad
+/// one but of no good one, the untested commit that halves them best, like
+/// `git bisect` picks it.
+pub fn next_step(repo: &Repository, bisect: &Bisect) -> AppResult<BisectStep> {
+    let Some(bad) = bisect.bad.filter(|_| !bisect.good.is_empty()) else {
+        return Ok(BisectStep::Incomplete);
+    };
+    for &good in &bisect.good {
+        if good == bad || repo.graph_descendant_of(good, bad)? {
+            return Err(AppError::Bisect(format!(
+                "the good commit {:.7} comes after the bad commit {:.7}",
+                good.to_string(),
+                bad.to_string()
+            )));
+        }
+    }
+    let mut walk = repo.revwalk()?;
+    walk.set_sorting(Sort::TOPOLOGICAL)?;
+    walk.push(bad)?;
+    for &good in &bisect.good {
+        walk.hide(good)?;
+    }
+    // Children come before their parents.
+    let candidates = walk.collect::<Result<Vec<Oid>, _>>()?;
+    let index: HashMap<Oid, usize> = candidates.iter().enumerate().map(|(i, &oid)| (oid, i)).collect();
+
+    // Each candidate's ancestors among the candidates, itself included, as
+    // bit sets filled from the oldest commit up.
+    let words = candidates.len().div_ceil(64);
+    let mut ancestors = vec![vec![0u64; words]; candidates.len()];
+    for i in (0..candidates.len()).rev() {
+        let mut set = vec![0u64; words];
+        set[i / 64] |= 1 << (i % 64);
+        for parent in repo.find_commit(candidates[i])?.parent_ids() {
+            if let Some(&p) = index.get(&parent) {
+                for (word, parent_word) in set.iter_mut().zip(&ancestors[p]) {
+                    *word |= parent_word;
+                }
+            }
+        }
+        ancestors[i] = set;
+    }
+
+    let total = candidates.len();
+    let best = candidates
+        .iter()
+        .enumerate()
+        .filter(|(_, oid)| **oid != bad && !bisect.skipped.contains(oid))
+        .map(|(i, oid)| {
+            let below = ancestors[i].iter().map(|word| word.count_ones() as usize).sum::<usize>();
+            (below.min(total - below), *oid)
+        })
+        // The first of equally good ones, i.e. the newest.
+        .fold(None, |best: Option<(usize, Oid)>, (score, oid)| match best {
+            Some((best_score, _)) if best_score >= score => best,
+            _ => Some((score, oid)),
+        });
+    Ok(match best {
+        Some((_, commit)) => BisectStep::Test {
+            commit,
+            candidates: total,
+            steps: total.next_power_of_two().trailing_zeros(),
+        },
+        None if total == 1 => BisectStep::Found(bad),
+        None => BisectStep::Undecided(candidates),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::RepoFixture;
+
+    #[test]
+    fn bisecting_a_line_of_commits_finds_the_first_bad_one() {
+        let mut fixture = RepoFixture::new();
+        for i in 0..8 {
+            fixture = fixture.committed("zshrc", &format!("{}\n", i));
+        }
+        let repo = Repository::open(fixture.path()).unwrap();
+        let mut walk = repo.revwalk().unwrap();
+        walk.push_head().unwrap();
+        // Oldest first.
+        let commits: Vec<Oid> = walk.map(Result::unwrap).collect::<Vec<_>>().into_iter().rev().collect();
+        let first_bad = commits[5];
+
+        let mut bisect = Bisect::default();
+        assert_eq!(next_step(&repo, &bisect).unwrap(), BisectStep::Incomplete);
+        bisect.mark(commits[7], Verdict::Bad);
+        bisect.mark(commits[0], Verdict::Good);
+        let mut tests = 0;
+        let found = loop {
+            match next_step(&repo, &bisect).unwrap() {
+                BisectStep::Test { commit, .. } => {
+                    tests += 1;
+                    let position = commits.iter().position(|&oid| oid == commit).unwrap();
+                    let verdict = if position >= 5 { Verdict::Bad } else { Verdict::Good };
+                    bisect.mark(commit, verdict);
+                }
+                BisectStep::Found(oid) => break oid,
+                step => panic!("{:?}", step),
+            }
+        };
+        assert_eq!(found, first_bad);
+        assert!(tests <= 3);
+
+        let mut bisect = Bisect::default();
+        bisect.mark(commits[6], Verdict::Bad);
+        bisect.mark(commits[4], Verdict::Good);
+        bisect.mark(commits[5], Verdict::Skip);
+        assert_eq!(next_step(&repo, &bisect).unwrap(), BisectStep::Undecided(vec![commits[6], commits[5]]));
+        bisect.mark(commits[7], Verdict::Good);
+        assert!(matches!(next_step(&repo, &bisect), Err(AppError::Bisect(_))));
+    }
+}
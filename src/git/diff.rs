@@ -0,0 +1,235 @@
+//! src/git/diff.rs
+
+use super::Hunk;
+use std::ops::RangeInclusive;
+use unicode_segmentation::UnicodeSegmentation;
+
+/// A run of text from one side of a word diff.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WordSpan {
+    pub text: String,
+    /// Whether the text is missing from the other side.
+    pub changed: bool,
+}
+
+/// Lines with more tokens than this are not word-diffed, which keeps the
+/// quadratic comparison table small.
+const MAX_TOKENS: usize = 400;
+
+/// Compares two versions of a line word by word and returns the spans of
+/// `old` and of `new`. When the lines share no words at all, nothing is
+/// marked as changed, since highlighting every word would add no
+/// information.
+pub fn word_diff(old: &str, new: &str) -> (Vec<WordSpan>, Vec<WordSpan>) {
+    let old_tokens = tokenize(old);
+    let new_tokens = tokenize(new);
+    let unchanged = |text: &str| vec![WordSpan { text: text.to_string(), changed: false }];
+    if old_tokens.len() > MAX_TOKENS || new_tokens.len() > MAX_TOKENS {
+        return (unchanged(old), unchanged(new));
+    }
+
+    // lcs[i][j] is the length of the longest common subsequence of
+    // old_tokens[i..] and new_tokens[j..].
+    let (n, m) = (old_tokens.len(), new_tokens.len());
+    let mut lcs = vec![vec![0usize; m + 1]; n + 1];
+    for i in (0..n).rev() {
+        for j in (0..m).rev() {
+            lcs[i][j] = if old_tokens[i] == new_tokens[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let mut old_spans = Vec::new();
+    let mut new_spans = Vec::new();
+    let mut shares_words = false;
+    let (mut i, mut j) = (0, 0);
+    while i < n || j < m {
+        if i < n && j < m && old_tokens[i] == new_tokens[j] {
+            shares_words |= !old_tokens[i].trim().is_empty();
+            push_span(&mut old_spans, old_tokens[i], false);
+            push_span(&mut new_spans, new_tokens[j], false);
+            i += 1;
+            j += 1;
+        } else if j == m || (i < n && lcs[i + 1][j] >= lcs[i][j + 1]) {
+            push_span(&mut old_spans, old_tokens[i], true);
+            i += 1;
+        } else {
+            push_span(&mut new_spans, new_tokens[j], true);
+            j += 1;
+        }
+    }
+
+    if shares_words {
+        (old_spans, new_spans)
+    } else {
+        (unchanged(old), unchanged(new))
+    }
+}
+
+/// Builds a patch that applies only the changed lines of `hunk` whose
+/// indices are in `selected`. Unselected removals become context and
+/// unselected additions are dropped, so the rest of the hunk is left as it
+/// is. With `reverse`, the patch undoes the selected lines instead, for
+/// unstaging a hunk of the staged diff. `None` when the hunk header cannot
+/// be read.
+pub fn partial_patch(path: &str, hunk: &Hunk, selected: RangeInclusive<usize>, reverse: bool) -> Option<String> {
+    let (old_start, new_start) = hunk_starts(&hunk.header)?;
+    let start = if reverse { new_start } else { old_start };
+    let mut body = String::new();
+    let (mut old_count, mut new_count) = (0, 0);
+    for (i, line) in hunk.lines.iter().enumerate() {
+        let origin = match (line.origin, reverse) {
+            ('+', true) => '-',
+            ('-', true) => '+',
+            (origin, _) => origin,
+        };
+        let origin = match origin {
+            ' ' => ' ',
+            '+' | '-' if selected.contains(&i) => origin,
+            '-' => ' ',
+            // Unselected additions, and git's end-of-file markers, which are
+            // added back below where still needed.
+            _ => continue,
+        };
+        if origin != '+' {
+            old_count += 1;
+        }
+        if origin != '-' {
+            new_count += 1;
+        }
+        body.push(origin);
+        body.push_str(&line.content);
+        if !line.content.ends_with('\n') {
+            body.push_str("\n\\ No newline at end of file\n");
+        }
+    }
+    // A hunk without old lines names the line it inserts after.
+    let new_start = if old_count == 0 { start + 1 } else { start };
+    Some(format!(
+        "diff --git a/{path} b/{path}\n--- a/{path}\n+++ b/{path}\n@@ -{},{} +{},{} @@\n{}",
+        start,
+        old_count,
+        new_start,
+        new_count,
+        body,
+        path = path
+    ))
+}
+
+/// `hunk` as it appears in a unified diff: the header, then each line
+/// behind its `+`, `-` or space.
+pub fn hunk_text(hunk: &Hunk) -> String {
+    let mut text = hunk.header.clone();
+    if !text.ends_with('\n') {
+        text.push('\n');
+    }
+    for line in hunk.lines.iter().filter(|line| matches!(line.origin, ' ' | '+' | '-')) {
+        text.push(line.origin);
+        text.push_str(&line.content);
+        if !line.content.ends_with('\n') {
+            text.push('\n');
+        }
+    }
+    text
+}
+
+/// The line endings of the old and the new version, e.g. `("LF", "CRLF")`,
+/// when they are all that `hunks` change. A side is `mixed` when its lines
+/// do not all end the same way.
+pub fn line_ending_change(hunks: &[Hunk]) -> Option<(&'static str, &'static str)> {
+    let changed = |origin: char| {
+        hunks
+            .iter()
+            .flat_map(|hunk| &hunk.lines)
+            .filter(move |line| line.origin == origin)
+    };
+    let (mut removed, mut added) = (changed('-'), changed('+'));
+    let (mut old_ending, mut new_ending) = (None, None);
+    loop {
+        match (removed.next(), added.next()) {
+            (None, None) => break,
+            (Some(old), Some(new)) => {
+                let (old_text, old_eol) = split_line_ending(&old.content);
+                let (new_text, new_eol) = split_line_ending(&new.content);
+                if old_text != new_text || old_eol == new_eol {
+                    return None;
+                }
+                for (ending, eol) in [(&mut old_ending, old_eol), (&mut new_ending, new_eol)] {
+                    *ending = match *ending {
+                        Some(seen) if seen != eol => Some("mixed"),
+                        _ => Some(eol),
+                    };
+                }
+            }
+            _ => return None,
+        }
+    }
+    Some((old_ending?, new_ending?))
+}
+
+/// A line without its line ending, and the name of the ending.
+fn split_line_ending(line: &str) -> (&str, &'static str) {
+    if let Some(text) = line.strip_suffix("\r\n") {
+        (text, "CRLF")
+    } else if let Some(text) = line.strip_suffix('\n') {
+        (text, "LF")
+    } else {
+        (line, "no newline")
+    }
+}
+
+/// The old and new start lines of a hunk header such as `@@ -3,7 +3,8 @@`.
+fn hunk_starts(header: &str) -> Option<(u32, u32)> {
+    let mut ranges = header.strip_prefix("@@ -")?.split_whitespace();
+    let old = ranges.next()?;
+    let new = ranges.next()?.strip_prefix('+')?;
+    let start = |range: &str| range.split(',').next()?.parse().ok();
+    Some((start(old)?, start(new)?))
+}
+
+/// Splits a line into words, runs of whitespace, and single punctuation
+/// characters.
+fn tokenize(line: &str) -> Vec<&str> {
+    let class = |c: char| {
+        if c.is_alphanumeric() || c == '_' {
+            0
+        } else if c.is_whitespace() {
+            1
+        } else {
+            2
+        }
+    };
+    let mut tokens = Vec::new();
+    let mut start = 0;
+    let mut previous = None;
+    // Graphemes rather than chars, so that an accent or an emoji modifier
+    // stays with the character it belongs to.
+    for (i, grapheme) in line.grapheme_indices(true) {
+        let current = grapheme.chars().next().map_or(2, class);
+        // Punctuation is never grouped, so `->` and `==` diff per character.
+        if previous.is_some() && (previous != Some(current) || current == 2) {
+            tokens.push(&line[start..i]);
+            start = i;
+        }
+        previous = Some(current);
+    }
+    if start < line.len() {
+        tokens.push(&line[start..]);
+    }
+    tokens
+}
+
+/// Appends `text`, merging it into the last span when both have the same
+/// `changed` flag.
+fn push_span(spans: &mut Vec<WordSpan>, text: &str, changed: bool) {
+    match spans.last_mut() {
+        Some(last) if last.changed == changed => last.text.push_str(text),
+        _ => spans.push(WordSpan {
+            text: text.to_string(),
+            changed,
+        }),
+    }
+}
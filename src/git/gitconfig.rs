@@ -0,0 +1,302 @@
+//! src/git/gitconfig.rs
+
+use crate::{dotfiles::home_dir, error::AppResult};
+use git2::{Config, ConfigLevel};
+use std::collections::BTreeMap;
+
+/// The config file an entry is read from and written to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConfigScope {
+    /// `.git/config` of the repository.
+    Local,
+    /// `~/.gitconfig`, or `$XDG_CONFIG_HOME/git/config` when git uses it.
+    Global,
+}
+
+impl ConfigScope {
+    pub fn name(self) -> &'static str {
+        match self {
+            ConfigScope::Local => "local",
+            ConfigScope::Global => "global",
+        }
+    }
+
+    pub fn other(self) -> Self {
+        match self {
+            ConfigScope::Local => ConfigScope::Global,
+            ConfigScope::Global => ConfigScope::Local,
+        }
+    }
+}
+
+/// The values a setting takes, so that a typo is caught before git trips
+/// over it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ValueKind {
+    Text,
+    Bool,
+    /// A number, optionally with git's `k`, `m` or `g` suffix.
+    Int,
+    Email,
+    /// One of these words.
+    Choice(&'static [&'static str]),
+}
+
+/// A setting the config view lists even while it is unset.
+pub struct Setting {
+    pub name: &'static str,
+    pub kind: ValueKind,
+    pub description: &'static str,
+}
+
+/// What a new machine usually needs before the first commit and push.
+pub const COMMON_SETTINGS: [Setting; 17] = [
+    Setting { name: "user.name", kind: ValueKind::Text, description: "your name in commits" },
+    Setting { name: "user.email", kind: ValueKind::Email, description: "your email address in commits" },
+    Setting { name: "init.defaultBranch", kind: ValueKind::Text, description: "the branch of new repositories" },
+    Setting {
+        name: "pull.rebase",
+        kind: ValueKind::Choice(&["false", "true", "merges", "interactive"]),
+        description: "rebase onto the upstream instead of merging it",
+    },
+    Setting {
+        name: "pull.ff",
+        kind: ValueKind::Choice(&["true", "false", "only"]),
+        description: "whether a pull may fast-forward, or only that",
+    },
+    Setting {
+        name: "push.default",
+        kind: ValueKind::Choice(&["simple", "current", "upstream", "matching", "nothing"]),
+        description: "what a push without a refspec pushes",
+    },
+    Setting { name: "push.autoSetupRemote", kind: ValueKind::Bool, description: "set the upstream on the first push" },
+    Setting { name: "fetch.prune", kind: ValueKind::Bool, description: "delete branches the remote deleted" },
+    Setting { name: "core.editor", kind: ValueKind::Text, description: "the editor for commit messages" },
+    Setting {
+        name: "core.autocrlf",
+        kind: ValueKind::Choice(&["false", "true", "input"]),
+        description: "convert line endings on checkout and commit",
+    },
+    Setting { name: "core.excludesFile", kind: ValueKind::Text, description: "ignore patterns of every repository" },
+    Setting { name: "commit.gpgSign", kind: ValueKind::Bool, description: "sign every commit" },
+    Setting {
+        name: "gpg.format",
+        kind: ValueKind::Choice(&["openpgp", "x509", "ssh"]),
+        description: "what signs the commits",
+    },
+    Setting { name: "user.signingKey", kind: ValueKind::Text, description: "the key that signs the commits" },
+    Setting {
+        name: "merge.conflictStyle",
+        kind: ValueKind::Choice(&["merge", "diff3", "zdiff3"]),
+        description: "show the common ancestor in conflicts",
+    },
+    Setting { name: "credential.helper", kind: ValueKind::Text, description: "where HTTPS passwords are stored" },
+    Setting { name: "diff.renameLimit", kind: ValueKind::Int, description: "files compared when detecting renames" },
+];
+
+/// A setting with its values in both scopes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigEntry {
+    pub name: String,
+    pub local: Option<String>,
+    pub global: Option<String>,
+    /// Set more than once in one file, like `remote.origin.fetch`; such
+    /// entries are left to `git config`.
+    pub multivalued: bool,
+    pub kind: ValueKind,
+    pub description: &'static str,
+}
+
+impl ConfigEntry {
+    pub fn value(&self, scope: ConfigScope) -> Option<&str> {
+        match scope {
+            ConfigScope::Local => self.local.as_deref(),
+            ConfigScope::Global => self.global.as_deref(),
+        }
+    }
+}
+
+/// The common settings, then every other entry of the local and global
+/// files, sorted by name. `config` is the repository's config.
+pub fn entries(config: &Config) -> Vec<ConfigEntry> {
+    let mut entries: Vec<ConfigEntry> = COMMON_SETTINGS
+        .iter()
+        .map(|setting| ConfigEntry {
+            name: setting.name.to_string(),
+            local: None,
+            global: None,
+            multivalued: false,
+            kind: setting.kind,
+            description: setting.description,
+        })
+        .collect();
+    let mut others = BTreeMap::new();
+    for scope in [ConfigScope::Local, ConfigScope::Global] {
+        let Ok(level) = config.open_level(level(scope)) else {
+            continue;
+        };
+        let mut values: Vec<(String, String)> = Vec::new();
+        if let Ok(mut iter) = level.entries(None) {
+            while let Some(Ok(entry)) = iter.next() {
+                if let (Some(name), Some(value)) = (entry.name(), entry.value()) {
+                    values.push((name.to_string(), value.to_string()));
+                }
+            }
+        }
+        for (name, value) in values {
+            let index = match entries.iter().position(|entry| entry.name.eq_ignore_ascii_case(&name)) {
+                Some(index) => index,
+                None => *others.entry(name.clone()).or_insert_with(|| {
+                    entries.push(ConfigEntry {
+                        name,
+                        local: None,
+                        global: None,
+                        multivalued: false,
+                        kind: ValueKind::Text,
+                        description: "",
+                    });
+                    entries.len() - 1
+                }),
+            };
+            let entry = &mut entries[index];
+            let slot = match scope {
+                ConfigScope::Local => &mut entry.local,
+                ConfigScope::Global => &mut entry.global,
+            };
+            match slot {
+                Some(previous) => {
+                    previous.push_str(", ");
+                    previous.push_str(&value);
+                    entry.multivalued = true;
+                }
+                None => *slot = Some(value),
+            }
+        }
+    }
+    entries[COMMON_SETTINGS.len()..].sort_by(|a, b| a.name.cmp(&b.name));
+    entries
+}
+
+/// The kind of the setting `name`; settings not in `COMMON_SETTINGS` take
+/// any text.
+pub fn kind_of(name: &str) -> ValueKind {
+    COMMON_SETTINGS
+        .iter()
+        .find(|setting| setting.name.eq_ignore_ascii_case(name))
+        .map_or(ValueKind::Text, |setting| setting.kind)
+}
+
+/// Checks `value` for a setting of `kind` and returns it the way git
+/// writes it, e.g. `true` for `yes`.
+pub fn normalize(kind: ValueKind, value: &str) -> Result<String, String> {
+    let value = value.trim();
+    match kind {
+        ValueKind::Text => Ok(value.to_string()),
+        ValueKind::Bool => match value.to_ascii_lowercase().as_str() {
+            "true" | "yes" | "on" | "1" => Ok("true".to_string()),
+            "false" | "no" | "off" | "0" => Ok("false".to_string()),
+            _ => Err(format!("'{}' is not true or false", value)),
+        },
+        ValueKind::Int => {
+            let digits = value.strip_suffix(['k', 'K', 'm', 'M', 'g', 'G']).unwrap_or(value);
+            match digits.parse::<i64>() {
+                Ok(_) => Ok(value.to_string()),
+                Err(_) => Err(format!("'{}' is not a number", value)),
+            }
+        }
+        ValueKind::Email => {
+            let valid = value.split_once('@').is_some_and(|(user, host)| !user.is_empty() && !host.is_empty())
+                && !value.contains(|c: char| c.is_whitespace() || c == '<' || c == '>');
+            if valid {
+                Ok(value.to_string())
+            } else {
+                Err(format!("'{}' is not an email address", value))
+            }
+        }
+        ValueKind::Choice(choices) => choices
+            .iter()
+            .find(|choice| choice.eq_ignore_ascii_case(value))
+            .map(|choice| choice.to_string())
+            .ok_or_else(|| format!("'{}' is not one of {}", value, choices.join(", "))),
+    }
+}
+
+/// Refuses names git cannot store, which need a section and a key, like
+/// `user.name`.
+pub fn validate_name(name: &str) -> Result<(), String> {
+    let valid = match name.split_once('.') {
+        Some((section, rest)) => {
+            let key = rest.rsplit('.').next().unwrap_or(rest);
+            !section.is_empty()
+                && section.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+                && key.starts_with(|c: char| c.is_ascii_alphabetic())
+                && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+        }
+        None => false,
+    };
+    if valid {
+        Ok(())
+    } else {
+        Err(format!("'{}' is not a config name like user.name", name))
+    }
+}
+
+/// Sets `name` in the file of `scope`, creating `~/.gitconfig` for the
+/// global scope when the user has no global file yet.
+pub fn set(config: &Config, scope: ConfigScope, name: &str, value: &str) -> AppResult<()> {
+    open(config, scope)?.set_str(name, value)?;
+    Ok(())
+}
+
+pub fn unset(config: &Config, scope: ConfigScope, name: &str) -> AppResult<()> {
+    open(config, scope)?.remove(name)?;
+    Ok(())
+}
+
+fn open(config: &Config, scope: ConfigScope) -> AppResult<Config> {
+    match (config.open_level(level(scope)), scope) {
+        (Ok(config), _) => Ok(config),
+        (Err(_), ConfigScope::Global) => {
+            let path = Config::find_global()
+                .ok()
+                .or_else(|| home_dir().map(|home| home.join(".gitconfig")))
+                .ok_or_else(|| git2::Error::from_str("no home directory for the global config"))?;
+            Ok(Config::open(&path)?)
+        }
+        (Err(e), ConfigScope::Local) => Err(e.into()),
+    }
+}
+
+fn level(scope: ConfigScope) -> ConfigLevel {
+    match scope {
+        ConfigScope::Local => ConfigLevel::Local,
+        ConfigScope::Global => ConfigLevel::Global,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn values_are_checked_by_kind_and_written_the_way_git_does() {
+        assert_eq!(normalize(ValueKind::Bool, "Yes"), Ok("true".to_string()));
+        assert_eq!(normalize(ValueKind::Bool, "off"), Ok("false".to_string()));
+        assert!(normalize(ValueKind::Bool, "maybe").is_err());
+        assert_eq!(normalize(ValueKind::Int, "512k"), Ok("512k".to_string()));
+        assert!(normalize(ValueKind::Int, "lots").is_err());
+        assert!(normalize(ValueKind::Email, "me@example.com").is_ok());
+        assert!(normalize(ValueKind::Email, "Me <me@example.com>").is_err());
+        assert_eq!(normalize(kind_of("pull.rebase"), "Merges"), Ok("merges".to_string()));
+        assert_eq!(
+            normalize(kind_of("PULL.REBASE"), "always"),
+            Err("'always' is not one of false, true, merges, interactive".to_string())
+        );
+        assert_eq!(kind_of("alias.st"), ValueKind::Text);
+
+        assert!(validate_name("user.name").is_ok());
+        assert!(validate_name("remote.origin.url").is_ok());
+        assert!(validate_name("name").is_err());
+        assert!(validate_name("user.1st").is_err());
+    }
+}
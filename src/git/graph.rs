@@ -0,0 +1,115 @@
+//! src/git/graph.rs
+
+use super::CommitInfo;
+use git2::Oid;
+
+const COMMIT: char = '●';
+const VERTICAL: char = '│';
+const HORIZONTAL: char = '─';
+const CROSSING: char = '┼';
+
+/// Lays out the commit graph for `commits`, which must be ordered children
+/// before parents (as `GitRepo::get_log` returns them). Returns one row of
+/// box-drawing characters per commit, similar to `git log --graph` but
+/// without the extra connector lines.
+pub fn render_graph(commits: &[CommitInfo]) -> Vec<String> {
+    // Each lane holds the commit it is waiting for further down the log.
+    let mut lanes: Vec<Option<Oid>> = Vec::new();
+    let mut rows = Vec::with_capacity(commits.len());
+
+    for commit in commits {
+        let column = match lanes.iter().position(|lane| *lane == Some(commit.oid)) {
+            Some(column) => column,
+            None => free_lane(&mut lanes),
+        };
+        // Other lanes waiting for this commit end here: their branches merge
+        // back into it.
+        let closing: Vec<usize> = lanes
+            .iter()
+            .enumerate()
+            .filter(|&(i, lane)| i != column && *lane == Some(commit.oid))
+            .map(|(i, _)| i)
+            .collect();
+        for &i in &closing {
+            lanes[i] = None;
+        }
+
+        lanes[column] = commit.parents.first().copied();
+        // Additional parents of a merge commit open new lanes, or join the
+        // lane that already waits for them.
+        let mut opening = Vec::new();
+        let mut joining = Vec::new();
+        for parent in commit.parents.iter().skip(1) {
+            match lanes.iter().position(|lane| *lane == Some(*parent)) {
+                Some(lane) => joining.push(lane),
+                None => {
+                    let lane = free_lane(&mut lanes);
+                    lanes[lane] = Some(*parent);
+                    opening.push(lane);
+                }
+            }
+        }
+
+        rows.push(draw_row(&lanes, column, &closing, &opening, &joining));
+        while lanes.last() == Some(&None) {
+            lanes.pop();
+        }
+    }
+    rows
+}
+
+/// Returns the index of the first unused lane, adding one if all are taken.
+fn free_lane(lanes: &mut Vec<Option<Oid>>) -> usize {
+    match lanes.iter().position(Option::is_none) {
+        Some(i) => i,
+        None => {
+            lanes.push(None);
+            lanes.len() - 1
+        }
+    }
+}
+
+fn draw_row(
+    lanes: &[Option<Oid>],
+    column: usize,
+    closing: &[usize],
+    opening: &[usize],
+    joining: &[usize],
+) -> String {
+    let width = lanes
+        .len()
+        .max(closing.iter().chain(opening).map(|i| i + 1).max().unwrap_or(0));
+    // Two cells per lane: the lane itself and the gap to its right.
+    let mut cells: Vec<char> = (0..width)
+        .flat_map(|i| {
+            let lane = if lanes.get(i).copied().flatten().is_some() { VERTICAL } else { ' ' };
+            [lane, ' ']
+        })
+        .collect();
+
+    let mut connect = |target: usize, left: char, right: char| {
+        let (from, to) = (column.min(target), column.max(target));
+        for cell in &mut cells[from * 2 + 1..to * 2] {
+            *cell = match *cell {
+                VERTICAL => CROSSING,
+                '╰' | '╯' => '┴',
+                '╭' | '╮' => '┬',
+                '├' | '┤' => CROSSING,
+                _ => HORIZONTAL,
+            };
+        }
+        cells[target * 2] = if target > column { right } else { left };
+    };
+    for &i in closing {
+        connect(i, '╰', '╯');
+    }
+    for &i in opening {
+        connect(i, '╭', '╮');
+    }
+    for &i in joining {
+        connect(i, '├', '┤');
+    }
+    cells[column * 2] = COMMIT;
+
+    cells.into_iter().collect::<String>().trim_end().to_string()
+}
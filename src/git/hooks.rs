@@ -0,0 +1,140 @@
+//! src/git/hooks.rs
+
+use crate::{
+    dotfiles::expand_home,
+    error::{AppError, AppResult},
+};
+use std::{
+    ffi::OsStr,
+    fs,
+    io::{BufRead, BufReader, Read},
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    sync::mpsc,
+    thread,
+};
+
+/// The hooks `git commit` runs before it creates the commit, in order.
+pub const COMMIT_HOOKS: [&str; 2] = ["pre-commit", "commit-msg"];
+
+/// The directory git looks for hooks in: `core.hooksPath`, relative to the
+/// work tree when not absolute, or the `hooks` directory of the common git
+/// directory.
+pub fn hooks_dir(config: &git2::Config, common_dir: &Path, work_tree: &Path) -> PathBuf {
+    match config.get_string("core.hooksPath") {
+        Ok(path) if path.starts_with('~') => expand_home(&path),
+        Ok(path) => work_tree.join(path),
+        Err(_) => common_dir.join("hooks"),
+    }
+}
+
+/// `dir/name` when it is an executable file; git skips the others.
+pub fn find_hook(dir: &Path, name: &str) -> Option<PathBuf> {
+    let path = dir.join(name);
+    let metadata = fs::metadata(&path).ok()?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        if metadata.permissions().mode() & 0o111 == 0 {
+            return None;
+        }
+    }
+    metadata.is_file().then_some(path)
+}
+
+/// What a hook needs to find the repository, set like `git commit` does so
+/// that hooks also work for bare dotfiles repositories.
+#[derive(Debug, Clone)]
+pub struct HookEnv {
+    pub git_dir: PathBuf,
+    pub work_tree: PathBuf,
+}
+
+/// Runs `hook` with `args` in the work tree, passing each line it prints on
+/// stdout or stderr to `on_output` as it arrives. Fails when the hook exits
+/// with a non-zero status.
+pub fn run_hook<F>(hook: &Path, args: &[&OsStr], env: &HookEnv, mut on_output: F) -> AppResult<()>
+where
+    F: FnMut(String),
+{
+    let name = hook.file_name().unwrap_or_default().to_string_lossy().into_owned();
+    let mut child = hook_command(hook)
+        .args(args)
+        .current_dir(&env.work_tree)
+        .env("GIT_DIR", &env.git_dir)
+        .env("GIT_WORK_TREE", &env.work_tree)
+        .env("GIT_INDEX_FILE", env.git_dir.join("index"))
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| AppError::Hook(format!("could not run {}: {}", name, e)))?;
+
+    // Both pipes are read at once so that a hook writing a lot to one of
+    // them cannot block on the other.
+    let (sender, lines) = mpsc::channel();
+    let pipes: [Option<Box<dyn Read + Send>>; 2] = [
+        child.stdout.take().map(|pipe| Box::new(pipe) as Box<dyn Read + Send>),
+        child.stderr.take().map(|pipe| Box::new(pipe) as Box<dyn Read + Send>),
+    ];
+    for pipe in pipes.into_iter().flatten() {
+        let sender = sender.clone();
+        thread::spawn(move || {
+            for line in BufReader::new(pipe).lines().map_while(Result::ok) {
+                if sender.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+    }
+    drop(sender);
+    for line in lines {
+        on_output(line);
+    }
+
+    let status = child.wait()?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(AppError::Hook(format!("{} hook failed ({})", name, status)))
+    }
+}
+
+/// Hooks are usually shell scripts, which Windows cannot start on its own;
+/// there they are run through the `sh` that comes with Git for Windows,
+/// like git does.
+fn hook_command(hook: &Path) -> Command {
+    if cfg!(windows) {
+        let mut command = Command::new("sh");
+        command.arg(hook);
+        command
+    } else {
+        Command::new(hook)
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+
+    #[test]
+    fn hooks_stream_their_output_and_report_failures() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let hook = dir.path().join("pre-commit");
+        fs::write(&hook, "#!/bin/sh\necho checking\necho \"bad: $1\" >&2\nexit 3\n").unwrap();
+        assert_eq!(find_hook(dir.path(), "pre-commit"), None);
+        fs::set_permissions(&hook, fs::Permissions::from_mode(0o755)).unwrap();
+        assert_eq!(find_hook(dir.path(), "pre-commit"), Some(hook.clone()));
+
+        let env = HookEnv {
+            git_dir: dir.path().to_path_buf(),
+            work_tree: dir.path().to_path_buf(),
+        };
+        let mut output = Vec::new();
+        let result = run_hook(&hook, &[OsStr::new("x")], &env, |line| output.push(line));
+        output.sort();
+        assert_eq!(output, ["bad: x", "checking"]);
+        assert!(matches!(result, Err(AppError::Hook(_))), "{:?}", result);
+    }
+}
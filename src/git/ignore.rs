@@ -0,0 +1,246 @@
+//! src/git/ignore.rs
+
+use crate::error::AppResult;
+use std::{
+    fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+
+/// A file with ignore patterns, e.g. a `.gitignore` or `info/exclude`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IgnoreFile {
+    pub path: PathBuf,
+    /// Shown in place of the full path, e.g. `.config/.gitignore`.
+    pub label: String,
+    /// The work tree directory the patterns are relative to, with a
+    /// trailing `/`; empty for the root and for files outside the work tree.
+    pub base: String,
+    /// Whether the file belongs to the work tree and can be staged.
+    pub in_work_tree: bool,
+}
+
+/// One pattern of an ignore file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IgnoreRule {
+    pub file: IgnoreFile,
+    /// 1-based line of the pattern in `file`.
+    pub line: usize,
+    pub pattern: String,
+    /// Whether the pattern matches regardless of case, as with
+    /// `core.ignorecase`.
+    pub ignore_case: bool,
+}
+
+impl IgnoreRule {
+    /// Whether the rule re-includes what it matches (`!pattern`).
+    pub fn is_negated(&self) -> bool {
+        self.pattern.starts_with('!')
+    }
+
+    /// Whether the rule matches `path`, given relative to the work tree with
+    /// `/` as separator. Follows gitignore(5): patterns without a slash
+    /// match the file name at any depth, others are anchored to the
+    /// directory of the ignore file, and a trailing `/` only matches
+    /// directories.
+    pub fn matches(&self, path: &str, is_dir: bool) -> bool {
+        let fold = |text: &str| match self.ignore_case {
+            true => text.to_lowercase(),
+            false => text.to_string(),
+        };
+        let (path, base, pattern) = (fold(path), fold(&self.file.base), fold(&self.pattern));
+        let Some(path) = path.strip_prefix(base.as_str()) else {
+            return false;
+        };
+        let mut pattern = pattern.strip_prefix('!').unwrap_or(&pattern);
+        if let Some(dir_pattern) = pattern.strip_suffix('/') {
+            if !is_dir {
+                return false;
+            }
+            pattern = dir_pattern;
+        }
+        let pattern: Vec<char> = pattern.chars().collect();
+        let (pattern, text) = if pattern.contains(&'/') {
+            let anchored = pattern.strip_prefix(&['/']).unwrap_or(&pattern);
+            (anchored.to_vec(), path)
+        } else {
+            (pattern, path.rsplit('/').next().unwrap_or(path))
+        };
+        let text: Vec<char> = text.chars().collect();
+        wildmatch(&pattern, &text, true)
+    }
+}
+
+/// Reads the patterns of `files`, which must be given lowest precedence
+/// first. Blank lines and comments are skipped.
+pub fn read_rules(files: &[IgnoreFile], ignore_case: bool) -> AppResult<Vec<IgnoreRule>> {
+    let mut rules = Vec::new();
+    for file in files {
+        let contents = match fs::read_to_string(&file.path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == ErrorKind::NotFound => continue,
+            Err(e) => return Err(e.into()),
+        };
+        for (index, line) in contents.lines().enumerate() {
+            let pattern = line.trim_end();
+            if pattern.is_empty() || pattern.starts_with('#') {
+                continue;
+            }
+            rules.push(IgnoreRule {
+                file: file.clone(),
+                line: index + 1,
+                pattern: pattern.to_string(),
+                ignore_case,
+            });
+        }
+    }
+    Ok(rules)
+}
+
+/// The rule that decides whether `path` is ignored, like
+/// `git check-ignore -v`: the last matching rule, unless a parent directory
+/// is already excluded, since git does not look inside those.
+pub fn deciding_rule<'a>(rules: &'a [IgnoreRule], path: &str, is_dir: bool) -> Option<&'a IgnoreRule> {
+    let path = path.trim_end_matches('/');
+    let last_match = |path: &str, is_dir: bool| rules.iter().rev().find(|rule| rule.matches(path, is_dir));
+    for (i, _) in path.match_indices('/') {
+        if let Some(rule) = last_match(&path[..i], true).filter(|rule| !rule.is_negated()) {
+            return Some(rule);
+        }
+    }
+    last_match(path, is_dir)
+}
+
+/// Replaces line `line` (1-based) of `path` with `new`, or removes it when
+/// `new` is `None`.
+pub fn replace_line(path: &Path, line: usize, new: Option<&str>) -> AppResult<()> {
+    let contents = fs::read_to_string(path)?;
+    let mut lines: Vec<&str> = contents.lines().collect();
+    if line == 0 || line > lines.len() {
+        return Ok(());
+    }
+    match new {
+        Some(new) => lines[line - 1] = new,
+        None => {
+            lines.remove(line - 1);
+        }
+    }
+    let mut updated = lines.join("\n");
+    if !lines.is_empty() {
+        updated.push('\n');
+    }
+    fs::write(path, updated)?;
+    Ok(())
+}
+
+/// Glob matching as git does it for paths: `*` and `?` stop at `/`, and
+/// `[...]` matches a character class. `**` crosses directories only as a
+/// whole path segment: `**/` matches any number of leading directories,
+/// none included, and a trailing `/**` everything inside; elsewhere it is
+/// a plain `*`. `segment_start` tells whether `pattern` starts a segment.
+fn wildmatch(pattern: &[char], text: &[char], segment_start: bool) -> bool {
+    match pattern {
+        [] => text.is_empty(),
+        ['*', '*'] if segment_start => true,
+        ['*', '*', '/', rest @ ..] if segment_start => (0..=text.len())
+            .filter(|&i| i == 0 || text[i - 1] == '/')
+            .any(|i| wildmatch(rest, &text[i..], true)),
+        ['*', rest @ ..] => (0..=text.len())
+            .take_while(|&i| i == 0 || text[i - 1] != '/')
+            .any(|i| wildmatch(rest, &text[i..], false)),
+        ['?', rest @ ..] => matches!(text, [c, ..] if *c != '/') && wildmatch(rest, &text[1..], false),
+        ['[', rest @ ..] => match match_class(rest, text.first().copied()) {
+            Some((true, after)) => wildmatch(after, &text[1..], false),
+            Some((false, _)) => false,
+            // No closing bracket: the `[` is literal.
+            None => text.first() == Some(&'[') && wildmatch(rest, &text[1..], false),
+        },
+        ['\\', c, rest @ ..] => text.first() == Some(c) && wildmatch(rest, &text[1..], false),
+        [c, rest @ ..] => text.first() == Some(c) && wildmatch(rest, &text[1..], *c == '/'),
+    }
+}
+
+/// Matches `c` against the class starting after `[`. Returns whether it
+/// matched and the pattern after the closing `]`, or `None` when the class
+/// is not closed.
+fn match_class(class: &[char], c: Option<char>) -> Option<(bool, &[char])> {
+    let (negated, mut rest) = match class {
+        ['!' | '^', rest @ ..] => (true, rest),
+        _ => (false, class),
+    };
+    let mut matched = false;
+    let mut first = true;
+    loop {
+        match rest {
+            [']', after @ ..] if !first => {
+                let matched = c.is_some_and(|c| c != '/') && matched != negated;
+                return Some((matched, after));
+            }
+            [low, '-', high, after @ ..] if *high != ']' => {
+                matched |= c.is_some_and(|c| (*low..=*high).contains(&c));
+                rest = after;
+            }
+            [ch, after @ ..] => {
+                matched |= c == Some(*ch);
+                rest = after;
+            }
+            [] => return None,
+        }
+        first = false;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(base: &str, pattern: &str) -> IgnoreRule {
+        IgnoreRule {
+            file: IgnoreFile {
+                path: PathBuf::from(format!("{}.gitignore", base)),
+                label: format!("{}.gitignore", base),
+                base: base.to_string(),
+                in_work_tree: true,
+            },
+            line: 1,
+            pattern: pattern.to_string(),
+            ignore_case: false,
+        }
+    }
+
+    #[test]
+    fn patterns_follow_gitignore_rules() {
+        assert!(rule("", "*.log").matches("logs/app.log", false));
+        assert!(!rule("", "/*.log").matches("logs/app.log", false));
+        assert!(rule("", "/*.log").matches("app.log", false));
+        assert!(rule("", "cache/").matches(".config/cache", true));
+        assert!(!rule("", "cache/").matches(".config/cache", false));
+        assert!(rule("", ".config/**/history").matches(".config/history", false));
+        assert!(rule("", ".config/**/history").matches(".config/a/b/history", false));
+        assert!(rule("", "**/history").matches(".config/a/history", false));
+        assert!(rule("", "/.config/**").matches(".config/a/history", false));
+        assert!(!rule("", "/.config/**").matches(".config", true));
+        // `**` inside a segment is a plain `*`.
+        assert!(rule("", "/.config/nv**").matches(".config/nvim", true));
+        assert!(!rule("", "/.config/nv**").matches(".config/nvim/init.lua", false));
+        assert!(!rule("", "a**b").matches("a/x/b", false));
+        assert!(rule("", "file[0-9].txt").matches("file7.txt", false));
+        assert!(!rule("", "file[!0-9].txt").matches("file7.txt", false));
+        assert!(rule(".config/", "secrets").matches(".config/nvim/secrets", false));
+        assert!(!rule(".config/", "secrets").matches("secrets", false));
+
+        let mut folded = rule(".Config/", "*.LOG");
+        assert!(!folded.matches(".config/app.log", false));
+        folded.ignore_case = true;
+        assert!(folded.matches(".config/App.Log", false));
+    }
+
+    #[test]
+    fn the_last_match_decides_unless_a_parent_is_excluded() {
+        let rules = [rule("", "*.toml"), rule("", "!config.toml"), rule("", "/private/")];
+        assert_eq!(deciding_rule(&rules, "a.toml", false), Some(&rules[0]));
+        assert_eq!(deciding_rule(&rules, "config.toml", false), Some(&rules[1]));
+        assert_eq!(deciding_rule(&rules, "private/config.toml", false), Some(&rules[2]));
+        assert_eq!(deciding_rule(&rules, "readme.md", false), None);
+    }
+}
@@ -0,0 +1,109 @@
+//! src/git/lint.rs
+
+use unicode_width::UnicodeWidthStr;
+
+/// Default width of body lines, used when `body_width` is not configured.
+pub const BODY_WIDTH: usize = 72;
+
+/// Checks a commit message for overlong lines. `prefix` is the width of
+/// text that will be put in front of the subject. Comment lines are
+/// ignored, since they are stripped before committing.
+pub fn lint_message(message: &str, prefix: usize, subject_limit: usize, body_width: usize) -> Vec<String> {
+    let mut warnings = Vec::new();
+    let mut lines = message
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.starts_with('#'));
+    if let Some((_, subject)) = lines.next() {
+        let width = prefix + subject.trim_end().width();
+        if width > subject_limit {
+            warnings.push(format!(
+                "Subject is {} characters long (limit {})",
+                width, subject_limit
+            ));
+        }
+    }
+    for (number, line) in lines {
+        let width = line.trim_end().width();
+        if width > body_width {
+            warnings.push(format!(
+                "Line {} is {} characters long (limit {})",
+                number + 1,
+                width,
+                body_width
+            ));
+        }
+    }
+    warnings
+}
+
+#[cfg(feature = "spellcheck")]
+pub use self::spelling::{Dictionary, DEFAULT_DICTIONARY};
+
+#[cfg(feature = "spellcheck")]
+mod spelling {
+    use crate::error::AppResult;
+    use std::{collections::HashSet, fs, path::Path};
+
+    /// The word list most Unix systems install.
+    pub const DEFAULT_DICTIONARY: &str = "/usr/share/dict/words";
+
+    /// A word list for spell-checking commit messages.
+    #[derive(Debug, Clone, Default, PartialEq, Eq)]
+    pub struct Dictionary {
+        words: HashSet<String>,
+    }
+
+    impl Dictionary {
+        /// Reads a word list with one word per line.
+        pub fn load(path: &Path) -> AppResult<Self> {
+            let contents = fs::read_to_string(path)?;
+            Ok(Self {
+                words: contents
+                    .lines()
+                    .map(|word| word.trim().to_lowercase())
+                    .filter(|word| !word.is_empty())
+                    .collect(),
+            })
+        }
+
+        /// Returns the unknown words of `text`, each once, in order of
+        /// appearance. Words that look like identifiers (mixed case, digits,
+        /// underscores) and anything in backticks are skipped.
+        pub fn misspelled(&self, text: &str) -> Vec<String> {
+            let mut unknown: Vec<String> = Vec::new();
+            for (i, part) in text.split('`').enumerate() {
+                // Odd parts are inside backticks.
+                if i % 2 == 1 {
+                    continue;
+                }
+                for token in part.split(|c: char| c.is_whitespace() || "()[]{}<>,.;:!?\"/=".contains(c)) {
+                    let word = token.trim_matches(|c: char| c == '\'' || c == '-');
+                    if !is_word_to_check(word) || self.knows(word) {
+                        continue;
+                    }
+                    if !unknown.iter().any(|w| w == word) {
+                        unknown.push(word.to_string());
+                    }
+                }
+            }
+            unknown
+        }
+
+        fn knows(&self, word: &str) -> bool {
+            let word = word.to_lowercase();
+            let base = word.strip_suffix("'s").unwrap_or(&word);
+            self.words.contains(base)
+                || base
+                    .split('-')
+                    .all(|part| part.is_empty() || self.words.contains(part))
+        }
+    }
+
+    /// Only plain words are checked; `README`, `GitHub` or `v2` are not.
+    fn is_word_to_check(word: &str) -> bool {
+        word.chars().count() > 1
+            && word.chars().all(|c| c.is_alphabetic() || c == '\'' || c == '-')
+            && !word.chars().skip(1).any(char::is_uppercase)
+    }
+}
@@ -0,0 +1,155 @@
+//! src/git/signing.rs
+
+use crate::{
+    dotfiles::expand_home,
+    error::{AppError, AppResult},
+};
+use std::{
+    io::Write,
+    path::PathBuf,
+    process::{Command, Stdio},
+};
+
+/// Produces the signature stored in a commit's `gpgsig` header.
+pub trait Signer {
+    fn sign(&self, payload: &str) -> AppResult<String>;
+}
+
+/// Signs with an OpenPGP key through `gpg` (or `gpg.program`).
+pub struct GpgSigner {
+    program: String,
+    key: String,
+}
+
+impl Signer for GpgSigner {
+    fn sign(&self, payload: &str) -> AppResult<String> {
+        run_signer(
+            Command::new(&self.program).args(["--status-fd=2", "-bsau", &self.key]),
+            payload,
+        )
+    }
+}
+
+/// Signs with an SSH key through `ssh-keygen -Y sign` (or `gpg.ssh.program`).
+pub struct SshSigner {
+    program: String,
+    key_file: PathBuf,
+}
+
+impl Signer for SshSigner {
+    fn sign(&self, payload: &str) -> AppResult<String> {
+        run_signer(
+            Command::new(&self.program)
+                .args(["-Y", "sign", "-n", "git", "-f"])
+                .arg(&self.key_file),
+            payload,
+        )
+    }
+}
+
+/// Builds the signer git would use for this repository, based on
+/// `gpg.format`, `user.signingkey`, `gpg.program` and `gpg.ssh.program`.
+/// Without a signing key, GPG falls back to the committer identity.
+pub fn signer_from_config(
+    config: &git2::Config,
+    committer: &git2::Signature<'_>,
+) -> AppResult<Box<dyn Signer>> {
+    let key = config.get_string("user.signingkey").ok();
+    match config.get_string("gpg.format").as_deref() {
+        Err(_) | Ok("openpgp") => Ok(Box::new(GpgSigner {
+            program: config
+                .get_string("gpg.program")
+                .unwrap_or_else(|_| "gpg".to_string()),
+            key: key.unwrap_or_else(|| committer.to_string()),
+        })),
+        Ok("ssh") => {
+            let key = key.ok_or_else(|| {
+                AppError::Signing("user.signingkey must name an SSH key file".to_string())
+            })?;
+            if key.starts_with("key::") {
+                return Err(AppError::Signing(
+                    "literal SSH keys in user.signingkey are not supported; use a key file".to_string(),
+                ));
+            }
+            let key_file = expand_home(&key);
+            Ok(Box::new(SshSigner {
+                program: config
+                    .get_string("gpg.ssh.program")
+                    .unwrap_or_else(|_| "ssh-keygen".to_string()),
+                key_file,
+            }))
+        }
+        Ok(format) => Err(AppError::Signing(format!(
+            "unsupported gpg.format '{}'",
+            format
+        ))),
+    }
+}
+
+/// Feeds `payload` to the signing command and returns what it prints.
+fn run_signer(command: &mut Command, payload: &str) -> AppResult<String> {
+    let program = command.get_program().to_string_lossy().into_owned();
+    let mut child = command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| AppError::Signing(format!("could not run {}: {}", program, e)))?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(payload.as_bytes())?;
+    }
+    let output = child.wait_with_output()?;
+    let signature = String::from_utf8_lossy(&output.stdout).into_owned();
+    if !output.status.success() || signature.is_empty() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let reasons = failure_reasons(&stderr);
+        return Err(AppError::Signing(format!(
+            "{} failed: {}",
+            program,
+            if reasons.is_empty() { "no signature produced".to_string() } else { reasons.join("; ") }
+        )));
+    }
+    Ok(signature)
+}
+
+/// The `[GNUPG:]` status lines that say why gpg did not sign.
+const FAILURE_STATUSES: [&str; 6] = ["FAILURE", "INV_SGNR", "NO_SGNR", "KEYEXPIRED", "KEYREVOKED", "ERROR"];
+
+/// The lines of a signer's stderr that explain the failure: the messages
+/// of gpg and ssh-keygen, and of the `--status-fd` lines only those in
+/// `FAILURE_STATUSES`, leaving out the progress ones.
+fn failure_reasons(stderr: &str) -> Vec<&str> {
+    stderr
+        .lines()
+        .map(str::trim)
+        .filter(|line| match line.strip_prefix("[GNUPG:] ") {
+            Some(status) => FAILURE_STATUSES.iter().any(|keyword| status.split(' ').next() == Some(*keyword)),
+            None => !line.is_empty(),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_the_status_lines_that_explain_a_failure_are_kept() {
+        let stderr = "[GNUPG:] KEY_CONSIDERED 1234 2\n\
+            gpg: skipped \"dev@example.com\": No secret key\n\
+            [GNUPG:] INV_SGNR 9 dev@example.com\n\
+            [GNUPG:] FAILURE sign 17\n\
+            gpg: signing failed: No secret key\n";
+        assert_eq!(
+            failure_reasons(stderr),
+            [
+                "gpg: skipped \"dev@example.com\": No secret key",
+                "[GNUPG:] INV_SGNR 9 dev@example.com",
+                "[GNUPG:] FAILURE sign 17",
+                "gpg: signing failed: No secret key",
+            ]
+        );
+        let stderr = "Couldn't load public key /home/me/.ssh/id_ed25519.pub: No such file or directory\n";
+        assert_eq!(failure_reasons(stderr), [stderr.trim()]);
+    }
+}
@@ -0,0 +1,125 @@
+//! src/git/subject.rs
+
+use super::StatusItem;
+use std::collections::BTreeSet;
+use std::fmt;
+
+/// At most this many topics are named in a subject; the rest are counted.
+const MAX_TOPICS: usize = 3;
+
+/// File name endings that do not tell the program apart, e.g. the `rc` of
+/// `.zshrc` or the `config` of `.gitconfig`.
+const NAME_SUFFIXES: [&str; 3] = ["rc", "config", "_profile"];
+
+/// A commit subject guessed from the staged files, like
+/// `nvim, zsh: update configs`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Subject {
+    /// The programs the files configure, e.g. `nvim, zsh`.
+    pub scope: String,
+    /// What happened to them, e.g. `update configs`.
+    pub summary: String,
+}
+
+impl fmt::Display for Subject {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.scope, self.summary)
+    }
+}
+
+/// Guesses a subject for committing `staged`, or `None` when nothing is
+/// staged.
+pub fn suggest_subject(staged: &[StatusItem]) -> Option<Subject> {
+    if staged.is_empty() {
+        return None;
+    }
+    let topics: BTreeSet<String> = staged.iter().map(|item| topic(&item.path)).collect();
+    let mut named: Vec<&str> = topics.iter().map(String::as_str).take(MAX_TOPICS).collect();
+    let scope = if topics.len() > MAX_TOPICS {
+        named.truncate(MAX_TOPICS - 1);
+        format!("{} and {} more", named.join(", "), topics.len() - named.len())
+    } else {
+        named.join(", ")
+    };
+
+    let verb = if staged.iter().all(|item| item.status.is_index_new()) {
+        "add"
+    } else if staged.iter().all(|item| item.status.is_index_deleted()) {
+        "remove"
+    } else if staged.iter().all(|item| item.status.is_index_renamed()) {
+        "rename"
+    } else {
+        "update"
+    };
+    let noun = if staged.len() == 1 { "config" } else { "configs" };
+    Some(Subject {
+        scope,
+        summary: format!("{} {}", verb, noun),
+    })
+}
+
+/// The program a dotfile belongs to: the directory under `.config`, the
+/// top directory, or the file name without its leading dot, extension and
+/// suffixes like `rc`.
+fn topic(path: &str) -> String {
+    let path = path.trim_end_matches('/');
+    let path = path.strip_prefix(".config/").unwrap_or(path);
+    match path.split_once('/') {
+        Some((dir, _)) => dir.trim_start_matches('.').to_string(),
+        None => {
+            let name = path.trim_start_matches('.');
+            let stem = name.split('.').next().unwrap_or(name);
+            NAME_SUFFIXES
+                .iter()
+                .find_map(|suffix| stem.strip_suffix(suffix).filter(|rest| rest.len() > 1))
+                .unwrap_or(stem)
+                .to_string()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use git2::Status;
+
+    fn staged(path: &str, status: Status) -> StatusItem {
+        StatusItem {
+            path: path.to_string(),
+            status,
+            is_staged: true,
+        }
+    }
+
+    #[test]
+    fn topics_come_from_the_program_a_file_configures() {
+        assert_eq!(topic(".config/nvim/init.lua"), "nvim");
+        assert_eq!(topic(".config/starship.toml"), "starship");
+        assert_eq!(topic(".ssh/config"), "ssh");
+        assert_eq!(topic(".zshrc"), "zsh");
+        assert_eq!(topic(".gitconfig"), "git");
+        assert_eq!(topic(".bash_profile"), "bash");
+        assert_eq!(topic(".tmux.conf"), "tmux");
+        assert_eq!(topic("install.sh"), "install");
+    }
+
+    #[test]
+    fn subjects_name_the_topics_and_what_happened() {
+        let modified = [
+            staged(".config/nvim/init.lua", Status::INDEX_MODIFIED),
+            staged(".config/nvim/lua/keys.lua", Status::INDEX_MODIFIED),
+            staged(".zshrc", Status::INDEX_MODIFIED),
+        ];
+        assert_eq!(suggest_subject(&modified).unwrap().to_string(), "nvim, zsh: update configs");
+
+        let added = [staged(".tmux.conf", Status::INDEX_NEW)];
+        assert_eq!(suggest_subject(&added).unwrap().to_string(), "tmux: add config");
+
+        let many: Vec<StatusItem> = [".a", ".b", ".c", ".d"]
+            .iter()
+            .map(|path| staged(path, Status::INDEX_DELETED))
+            .collect();
+        assert_eq!(suggest_subject(&many).unwrap().to_string(), "a, b and 2 more: remove configs");
+        assert_eq!(suggest_subject(&[]), None);
+    }
+}
@@ -0,0 +1,109 @@
+//! src/git/tools.rs
+
+use std::path::{Path, PathBuf};
+
+/// The files an external diff or merge tool is started with, named after
+/// the variables `git difftool` and `git mergetool` set.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ToolFiles {
+    pub local: PathBuf,
+    pub remote: PathBuf,
+    /// The common ancestor of a conflict; empty when the sides have none.
+    pub base: Option<PathBuf>,
+    /// The work tree file a merge tool writes the resolution to.
+    pub merged: Option<PathBuf>,
+}
+
+/// Builds the command line for `tool`, e.g. `nvim -d` or
+/// `meld $LOCAL $MERGED $REMOTE`. `$LOCAL`, `$REMOTE`, `$BASE` and `$MERGED`
+/// are replaced by the paths in `files`, and an argument naming a file that
+/// `files` lacks is dropped. Without any of them the files are appended:
+/// `$LOCAL $REMOTE` for a diff, `$LOCAL $MERGED $REMOTE` for a merge.
+pub fn tool_command(tool: &str, files: &ToolFiles) -> Vec<String> {
+    let variables = [
+        ("$LOCAL", Some(&files.local)),
+        ("$REMOTE", Some(&files.remote)),
+        ("$BASE", files.base.as_ref()),
+        ("$MERGED", files.merged.as_ref()),
+    ];
+    let mut command = Vec::new();
+    let mut substituted = false;
+    'words: for word in tool.split_whitespace() {
+        let mut word = word.to_string();
+        for (name, path) in variables {
+            if word.contains(name) {
+                substituted = true;
+                match path {
+                    Some(path) => word = word.replace(name, &path.to_string_lossy()),
+                    None => continue 'words,
+                }
+            }
+        }
+        command.push(word);
+    }
+    if !substituted {
+        let files = [Some(&files.local), files.merged.as_ref(), Some(&files.remote)];
+        command.extend(files.into_iter().flatten().map(|path| path.to_string_lossy().into_owned()));
+    }
+    command
+}
+
+/// Where the copy of `path` labelled `label` goes in `dir`: `name_LABEL.ext`
+/// like `git mergetool` names them, so that tools still recognise the file
+/// type.
+pub fn tool_file_name(dir: &Path, path: &str, label: &str) -> PathBuf {
+    let path = Path::new(path);
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let name = match path.extension() {
+        Some(ext) => format!("{}_{}.{}", stem, label, ext.to_string_lossy()),
+        None => format!("{}_{}", stem, label),
+    };
+    dir.join(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tool_commands_substitute_or_append_the_files() {
+        let dir = Path::new("/tmp/tool");
+        let tool_file = |name: &str| dir.join(name).to_string_lossy().into_owned();
+        let diff = ToolFiles {
+            local: tool_file_name(dir, "src/main.rs", "LOCAL"),
+            remote: PathBuf::from("/repo/src/main.rs"),
+            base: None,
+            merged: None,
+        };
+        assert_eq!(
+            tool_command("nvim -d", &diff),
+            ["nvim", "-d", &tool_file("main_LOCAL.rs"), "/repo/src/main.rs"]
+        );
+        assert_eq!(
+            tool_command("meld --label=x $REMOTE $BASE $LOCAL", &diff),
+            ["meld", "--label=x", "/repo/src/main.rs", &tool_file("main_LOCAL.rs")]
+        );
+
+        let merge = ToolFiles {
+            local: tool_file_name(dir, ".bashrc", "LOCAL"),
+            remote: tool_file_name(dir, ".bashrc", "REMOTE"),
+            base: Some(tool_file_name(dir, ".bashrc", "BASE")),
+            merged: Some(PathBuf::from("/home/me/.bashrc")),
+        };
+        assert_eq!(
+            tool_command("vimdiff", &merge),
+            ["vimdiff", &tool_file(".bashrc_LOCAL"), "/home/me/.bashrc", &tool_file(".bashrc_REMOTE")]
+        );
+        assert_eq!(
+            tool_command("kdiff3 $BASE $LOCAL $REMOTE -o $MERGED", &merge),
+            [
+                "kdiff3",
+                &tool_file(".bashrc_BASE"),
+                &tool_file(".bashrc_LOCAL"),
+                &tool_file(".bashrc_REMOTE"),
+                "-o",
+                "/home/me/.bashrc"
+            ]
+        );
+    }
+}
@@ -0,0 +1,285 @@
+//! src/git/worker.rs
+
+use super::{
+    BinaryChange, BranchStatus, Churn, CommitInfo, GitRepo, Hunk, ReflogEntry, RepoLocation, StashInfo, StatusItem,
+    UntrackedFiles, WorkTreeSummary,
+};
+use crate::{
+    dotfiles::{LinkEntry, Manifest, Profile},
+    error::AppResult,
+    event::AppEvent,
+};
+use git2::Oid;
+use log::{debug, error, warn};
+use std::{sync::mpsc as std_mpsc, thread, time::SystemTime};
+use tokio::sync::mpsc;
+
+/// Reads the repository on a dedicated thread so that slow status scans
+/// (e.g. a bare dotfiles repo with `$HOME` as the work tree) and large diffs
+/// do not freeze the UI. Results come back as `AppEvent`s tagged with the
+/// generation of the request; the app ignores answers to outdated requests.
+pub struct GitWorker {
+    requests: std_mpsc::Sender<GitRequest>,
+}
+
+#[derive(Debug)]
+pub enum GitRequest {
+    /// Status, its totals, branch status, the first `log_count` commits (or
+    /// the whole history of `log_path`), the stashes, the reflog, the
+    /// tracked files and the links.
+    Refresh {
+        generation: u64,
+        untracked: UntrackedFiles,
+        log_count: usize,
+        log_path: Option<String>,
+    },
+    /// The diff of a file shown in the status view. Files larger than
+    /// `size_limit` bytes are only summarised.
+    Diff {
+        generation: u64,
+        item: StatusItem,
+        size_limit: u64,
+    },
+    /// The whole history, for jumping to the oldest commit or sorting it.
+    WholeLog { generation: u64 },
+    /// The positions of the commits matching `query`, see
+    /// `GitRepo::search_log`.
+    SearchLog { generation: u64, query: String },
+    /// The churn of status items the app has none for yet. Files larger
+    /// than `size_limit` bytes only get their size change.
+    Churn {
+        items: Vec<(ChurnKey, StatusItem)>,
+        size_limit: u64,
+    },
+}
+
+/// What a churn was computed from: the app asks again once the index or
+/// the work tree file changed.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ChurnKey {
+    pub path: String,
+    pub staged: bool,
+    pub index_mtime: Option<SystemTime>,
+    /// When the work tree file was last modified; unset for staged items.
+    pub file_mtime: Option<SystemTime>,
+}
+
+/// The result of `GitRequest::Refresh`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RepoSnapshot {
+    pub status_items: Vec<StatusItem>,
+    pub summary: WorkTreeSummary,
+    pub branch_status: BranchStatus,
+    pub log_entries: Vec<CommitInfo>,
+    /// Whether `log_entries` holds the whole history.
+    pub log_exhausted: bool,
+    /// The file whose history `log_entries` is, if any.
+    pub log_path: Option<String>,
+    pub stashes: Vec<StashInfo>,
+    pub reflog: Vec<ReflogEntry>,
+    /// The files of HEAD, for the file tree.
+    pub tracked_files: Vec<String>,
+    /// The links of the manifest, or why it could not be read.
+    pub links: Result<LinksSnapshot, String>,
+}
+
+/// The link manifest resolved against the file system.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LinksSnapshot {
+    pub links: Vec<LinkEntry>,
+    pub profiles: Vec<Profile>,
+    pub active_profile: Option<Profile>,
+}
+
+/// Reads the link manifest of `repo` and the state of every link target.
+pub fn read_links(repo: &GitRepo) -> Result<LinksSnapshot, String> {
+    let manifest = Manifest::load(repo.path()).map_err(|e| e.to_string())?;
+    let selected = repo.selected_profile();
+    let active_profile = manifest.active_profile(selected.as_deref()).cloned();
+    Ok(LinksSnapshot {
+        links: manifest.resolve(repo.path(), active_profile.as_ref()),
+        profiles: manifest.profiles,
+        active_profile,
+    })
+}
+
+/// The result of `GitRequest::Diff`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileDiff {
+    pub item: StatusItem,
+    pub hunks: Vec<Hunk>,
+    /// Set instead of hunks for binary and oversized files.
+    pub binary: Option<BinaryChange>,
+    /// git's summary for other diffs without hunks, such as mode changes.
+    pub text: String,
+}
+
+impl GitWorker {
+    /// Starts a worker that opens its own handle on the repository at
+    /// `location`. The thread ends when the worker is dropped.
+    pub fn spawn(location: RepoLocation, events: mpsc::UnboundedSender<AppEvent>) -> AppResult<Self> {
+        let (requests, receiver) = std_mpsc::channel();
+        thread::Builder::new()
+            .name("git-worker".to_string())
+            .spawn(move || run(location, receiver, events))?;
+        Ok(Self { requests })
+    }
+
+    pub fn send(&self, request: GitRequest) {
+        if self.requests.send(request).is_err() {
+            error!("The git worker has stopped");
+        }
+    }
+}
+
+fn run(
+    location: RepoLocation,
+    receiver: std_mpsc::Receiver<GitRequest>,
+    events: mpsc::UnboundedSender<AppEvent>,
+) {
+    let mut repo = match GitRepo::open(&location) {
+        Ok(repo) => Some(repo),
+        Err(e) => {
+            error!("The git worker could not open {}: {}", location.git_dir.display(), e);
+            None
+        }
+    };
+    // Searching diffs every commit of the history, so the last answer is
+    // kept for as long as HEAD stays where it is.
+    let mut last_search: Option<(String, Option<Oid>, Vec<usize>)> = None;
+    while let Ok(request) = receiver.recv() {
+        // Only the newest refresh, diff and search matter; skip the ones
+        // that were overtaken while the last request was running. Churn
+        // comes last, since nothing waits for it.
+        let mut refresh = None;
+        let mut diff = None;
+        let mut search = None;
+        let mut whole_log = None;
+        let mut churns = Vec::new();
+        for request in std::iter::once(request).chain(receiver.try_iter()) {
+            match request {
+                GitRequest::Refresh { .. } => refresh = Some(request),
+                GitRequest::Diff { .. } => diff = Some(request),
+                GitRequest::SearchLog { .. } => search = Some(request),
+                GitRequest::WholeLog { .. } => whole_log = Some(request),
+                GitRequest::Churn { .. } => churns.push(request),
+            }
+        }
+        for request in [refresh, diff, search, whole_log].into_iter().flatten().chain(churns) {
+            let event = match request {
+                GitRequest::Refresh {
+                    generation,
+                    untracked,
+                    log_count,
+                    log_path,
+                } => {
+                    debug!("Worker: refresh #{}", generation);
+                    let result = with_repo(&mut repo, &location, |repo| {
+                        let (log_entries, log_exhausted) = match &log_path {
+                            Some(path) => (repo.get_file_log(path)?, true),
+                            None => {
+                                let log_entries = repo.get_log(0, log_count)?;
+                                let exhausted = log_entries.len() < log_count;
+                                (log_entries, exhausted)
+                            }
+                        };
+                        Ok(RepoSnapshot {
+                            status_items: repo.get_status(&untracked)?,
+                            summary: repo.work_tree_summary(&untracked)?,
+                            branch_status: repo.branch_status(),
+                            log_entries,
+                            log_exhausted,
+                            log_path,
+                            stashes: repo.get_stashes()?,
+                            reflog: repo.get_reflog()?,
+                            tracked_files: repo.tracked_files()?,
+                            links: read_links(repo),
+                        })
+                    });
+                    AppEvent::RefreshLoaded(generation, result.map(Box::new))
+                }
+                GitRequest::Diff {
+                    generation,
+                    item,
+                    size_limit,
+                } => {
+                    debug!("Worker: diff #{} of {}", generation, item.path);
+                    let result = with_repo(&mut repo, &location, |repo| {
+                        if item.is_untracked_dir() {
+                            let text = "An untracked directory. Enter lists its files, space stages all of them.";
+                            return Ok(FileDiff {
+                                item,
+                                hunks: Vec::new(),
+                                binary: None,
+                                text: text.to_string(),
+                            });
+                        }
+                        let (hunks, binary) = repo.get_diff_hunks(&item, size_limit)?;
+                        let text = if hunks.is_empty() && binary.is_none() {
+                            repo.get_diff_text(&item)?
+                        } else {
+                            String::new()
+                        };
+                        Ok(FileDiff {
+                            item,
+                            hunks,
+                            binary,
+                            text,
+                        })
+                    });
+                    AppEvent::DiffLoaded(generation, result)
+                }
+                GitRequest::WholeLog { generation } => {
+                    debug!("Worker: whole log #{}", generation);
+                    let result = with_repo(&mut repo, &location, |repo| repo.get_log(0, usize::MAX));
+                    AppEvent::WholeLogLoaded(generation, result)
+                }
+                GitRequest::SearchLog { generation, query } => {
+                    debug!("Worker: log search #{} for '{}'", generation, query);
+                    let result = with_repo(&mut repo, &location, |repo| {
+                        let head = repo.head_commit_id().ok();
+                        if let Some((_, _, matches)) = last_search
+                            .as_ref()
+                            .filter(|(last_query, last_head, _)| *last_query == query && *last_head == head)
+                        {
+                            return Ok(matches.clone());
+                        }
+                        let matches = repo.search_log(&query)?;
+                        last_search = Some((query, head, matches.clone()));
+                        Ok(matches)
+                    });
+                    AppEvent::LogSearched(generation, result)
+                }
+                GitRequest::Churn { items, size_limit } => {
+                    debug!("Worker: churn of {} file(s)", items.len());
+                    let mut churns = Vec::with_capacity(items.len());
+                    for (key, item) in items {
+                        // A failure shows no churn rather than leaving the app waiting.
+                        let churn = with_repo(&mut repo, &location, |repo| repo.churn(&item, size_limit))
+                            .unwrap_or_else(|e| {
+                                warn!("Could not count the changes of {}: {}", item.path, e);
+                                Churn::default()
+                            });
+                        churns.push((key, churn));
+                    }
+                    AppEvent::ChurnLoaded(churns)
+                }
+            };
+            if events.send(event).is_err() {
+                return;
+            }
+        }
+    }
+}
+
+fn with_repo<T>(
+    repo: &mut Option<GitRepo>,
+    location: &RepoLocation,
+    read: impl FnOnce(&mut GitRepo) -> AppResult<T>,
+) -> AppResult<T> {
+    match repo {
+        Some(repo) => read(repo),
+        // Report the error again instead of leaving the app waiting.
+        None => GitRepo::open(location).and_then(|mut repo| read(&mut repo)),
+    }
+}
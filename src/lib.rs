@@ -1,15 +1,44 @@
 //! src/lib.rs
 
+/// Actions that change the app's state.
+pub mod action;
 /// Core application state and logic.
 pub mod app;
-/// Keybinding configuration.
+/// Command-line argument parsing and non-interactive subcommands.
+pub mod cli;
+/// User configuration and keybindings.
 pub mod config;
+/// Dotfiles link manifest and symlink management.
+pub mod dotfiles;
+/// The health checks of the doctor view.
+pub mod doctor;
 /// Custom error types.
 pub mod error;
 /// Event handling (input and custom app events).
 pub mod event;
+/// GitHub/GitLab integration: CI status and pull requests.
+pub mod forge;
 /// Git repository interactions.
 pub mod git;
+/// The log file and the recent records shown in the debug view.
+pub mod logging;
+/// Views that draw themselves and handle their keys, built in or registered.
+pub mod panel;
+/// Single-line text input with a cursor and history, used by popups.
+pub mod prompt;
+/// The UI state restored when a repository is opened again.
+pub mod session;
+/// User scripts (`init.lua`) that react to events and add palette actions.
+pub mod scripting;
+/// First-run flow for directories without a repository.
+pub mod setup;
+/// Toast notifications and their history.
+pub mod notification;
+/// Background tasks: progress reports and cancellation.
+pub mod task;
+/// Repository fixtures and a headless app for tests.
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
 /// Terminal User Interface setup and teardown.
 pub mod tui;
 /// UI rendering logic.
@@ -0,0 +1,137 @@
+//! src/logging.rs
+
+use crate::error::{AppError, AppResult};
+use chrono::{DateTime, Local};
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use simplelog::{CombinedLogger, ConfigBuilder, SharedLogger, WriteLogger};
+use std::{
+    collections::VecDeque,
+    env,
+    fs::{self, File},
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+/// Records kept in memory for the debug view.
+const RECENT_LIMIT: usize = 1000;
+
+static RECENT: Mutex<VecDeque<LogLine>> = Mutex::new(VecDeque::new());
+
+/// A log record as the debug view shows it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogLine {
+    pub time: DateTime<Local>,
+    pub level: Level,
+    /// The module that logged it, e.g. `dotatui::app`.
+    pub target: String,
+    pub message: String,
+}
+
+/// `$XDG_CACHE_HOME/dotatui/dotatui.log`, falling back to `~/.cache` (or
+/// `%LOCALAPPDATA%` on Windows), and to the temporary directory when none
+/// of them is set. Never inside the repository, which it would pollute.
+pub fn default_log_path() -> PathBuf {
+    env::var_os("XDG_CACHE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("LOCALAPPDATA").map(PathBuf::from))
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))
+        .unwrap_or_else(env::temp_dir)
+        .join("dotatui")
+        .join("dotatui.log")
+}
+
+/// Writes records at `level` and above to `path`, replacing what an
+/// earlier run left there, and keeps the newest ones for the debug view.
+pub fn init(level: LevelFilter, path: &Path) -> AppResult<()> {
+    let cannot_write = |e: std::io::Error| AppError::Logging(format!("cannot write {}: {}", path.display(), e));
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(cannot_write)?;
+    }
+    let config = ConfigBuilder::new()
+        .set_target_level(LevelFilter::Error)
+        .set_time_format_rfc3339()
+        .build();
+    CombinedLogger::init(vec![
+        WriteLogger::new(level, config, File::create(path).map_err(cannot_write)?),
+        Box::new(RecentLogger { level }),
+    ])
+    .map_err(|e| AppError::Logging(e.to_string()))
+}
+
+/// Calls `f` with the kept records, oldest first.
+pub fn with_recent<R>(f: impl FnOnce(&VecDeque<LogLine>) -> R) -> R {
+    let recent = RECENT.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    f(&recent)
+}
+
+/// Keeps the newest `RECENT_LIMIT` records in `RECENT`.
+struct RecentLogger {
+    level: LevelFilter,
+}
+
+impl Log for RecentLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.level
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let line = LogLine {
+            time: Local::now(),
+            level: record.level(),
+            target: record.target().to_string(),
+            message: record.args().to_string(),
+        };
+        let mut recent = RECENT.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        if recent.len() == RECENT_LIMIT {
+            recent.pop_front();
+        }
+        recent.push_back(line);
+    }
+
+    fn flush(&self) {}
+}
+
+impl SharedLogger for RecentLogger {
+    fn level(&self) -> LevelFilter {
+        self.level
+    }
+
+    fn config(&self) -> Option<&simplelog::Config> {
+        None
+    }
+
+    fn as_log(self: Box<Self>) -> Box<dyn Log> {
+        Box::new(*self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn records_are_kept_for_the_debug_view_up_to_the_level() {
+        let logger = RecentLogger { level: LevelFilter::Info };
+        let record = |level, message| {
+            let args = format_args!("{}", message);
+            logger.log(&Record::builder().level(level).target("dotatui::app").args(args).build());
+        };
+        record(Level::Debug, "hidden");
+        record(Level::Warn, "shown");
+        let lines = with_recent(|recent| recent.iter().cloned().collect::<Vec<_>>());
+        assert_eq!(lines.len(), 1);
+        assert_eq!((lines[0].level, lines[0].target.as_str()), (Level::Warn, "dotatui::app"));
+        assert_eq!(lines[0].message, "shown");
+    }
+
+    #[test]
+    fn a_log_file_that_cannot_be_written_is_an_error() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let file = dir.path().join("cache");
+        fs::write(&file, "").unwrap();
+        assert!(matches!(init(LevelFilter::Info, &file.join("dotatui.log")), Err(AppError::Logging(_))));
+    }
+}
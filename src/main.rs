@@ -1,67 +1,159 @@
 //! src/main.rs
 
+use clap::Parser;
 use dotatui::{
+    action::Action,
     app::{App, AppReturn},
+    cli::{self, Cli, Command},
+    config::Config,
     error::{AppError, AppResult},
-    event::{AppEvent, Either, EventHandler, InputEvent},
-    git::GitRepo,
+    event::{Either, EventHandler, InputEvent, DEFAULT_TICK_INTERVAL},
+    git::CloneOptions,
+    logging,
+    setup,
     tui::Tui,
 };
-use std::{env, fs::File};
-
-use log::LevelFilter;
-use simplelog::{Config, WriteLogger};
+use std::env;
+use std::io::{self, IsTerminal};
+use std::time::Duration;
 
 #[tokio::main]
 async fn main() -> AppResult<()> {
-    let repo_path_raw = git2::Repository::discover(env::current_dir()?)?
-        .path()
-        .parent()
-        .ok_or(AppError::RepoNotFound)?
-        .to_path_buf();
+    let cli = Cli::parse();
+    let mut config = Config::load(cli.config.as_deref())?;
+    config.read_only |= cli.read_only;
 
+    let repo = match &cli.command {
+        Some(Command::Clone {
+            url,
+            path,
+            depth,
+            branch,
+            single_branch,
+        }) => {
+            let options = CloneOptions {
+                depth: *depth,
+                branch: branch.clone(),
+                single_branch: *single_branch,
+            };
+            setup::clone_repo(url, path.as_deref(), &options)?
+        }
+        _ => match cli.open_repo() {
+            Err(AppError::RepoNotFound) if cli.command.is_none() && cli.git_dir.is_none() => {
+                let dir = match &cli.path {
+                    Some(path) => path.clone(),
+                    None => env::current_dir()?,
+                };
+                match setup::run_setup_prompt(&dir, &mut config)? {
+                    Some(repo) => repo,
+                    None => return Ok(()),
+                }
+            }
+            result => {
+                let repo = result?;
+                // Only ask when someone is there to answer, not when a
+                // script starts dotatui.
+                if cli.command.is_none() && io::stdin().is_terminal() {
+                    setup::run_first_start(&repo, &mut config)?;
+                }
+                repo
+            }
+        },
+    };
+    let repo_path_raw = repo.path().to_path_buf();
+
+    // Made absolute before the directory changes, and for the file watcher
+    // to recognise it.
+    let log_path = env::current_dir()?.join(cli.log_file(&config));
+    if let Some(path) = &mut config.path {
+        *path = env::current_dir()?.join(&path);
+    }
     env::set_current_dir(&repo_path_raw)?;
 
-    WriteLogger::init(
-        LevelFilter::Debug,
-        Config::default(),
-        File::create("dotatui.log")?,
-    )
-    .expect("Failed to initialize logger");
+    logging::init(cli.log_level(&config)?, &log_path)?;
 
-    log::info!("Dotatui started in repository: {:?}", repo_path_raw);
+    match &cli.command {
+        // The TUI opens on the fresh clone.
+        Some(Command::Clone { .. }) | None => {}
+        Some(command) => return cli::run_command(command, &repo, &config),
+    }
 
-    let repo = GitRepo::new(".")?;
+    log::info!("Dotatui started in repository: {:?}", repo_path_raw);
 
+    Tui::install_panic_hook(log_path.clone());
     let mut tui = Tui::new()?;
     tui.enter()?;
-    let mut event_handler = EventHandler::new();
+    let tick_interval = config.tick_interval_ms.map_or(DEFAULT_TICK_INTERVAL, Duration::from_millis);
+    let mut event_handler = EventHandler::new(tick_interval);
+    if let Err(e) = event_handler.watch_repo(&repo, vec![log_path.clone()]) {
+        log::warn!("Auto-refresh disabled: {}", e);
+    }
+
+    if let Some(path) = &config.path {
+        if let Err(e) = event_handler.watch_config(path) {
+            log::warn!("Config reloading disabled: {}", e);
+        }
+    }
 
-    let mut app = App::new(repo, &event_handler);
+    let mut app = App::new(repo, config, event_handler.get_app_event_sender())?;
+    tui.set_mouse_capture(app.mouse_capture)?;
 
     while !app.is_exiting() {
-        tui.draw(|frame| {
-            dotatui::ui::render(frame, &mut app);
-        })?;
+        if app.needs_redraw() {
+            tui.draw(|frame| {
+                dotatui::ui::render(frame, &mut app);
+            })?;
+            app.mark_drawn();
+        }
 
-        // Update the main event loop match
-        match event_handler.next().await? {
-            Either::Left(InputEvent::Key(key_event)) => {
-                if app.handle_key_event(key_event)? == AppReturn::Exit {
-                    break;
-                }
+        let action = match event_handler.next().await? {
+            Either::Left(InputEvent::Key(key_event)) => app.action_for_key(key_event),
+            Either::Left(InputEvent::Mouse(mouse_event)) => Some(Action::Mouse(mouse_event)),
+            Either::Left(InputEvent::Resize) => {
+                app.request_redraw();
+                None
             }
-            // Add a new arm for Mouse events
-            Either::Left(InputEvent::Mouse(mouse_event)) => {
-                app.handle_mouse_event(mouse_event)?;
+            Either::Left(InputEvent::Tick) => Some(Action::Tick),
+            Either::Right(app_event) => Some(Action::from(app_event)),
+        };
+        if let Some(action) = action {
+            if app.dispatch(action)? == AppReturn::Exit {
+                break;
             }
-            Either::Right(AppEvent::PushFinished(result)) => {
-                app.handle_app_event(AppEvent::PushFinished(result))?;
+        }
+        if let Some(path) = app.take_editor_request() {
+            event_handler.pause_input();
+            let edited = tui.edit_file(&path);
+            event_handler.resume_input();
+            if let Err(e) = edited {
+                log::error!("Editing {} failed: {}", path.display(), e);
+            }
+            let refreshed = app.refresh();
+            app.recover("Refresh failed", refreshed)?;
+            app.request_redraw();
+        }
+        if let Some(request) = app.take_clipboard_request() {
+            let result = tui.copy_to_clipboard(&request.text);
+            app.finish_copy(request, result);
+        }
+        if let Some(request) = app.take_tool_request() {
+            event_handler.pause_input();
+            let result = tui.run_tool(&request.command);
+            event_handler.resume_input();
+            let finished = app.finish_tool(request, result);
+            app.recover("External tool failed", finished)?;
+            app.request_redraw();
+        }
+        tui.set_mouse_capture(app.mouse_capture)?;
+        if app.take_repo_switch() {
+            env::set_current_dir(app.repo.path())?;
+            if let Err(e) = event_handler.watch_repo(&app.repo, vec![log_path.clone()]) {
+                log::warn!("Auto-refresh disabled: {}", e);
             }
-            _ => {}
         }
     }
 
+    app.save_session();
     tui.exit()?;
     Ok(())
 }
@@ -0,0 +1,136 @@
+//! src/notification.rs
+
+use chrono::{DateTime, Local};
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+/// How long a toast stays on screen. Warnings and errors stay longer so
+/// that there is time to read them.
+const TOAST_DURATION: Duration = Duration::from_secs(4);
+const ERROR_TOAST_DURATION: Duration = Duration::from_secs(8);
+/// Toasts shown at once; when more arrive, the oldest is dropped early.
+const MAX_TOASTS: usize = 3;
+/// Notifications kept for the messages view.
+const HISTORY_LIMIT: usize = 200;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Info,
+    Success,
+    Warning,
+    Error,
+}
+
+impl Severity {
+    pub fn label(self) -> &'static str {
+        match self {
+            Severity::Info => "info",
+            Severity::Success => "ok",
+            Severity::Warning => "warn",
+            Severity::Error => "error",
+        }
+    }
+
+    fn toast_duration(self) -> Duration {
+        match self {
+            Severity::Info | Severity::Success => TOAST_DURATION,
+            Severity::Warning | Severity::Error => ERROR_TOAST_DURATION,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Notification {
+    pub severity: Severity,
+    pub message: String,
+    pub time: DateTime<Local>,
+    shown: Instant,
+}
+
+/// Results and messages for the user: a short queue of toasts that expire
+/// on their own, and a history of everything that was shown.
+#[derive(Debug, Default)]
+pub struct Notifications {
+    /// Oldest first.
+    toasts: VecDeque<Notification>,
+    /// Oldest first.
+    history: VecDeque<Notification>,
+}
+
+impl Notifications {
+    pub fn push(&mut self, severity: Severity, message: String) {
+        let notification = Notification {
+            severity,
+            message,
+            time: Local::now(),
+            shown: Instant::now(),
+        };
+        if self.history.len() == HISTORY_LIMIT {
+            self.history.pop_front();
+        }
+        self.history.push_back(notification.clone());
+        if self.toasts.len() == MAX_TOASTS {
+            self.toasts.pop_front();
+        }
+        self.toasts.push_back(notification);
+    }
+
+    /// Removes the toasts whose time is up at `now`, returning whether there
+    /// were any. Called on every tick.
+    pub fn expire(&mut self, now: Instant) -> bool {
+        let count = self.toasts.len();
+        self.toasts
+            .retain(|toast| now.duration_since(toast.shown) < toast.severity.toast_duration());
+        self.toasts.len() != count
+    }
+
+    /// The toasts on screen, oldest first.
+    pub fn toasts(&self) -> impl DoubleEndedIterator<Item = &Notification> {
+        self.toasts.iter()
+    }
+
+    /// Every notification still kept, newest first.
+    pub fn history(&self) -> impl Iterator<Item = &Notification> {
+        self.history.iter().rev()
+    }
+
+    pub fn history_len(&self) -> usize {
+        self.history.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn messages<'a>(notifications: impl Iterator<Item = &'a Notification>) -> Vec<&'a str> {
+        notifications.map(|n| n.message.as_str()).collect()
+    }
+
+    #[test]
+    fn toasts_expire_but_stay_in_the_history() {
+        let mut notifications = Notifications::default();
+        notifications.push(Severity::Success, "Push successful!".to_string());
+        notifications.push(Severity::Error, "Fetch failed".to_string());
+
+        notifications.expire(Instant::now() + TOAST_DURATION);
+        assert_eq!(messages(notifications.toasts()), ["Fetch failed"]);
+        assert!(notifications.expire(Instant::now() + ERROR_TOAST_DURATION));
+        assert_eq!(notifications.toasts().count(), 0);
+        assert!(!notifications.expire(Instant::now() + ERROR_TOAST_DURATION));
+
+        assert_eq!(messages(notifications.history()), ["Fetch failed", "Push successful!"]);
+    }
+
+    #[test]
+    fn only_the_newest_toasts_are_shown() {
+        let mut notifications = Notifications::default();
+        for i in 0..=MAX_TOASTS {
+            notifications.push(Severity::Info, i.to_string());
+        }
+        assert_eq!(messages(notifications.toasts()), ["1", "2", "3"]);
+        assert_eq!(notifications.history_len(), MAX_TOASTS + 1);
+    }
+}
@@ -0,0 +1,194 @@
+//! src/panel.rs
+
+use crate::action::Action;
+use crate::app::{App, Mode};
+use crate::error::AppResult;
+use crate::ui;
+use ratatui::{layout::Rect, Frame};
+
+/// A view with a tab of its own that draws itself and handles the keys
+/// pressed while it is shown. The links, forge and stats views are panels;
+/// more are compiled in with `App::register_panel`, without touching
+/// `ui::render`.
+pub trait Panel {
+    /// The tab title. A letter in brackets marks the key that opens it.
+    fn title(&self) -> &str;
+
+    /// Called when the user switches to the panel, e.g. to load its data.
+    fn open(&mut self, _app: &mut App) {}
+
+    fn render(&mut self, frame: &mut Frame, app: &mut App, area: Rect);
+
+    /// Handles an action of the panel's keymap, or `Action::Key` with a key
+    /// no keymap binds. Global keys never get here, and neither do digits,
+    /// which count how often the next action repeats.
+    fn handle_key(&mut self, app: &mut App, action: Action) -> AppResult<()>;
+}
+
+/// The registered panels with the modes that show them.
+pub struct Panels {
+    panels: Vec<(Mode, Box<dyn Panel>)>,
+    /// How many panels `register` added, to number their modes.
+    custom: usize,
+}
+
+impl Default for Panels {
+    /// The built-in panels.
+    fn default() -> Self {
+        Self {
+            panels: vec![
+                (Mode::Links, Box::new(LinksPanel)),
+                (Mode::Forge, Box::new(ForgePanel)),
+                (Mode::Stats, Box::new(StatsPanel)),
+                (Mode::Doctor, Box::new(DoctorPanel)),
+                (Mode::GitConfig, Box::new(GitConfigPanel)),
+            ],
+            custom: 0,
+        }
+    }
+}
+
+impl Panels {
+    /// Adds `panel` and returns the mode that shows it. Its tab comes after
+    /// the built-in ones.
+    pub fn register(&mut self, panel: Box<dyn Panel>) -> Mode {
+        let mode = Mode::Custom(self.custom);
+        self.custom += 1;
+        self.panels.push((mode, panel));
+        mode
+    }
+
+    pub fn contains(&self, mode: Mode) -> bool {
+        self.panels.iter().any(|(shown_in, _)| *shown_in == mode)
+    }
+
+    pub fn title(&self, mode: Mode) -> Option<&str> {
+        self.panels
+            .iter()
+            .find(|(shown_in, _)| *shown_in == mode)
+            .map(|(_, panel)| panel.title())
+    }
+
+    /// The modes and titles of the panels added with `register`.
+    pub fn custom(&self) -> impl Iterator<Item = (Mode, &str)> {
+        self.panels
+            .iter()
+            .filter(|(mode, _)| matches!(mode, Mode::Custom(_)))
+            .map(|(mode, panel)| (*mode, panel.title()))
+    }
+
+    /// Takes out the panel of `mode` with its position, so that it can be
+    /// handed the app it belongs to.
+    pub(crate) fn take(&mut self, mode: Mode) -> Option<(usize, Box<dyn Panel>)> {
+        let index = self.panels.iter().position(|(shown_in, _)| *shown_in == mode)?;
+        Some((index, self.panels.remove(index).1))
+    }
+
+    /// Puts back a panel `take` took out.
+    pub(crate) fn put_back(&mut self, index: usize, mode: Mode, panel: Box<dyn Panel>) {
+        self.panels.insert(index.min(self.panels.len()), (mode, panel));
+    }
+}
+
+/// The dotfile links of the manifest.
+struct LinksPanel;
+
+impl Panel for LinksPanel {
+    fn title(&self) -> &str {
+        "L[i]nks"
+    }
+
+    fn render(&mut self, frame: &mut Frame, app: &mut App, area: Rect) {
+        ui::render_links_view(frame, app, area);
+    }
+
+    fn handle_key(&mut self, app: &mut App, action: Action) -> AppResult<()> {
+        app.handle_links_action(action)
+    }
+}
+
+/// CI and pull requests of HEAD on GitHub or GitLab.
+struct ForgePanel;
+
+impl Panel for ForgePanel {
+    fn title(&self) -> &str {
+        "[W]eb"
+    }
+
+    fn open(&mut self, app: &mut App) {
+        app.load_forge();
+    }
+
+    fn render(&mut self, frame: &mut Frame, app: &mut App, area: Rect) {
+        ui::render_forge_view(frame, app, area);
+    }
+
+    fn handle_key(&mut self, app: &mut App, action: Action) -> AppResult<()> {
+        app.handle_forge_action(action);
+        Ok(())
+    }
+}
+
+/// Checks of the setup with fixes for what is wrong, run again every time
+/// it is opened.
+struct DoctorPanel;
+
+impl Panel for DoctorPanel {
+    fn title(&self) -> &str {
+        "[D]octor"
+    }
+
+    fn open(&mut self, app: &mut App) {
+        app.load_doctor();
+    }
+
+    fn render(&mut self, frame: &mut Frame, app: &mut App, area: Rect) {
+        ui::render_doctor_view(frame, app, area);
+    }
+
+    fn handle_key(&mut self, app: &mut App, action: Action) -> AppResult<()> {
+        app.handle_doctor_action(action)
+    }
+}
+
+/// The local and global git config, read again every time it is opened.
+struct GitConfigPanel;
+
+impl Panel for GitConfigPanel {
+    fn title(&self) -> &str {
+        "Git [C]onfig"
+    }
+
+    fn open(&mut self, app: &mut App) {
+        app.load_git_config();
+    }
+
+    fn render(&mut self, frame: &mut Frame, app: &mut App, area: Rect) {
+        ui::render_git_config_view(frame, app, area);
+    }
+
+    fn handle_key(&mut self, app: &mut App, action: Action) -> AppResult<()> {
+        app.handle_git_config_action(action)
+    }
+}
+
+/// Statistics of the whole history, recomputed every time it is opened.
+struct StatsPanel;
+
+impl Panel for StatsPanel {
+    fn title(&self) -> &str {
+        "Stat[S]"
+    }
+
+    fn open(&mut self, app: &mut App) {
+        app.load_stats();
+    }
+
+    fn render(&mut self, frame: &mut Frame, app: &mut App, area: Rect) {
+        ui::render_stats_view(frame, app, area);
+    }
+
+    fn handle_key(&mut self, _app: &mut App, _action: Action) -> AppResult<()> {
+        Ok(())
+    }
+}
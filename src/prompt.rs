@@ -0,0 +1,242 @@
+//! src/prompt.rs
+
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use std::fmt;
+use unicode_width::UnicodeWidthStr;
+
+/// How many submitted entries a prompt remembers.
+const HISTORY_LIMIT: usize = 50;
+
+/// Checks the trimmed text of a prompt; `Err` says why it cannot be
+/// submitted.
+pub type Validator = fn(&str) -> Result<(), String>;
+
+/// A single-line text input of a popup or the search bar: the text with a
+/// cursor, the entries submitted before, recalled with Up and Down, and an
+/// optional check of what was typed.
+#[derive(Debug, Clone, Default)]
+pub struct PromptState {
+    text: String,
+    /// A byte offset into `text`, always on a character boundary.
+    cursor: usize,
+    history: Vec<String>,
+    /// The history entry shown, counted from the newest; `None` while a new
+    /// entry is typed.
+    recalled: Option<usize>,
+    /// What was typed before the history was browsed.
+    draft: String,
+    validator: Option<Validator>,
+}
+
+impl PromptState {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_validator(validator: Validator) -> Self {
+        Self {
+            validator: Some(validator),
+            ..Self::default()
+        }
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// Replaces the text, with the cursor at its end.
+    pub fn set(&mut self, text: impl Into<String>) {
+        self.text = text.into();
+        self.cursor = self.text.len();
+        self.recalled = None;
+    }
+
+    pub fn clear(&mut self) {
+        self.set(String::new());
+    }
+
+    /// The columns the text before the cursor takes, to place the terminal
+    /// cursor after the prompt's prefix.
+    pub fn cursor_width(&self) -> u16 {
+        self.text[..self.cursor].width() as u16
+    }
+
+    /// Why the text cannot be submitted, when the validator objects to it.
+    /// An empty text is left to the caller.
+    pub fn error(&self) -> Option<String> {
+        let text = self.text.trim();
+        if text.is_empty() {
+            return None;
+        }
+        self.validator.and_then(|validate| validate(text).err())
+    }
+
+    /// Adds the text to the history, unless it is blank or repeats the
+    /// newest entry. For prompts whose text is still needed after they
+    /// were submitted.
+    pub fn remember(&mut self) {
+        let text = self.text.trim();
+        if !text.is_empty() && self.history.last().map(String::as_str) != Some(text) {
+            self.history.push(text.to_string());
+            if self.history.len() > HISTORY_LIMIT {
+                self.history.remove(0);
+            }
+        }
+        self.recalled = None;
+    }
+
+    /// Takes the text, remembering it. Fails with the validator's objection
+    /// and keeps the text to be corrected.
+    pub fn submit(&mut self) -> Result<String, String> {
+        if let Some(error) = self.error() {
+            return Err(error);
+        }
+        self.remember();
+        let text = std::mem::take(&mut self.text);
+        self.cursor = 0;
+        Ok(text)
+    }
+
+    /// Applies an editing key: characters, Backspace and Delete, Left,
+    /// Right, Home and End (also `Ctrl+a` and `Ctrl+e`), `Ctrl+u` to delete
+    /// up to the cursor, `Ctrl+w` to delete the word before it, and Up and
+    /// Down to go through the history. Returns whether the key was used.
+    pub fn handle_key(&mut self, key: KeyEvent) -> bool {
+        let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+        match key.code {
+            KeyCode::Char('a') if ctrl => self.cursor = 0,
+            KeyCode::Char('e') if ctrl => self.cursor = self.text.len(),
+            KeyCode::Char('u') if ctrl => self.edit(|text, cursor| {
+                text.replace_range(..cursor, "");
+                0
+            }),
+            KeyCode::Char('w') if ctrl => self.edit(|text, cursor| {
+                let before = text[..cursor].trim_end();
+                let start = before.rfind(char::is_whitespace).map_or(0, |i| i + 1);
+                text.replace_range(start..cursor, "");
+                start
+            }),
+            KeyCode::Char(_) if ctrl || key.modifiers.contains(KeyModifiers::ALT) => return false,
+            KeyCode::Char(c) => self.edit(|text, cursor| {
+                text.insert(cursor, c);
+                cursor + c.len_utf8()
+            }),
+            KeyCode::Backspace => self.edit(|text, cursor| match text[..cursor].chars().next_back() {
+                Some(c) => {
+                    text.remove(cursor - c.len_utf8());
+                    cursor - c.len_utf8()
+                }
+                None => cursor,
+            }),
+            KeyCode::Delete => self.edit(|text, cursor| {
+                if cursor < text.len() {
+                    text.remove(cursor);
+                }
+                cursor
+            }),
+            KeyCode::Left => {
+                self.cursor -= self.text[..self.cursor].chars().next_back().map_or(0, char::len_utf8);
+            }
+            KeyCode::Right => {
+                self.cursor += self.text[self.cursor..].chars().next().map_or(0, char::len_utf8);
+            }
+            KeyCode::Home => self.cursor = 0,
+            KeyCode::End => self.cursor = self.text.len(),
+            KeyCode::Up => self.recall_older(),
+            KeyCode::Down => self.recall_newer(),
+            _ => return false,
+        }
+        true
+    }
+
+    /// Changes the text with `change`, which gets the cursor and returns
+    /// where it is afterwards. Editing a recalled entry makes it a new one.
+    fn edit(&mut self, change: impl FnOnce(&mut String, usize) -> usize) {
+        self.cursor = change(&mut self.text, self.cursor);
+        self.recalled = None;
+    }
+
+    fn recall_older(&mut self) {
+        let Some(last) = self.history.len().checked_sub(1) else {
+            return;
+        };
+        let recalled = match self.recalled {
+            None => {
+                self.draft = self.text.clone();
+                0
+            }
+            Some(i) => (i + 1).min(last),
+        };
+        self.text = self.history[last - recalled].clone();
+        self.cursor = self.text.len();
+        self.recalled = Some(recalled);
+    }
+
+    fn recall_newer(&mut self) {
+        match self.recalled {
+            None => {}
+            Some(0) => {
+                let draft = std::mem::take(&mut self.draft);
+                self.set(draft);
+            }
+            Some(i) => {
+                self.text = self.history[self.history.len() - i].clone();
+                self.cursor = self.text.len();
+                self.recalled = Some(i - 1);
+            }
+        }
+    }
+}
+
+impl fmt::Display for PromptState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn press(prompt: &mut PromptState, code: KeyCode) {
+        prompt.handle_key(KeyEvent::new(code, KeyModifiers::NONE));
+    }
+
+    fn type_text(prompt: &mut PromptState, text: &str) {
+        text.chars().for_each(|c| press(prompt, KeyCode::Char(c)));
+    }
+
+    #[test]
+    fn prompts_edit_at_the_cursor_and_recall_submitted_entries() {
+        let mut prompt = PromptState::with_validator(|text| match text.contains(' ') {
+            true => Err("no spaces".to_string()),
+            false => Ok(()),
+        });
+        type_text(&mut prompt, "fix-ü");
+        press(&mut prompt, KeyCode::Left);
+        press(&mut prompt, KeyCode::Backspace);
+        type_text(&mut prompt, " ");
+        assert_eq!((prompt.text(), prompt.cursor_width()), ("fix ü", 4));
+        assert_eq!(prompt.submit(), Err("no spaces".to_string()));
+
+        press(&mut prompt, KeyCode::Left);
+        press(&mut prompt, KeyCode::Delete);
+        assert_eq!(prompt.submit(), Ok("fixü".to_string()));
+        type_text(&mut prompt, "main");
+        assert_eq!(prompt.submit(), Ok("main".to_string()));
+
+        type_text(&mut prompt, "dr");
+        press(&mut prompt, KeyCode::Up);
+        press(&mut prompt, KeyCode::Up);
+        press(&mut prompt, KeyCode::Up);
+        assert_eq!(prompt.text(), "fixü");
+        press(&mut prompt, KeyCode::Down);
+        assert_eq!(prompt.text(), "main");
+        press(&mut prompt, KeyCode::Down);
+        assert_eq!(prompt.text(), "dr");
+
+        prompt.set("feature/a b");
+        prompt.handle_key(KeyEvent::new(KeyCode::Char('w'), KeyModifiers::CONTROL));
+        assert_eq!(prompt.text(), "feature/a ");
+    }
+}
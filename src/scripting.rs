@@ -0,0 +1,122 @@
+//! src/scripting.rs
+
+#[cfg(feature = "scripting")]
+mod lua;
+
+use crate::error::{AppError, AppResult};
+use crate::git::RepoLocation;
+use std::path::Path;
+
+/// The script run at startup, next to the config file.
+pub const SCRIPT_FILE: &str = "init.lua";
+
+/// The moments scripts can react to with `dotatui.on(event, handler)`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScriptEvent {
+    /// A commit was made; the handler gets its id.
+    PostCommit,
+    /// A push went through; the handler gets the remotes pushed to,
+    /// separated by spaces.
+    PostPush,
+    PostFetch,
+    PostPull,
+}
+
+impl ScriptEvent {
+    pub const ALL: [ScriptEvent; 4] = [
+        ScriptEvent::PostCommit,
+        ScriptEvent::PostPush,
+        ScriptEvent::PostFetch,
+        ScriptEvent::PostPull,
+    ];
+
+    /// The name scripts use for the event.
+    pub fn name(self) -> &'static str {
+        match self {
+            ScriptEvent::PostCommit => "PostCommit",
+            ScriptEvent::PostPush => "PostPush",
+            ScriptEvent::PostFetch => "PostFetch",
+            ScriptEvent::PostPull => "PostPull",
+        }
+    }
+}
+
+/// What a script asked the app to do. Scripts do not touch the app
+/// directly; their requests are carried out once they return.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ScriptRequest {
+    /// `dotatui.notify(message)`.
+    Notify(String),
+    /// `dotatui.refresh()`.
+    Refresh,
+}
+
+/// The user's scripts, with the handlers and actions they registered.
+/// Without the `scripting` feature there never are any.
+#[derive(Default)]
+pub struct Scripts {
+    #[cfg(feature = "scripting")]
+    engine: Option<lua::Engine>,
+}
+
+impl Scripts {
+    /// Runs the script at `path`, if there is one. `location` is the
+    /// repository `dotatui.git` works on.
+    pub fn load(path: &Path, location: &RepoLocation) -> AppResult<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        #[cfg(feature = "scripting")]
+        {
+            let engine = lua::Engine::load(path, location)?;
+            Ok(Self { engine: Some(engine) })
+        }
+        #[cfg(not(feature = "scripting"))]
+        {
+            let _ = location;
+            Err(AppError::Script(format!(
+                "{} was not run: dotatui was built without the `scripting` feature",
+                path.display()
+            )))
+        }
+    }
+
+    /// Points `dotatui.git` at another repository, e.g. after switching.
+    pub fn set_repo(&mut self, location: &RepoLocation) {
+        #[cfg(feature = "scripting")]
+        if let Some(engine) = &self.engine {
+            engine.set_repo(location);
+        }
+        #[cfg(not(feature = "scripting"))]
+        let _ = location;
+    }
+
+    /// The names of the actions registered with `dotatui.action`, in the
+    /// order they were registered.
+    pub fn actions(&self) -> Vec<String> {
+        #[cfg(feature = "scripting")]
+        if let Some(engine) = &self.engine {
+            return engine.actions();
+        }
+        Vec::new()
+    }
+
+    /// Calls the handlers of `event` with `argument`.
+    pub fn emit(&self, event: ScriptEvent, argument: Option<&str>) -> AppResult<Vec<ScriptRequest>> {
+        #[cfg(feature = "scripting")]
+        if let Some(engine) = &self.engine {
+            return engine.emit(event, argument);
+        }
+        let _ = (event, argument);
+        Ok(Vec::new())
+    }
+
+    /// Calls the action registered as `name`.
+    pub fn run_action(&self, name: &str) -> AppResult<Vec<ScriptRequest>> {
+        #[cfg(feature = "scripting")]
+        if let Some(engine) = &self.engine {
+            return engine.run_action(name);
+        }
+        Err(AppError::Script(format!("there is no action '{}'", name)))
+    }
+}
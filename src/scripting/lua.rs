@@ -0,0 +1,228 @@
+//! src/scripting/lua.rs
+
+use super::{ScriptEvent, ScriptRequest};
+use crate::error::{AppError, AppResult};
+use crate::git::RepoLocation;
+use mlua::{Function, Lua, RegistryKey, Variadic};
+use std::{cell::RefCell, fs, path::Path, process::Command, rc::Rc};
+
+/// What the `dotatui` table's functions share with the engine.
+struct State {
+    location: RefCell<RepoLocation>,
+    handlers: RefCell<Vec<(ScriptEvent, RegistryKey)>>,
+    actions: RefCell<Vec<(String, RegistryKey)>>,
+    requests: RefCell<Vec<ScriptRequest>>,
+}
+
+/// A Lua interpreter that ran the user's script, holding the functions it
+/// registered.
+pub struct Engine {
+    lua: Lua,
+    state: Rc<State>,
+}
+
+impl Engine {
+    /// Runs the script at `path` with the `dotatui` table in place:
+    ///
+    /// - `dotatui.on(event, handler)` calls `handler` after `event`, e.g.
+    ///   `PostCommit`;
+    /// - `dotatui.action(name, fn)` adds `name` to the command palette;
+    /// - `dotatui.notify(message)` shows a notification;
+    /// - `dotatui.refresh()` reloads the repository afterwards;
+    /// - `dotatui.git(args...)` runs git on the open repository and returns
+    ///   its output, raising an error with git's when it fails.
+    pub fn load(path: &Path, location: &RepoLocation) -> AppResult<Self> {
+        let source = fs::read_to_string(path)?;
+        let lua = Lua::new();
+        let state = Rc::new(State {
+            location: RefCell::new(location.clone()),
+            handlers: RefCell::new(Vec::new()),
+            actions: RefCell::new(Vec::new()),
+            requests: RefCell::new(Vec::new()),
+        });
+        install_api(&lua, &state).map_err(script_error)?;
+        lua.load(source)
+            .set_name(format!("@{}", path.display()))
+            .exec()
+            .map_err(script_error)?;
+        Ok(Self { lua, state })
+    }
+
+    pub fn set_repo(&self, location: &RepoLocation) {
+        *self.state.location.borrow_mut() = location.clone();
+    }
+
+    pub fn actions(&self) -> Vec<String> {
+        self.state.actions.borrow().iter().map(|(name, _)| name.clone()).collect()
+    }
+
+    pub fn emit(&self, event: ScriptEvent, argument: Option<&str>) -> AppResult<Vec<ScriptRequest>> {
+        // Handlers may register more handlers, so none is borrowed while
+        // they run.
+        let handlers = self
+            .state
+            .handlers
+            .borrow()
+            .iter()
+            .filter(|(registered, _)| *registered == event)
+            .map(|(_, key)| self.lua.registry_value::<Function>(key))
+            .collect::<mlua::Result<Vec<_>>>()
+            .map_err(script_error)?;
+        let result = handlers
+            .into_iter()
+            .try_for_each(|handler| handler.call::<_, ()>(argument))
+            .map_err(script_error);
+        let requests = self.state.requests.take();
+        result.map(|()| requests)
+    }
+
+    pub fn run_action(&self, name: &str) -> AppResult<Vec<ScriptRequest>> {
+        let action = self
+            .state
+            .actions
+            .borrow()
+            .iter()
+            .find(|(registered, _)| registered == name)
+            .map(|(_, key)| self.lua.registry_value::<Function>(key))
+            .transpose()
+            .map_err(script_error)?;
+        let Some(action) = action else {
+            return Err(AppError::Script(format!("there is no action '{}'", name)));
+        };
+        let result = action.call::<_, ()>(()).map_err(script_error);
+        // The requests of a failed action are dropped with it.
+        let requests = self.state.requests.take();
+        result.map(|()| requests)
+    }
+}
+
+fn install_api(lua: &Lua, state: &Rc<State>) -> mlua::Result<()> {
+    let api = lua.create_table()?;
+
+    let shared = Rc::clone(state);
+    api.set(
+        "on",
+        lua.create_function(move |lua, (name, handler): (String, Function)| {
+            let event = ScriptEvent::ALL
+                .into_iter()
+                .find(|event| event.name() == name)
+                .ok_or_else(|| {
+                    let names: Vec<&str> = ScriptEvent::ALL.iter().map(|event| event.name()).collect();
+                    mlua::Error::RuntimeError(format!("unknown event '{}', expected one of {}", name, names.join(", ")))
+                })?;
+            let key = lua.create_registry_value(handler)?;
+            shared.handlers.borrow_mut().push((event, key));
+            Ok(())
+        })?,
+    )?;
+
+    let shared = Rc::clone(state);
+    api.set(
+        "action",
+        lua.create_function(move |lua, (name, action): (String, Function)| {
+            let key = lua.create_registry_value(action)?;
+            let mut actions = shared.actions.borrow_mut();
+            // Registering a name again replaces the action.
+            match actions.iter_mut().find(|(registered, _)| *registered == name) {
+                Some(entry) => entry.1 = key,
+                None => actions.push((name, key)),
+            }
+            Ok(())
+        })?,
+    )?;
+
+    let shared = Rc::clone(state);
+    api.set(
+        "notify",
+        lua.create_function(move |_, message: String| {
+            shared.requests.borrow_mut().push(ScriptRequest::Notify(message));
+            Ok(())
+        })?,
+    )?;
+
+    let shared = Rc::clone(state);
+    api.set(
+        "refresh",
+        lua.create_function(move |_, ()| {
+            shared.requests.borrow_mut().push(ScriptRequest::Refresh);
+            Ok(())
+        })?,
+    )?;
+
+    let shared = Rc::clone(state);
+    api.set(
+        "git",
+        lua.create_function(move |_, args: Variadic<String>| {
+            run_git(&shared.location.borrow(), &args).map_err(mlua::Error::RuntimeError)
+        })?,
+    )?;
+
+    lua.globals().set("dotatui", api)
+}
+
+/// Runs git on the repository at `location`, from its work tree, and
+/// returns what it printed.
+fn run_git(location: &RepoLocation, args: &[String]) -> Result<String, String> {
+    let mut command = Command::new("git");
+    command.arg("--git-dir").arg(&location.git_dir);
+    if let Some(work_tree) = &location.work_tree {
+        command.arg("--work-tree").arg(work_tree).current_dir(work_tree);
+    }
+    let output = command
+        .args(args)
+        .output()
+        .map_err(|e| format!("could not run git: {}", e))?;
+    match output.status.success() {
+        true => Ok(String::from_utf8_lossy(&output.stdout).into_owned()),
+        false => Err(String::from_utf8_lossy(&output.stderr).trim_end().to_string()),
+    }
+}
+
+fn script_error(error: mlua::Error) -> AppError {
+    AppError::Script(error.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::RepoFixture;
+
+    #[test]
+    fn scripts_register_handlers_and_actions() {
+        let fixture = RepoFixture::new().committed("a", "1\n");
+        let script = fixture.path().join("init.lua");
+        fs::write(
+            &script,
+            r#"
+            dotatui.on("PostCommit", function(id)
+                dotatui.notify("committed " .. id)
+                dotatui.refresh()
+            end)
+            dotatui.action("Show branch", function()
+                dotatui.notify(dotatui.git("branch", "--show-current"))
+            end)
+            dotatui.action("Fail", function() dotatui.git("no-such-command") end)
+            "#,
+        )
+        .unwrap();
+        let location = fixture.open().location();
+        let engine = Engine::load(&script, &location).unwrap();
+        assert_eq!(engine.actions(), ["Show branch", "Fail"]);
+
+        assert_eq!(
+            engine.emit(ScriptEvent::PostCommit, Some("abc123")).unwrap(),
+            [ScriptRequest::Notify("committed abc123".to_string()), ScriptRequest::Refresh]
+        );
+        assert_eq!(engine.emit(ScriptEvent::PostPush, None).unwrap(), []);
+        assert_eq!(
+            engine.run_action("Show branch").unwrap(),
+            [ScriptRequest::Notify("main\n".to_string())]
+        );
+        let error = engine.run_action("Fail").unwrap_err().to_string();
+        assert!(error.contains("is not a git command"), "{}", error);
+
+        fs::write(&script, "dotatui.on('PreCommit', print)").unwrap();
+        let error = Engine::load(&script, &location).err().unwrap().to_string();
+        assert!(error.contains("unknown event 'PreCommit'"), "{}", error);
+    }
+}
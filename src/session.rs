@@ -0,0 +1,175 @@
+//! src/session.rs
+
+use crate::{
+    app::Mode,
+    dotfiles::home_dir,
+    error::{AppError, AppResult},
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    env, fs, io,
+    path::{Path, PathBuf},
+};
+
+/// The file in the git directory the session is kept in, so that every
+/// repository and worktree remembers its own.
+pub const SESSION_FILE: &str = "dotatui-session.toml";
+
+/// Where the user left off in a repository, saved on exit and restored on
+/// the next launch.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Session {
+    pub mode: Option<Mode>,
+    /// The file selected in the status view.
+    pub selected_path: Option<String>,
+    /// The commit selected in the log view.
+    pub log_selected: Option<String>,
+    /// The first commit row shown in the log view.
+    pub log_offset: usize,
+    pub group_by_dir: bool,
+    pub collapsed_dirs: Vec<String>,
+    /// The keys of the last recorded macro, written as in the config's
+    /// `[keys]` tables.
+    pub macro_keys: Vec<String>,
+}
+
+impl Session {
+    /// Reads the session of the repository at `git_dir`, which is empty
+    /// when none was saved yet.
+    pub fn load(git_dir: &Path) -> AppResult<Self> {
+        let path = session_path(git_dir);
+        let contents = match fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(e) => return Err(e.into()),
+        };
+        toml::from_str(&contents).map_err(|e| AppError::Session(format!("{}: {}", path.display(), e)))
+    }
+
+    pub fn save(&self, git_dir: &Path) -> AppResult<()> {
+        let contents = toml::to_string(self).map_err(|e| AppError::Session(e.to_string()))?;
+        fs::write(session_path(git_dir), contents)?;
+        Ok(())
+    }
+}
+
+fn session_path(git_dir: &Path) -> PathBuf {
+    git_dir.join(SESSION_FILE)
+}
+
+/// Most repositories remembered in `RecentRepos`.
+const RECENT_LIMIT: usize = 10;
+
+/// A repository opened before: where to reopen it from.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RecentRepo {
+    pub git_dir: PathBuf,
+    /// Only set when the work tree is not the git directory's parent, e.g.
+    /// for a bare dotfiles repository.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub work_tree: Option<PathBuf>,
+}
+
+/// The repositories opened last, most recent first. They are kept in a
+/// state file of their own, so that the config file is only ever written
+/// by the user.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RecentRepos {
+    pub recent: Vec<RecentRepo>,
+}
+
+impl RecentRepos {
+    /// Reads the list from `path`, which is empty when none was saved yet.
+    pub fn load(path: &Path) -> AppResult<Self> {
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(e) => return Err(e.into()),
+        };
+        toml::from_str(&contents).map_err(|e| AppError::Session(format!("{}: {}", path.display(), e)))
+    }
+
+    pub fn save(&self, path: &Path) -> AppResult<()> {
+        let contents = toml::to_string(self).map_err(|e| AppError::Session(e.to_string()))?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, contents)?;
+        Ok(())
+    }
+
+    /// Moves `repo` to the top, returning whether the list changed.
+    pub fn remember(&mut self, repo: RecentRepo) -> bool {
+        if self.recent.first() == Some(&repo) {
+            return false;
+        }
+        self.recent.retain(|recent| recent != &repo);
+        self.recent.insert(0, repo);
+        self.recent.truncate(RECENT_LIMIT);
+        true
+    }
+}
+
+/// `$XDG_STATE_HOME/dotatui/recent.toml`, falling back to `~/.local/state`.
+pub fn default_recent_path() -> Option<PathBuf> {
+    env::var_os("XDG_STATE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| home_dir().map(|home| home.join(".local").join("state")))
+        .map(|dir| dir.join("dotatui").join("recent.toml"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::StatusMode;
+
+    #[test]
+    fn sessions_survive_a_round_trip() {
+        let dir = tempfile::TempDir::new().unwrap();
+        assert_eq!(Session::load(dir.path()).unwrap(), Session::default());
+
+        for mode in [Mode::Log, Mode::Status(StatusMode::FileSelection)] {
+            let session = Session {
+                mode: Some(mode),
+                selected_path: Some("nvim/init.lua".to_string()),
+                log_selected: Some("0123abcd".to_string()),
+                log_offset: 40,
+                group_by_dir: true,
+                collapsed_dirs: vec!["zsh/".to_string()],
+                macro_keys: vec!["space".to_string(), "ctrl+s".to_string()],
+            };
+            session.save(dir.path()).unwrap();
+            assert_eq!(Session::load(dir.path()).unwrap(), session);
+        }
+
+        fs::write(dir.path().join(SESSION_FILE), "mode = 3").unwrap();
+        assert!(matches!(Session::load(dir.path()), Err(AppError::Session(_))));
+    }
+
+    #[test]
+    fn recent_repositories_keep_the_newest_first_in_their_own_file() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("state").join("recent.toml");
+        assert_eq!(RecentRepos::load(&path).unwrap(), RecentRepos::default());
+
+        let repo = |name: &str| RecentRepo {
+            git_dir: PathBuf::from(name),
+            work_tree: None,
+        };
+        let mut recent = RecentRepos::default();
+        for i in 0..12 {
+            assert!(recent.remember(repo(&i.to_string())));
+        }
+        assert!(!recent.remember(repo("11")));
+        assert!(recent.remember(repo("5")));
+        let names: Vec<_> = recent.recent.iter().map(|r| r.git_dir.to_string_lossy().into_owned()).collect();
+        assert_eq!(names, ["5", "11", "10", "9", "8", "7", "6", "4", "3", "2"]);
+
+        recent.recent[1].work_tree = Some(PathBuf::from("/home/me"));
+        recent.save(&path).unwrap();
+        assert_eq!(RecentRepos::load(&path).unwrap(), recent);
+    }
+}
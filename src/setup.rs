@@ -0,0 +1,355 @@
+//! src/setup.rs
+
+use crate::{
+    config::{Config, Theme},
+    dotfiles::expand_home,
+    error::{AppError, AppResult},
+    git::{
+        credentials::{CredentialPrompt, Prompter},
+        gitconfig::{self, ConfigScope, ValueKind},
+        clone_dir_name, CloneOptions, GitRepo, TransferStats,
+    },
+    task::CancelToken,
+};
+use crossterm::{
+    event::{self, Event, KeyCode, KeyEventKind, KeyModifiers},
+    terminal::{disable_raw_mode, enable_raw_mode},
+};
+use std::{
+    fs,
+    io::{self, BufRead, Write},
+    path::{Path, PathBuf},
+};
+
+/// The settings the setup asks about for a new config file: the question
+/// and the line written when it is answered with yes.
+const CONFIG_QUESTIONS: [(&str, &str); 4] = [
+    (
+        "Leave the mouse to the terminal, so that selecting text works?",
+        "disable_mouse = true",
+    ),
+    ("Open the commit popup with the conventional-commit helper?", "conventional_commits = true"),
+    ("Fetch the upstream in the background every 5 minutes?", "auto_fetch_interval = 300"),
+    ("Commit and push every change on its own while dotatui is open?", "sync = true"),
+];
+
+/// Sets up a new machine when no repository was found at or above `dir`:
+/// clones an existing dotfiles repository or initializes a new one, asks
+/// for `user.name` and `user.email` when git has none, and, when there is
+/// no config file yet, for a theme and whether to write one, applying the
+/// answers to `config`. Runs on the plain terminal, before the TUI starts.
+/// Returns `None` when the user quits.
+pub fn run_setup_prompt(dir: &Path, config: &mut Config) -> AppResult<Option<GitRepo>> {
+    println!("No git repository found at or above {}.", dir.display());
+    let write_config = missing_config(config);
+    let steps = if write_config.is_some() { 4 } else { 2 };
+    step(1, steps, "the dotfiles repository");
+    let Some(repo) = choose_repo(dir)? else {
+        return Ok(None);
+    };
+    step(2, steps, "who you are in commits");
+    set_identity(&repo)?;
+    if let Some(path) = write_config {
+        step(3, steps, "the colours");
+        let theme = choose_theme()?;
+        step(4, steps, "the config file");
+        create_config(&path, config, theme, true)?;
+    }
+    Ok(Some(repo))
+}
+
+/// The first start in a repository without a config file at `config.path`:
+/// asks for the commit identity git lacks, a theme and the settings of
+/// the config file, which is written either way so that the next start
+/// goes straight to the TUI.
+pub fn run_first_start(repo: &GitRepo, config: &mut Config) -> AppResult<()> {
+    let Some(path) = missing_config(config) else {
+        return Ok(());
+    };
+    println!("Welcome to dotatui. There is no config file at {} yet.", path.display());
+    step(1, 3, "who you are in commits");
+    set_identity(repo)?;
+    step(2, 3, "the colours");
+    let theme = choose_theme()?;
+    step(3, 3, "the config file");
+    create_config(&path, config, theme, false)
+}
+
+/// Where the config file belongs, when it was not written yet.
+fn missing_config(config: &Config) -> Option<PathBuf> {
+    config.path.as_ref().filter(|path| !path.exists()).cloned()
+}
+
+fn step(number: usize, steps: usize, title: &str) {
+    println!();
+    println!("Step {}/{}: {}", number, steps, title);
+}
+
+fn choose_repo(dir: &Path) -> AppResult<Option<GitRepo>> {
+    loop {
+        println!();
+        println!("  [c] Clone an existing dotfiles repository");
+        println!("  [i] Initialize a new repository");
+        println!("  [q] Quit");
+        let result = match ask("Choice [c/i/q]: ")?.as_deref().map(str::trim) {
+            Some("c") => clone(dir),
+            Some("i") => init(dir),
+            Some("q") | None => return Ok(None),
+            Some(_) => continue,
+        };
+        match result {
+            Ok(repo) => return Ok(Some(repo)),
+            // Let the user fix a typo in the URL or path and try again.
+            Err(e) => println!("{}", e),
+        }
+    }
+}
+
+/// Asks for the `user.name` and `user.email` git does not know yet and
+/// sets them in the global config, so that every repository of the
+/// machine can commit. An empty answer skips a setting.
+fn set_identity(repo: &GitRepo) -> AppResult<()> {
+    let (name, email) = repo.user_identity();
+    if let (Some(name), Some(email)) = (&name, &email) {
+        println!("Committing as {} <{}>.", name, email);
+        return Ok(());
+    }
+    for (setting, value, kind) in [("user.name", name, ValueKind::Text), ("user.email", email, ValueKind::Email)] {
+        if value.is_some() {
+            continue;
+        }
+        loop {
+            let answer = ask(&format!("{} (empty to skip): ", setting))?.unwrap_or_default();
+            if answer.trim().is_empty() {
+                break;
+            }
+            match gitconfig::normalize(kind, &answer) {
+                Ok(value) => {
+                    repo.set_config(ConfigScope::Global, setting, &value)?;
+                    break;
+                }
+                Err(e) => println!("{}", e),
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Asks for one of the built-in themes by number; an empty answer or the
+/// end of input keeps the first, the default.
+fn choose_theme() -> AppResult<&'static str> {
+    for (i, name) in Theme::PRESETS.iter().enumerate() {
+        println!("  [{}] {}", i + 1, name);
+    }
+    loop {
+        let answer = ask(&format!("Theme [1-{}]: ", Theme::PRESETS.len()))?.unwrap_or_default();
+        let answer = answer.trim();
+        if answer.is_empty() {
+            return Ok(Theme::PRESETS[0]);
+        }
+        match answer.parse::<usize>().ok().and_then(|n| n.checked_sub(1)).and_then(|i| Theme::PRESETS.get(i)) {
+            Some(name) => return Ok(name),
+            None => continue,
+        }
+    }
+}
+
+/// Writes a config file to `path` with `theme` and the answers to
+/// `CONFIG_QUESTIONS`, after asking whether to when `confirm` is set, and
+/// applies it to `config`. Without a file, the theme still applies to this
+/// run.
+fn create_config(path: &Path, config: &mut Config, theme: &str, confirm: bool) -> AppResult<()> {
+    if confirm && !ask_yes_no(&format!("Write a config file to {}?", path.display()), true)? {
+        config.theme = Theme::preset(theme).unwrap_or_default();
+        return Ok(());
+    }
+    let mut answers = Vec::new();
+    for (question, _) in CONFIG_QUESTIONS {
+        answers.push(ask_yes_no(question, false)?);
+    }
+    let contents = config_file(&answers, theme);
+    let mut written: Config =
+        toml::from_str(&contents).map_err(|e| AppError::Config(format!("{}: {}", path.display(), e)))?;
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(path, contents)?;
+    println!("Wrote {}. Keys, tools and the rest are described in the README.", path.display());
+    written.path = config.path.take();
+    written.read_only |= config.read_only;
+    *config = written;
+    Ok(())
+}
+
+/// The contents of a new config file, with the lines of the
+/// `CONFIG_QUESTIONS` answered with yes and the `[theme]` preset.
+fn config_file(answers: &[bool], theme: &str) -> String {
+    let mut contents = "# dotatui config, written by the setup.\n".to_string();
+    for ((_, line), _) in CONFIG_QUESTIONS.iter().zip(answers).filter(|(_, yes)| **yes) {
+        contents.push_str(line);
+        contents.push('\n');
+    }
+    contents.push_str(&format!("\n[theme]\npreset = \"{}\"\n", theme));
+    contents
+}
+
+fn clone(dir: &Path) -> AppResult<GitRepo> {
+    let url = loop {
+        match ask("Repository URL: ")? {
+            Some(url) if !url.trim().is_empty() => break url.trim().to_string(),
+            Some(_) => continue,
+            None => return Err(AppError::CloneFailed("no URL given".to_string())),
+        }
+    };
+    let default = dir.join(clone_dir_name(&url));
+    let path = ask_path("Clone into", &default)?;
+    clone_into(&url, &path, &CloneOptions::default())
+}
+
+/// Clones `url` into `path`, or into a directory named after the repository
+/// when `path` is `None`, showing the progress on the terminal. This backs
+/// `dotatui clone`.
+pub fn clone_repo(url: &str, path: Option<&Path>, options: &CloneOptions) -> AppResult<GitRepo> {
+    let path = match path {
+        Some(path) => path.to_path_buf(),
+        None => PathBuf::from(clone_dir_name(url)),
+    };
+    clone_into(url, &path, options)
+}
+
+fn clone_into(url: &str, path: &Path, options: &CloneOptions) -> AppResult<GitRepo> {
+    println!("Cloning {} into {}...", url, path.display());
+    let repo = GitRepo::clone_from_url(url, path, options, &mut TerminalPrompter, &CancelToken::new(), print_progress);
+    println!();
+    repo
+}
+
+fn init(dir: &Path) -> AppResult<GitRepo> {
+    let path = ask_path("Directory to initialize", dir)?;
+    let repo = GitRepo::init(&path)?;
+    println!("Initialized an empty repository in {}.", repo.path().display());
+    Ok(repo)
+}
+
+/// Width of the progress bar drawn while cloning.
+const GAUGE_WIDTH: usize = 30;
+
+fn print_progress(stats: TransferStats) {
+    let filled = (stats.ratio() * GAUGE_WIDTH as f64).round() as usize;
+    print!(
+        "\r[{}{}] {:>3}%  {}/{} objects, {} KiB",
+        "#".repeat(filled),
+        " ".repeat(GAUGE_WIDTH - filled),
+        (stats.ratio() * 100.0).round(),
+        stats.received_objects,
+        stats.total_objects,
+        stats.received_bytes / 1024
+    );
+    let _ = io::stdout().flush();
+}
+
+/// Prints `prompt` and reads a line; `None` at end of input.
+fn ask(prompt: &str) -> AppResult<Option<String>> {
+    print!("{}", prompt);
+    io::stdout().flush()?;
+    let mut line = String::new();
+    if io::stdin().lock().read_line(&mut line)? == 0 {
+        return Ok(None);
+    }
+    Ok(Some(line.trim_end_matches(['\r', '\n']).to_string()))
+}
+
+/// Asks a yes/no question; an empty answer or the end of input picks
+/// `default`.
+fn ask_yes_no(question: &str, default: bool) -> AppResult<bool> {
+    let choices = if default { "[Y/n]" } else { "[y/N]" };
+    loop {
+        let answer = ask(&format!("{} {} ", question, choices))?.unwrap_or_default();
+        match answer.trim().to_lowercase().as_str() {
+            "" => return Ok(default),
+            "y" | "yes" => return Ok(true),
+            "n" | "no" => return Ok(false),
+            _ => continue,
+        }
+    }
+}
+
+/// Asks for a path, returning `default` when the answer is empty. A leading
+/// `~` is expanded.
+fn ask_path(prompt: &str, default: &Path) -> AppResult<PathBuf> {
+    let answer = ask(&format!("{} [{}]: ", prompt, default.display()))?.unwrap_or_default();
+    let answer = answer.trim();
+    if answer.is_empty() {
+        return Ok(default.to_path_buf());
+    }
+    Ok(expand_home(answer))
+}
+
+/// Asks for credentials on the terminal, without echoing secrets.
+struct TerminalPrompter;
+
+impl Prompter for TerminalPrompter {
+    fn ask(&mut self, prompt: &CredentialPrompt) -> Option<String> {
+        // Start on a fresh line, since a progress line may be showing.
+        println!();
+        if prompt.secret {
+            read_secret(&format!("{} ", prompt.message)).ok().flatten()
+        } else {
+            ask(&format!("{} ", prompt.message)).ok().flatten()
+        }
+    }
+}
+
+/// Reads a line in raw mode so that the typed characters are not shown.
+/// `None` when the user presses Esc or Ctrl+C.
+fn read_secret(prompt: &str) -> AppResult<Option<String>> {
+    print!("{}", prompt);
+    io::stdout().flush()?;
+    enable_raw_mode()?;
+    let result = read_hidden_line();
+    disable_raw_mode()?;
+    println!();
+    result
+}
+
+fn read_hidden_line() -> AppResult<Option<String>> {
+    let mut secret = String::new();
+    loop {
+        let Event::Key(key) = event::read()? else {
+            continue;
+        };
+        if key.kind != KeyEventKind::Press {
+            continue;
+        }
+        match key.code {
+            KeyCode::Enter => return Ok(Some(secret)),
+            KeyCode::Esc => return Ok(None),
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => return Ok(None),
+            KeyCode::Char(c) => secret.push(c),
+            KeyCode::Backspace => {
+                secret.pop();
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_config_file_holds_the_settings_answered_with_yes() {
+        let contents = config_file(&[true, false, true, false], "dark");
+        let config: Config = toml::from_str(&contents).unwrap();
+        assert!(config.disable_mouse);
+        assert!(!config.conventional_commits);
+        assert_eq!(config.auto_fetch_interval, Some(300));
+        assert!(!config.sync);
+        assert_eq!(config.theme, Theme::default());
+
+        let config: Config = toml::from_str(&config_file(&[true; 4], "light")).unwrap();
+        assert!(config.conventional_commits && config.sync);
+        assert_eq!(config.theme, Theme::preset("light").unwrap());
+    }
+}
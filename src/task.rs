@@ -0,0 +1,106 @@
+//! src/task.rs
+
+use crate::git::TransferStats;
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+
+/// Identifies a background task in `AppEvent::TaskProgress`, so that
+/// reports of a task that was replaced are ignored.
+pub type TaskId = u64;
+
+/// Asks a background task to stop. Network operations check the token in
+/// their progress callbacks and abort at the next one.
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// The long-running git operations started from the TUI.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TaskKind {
+    Push,
+    Fetch,
+    Pull,
+    Clone,
+}
+
+impl TaskKind {
+    pub fn name(self) -> &'static str {
+        match self {
+            TaskKind::Push => "Push",
+            TaskKind::Fetch => "Fetch",
+            TaskKind::Pull => "Pull",
+            TaskKind::Clone => "Clone",
+        }
+    }
+
+    /// Shown while the task runs, e.g. "Pushing".
+    pub fn verb(self) -> &'static str {
+        match self {
+            TaskKind::Push => "Pushing",
+            TaskKind::Fetch => "Fetching",
+            TaskKind::Pull => "Pulling",
+            TaskKind::Clone => "Cloning",
+        }
+    }
+}
+
+/// The running background task and the progress it last reported.
+#[derive(Debug, Clone)]
+pub struct Task {
+    pub id: TaskId,
+    pub kind: TaskKind,
+    /// 0 to 100.
+    pub pct: u16,
+    pub msg: String,
+    pub cancel: CancelToken,
+}
+
+impl Task {
+    pub fn new(id: TaskId, kind: TaskKind) -> Self {
+        Self {
+            id,
+            kind,
+            pct: 0,
+            msg: "Connecting...".to_string(),
+            cancel: CancelToken::new(),
+        }
+    }
+}
+
+/// Percentage and description of a fetch or clone transfer.
+pub fn transfer_progress(stats: &TransferStats) -> (u16, String) {
+    let pct = (stats.ratio().clamp(0.0, 1.0) * 100.0).round() as u16;
+    let msg = if stats.total_objects == 0 {
+        "Connecting...".to_string()
+    } else {
+        format!(
+            "Received {}/{} objects ({} indexed), {} KiB",
+            stats.received_objects,
+            stats.total_objects,
+            stats.indexed_objects,
+            stats.received_bytes / 1024
+        )
+    };
+    (pct, msg)
+}
+
+/// Percentage and description of a push upload.
+pub fn push_progress(current: usize, total: usize, bytes: usize) -> (u16, String) {
+    let pct = (current.min(total) * 100).checked_div(total).unwrap_or(0) as u16;
+    (pct, format!("Sent {}/{} objects, {} KiB", current, total, bytes / 1024))
+}
@@ -0,0 +1,315 @@
+//! src/testing.rs
+
+use crate::{
+    action::Action,
+    app::{App, AppReturn},
+    config::Config,
+    event::AppEvent,
+    git::GitRepo,
+    ui,
+};
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use git2::{build::CheckoutBuilder, IndexAddOption, Repository, RepositoryInitOptions, Signature, Time};
+use ratatui::{backend::TestBackend, Terminal};
+use std::{fs, path::Path};
+use tempfile::TempDir;
+use tokio::sync::mpsc;
+
+/// The branch fixtures start on, independent of `init.defaultBranch`.
+pub const FIXTURE_BRANCH: &str = "main";
+
+/// Builds a throwaway repository for tests. Commits get a fixed author and
+/// date so that rendered output does not change between runs. The directory
+/// is deleted when the fixture is dropped.
+pub struct RepoFixture {
+    dir: TempDir,
+    repo: Repository,
+}
+
+impl RepoFixture {
+    /// An empty repository on `FIXTURE_BRANCH`.
+    pub fn new() -> Self {
+        let dir = TempDir::new().expect("create temp dir");
+        let mut options = RepositoryInitOptions::new();
+        options.initial_head(FIXTURE_BRANCH);
+        let repo = Repository::init_opts(dir.path(), &options).expect("init repository");
+        let mut config = repo.config().expect("repository config");
+        config.set_str("user.name", "Test").unwrap();
+        config.set_str("user.email", "test@example.com").unwrap();
+        config.set_bool("commit.gpgsign", false).unwrap();
+        Self { dir, repo }
+    }
+
+    pub fn path(&self) -> &Path {
+        self.dir.path()
+    }
+
+    /// Opens the fixture the way the app does.
+    pub fn open(&self) -> GitRepo {
+        GitRepo::new(self.path()).expect("open fixture")
+    }
+
+    /// Writes `path` and commits it on the current branch.
+    pub fn committed(self, path: &str, contents: &str) -> Self {
+        self.write(path, contents);
+        self.commit_all(&format!("Add {}", path));
+        self
+    }
+
+    /// Writes `path` and stages it.
+    pub fn staged(self, path: &str, contents: &str) -> Self {
+        self.write(path, contents);
+        let mut index = self.repo.index().unwrap();
+        index.add_path(Path::new(path)).unwrap();
+        index.write().unwrap();
+        self
+    }
+
+    /// Writes `path` without staging it: a modification for tracked files,
+    /// an untracked file otherwise.
+    pub fn unstaged(self, path: &str, contents: &str) -> Self {
+        self.write(path, contents);
+        self
+    }
+
+    /// Leaves `path` conflicted: it is changed to `ours` on the current
+    /// branch and to `theirs` on another branch, which is then merged.
+    /// Needs at least one commit.
+    pub fn conflicted(self, path: &str, ours: &str, theirs: &str) -> Self {
+        let branch_ref = {
+            let head = self.repo.head().unwrap().peel_to_commit().unwrap();
+            let branch = self.repo.branch("theirs", &head, true).unwrap();
+            branch.get().name().unwrap().to_string()
+        };
+
+        self.repo.set_head(&branch_ref).unwrap();
+        self.write(path, theirs);
+        let theirs_commit = self.commit_all(&format!("Change {} on theirs", path));
+
+        let main_ref = format!("refs/heads/{}", FIXTURE_BRANCH);
+        self.repo.set_head(&main_ref).unwrap();
+        self.repo
+            .checkout_head(Some(CheckoutBuilder::new().force()))
+            .unwrap();
+        self.write(path, ours);
+        self.commit_all(&format!("Change {} on {}", path, FIXTURE_BRANCH));
+
+        {
+            let annotated = self.repo.find_annotated_commit(theirs_commit).unwrap();
+            self.repo.merge(&[&annotated], None, None).unwrap();
+        }
+        self
+    }
+
+    /// Adds `upstream` as `origin`, fetches it and starts the current branch
+    /// at its `FIXTURE_BRANCH`, which becomes the upstream. Needs an empty
+    /// repository.
+    pub fn tracking(self, upstream: &RepoFixture) -> Self {
+        self.repo.remote("origin", upstream.path().to_str().unwrap()).unwrap();
+        let fixture = self.fetched();
+        let tracking = format!("refs/remotes/origin/{}", FIXTURE_BRANCH);
+        let base = fixture.repo.refname_to_id(&tracking).unwrap();
+        let branch = format!("refs/heads/{}", FIXTURE_BRANCH);
+        fixture.repo.reference(&branch, base, false, "track upstream").unwrap();
+        fixture
+            .repo
+            .checkout_head(Some(CheckoutBuilder::new().force()))
+            .unwrap();
+        let mut config = fixture.repo.config().unwrap();
+        config.set_str(&format!("branch.{}.remote", FIXTURE_BRANCH), "origin").unwrap();
+        config.set_str(&format!("branch.{}.merge", FIXTURE_BRANCH), &branch).unwrap();
+        fixture
+    }
+
+    /// Fetches `origin` again, e.g. after committing to its fixture.
+    pub fn fetched(self) -> Self {
+        {
+            let mut remote = self.repo.find_remote("origin").unwrap();
+            remote.fetch(&[] as &[&str], None, None).unwrap();
+        }
+        self
+    }
+
+    fn write(&self, path: &str, contents: &str) {
+        let full = self.path().join(path);
+        if let Some(parent) = full.parent() {
+            fs::create_dir_all(parent).unwrap();
+        }
+        fs::write(full, contents).unwrap();
+    }
+
+    /// Stages everything and commits it on HEAD.
+    fn commit_all(&self, message: &str) -> git2::Oid {
+        let mut index = self.repo.index().unwrap();
+        index.add_all(["*"], IndexAddOption::DEFAULT, None).unwrap();
+        index.write().unwrap();
+        let tree = self.repo.find_tree(index.write_tree().unwrap()).unwrap();
+        let signature = Signature::new("Test", "test@example.com", &Time::new(0, 0)).unwrap();
+        let parent = self.repo.head().ok().and_then(|head| head.peel_to_commit().ok());
+        let parents: Vec<_> = parent.iter().collect();
+        self.repo
+            .commit(Some("HEAD"), &signature, &signature, message, &tree, &parents)
+            .unwrap()
+    }
+}
+
+impl Default for RepoFixture {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Runs an `App` on a fixture without a terminal: keys and actions go in,
+/// and frames are rendered to ratatui's `TestBackend`. After every action
+/// it waits for the git worker, so tests see the refreshed state.
+pub struct TestApp {
+    pub app: App,
+    events: mpsc::UnboundedReceiver<AppEvent>,
+    terminal: Terminal<TestBackend>,
+    fixture: RepoFixture,
+}
+
+impl TestApp {
+    /// An 80x24 app with the default config. Nothing is written to the
+    /// user's config file.
+    pub fn new(fixture: RepoFixture) -> Self {
+        Self::with_size(fixture, 80, 24)
+    }
+
+    pub fn with_size(fixture: RepoFixture, width: u16, height: u16) -> Self {
+        let (sender, events) = mpsc::unbounded_channel();
+        let app = App::new(fixture.open(), Config::default(), sender).expect("create app");
+        let terminal = Terminal::new(TestBackend::new(width, height)).expect("test terminal");
+        let mut test_app = Self {
+            app,
+            events,
+            terminal,
+            fixture,
+        };
+        test_app.settle();
+        test_app
+    }
+
+    /// Quits and starts a new app on the same repository, as launching
+    /// `dotatui` again would.
+    pub fn relaunch(self) -> Self {
+        self.app.save_session();
+        let (width, height) = {
+            let area = self.terminal.backend().buffer().area;
+            (area.width, area.height)
+        };
+        Self::with_size(self.fixture, width, height)
+    }
+
+    pub fn fixture(&self) -> &RepoFixture {
+        &self.fixture
+    }
+
+    /// Presses `key` as if it was typed in the terminal.
+    pub fn press(&mut self, key: KeyEvent) -> AppReturn {
+        match self.app.action_for_key(key) {
+            Some(action) => self.dispatch(action),
+            None => AppReturn::Continue,
+        }
+    }
+
+    /// Types every character of `text`.
+    pub fn type_text(&mut self, text: &str) {
+        for c in text.chars() {
+            self.press(key(KeyCode::Char(c)));
+        }
+    }
+
+    pub fn dispatch(&mut self, action: Action) -> AppReturn {
+        let result = self.app.dispatch(action).expect("dispatch action");
+        self.settle();
+        result
+    }
+
+    /// Dispatches the git worker's answers until nothing is loading.
+    fn settle(&mut self) {
+        while self.app.is_loading() || self.app.is_counting_churn() || self.app.is_searching_log()
+            || self.app.is_loading_whole_log()
+            || self.app.sync.is_committing()
+        {
+            let event = self.events.blocking_recv().expect("git worker stopped");
+            self.app.dispatch(Action::from(event)).expect("dispatch event");
+        }
+    }
+
+    /// Dispatches the events background tasks have sent so far.
+    pub fn process_events(&mut self) {
+        while let Ok(event) = self.events.try_recv() {
+            self.dispatch(Action::from(event));
+        }
+    }
+
+    /// Renders a frame and returns the screen as text, one line per row
+    /// with trailing spaces removed.
+    pub fn render(&mut self) -> String {
+        self.terminal
+            .draw(|frame| ui::render(frame, &mut self.app))
+            .expect("draw frame");
+        let buffer = self.terminal.backend().buffer();
+        let area = buffer.area;
+        let mut screen = String::new();
+        for y in 0..area.height {
+            let line: String = (0..area.width)
+                .map(|x| buffer.get(area.x + x, area.y + y).symbol())
+                .collect();
+            screen.push_str(line.trim_end());
+            screen.push('\n');
+        }
+        screen
+    }
+}
+
+/// A key press without modifiers. Upper-case letters get Shift, like the
+/// terminal reports them.
+pub fn key(code: KeyCode) -> KeyEvent {
+    match code {
+        KeyCode::Char(c) if c.is_ascii_uppercase() => KeyEvent::new(code, KeyModifiers::SHIFT),
+        _ => KeyEvent::new(code, KeyModifiers::NONE),
+    }
+}
+
+pub fn ctrl(c: char) -> KeyEvent {
+    KeyEvent::new(KeyCode::Char(c), KeyModifiers::CONTROL)
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::git::{GitRepo, UntrackedFiles};
+
+    #[test]
+    fn fixture_has_the_requested_changes() {
+        let fixture = RepoFixture::new()
+            .committed("a.txt", "one\n")
+            .committed("b.txt", "base\n")
+            .conflicted("b.txt", "ours\n", "theirs\n")
+            .staged("c.txt", "new\n")
+            .unstaged("a.txt", "two\n")
+            .unstaged("d.txt", "untracked\n");
+        let repo: GitRepo = fixture.open();
+        let mut status: Vec<(String, bool, bool)> = repo
+            .get_status(&UntrackedFiles::All)
+            .unwrap()
+            .into_iter()
+            .map(|item| (item.path, item.is_staged, item.status.is_conflicted()))
+            .collect();
+        status.sort();
+        assert_eq!(
+            status,
+            [
+                ("a.txt".to_string(), false, false),
+                ("b.txt".to_string(), false, true),
+                ("c.txt".to_string(), true, false),
+                ("d.txt".to_string(), false, false),
+            ]
+        );
+        assert_eq!(repo.current_branch().unwrap().as_deref(), Some(FIXTURE_BRANCH));
+    }
+}
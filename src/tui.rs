@@ -1,17 +1,27 @@
 //! src/tui.rs
 
-use crate::error::AppResult;
+use crate::error::{AppError, AppResult};
 use crossterm::{
     event::{DisableMouseCapture, EnableMouseCapture},
     execute,
     terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
 };
 use ratatui::{backend::CrosstermBackend, Terminal};
-use std::io::{self, Stdout};
+use std::{
+    env,
+    io::{self, Stdout, Write},
+    panic,
+    path::{Path, PathBuf},
+    process::{Command, ExitStatus, Stdio},
+    thread,
+};
 
 /// A wrapper around the `ratatui` Terminal.
 pub struct Tui {
     terminal: Terminal<CrosstermBackend<Stdout>>,
+    /// Whether the TUI takes the mouse; without it the terminal keeps it for
+    /// its own text selection.
+    mouse_capture: bool,
 }
 
 impl Tui {
@@ -19,20 +29,113 @@ impl Tui {
     pub fn new() -> AppResult<Self> {
         let backend = CrosstermBackend::new(io::stdout());
         let terminal = Terminal::new(backend)?;
-        Ok(Self { terminal })
+        Ok(Self { terminal, mouse_capture: true })
     }
 
-    /// Enters the alternate screen and raw mode.
+    /// Enters the alternate screen and raw mode, capturing the mouse unless
+    /// that was turned off.
     pub fn enter(&mut self) -> AppResult<()> {
         enable_raw_mode()?;
-        execute!(io::stdout(), EnterAlternateScreen, EnableMouseCapture)?;
+        execute!(io::stdout(), EnterAlternateScreen)?;
+        if self.mouse_capture {
+            execute!(io::stdout(), EnableMouseCapture)?;
+        }
+        Ok(())
+    }
+
+    /// Starts or stops capturing the mouse; meant to be called while the TUI
+    /// is entered. Does nothing when the capture is already as asked.
+    pub fn set_mouse_capture(&mut self, capture: bool) -> AppResult<()> {
+        if capture != self.mouse_capture {
+            self.mouse_capture = capture;
+            if capture {
+                execute!(io::stdout(), EnableMouseCapture)?;
+            } else {
+                execute!(io::stdout(), DisableMouseCapture)?;
+            }
+        }
         Ok(())
     }
 
     /// Exits the alternate screen and raw mode.
     pub fn exit(&mut self) -> AppResult<()> {
-        execute!(io::stdout(), LeaveAlternateScreen, DisableMouseCapture)?;
-        disable_raw_mode()?;
+        restore_terminal()?;
+        Ok(())
+    }
+
+    /// Makes a panic on the main thread restore the terminal before the
+    /// panic message is printed, so that the message is readable and the
+    /// shell usable, and point to the log at `log_path`, which records the
+    /// panic too. A panicking background task only ends that task, so it
+    /// leaves the TUI alone.
+    pub fn install_panic_hook(log_path: PathBuf) {
+        let default_hook = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            log::error!("{}", info);
+            if thread::current().name() != Some("main") {
+                return;
+            }
+            let _ = restore_terminal();
+            default_hook(info);
+            eprintln!("dotatui crashed; the log is at {}", log_path.display());
+        }));
+    }
+
+    /// Leaves the TUI, opens `path` in `$VISUAL`/`$EDITOR` (falling back to
+    /// `vi`, or Notepad on Windows) and restores the TUI once the editor
+    /// exits.
+    pub fn edit_file(&mut self, path: &Path) -> AppResult<()> {
+        let fallback = if cfg!(windows) { "notepad" } else { "vi" };
+        let editor = env::var("VISUAL")
+            .or_else(|_| env::var("EDITOR"))
+            .unwrap_or_else(|_| fallback.to_string());
+        // `$EDITOR` may carry arguments, e.g. `code --wait`.
+        let mut words = editor.split_whitespace();
+        let program = words.next().unwrap_or(fallback);
+        let mut command = Command::new(program);
+        command.args(words).arg(path);
+        match self.run_suspended(&mut command)? {
+            Ok(status) if status.success() => Ok(()),
+            Ok(status) => Err(AppError::Editor(format!("{} exited with {}", editor, status))),
+            Err(e) => Err(AppError::Editor(format!("{}: {}", editor, e))),
+        }
+    }
+
+    /// Leaves the TUI, runs an external diff or merge tool given as program
+    /// and arguments, and restores the TUI once it exits.
+    pub fn run_tool(&mut self, command: &[String]) -> AppResult<()> {
+        let Some((program, args)) = command.split_first() else {
+            return Err(AppError::Tool("the configured tool is empty".to_string()));
+        };
+        match self.run_suspended(Command::new(program).args(args))? {
+            Ok(status) if status.success() => Ok(()),
+            Ok(status) => Err(AppError::Tool(format!("{} exited with {}", program, status))),
+            Err(e) => Err(AppError::Tool(format!("{}: {}", program, e))),
+        }
+    }
+
+    /// Runs `command` on the terminal the TUI gives up meanwhile. The outer
+    /// result is the TUI's, the inner one the command's.
+    fn run_suspended(&mut self, command: &mut Command) -> AppResult<io::Result<ExitStatus>> {
+        self.exit()?;
+        let status = command.status();
+        self.enter()?;
+        self.terminal.clear()?;
+        Ok(status)
+    }
+
+    /// Puts `text` on the system clipboard through the platform's clipboard
+    /// command. Where none works, e.g. over SSH, the terminal is asked to
+    /// set it with an OSC 52 escape sequence, which most terminals support.
+    pub fn copy_to_clipboard(&mut self, text: &str) -> AppResult<()> {
+        for command in clipboard_commands() {
+            if pipe_to(command, text) {
+                return Ok(());
+            }
+        }
+        let mut stdout = io::stdout();
+        write!(stdout, "\x1b]52;c;{}\x07", base64(text.as_bytes()))?;
+        stdout.flush()?;
         Ok(())
     }
 
@@ -45,3 +148,83 @@ impl Tui {
         Ok(())
     }
 }
+
+/// Leaves the alternate screen, stops capturing the mouse and turns raw
+/// mode off. Doing so when the terminal is not in the TUI is harmless.
+fn restore_terminal() -> io::Result<()> {
+    execute!(io::stdout(), LeaveAlternateScreen, DisableMouseCapture)?;
+    disable_raw_mode()
+}
+
+/// The clipboard commands worth trying on this system, best first.
+fn clipboard_commands() -> Vec<&'static [&'static str]> {
+    let mut commands: Vec<&'static [&'static str]> = Vec::new();
+    if cfg!(target_os = "macos") {
+        commands.push(&["pbcopy"]);
+    } else if cfg!(windows) {
+        commands.push(&["clip"]);
+    } else {
+        if env::var_os("WAYLAND_DISPLAY").is_some() {
+            commands.push(&["wl-copy"]);
+        }
+        if env::var_os("DISPLAY").is_some() {
+            commands.push(&["xclip", "-selection", "clipboard"]);
+            commands.push(&["xsel", "--clipboard", "--input"]);
+        }
+    }
+    commands
+}
+
+/// Runs `command` with `text` on its stdin; whether it succeeded.
+fn pipe_to(command: &[&str], text: &str) -> bool {
+    let Some((program, args)) = command.split_first() else {
+        return false;
+    };
+    let child = Command::new(program)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn();
+    let Ok(mut child) = child else {
+        return false;
+    };
+    let written = child.stdin.take().is_some_and(|mut stdin| stdin.write_all(text.as_bytes()).is_ok());
+    child.wait().is_ok_and(|status| status.success()) && written
+}
+
+/// Standard base64 with padding, as OSC 52 expects.
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut encoded = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        // Three bytes make four 6-bit digits; a short chunk is padded.
+        let group = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |group, (i, &byte)| group | (byte as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                let digit = (group >> (18 - 6 * i)) & 0x3f;
+                encoded.push(ALPHABET[digit as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn base64_pads_partial_groups() {
+        assert_eq!(base64(b""), "");
+        assert_eq!(base64(b"f"), "Zg==");
+        assert_eq!(base64(b"fo"), "Zm8=");
+        assert_eq!(base64(b"foo"), "Zm9v");
+        assert_eq!(base64(b"dotfiles\n"), "ZG90ZmlsZXMK");
+    }
+}
@@ -1,12 +1,44 @@
 //! src/ui.rs
 
-use crate::app::{ActivePanel, App, Mode, Popup, StatusItemType, StatusMode};
-use crate::git::StatusItem;
+pub mod text;
+pub mod widgets;
+
+use crate::action::key_label;
+use crate::app::{
+    ActivePanel, App, BranchField, CloneField, LayoutRects, CommitField, DoctorView, ForgeView, LogColumn, Mode, PaletteEntry, Popup,
+    StatsView, StatusItemType, StatusMode, COMMIT_TYPES,
+};
+use crate::config::{Cipher, Theme};
+use crate::doctor::{Fix, Health};
+use crate::dotfiles::{apply::ApplyOutcome, LinkState, MANIFEST_FILE};
+use crate::forge::CiStatus;
+use crate::git::{
+    bisect::{BisectStep, Verdict},
+    diff::{line_ending_change, word_diff, WordSpan},
+    gitconfig::{self, ConfigScope, ValueKind},
+    worker::FileDiff,
+    Churn, CommitRef, DiffFile, DiffSummary, Hunk, StatusItem,
+};
+use crate::logging;
+use crate::notification::Severity;
+use crate::prompt::PromptState;
+use crate::task::Task;
+use text::{expand_tabs, fit, truncate, truncate_spans};
 use git2::Status;
 use ratatui::{
     prelude::*,
-    widgets::{Block, Borders, Cell, Clear, List, ListItem, Paragraph, Row, Table, Tabs, Wrap},
+    widgets::{
+        block::{Position, Title},
+        Bar, BarChart, BarGroup, Block, Borders, Cell, Clear, Gauge, List, ListItem, ListState,
+        Paragraph, Row, Scrollbar, ScrollbarOrientation, ScrollbarState, Sparkline, Table, TableState, Tabs, Wrap,
+    },
 };
+use chrono::Local;
+use std::ops::Range;
+use unicode_width::UnicodeWidthStr;
+
+/// The frames of the spinner shown while the status loads, one per tick.
+const SPINNER: [char; 10] = ['⠋', '⠙', '⠹', '⠸', '⠼', '⠴', '⠦', '⠧', '⠇', '⠏'];
 
 pub fn render(frame: &mut Frame, app: &mut App) {
     let main_layout = Layout::default()
@@ -14,25 +46,72 @@ pub fn render(frame: &mut Frame, app: &mut App) {
         .constraints([Constraint::Length(1), Constraint::Min(0), Constraint::Length(1)])
         .split(frame.size());
 
+    app.layout = LayoutRects {
+        main: main_layout[1],
+        ..LayoutRects::default()
+    };
     render_tabs(frame, app, main_layout[0]);
     render_footer(frame, app, main_layout[2]);
 
     match app.mode {
         Mode::Status(sub_mode) => render_status_view(frame, app, main_layout[1], sub_mode),
         Mode::Log => render_log_view(frame, app, main_layout[1]),
+        Mode::Stash => render_stash_view(frame, app, main_layout[1]),
+        Mode::Tree => render_tree_view(frame, app, main_layout[1]),
+        Mode::Reflog => render_reflog_view(frame, app, main_layout[1]),
+        Mode::Ignore => render_ignore_view(frame, app, main_layout[1]),
+        Mode::Messages => render_messages_view(frame, app, main_layout[1]),
+        Mode::Debug => render_debug_view(frame, app, main_layout[1]),
+        mode => {
+            app.with_panel(mode, |panel, app| panel.render(frame, app, main_layout[1]));
+        }
     }
 
-    if let Some(popup) = &app.popup {
-        render_popup(frame, popup, &app.commit_msg, app.cursor_pos);
+    // The divergence and help popups keep their scroll clamped, so they
+    // need the app mutably.
+    if app.popup == Some(Popup::Divergence) {
+        render_divergence(frame, app);
+    } else if app.popup == Some(Popup::Help) {
+        render_help(frame, app);
+    } else if let Some(popup) = &app.popup {
+        render_popup(frame, popup, app);
     }
+    render_toasts(frame, app, main_layout[1]);
 }
 
+/// The views in the order of their tabs, with the titles of those that are
+/// not panels; panels bring their own.
+const TABS: [(Mode, Option<&str>); 13] = [
+    (Mode::Status(StatusMode::FileSelection), Some("[S]tatus")),
+    (Mode::Log, Some("[L]og")),
+    (Mode::Stash, Some("S[t]ash")),
+    (Mode::Links, None),
+    (Mode::Tree, Some("T[r]ee")),
+    (Mode::Reflog, Some("[R]eflog")),
+    (Mode::Forge, None),
+    (Mode::Ignore, Some("Gitign[o]re")),
+    (Mode::Messages, Some("[M]essages")),
+    (Mode::Debug, Some("Debug")),
+    (Mode::Stats, None),
+    (Mode::Doctor, None),
+    (Mode::GitConfig, None),
+];
+
 fn render_tabs(frame: &mut Frame, app: &App, area: Rect) {
-    let titles = vec!["[S]tatus", "[L]og"];
-    let selected_index = match app.mode {
-        Mode::Status(_) => 0,
-        Mode::Log => 1,
-    };
+    let theme = app.config.theme;
+    let mut tabs: Vec<(Mode, &str)> = TABS
+        .iter()
+        .map(|&(mode, title)| (mode, title.or(app.panels.title(mode)).unwrap_or_default()))
+        .collect();
+    tabs.extend(app.panels.custom());
+    let selected_index = tabs
+        .iter()
+        .position(|(mode, _)| match (mode, app.mode) {
+            (Mode::Status(_), Mode::Status(_)) => true,
+            (mode, current) => *mode == current,
+        })
+        .unwrap_or(0);
+    let titles: Vec<&str> = tabs.into_iter().map(|(_, title)| title).collect();
     let tabs = Tabs::new(titles)
         .block(Block::default())
         .select(selected_index)
@@ -40,126 +119,1259 @@ fn render_tabs(frame: &mut Frame, app: &App, area: Rect) {
         .highlight_style(
             Style::default()
                 .add_modifier(Modifier::BOLD)
-                .bg(Color::DarkGray),
+                .bg(theme.selection),
         );
     frame.render_widget(tabs, area);
 }
 
 fn render_status_view(frame: &mut Frame, app: &mut App, area: Rect, sub_mode: StatusMode) {
+    let theme = app.config.theme;
     let chunks = Layout::default()
         .direction(Direction::Horizontal)
         .constraints([Constraint::Percentage(40), Constraint::Percentage(60)].as_ref())
         .split(area);
 
-    let files_border_style = if app.active_panel == ActivePanel::Files { Style::default().fg(Color::Cyan) } else { Style::default() };
-    let diff_border_style = if app.active_panel == ActivePanel::Diff { Style::default().fg(Color::Cyan) } else { Style::default() };
+    let files_border_style = if app.active_panel == ActivePanel::Files { Style::default().fg(theme.accent) } else { Style::default() };
+    let diff_border_style = if app.active_panel == ActivePanel::Diff { Style::default().fg(theme.accent) } else { Style::default() };
 
     let list_items: Vec<ListItem> = app.status_display_list.iter().map(|item_type| match item_type {
         StatusItemType::Header(header) => ListItem::new(header.clone()).style(Style::default().add_modifier(Modifier::BOLD)),
-        StatusItemType::Item(item) => status_to_list_item(item),
+        StatusItemType::Item(item) => {
+            // The marker column is only there while something is marked.
+            let marked = (app.marked_count() > 0).then(|| app.is_marked(item));
+            status_to_list_item(item, app.group_by_dir, app.churn_of(item), marked, app.is_secret(&item.path), &theme)
+        }
+        StatusItemType::Dir { path, count, collapsed } => {
+            let marker = if *collapsed { "▸ " } else { "▾ " };
+            ListItem::new(format!("{}{} ({})", marker, path, count)).style(Style::default().fg(Color::Blue))
+        }
     }).collect();
 
+    let mut files_title = if app.marked_count() > 0 {
+        format!("Files ({} marked, Esc to clear)", app.marked_count())
+    } else if app.status_filter.is_empty() {
+        "Files ('h' to focus)".to_string()
+    } else {
+        format!("Files matching '{}' (Esc to clear)", app.status_filter)
+    };
+    // A large work tree takes a while to read; what was read before stays
+    // until the worker answers.
+    if app.is_refreshing() {
+        files_title = format!("{} {}", SPINNER[app.spinner_frame % SPINNER.len()], files_title);
+    }
     let file_list = List::new(list_items)
-        .block(Block::default().borders(Borders::ALL).title("Files ('h' to focus)").border_style(files_border_style))
-        .highlight_style(Style::default().bg(Color::DarkGray))
+        .block(Block::default().borders(Borders::ALL).title(files_title).border_style(files_border_style))
+        .highlight_style(Style::default().bg(theme.selection))
         .highlight_symbol(">> ");
-    frame.render_stateful_widget(file_list, chunks[0], &mut app.status_list_state);
+    let files_chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Length(4), Constraint::Min(0)])
+        .split(chunks[0]);
+    let summary = &app.work_tree_summary;
+    let summary_lines = vec![summary_line("Staged", &summary.staged, &theme), summary_line("Unstaged", &summary.unstaged, &theme)];
+    frame.render_widget(
+        Paragraph::new(summary_lines).block(Block::default().borders(Borders::ALL).title("Changes")),
+        files_chunks[0],
+    );
+    frame.render_stateful_widget(file_list, files_chunks[1], &mut app.status_list_state);
+    app.layout.files = files_chunks[1];
+    app.layout.diff = chunks[1];
 
     let diff_title = match sub_mode {
         StatusMode::FileSelection => "Diff ('l' to focus, 'enter' to select hunks)",
-        StatusMode::HunkSelection => "Diff ('j'/'k' to select, 'space' to stage, 'q' to exit)",
+        StatusMode::HunkSelection => "Diff ('j'/'k' to select, 'space' to stage/unstage, 'V' to pick lines, 'q' to exit)",
+        StatusMode::LineSelection => "Lines ('j'/'k' to move, 'V' to start a range, 'space' to stage/unstage, 'esc' to go back)",
+        StatusMode::ConflictResolution => "Base ('e' to edit, 'space' to mark resolved, 'q' to exit)",
     };
 
     match sub_mode {
         StatusMode::FileSelection => {
-            let diff_text = if let Some(item) = app.get_selected_status_item() {
-                app.repo.get_diff_text(&item).unwrap_or_else(|_| "Error loading diff".to_string())
-            } else { "Select a file to see the diff.".to_string() };
-            let diff_lines: Vec<Line> = diff_text.lines().map(|line| {
-                let (style, line_content) = if line.starts_with('+') { (Style::default().fg(Color::Green), line) }
-                else if line.starts_with('-') { (Style::default().fg(Color::Red), line) }
-                else if line.starts_with("@@") { (Style::default().fg(Color::Cyan), line) }
-                else { (Style::default(), line) };
-                Line::styled(line_content.to_string(), style)
-            }).collect();
-            let diff_view = Paragraph::new(diff_lines)
-                .block(Block::default().borders(Borders::ALL).title(diff_title).border_style(diff_border_style));
-            frame.render_widget(diff_view, chunks[1]);
+            // The diff is loaded by the git worker; until it arrives for the
+            // selected file, say so instead of showing another file's diff.
+            let diff = match app.get_selected_status_item() {
+                Some(item) => app.diff.as_ref().filter(|diff| diff.item == item).ok_or("Loading diff..."),
+                None => Err("Select a file to see the diff."),
+            };
+            let diff_lines: Vec<Line> = match diff {
+                Ok(FileDiff { binary: Some(binary), .. }) => vec![Line::from(binary.summary())],
+                // Mode changes have no hunks; show git's summary instead.
+                Ok(diff) if diff.hunks.is_empty() => diff
+                    .text
+                    .lines()
+                    .map(|line| Line::from(line.to_string()))
+                    .collect(),
+                Ok(diff) => diff_lines(&diff.hunks, app.diff_line_limit, &theme),
+                Err(message) => vec![Line::from(message)],
+            };
+            let block = Block::default().borders(Borders::ALL).title(diff_title).border_style(diff_border_style);
+            let block = match diff {
+                Ok(diff) => line_ending_note(block, &diff.hunks),
+                Err(_) => block,
+            };
+            render_diff_panel(frame, diff_lines, block, chunks[1], &mut app.diff_scroll);
         }
-        StatusMode::HunkSelection => {
+        StatusMode::HunkSelection | StatusMode::LineSelection => {
             let mut hunk_list_items = Vec::new();
             let selected_hunk = app.hunk_list_state.selected();
+            let width = gutter_width(&app.current_hunks);
+            // The row of the line cursor, which the list keeps in view.
+            let mut cursor_row = None;
 
             for (i, hunk) in app.current_hunks.iter().enumerate() {
                 let is_selected = Some(i) == selected_hunk;
-                let bg_color = if is_selected { Color::DarkGray } else { Color::Reset };
+                let line_range = app
+                    .line_selection
+                    .filter(|_| is_selected && sub_mode == StatusMode::LineSelection);
+                let bg_color = if is_selected && line_range.is_none() { theme.selection } else { Color::Reset };
 
                 hunk_list_items.push(ListItem::new(ratatui::text::Line::from(vec![Span::styled(
                     hunk.header.trim_end(),
-                    Style::default().fg(Color::Cyan).bg(bg_color),
+                    Style::default().fg(theme.accent).bg(bg_color),
                 )])));
 
-                for line in &hunk.lines {
-                    let (prefix, style) = match line.origin {
-                        '+' => ("+", Style::default().fg(Color::Green).bg(bg_color)),
-                        '-' => ("-", Style::default().fg(Color::Red).bg(bg_color)),
-                        _ => (" ", Style::default().bg(bg_color)),
-                    };
-                    hunk_list_items.push(ListItem::new(ratatui::text::Line::from(vec![
-                        Span::styled(prefix, style),
-                        Span::styled(line.content.trim_end(), style),
-                    ])));
+                let mut lines = hunk_lines(hunk, width, Style::default().bg(bg_color), &theme);
+                if let Some(range) = line_range {
+                    cursor_row = Some(hunk_list_items.len() + range.cursor);
+                    for (index, line) in lines.iter_mut().enumerate() {
+                        let bg = match index {
+                            _ if index == range.cursor => Color::Blue,
+                            _ if range.lines().contains(&index) => theme.selection,
+                            _ => continue,
+                        };
+                        for span in &mut line.spans {
+                            span.style = span.style.bg(bg);
+                        }
+                    }
                 }
+                hunk_list_items.extend(lines.into_iter().map(ListItem::new));
             }
 
             let hunk_list = List::new(hunk_list_items)
                 .block(Block::default().borders(Borders::ALL).title(diff_title).border_style(diff_border_style));
 
-            // We don't need a stateful widget here because we are manually applying the background color.
-            // A stateful list would try to draw its own highlight over ours.
-            frame.render_widget(hunk_list, chunks[1]);
+            // The list has no highlight style of its own, since the colours
+            // are applied above; the state only scrolls the cursor into view.
+            let mut state = ListState::default().with_selected(cursor_row);
+            frame.render_stateful_widget(hunk_list, chunks[1], &mut state);
+        }
+        StatusMode::ConflictResolution => {
+            let Some(conflict) = &app.conflict else { return };
+            let columns = Layout::default()
+                .direction(Direction::Horizontal)
+                .constraints([Constraint::Ratio(1, 3), Constraint::Ratio(1, 3), Constraint::Ratio(1, 3)])
+                .split(chunks[1]);
+            let sides = [
+                (diff_title, &conflict.base),
+                ("Ours ('o' to take)", &conflict.ours),
+                ("Theirs ('T' to take)", &conflict.theirs),
+            ];
+            for ((title, contents), column) in sides.into_iter().zip(columns.iter()) {
+                let text = match contents {
+                    Some(contents) => Text::from(expand_tabs(contents)),
+                    None => Text::styled("(deleted)", Style::default().fg(Color::DarkGray)),
+                };
+                let side = Paragraph::new(text)
+                    .block(Block::default().borders(Borders::ALL).title(title).border_style(diff_border_style))
+                    .scroll((app.diff_scroll, 0));
+                frame.render_widget(side, *column);
+            }
+        }
+    }
+}
+
+/// Each hunk's header followed by its lines, cut off after `limit` lines
+/// with a note on how many are left. The hunk jump keys rely on this
+/// layout.
+fn diff_lines<'a>(hunks: &'a [Hunk], limit: usize, theme: &Theme) -> Vec<Line<'a>> {
+    let width = gutter_width(hunks);
+    let length = |hunks: &[Hunk]| hunks.iter().map(|hunk| hunk.lines.len() + 1).sum::<usize>();
+    let mut lines = Vec::new();
+    for (i, hunk) in hunks.iter().enumerate() {
+        let room = limit.saturating_sub(lines.len() + 1);
+        if room == 0 {
+            lines.push(more_lines(length(&hunks[i..])));
+            break;
+        }
+        lines.push(Line::from(vec![
+            Span::raw(" ".repeat(width * 2 + 3)),
+            Span::styled(hunk.header.trim_end().to_string(), Style::default().fg(theme.accent)),
+        ]));
+        if hunk.lines.len() <= room {
+            lines.extend(hunk_lines(hunk, width, Style::default(), theme));
+            continue;
         }
+        // Only the shown part is rendered, which is what keeps huge diffs
+        // responsive.
+        let shown = Hunk { header: hunk.header.clone(), lines: hunk.lines[..room].to_vec() };
+        lines.extend(hunk_lines(&shown, width, Style::default(), theme));
+        lines.push(more_lines(hunk.lines.len() - room + length(&hunks[i + 1..])));
+        break;
+    }
+    lines
+}
+
+/// Says at the bottom of a diff panel when the hunks only change line
+/// endings, which otherwise looks like every line was rewritten.
+fn line_ending_note<'a>(block: Block<'a>, hunks: &[Hunk]) -> Block<'a> {
+    match line_ending_change(hunks) {
+        Some((old, new)) => block.title(
+            Title::from(Span::styled(
+                format!(" only line endings differ: {} → {} ", old, new),
+                Style::default().fg(Color::Yellow),
+            ))
+            .position(Position::Bottom),
+        ),
+        None => block,
     }
 }
 
+/// The note below a diff that was cut off.
+fn more_lines<'a>(hidden: usize) -> Line<'a> {
+    Line::styled(
+        format!("… {} more lines ('+' to load more)", hidden),
+        Style::default().fg(Color::DarkGray),
+    )
+}
+
+/// Draws `lines` with a scrollbar, scrolled by `scroll`, which is clamped so
+/// that scrolling stops at the end of the diff.
+fn render_diff_panel(frame: &mut Frame, lines: Vec<Line>, block: Block, area: Rect, scroll: &mut u16) {
+    let visible_height = area.height.saturating_sub(2) as usize;
+    let max_scroll = lines.len().saturating_sub(visible_height);
+    *scroll = (*scroll).min(max_scroll as u16);
+
+    let mut scrollbar_state = ScrollbarState::new(max_scroll).position(*scroll as usize);
+    frame.render_widget(Paragraph::new(lines).block(block).scroll((*scroll, 0)), area);
+    frame.render_stateful_widget(
+        Scrollbar::new(ScrollbarOrientation::VerticalRight),
+        area.inner(&Margin { vertical: 1, horizontal: 0 }),
+        &mut scrollbar_state,
+    );
+}
+
+/// The files and diff of `App::compare`, laid out like the status view.
+fn render_compare_view(frame: &mut Frame, app: &mut App, area: Rect) {
+    let theme = app.config.theme;
+    let Some(compare) = &mut app.compare else {
+        return;
+    };
+    let chunks = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Percentage(40), Constraint::Percentage(60)])
+        .split(area);
+    let border_style = |panel| match app.active_panel == panel {
+        true => Style::default().fg(theme.accent),
+        false => Style::default(),
+    };
+
+    let items: Vec<ListItem> = compare
+        .files
+        .iter()
+        .map(|file| {
+            let color = match file.status {
+                'A' => Color::Green,
+                'D' => Color::Red,
+                'R' | 'C' => Color::Cyan,
+                _ => Color::Yellow,
+            };
+            ListItem::new(Line::from(vec![
+                Span::styled(format!("{} ", file.status), Style::default().fg(color).bold()),
+                Span::styled(file.path.as_str(), Style::default().fg(color)),
+            ]))
+        })
+        .collect();
+    let title = format!("{}..{} - {} files (Esc to close)", compare.from, compare.to, compare.files.len());
+    let list = List::new(items)
+        .block(Block::default().borders(Borders::ALL).title(title).border_style(border_style(ActivePanel::Files)))
+        .highlight_style(Style::default().bg(theme.selection))
+        .highlight_symbol(">> ");
+    frame.render_stateful_widget(list, chunks[0], &mut compare.list_state);
+    app.layout.files = chunks[0];
+    app.layout.diff = chunks[1];
+
+    let lines = match compare.selected_file() {
+        Some(DiffFile { binary: Some(binary), .. }) => vec![Line::from(binary.summary())],
+        Some(file) if file.hunks.is_empty() => vec![Line::from("Only the mode or name changed.")],
+        Some(file) => diff_lines(&file.hunks, app.diff_line_limit, &theme),
+        None => vec![Line::from("No differences.")],
+    };
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .title("Diff ('l' to focus, '{'/'}' to jump between hunks)")
+        .border_style(border_style(ActivePanel::Diff));
+    let block = match compare.selected_file() {
+        Some(file) => line_ending_note(block, &file.hunks),
+        None => block,
+    };
+    render_diff_panel(frame, lines, block, chunks[1], &mut app.diff_scroll);
+}
+
 fn render_log_view(frame: &mut Frame, app: &mut App, area: Rect) {
-    let header_cells = ["Commit", "Author", "Date"]
+    let theme = app.config.theme;
+    if app.compare.is_some() {
+        render_compare_view(frame, app, area);
+        return;
+    }
+    // The sortable columns, in `LogColumn::ALL` order, are at these indices.
+    const SORTABLE_COLUMNS: [usize; 3] = [1, 3, 4];
+    let mut headers = ["Graph", "Commit", "Message", "Author", "Date"].map(String::from);
+    if let Some(sort) = app.log_sort {
+        let i = SORTABLE_COLUMNS[LogColumn::ALL.iter().position(|&c| c == sort.column).unwrap_or(0)];
+        headers[i].push_str(if sort.descending { " ▼" } else { " ▲" });
+    }
+    let header_cells = headers
+        .into_iter()
+        .map(|h| Cell::from(h).style(Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD)));
+    let header = Row::new(header_cells).height(1).bottom_margin(1);
+    let query = app.log_search.as_ref().map(|search| search.query.to_lowercase());
+    // The graph only makes sense in history order.
+    let graph_width = match app.log_sort {
+        None => app.log_graph_width,
+        Some(_) => 0,
+    };
+    let title = match &app.log_search {
+        Some(search) if app.is_searching_log() => format!("Log - searching for '{}'...", search.query),
+        Some(search) => format!(
+            "Log - {} commits matching '{}' (n/N to jump, Esc to clear)",
+            search.matches.len(),
+            search.query
+        ),
+        None => match &app.log_path {
+            Some(path) => format!("History of {} - {} commits (Esc for the full log)", path, app.log_entries.len()),
+            None if app.is_loading_whole_log() => "Log - loading the rest of the history...".to_string(),
+            None => "Log".to_string(),
+        },
+    };
+    let title = match app.log_mark {
+        Some(mark) => format!(
+            "{} - marked {:.7} ('d' to compare with the selected commit)",
+            title, mark
+        ),
+        None => title,
+    };
+    let title = match app.bisect.as_ref().map(|bisect| &bisect.step) {
+        Some(BisectStep::Test { commit, .. }) => format!(
+            "{} - bisecting, testing {:.7} ('{}' to mark it)",
+            title,
+            commit.to_string(),
+            key_label(app.keys.log.bisect)
+        ),
+        Some(BisectStep::Incomplete) => format!("{} - bisecting ('{}' to mark a commit)", title, key_label(app.keys.log.bisect)),
+        _ => title,
+    };
+    let widths = [
+        Constraint::Length(graph_width.max(5) as u16),
+        Constraint::Length(9),
+        Constraint::Min(30),
+        Constraint::Length(15),
+        Constraint::Length(20),
+    ];
+    let highlight_symbol = ">> ";
+    let block = Block::default().borders(Borders::ALL).title(title);
+    if app.log_entries.is_empty() && app.branch_status.unborn {
+        let hint = format!(
+            "No commits yet. Stage files in the status view with '{}' and press '{}' to make the first commit.",
+            key_label(app.keys.status.stage_item),
+            key_label(app.keys.status.commit)
+        );
+        let paragraph = Paragraph::new(hint).block(block).wrap(Wrap { trim: true });
+        frame.render_widget(paragraph, area);
+        return;
+    }
+
+    // Lay the columns out the way the table does, so that clicks on the
+    // header hit the right column.
+    let inner = block.inner(area);
+    let selection_width = match app.log_table_state.selected() {
+        Some(_) => highlight_symbol.width() as u16,
+        None => 0,
+    };
+    let [_, columns_area] =
+        Layout::horizontal([Constraint::Length(selection_width), Constraint::Fill(0)]).areas(inner);
+    let columns = Layout::horizontal(widths).spacing(1).split(columns_area);
+    for (rect, i) in app.layout.log_headers.iter_mut().zip(SORTABLE_COLUMNS) {
+        *rect = Rect { height: inner.height.min(1), ..columns[i] };
+    }
+    // Below the header and its bottom margin.
+    app.layout.log_rows = Rect {
+        y: inner.y + 2.min(inner.height),
+        height: inner.height.saturating_sub(2),
+        ..inner
+    };
+
+    // Only the rows on screen are built; the history may be long.
+    let rows = app.log_row_count();
+    let window = visible_window(&mut app.log_table_state, rows, app.layout.log_rows.height as usize);
+    let mut state = TableState::default().with_selected(app.log_table_state.selected().map(|row| row - window.start));
+    let order: Vec<usize> = window.filter_map(|row| app.log_entry_index(row)).collect();
+    let rows = order.into_iter().map(|index| {
+        let commit = &app.log_entries[index];
+        let graph = match app.log_sort {
+            None => app.log_graph.get(index).map_or("", String::as_str),
+            Some(_) => "",
+        };
+        Row::new(vec![
+            Cell::from(graph).style(Style::default().fg(Color::Magenta)),
+            Cell::from(Line::from(vec![
+                Span::raw(commit.id.as_str()),
+                Span::styled(if commit.signed { " ✓" } else { "" }, Style::default().fg(Color::Green)),
+            ])),
+            Cell::from({
+                let mut line = highlight_matches(&commit.message, query.as_deref());
+                if app.log_mark == Some(commit.oid) {
+                    line.spans.insert(0, Span::styled("◆ ", Style::default().fg(Color::Cyan)));
+                }
+                match app.bisect.as_ref().and_then(|bisect| bisect.marks.verdict(commit.oid)) {
+                    Some(Verdict::Good) => line.spans.insert(0, Span::styled("good ", Style::default().fg(Color::Green))),
+                    Some(Verdict::Bad) => line.spans.insert(0, Span::styled("bad ", Style::default().fg(Color::Red))),
+                    Some(Verdict::Skip) => line.spans.insert(0, Span::styled("skip ", Style::default().fg(Color::DarkGray))),
+                    None => {}
+                }
+                if commit.shallow {
+                    line.spans.push(Span::styled(" (shallow clone: older history not fetched)", Style::default().fg(Color::DarkGray)));
+                }
+                line
+            }),
+            Cell::from(highlight_matches(&commit.author, query.as_deref())),
+            Cell::from(commit.time.as_str()),
+        ])
+    });
+    let table = Table::new(rows, widths)
+        .header(header)
+        .block(block)
+        .highlight_style(Style::default().bg(theme.selection))
+        .highlight_symbol(highlight_symbol);
+    frame.render_stateful_widget(table, area, &mut state);
+}
+
+/// The rows of a table `height` rows high with rows of one line that are on
+/// screen, scrolling `state` the way `Table` does to keep the selection in
+/// view.
+fn visible_window(state: &mut TableState, len: usize, height: usize) -> Range<usize> {
+    let height = height.max(1);
+    let mut offset = state.offset().min(len.saturating_sub(1));
+    if let Some(selected) = state.selected() {
+        if selected < offset {
+            offset = selected;
+        } else if selected >= offset + height {
+            offset = selected + 1 - height;
+        }
+    }
+    *state.offset_mut() = offset;
+    offset..len.min(offset + height)
+}
+
+/// Splits `text` into spans, highlighting every case-insensitive occurrence
+/// of the already lowercased `query`.
+fn highlight_matches<'a>(text: &'a str, query: Option<&str>) -> Line<'a> {
+    let lower = text.to_lowercase();
+    // Lowercasing can change byte lengths; skip highlighting in that case
+    // rather than slicing at the wrong offsets.
+    let query = match query {
+        Some(query) if !query.is_empty() && lower.len() == text.len() => query,
+        _ => return Line::from(text),
+    };
+    let style = Style::default().fg(Color::Black).bg(Color::Yellow);
+    let mut spans = Vec::new();
+    let mut last = 0;
+    for (start, matched) in lower.match_indices(query) {
+        spans.push(Span::raw(&text[last..start]));
+        spans.push(Span::styled(&text[start..start + matched.len()], style));
+        last = start + matched.len();
+    }
+    spans.push(Span::raw(&text[last..]));
+    Line::from(spans)
+}
+
+/// Pairs up the lines of each block of removed lines (`-`) that is directly
+/// followed by added lines (`+`), in order, as modifications of each other.
+fn modified_line_pairs(origins: &[char]) -> Vec<(usize, usize)> {
+    let mut pairs = Vec::new();
+    let mut i = 0;
+    while i < origins.len() {
+        let removed = origins[i..].iter().take_while(|&&c| c == '-').count();
+        if removed == 0 {
+            i += 1;
+            continue;
+        }
+        let added_start = i + removed;
+        let added = origins[added_start..].iter().take_while(|&&c| c == '+').count();
+        pairs.extend((0..removed.min(added)).map(|k| (i + k, added_start + k)));
+        i = added_start + added;
+    }
+    pairs
+}
+
+/// Number of digits needed for the largest line number in `hunks`.
+fn gutter_width(hunks: &[Hunk]) -> usize {
+    hunks
+        .iter()
+        .flat_map(|hunk| &hunk.lines)
+        .flat_map(|line| [line.old_lineno, line.new_lineno])
+        .flatten()
+        .max()
+        .unwrap_or(0)
+        .to_string()
+        .len()
+}
+
+/// Renders the lines of a hunk behind a gutter with their old and new line
+/// numbers, word-diffing modified line pairs.
+fn hunk_lines<'a>(hunk: &Hunk, width: usize, base: Style, theme: &Theme) -> Vec<Line<'a>> {
+    let mut lines: Vec<Line> = hunk.lines.iter().map(|line| {
+        let (prefix, style) = match line.origin {
+            '+' => ("+", base.fg(theme.added)),
+            '-' => ("-", base.fg(theme.removed)),
+            _ => (" ", base),
+        };
+        Line::from(vec![
+            Span::styled(prefix, style),
+            Span::styled(expand_tabs(line.content.trim_end()), style),
+        ])
+    }).collect();
+    let origins: Vec<char> = hunk.lines.iter().map(|line| line.origin).collect();
+    for (removed, added) in modified_line_pairs(&origins) {
+        let (old, new) = word_diff_lines(
+            &expand_tabs(hunk.lines[removed].content.trim_end()),
+            &expand_tabs(hunk.lines[added].content.trim_end()),
+            base,
+            theme,
+        );
+        lines[removed] = old;
+        lines[added] = new;
+    }
+    for (line, diff_line) in lines.iter_mut().zip(&hunk.lines) {
+        let number = |n: Option<u32>| n.map_or_else(|| " ".repeat(width), |n| format!("{:>width$}", n));
+        let gutter = format!(
+            "{} {} │",
+            number(diff_line.old_lineno),
+            number(diff_line.new_lineno)
+        );
+        line.spans.insert(0, Span::styled(gutter, base.fg(Color::DarkGray)));
+    }
+    lines
+}
+
+/// Renders a modified line pair, reversing the colours of the words that
+/// differ between `old` and `new`.
+fn word_diff_lines<'a>(old: &str, new: &str, base: Style, theme: &Theme) -> (Line<'a>, Line<'a>) {
+    let (old_spans, new_spans) = word_diff(old, new);
+    let render = |prefix: &'static str, spans: Vec<WordSpan>, color: Color| {
+        let style = base.fg(color);
+        let mut line = vec![Span::styled(prefix, style)];
+        line.extend(spans.into_iter().map(|span| {
+            let style = if span.changed { style.add_modifier(Modifier::REVERSED) } else { style };
+            Span::styled(span.text, style)
+        }));
+        Line::from(line)
+    };
+    (render("-", old_spans, theme.removed), render("+", new_spans, theme.added))
+}
+
+fn render_tree_view(frame: &mut Frame, app: &mut App, area: Rect) {
+    let theme = app.config.theme;
+    let chunks = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Percentage(40), Constraint::Percentage(60)])
+        .split(area);
+    let tree_border_style = if app.active_panel == ActivePanel::Files { Style::default().fg(theme.accent) } else { Style::default() };
+    let preview_border_style = if app.active_panel == ActivePanel::Diff { Style::default().fg(theme.accent) } else { Style::default() };
+    app.layout.files = chunks[0];
+    app.layout.diff = chunks[1];
+
+    let items: Vec<ListItem> = app
+        .file_tree
+        .rows()
+        .iter()
+        .map(|row| {
+            let indent = "  ".repeat(row.depth);
+            if row.is_dir {
+                let marker = if row.expanded { "▾ " } else { "▸ " };
+                ListItem::new(format!("{}{}{}/", indent, marker, row.name))
+                    .style(Style::default().fg(Color::Blue).add_modifier(Modifier::BOLD))
+            } else if row.tracked {
+                ListItem::new(format!("{}  {}", indent, row.name))
+            } else {
+                ListItem::new(format!("{}  {} (new)", indent, row.name)).style(Style::default().fg(Color::Green))
+            }
+        })
+        .collect();
+    let tree = List::new(items)
+        .block(Block::default().borders(Borders::ALL).title("Files ('enter' to expand)").border_style(tree_border_style))
+        .highlight_style(Style::default().bg(theme.selection))
+        .highlight_symbol(">> ");
+    frame.render_stateful_widget(tree, chunks[0], &mut app.file_tree.state);
+
+    let selected = app.file_tree.selected().filter(|row| !row.is_dir).cloned();
+    let (title, lines): (String, Vec<Line>) = match selected {
+        None => ("Preview".to_string(), vec![Line::from("Select a file to preview it.")]),
+        Some(row) => match &app.tree_blame {
+            Some((path, blame)) if *path == row.path => (
+                format!("Blame: {} ('b' to hide)", row.path),
+                blame
+                    .iter()
+                    .map(|line| {
+                        Line::from(vec![
+                            Span::styled(format!("{:<8}", line.id), Style::default().fg(Color::Yellow)),
+                            Span::styled(format!("{} ", fit(&line.author, 15)), Style::default().fg(Color::Cyan)),
+                            Span::raw(expand_tabs(&line.content)),
+                        ])
+                    })
+                    .collect(),
+            ),
+            _ => (
+                format!("{} ('b' blame, 'L' log, 'D' diff)", row.path),
+                app.tree_preview(&row.path)
+                    .lines()
+                    .map(|line| Line::from(expand_tabs(line)))
+                    .collect(),
+            ),
+        },
+    };
+    let visible_height = chunks[1].height.saturating_sub(2) as usize;
+    let max_scroll = lines.len().saturating_sub(visible_height);
+    app.diff_scroll = app.diff_scroll.min(max_scroll as u16);
+    let preview = Paragraph::new(lines)
+        .block(Block::default().borders(Borders::ALL).title(title).border_style(preview_border_style))
+        .scroll((app.diff_scroll, 0));
+    frame.render_widget(preview, chunks[1]);
+}
+
+fn render_stash_view(frame: &mut Frame, app: &mut App, area: Rect) {
+    let theme = app.config.theme;
+    let header_cells = ["Stash", "Message", "Date"]
         .iter()
         .map(|h| Cell::from(*h).style(Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD)));
     let header = Row::new(header_cells).height(1).bottom_margin(1);
-    let rows = app.log_entries.iter().map(|commit| {
+    let rows = app.stash_entries.iter().map(|stash| {
         Row::new(vec![
-            Cell::from(commit.id.clone()),
-            Cell::from(commit.author.clone()),
-            Cell::from(commit.time.clone()),
+            Cell::from(format!("stash@{{{}}}", stash.index)),
+            Cell::from(stash.message.clone()),
+            Cell::from(stash.time.clone()),
         ])
     });
     let table = Table::new(
         rows,
         [
+            Constraint::Length(10),
+            Constraint::Min(30),
+            Constraint::Length(20),
+        ],
+    )
+    .header(header)
+    .block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title("Stash ('space' apply, 'p' pop, 'd' drop)"),
+    )
+    .highlight_style(Style::default().bg(theme.selection))
+    .highlight_symbol(">> ");
+    frame.render_stateful_widget(table, area, &mut app.stash_table_state);
+}
+
+fn render_reflog_view(frame: &mut Frame, app: &mut App, area: Rect) {
+    let theme = app.config.theme;
+    let header_cells = ["Entry", "Old", "New", "Message", "Date"]
+        .iter()
+        .map(|h| Cell::from(*h).style(Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD)));
+    let header = Row::new(header_cells).height(1).bottom_margin(1);
+    let rows = app.reflog.entries.iter().map(|entry| {
+        Row::new(vec![
+            Cell::from(format!("HEAD@{{{}}}", entry.index)),
+            Cell::from(format!("{:.7}", entry.old_oid.to_string())).style(Style::default().fg(Color::DarkGray)),
+            Cell::from(format!("{:.7}", entry.new_oid.to_string())).style(Style::default().fg(Color::Yellow)),
+            Cell::from(entry.message.clone()),
+            Cell::from(entry.time.clone()),
+        ])
+    });
+    let table = Table::new(
+        rows,
+        [
+            Constraint::Length(10),
             Constraint::Length(8),
-            Constraint::Length(15),
+            Constraint::Length(8),
+            Constraint::Min(30),
+            Constraint::Length(20),
+        ],
+    )
+    .header(header)
+    .block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title("Reflog ('c' checkout, 'x' reset --hard)"),
+    )
+    .highlight_style(Style::default().bg(theme.selection))
+    .highlight_symbol(">> ");
+    frame.render_stateful_widget(table, area, &mut app.reflog.table_state);
+}
+
+fn render_messages_view(frame: &mut Frame, app: &mut App, area: Rect) {
+    let theme = app.config.theme;
+    let items: Vec<ListItem> = app
+        .notifications
+        .history()
+        .map(|notification| {
+            ListItem::new(Line::from(vec![
+                Span::styled(
+                    notification.time.format("%H:%M:%S ").to_string(),
+                    Style::default().fg(Color::DarkGray),
+                ),
+                Span::styled(
+                    format!("{:<6}", notification.severity.label()),
+                    Style::default().fg(severity_color(notification.severity)),
+                ),
+                Span::raw(notification.message.clone()),
+            ]))
+        })
+        .collect();
+    let list = List::new(items)
+        .block(Block::default().borders(Borders::ALL).title("Messages (newest first)"))
+        .highlight_style(Style::default().bg(theme.selection))
+        .highlight_symbol(">> ");
+    frame.render_stateful_widget(list, area, &mut app.messages_list_state);
+}
+
+/// The newest log records, followed as they arrive unless scrolled up.
+fn render_debug_view(frame: &mut Frame, app: &mut App, area: Rect) {
+    let height = area.height.saturating_sub(2) as usize;
+    let lines: Vec<Line> = logging::with_recent(|recent| {
+        let max_scroll = recent.len().saturating_sub(height);
+        app.debug_scroll = app.debug_scroll.min(max_scroll as u16);
+        let end = recent.len() - app.debug_scroll as usize;
+        recent
+            .range(end.saturating_sub(height)..end)
+            .map(|line| {
+                Line::from(vec![
+                    Span::styled(line.time.format("%H:%M:%S ").to_string(), Style::default().fg(Color::DarkGray)),
+                    Span::styled(format!("{:<6}", line.level), Style::default().fg(level_color(line.level))),
+                    Span::styled(format!("{}: ", line.target), Style::default().fg(Color::DarkGray)),
+                    Span::raw(line.message.clone()),
+                ])
+            })
+            .collect()
+    });
+    let title = if app.debug_scroll == 0 {
+        "Log (following; 'k' to scroll up)".to_string()
+    } else {
+        format!("Log ({} lines up; 'G' to follow)", app.debug_scroll)
+    };
+    let paragraph = Paragraph::new(lines).block(Block::default().borders(Borders::ALL).title(title));
+    frame.render_widget(paragraph, area);
+}
+
+/// Commits per month as a sparkline over the summary, with the most changed
+/// files and the authors as bar charts below.
+pub(crate) fn render_stats_view(frame: &mut Frame, app: &App, area: Rect) {
+    let theme = app.config.theme;
+    let block = Block::default().borders(Borders::ALL).title("Stats ('S' to recompute)");
+    let stats = match &app.stats_view {
+        StatsView::Loaded(stats) => stats,
+        StatsView::Loading => {
+            frame.render_widget(Paragraph::new("Reading the history...").block(block), area);
+            return;
+        }
+        StatsView::Failed(error) => {
+            let text = format!("Could not compute the statistics: {}", error);
+            frame.render_widget(Paragraph::new(text).block(block).wrap(Wrap { trim: true }), area);
+            return;
+        }
+    };
+    let inner = block.inner(area);
+    frame.render_widget(block, area);
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Length(1), Constraint::Length(6), Constraint::Min(0)])
+        .split(inner);
+
+    frame.render_widget(
+        Paragraph::new(Line::from(vec![
+            Span::raw(format!("Commits: {}  Authors: {}  Lines: ", stats.commits, stats.authors.len())),
+            Span::styled(format!("+{}", stats.additions), Style::default().fg(theme.added)),
+            Span::raw(" / "),
+            Span::styled(format!("-{}", stats.deletions), Style::default().fg(theme.removed)),
+        ])),
+        chunks[0],
+    );
+
+    // The newest months, as many as fit; one column each.
+    let months = &stats.commits_per_month;
+    let shown = &months[months.len().saturating_sub(chunks[1].width as usize)..];
+    let title = match (shown.first(), shown.last()) {
+        (Some((first, _)), Some((last, _))) => format!(" Commits per month, {} to {} ", first, last),
+        _ => " Commits per month ".to_string(),
+    };
+    let counts: Vec<u64> = shown.iter().map(|(_, count)| *count).collect();
+    frame.render_widget(
+        Sparkline::default()
+            .block(Block::default().borders(Borders::TOP).title(title))
+            .data(&counts)
+            .style(Style::default().fg(Color::Cyan)),
+        chunks[1],
+    );
+
+    let columns = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Percentage(60), Constraint::Percentage(40)])
+        .split(chunks[2]);
+    render_count_bars(frame, " Most changed files ", &stats.top_files, Color::Yellow, columns[0]);
+    render_count_bars(frame, " Authors ", &stats.authors, Color::Magenta, columns[1]);
+}
+
+/// Horizontal bars for `counts`, labelled with their names cut to half the
+/// width so that the bars keep some room.
+fn render_count_bars(frame: &mut Frame, title: &str, counts: &[(String, usize)], color: Color, area: Rect) {
+    let label_width = (area.width / 2) as usize;
+    let bars: Vec<Bar> = counts
+        .iter()
+        .map(|(name, count)| {
+            Bar::default()
+                .label(Line::raw(truncate(name, label_width)))
+                .value(*count as u64)
+        })
+        .collect();
+    let chart = BarChart::default()
+        .block(Block::default().borders(Borders::TOP).title(title))
+        .direction(Direction::Horizontal)
+        .bar_width(1)
+        .bar_gap(0)
+        .bar_style(Style::default().fg(color))
+        .value_style(Style::default().fg(Color::Black).bg(color))
+        .data(BarGroup::default().bars(&bars));
+    frame.render_widget(chart, area);
+}
+
+fn level_color(level: log::Level) -> Color {
+    match level {
+        log::Level::Error => Color::Red,
+        log::Level::Warn => Color::Yellow,
+        log::Level::Info => Color::Green,
+        log::Level::Debug => Color::Blue,
+        log::Level::Trace => Color::DarkGray,
+    }
+}
+
+/// Stacks the current toasts in the bottom-right corner of `area`, newest
+/// at the bottom.
+fn render_toasts(frame: &mut Frame, app: &App, area: Rect) {
+    let width = area.width.min(50);
+    let mut bottom = area.y + area.height;
+    for toast in app.notifications.toasts().rev() {
+        let inner_width = width.saturating_sub(2).max(1) as usize;
+        let lines = toast.message.width().div_ceil(inner_width).clamp(1, 3) as u16;
+        let height = lines + 2;
+        if bottom < area.y + height {
+            break;
+        }
+        bottom -= height;
+        let toast_area = Rect::new(area.x + area.width - width, bottom, width, height);
+        let color = severity_color(toast.severity);
+        frame.render_widget(Clear, toast_area);
+        frame.render_widget(
+            Paragraph::new(toast.message.as_str())
+                .wrap(Wrap { trim: true })
+                .block(
+                    Block::default()
+                        .borders(Borders::ALL)
+                        .border_style(Style::default().fg(color))
+                        .title(Span::styled(format!(" {} ", toast.severity.label()), Style::default().fg(color))),
+                ),
+            toast_area,
+        );
+    }
+}
+
+fn severity_color(severity: Severity) -> Color {
+    match severity {
+        Severity::Info => Color::Cyan,
+        Severity::Success => Color::Green,
+        Severity::Warning => Color::Yellow,
+        Severity::Error => Color::Red,
+    }
+}
+
+fn render_ignore_view(frame: &mut Frame, app: &mut App, area: Rect) {
+    let theme = app.config.theme;
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Min(0), Constraint::Length(3)])
+        .split(area);
+
+    let deciding = app.ignore_check.as_ref().and_then(|check| check.rule.as_ref());
+    let header_cells = ["File", "Line", "Pattern"]
+        .iter()
+        .map(|h| Cell::from(*h).style(Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD)));
+    let header = Row::new(header_cells).height(1).bottom_margin(1);
+    let rows = app.ignore_rules.iter().map(|rule| {
+        let color = if rule.is_negated() { Color::Green } else { Color::Reset };
+        let row = Row::new(vec![
+            Cell::from(rule.file.label.clone()).style(Style::default().fg(Color::DarkGray)),
+            Cell::from(rule.line.to_string()),
+            Cell::from(rule.pattern.clone()).style(Style::default().fg(color)),
+        ]);
+        if Some(rule) == deciding {
+            row.style(Style::default().add_modifier(Modifier::BOLD))
+        } else {
+            row
+        }
+    });
+    let table = Table::new(
+        rows,
+        [Constraint::Percentage(40), Constraint::Length(6), Constraint::Min(20)],
+    )
+    .header(header)
+    .block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title("Ignore rules ('c' check a path, 'a' add, 'e' edit, 'x' remove, 'd' find secrets)"),
+    )
+    .highlight_style(Style::default().bg(theme.selection))
+    .highlight_symbol(">> ");
+    frame.render_stateful_widget(table, chunks[0], &mut app.ignore_table_state);
+
+    let line = match &app.ignore_check {
+        None => Line::styled(
+            "Press 'c' to see whether a path is ignored, and why.",
+            Style::default().fg(Color::DarkGray),
+        ),
+        Some(check) => {
+            let verdict = if check.ignored {
+                Span::styled("ignored", Style::default().fg(Color::Red).bold())
+            } else {
+                Span::styled("not ignored", Style::default().fg(Color::Green).bold())
+            };
+            let reason = match &check.rule {
+                Some(rule) => format!(" ({}:{}: {})", rule.file.label, rule.line, rule.pattern),
+                None if check.ignored => " (by a rule not listed here)".to_string(),
+                None => String::new(),
+            };
+            Line::from(vec![Span::raw(format!("{} is ", check.path)), verdict, Span::raw(reason)])
+        }
+    };
+    frame.render_widget(
+        Paragraph::new(line).block(Block::default().borders(Borders::ALL).title("Check")),
+        chunks[1],
+    );
+}
+
+pub(crate) fn render_doctor_view(frame: &mut Frame, app: &mut App, area: Rect) {
+    let theme = app.config.theme;
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .title("Doctor ('enter' fix, 'c' check again)");
+    let DoctorView::Loaded(checks) = &app.doctor_view else {
+        frame.render_widget(Paragraph::new("Checking...").block(block), area);
+        return;
+    };
+    let header_cells = ["", "Check", "Result", "Fix"]
+        .iter()
+        .map(|h| Cell::from(*h).style(Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD)));
+    let header = Row::new(header_cells).height(1).bottom_margin(1);
+    let rows = checks.iter().map(|check| {
+        let (mark, color) = match check.health {
+            Health::Pass => ("✓", Color::Green),
+            Health::Warn => ("!", Color::Yellow),
+            Health::Fail => ("✗", Color::Red),
+        };
+        Row::new(vec![
+            Cell::from(mark).style(Style::default().fg(color).bold()),
+            Cell::from(check.name),
+            Cell::from(check.message.clone()),
+            Cell::from(check.fix.as_ref().map(Fix::description).unwrap_or_default())
+                .style(Style::default().fg(Color::DarkGray)),
+        ])
+    });
+    let table = Table::new(
+        rows,
+        [
+            Constraint::Length(2),
+            Constraint::Length(11),
+            Constraint::Percentage(50),
+            Constraint::Min(20),
+        ],
+    )
+    .header(header)
+    .block(block)
+    .highlight_style(Style::default().bg(theme.selection))
+    .highlight_symbol(">> ");
+    frame.render_stateful_widget(table, area, &mut app.doctor_table_state);
+}
+
+pub(crate) fn render_git_config_view(frame: &mut Frame, app: &mut App, area: Rect) {
+    let theme = app.config.theme;
+    let scope = app.git_config_scope;
+    let block = Block::default().borders(Borders::ALL).title(format!(
+        "Git Config, editing {} ('enter' edit, 'a' add, 'x' unset, 'tab' local/global)",
+        scope.name()
+    ));
+    if let Some(error) = &app.git_config_error {
+        frame.render_widget(Paragraph::new(error.clone()).block(block), area);
+        return;
+    }
+    let header_cells = ["Name", "Local", "Global", ""]
+        .iter()
+        .map(|h| Cell::from(*h).style(Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD)));
+    let header = Row::new(header_cells).height(1).bottom_margin(1);
+    let value_cell = |value: Option<&str>, edited: bool| {
+        let style = if edited { Style::default().fg(Color::Cyan) } else { Style::default() };
+        match value {
+            Some(value) => Cell::from(value.to_string()).style(style),
+            None => Cell::from("-").style(Style::default().fg(Color::DarkGray)),
+        }
+    };
+    let rows = app.git_config.iter().map(|entry| {
+        Row::new(vec![
+            Cell::from(entry.name.clone()),
+            value_cell(entry.local.as_deref(), scope == ConfigScope::Local),
+            value_cell(entry.global.as_deref(), scope == ConfigScope::Global),
+            Cell::from(entry.description).style(Style::default().fg(Color::DarkGray)),
+        ])
+    });
+    let table = Table::new(
+        rows,
+        [
+            Constraint::Length(24),
+            Constraint::Percentage(25),
+            Constraint::Percentage(25),
+            Constraint::Min(20),
+        ],
+    )
+    .header(header)
+    .block(block)
+    .highlight_style(Style::default().bg(theme.selection))
+    .highlight_symbol(">> ");
+    frame.render_stateful_widget(table, area, &mut app.git_config_table_state);
+}
+
+pub(crate) fn render_forge_view(frame: &mut Frame, app: &mut App, area: Rect) {
+    let theme = app.config.theme;
+    let title = match &app.forge.project {
+        Some(forge) => format!("{} - {} ('enter' open PR, 'b' repo, 'c' commit, 'w' reload)", forge.name(), forge.path),
+        None => "Web".to_string(),
+    };
+    let block = Block::default().borders(Borders::ALL).title(title);
+    let status = match &app.forge.view {
+        ForgeView::Loaded(status) => status,
+        other => {
+            let text = match other {
+                ForgeView::Unavailable(reason) => reason.clone(),
+                ForgeView::Failed(error) => format!("Could not load the status: {}", error),
+                _ => "Loading...".to_string(),
+            };
+            frame.render_widget(Paragraph::new(text).block(block).wrap(Wrap { trim: true }), area);
+            return;
+        }
+    };
+
+    let inner = block.inner(area);
+    frame.render_widget(block, area);
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Length(2), Constraint::Min(0)])
+        .split(inner);
+
+    let (ci_text, ci_color) = match status.ci {
+        Some(CiStatus::Success) => ("✓ passing", Color::Green),
+        Some(CiStatus::Pending) => ("● running", Color::Yellow),
+        Some(CiStatus::Failure) => ("✗ failing", Color::Red),
+        None => ("no runs", Color::DarkGray),
+    };
+    frame.render_widget(
+        Paragraph::new(Line::from(vec![
+            Span::raw("CI for HEAD: "),
+            Span::styled(ci_text, Style::default().fg(ci_color)),
+        ])),
+        chunks[0],
+    );
+
+    if status.pull_requests.is_empty() {
+        frame.render_widget(Paragraph::new("No open pull requests target this branch."), chunks[1]);
+        return;
+    }
+    let header_cells = ["#", "Title", "Author"]
+        .iter()
+        .map(|h| Cell::from(*h).style(Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD)));
+    let header = Row::new(header_cells).height(1).bottom_margin(1);
+    let rows = status.pull_requests.iter().map(|pr| {
+        Row::new(vec![
+            Cell::from(format!("#{}", pr.number)),
+            Cell::from(pr.title.clone()),
+            Cell::from(pr.author.clone()),
+        ])
+    });
+    let table = Table::new(
+        rows,
+        [Constraint::Length(7), Constraint::Min(30), Constraint::Length(20)],
+    )
+    .header(header)
+    .highlight_style(Style::default().bg(theme.selection))
+    .highlight_symbol(">> ");
+    frame.render_stateful_widget(table, chunks[1], &mut app.forge.table_state);
+}
+
+pub(crate) fn render_links_view(frame: &mut Frame, app: &mut App, area: Rect) {
+    let theme = app.config.theme;
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .title(match &app.active_profile {
+            Some(profile) => format!("Links - profile '{}' ('c' create, 'x' remove, 'a' adopt, 'A' apply all, 'p' switch profile)", profile.name),
+            None => "Links ('c' create, 'x' remove, 'a' adopt, 'A' apply all, 'p' switch profile)".to_string(),
+        });
+
+    let (table_area, error_area) = if app.links_error.is_some() {
+        let chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Min(0), Constraint::Length(1)])
+            .split(area);
+        (chunks[0], Some(chunks[1]))
+    } else {
+        (area, None)
+    };
+
+    if let (Some(error_area), Some(error)) = (error_area, &app.links_error) {
+        let error_line = Paragraph::new(error.clone()).style(Style::default().fg(Color::Red));
+        frame.render_widget(error_line, error_area);
+    }
+
+    if app.links.is_empty() {
+        let hint = format!(
+            "No links defined. Add [[link]] entries with `source` and `target` to {} in the repository root, or press {} to import them from GNU Stow packages or chezmoi.",
+            MANIFEST_FILE,
+            key_label(app.keys.links.link_import)
+        );
+        let paragraph = Paragraph::new(hint).block(block).wrap(Wrap { trim: true });
+        frame.render_widget(paragraph, table_area);
+        return;
+    }
+
+    let header_cells = ["State", "Source", "Target"]
+        .iter()
+        .map(|h| Cell::from(*h).style(Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD)));
+    let header = Row::new(header_cells).height(1).bottom_margin(1);
+    let rows = app.links.iter().map(|entry| {
+        let (label, color) = match entry.state {
+            LinkState::Linked => ("linked", Color::Green),
+            LinkState::Missing => ("missing", Color::Yellow),
+            LinkState::Stale => ("stale", Color::Cyan),
+            LinkState::Conflict => ("conflict", Color::Red),
+            LinkState::SourceMissing => ("no source", Color::Magenta),
+            LinkState::Decrypted => ("decrypted", Color::Blue),
+        };
+        Row::new(vec![
+            Cell::from(label).style(Style::default().fg(color)),
+            Cell::from(if entry.overlaid {
+                format!("{} (overlay)", entry.spec.source)
+            } else {
+                entry.spec.source.clone()
+            }),
+            Cell::from(entry.target.display().to_string()),
+        ])
+    });
+    let table = Table::new(
+        rows,
+        [
+            Constraint::Length(10),
+            Constraint::Percentage(40),
             Constraint::Min(20),
         ],
     )
     .header(header)
-    .block(Block::default().borders(Borders::ALL).title("Log"))
-    .highlight_style(Style::default().bg(Color::DarkGray))
+    .block(block)
+    .highlight_style(Style::default().bg(theme.selection))
     .highlight_symbol(">> ");
-    frame.render_stateful_widget(table, area, &mut app.log_table_state);
+    frame.render_stateful_widget(table, table_area, &mut app.links_table_state);
 }
 
-fn status_to_list_item(item: &StatusItem) -> ListItem {
+/// Files below a directory node show only their name, indented under it.
+/// `secret` marks files `[encryption]` keeps encrypted with a lock.
+fn status_to_list_item<'a>(
+    item: &'a StatusItem,
+    grouped: bool,
+    churn: Option<&Churn>,
+    marked: Option<bool>,
+    secret: bool,
+    theme: &Theme,
+) -> ListItem<'a> {
     let (prefix, color) = status_to_prefix_and_color(item.status);
     let style = Style::default().fg(color);
-    ListItem::new(ratatui::text::Line::from(vec![
-        Span::styled(prefix, style.clone().add_modifier(Modifier::BOLD)),
-        Span::styled(item.path.clone(), style),
-    ]))
+    let (indent, name) = match item.path.rsplit_once('/') {
+        Some((_, name)) if grouped => ("  ", name),
+        _ => ("", item.path.as_str()),
+    };
+    let marker = match marked {
+        Some(true) => Span::styled("* ", Style::default().fg(Color::Magenta).bold()),
+        Some(false) => Span::raw("  "),
+        None => Span::raw(""),
+    };
+    let mut spans = vec![
+        marker,
+        Span::raw(indent),
+        Span::styled(prefix, style.add_modifier(Modifier::BOLD)),
+        Span::styled(name.to_string(), style),
+    ];
+    if secret {
+        spans.push(Span::styled(" 🔒", Style::default().fg(Color::Blue)));
+    }
+    if let Some(churn) = churn {
+        spans.extend(churn_spans(churn, theme));
+    }
+    ListItem::new(ratatui::text::Line::from(spans))
+}
+
+/// ` +3/−1 (+120 B)`: the lines a change adds and deletes, and how much it
+/// grows or shrinks the file. Binary files only show the size.
+fn churn_spans(churn: &Churn, theme: &Theme) -> Vec<Span<'static>> {
+    let dim = Style::default().fg(Color::DarkGray);
+    let mut spans = Vec::new();
+    if churn.additions > 0 || churn.deletions > 0 {
+        spans.push(Span::raw(" "));
+        spans.push(Span::styled(format!("+{}", churn.additions), Style::default().fg(theme.added)));
+        spans.push(Span::styled("/", dim));
+        spans.push(Span::styled(format!("−{}", churn.deletions), Style::default().fg(theme.removed)));
+    }
+    if churn.size_delta != 0 {
+        spans.push(Span::styled(format!(" ({})", format_size_delta(churn.size_delta)), dim));
+    }
+    spans
+}
+
+/// `Staged:   2 files, +10/−3`, or `no changes`.
+fn summary_line(label: &str, summary: &DiffSummary, theme: &Theme) -> Line<'static> {
+    let label = Span::raw(format!("{:<10}", format!("{}:", label)));
+    if summary.files == 0 {
+        return Line::from(vec![label, Span::styled("no changes", Style::default().fg(Color::DarkGray))]);
+    }
+    let files = match summary.files {
+        1 => "1 file, ".to_string(),
+        files => format!("{} files, ", files),
+    };
+    Line::from(vec![
+        label,
+        Span::raw(files),
+        Span::styled(format!("+{}", summary.insertions), Style::default().fg(theme.added)),
+        Span::styled("/", Style::default().fg(Color::DarkGray)),
+        Span::styled(format!("−{}", summary.deletions), Style::default().fg(theme.removed)),
+    ])
+}
+
+/// A size change like `+120 B`, `−2.5 KiB` or `+1.0 MiB`.
+fn format_size_delta(delta: i64) -> String {
+    let sign = if delta < 0 { '−' } else { '+' };
+    let bytes = delta.unsigned_abs();
+    if bytes < 1024 {
+        return format!("{}{} B", sign, bytes);
+    }
+    let (value, unit) = if bytes < 1024 * 1024 {
+        (bytes as f64 / 1024.0, "KiB")
+    } else {
+        (bytes as f64 / (1024.0 * 1024.0), "MiB")
+    };
+    format!("{}{:.1} {}", sign, value, unit)
 }
 
-fn status_to_prefix_and_color(status: Status) -> (&'static str, Color) {
-    if status.is_wt_new() || status.is_index_new() {
+pub(crate) fn status_to_prefix_and_color(status: Status) -> (&'static str, Color) {
+    if status.is_conflicted() {
+        ("U ", Color::LightRed)
+    } else if status.is_wt_new() || status.is_index_new() {
         ("A ", Color::Green)
     } else if status.is_wt_modified() || status.is_index_modified() {
         ("M ", Color::Yellow)
@@ -174,49 +1386,1254 @@ fn status_to_prefix_and_color(status: Status) -> (&'static str, Color) {
     }
 }
 
-fn render_popup(frame: &mut Frame, popup: &Popup, commit_msg: &str, cursor_pos: usize) {
-    let popup_area = centered_rect(60, 25, frame.size());
+fn render_popup(frame: &mut Frame, popup: &Popup, app: &App) {
+    let theme = app.config.theme;
+    let popup_area = match popup {
+        Popup::Commit => centered_rect(70, 50, frame.size()),
+        Popup::CommitDetail | Popup::Hooks | Popup::ApplyResults => centered_rect(70, 60, frame.size()),
+        Popup::BranchEdit
+        | Popup::DetachedHead
+        | Popup::IgnoreTemplates
+        | Popup::LinkImport
+        | Popup::Bisect
+        | Popup::PushTargets
+        | Popup::ClonePath
+        | Popup::Palette => centered_rect(60, 40, frame.size()),
+        _ => centered_rect(60, 25, frame.size()),
+    };
     let block = Block::default().borders(Borders::ALL);
     frame.render_widget(Clear, popup_area);
     let content = match popup {
-        Popup::Help => {
+        Popup::Divergence | Popup::Help => return,
+        Popup::Commit => {
+            render_commit_editor(frame, app, block, popup_area);
+            return;
+        }
+        Popup::ProfileSwitch => {
+            render_profile_switch(frame, app, block, popup_area);
+            return;
+        }
+        Popup::LogCheckout => {
+            render_log_checkout(frame, app, block, popup_area);
+            return;
+        }
+        Popup::Bisect => {
+            render_bisect(frame, app, block, popup_area);
+            return;
+        }
+        Popup::DetachedHead => {
+            render_detached_head(frame, app, block, popup_area);
+            return;
+        }
+        Popup::IgnoreTemplates => {
+            render_ignore_templates(frame, app, block, popup_area);
+            return;
+        }
+        Popup::LinkImport => {
+            render_link_import(frame, app, block, popup_area);
+            return;
+        }
+        Popup::ApplyResults => {
+            render_apply_results(frame, app, block, popup_area);
+            return;
+        }
+        Popup::PushTargets => {
+            render_push_targets(frame, app, block, popup_area);
+            return;
+        }
+        Popup::Hooks => {
+            let Some(run) = &app.hook_run else {
+                return;
+            };
+            let mut lines: Vec<Line> = run.output.iter().map(|line| Line::raw(line.as_str())).collect();
+            let title = match &run.error {
+                Some(error) => {
+                    lines.push(Line::styled(error.as_str(), Style::default().fg(Color::Red).bold()));
+                    " Commit Hooks failed (Esc to edit the message) "
+                }
+                None => " Commit Hooks (running...) ",
+            };
+            // Follow the output as it arrives.
+            let scroll = lines.len().saturating_sub(popup_area.height.saturating_sub(2) as usize);
+            Paragraph::new(lines)
+                .block(block.title(title))
+                .scroll((scroll as u16, 0))
+        }
+        Popup::Worktrees => {
+            render_worktrees(frame, app, block, popup_area);
+            return;
+        }
+        Popup::RepoSwitch => {
+            render_repo_switch(frame, app, block, popup_area);
+            return;
+        }
+        Popup::Palette => {
+            render_palette(frame, app, block, popup_area);
+            return;
+        }
+        Popup::CloneUrl => {
+            frame.set_cursor(
+                popup_area.x + 3 + app.clone_url.cursor_width(),
+                popup_area.y + 1,
+            );
+            Paragraph::new(format!("> {}", app.clone_url))
+                .block(block.title(" Clone Repository: URL (Enter to continue, Esc to cancel) "))
+        }
+        Popup::ClonePath => {
+            let fields = [
+                (CloneField::Path, "Directory:", &app.clone_path),
+                (CloneField::Depth, "Depth:    ", &app.clone_depth),
+                (CloneField::Branch, "Branch:   ", &app.clone_branch),
+            ];
+            let mut text: Vec<Line> = fields
+                .iter()
+                .map(|&(field, label, input)| {
+                    let style = match field == app.clone_field {
+                        true => Style::default().fg(theme.accent).bold(),
+                        false => Style::default(),
+                    };
+                    Line::from(vec![Span::styled(label, style), Span::raw(format!(" {}", input))])
+                })
+                .collect();
+            let row = fields.iter().position(|&(field, _, _)| field == app.clone_field).unwrap_or(0);
+            frame.set_cursor(
+                popup_area.x + 12 + fields[row].2.cursor_width(),
+                popup_area.y + 1 + row as u16,
+            );
+            text.push(match app.clone_depth.error() {
+                Some(_) => prompt_error(&app.clone_depth),
+                None => prompt_error(&app.clone_branch),
+            });
+            text.push(Line::styled(
+                format!(
+                    "Cloning {}. Relative paths start at the current work tree. Leave the depth and branch empty to fetch everything.",
+                    app.clone_url.text().trim()
+                ),
+                Style::default().fg(Color::DarkGray),
+            ));
+            Paragraph::new(text)
+                .block(block.title(" Clone Repository (Enter: clone, Tab: next field, Esc: back) "))
+                .wrap(Wrap { trim: false })
+        }
+        Popup::CommitDetail => {
+            let Some(detail) = &app.commit_detail else {
+                return;
+            };
+            let mut text = vec![
+                Line::styled(format!("commit {}", detail.id), Style::default().fg(Color::Yellow)),
+                Line::from(format!("Author: {}", detail.author)),
+                Line::from(format!("Date:   {}", detail.time)),
+                Line::from(""),
+            ];
+            text.extend(detail.message.lines().map(|line| Line::from(format!("    {}", line))));
+            text.push(Line::from(""));
+            text.push(Line::from(vec![
+                Span::raw(format!("{} files changed, ", detail.files.len())),
+                Span::styled(format!("+{}", detail.insertions), Style::default().fg(Color::Green)),
+                Span::raw(" "),
+                Span::styled(format!("-{}", detail.deletions), Style::default().fg(Color::Red)),
+            ]));
+            for (status, path) in &detail.files {
+                let color = match status {
+                    'A' => Color::Green,
+                    'D' => Color::Red,
+                    'R' | 'C' => Color::Cyan,
+                    _ => Color::Yellow,
+                };
+                text.push(Line::from(vec![
+                    Span::styled(format!("{} ", status), Style::default().fg(color).bold()),
+                    Span::raw(path.as_str()),
+                ]));
+            }
+            Paragraph::new(text)
+                .block(block.title(" Commit (Esc to close) "))
+                .wrap(Wrap { trim: false })
+        }
+        Popup::Task => {
+            if let Some(task) = &app.task {
+                render_task_progress(frame, task, block, popup_area);
+            }
+            return;
+        }
+        Popup::IgnoreCheckPath => {
+            frame.set_cursor(
+                popup_area.x + 3 + app.ignore_input.cursor_width(),
+                popup_area.y + 1,
+            );
+            Paragraph::new(format!("> {}", app.ignore_input))
+                .block(block.title(" Check Path (relative to the work tree, Enter to check) "))
+        }
+        Popup::ConfigName => {
             let text = vec![
-                ratatui::text::Line::from(vec![Span::styled("q", Style::default().bold()), Span::raw(": quit")]),
+                ratatui::text::Line::from(format!("> {}", app.config_name)),
+                prompt_error(&app.config_name),
+                ratatui::text::Line::styled(
+                    "A section and a key, like 'alias.st' or 'remote.origin.url'.",
+                    Style::default().fg(Color::DarkGray),
+                ),
+            ];
+            frame.set_cursor(
+                popup_area.x + 3 + app.config_name.cursor_width(),
+                popup_area.y + 1,
+            );
+            Paragraph::new(text)
+                .block(block.title(format!(" Set a {} Config Entry (Enter for its value) ", app.git_config_scope.name())))
+                .wrap(Wrap { trim: false })
+        }
+        Popup::ConfigValue(name) => {
+            let kind = gitconfig::kind_of(name);
+            let typed = app.config_value.text().trim();
+            let error = match gitconfig::normalize(kind, typed) {
+                Err(error) if !typed.is_empty() => {
+                    ratatui::text::Line::styled(error, Style::default().fg(Color::Red))
+                }
+                _ => ratatui::text::Line::from(""),
+            };
+            let hint = match kind {
+                ValueKind::Choice(choices) => format!("One of {}; 'tab' goes through them.", choices.join(", ")),
+                ValueKind::Bool => "true or false (also yes, no, on, off).".to_string(),
+                ValueKind::Int => "A number, optionally with k, m or g.".to_string(),
+                ValueKind::Email => "An email address, without the name.".to_string(),
+                ValueKind::Text => "Any text.".to_string(),
+            };
+            let text = vec![
+                ratatui::text::Line::from(format!("> {}", app.config_value)),
+                error,
+                ratatui::text::Line::styled(
+                    format!("{} Empty unsets it.", hint),
+                    Style::default().fg(Color::DarkGray),
+                ),
+            ];
+            frame.set_cursor(
+                popup_area.x + 3 + app.config_value.cursor_width(),
+                popup_area.y + 1,
+            );
+            let title = format!(" {} ({}, Enter to save, Esc to cancel) ", name, app.git_config_scope.name());
+            Paragraph::new(text).block(block.title(title)).wrap(Wrap { trim: false })
+        }
+        Popup::IgnorePattern(index) => {
+            let title = match index {
+                Some(_) => " Edit Ignore Pattern (Enter to save, Esc to cancel) ",
+                None => " Add Pattern to .gitignore (Enter to add, Esc to cancel) ",
+            };
+            let text = vec![
+                ratatui::text::Line::from(format!("> {}", app.ignore_input)),
+                ratatui::text::Line::from(""),
+                ratatui::text::Line::styled(
+                    "gitignore syntax: '*.log', '/build/', '.config/**/cache', '!keep.me'.",
+                    Style::default().fg(Color::DarkGray),
+                ),
+            ];
+            frame.set_cursor(
+                popup_area.x + 3 + app.ignore_input.cursor_width(),
+                popup_area.y + 1,
+            );
+            Paragraph::new(text).block(block.title(title)).wrap(Wrap { trim: false })
+        }
+        Popup::PatchExport => {
+            let Some(export) = &app.patch_export else {
+                return;
+            };
+            let mut text = vec![
+                ratatui::text::Line::from(format!("> {}", app.patch_input)),
                 ratatui::text::Line::from(""),
-                ratatui::text::Line::from(vec![Span::styled("s", Style::default().bold()), Span::raw(": Status View")]),
-                ratatui::text::Line::from(vec![Span::styled("l", Style::default().bold()), Span::raw(": Log View")]),
+                ratatui::text::Line::from(format!("Exports {}.", export.scope().label())),
+            ];
+            if export.scopes.len() > 1 {
+                text.push(ratatui::text::Line::styled(
+                    "Tab switches between the file, the hunk and all staged changes.",
+                    Style::default().fg(Color::DarkGray),
+                ));
+            }
+            frame.set_cursor(
+                popup_area.x + 3 + app.patch_input.cursor_width(),
+                popup_area.y + 1,
+            );
+            Paragraph::new(text)
+                .block(block.title(" Export Patch (Enter to write, Esc to cancel) "))
+                .wrap(Wrap { trim: false })
+        }
+        Popup::BranchEdit => {
+            let Some(edit) = &app.branch_edit else {
+                return;
+            };
+            let fields = [
+                (BranchField::Name, "Name:  "),
+                (BranchField::Remote, "Remote:"),
+                (BranchField::Merge, "Branch:"),
+            ];
+            let mut text: Vec<Line> = fields
+                .iter()
+                .map(|&(field, label)| {
+                    let style = match field == edit.focus {
+                        true => Style::default().fg(theme.accent).bold(),
+                        false => Style::default(),
+                    };
+                    Line::from(vec![Span::styled(label, style), Span::raw(format!(" {}", edit.field(field)))])
+                })
+                .collect();
+            let row = fields.iter().position(|&(field, _)| field == edit.focus).unwrap_or(0);
+            frame.set_cursor(
+                popup_area.x + 9 + edit.field(edit.focus).cursor_width(),
+                popup_area.y + 1 + row as u16,
+            );
+            text.push(prompt_error(&edit.name));
+            text.push(Line::styled(
+                "Tab moves between the fields. Without a remote the branch has no upstream; without an upstream branch it tracks the one of the same name.",
+                Style::default().fg(Color::DarkGray),
+            ));
+            Paragraph::new(text)
+                .block(block.title(format!(" Branch {} (Enter to save, Esc to cancel) ", edit.branch)))
+                .wrap(Wrap { trim: false })
+        }
+        Popup::BranchCreate => {
+            let text = vec![
+                Line::from(format!("> {}", app.branch_input)),
+                prompt_error(&app.branch_input),
+                Line::styled(
+                    "The branch starts at the detached HEAD and is checked out; the work tree stays as it is.",
+                    Style::default().fg(Color::DarkGray),
+                ),
+            ];
+            frame.set_cursor(popup_area.x + 3 + app.branch_input.cursor_width(), popup_area.y + 1);
+            Paragraph::new(text)
+                .block(block.title(" New Branch (Enter to create, Esc to go back) "))
+                .wrap(Wrap { trim: false })
+        }
+        Popup::PatchApply => {
+            let text = vec![
+                ratatui::text::Line::from(format!("> {}", app.patch_input)),
                 ratatui::text::Line::from(""),
-                ratatui::text::Line::from(vec![Span::styled("h/l", Style::default().bold()), Span::raw(": change active panel")]),
-                ratatui::text::Line::from(vec![Span::styled("j/k", Style::default().bold()), Span::raw(" or "), Span::styled("↓/↑", Style::default().bold()), Span::raw(": navigate lists")]),
-                ratatui::text::Line::from(vec![Span::styled("enter", Style::default().bold()), Span::raw(": enter hunk selection mode")]),
-                ratatui::text::Line::from(vec![Span::styled("space", Style::default().bold()), Span::raw(": stage item/hunk")]),
-                ratatui::text::Line::from(vec![Span::styled("u", Style::default().bold()), Span::raw(": unstage item")]),
-                ratatui::text::Line::from(vec![Span::styled("c", Style::default().bold()), Span::raw(": commit")]),
-                ratatui::text::Line::from(vec![Span::styled("Shift+P", Style::default().bold()), Span::raw(": push to origin")]),
+                ratatui::text::Line::styled(
+                    "The patch is applied to the work tree like `git apply`, and only if all of it applies. Relative paths start at the work tree.",
+                    Style::default().fg(Color::DarkGray),
+                ),
+            ];
+            frame.set_cursor(
+                popup_area.x + 3 + app.patch_input.cursor_width(),
+                popup_area.y + 1,
+            );
+            Paragraph::new(text)
+                .block(block.title(" Apply Patch (Enter to apply, Esc to cancel) "))
+                .wrap(Wrap { trim: false })
+        }
+        Popup::WorktreeAdd => {
+            let text = vec![
+                ratatui::text::Line::from(format!("> {}", app.worktrees.input)),
                 ratatui::text::Line::from(""),
-                ratatui::text::Line::from(vec![Span::styled("esc", Style::default().bold()), Span::raw(": close popups")]),
+                ratatui::text::Line::styled(
+                    "Relative paths start next to the current work tree. The worktree checks out the branch named after its directory, which is created from HEAD if needed.",
+                    Style::default().fg(Color::DarkGray),
+                ),
             ];
-            Paragraph::new(text).block(block.title(" Help (?) ")).alignment(Alignment::Left)
+            frame.set_cursor(
+                popup_area.x + 3 + app.worktrees.input.cursor_width(),
+                popup_area.y + 1,
+            );
+            Paragraph::new(text)
+                .block(block.title(" Add Worktree (Enter to add, Esc to cancel) "))
+                .wrap(Wrap { trim: false })
         }
-        Popup::Commit => {
-            let p = Paragraph::new(commit_msg).block(block.title(" Commit Message (Enter to confirm, Esc to cancel) "));
-            frame.set_cursor(popup_area.x + cursor_pos as u16 + 1, popup_area.y + 1);
-            p
+        Popup::Error { title, message, retry } => {
+            let hint = match retry {
+                Some(_) => format!("{}: retry, Enter/Esc: close", key_label(app.keys.popup.retry)),
+                None => "Enter/Esc: close".to_string(),
+            };
+            let mut text: Vec<Line> = message.lines().map(Line::from).collect();
+            text.push(Line::from(""));
+            text.push(Line::styled(hint, Style::default().fg(Color::DarkGray)));
+            Paragraph::new(text)
+                .block(block.title(format!(" {} ", title)).border_style(Style::default().fg(Color::Red)))
+                .wrap(Wrap { trim: true })
         }
-        Popup::Pushing(msg) => Paragraph::new(msg.clone())
-            .block(block.title(" Pushing to remote... (Esc to close) "))
+        Popup::Confirm { title, message, .. } => Paragraph::new(format!("{} (y/n)", message))
+            .block(block.title(format!(" {} ", title)))
             .alignment(Alignment::Center)
             .wrap(Wrap { trim: true }),
+        Popup::CredentialPrompt { message, secret } => {
+            let input = if *secret {
+                "*".repeat(app.credential_input.chars().count())
+            } else {
+                app.credential_input.clone()
+            };
+            let text = vec![
+                ratatui::text::Line::from(message.clone()),
+                ratatui::text::Line::from(""),
+                ratatui::text::Line::from(format!("> {}", input)),
+            ];
+            frame.set_cursor(
+                popup_area.x + 3 + input.width() as u16,
+                popup_area.y + 3,
+            );
+            Paragraph::new(text)
+                .block(block.title(" Authentication required (Enter to submit, Esc to cancel) "))
+                .wrap(Wrap { trim: false })
+        }
+        Popup::SecretPrompt(index) => {
+            let gpg = app
+                .config
+                .encryption
+                .as_ref()
+                .is_some_and(|config| config.tool == Cipher::Gpg);
+            let (message, input) = if gpg {
+                ("Passphrase of your gpg key:", "*".repeat(app.secret_input.chars().count()))
+            } else {
+                ("Path of your age identity file:", app.secret_input.clone())
+            };
+            let source = app
+                .links
+                .get(*index)
+                .map(|entry| entry.spec.source.as_str())
+                .unwrap_or_default();
+            let text = vec![
+                Line::from(format!("Decrypting {}", source)),
+                Line::from(message),
+                Line::from(format!("> {}", input)),
+            ];
+            frame.set_cursor(popup_area.x + 3 + input.width() as u16, popup_area.y + 3);
+            Paragraph::new(text)
+                .block(block.title(" Secret (Enter to decrypt, Esc to cancel) "))
+                .wrap(Wrap { trim: false })
+        }
+        Popup::SetUpstreamConfirm(branch) => Paragraph::new(format!(
+            "'{}' has no upstream branch. Push to origin/{} and track it? (y: push and track, n: push only, esc: cancel)",
+            branch, branch
+        ))
+        .block(block.title(" Set Upstream "))
+        .alignment(Alignment::Center)
+        .wrap(Wrap { trim: true }),
+        Popup::AutoStashConfirm(operation) => Paragraph::new(format!(
+            "You have local changes that the {} could overwrite. Stash them first and offer them back afterwards? (y: stash and continue, n: cancel)",
+            operation.name()
+        ))
+        .block(block.title(" Local Changes "))
+        .alignment(Alignment::Center)
+        .wrap(Wrap { trim: true }),
+        Popup::AutoStashRestore => {
+            let operation = app
+                .auto_stash
+                .as_ref()
+                .map(|stash| stash.operation.as_str())
+                .unwrap_or("operation");
+            Paragraph::new(format!(
+                "The {} has ended. Re-apply the changes stashed before it? (y: re-apply, n: keep them in the stash)",
+                operation
+            ))
+            .block(block.title(" Auto-Stash "))
+            .alignment(Alignment::Center)
+            .wrap(Wrap { trim: true })
+        }
     };
     frame.render_widget(content, popup_area);
 }
 
+fn render_repo_switch(frame: &mut Frame, app: &App, block: Block, area: Rect) {
+    let theme = app.config.theme;
+    let current = app.repo.location().git_dir;
+    let items: Vec<ListItem> = app
+        .recent
+        .recent
+        .iter()
+        .map(|repo| {
+            let work_tree = repo
+                .work_tree
+                .as_deref()
+                .or_else(|| repo.git_dir.parent())
+                .unwrap_or(&repo.git_dir);
+            let mut spans = vec![Span::styled(
+                work_tree.display().to_string(),
+                Style::default().add_modifier(Modifier::BOLD),
+            )];
+            if repo.git_dir == current {
+                spans.push(Span::styled(" (current)", Style::default().fg(Color::Green)));
+            }
+            if repo.work_tree.is_some() {
+                spans.push(Span::styled(
+                    format!("  git dir: {}", repo.git_dir.display()),
+                    Style::default().fg(Color::DarkGray),
+                ));
+            }
+            ListItem::new(Line::from(spans))
+        })
+        .collect();
+    let list = List::new(items)
+        .block(block.title(" Recent Repositories (Enter to switch, c to clone, Esc to cancel) "))
+        .highlight_style(Style::default().bg(theme.selection))
+        .highlight_symbol(">> ");
+    let mut state = app.repo_list_state.clone();
+    frame.render_stateful_widget(list, area, &mut state);
+}
+
+fn render_palette(frame: &mut Frame, app: &App, block: Block, area: Rect) {
+    let theme = app.config.theme;
+    let items: Vec<ListItem> = app
+        .palette
+        .iter()
+        .map(|entry| match entry {
+            PaletteEntry::Script(name) => ListItem::new(Line::from(vec![
+                Span::styled(format!("{:<10}", "script"), Style::default().fg(Color::Magenta)),
+                Span::raw(name.as_str()),
+            ])),
+            PaletteEntry::Key(key, description) => ListItem::new(Line::from(vec![
+                Span::styled(format!("{:<10}", key_label(*key)), Style::default().fg(theme.accent)),
+                Span::raw(*description),
+            ])),
+            PaletteEntry::Panel(_, title) => ListItem::new(Line::from(vec![
+                Span::styled(format!("{:<10}", "panel"), Style::default().fg(Color::Blue)),
+                Span::raw(format!("open {}", title)),
+            ])),
+        })
+        .collect();
+    let list = List::new(items)
+        .block(block.title(" Command Palette (Enter to run, Esc to cancel) "))
+        .highlight_style(Style::default().bg(theme.selection))
+        .highlight_symbol(">> ");
+    let mut state = app.palette_state.clone();
+    frame.render_stateful_widget(list, area, &mut state);
+}
+
+fn render_task_progress(frame: &mut Frame, task: &Task, block: Block, area: Rect) {
+    let title = if task.cancel.is_cancelled() {
+        format!(" {}... ", task.kind.verb())
+    } else {
+        format!(" {}... (Esc to cancel) ", task.kind.verb())
+    };
+    let block = block.title(title);
+    let inner = block.inner(area);
+    frame.render_widget(block, area);
+    let gauge_area = Rect { height: inner.height.min(1), ..inner };
+    let gauge = Gauge::default()
+        .gauge_style(Style::default().fg(Color::Green).bg(Color::DarkGray))
+        .percent(task.pct.min(100))
+        .label(format!("{}%", task.pct.min(100)));
+    frame.render_widget(gauge, gauge_area);
+    let details_area = Rect {
+        y: inner.y + 2,
+        height: inner.height.saturating_sub(2),
+        ..inner
+    };
+    frame.render_widget(
+        Paragraph::new(task.msg.as_str())
+            .alignment(Alignment::Center)
+            .wrap(Wrap { trim: true }),
+        details_area,
+    );
+}
+
+fn render_worktrees(frame: &mut Frame, app: &App, block: Block, area: Rect) {
+    let theme = app.config.theme;
+    let items: Vec<ListItem> = app
+        .worktrees
+        .list
+        .iter()
+        .map(|worktree| {
+            let name = worktree.name.as_deref().unwrap_or("(main)");
+            let mut spans = vec![Span::styled(
+                name.to_string(),
+                Style::default().add_modifier(Modifier::BOLD),
+            )];
+            if worktree.is_current {
+                spans.push(Span::styled(" (current)", Style::default().fg(Color::Green)));
+            }
+            if worktree.locked {
+                spans.push(Span::styled(" (locked)", Style::default().fg(Color::Yellow)));
+            }
+            if !worktree.valid {
+                spans.push(Span::styled(" (missing)", Style::default().fg(Color::Red)));
+            }
+            spans.push(Span::raw(format!(
+                "  [{}]",
+                worktree.branch.as_deref().unwrap_or("detached")
+            )));
+            spans.push(Span::styled(
+                format!("  {}", worktree.path.display()),
+                Style::default().fg(Color::DarkGray),
+            ));
+            ListItem::new(Line::from(spans))
+        })
+        .collect();
+    let list = List::new(items)
+        .block(block.title(" Worktrees (Enter: switch, a: add, x: remove, Esc: close) "))
+        .highlight_style(Style::default().bg(theme.selection))
+        .highlight_symbol(">> ");
+    let mut state = app.worktrees.list_state.clone();
+    frame.render_stateful_widget(list, area, &mut state);
+}
+
+fn render_profile_switch(frame: &mut Frame, app: &App, block: Block, area: Rect) {
+    let theme = app.config.theme;
+    let active = app.active_profile.as_ref().map(|p| p.name.as_str());
+    let items: Vec<ListItem> = app
+        .profiles
+        .iter()
+        .map(|profile| {
+            let mut spans = vec![Span::styled(
+                profile.name.clone(),
+                Style::default().add_modifier(Modifier::BOLD),
+            )];
+            if Some(profile.name.as_str()) == active {
+                spans.push(Span::styled(" (active)", Style::default().fg(Color::Green)));
+            }
+            if let Some(branch) = &profile.branch {
+                spans.push(Span::raw(format!("  branch: {}", branch)));
+            }
+            if let Some(overlay) = &profile.overlay {
+                spans.push(Span::raw(format!("  overlay: {}", overlay)));
+            }
+            if !profile.hosts.is_empty() {
+                spans.push(Span::styled(
+                    format!("  hosts: {}", profile.hosts.join(", ")),
+                    Style::default().fg(Color::DarkGray),
+                ));
+            }
+            ListItem::new(Line::from(spans))
+        })
+        .collect();
+    let list = List::new(items)
+        .block(block.title(" Switch Profile (Enter to switch, Esc to cancel) "))
+        .highlight_style(Style::default().bg(theme.selection))
+        .highlight_symbol(">> ");
+    let mut state = app.profile_list_state.clone();
+    frame.render_stateful_widget(list, area, &mut state);
+}
+
+/// The commits only on HEAD and only on its upstream, stacked on the left,
+/// and the diff of the selected one on the right.
+/// Why a prompt's text cannot be submitted, in red, or an empty line.
+fn prompt_error(prompt: &PromptState) -> Line<'static> {
+    match prompt.error() {
+        Some(error) => Line::styled(error, Style::default().fg(Color::Red)),
+        None => Line::from(""),
+    }
+}
+
+/// Draws the keys of the current view, or of every view once `tab` is
+/// pressed, scrolled by `app.help`.
+fn render_help(frame: &mut Frame, app: &mut App) {
+    let area = centered_rect(70, 80, frame.size());
+    frame.render_widget(Clear, area);
+    let sections = app.keys.help(app.mode, app.help.all_modes);
+    let current = sections.get(1).map_or("", |section| section.title);
+    let mut text = Vec::new();
+    for section in sections {
+        if !text.is_empty() {
+            text.push(Line::from(""));
+        }
+        text.push(Line::styled(section.title, Style::default().fg(Color::Yellow).bold()));
+        for (keys, description, mutating) in section.entries {
+            let line = Line::from(vec![
+                Span::styled(keys, Style::default().bold()),
+                Span::raw(format!(": {}", description)),
+            ]);
+            // Read-only mode refuses these, so they are greyed out.
+            text.push(match mutating && app.config.read_only {
+                true => line.style(Style::default().fg(Color::DarkGray)),
+                false => line,
+            });
+        }
+    }
+    let visible_height = area.height.saturating_sub(2) as usize;
+    let max_scroll = text.len().saturating_sub(visible_height);
+    app.help.scroll = app.help.scroll.min(max_scroll as u16);
+
+    let title = match app.config.read_only {
+        true => format!(" Help (?) - read-only - {} ", current),
+        false => format!(" Help (?) - {} ", current),
+    };
+    let scope = match app.help.all_modes {
+        true => " tab: this view only ",
+        false => " tab: all views ",
+    };
+    let position = match max_scroll {
+        0 => String::new(),
+        _ => format!(" j/k scroll, {}/{} ", app.help.scroll, max_scroll),
+    };
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .title(title)
+        .title(Title::from(scope).position(Position::Bottom).alignment(Alignment::Left))
+        .title(Title::from(position).position(Position::Bottom).alignment(Alignment::Right));
+    let help = Paragraph::new(text).block(block).scroll((app.help.scroll, 0));
+    frame.render_widget(help, area);
+}
+
+fn render_divergence(frame: &mut Frame, app: &mut App) {
+    let theme = app.config.theme;
+    let Some(view) = &mut app.divergence else {
+        return;
+    };
+    let area = centered_rect(90, 80, frame.size());
+    frame.render_widget(Clear, area);
+    let columns = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Percentage(40), Constraint::Percentage(60)])
+        .split(area);
+    let lists = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Percentage(50), Constraint::Percentage(50)])
+        .split(columns[0]);
+
+    let sides = [
+        (
+            format!(" Only local: {} to push ", view.divergence.ahead.len()),
+            &view.divergence.ahead,
+            &mut view.ahead_state,
+            !view.upstream_focused,
+        ),
+        (
+            format!(" Only on {}: {} to pull ", view.divergence.upstream, view.divergence.behind.len()),
+            &view.divergence.behind,
+            &mut view.behind_state,
+            view.upstream_focused,
+        ),
+    ];
+    for ((title, commits, state, focused), area) in sides.into_iter().zip(lists.iter()) {
+        let items: Vec<ListItem> = commits
+            .iter()
+            .map(|commit| {
+                ListItem::new(Line::from(vec![
+                    Span::styled(format!("{} ", commit.id), Style::default().fg(Color::Yellow)),
+                    Span::raw(commit.message.as_str()),
+                ]))
+            })
+            .collect();
+        let border_style = match focused {
+            true => Style::default().fg(theme.accent),
+            false => Style::default(),
+        };
+        let list = List::new(items)
+            .block(Block::default().borders(Borders::ALL).title(title).border_style(border_style))
+            .highlight_style(Style::default().bg(theme.selection))
+            .highlight_symbol(">> ");
+        frame.render_stateful_widget(list, *area, state);
+    }
+
+    let mut lines = Vec::new();
+    for file in &view.diff {
+        let note = match line_ending_change(&file.hunks) {
+            Some((old, new)) => format!(" (only line endings differ: {} → {})", old, new),
+            None => String::new(),
+        };
+        lines.push(Line::styled(
+            format!("{} {}{}", file.status, file.path, note),
+            Style::default().fg(Color::Yellow).bold(),
+        ));
+        if let Some(binary) = &file.binary {
+            lines.push(Line::from(binary.summary()));
+        }
+        lines.extend(diff_lines(&file.hunks, app.diff_line_limit, &theme));
+    }
+    if lines.is_empty() {
+        lines.push(Line::from("No commit selected."));
+    }
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .title(" Diff (Tab to switch lists, Ctrl+d/Ctrl+u to scroll, Esc to close) ");
+    render_diff_panel(frame, lines, block, columns[1], &mut view.diff_scroll);
+}
+
+fn render_log_checkout(frame: &mut Frame, app: &App, block: Block, area: Rect) {
+    let theme = app.config.theme;
+    let Some(checkout) = &app.log_checkout else {
+        return;
+    };
+    let mut items: Vec<ListItem> = checkout
+        .refs
+        .iter()
+        .map(|commit_ref| {
+            let line = match commit_ref {
+                CommitRef::Branch(branch) => Line::from(vec![
+                    Span::styled(branch.clone(), Style::default().fg(Color::Green).bold()),
+                    Span::raw("  branch"),
+                ]),
+                CommitRef::Tag(tag) => Line::from(vec![
+                    Span::styled(tag.clone(), Style::default().fg(Color::Yellow).bold()),
+                    Span::styled("  tag, detaches HEAD", Style::default().fg(Color::DarkGray)),
+                ]),
+            };
+            ListItem::new(line)
+        })
+        .collect();
+    items.push(ListItem::new(Line::from(vec![
+        Span::styled(format!("{:.7}", checkout.oid.to_string()), Style::default().bold()),
+        Span::styled(
+            "  detached HEAD: commits made there belong to no branch",
+            Style::default().fg(Color::Red),
+        ),
+    ])));
+    let list = List::new(items)
+        .block(block.title(" Check Out (Enter to check out, Esc to cancel) "))
+        .highlight_style(Style::default().bg(theme.selection))
+        .highlight_symbol(">> ");
+    let mut state = checkout.list_state.clone();
+    frame.render_stateful_widget(list, area, &mut state);
+}
+
+/// The marks of the bisection, what it tests or found, and the keys that
+/// mark the commit it is about.
+fn render_bisect(frame: &mut Frame, app: &App, block: Block, area: Rect) {
+    let short = |oid: git2::Oid| format!("{:.7}", oid.to_string());
+    let subject = |oid: git2::Oid| {
+        app.log_entries
+            .iter()
+            .find(|entry| entry.oid == oid)
+            .map_or_else(String::new, |entry| entry.message.clone())
+    };
+    let mut text = Vec::new();
+    if let Some(bisect) = &app.bisect {
+        let list = |oids: &[git2::Oid]| match oids {
+            [] => "-".to_string(),
+            oids => oids.iter().map(|&oid| short(oid)).collect::<Vec<_>>().join(", "),
+        };
+        text.push(Line::from(format!(
+            "Good: {}   Bad: {}   Skipped: {}",
+            list(&bisect.marks.good),
+            list(bisect.marks.bad.as_slice()),
+            list(&bisect.marks.skipped)
+        )));
+        text.push(Line::from(""));
+        match &bisect.step {
+            BisectStep::Found(oid) => {
+                text.push(Line::from(vec![
+                    Span::raw("The first bad commit is "),
+                    Span::styled(short(*oid), Style::default().fg(Color::Red).bold()),
+                    Span::raw(format!(" {}", subject(*oid))),
+                ]));
+                text.push(Line::styled(
+                    "HEAD is back where the bisect started. Enter to close.",
+                    Style::default().fg(Color::DarkGray),
+                ));
+            }
+            BisectStep::Undecided(oids) => {
+                text.push(Line::from(format!(
+                    "Only skipped commits are left. The first bad commit is one of {}.",
+                    list(oids)
+                )));
+                text.push(Line::styled(
+                    "HEAD is back where the bisect started. Enter to close.",
+                    Style::default().fg(Color::DarkGray),
+                ));
+            }
+            BisectStep::Test { candidates, steps, .. } => text.push(Line::from(format!(
+                "{} commits may be the first bad one, about {} more tests.",
+                candidates, steps
+            ))),
+            BisectStep::Incomplete => {}
+        }
+    } else {
+        text.push(Line::from(
+            "Mark a commit where the problem shows as bad and an older one without it as good. \
+             The commits between them are then checked out one at a time for you to test.",
+        ));
+    }
+    let result = app
+        .bisect
+        .as_ref()
+        .is_some_and(|bisect| matches!(bisect.step, BisectStep::Found(_) | BisectStep::Undecided(_)));
+    if let Some(oid) = app.bisect_target().filter(|_| !result) {
+        let keys = &app.keys.popup;
+        text.push(Line::from(""));
+        text.push(Line::from(vec![
+            Span::raw("Mark "),
+            Span::styled(short(oid), Style::default().bold()),
+            Span::raw(format!(" {}", subject(oid))),
+        ]));
+        text.push(Line::styled(
+            format!(
+                "'{}' good, '{}' bad, '{}' skip, '{}' end the bisect, Esc to close",
+                key_label(keys.bisect_good),
+                key_label(keys.bisect_bad),
+                key_label(keys.bisect_skip),
+                key_label(keys.bisect_stop)
+            ),
+            Style::default().fg(Color::DarkGray),
+        ));
+    }
+    frame.render_widget(
+        Paragraph::new(text).block(block.title(" Bisect ")).wrap(Wrap { trim: false }),
+        area,
+    );
+}
+
+fn render_ignore_templates(frame: &mut Frame, app: &App, block: Block, area: Rect) {
+    let theme = app.config.theme;
+    let Some(picker) = &app.ignore_templates else {
+        return;
+    };
+    let items: Vec<ListItem> = picker
+        .matches
+        .iter()
+        .zip(&picker.chosen)
+        .map(|(found, chosen)| {
+            let mark = if *chosen { "[x] " } else { "[ ] " };
+            let mut paths = found.paths.iter().take(2).cloned().collect::<Vec<_>>().join(", ");
+            if found.paths.len() > 2 {
+                paths.push_str(&format!(", {} more", found.paths.len() - 2));
+            }
+            ListItem::new(Line::from(vec![
+                Span::raw(mark),
+                Span::styled(found.template.name, Style::default().bold()),
+                Span::styled(format!("  {}", paths), Style::default().fg(Color::Red)),
+            ]))
+        })
+        .collect();
+    let list = List::new(items)
+        .block(block.title(" Ignore Secrets (Space to choose, Enter to add to .gitignore, Esc to cancel) "))
+        .highlight_style(Style::default().bg(theme.selection))
+        .highlight_symbol(">> ");
+    let mut state = picker.list_state.clone();
+    frame.render_stateful_widget(list, area, &mut state);
+}
+
+fn render_link_import(frame: &mut Frame, app: &App, block: Block, area: Rect) {
+    let theme = app.config.theme;
+    let Some(picker) = &app.link_import else {
+        return;
+    };
+    let items: Vec<ListItem> = picker
+        .import
+        .links
+        .iter()
+        .zip(&picker.chosen)
+        .map(|(link, chosen)| {
+            let mark = if *chosen { "[x] " } else { "[ ] " };
+            ListItem::new(Line::from(vec![
+                Span::raw(mark),
+                Span::styled(link.target.as_str(), Style::default().bold()),
+                Span::styled(format!("  → {}", link.source), Style::default().fg(Color::DarkGray)),
+            ]))
+        })
+        .collect();
+    let mut title = format!(" Import from {} (Space to choose, Enter to add to {}", picker.import.source.name(), MANIFEST_FILE);
+    if !picker.import.skipped.is_empty() {
+        title.push_str(&format!(", {} unlinkable skipped", picker.import.skipped.len()));
+    }
+    title.push_str(") ");
+    let list = List::new(items)
+        .block(block.title(title))
+        .highlight_style(Style::default().bg(theme.selection))
+        .highlight_symbol(">> ");
+    let mut state = picker.list_state.clone();
+    frame.render_stateful_widget(list, area, &mut state);
+}
+
+fn render_apply_results(frame: &mut Frame, app: &App, block: Block, area: Rect) {
+    let rows = app.apply_results.iter().map(|result| {
+        let color = match result.outcome {
+            ApplyOutcome::Unchanged => Color::DarkGray,
+            ApplyOutcome::Linked | ApplyOutcome::Relinked | ApplyOutcome::Copied => Color::Green,
+            ApplyOutcome::BackedUp(_) => Color::Yellow,
+            ApplyOutcome::Failed(_) => Color::Red,
+        };
+        Row::new(vec![
+            Cell::from(result.outcome.label()).style(Style::default().fg(color)),
+            Cell::from(result.target.display().to_string()),
+            Cell::from(result.outcome.detail()).style(Style::default().fg(Color::DarkGray)),
+        ])
+    });
+    let failed = app
+        .apply_results
+        .iter()
+        .filter(|result| matches!(result.outcome, ApplyOutcome::Failed(_)))
+        .count();
+    let title = match failed {
+        0 => format!(" Applied {} links (Enter to close) ", app.apply_results.len()),
+        failed => format!(" Applied {} links, {} failed (Enter to close) ", app.apply_results.len(), failed),
+    };
+    let table = Table::new(rows, [Constraint::Length(10), Constraint::Percentage(45), Constraint::Min(20)])
+        .header(Row::new(["Result", "Target", "Details"]).style(Style::default().fg(Color::Yellow).bold()))
+        .block(block.title(title));
+    frame.render_widget(table, area);
+}
+
+fn render_push_targets(frame: &mut Frame, app: &App, block: Block, area: Rect) {
+    let theme = app.config.theme;
+    let Some(targets) = &app.push_targets else {
+        return;
+    };
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Min(0), Constraint::Length(4)])
+        .split(block.inner(area));
+    frame.render_widget(block.title(" Push To (Space: choose, Tab: refspec) "), area);
+    let items: Vec<ListItem> = targets
+        .remotes
+        .iter()
+        .zip(&targets.urls)
+        .zip(&targets.chosen)
+        .map(|((remote, url), chosen)| {
+            let mark = if *chosen { "[x] " } else { "[ ] " };
+            ListItem::new(Line::from(vec![
+                Span::raw(mark),
+                Span::styled(remote.as_str(), Style::default().bold()),
+                Span::styled(format!("  {}", url), Style::default().fg(Color::DarkGray)),
+            ]))
+        })
+        .collect();
+    let list = List::new(items)
+        .highlight_style(Style::default().bg(theme.selection))
+        .highlight_symbol(">> ");
+    let mut state = targets.list_state.clone();
+    frame.render_stateful_widget(list, chunks[0], &mut state);
+
+    let label_style = match targets.editing_refspec {
+        true => Style::default().fg(theme.accent).bold(),
+        false => Style::default(),
+    };
+    let text = vec![
+        Line::from(vec![Span::styled("Refspec:", label_style), Span::raw(format!(" {}", app.push_refspec))]),
+        prompt_error(&app.push_refspec),
+        Line::styled(
+            "A branch, or local:remote to push it under another name; a leading + forces the push.",
+            Style::default().fg(Color::DarkGray),
+        ),
+    ];
+    frame.render_widget(Paragraph::new(text).wrap(Wrap { trim: false }), chunks[1]);
+    if targets.editing_refspec {
+        frame.set_cursor(chunks[1].x + 9 + app.push_refspec.cursor_width(), chunks[1].y);
+    }
+}
+
+fn render_detached_head(frame: &mut Frame, app: &App, block: Block, area: Rect) {
+    let theme = app.config.theme;
+    let Some(detached) = &app.detached_head else {
+        return;
+    };
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Length(2), Constraint::Min(0)])
+        .split(block.inner(area));
+    frame.render_widget(block.title(" Detached HEAD (Enter to choose, Esc to cancel) "), area);
+    let note = match detached.commits_on_no_branch {
+        0 => Line::styled("Every commit of HEAD is on a branch.", Style::default().fg(Color::DarkGray)),
+        1 => Line::styled("1 commit of HEAD is on no branch yet.", Style::default().fg(Color::Red)),
+        count => Line::styled(format!("{} commits of HEAD are on no branch yet.", count), Style::default().fg(Color::Red)),
+    };
+    frame.render_widget(Paragraph::new(note), chunks[0]);
+
+    let mut items = vec![ListItem::new(Line::from(vec![
+        Span::styled("new branch", Style::default().fg(Color::Green).bold()),
+        Span::raw(format!("  at {:.7}", detached.oid.to_string())),
+    ]))];
+    let leaves_commits = detached.commits_on_no_branch > 0;
+    items.extend(detached.branches.iter().map(|branch| {
+        let mut spans = vec![Span::styled(branch.clone(), Style::default().bold()), Span::raw("  return to it")];
+        if leaves_commits {
+            spans.push(Span::styled(", leaving those commits behind", Style::default().fg(Color::DarkGray)));
+        }
+        ListItem::new(Line::from(spans))
+    }));
+    let list = List::new(items)
+        .highlight_style(Style::default().bg(theme.selection))
+        .highlight_symbol(">> ");
+    let mut state = detached.list_state.clone();
+    frame.render_stateful_widget(list, chunks[1], &mut state);
+}
+
+fn render_commit_editor(frame: &mut Frame, app: &App, block: Block, area: Rect) {
+    let (prefix, fields_area, area) = if app.conventional.enabled {
+        let chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Length(3), Constraint::Min(0)])
+            .split(area);
+        (app.conventional.prefix(), Some(chunks[0]), chunks[1])
+    } else {
+        (String::new(), None, area)
+    };
+    let area = if app.commit_warnings.is_empty() {
+        area
+    } else {
+        let chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([
+                Constraint::Min(3),
+                Constraint::Length(app.commit_warnings.len().min(5) as u16 + 2),
+            ])
+            .split(area);
+        let warnings: Vec<Line> = app
+            .commit_warnings
+            .iter()
+            .map(|warning| Line::from(format!("• {}", warning)))
+            .collect();
+        let block = Block::default()
+            .borders(Borders::ALL)
+            .border_style(Style::default().fg(Color::Yellow))
+            .title(" Warnings ");
+        frame.render_widget(
+            Paragraph::new(warnings)
+                .style(Style::default().fg(Color::Yellow))
+                .wrap(Wrap { trim: false })
+                .block(block),
+            chunks[1],
+        );
+        chunks[0]
+    };
+    let limit = app.subject_limit();
+    let subject_len = prefix.width() + app.commit_editor.lines()[0].width();
+    let color = if subject_len > limit {
+        Color::Yellow
+    } else {
+        Color::DarkGray
+    };
+    let block = block
+        .title(" Commit Message (Ctrl+S to commit, Esc to cancel) ")
+        .title(
+            Title::from(Span::styled(
+                format!(" subject {}/{} ", subject_len, limit),
+                Style::default().fg(color),
+            ))
+            .position(Position::Bottom)
+            .alignment(Alignment::Right),
+        )
+        .title(
+            Title::from(if app.sign_commit {
+                Span::styled(" signed (Ctrl+G) ", Style::default().fg(Color::Green))
+            } else {
+                Span::styled(" unsigned (Ctrl+G) ", Style::default().fg(Color::DarkGray))
+            })
+            .position(Position::Bottom)
+            .alignment(Alignment::Left),
+        )
+        .title(
+            Title::from(if app.no_verify {
+                Span::styled(" --no-verify (Ctrl+N) ", Style::default().fg(Color::Yellow))
+            } else {
+                Span::styled(" hooks (Ctrl+N) ", Style::default().fg(Color::DarkGray))
+            })
+            .position(Position::Bottom)
+            .alignment(Alignment::Center),
+        );
+    app.commit_editor.render(frame, area, block);
+    // Drawn after the editor so that the cursor ends up in a focused field.
+    if let Some(fields_area) = fields_area {
+        render_conventional_fields(frame, app, fields_area);
+    }
+}
+
+/// Draws the type and scope fields of the conventional-commit helper and
+/// places the cursor on the focused one.
+fn render_conventional_fields(frame: &mut Frame, app: &App, area: Rect) {
+    let conventional = &app.conventional;
+    let field = |focus: CommitField| {
+        if conventional.focus == focus {
+            Style::default().fg(Color::Black).bg(Color::Yellow)
+        } else {
+            Style::default().fg(Color::Yellow)
+        }
+    };
+    let kind = COMMIT_TYPES[conventional.type_index];
+    let line = Line::from(vec![
+        Span::raw("Type: "),
+        Span::styled(format!("< {} >", kind), field(CommitField::Type)),
+        Span::raw("  Scope: "),
+        Span::styled(conventional.scope.clone(), field(CommitField::Scope)),
+    ]);
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .title(" Conventional Commit (Tab: next field, ←/→: type, Ctrl+T: off) ");
+    frame.render_widget(Paragraph::new(line).block(block), area);
+    let scope_x = area.x + 1 + format!("Type: < {} >  Scope: ", kind).width() as u16;
+    match conventional.focus {
+        CommitField::Type => frame.set_cursor(area.x + 9, area.y + 1),
+        CommitField::Scope => frame.set_cursor(scope_x + conventional.scope.width() as u16, area.y + 1),
+        CommitField::Message => {}
+    }
+}
+
 fn render_footer(frame: &mut Frame, app: &App, area: Rect) {
-    let text = format!("Repo: {} | Press '?' for help", app.repo.path_str());
-    let footer = Paragraph::new(text)
-        .style(Style::default().fg(Color::DarkGray))
-        .alignment(Alignment::Left);
-    frame.render_widget(footer, area);
+    let theme = app.config.theme;
+    if app.searching {
+        frame.render_widget(Paragraph::new(format!("/{}", app.search_input)), area);
+        frame.set_cursor(area.x + 1 + app.search_input.cursor_width(), area.y);
+        return;
+    }
+    let muted = Style::default().fg(Color::DarkGray);
+    let status = &app.branch_status;
+    let branch = status.branch.as_deref().unwrap_or("(no branch)");
+    let mut spans = if status.detached {
+        // Commits made now belong to no branch, so this stands out.
+        vec![
+            Span::styled(
+                format!(" DETACHED HEAD at {} ", branch),
+                Style::default().fg(Color::Black).bg(Color::Yellow).bold(),
+            ),
+            Span::styled(format!(" {}: keep it on a branch", key_label(app.keys.status.edit_branch)), Style::default().fg(Color::Yellow)),
+        ]
+    } else if status.unborn {
+        vec![
+            Span::styled(format!(" {}", branch), Style::default().fg(theme.accent)),
+            Span::styled(" (no commits yet)", muted),
+        ]
+    } else {
+        vec![Span::styled(format!(" {}", branch), Style::default().fg(theme.accent))]
+    };
+    if let Some(upstream) = &status.upstream {
+        spans.push(Span::styled(format!(" → {}", upstream), muted));
+    }
+    if let Some(profile) = &app.active_profile {
+        spans.push(Span::styled(format!(" [{}]", profile.name), Style::default().fg(Color::Magenta)));
+    }
+    if let Some((ahead, behind)) = status.ahead_behind {
+        if ahead > 0 {
+            spans.push(Span::styled(format!(" ↑{}", ahead), Style::default().fg(Color::Green)));
+        }
+        if behind > 0 {
+            spans.push(Span::styled(format!(" ↓{}", behind), Style::default().fg(Color::Red)));
+        }
+    }
+    let (staged, unstaged, untracked) = app.status_counts();
+    spans.push(Span::styled(" | ", muted));
+    spans.push(Span::styled(format!("+{}", staged), Style::default().fg(Color::Green)));
+    spans.push(Span::styled(format!(" ~{}", unstaged), Style::default().fg(Color::Yellow)));
+    spans.push(Span::styled(format!(" ?{}", untracked), Style::default().fg(Color::Red)));
+    if app.sync.enabled {
+        let (label, color) = if app.sync.pushing {
+            ("syncing".to_string(), Color::Cyan)
+        } else if app.sync.error.is_some() {
+            ("sync failed".to_string(), Color::Red)
+        } else if app.sync.changed.is_some() {
+            ("sync pending".to_string(), Color::Yellow)
+        } else if let Some(time) = app.sync.last_synced {
+            (format!("synced {}", format_elapsed(Local::now() - time)), Color::Green)
+        } else {
+            ("sync on".to_string(), Color::Green)
+        };
+        spans.push(Span::styled(format!(" | {}", label), Style::default().fg(color)));
+    } else if app.config.sync {
+        spans.push(Span::styled(" | sync paused", Style::default().fg(Color::Yellow)));
+    }
+    let fetched = status
+        .last_fetch
+        .map_or("never".to_string(), |time| format_elapsed(Local::now() - time));
+    spans.push(Span::styled(format!(" | fetched {}", fetched), muted));
+    if !app.mouse_capture {
+        spans.push(Span::styled(" | mouse: select text", Style::default().fg(Color::Yellow)));
+    }
+    if app.is_recording_macro() {
+        spans.push(Span::styled(" | recording macro", Style::default().fg(Color::Red).bold()));
+    }
+
+    // The status gives way to the help hint on narrow terminals, cut at a
+    // character boundary rather than in the middle of a wide one.
+    // Keys that wait for more, like the `g` of `gg`, take the hint's place.
+    let (hint, style) = match app.pending_keys_label() {
+        Some(keys) => (format!("{} … ", keys), Style::default().fg(Color::Yellow).bold()),
+        None => ("Press '?' for help ".to_string(), muted),
+    };
+    let spans = truncate_spans(spans, (area.width as usize).saturating_sub(hint.width() + 1));
+    frame.render_widget(Paragraph::new(Line::from(spans)), area);
+    frame.render_widget(Paragraph::new(hint).style(style).alignment(Alignment::Right), area);
+}
+
+/// Formats a duration the way the status bar shows the last fetch time.
+fn format_elapsed(elapsed: chrono::Duration) -> String {
+    let seconds = elapsed.num_seconds().max(0);
+    match seconds {
+        0..=59 => "just now".to_string(),
+        60..=3599 => format!("{}m ago", seconds / 60),
+        3600..=86399 => format!("{}h ago", seconds / 3600),
+        _ => format!("{}d ago", seconds / 86400),
+    }
 }
 
 fn centered_rect(percent_x: u16, percent_y: u16, r: Rect) -> Rect {
@@ -237,3 +2654,150 @@ fn centered_rect(percent_x: u16, percent_y: u16, r: Rect) -> Rect {
         ])
         .split(popup_layout[1])[1]
 }
+
+#[cfg(test)]
+mod tests {
+    use super::StatsView;
+    use crate::testing::{ctrl, key, RepoFixture, TestApp};
+    use crossterm::event::KeyCode;
+
+    #[test]
+    fn status_view_lists_changes_and_shows_the_selected_diff() {
+        let fixture = RepoFixture::new()
+            .committed("a.txt", "one\n")
+            .staged("c.txt", "new\n")
+            .unstaged("a.txt", "two\n");
+        let mut app = TestApp::with_size(fixture, 60, 14);
+        app.press(key(KeyCode::Char('j')));
+        assert_eq!(
+            app.render(),
+            [
+                " [S]tatus │ [L]og │ S[t]ash │ L[i]nks │ T[r]ee │ [R]eflog │",
+                "┌Changes───────────────┐┌Diff ('l' to focus, 'enter' to sel┐",
+                "│Staged:   1 file, +1/−││     @@ -1 +1 @@                  │",
+                "│Unstaged: 1 file, +1/−││1   │-one                         │",
+                "└──────────────────────┘│  1 │+two                         │",
+                "┌Files ('h' to focus)──┐│                                  │",
+                "│   Staged changes:    ││                                  │",
+                "│   A c.txt +1/−0 (+4 B││                                  │",
+                "│   Unstaged changes:  ││                                  │",
+                "│>> M a.txt +1/−1      ││                                  │",
+                "│                      ││                                  │",
+                "│                      ││                                  │",
+                "└──────────────────────┘└──────────────────────────────────┘",
+                " main | +1 ~1 ?0 | fetched never         Press '?' for help",
+                "",
+            ]
+            .join("\n")
+        );
+    }
+
+    #[test]
+    fn binary_and_long_diffs_are_cut_short() {
+        let fixture = RepoFixture::new()
+            .committed("a.bin", "\0\0")
+            .committed("b.txt", "")
+            .unstaged("a.bin", "\0\0\0\0\0")
+            .unstaged("b.txt", &"line\n".repeat(30));
+        let mut app = TestApp::with_size(fixture, 60, 12);
+        app.app.config.diff_line_limit = Some(4);
+        assert!(app.render().contains("│(binary file, 3 bytes changed)    │"));
+
+        app.press(key(KeyCode::Char('j')));
+        let screen = app.render();
+        assert!(screen.contains("│    3 │+line "), "{}", screen);
+        assert!(!screen.contains("│    4 │+line "), "{}", screen);
+        assert!(screen.contains("│… 27 more lines ('+' to load more)│"), "{}", screen);
+
+        app.press(key(KeyCode::Char('+')));
+        let screen = app.render();
+        assert!(screen.contains("│    7 │+line "), "{}", screen);
+    }
+
+    #[test]
+    fn help_popup_opens_and_closes() {
+        let mut app = TestApp::new(RepoFixture::new().committed("a.txt", "one\n"));
+        app.press(key(KeyCode::Char('?')));
+        assert!(app.render().contains(" Help (?) "));
+        app.press(key(KeyCode::Esc));
+        assert!(!app.render().contains(" Help (?) "));
+    }
+
+    #[test]
+    fn grouped_file_list_collapses_directories() {
+        let fixture = RepoFixture::new()
+            .unstaged(".bashrc", "bash\n")
+            .unstaged(".config/nvim/init.lua", "vim\n")
+            .unstaged(".config/nvim/lazy.lua", "lazy\n");
+        let mut app = TestApp::new(fixture);
+        app.press(key(KeyCode::Char('v')));
+        let screen = app.render();
+        assert!(screen.contains("▾ .config/nvim/ (2)"));
+        assert!(screen.contains("  A init.lua"));
+
+        // Select the directory node and collapse it.
+        app.press(key(KeyCode::Char('j')));
+        app.press(key(KeyCode::Enter));
+        let screen = app.render();
+        assert!(screen.contains("▸ .config/nvim/ (2)"));
+        assert!(!screen.contains("init.lua"));
+        assert!(screen.contains("A .bashrc"));
+    }
+
+    #[test]
+    fn notifications_show_as_toasts_and_stay_in_the_messages_view() {
+        let mut app = TestApp::new(RepoFixture::new().committed("a.txt", "one\n"));
+        app.press(ctrl('z'));
+        let screen = app.render();
+        assert!(screen.contains("┌ info"));
+        assert!(screen.contains("│Nothing to undo"));
+        // Keys still reach the view underneath.
+        assert!(app.app.popup.is_none());
+
+        app.press(key(KeyCode::Char('M')));
+        assert!(app.render().contains(" info  Nothing to undo"));
+    }
+
+    #[test]
+    fn stats_are_computed_in_the_background() {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let _guard = runtime.enter();
+        let fixture = RepoFixture::new()
+            .committed(".bashrc", "one\n")
+            .committed(".bashrc", "two\n");
+        let mut app = TestApp::with_size(fixture, 100, 20);
+        app.press(key(KeyCode::Char('S')));
+        while app.app.stats_view == StatsView::Loading {
+            std::thread::sleep(std::time::Duration::from_millis(10));
+            app.process_events();
+        }
+        let screen = app.render();
+        assert!(screen.contains("Commits: 2  Authors: 1  Lines: +2 / -1"), "{}", screen);
+        assert!(screen.contains(".bashrc"), "{}", screen);
+    }
+
+    #[test]
+    fn long_logs_build_only_the_rows_on_screen() {
+        let mut fixture = RepoFixture::new();
+        for i in 0..40 {
+            fixture = fixture.committed(&format!("f{:02}", i), "x\n");
+        }
+        let mut app = TestApp::new(fixture);
+        app.press(ctrl('l'));
+        let screen = app.render();
+        assert!(screen.contains(">> "));
+        assert!(screen.contains("Add f39") && !screen.contains("Add f00"));
+
+        app.type_text("G");
+        let screen = app.render();
+        assert!(screen.contains("Add f00") && !screen.contains("Add f39"));
+        assert!(screen.lines().any(|line| line.contains(">> ") && line.contains("Add f00")));
+        // 24 rows less the tabs, the footer, the borders and the header
+        // with its margin leave 18 for commits.
+        assert_eq!(app.app.log_table_state.offset(), 40 - 18);
+
+        app.type_text("gg");
+        assert!(app.render().contains("Add f39"));
+        assert_eq!(app.app.log_table_state.offset(), 0);
+    }
+}
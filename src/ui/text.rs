@@ -0,0 +1,108 @@
+//! src/ui/text.rs
+
+use ratatui::text::Span;
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+/// Columns a tab advances to the next multiple of. Terminals draw a raw tab
+/// as a jump that the buffer does not know about, so tabs are expanded
+/// before they reach it.
+pub const TAB_WIDTH: usize = 4;
+
+/// Replaces the tabs in `text` by the spaces up to the next tab stop,
+/// counting wide characters as the two cells they take.
+pub fn expand_tabs(text: &str) -> String {
+    if !text.contains('\t') {
+        return text.to_string();
+    }
+    let mut expanded = String::with_capacity(text.len());
+    let mut column = 0;
+    for grapheme in text.graphemes(true) {
+        if grapheme == "\n" || grapheme == "\r\n" {
+            expanded.push_str(grapheme);
+            column = 0;
+        } else if grapheme == "\t" {
+            let spaces = TAB_WIDTH - column % TAB_WIDTH;
+            expanded.push_str(&" ".repeat(spaces));
+            column += spaces;
+        } else {
+            expanded.push_str(grapheme);
+            column += grapheme.width();
+        }
+    }
+    expanded
+}
+
+/// `text` cut to at most `width` cells, ending in `…` when something was
+/// cut. Whole graphemes are kept, so a wide character that does not fit is
+/// dropped rather than split.
+pub fn truncate(text: &str, width: usize) -> String {
+    if text.width() <= width {
+        return text.to_string();
+    }
+    if width == 0 {
+        return String::new();
+    }
+    let mut truncated = String::new();
+    let mut used = 0;
+    for grapheme in text.graphemes(true) {
+        let grapheme_width = grapheme.width();
+        if used + grapheme_width > width - 1 {
+            break;
+        }
+        truncated.push_str(grapheme);
+        used += grapheme_width;
+    }
+    truncated.push('…');
+    truncated
+}
+
+/// `text` truncated or padded with spaces to exactly `width` cells, for
+/// columns that `format!` would align by characters instead.
+pub fn fit(text: &str, width: usize) -> String {
+    let mut fitted = truncate(text, width);
+    let padding = width.saturating_sub(fitted.width());
+    fitted.push_str(&" ".repeat(padding));
+    fitted
+}
+
+/// The spans of a line cut to `width` cells, the last one kept ending in `…`.
+pub fn truncate_spans(spans: Vec<Span<'_>>, width: usize) -> Vec<Span<'_>> {
+    let mut remaining = width;
+    let mut truncated = Vec::with_capacity(spans.len());
+    for span in spans {
+        let span_width = span.content.width();
+        if span_width <= remaining {
+            remaining -= span_width;
+            truncated.push(span);
+        } else {
+            if remaining > 0 {
+                truncated.push(Span::styled(truncate(&span.content, remaining), span.style));
+            }
+            break;
+        }
+    }
+    truncated
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wide_characters_count_as_two_cells() {
+        assert_eq!(expand_tabs("a\tb"), "a   b");
+        assert_eq!(expand_tabs("日本\t#"), "日本    #");
+        assert_eq!(expand_tabs("ab\tc\n\td"), "ab  c\n    d");
+        assert_eq!(truncate("日本語のファイル", 7), "日本語…");
+        assert_eq!(truncate("🎉 party", 8), "🎉 party");
+        assert_eq!(truncate("🎉 party", 3), "🎉…");
+        assert_eq!(truncate("e\u{301}e\u{301}e\u{301}", 2), "e\u{301}…");
+        assert_eq!(fit("李小龍", 5), "李小…");
+        assert_eq!(fit("李", 4), "李  ");
+
+        let spans = truncate_spans(vec![Span::raw(" main"), Span::raw(" → 起源/main")], 11);
+        let text: Vec<_> = spans.iter().map(|span| span.content.as_ref()).collect();
+        assert_eq!(text, [" main", " → 起…"]);
+    }
+}
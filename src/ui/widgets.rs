@@ -0,0 +1,403 @@
+//! src/ui/widgets.rs
+
+use crate::config::DEFAULT_SUBJECT_LIMIT;
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use ratatui::{
+    prelude::*,
+    widgets::{Block, ListState, Paragraph},
+};
+use std::collections::{BTreeMap, HashSet};
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+/// Column at which commit body lines should be wrapped.
+pub const BODY_GUIDE: usize = 72;
+
+/// A multi-line text editor. The cursor is tracked as a (line, grapheme)
+/// pair so that multi-byte characters and combining sequences move as one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TextArea {
+    /// Always holds at least one (possibly empty) line.
+    lines: Vec<String>,
+    row: usize,
+    col: usize,
+    /// Column after which the first line is highlighted.
+    subject_guide: usize,
+}
+
+impl Default for TextArea {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TextArea {
+    pub fn new() -> Self {
+        Self {
+            lines: vec![String::new()],
+            row: 0,
+            col: 0,
+            subject_guide: DEFAULT_SUBJECT_LIMIT,
+        }
+    }
+
+    /// Creates an editor holding `text`, with the cursor at the end.
+    pub fn from_text(text: &str) -> Self {
+        let mut lines: Vec<String> = text.split('\n').map(str::to_string).collect();
+        if lines.is_empty() {
+            lines.push(String::new());
+        }
+        let row = lines.len() - 1;
+        let col = lines[row].graphemes(true).count();
+        Self {
+            lines,
+            row,
+            col,
+            subject_guide: DEFAULT_SUBJECT_LIMIT,
+        }
+    }
+
+    pub fn text(&self) -> String {
+        self.lines.join("\n")
+    }
+
+    pub fn lines(&self) -> &[String] {
+        &self.lines
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lines.iter().all(|line| line.trim().is_empty())
+    }
+
+    pub fn clear(&mut self) {
+        self.lines = vec![String::new()];
+        self.row = 0;
+        self.col = 0;
+    }
+
+    /// Replaces the text, keeping the settings, and puts the cursor at the start.
+    pub fn set_text(&mut self, text: &str) {
+        self.lines = text.split('\n').map(str::to_string).collect();
+        self.row = 0;
+        self.col = 0;
+    }
+
+    /// Sets the column after which the first line is highlighted, e.g. to
+    /// leave room for a prefix added to the subject later.
+    pub fn set_subject_guide(&mut self, columns: usize) {
+        self.subject_guide = columns;
+    }
+
+    /// Applies an editing or movement key. Keys that mean something else
+    /// (confirm, cancel) must be filtered out by the caller.
+    pub fn input(&mut self, key: KeyEvent) {
+        match key.code {
+            KeyCode::Char(c) if !key.modifiers.contains(KeyModifiers::CONTROL) => {
+                self.insert_char(c)
+            }
+            KeyCode::Enter => self.insert_newline(),
+            KeyCode::Backspace => self.backspace(),
+            KeyCode::Delete => self.delete(),
+            KeyCode::Left => self.move_left(),
+            KeyCode::Right => self.move_right(),
+            KeyCode::Up => self.move_up(),
+            KeyCode::Down => self.move_down(),
+            KeyCode::Home => self.col = 0,
+            KeyCode::End => self.col = self.line_len(self.row),
+            _ => {}
+        }
+    }
+
+    pub fn insert_char(&mut self, c: char) {
+        let offset = self.byte_offset();
+        self.lines[self.row].insert(offset, c);
+        // A combining character merges into the previous grapheme, so
+        // recompute the column from the byte position instead of adding one.
+        let end = offset + c.len_utf8();
+        self.col = self.lines[self.row][..end].graphemes(true).count();
+    }
+
+    pub fn insert_newline(&mut self) {
+        let offset = self.byte_offset();
+        let rest = self.lines[self.row].split_off(offset);
+        self.lines.insert(self.row + 1, rest);
+        self.row += 1;
+        self.col = 0;
+    }
+
+    pub fn backspace(&mut self) {
+        if self.col > 0 {
+            self.col -= 1;
+            self.remove_grapheme_at_cursor();
+        } else if self.row > 0 {
+            let line = self.lines.remove(self.row);
+            self.row -= 1;
+            self.col = self.line_len(self.row);
+            self.lines[self.row].push_str(&line);
+        }
+    }
+
+    pub fn delete(&mut self) {
+        if self.col < self.line_len(self.row) {
+            self.remove_grapheme_at_cursor();
+        } else if self.row + 1 < self.lines.len() {
+            let next = self.lines.remove(self.row + 1);
+            self.lines[self.row].push_str(&next);
+        }
+    }
+
+    pub fn move_left(&mut self) {
+        if self.col > 0 {
+            self.col -= 1;
+        } else if self.row > 0 {
+            self.row -= 1;
+            self.col = self.line_len(self.row);
+        }
+    }
+
+    pub fn move_right(&mut self) {
+        if self.col < self.line_len(self.row) {
+            self.col += 1;
+        } else if self.row + 1 < self.lines.len() {
+            self.row += 1;
+            self.col = 0;
+        }
+    }
+
+    pub fn move_up(&mut self) {
+        if self.row > 0 {
+            self.row -= 1;
+            self.col = self.col.min(self.line_len(self.row));
+        }
+    }
+
+    pub fn move_down(&mut self) {
+        if self.row + 1 < self.lines.len() {
+            self.row += 1;
+            self.col = self.col.min(self.line_len(self.row));
+        }
+    }
+
+    fn line_len(&self, row: usize) -> usize {
+        self.lines[row].graphemes(true).count()
+    }
+
+    fn byte_offset(&self) -> usize {
+        let line = &self.lines[self.row];
+        line.grapheme_indices(true)
+            .nth(self.col)
+            .map_or(line.len(), |(i, _)| i)
+    }
+
+    fn remove_grapheme_at_cursor(&mut self) {
+        let start = self.byte_offset();
+        let line = &mut self.lines[self.row];
+        let len = line[start..].graphemes(true).next().map_or(0, str::len);
+        line.replace_range(start..start + len, "");
+    }
+
+    /// Renders the text soft-wrapped to the width of `block`'s inner area and
+    /// places the terminal cursor. Subject characters past the subject guide
+    /// (column 50 by default) and any characters past column 72 are
+    /// highlighted as a guide.
+    pub fn render(&self, frame: &mut Frame, area: Rect, block: Block) {
+        let inner = block.inner(area);
+        let width = usize::from(inner.width.max(1));
+        let mut visual_lines: Vec<Line> = Vec::new();
+        let mut cursor = (0usize, 0usize);
+
+        for (row, line) in self.lines.iter().enumerate() {
+            let mut spans: Vec<Span> = Vec::new();
+            let mut x = 0;
+            let mut column = 0;
+            for (index, grapheme) in line.graphemes(true).enumerate() {
+                let grapheme_width = grapheme.width();
+                if x + grapheme_width > width && x > 0 {
+                    visual_lines.push(Line::from(std::mem::take(&mut spans)));
+                    x = 0;
+                }
+                if row == self.row && index == self.col {
+                    cursor = (visual_lines.len(), x);
+                }
+                spans.push(Span::styled(grapheme.to_string(), self.guide_style(row, column)));
+                x += grapheme_width;
+                column += grapheme_width;
+            }
+            if row == self.row && self.col >= self.line_len(row) {
+                if x >= width {
+                    visual_lines.push(Line::from(std::mem::take(&mut spans)));
+                    x = 0;
+                }
+                cursor = (visual_lines.len(), x);
+            }
+            visual_lines.push(Line::from(spans));
+        }
+
+        let height = usize::from(inner.height.max(1));
+        let scroll = cursor.0.saturating_sub(height - 1);
+        frame.render_widget(
+            Paragraph::new(visual_lines)
+                .block(block)
+                .scroll((scroll as u16, 0)),
+            area,
+        );
+        frame.set_cursor(
+            inner.x + cursor.1 as u16,
+            inner.y + (cursor.0 - scroll) as u16,
+        );
+    }
+
+    fn guide_style(&self, row: usize, column: usize) -> Style {
+        if column >= BODY_GUIDE {
+            Style::default().fg(Color::Red)
+        } else if row == 0 && column >= self.subject_guide {
+            Style::default().fg(Color::Yellow)
+        } else {
+            Style::default()
+        }
+    }
+}
+
+/// A path shown in a `FileTree`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TreeEntry {
+    pub path: String,
+    /// Whether the file is in HEAD, as opposed to only in the work tree.
+    pub tracked: bool,
+}
+
+/// A visible line of a `FileTree`: a directory or a file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TreeRow {
+    pub path: String,
+    pub name: String,
+    pub depth: usize,
+    pub is_dir: bool,
+    pub expanded: bool,
+    pub tracked: bool,
+}
+
+#[derive(Debug, Default)]
+struct TreeNode {
+    children: BTreeMap<String, TreeNode>,
+    /// `Some(tracked)` for files, `None` for directories.
+    file: Option<bool>,
+}
+
+/// A collapsible directory tree built from a flat list of file paths.
+/// Directories start collapsed and stay expanded across `set_entries`.
+#[derive(Debug, Default)]
+pub struct FileTree {
+    root: TreeNode,
+    expanded: HashSet<String>,
+    rows: Vec<TreeRow>,
+    pub state: ListState,
+}
+
+impl FileTree {
+    /// Replaces the listed files, keeping the selected path selected when it
+    /// is still visible.
+    pub fn set_entries(&mut self, entries: Vec<TreeEntry>) {
+        let selected = self.selected().map(|row| row.path.clone());
+        self.root = TreeNode::default();
+        for entry in entries {
+            let mut node = &mut self.root;
+            for component in entry.path.split('/') {
+                node = node.children.entry(component.to_string()).or_default();
+            }
+            node.file = Some(entry.tracked);
+        }
+        self.rebuild(selected);
+    }
+
+    pub fn rows(&self) -> &[TreeRow] {
+        &self.rows
+    }
+
+    pub fn selected(&self) -> Option<&TreeRow> {
+        self.state.selected().and_then(|i| self.rows.get(i))
+    }
+
+    pub fn select_next(&mut self) {
+        if self.rows.is_empty() { return; }
+        let i = self.state.selected().map_or(0, |i| (i + 1) % self.rows.len());
+        self.state.select(Some(i));
+    }
+
+    pub fn select_previous(&mut self) {
+        if self.rows.is_empty() { return; }
+        let i = self.state.selected().map_or(0, |i| {
+            if i == 0 { self.rows.len() - 1 } else { i - 1 }
+        });
+        self.state.select(Some(i));
+    }
+
+    /// Expands or collapses the selected directory.
+    pub fn toggle(&mut self) {
+        let Some(row) = self.selected().filter(|row| row.is_dir).cloned() else {
+            return;
+        };
+        if !self.expanded.remove(&row.path) {
+            self.expanded.insert(row.path.clone());
+        }
+        self.rebuild(Some(row.path));
+    }
+
+    /// Collapses the selected directory, or moves to the parent directory
+    /// when the selection is a file or an already collapsed directory.
+    pub fn collapse(&mut self) {
+        let Some(row) = self.selected().cloned() else {
+            return;
+        };
+        if row.is_dir && self.expanded.remove(&row.path) {
+            self.rebuild(Some(row.path));
+        } else if let Some((parent, _)) = row.path.rsplit_once('/') {
+            let index = self.rows.iter().position(|r| r.path == parent);
+            self.state.select(index);
+        }
+    }
+
+    fn rebuild(&mut self, selected: Option<String>) {
+        let mut rows = Vec::new();
+        push_rows(&self.root, "", 0, &self.expanded, &mut rows);
+        self.rows = rows;
+        let index = selected
+            .and_then(|path| self.rows.iter().position(|row| row.path == path))
+            .or(if self.rows.is_empty() { None } else { Some(0) })
+            .map(|i| i.min(self.rows.len().saturating_sub(1)));
+        self.state.select(index);
+    }
+}
+
+/// Appends the rows below `node`, directories first, descending only into
+/// expanded directories.
+fn push_rows(
+    node: &TreeNode,
+    prefix: &str,
+    depth: usize,
+    expanded: &HashSet<String>,
+    rows: &mut Vec<TreeRow>,
+) {
+    let (dirs, files): (Vec<_>, Vec<_>) =
+        node.children.iter().partition(|(_, child)| child.file.is_none());
+    for (name, child) in dirs.into_iter().chain(files) {
+        let path = if prefix.is_empty() {
+            name.clone()
+        } else {
+            format!("{}/{}", prefix, name)
+        };
+        let is_dir = child.file.is_none();
+        let is_expanded = is_dir && expanded.contains(&path);
+        rows.push(TreeRow {
+            path: path.clone(),
+            name: name.clone(),
+            depth,
+            is_dir,
+            expanded: is_expanded,
+            tracked: child.file.unwrap_or(true),
+        });
+        if is_expanded {
+            push_rows(child, &path, depth + 1, expanded, rows);
+        }
+    }
+}